@@ -1,6 +1,37 @@
-use crate::Frame;
+use crate::db::{
+    Aggregate, Aggregation, BloomInfoField, ClaimOptions, CuckooOptions, DuplicatePolicy, FtQuery,
+    FtSearchOptions, GeoQuery, GeoUnit, GroupReadFrom, GroupStart, IndexSpec, JsonCondition,
+    JsonFormat, JsonPath, LabelFilter, PendingFilter, RangeBy, RestorePolicy, SetOp, StreamId,
+    StreamTrim, TopKOptions, TrackingOptions, TsOptions, TsRangeQuery, VAddOptions, VSimQuery,
+    XAddId, XReadFrom, ZAddFlags, ZRange,
+};
+use crate::{Db, Frame};
 use bytes::Bytes;
+use std::str::FromStr;
 use std::time::Duration;
+use zset::RangeKind;
+
+mod bloom;
+mod client;
+mod cms;
+mod config;
+mod cuckoo;
+mod geo;
+mod hash;
+mod hyperloglog;
+mod json;
+mod module;
+mod pubsub;
+mod script;
+mod search;
+mod set;
+mod stream;
+mod tdigest;
+mod timeseries;
+mod topk;
+mod transaction;
+mod vset;
+mod zset;
 
 pub enum Command {
     Ping {
@@ -17,6 +48,726 @@ pub enum Command {
         value: Bytes,
         expiry: Option<Duration>,
     },
+    SAdd {
+        key: Bytes,
+        members: Vec<Bytes>,
+    },
+    SRem {
+        key: Bytes,
+        members: Vec<Bytes>,
+    },
+    SIsMember {
+        key: Bytes,
+        member: Bytes,
+    },
+    SMIsMember {
+        key: Bytes,
+        members: Vec<Bytes>,
+    },
+    SMembers {
+        key: Bytes,
+    },
+    SCard {
+        key: Bytes,
+    },
+    SPop {
+        key: Bytes,
+        count: Option<usize>,
+    },
+    SRandMember {
+        key: Bytes,
+        count: Option<i64>,
+    },
+    // SINTER, SUNION and SDIFF
+    SetOp {
+        op: SetOp,
+        keys: Vec<Bytes>,
+    },
+    // SINTERSTORE, SUNIONSTORE and SDIFFSTORE
+    SetOpStore {
+        op: SetOp,
+        destination: Bytes,
+        keys: Vec<Bytes>,
+    },
+    SInterCard {
+        keys: Vec<Bytes>,
+        limit: usize,
+    },
+    SMove {
+        source: Bytes,
+        destination: Bytes,
+        member: Bytes,
+    },
+    HSet {
+        key: Bytes,
+        pairs: Vec<(Bytes, Bytes)>,
+    },
+    HGet {
+        key: Bytes,
+        field: Bytes,
+    },
+    HDel {
+        key: Bytes,
+        fields: Vec<Bytes>,
+    },
+    HGetAll {
+        key: Bytes,
+    },
+    HLen {
+        key: Bytes,
+    },
+    // ZADD, with INCR turning it into ZINCRBY
+    ZAdd {
+        key: Bytes,
+        flags: ZAddFlags,
+        incr: bool,
+        pairs: Vec<(f64, Bytes)>,
+    },
+    ZIncrBy {
+        key: Bytes,
+        delta: f64,
+        member: Bytes,
+    },
+    ZRem {
+        key: Bytes,
+        members: Vec<Bytes>,
+    },
+    ZScore {
+        key: Bytes,
+        member: Bytes,
+    },
+    ZMScore {
+        key: Bytes,
+        members: Vec<Bytes>,
+    },
+    ZCard {
+        key: Bytes,
+    },
+    // ZRANK and ZREVRANK
+    ZRank {
+        key: Bytes,
+        member: Bytes,
+        rev: bool,
+        with_score: bool,
+    },
+    // ZRANGE and the older ZREVRANGE, ZRANGEBYSCORE and ZRANGEBYLEX family
+    ZRange {
+        key: Bytes,
+        range: ZRange,
+        with_scores: bool,
+    },
+    // ZCOUNT and ZLEXCOUNT
+    ZCount {
+        key: Bytes,
+        by: RangeBy,
+    },
+    // ZPOPMIN and ZPOPMAX
+    ZPop {
+        key: Bytes,
+        count: Option<usize>,
+        max: bool,
+    },
+    ZRandMember {
+        key: Bytes,
+        count: Option<i64>,
+        with_scores: bool,
+    },
+    // ZUNION, ZINTER and ZDIFF
+    ZSetOp {
+        op: SetOp,
+        keys: Vec<Bytes>,
+        weights: Option<Vec<f64>>,
+        aggregate: Aggregate,
+        with_scores: bool,
+    },
+    // ZUNIONSTORE, ZINTERSTORE and ZDIFFSTORE
+    ZSetOpStore {
+        op: SetOp,
+        destination: Bytes,
+        keys: Vec<Bytes>,
+        weights: Option<Vec<f64>>,
+        aggregate: Aggregate,
+    },
+    ZRangeStore {
+        destination: Bytes,
+        source: Bytes,
+        range: ZRange,
+    },
+    // BZPOPMIN and BZPOPMAX; a timeout of None blocks forever
+    BZPop {
+        keys: Vec<Bytes>,
+        max: bool,
+        timeout: Option<Duration>,
+    },
+    ZMPop {
+        keys: Vec<Bytes>,
+        max: bool,
+        count: usize,
+    },
+    BZMPop {
+        keys: Vec<Bytes>,
+        max: bool,
+        count: usize,
+        timeout: Option<Duration>,
+    },
+    XAdd {
+        key: Bytes,
+        id: XAddId,
+        fields: Vec<(Bytes, Bytes)>,
+        nomkstream: bool,
+        trim: Option<StreamTrim>,
+    },
+    // XRANGE and XREVRANGE, with both bounds inclusive
+    XRange {
+        key: Bytes,
+        start: StreamId,
+        end: StreamId,
+        count: Option<usize>,
+        rev: bool,
+    },
+    XLen {
+        key: Bytes,
+    },
+    XDel {
+        key: Bytes,
+        ids: Vec<StreamId>,
+    },
+    XTrim {
+        key: Bytes,
+        trim: StreamTrim,
+    },
+    // With `block` set a timeout of None blocks forever
+    XRead {
+        streams: Vec<(Bytes, XReadFrom)>,
+        count: Option<usize>,
+        block: bool,
+        timeout: Option<Duration>,
+    },
+    XGroupCreate {
+        key: Bytes,
+        group: Bytes,
+        start: GroupStart,
+        mkstream: bool,
+        entries_read: Option<u64>,
+    },
+    XGroupSetId {
+        key: Bytes,
+        group: Bytes,
+        start: GroupStart,
+        entries_read: Option<u64>,
+    },
+    XGroupDestroy {
+        key: Bytes,
+        group: Bytes,
+    },
+    XGroupCreateConsumer {
+        key: Bytes,
+        group: Bytes,
+        consumer: Bytes,
+    },
+    XGroupDelConsumer {
+        key: Bytes,
+        group: Bytes,
+        consumer: Bytes,
+    },
+    XReadGroup {
+        group: Bytes,
+        consumer: Bytes,
+        streams: Vec<(Bytes, GroupReadFrom)>,
+        count: Option<usize>,
+        noack: bool,
+        block: bool,
+        timeout: Option<Duration>,
+    },
+    XAck {
+        key: Bytes,
+        group: Bytes,
+        ids: Vec<StreamId>,
+    },
+    // The summary form when there's no filter
+    XPending {
+        key: Bytes,
+        group: Bytes,
+        filter: Option<PendingFilter>,
+    },
+    XClaim {
+        key: Bytes,
+        group: Bytes,
+        consumer: Bytes,
+        min_idle: u64,
+        ids: Vec<StreamId>,
+        opts: ClaimOptions,
+    },
+    XAutoClaim {
+        key: Bytes,
+        group: Bytes,
+        consumer: Bytes,
+        min_idle: u64,
+        start: StreamId,
+        count: usize,
+        just_id: bool,
+    },
+    XInfoStream {
+        key: Bytes,
+    },
+    XInfoGroups {
+        key: Bytes,
+    },
+    XInfoConsumers {
+        key: Bytes,
+        group: Bytes,
+    },
+    PfAdd {
+        key: Bytes,
+        elements: Vec<Bytes>,
+    },
+    PfCount {
+        keys: Vec<Bytes>,
+    },
+    PfMerge {
+        destination: Bytes,
+        sources: Vec<Bytes>,
+    },
+    // Points are (longitude, latitude, member)
+    GeoAdd {
+        key: Bytes,
+        flags: ZAddFlags,
+        points: Vec<(f64, f64, Bytes)>,
+    },
+    GeoPos {
+        key: Bytes,
+        members: Vec<Bytes>,
+    },
+    GeoDist {
+        key: Bytes,
+        a: Bytes,
+        b: Bytes,
+        unit: GeoUnit,
+    },
+    GeoHash {
+        key: Bytes,
+        members: Vec<Bytes>,
+    },
+    GeoSearch {
+        key: Bytes,
+        query: GeoQuery,
+        with_coord: bool,
+        with_dist: bool,
+        with_hash: bool,
+    },
+    GeoSearchStore {
+        destination: Bytes,
+        source: Bytes,
+        query: GeoQuery,
+        store_dist: bool,
+    },
+    // An expansion of None makes a non-scaling filter
+    BfReserve {
+        key: Bytes,
+        error_rate: f64,
+        capacity: u64,
+        expansion: Option<u64>,
+    },
+    BfAdd {
+        key: Bytes,
+        item: Bytes,
+    },
+    BfMAdd {
+        key: Bytes,
+        items: Vec<Bytes>,
+    },
+    BfExists {
+        key: Bytes,
+        item: Bytes,
+    },
+    BfMExists {
+        key: Bytes,
+        items: Vec<Bytes>,
+    },
+    BfInfo {
+        key: Bytes,
+        field: Option<BloomInfoField>,
+    },
+    CfReserve {
+        key: Bytes,
+        options: CuckooOptions,
+    },
+    CfAdd {
+        key: Bytes,
+        item: Bytes,
+        nx: bool,
+    },
+    CfDel {
+        key: Bytes,
+        item: Bytes,
+    },
+    CfExists {
+        key: Bytes,
+        item: Bytes,
+    },
+    CfMExists {
+        key: Bytes,
+        items: Vec<Bytes>,
+    },
+    CfCount {
+        key: Bytes,
+        item: Bytes,
+    },
+    // Both CMS.INITBYDIM and CMS.INITBYPROB
+    CmsInit {
+        key: Bytes,
+        width: usize,
+        depth: usize,
+    },
+    CmsIncrBy {
+        key: Bytes,
+        items: Vec<(Bytes, u64)>,
+    },
+    CmsQuery {
+        key: Bytes,
+        items: Vec<Bytes>,
+    },
+    // Sources are (key, weight)
+    CmsMerge {
+        destination: Bytes,
+        sources: Vec<(Bytes, i64)>,
+    },
+    CmsInfo {
+        key: Bytes,
+    },
+    TopKReserve {
+        key: Bytes,
+        options: TopKOptions,
+    },
+    // Both TOPK.ADD and TOPK.INCRBY
+    TopKIncrBy {
+        key: Bytes,
+        items: Vec<(Bytes, u64)>,
+    },
+    TopKQuery {
+        key: Bytes,
+        items: Vec<Bytes>,
+    },
+    TopKList {
+        key: Bytes,
+        with_count: bool,
+    },
+    TopKInfo {
+        key: Bytes,
+    },
+    TDigestCreate {
+        key: Bytes,
+        compression: u64,
+    },
+    TDigestAdd {
+        key: Bytes,
+        values: Vec<f64>,
+    },
+    TDigestQuantile {
+        key: Bytes,
+        quantiles: Vec<f64>,
+    },
+    TDigestCdf {
+        key: Bytes,
+        values: Vec<f64>,
+    },
+    TDigestRank {
+        key: Bytes,
+        values: Vec<f64>,
+        rev: bool,
+    },
+    TDigestTrimmedMean {
+        key: Bytes,
+        low: f64,
+        high: f64,
+    },
+    TDigestMinMax {
+        key: Bytes,
+        max: bool,
+    },
+    TDigestMerge {
+        destination: Bytes,
+        sources: Vec<Bytes>,
+        compression: Option<u64>,
+        override_dest: bool,
+    },
+    TDigestInfo {
+        key: Bytes,
+    },
+    JsonSet {
+        key: Bytes,
+        path: JsonPath,
+        value: serde_json::Value,
+        condition: Option<JsonCondition>,
+    },
+    JsonGet {
+        key: Bytes,
+        paths: Vec<JsonPath>,
+        format: JsonFormat,
+    },
+    JsonMGet {
+        keys: Vec<Bytes>,
+        path: JsonPath,
+    },
+    JsonDel {
+        key: Bytes,
+        path: JsonPath,
+    },
+    JsonType {
+        key: Bytes,
+        path: JsonPath,
+    },
+    JsonObjKeys {
+        key: Bytes,
+        path: JsonPath,
+    },
+    JsonNumIncrBy {
+        key: Bytes,
+        path: JsonPath,
+        by: serde_json::Number,
+    },
+    JsonStrAppend {
+        key: Bytes,
+        path: JsonPath,
+        value: String,
+    },
+    JsonArrAppend {
+        key: Bytes,
+        path: JsonPath,
+        values: Vec<serde_json::Value>,
+    },
+    JsonArrInsert {
+        key: Bytes,
+        path: JsonPath,
+        index: i64,
+        values: Vec<serde_json::Value>,
+    },
+    JsonArrPop {
+        key: Bytes,
+        path: JsonPath,
+        index: i64,
+    },
+    JsonArrTrim {
+        key: Bytes,
+        path: JsonPath,
+        start: i64,
+        stop: i64,
+    },
+    TsCreate {
+        key: Bytes,
+        options: TsOptions,
+    },
+    // TS.ADD, where a missing timestamp means the current time
+    TsAdd {
+        key: Bytes,
+        timestamp: Option<u64>,
+        value: f64,
+        options: TsOptions,
+        on_duplicate: Option<DuplicatePolicy>,
+    },
+    TsGet {
+        key: Bytes,
+    },
+    // TS.RANGE and TS.REVRANGE
+    TsRange {
+        key: Bytes,
+        query: TsRangeQuery,
+    },
+    // TS.MRANGE and TS.MREVRANGE
+    TsMRange {
+        query: TsRangeQuery,
+        filters: Vec<LabelFilter>,
+        with_labels: bool,
+    },
+    TsCreateRule {
+        source: Bytes,
+        destination: Bytes,
+        aggregation: Aggregation,
+        align: u64,
+    },
+    TsDeleteRule {
+        source: Bytes,
+        destination: Bytes,
+    },
+    TsInfo {
+        key: Bytes,
+    },
+    VAdd {
+        key: Bytes,
+        values: Vec<f32>,
+        element: Bytes,
+        options: VAddOptions,
+    },
+    VSim {
+        key: Bytes,
+        query: VSimQuery,
+        with_scores: bool,
+        with_attribs: bool,
+    },
+    VRem {
+        key: Bytes,
+        element: Bytes,
+    },
+    VCard {
+        key: Bytes,
+    },
+    VDim {
+        key: Bytes,
+    },
+    VEmb {
+        key: Bytes,
+        element: Bytes,
+    },
+    // VSETATTR, where None removes the attributes
+    VSetAttr {
+        key: Bytes,
+        element: Bytes,
+        attributes: Option<serde_json::Value>,
+    },
+    VGetAttr {
+        key: Bytes,
+        element: Bytes,
+    },
+    FtCreate {
+        name: Bytes,
+        spec: IndexSpec,
+    },
+    FtSearch {
+        name: Bytes,
+        query: FtQuery,
+        options: FtSearchOptions,
+    },
+    FtInfo {
+        name: Bytes,
+    },
+    FtDropIndex {
+        name: Bytes,
+        delete_docs: bool,
+    },
+    Subscribe {
+        channels: Vec<Bytes>,
+    },
+    // UNSUBSCRIBE, where no channels means all of them
+    Unsubscribe {
+        channels: Vec<Bytes>,
+    },
+    PSubscribe {
+        patterns: Vec<Bytes>,
+    },
+    // PUNSUBSCRIBE, where no patterns means all of them
+    PUnsubscribe {
+        patterns: Vec<Bytes>,
+    },
+    // SSUBSCRIBE, with every channel in the same key slot
+    SSubscribe {
+        channels: Vec<Bytes>,
+    },
+    SUnsubscribe {
+        channels: Vec<Bytes>,
+    },
+    Publish {
+        channel: Bytes,
+        message: Bytes,
+    },
+    SPublish {
+        channel: Bytes,
+        message: Bytes,
+    },
+    PubSubChannels {
+        pattern: Option<Bytes>,
+    },
+    PubSubNumSub {
+        channels: Vec<Bytes>,
+    },
+    PubSubNumPat,
+    PubSubShardChannels {
+        pattern: Option<Bytes>,
+    },
+    PubSubShardNumSub {
+        channels: Vec<Bytes>,
+    },
+    Quit,
+    ConfigGet {
+        patterns: Vec<Bytes>,
+    },
+    ConfigSet {
+        pairs: Vec<(Bytes, Bytes)>,
+    },
+    Hello {
+        protover: Option<u8>,
+    },
+    ClientId,
+    ClientGetRedir,
+    ClientCaching {
+        yes: bool,
+    },
+    ClientTracking {
+        on: bool,
+        options: TrackingOptions,
+    },
+    Multi,
+    Exec,
+    Discard,
+    Watch {
+        keys: Vec<Bytes>,
+    },
+    Unwatch,
+    Flush,
+    Eval {
+        script: Bytes,
+        keys: Vec<Bytes>,
+        args: Vec<Bytes>,
+    },
+    EvalSha {
+        sha: Bytes,
+        keys: Vec<Bytes>,
+        args: Vec<Bytes>,
+    },
+    ScriptLoad {
+        script: Bytes,
+    },
+    ScriptExists {
+        shas: Vec<Bytes>,
+    },
+    ScriptFlush,
+    ScriptKill,
+    FCall {
+        function: Bytes,
+        keys: Vec<Bytes>,
+        args: Vec<Bytes>,
+        read_only: bool,
+    },
+    FunctionLoad {
+        code: Bytes,
+        replace: bool,
+    },
+    FunctionList {
+        pattern: Option<Bytes>,
+        with_code: bool,
+    },
+    FunctionDelete {
+        library: Bytes,
+    },
+    FunctionDump,
+    FunctionRestore {
+        payload: Bytes,
+        policy: RestorePolicy,
+    },
+    FunctionFlush,
+    FunctionKill,
+    ModuleLoad {
+        path: Bytes,
+    },
+    ModuleUnload {
+        name: Bytes,
+    },
+    ModuleList,
+    // A command a WASM module registered
+    ModuleCall {
+        name: Bytes,
+        args: Vec<Bytes>,
+        read_only: bool,
+    },
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -33,10 +784,49 @@ pub enum CommandError {
     InvalidMsg,
     #[error("Invalid expiry")]
     InvalidExpiry,
+    #[error("Wrong number of arguments for '{0}' command")]
+    WrongArity(&'static str),
+    #[error("Value is not an integer or out of range")]
+    InvalidInteger,
+    #[error("Syntax error")]
+    Syntax,
+    #[error("Value is not a valid float")]
+    InvalidFloat,
+    #[error("Min or max is not a float")]
+    InvalidScoreRange,
+    #[error("Min or max not valid string range item")]
+    InvalidLexRange,
+    #[error("{0}")]
+    InvalidOption(&'static str),
+    #[error("At least 1 input key is needed for '{0}' command")]
+    NoKeys(&'static str),
+    #[error("Invalid stream ID specified as stream command argument")]
+    InvalidStreamId,
+    #[error(
+        "Unbalanced '{0}' list of streams: for each stream key an ID or '$' must be specified."
+    )]
+    UnbalancedStreams(&'static str),
+    #[error("{0}")]
+    InvalidJson(String),
+    #[error("Invalid JSONPath '{0}'")]
+    InvalidJsonPath(String),
+    #[error("CROSSSLOT Keys in request don't hash to the same slot")]
+    CrossSlot,
+    #[error("NOPROTO unsupported protocol version")]
+    NoProto,
 }
 
 impl Command {
     pub fn from_frame(frame: Frame) -> Result<Command, CommandError> {
+        Command::parse(frame, None)
+    }
+
+    // Like `from_frame`, also knowing the commands WASM modules have registered with `db`
+    pub fn from_frame_in(frame: Frame, db: &Db) -> Result<Command, CommandError> {
+        Command::parse(frame, Some(db))
+    }
+
+    fn parse(frame: Frame, modules: Option<&Db>) -> Result<Command, CommandError> {
         match frame {
             Frame::Array(mut frames) => {
                 if frames.is_empty() {
@@ -53,12 +843,378 @@ impl Command {
                     b"SET" => parse_set(&frames),
                     b"PING" => Ok(parse_ping(&frames)),
                     b"ECHO" => parse_echo(&frames),
-                    _ => Err(CommandError::Unknown(String::from_utf8_lossy(&cmd).into())),
+                    b"SADD" => set::parse_sadd(&frames),
+                    b"SREM" => set::parse_srem(&frames),
+                    b"SISMEMBER" => set::parse_sismember(&frames),
+                    b"SMISMEMBER" => set::parse_smismember(&frames),
+                    b"SMEMBERS" => set::parse_smembers(&frames),
+                    b"SCARD" => set::parse_scard(&frames),
+                    b"SPOP" => set::parse_spop(&frames),
+                    b"SRANDMEMBER" => set::parse_srandmember(&frames),
+                    b"SINTER" => set::parse_set_op(&frames, SetOp::Inter, "sinter"),
+                    b"SUNION" => set::parse_set_op(&frames, SetOp::Union, "sunion"),
+                    b"SDIFF" => set::parse_set_op(&frames, SetOp::Diff, "sdiff"),
+                    b"SINTERSTORE" => set::parse_set_op_store(&frames, SetOp::Inter, "sinterstore"),
+                    b"SUNIONSTORE" => set::parse_set_op_store(&frames, SetOp::Union, "sunionstore"),
+                    b"SDIFFSTORE" => set::parse_set_op_store(&frames, SetOp::Diff, "sdiffstore"),
+                    b"SINTERCARD" => set::parse_sintercard(&frames),
+                    b"SMOVE" => set::parse_smove(&frames),
+                    b"HSET" => hash::parse_hset(&frames),
+                    b"HGET" => hash::parse_hget(&frames),
+                    b"HDEL" => hash::parse_hdel(&frames),
+                    b"HGETALL" => hash::parse_hgetall(&frames),
+                    b"HLEN" => hash::parse_hlen(&frames),
+                    b"ZADD" => zset::parse_zadd(&frames),
+                    b"ZINCRBY" => zset::parse_zincrby(&frames),
+                    b"ZREM" => zset::parse_zrem(&frames),
+                    b"ZSCORE" => zset::parse_zscore(&frames),
+                    b"ZMSCORE" => zset::parse_zmscore(&frames),
+                    b"ZCARD" => zset::parse_zcard(&frames),
+                    b"ZRANK" => zset::parse_zrank(&frames, false, "zrank"),
+                    b"ZREVRANK" => zset::parse_zrank(&frames, true, "zrevrank"),
+                    b"ZRANGE" => zset::parse_zrange(&frames, None, "zrange"),
+                    b"ZREVRANGE" => {
+                        zset::parse_zrange(&frames, Some((RangeKind::Rank, true)), "zrevrange")
+                    }
+                    b"ZRANGEBYSCORE" => zset::parse_zrange(
+                        &frames,
+                        Some((RangeKind::Score, false)),
+                        "zrangebyscore",
+                    ),
+                    b"ZREVRANGEBYSCORE" => zset::parse_zrange(
+                        &frames,
+                        Some((RangeKind::Score, true)),
+                        "zrevrangebyscore",
+                    ),
+                    b"ZRANGEBYLEX" => {
+                        zset::parse_zrange(&frames, Some((RangeKind::Lex, false)), "zrangebylex")
+                    }
+                    b"ZREVRANGEBYLEX" => {
+                        zset::parse_zrange(&frames, Some((RangeKind::Lex, true)), "zrevrangebylex")
+                    }
+                    b"ZCOUNT" => zset::parse_zcount(&frames, RangeKind::Score, "zcount"),
+                    b"ZLEXCOUNT" => zset::parse_zcount(&frames, RangeKind::Lex, "zlexcount"),
+                    b"ZPOPMIN" => zset::parse_zpop(&frames, false, "zpopmin"),
+                    b"ZPOPMAX" => zset::parse_zpop(&frames, true, "zpopmax"),
+                    b"ZRANDMEMBER" => zset::parse_zrandmember(&frames),
+                    b"ZUNION" => zset::parse_zset_op(&frames, SetOp::Union, false, "zunion"),
+                    b"ZINTER" => zset::parse_zset_op(&frames, SetOp::Inter, false, "zinter"),
+                    b"ZDIFF" => zset::parse_zset_op(&frames, SetOp::Diff, false, "zdiff"),
+                    b"ZUNIONSTORE" => {
+                        zset::parse_zset_op(&frames, SetOp::Union, true, "zunionstore")
+                    }
+                    b"ZINTERSTORE" => {
+                        zset::parse_zset_op(&frames, SetOp::Inter, true, "zinterstore")
+                    }
+                    b"ZDIFFSTORE" => zset::parse_zset_op(&frames, SetOp::Diff, true, "zdiffstore"),
+                    b"ZRANGESTORE" => zset::parse_zrangestore(&frames),
+                    b"BZPOPMIN" => zset::parse_bzpop(&frames, false, "bzpopmin"),
+                    b"BZPOPMAX" => zset::parse_bzpop(&frames, true, "bzpopmax"),
+                    b"ZMPOP" => zset::parse_zmpop(&frames, false, "zmpop"),
+                    b"BZMPOP" => zset::parse_zmpop(&frames, true, "bzmpop"),
+                    b"XADD" => stream::parse_xadd(&frames),
+                    b"XRANGE" => stream::parse_xrange(&frames, false, "xrange"),
+                    b"XREVRANGE" => stream::parse_xrange(&frames, true, "xrevrange"),
+                    b"XLEN" => stream::parse_xlen(&frames),
+                    b"XDEL" => stream::parse_xdel(&frames),
+                    b"XTRIM" => stream::parse_xtrim(&frames),
+                    b"XREAD" => stream::parse_xread(&frames),
+                    b"XREADGROUP" => stream::parse_xreadgroup(&frames),
+                    b"XGROUP" => stream::parse_xgroup(&frames),
+                    b"XACK" => stream::parse_xack(&frames),
+                    b"XPENDING" => stream::parse_xpending(&frames),
+                    b"XCLAIM" => stream::parse_xclaim(&frames),
+                    b"XAUTOCLAIM" => stream::parse_xautoclaim(&frames),
+                    b"XINFO" => stream::parse_xinfo(&frames),
+                    b"PFADD" => hyperloglog::parse_pfadd(&frames),
+                    b"PFCOUNT" => hyperloglog::parse_pfcount(&frames),
+                    b"PFMERGE" => hyperloglog::parse_pfmerge(&frames),
+                    b"GEOADD" => geo::parse_geoadd(&frames),
+                    b"GEOPOS" => geo::parse_geopos(&frames, false),
+                    b"GEOHASH" => geo::parse_geopos(&frames, true),
+                    b"GEODIST" => geo::parse_geodist(&frames),
+                    b"GEOSEARCH" => geo::parse_geosearch(&frames, false),
+                    b"GEOSEARCHSTORE" => geo::parse_geosearch(&frames, true),
+                    b"BF.RESERVE" => bloom::parse_bf_reserve(&frames),
+                    b"BF.ADD" => bloom::parse_bf_add(&frames),
+                    b"BF.MADD" => bloom::parse_bf_madd(&frames),
+                    b"BF.EXISTS" => bloom::parse_bf_exists(&frames),
+                    b"BF.MEXISTS" => bloom::parse_bf_mexists(&frames),
+                    b"BF.INFO" => bloom::parse_bf_info(&frames),
+                    b"CF.RESERVE" => cuckoo::parse_cf_reserve(&frames),
+                    b"CF.ADD" => cuckoo::parse_cf_add(&frames, false, "cf.add"),
+                    b"CF.ADDNX" => cuckoo::parse_cf_add(&frames, true, "cf.addnx"),
+                    b"CF.DEL" => cuckoo::parse_cf_del(&frames),
+                    b"CF.EXISTS" => cuckoo::parse_cf_exists(&frames),
+                    b"CF.MEXISTS" => cuckoo::parse_cf_mexists(&frames),
+                    b"CF.COUNT" => cuckoo::parse_cf_count(&frames),
+                    b"CMS.INITBYDIM" => cms::parse_cms_initbydim(&frames),
+                    b"CMS.INITBYPROB" => cms::parse_cms_initbyprob(&frames),
+                    b"CMS.INCRBY" => cms::parse_cms_incrby(&frames),
+                    b"CMS.QUERY" => cms::parse_cms_query(&frames),
+                    b"CMS.MERGE" => cms::parse_cms_merge(&frames),
+                    b"CMS.INFO" => cms::parse_cms_info(&frames),
+                    b"TOPK.RESERVE" => topk::parse_topk_reserve(&frames),
+                    b"TOPK.ADD" => topk::parse_topk_add(&frames),
+                    b"TOPK.INCRBY" => topk::parse_topk_incrby(&frames),
+                    b"TOPK.QUERY" => topk::parse_topk_query(&frames),
+                    b"TOPK.LIST" => topk::parse_topk_list(&frames),
+                    b"TOPK.INFO" => topk::parse_topk_info(&frames),
+                    b"TDIGEST.CREATE" => tdigest::parse_tdigest_create(&frames),
+                    b"TDIGEST.ADD" => tdigest::parse_tdigest_add(&frames),
+                    b"TDIGEST.QUANTILE" => tdigest::parse_tdigest_quantile(&frames),
+                    b"TDIGEST.CDF" => tdigest::parse_tdigest_cdf(&frames),
+                    b"TDIGEST.RANK" => tdigest::parse_tdigest_rank(&frames, false, "tdigest.rank"),
+                    b"TDIGEST.REVRANK" => {
+                        tdigest::parse_tdigest_rank(&frames, true, "tdigest.revrank")
+                    }
+                    b"TDIGEST.TRIMMED_MEAN" => tdigest::parse_tdigest_trimmed_mean(&frames),
+                    b"TDIGEST.MIN" => tdigest::parse_tdigest_min_max(&frames, false, "tdigest.min"),
+                    b"TDIGEST.MAX" => tdigest::parse_tdigest_min_max(&frames, true, "tdigest.max"),
+                    b"TDIGEST.MERGE" => tdigest::parse_tdigest_merge(&frames),
+                    b"TDIGEST.INFO" => tdigest::parse_tdigest_info(&frames),
+                    b"JSON.SET" => json::parse_json_set(&frames),
+                    b"JSON.GET" => json::parse_json_get(&frames),
+                    b"JSON.MGET" => json::parse_json_mget(&frames),
+                    b"JSON.DEL" => json::parse_json_del(&frames, "json.del"),
+                    b"JSON.FORGET" => json::parse_json_del(&frames, "json.forget"),
+                    b"JSON.TYPE" => json::parse_json_type(&frames),
+                    b"JSON.OBJKEYS" => json::parse_json_objkeys(&frames),
+                    b"JSON.NUMINCRBY" => json::parse_json_numincrby(&frames),
+                    b"JSON.STRAPPEND" => json::parse_json_strappend(&frames),
+                    b"JSON.ARRAPPEND" => json::parse_json_arrappend(&frames),
+                    b"JSON.ARRINSERT" => json::parse_json_arrinsert(&frames),
+                    b"JSON.ARRPOP" => json::parse_json_arrpop(&frames),
+                    b"JSON.ARRTRIM" => json::parse_json_arrtrim(&frames),
+                    b"TS.CREATE" => timeseries::parse_ts_create(&frames),
+                    b"TS.ADD" => timeseries::parse_ts_add(&frames),
+                    b"TS.GET" => timeseries::parse_ts_get(&frames),
+                    b"TS.RANGE" => timeseries::parse_ts_range(&frames, false, "ts.range"),
+                    b"TS.REVRANGE" => timeseries::parse_ts_range(&frames, true, "ts.revrange"),
+                    b"TS.MRANGE" => timeseries::parse_ts_mrange(&frames, false, "ts.mrange"),
+                    b"TS.MREVRANGE" => timeseries::parse_ts_mrange(&frames, true, "ts.mrevrange"),
+                    b"TS.CREATERULE" => timeseries::parse_ts_createrule(&frames),
+                    b"TS.DELETERULE" => timeseries::parse_ts_deleterule(&frames),
+                    b"TS.INFO" => timeseries::parse_ts_info(&frames),
+                    b"VADD" => vset::parse_vadd(&frames),
+                    b"VSIM" => vset::parse_vsim(&frames),
+                    b"VREM" => vset::parse_vrem(&frames),
+                    b"VCARD" => vset::parse_vcard(&frames),
+                    b"VDIM" => vset::parse_vdim(&frames),
+                    b"VEMB" => vset::parse_vemb(&frames),
+                    b"VSETATTR" => vset::parse_vsetattr(&frames),
+                    b"VGETATTR" => vset::parse_vgetattr(&frames),
+                    b"FT.CREATE" => search::parse_ft_create(&frames),
+                    b"FT.SEARCH" => search::parse_ft_search(&frames),
+                    b"FT.INFO" => search::parse_ft_info(&frames),
+                    b"FT.DROPINDEX" => search::parse_ft_dropindex(&frames),
+                    b"SUBSCRIBE" => pubsub::parse_subscribe(&frames),
+                    b"UNSUBSCRIBE" => pubsub::parse_unsubscribe(&frames),
+                    b"PSUBSCRIBE" => pubsub::parse_psubscribe(&frames),
+                    b"PUNSUBSCRIBE" => pubsub::parse_punsubscribe(&frames),
+                    b"SSUBSCRIBE" => pubsub::parse_ssubscribe(&frames),
+                    b"SUNSUBSCRIBE" => pubsub::parse_sunsubscribe(&frames),
+                    b"PUBLISH" => pubsub::parse_publish(&frames),
+                    b"SPUBLISH" => pubsub::parse_spublish(&frames),
+                    b"PUBSUB" => pubsub::parse_pubsub(&frames),
+                    b"QUIT" => pubsub::parse_quit(&frames),
+                    b"CONFIG" => config::parse_config(&frames),
+                    b"HELLO" => client::parse_hello(&frames),
+                    b"CLIENT" => client::parse_client(&frames),
+                    b"MULTI" => transaction::parse_multi(&frames),
+                    b"EXEC" => transaction::parse_exec(&frames),
+                    b"DISCARD" => transaction::parse_discard(&frames),
+                    b"WATCH" => transaction::parse_watch(&frames),
+                    b"UNWATCH" => transaction::parse_unwatch(&frames),
+                    b"FLUSHDB" => parse_flush(&frames, "flushdb"),
+                    b"FLUSHALL" => parse_flush(&frames, "flushall"),
+                    b"EVAL" => script::parse_eval(&frames),
+                    b"EVALSHA" => script::parse_evalsha(&frames),
+                    b"SCRIPT" => script::parse_script(&frames),
+                    b"FCALL" => script::parse_fcall(&frames, "fcall", false),
+                    b"FCALL_RO" => script::parse_fcall(&frames, "fcall_ro", true),
+                    b"FUNCTION" => script::parse_function(&frames),
+                    b"MODULE" => module::parse_module(&frames),
+                    name => match modules.and_then(|db| db.module_command(name)) {
+                        Some((_, command)) => module::parse_module_call(command, &frames),
+                        None => Err(CommandError::Unknown(String::from_utf8_lossy(&cmd).into())),
+                    },
                 }
             }
             _ => Err(CommandError::ExpectedArray),
         }
     }
+
+    // Commands that may wait for another client's write before replying
+    pub fn is_blocking(&self) -> bool {
+        matches!(
+            self,
+            Command::BZPop { .. }
+                | Command::BZMPop { .. }
+                | Command::XRead { block: true, .. }
+                | Command::XReadGroup { block: true, .. }
+        )
+    }
+
+    // Scripts, functions and module commands, which run on a thread of their own with the gate
+    // held exclusively
+    pub fn is_script(&self) -> bool {
+        matches!(
+            self,
+            Command::Eval { .. }
+                | Command::EvalSha { .. }
+                | Command::FCall { .. }
+                | Command::ModuleCall { .. }
+        )
+    }
+
+    // What a RESP2 connection may still send once it has subscriptions
+    pub fn allowed_when_subscribed(&self) -> bool {
+        matches!(
+            self,
+            Command::Subscribe { .. }
+                | Command::Unsubscribe { .. }
+                | Command::PSubscribe { .. }
+                | Command::PUnsubscribe { .. }
+                | Command::SSubscribe { .. }
+                | Command::SUnsubscribe { .. }
+                | Command::Ping { .. }
+                | Command::Quit
+        )
+    }
+
+    // Commands that may change the keyspace, which a script can't be killed after
+    pub fn is_write(&self) -> bool {
+        matches!(
+            self,
+            Command::Set { .. }
+                | Command::SAdd { .. }
+                | Command::SRem { .. }
+                | Command::SPop { .. }
+                | Command::SetOpStore { .. }
+                | Command::SMove { .. }
+                | Command::HSet { .. }
+                | Command::HDel { .. }
+                | Command::ZAdd { .. }
+                | Command::ZIncrBy { .. }
+                | Command::ZRem { .. }
+                | Command::ZPop { .. }
+                | Command::ZSetOpStore { .. }
+                | Command::ZRangeStore { .. }
+                | Command::BZPop { .. }
+                | Command::ZMPop { .. }
+                | Command::BZMPop { .. }
+                | Command::XAdd { .. }
+                | Command::XDel { .. }
+                | Command::XTrim { .. }
+                | Command::XGroupCreate { .. }
+                | Command::XGroupSetId { .. }
+                | Command::XGroupDestroy { .. }
+                | Command::XGroupCreateConsumer { .. }
+                | Command::XGroupDelConsumer { .. }
+                | Command::XReadGroup { .. }
+                | Command::XAck { .. }
+                | Command::XClaim { .. }
+                | Command::XAutoClaim { .. }
+                | Command::PfAdd { .. }
+                | Command::PfMerge { .. }
+                | Command::GeoAdd { .. }
+                | Command::GeoSearchStore { .. }
+                | Command::BfReserve { .. }
+                | Command::BfAdd { .. }
+                | Command::BfMAdd { .. }
+                | Command::CfReserve { .. }
+                | Command::CfAdd { .. }
+                | Command::CfDel { .. }
+                | Command::CmsInit { .. }
+                | Command::CmsIncrBy { .. }
+                | Command::CmsMerge { .. }
+                | Command::TopKReserve { .. }
+                | Command::TopKIncrBy { .. }
+                | Command::TDigestCreate { .. }
+                | Command::TDigestAdd { .. }
+                | Command::TDigestMerge { .. }
+                | Command::JsonSet { .. }
+                | Command::JsonDel { .. }
+                | Command::JsonNumIncrBy { .. }
+                | Command::JsonStrAppend { .. }
+                | Command::JsonArrAppend { .. }
+                | Command::JsonArrInsert { .. }
+                | Command::JsonArrPop { .. }
+                | Command::JsonArrTrim { .. }
+                | Command::TsCreate { .. }
+                | Command::TsAdd { .. }
+                | Command::TsCreateRule { .. }
+                | Command::TsDeleteRule { .. }
+                | Command::VAdd { .. }
+                | Command::VRem { .. }
+                | Command::VSetAttr { .. }
+                | Command::FtCreate { .. }
+                | Command::FtDropIndex { .. }
+                | Command::Flush
+                | Command::ModuleCall {
+                    read_only: false,
+                    ..
+                }
+        )
+    }
+
+    // Commands that can't be called from a script
+    pub fn is_denied_in_script(&self) -> bool {
+        self.is_connection_state()
+            || matches!(
+                self,
+                Command::Eval { .. }
+                    | Command::EvalSha { .. }
+                    | Command::ScriptLoad { .. }
+                    | Command::ScriptExists { .. }
+                    | Command::ScriptFlush
+                    | Command::ScriptKill
+                    | Command::FCall { .. }
+                    | Command::FunctionLoad { .. }
+                    | Command::FunctionList { .. }
+                    | Command::FunctionDelete { .. }
+                    | Command::FunctionDump
+                    | Command::FunctionRestore { .. }
+                    | Command::FunctionFlush
+                    | Command::FunctionKill
+                    | Command::ModuleLoad { .. }
+                    | Command::ModuleUnload { .. }
+                    | Command::ModuleList
+            )
+    }
+
+    // What other clients may still send while a script is busy
+    pub fn allowed_when_busy(&self) -> bool {
+        matches!(
+            self,
+            Command::ScriptKill | Command::FunctionKill | Command::Quit
+        )
+    }
+
+    // Commands that change the connection rather than the data, which MULTI can't queue
+    pub fn is_connection_state(&self) -> bool {
+        matches!(
+            self,
+            Command::Subscribe { .. }
+                | Command::Unsubscribe { .. }
+                | Command::PSubscribe { .. }
+                | Command::PUnsubscribe { .. }
+                | Command::SSubscribe { .. }
+                | Command::SUnsubscribe { .. }
+                | Command::Quit
+                | Command::Hello { .. }
+                | Command::ClientId
+                | Command::ClientGetRedir
+                | Command::ClientCaching { .. }
+                | Command::ClientTracking { .. }
+                | Command::Multi
+                | Command::Exec
+                | Command::Discard
+                | Command::Watch { .. }
+                | Command::Unwatch
+        )
+    }
 }
 
 fn parse_get(frames: &[Frame]) -> Result<Command, CommandError> {
@@ -69,6 +1225,14 @@ fn parse_get(frames: &[Frame]) -> Result<Command, CommandError> {
     }
 }
 
+// FLUSHDB | FLUSHALL [ASYNC | SYNC], which both flush synchronously
+fn parse_flush(frames: &[Frame], name: &'static str) -> Result<Command, CommandError> {
+    let mut args = Args::new(name, frames);
+    let _ = args.eat("ASYNC") || args.eat("SYNC");
+    args.finish()?;
+    Ok(Command::Flush)
+}
+
 fn parse_set(frames: &[Frame]) -> Result<Command, CommandError> {
     if let [Frame::BulkString(key), Frame::BulkString(value), rest @ ..] = frames {
         let expiry = match rest {
@@ -112,3 +1276,104 @@ fn parse_echo(frames: &[Frame]) -> Result<Command, CommandError> {
         Err(CommandError::InvalidMsg)
     }
 }
+
+// Walks the arguments of a command, producing errors named after the command
+struct Args<'a> {
+    name: &'static str,
+    frames: std::slice::Iter<'a, Frame>,
+}
+
+impl<'a> Args<'a> {
+    fn new(name: &'static str, frames: &'a [Frame]) -> Args<'a> {
+        Args {
+            name,
+            frames: frames.iter(),
+        }
+    }
+
+    fn remaining(&self) -> usize {
+        self.frames.len()
+    }
+
+    fn next_bytes(&mut self) -> Result<Bytes, CommandError> {
+        match self.frames.next() {
+            Some(Frame::BulkString(b)) => Ok(b.clone()),
+            Some(_) => Err(CommandError::InvalidMsg),
+            None => Err(CommandError::WrongArity(self.name)),
+        }
+    }
+
+    fn next_int<T: FromStr>(&mut self) -> Result<T, CommandError> {
+        parse_int(&self.next_bytes()?)
+    }
+
+    fn next_float(&mut self) -> Result<f64, CommandError> {
+        parse_float(&self.next_bytes()?)
+    }
+
+    // Consume the next argument if it matches `keyword`, ignoring case
+    fn eat(&mut self, keyword: &str) -> bool {
+        match self.frames.as_slice().first() {
+            Some(Frame::BulkString(b)) if b.eq_ignore_ascii_case(keyword.as_bytes()) => {
+                self.frames.next();
+                true
+            }
+            _ => false,
+        }
+    }
+
+    // Collect every remaining argument, requiring at least one
+    fn rest(&mut self) -> Result<Vec<Bytes>, CommandError> {
+        if self.remaining() == 0 {
+            return Err(CommandError::WrongArity(self.name));
+        }
+        let mut out = Vec::with_capacity(self.remaining());
+        while self.remaining() > 0 {
+            out.push(self.next_bytes()?);
+        }
+        Ok(out)
+    }
+
+    fn finish(&self) -> Result<(), CommandError> {
+        if self.remaining() == 0 {
+            Ok(())
+        } else {
+            Err(CommandError::Syntax)
+        }
+    }
+}
+
+fn parse_int<T: FromStr>(bytes: &[u8]) -> Result<T, CommandError> {
+    std::str::from_utf8(bytes)
+        .ok()
+        .and_then(|s| s.parse().ok())
+        .ok_or(CommandError::InvalidInteger)
+}
+
+// Accepts `inf`, `+inf` and `-inf` but never NaN
+fn parse_float(bytes: &[u8]) -> Result<f64, CommandError> {
+    std::str::from_utf8(bytes)
+        .ok()
+        .and_then(|s| s.parse::<f64>().ok())
+        .filter(|f| !f.is_nan())
+        .ok_or(CommandError::InvalidFloat)
+}
+
+// Timeouts are seconds as a float, where 0 means wait forever
+fn parse_timeout(bytes: &[u8]) -> Result<Option<Duration>, CommandError> {
+    let secs = parse_float(bytes)
+        .ok()
+        .filter(|secs| secs.is_finite())
+        .ok_or(CommandError::InvalidOption(
+            "timeout is not a float or out of range",
+        ))?;
+    if secs < 0.0 {
+        return Err(CommandError::InvalidOption("timeout is negative"));
+    }
+    if secs == 0.0 {
+        return Ok(None);
+    }
+    Duration::try_from_secs_f64(secs)
+        .map(Some)
+        .map_err(|_| CommandError::InvalidOption("timeout is out of range"))
+}
@@ -0,0 +1,129 @@
+use super::{Args, Command, CommandError};
+use crate::Frame;
+use crate::db::BloomInfoField;
+use bytes::Bytes;
+
+// Larger filters are refused rather than allocated, as RedisBloom does
+pub(super) const MAX_CAPACITY: u64 = 1 << 30;
+const MAX_EXPANSION: u64 = 32768;
+
+// BF.RESERVE key error_rate capacity [EXPANSION expansion] [NONSCALING]
+pub(super) fn parse_bf_reserve(frames: &[Frame]) -> Result<Command, CommandError> {
+    let mut args = Args::new("bf.reserve", frames);
+    let key = args.next_bytes()?;
+    let error_rate = args.next_float()?;
+    if !(error_rate > 0.0 && error_rate < 1.0) {
+        return Err(CommandError::InvalidOption("(0 < error rate range < 1)"));
+    }
+    let capacity = args.next_int::<u64>()?;
+    if capacity == 0 {
+        return Err(CommandError::InvalidOption(
+            "(capacity should be larger than 0)",
+        ));
+    }
+    if capacity > MAX_CAPACITY {
+        return Err(CommandError::InvalidOption(
+            "(capacity should be at most 1073741824)",
+        ));
+    }
+
+    let mut expansion = None;
+    let mut nonscaling = false;
+    loop {
+        if args.eat("EXPANSION") {
+            let n = args.next_int::<u64>()?;
+            if n == 0 {
+                return Err(CommandError::InvalidOption(
+                    "expansion should be greater or equal to 1",
+                ));
+            }
+            if n > MAX_EXPANSION {
+                return Err(CommandError::InvalidOption(
+                    "expansion should be at most 32768",
+                ));
+            }
+            expansion = Some(n);
+        } else if args.eat("NONSCALING") {
+            nonscaling = true;
+        } else {
+            break;
+        }
+    }
+    args.finish()?;
+    if nonscaling && expansion.is_some() {
+        return Err(CommandError::InvalidOption(
+            "nonscaling filters cannot expand",
+        ));
+    }
+
+    Ok(Command::BfReserve {
+        key,
+        error_rate,
+        capacity,
+        expansion: (!nonscaling).then_some(expansion.unwrap_or(2)),
+    })
+}
+
+pub(super) fn parse_bf_add(frames: &[Frame]) -> Result<Command, CommandError> {
+    let (key, item) = key_item(frames, "bf.add")?;
+    Ok(Command::BfAdd { key, item })
+}
+
+pub(super) fn parse_bf_madd(frames: &[Frame]) -> Result<Command, CommandError> {
+    let (key, items) = key_items(frames, "bf.madd")?;
+    Ok(Command::BfMAdd { key, items })
+}
+
+pub(super) fn parse_bf_exists(frames: &[Frame]) -> Result<Command, CommandError> {
+    let (key, item) = key_item(frames, "bf.exists")?;
+    Ok(Command::BfExists { key, item })
+}
+
+pub(super) fn parse_bf_mexists(frames: &[Frame]) -> Result<Command, CommandError> {
+    let (key, items) = key_items(frames, "bf.mexists")?;
+    Ok(Command::BfMExists { key, items })
+}
+
+// BF.INFO key [CAPACITY | SIZE | FILTERS | ITEMS | EXPANSION]
+pub(super) fn parse_bf_info(frames: &[Frame]) -> Result<Command, CommandError> {
+    let mut args = Args::new("bf.info", frames);
+    let key = args.next_bytes()?;
+    let field = if args.remaining() == 0 {
+        None
+    } else if args.eat("CAPACITY") {
+        Some(BloomInfoField::Capacity)
+    } else if args.eat("SIZE") {
+        Some(BloomInfoField::Size)
+    } else if args.eat("FILTERS") {
+        Some(BloomInfoField::Filters)
+    } else if args.eat("ITEMS") {
+        Some(BloomInfoField::Items)
+    } else if args.eat("EXPANSION") {
+        Some(BloomInfoField::Expansion)
+    } else {
+        return Err(CommandError::InvalidOption("Invalid information value"));
+    };
+    args.finish()?;
+    Ok(Command::BfInfo { key, field })
+}
+
+// Shared by the filter commands that take a key and one item
+pub(super) fn key_item(
+    frames: &[Frame],
+    name: &'static str,
+) -> Result<(Bytes, Bytes), CommandError> {
+    let mut args = Args::new(name, frames);
+    let key = args.next_bytes()?;
+    let item = args.next_bytes()?;
+    args.finish()?;
+    Ok((key, item))
+}
+
+pub(super) fn key_items(
+    frames: &[Frame],
+    name: &'static str,
+) -> Result<(Bytes, Vec<Bytes>), CommandError> {
+    let mut args = Args::new(name, frames);
+    let key = args.next_bytes()?;
+    Ok((key, args.rest()?))
+}
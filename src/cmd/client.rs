@@ -0,0 +1,91 @@
+use super::{Args, Command, CommandError};
+use crate::Frame;
+use crate::db::TrackingOptions;
+
+// CLIENT ID | GETREDIR | CACHING YES|NO | TRACKING ON|OFF [REDIRECT id] [PREFIX prefix ...]
+//     [BCAST] [OPTIN] [OPTOUT] [NOLOOP]
+pub(super) fn parse_client(frames: &[Frame]) -> Result<Command, CommandError> {
+    let mut args = Args::new("client", frames);
+    let sub = args.next_bytes()?.to_ascii_uppercase();
+    match sub.as_slice() {
+        b"ID" => {
+            args.finish()?;
+            Ok(Command::ClientId)
+        }
+        b"GETREDIR" => {
+            args.finish()?;
+            Ok(Command::ClientGetRedir)
+        }
+        b"CACHING" => {
+            let yes = on_off(&mut args, "YES", "NO")?;
+            args.finish()?;
+            Ok(Command::ClientCaching { yes })
+        }
+        b"TRACKING" => parse_tracking(&mut args),
+        _ => Err(CommandError::Syntax),
+    }
+}
+
+fn parse_tracking(args: &mut Args) -> Result<Command, CommandError> {
+    let on = on_off(args, "ON", "OFF")?;
+    let mut options = TrackingOptions::default();
+    while args.remaining() > 0 {
+        if args.eat("REDIRECT") {
+            options.redirect = Some(args.next_int()?);
+        } else if args.eat("PREFIX") {
+            options.prefixes.push(args.next_bytes()?);
+        } else if args.eat("BCAST") {
+            options.bcast = true;
+        } else if args.eat("OPTIN") {
+            options.optin = true;
+        } else if args.eat("OPTOUT") {
+            options.optout = true;
+        } else if args.eat("NOLOOP") {
+            options.noloop = true;
+        } else {
+            return Err(CommandError::Syntax);
+        }
+    }
+
+    if options.optin && options.optout {
+        return Err(CommandError::InvalidOption(
+            "You can't use both OPTIN and OPTOUT",
+        ));
+    }
+    if options.bcast && (options.optin || options.optout) {
+        return Err(CommandError::InvalidOption(
+            "OPTIN and OPTOUT are not compatible with BCAST",
+        ));
+    }
+    if !options.bcast && !options.prefixes.is_empty() {
+        return Err(CommandError::InvalidOption(
+            "PREFIX option requires BCAST mode to be enabled",
+        ));
+    }
+    Ok(Command::ClientTracking { on, options })
+}
+
+fn on_off(args: &mut Args, on: &str, off: &str) -> Result<bool, CommandError> {
+    if args.eat(on) {
+        Ok(true)
+    } else if args.eat(off) {
+        Ok(false)
+    } else {
+        args.next_bytes()?;
+        Err(CommandError::Syntax)
+    }
+}
+
+// HELLO [protover], where only RESP2 and RESP3 are known
+pub(super) fn parse_hello(frames: &[Frame]) -> Result<Command, CommandError> {
+    let mut args = Args::new("hello", frames);
+    let protover = match args.remaining() {
+        0 => None,
+        _ => match args.next_int()? {
+            version @ (2 | 3) => Some(version),
+            _ => return Err(CommandError::NoProto),
+        },
+    };
+    args.finish()?;
+    Ok(Command::Hello { protover })
+}
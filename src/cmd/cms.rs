@@ -0,0 +1,113 @@
+use super::{Args, Command, CommandError};
+use crate::Frame;
+use crate::db::MAX_CMS_COUNTERS;
+use bytes::Bytes;
+
+// CMS.INITBYDIM key width depth
+pub(super) fn parse_cms_initbydim(frames: &[Frame]) -> Result<Command, CommandError> {
+    let mut args = Args::new("cms.initbydim", frames);
+    let key = args.next_bytes()?;
+    let width = args.next_int::<usize>()?;
+    if width == 0 {
+        return Err(CommandError::InvalidOption("CMS: invalid width"));
+    }
+    let depth = args.next_int::<usize>()?;
+    if depth == 0 {
+        return Err(CommandError::InvalidOption("CMS: invalid depth"));
+    }
+    args.finish()?;
+    cms_init(key, width, depth)
+}
+
+// CMS.INITBYPROB key error probability, sized the way RedisBloom does
+pub(super) fn parse_cms_initbyprob(frames: &[Frame]) -> Result<Command, CommandError> {
+    let mut args = Args::new("cms.initbyprob", frames);
+    let key = args.next_bytes()?;
+    let error = args.next_float()?;
+    if !(error > 0.0 && error < 1.0) {
+        return Err(CommandError::InvalidOption(
+            "CMS: invalid overestimation value",
+        ));
+    }
+    let probability = args.next_float()?;
+    if !(probability > 0.0 && probability < 1.0) {
+        return Err(CommandError::InvalidOption("CMS: invalid prob value"));
+    }
+    args.finish()?;
+
+    let width = (2.0 / error).ceil() as usize;
+    let depth = (probability.ln() / 0.5f64.ln()).ceil() as usize;
+    cms_init(key, width, depth)
+}
+
+// Sketches too big to allocate are refused up front
+fn cms_init(key: Bytes, width: usize, depth: usize) -> Result<Command, CommandError> {
+    if width
+        .checked_mul(depth)
+        .is_none_or(|counters| counters > MAX_CMS_COUNTERS)
+    {
+        return Err(CommandError::InvalidOption(
+            "CMS: width * depth is too large",
+        ));
+    }
+    Ok(Command::CmsInit { key, width, depth })
+}
+
+// CMS.INCRBY key item increment [item increment ...]
+pub(super) fn parse_cms_incrby(frames: &[Frame]) -> Result<Command, CommandError> {
+    let mut args = Args::new("cms.incrby", frames);
+    let key = args.next_bytes()?;
+    if args.remaining() == 0 || !args.remaining().is_multiple_of(2) {
+        return Err(CommandError::WrongArity("cms.incrby"));
+    }
+    let mut items = Vec::with_capacity(args.remaining() / 2);
+    while args.remaining() > 0 {
+        let item = args.next_bytes()?;
+        items.push((item, args.next_int()?));
+    }
+    Ok(Command::CmsIncrBy { key, items })
+}
+
+pub(super) fn parse_cms_query(frames: &[Frame]) -> Result<Command, CommandError> {
+    let mut args = Args::new("cms.query", frames);
+    let key = args.next_bytes()?;
+    let items = args.rest()?;
+    Ok(Command::CmsQuery { key, items })
+}
+
+// CMS.MERGE destination numKeys source [source ...] [WEIGHTS weight [weight ...]]
+pub(super) fn parse_cms_merge(frames: &[Frame]) -> Result<Command, CommandError> {
+    let mut args = Args::new("cms.merge", frames);
+    let destination = args.next_bytes()?;
+    let numkeys = args.next_int::<usize>()?;
+    if numkeys == 0 || numkeys > args.remaining() {
+        return Err(CommandError::InvalidOption("CMS: invalid numkeys"));
+    }
+    let keys: Vec<Bytes> = (0..numkeys)
+        .map(|_| args.next_bytes())
+        .collect::<Result<_, _>>()?;
+
+    let weights = if args.eat("WEIGHTS") {
+        if args.remaining() != numkeys {
+            return Err(CommandError::WrongArity("cms.merge"));
+        }
+        (0..numkeys)
+            .map(|_| args.next_int())
+            .collect::<Result<_, _>>()?
+    } else {
+        vec![1; numkeys]
+    };
+    args.finish()?;
+
+    Ok(Command::CmsMerge {
+        destination,
+        sources: keys.into_iter().zip(weights).collect(),
+    })
+}
+
+pub(super) fn parse_cms_info(frames: &[Frame]) -> Result<Command, CommandError> {
+    let mut args = Args::new("cms.info", frames);
+    let key = args.next_bytes()?;
+    args.finish()?;
+    Ok(Command::CmsInfo { key })
+}
@@ -0,0 +1,25 @@
+use super::{Args, Command, CommandError};
+use crate::Frame;
+
+// CONFIG GET pattern [pattern ...] | SET parameter value [parameter value ...]
+pub(super) fn parse_config(frames: &[Frame]) -> Result<Command, CommandError> {
+    let mut args = Args::new("config", frames);
+    let sub = args.next_bytes()?.to_ascii_uppercase();
+    match sub.as_slice() {
+        b"GET" => Ok(Command::ConfigGet {
+            patterns: args.rest()?,
+        }),
+        b"SET" => {
+            let rest = args.rest()?;
+            if rest.len() % 2 != 0 {
+                return Err(CommandError::WrongArity("config|set"));
+            }
+            let pairs = rest
+                .chunks_exact(2)
+                .map(|pair| (pair[0].clone(), pair[1].clone()))
+                .collect();
+            Ok(Command::ConfigSet { pairs })
+        }
+        _ => Err(CommandError::Syntax),
+    }
+}
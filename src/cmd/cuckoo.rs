@@ -0,0 +1,83 @@
+use super::bloom::{MAX_CAPACITY, key_item, key_items};
+use super::{Args, Command, CommandError};
+use crate::Frame;
+use crate::db::CuckooOptions;
+
+// CF.RESERVE key capacity [BUCKETSIZE bucketsize] [MAXITERATIONS maxiterations]
+//   [EXPANSION expansion]
+pub(super) fn parse_cf_reserve(frames: &[Frame]) -> Result<Command, CommandError> {
+    let mut args = Args::new("cf.reserve", frames);
+    let key = args.next_bytes()?;
+    let mut options = CuckooOptions {
+        capacity: args.next_int()?,
+        ..Default::default()
+    };
+    if options.capacity == 0 || options.capacity > MAX_CAPACITY {
+        return Err(CommandError::InvalidOption("Bad capacity"));
+    }
+
+    loop {
+        if args.eat("BUCKETSIZE") {
+            options.bucket_size = match args.next_int::<u8>() {
+                Ok(n) if n > 0 => n,
+                _ => {
+                    return Err(CommandError::InvalidOption(
+                        "BUCKETSIZE must be in the range [1, 255]",
+                    ));
+                }
+            };
+        } else if args.eat("MAXITERATIONS") {
+            options.max_iterations = match args.next_int::<u16>() {
+                Ok(n) if n > 0 => n,
+                _ => {
+                    return Err(CommandError::InvalidOption(
+                        "MAXITERATIONS must be in the range [1, 65535]",
+                    ));
+                }
+            };
+        } else if args.eat("EXPANSION") {
+            options.expansion = match args.next_int::<u16>() {
+                Ok(n) if n <= 32768 => n,
+                _ => {
+                    return Err(CommandError::InvalidOption(
+                        "EXPANSION must be in the range [0, 32768]",
+                    ));
+                }
+            };
+        } else {
+            break;
+        }
+    }
+    args.finish()?;
+
+    Ok(Command::CfReserve { key, options })
+}
+
+pub(super) fn parse_cf_add(
+    frames: &[Frame],
+    nx: bool,
+    name: &'static str,
+) -> Result<Command, CommandError> {
+    let (key, item) = key_item(frames, name)?;
+    Ok(Command::CfAdd { key, item, nx })
+}
+
+pub(super) fn parse_cf_del(frames: &[Frame]) -> Result<Command, CommandError> {
+    let (key, item) = key_item(frames, "cf.del")?;
+    Ok(Command::CfDel { key, item })
+}
+
+pub(super) fn parse_cf_exists(frames: &[Frame]) -> Result<Command, CommandError> {
+    let (key, item) = key_item(frames, "cf.exists")?;
+    Ok(Command::CfExists { key, item })
+}
+
+pub(super) fn parse_cf_mexists(frames: &[Frame]) -> Result<Command, CommandError> {
+    let (key, items) = key_items(frames, "cf.mexists")?;
+    Ok(Command::CfMExists { key, items })
+}
+
+pub(super) fn parse_cf_count(frames: &[Frame]) -> Result<Command, CommandError> {
+    let (key, item) = key_item(frames, "cf.count")?;
+    Ok(Command::CfCount { key, item })
+}
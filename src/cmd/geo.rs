@@ -0,0 +1,208 @@
+use super::{Args, Command, CommandError};
+use crate::Frame;
+use crate::db::{GeoFrom, GeoQuery, GeoShape, GeoSort, GeoUnit, ZAddCondition, ZAddFlags};
+
+// GEOADD key [NX|XX] [CH] longitude latitude member [longitude latitude member ...]
+pub(super) fn parse_geoadd(frames: &[Frame]) -> Result<Command, CommandError> {
+    let mut args = Args::new("geoadd", frames);
+    let key = args.next_bytes()?;
+
+    let mut flags = ZAddFlags::default();
+    loop {
+        if args.eat("NX") {
+            if flags.condition == Some(ZAddCondition::Xx) {
+                return Err(CommandError::InvalidOption(
+                    "XX and NX options at the same time are not compatible",
+                ));
+            }
+            flags.condition = Some(ZAddCondition::Nx);
+        } else if args.eat("XX") {
+            if flags.condition == Some(ZAddCondition::Nx) {
+                return Err(CommandError::InvalidOption(
+                    "XX and NX options at the same time are not compatible",
+                ));
+            }
+            flags.condition = Some(ZAddCondition::Xx);
+        } else if args.eat("CH") {
+            flags.ch = true;
+        } else {
+            break;
+        }
+    }
+
+    if args.remaining() == 0 || !args.remaining().is_multiple_of(3) {
+        return Err(CommandError::WrongArity("geoadd"));
+    }
+    let mut points = Vec::with_capacity(args.remaining() / 3);
+    while args.remaining() > 0 {
+        let lon = args.next_float()?;
+        let lat = args.next_float()?;
+        points.push((lon, lat, args.next_bytes()?));
+    }
+
+    Ok(Command::GeoAdd { key, flags, points })
+}
+
+// GEOPOS and GEOHASH both take a key and members
+pub(super) fn parse_geopos(frames: &[Frame], hash: bool) -> Result<Command, CommandError> {
+    let name = if hash { "geohash" } else { "geopos" };
+    let mut args = Args::new(name, frames);
+    let key = args.next_bytes()?;
+    let members = if args.remaining() > 0 {
+        args.rest()?
+    } else {
+        Vec::new()
+    };
+    Ok(if hash {
+        Command::GeoHash { key, members }
+    } else {
+        Command::GeoPos { key, members }
+    })
+}
+
+// GEODIST key member1 member2 [M|KM|FT|MI]
+pub(super) fn parse_geodist(frames: &[Frame]) -> Result<Command, CommandError> {
+    let mut args = Args::new("geodist", frames);
+    let key = args.next_bytes()?;
+    let a = args.next_bytes()?;
+    let b = args.next_bytes()?;
+    let unit = if args.remaining() > 0 {
+        parse_unit(&mut args)?
+    } else {
+        GeoUnit::Meters
+    };
+    args.finish()?;
+    Ok(Command::GeoDist { key, a, b, unit })
+}
+
+// GEOSEARCH key FROMMEMBER member|FROMLONLAT lon lat BYRADIUS radius unit|BYBOX width height
+// unit [ASC|DESC] [COUNT count [ANY]] [WITHCOORD] [WITHDIST] [WITHHASH]
+//
+// GEOSEARCHSTORE takes a destination first, [STOREDIST] instead of the WITH options
+pub(super) fn parse_geosearch(frames: &[Frame], store: bool) -> Result<Command, CommandError> {
+    let name = if store { "geosearchstore" } else { "geosearch" };
+    let mut args = Args::new(name, frames);
+    let destination = if store {
+        Some(args.next_bytes()?)
+    } else {
+        None
+    };
+    let key = args.next_bytes()?;
+
+    let mut from = None;
+    let mut shape = None;
+    let mut unit = GeoUnit::Meters;
+    let mut sort = None;
+    let mut count = None;
+    let mut any = false;
+    let (mut with_coord, mut with_dist, mut with_hash, mut store_dist) =
+        (false, false, false, false);
+    while args.remaining() > 0 {
+        if args.eat("FROMMEMBER") {
+            if from.is_some() {
+                return Err(CommandError::InvalidOption(
+                    "exactly one of FROMMEMBER or FROMLONLAT can be specified for geosearch",
+                ));
+            }
+            from = Some(GeoFrom::Member(args.next_bytes()?));
+        } else if args.eat("FROMLONLAT") {
+            if from.is_some() {
+                return Err(CommandError::InvalidOption(
+                    "exactly one of FROMMEMBER or FROMLONLAT can be specified for geosearch",
+                ));
+            }
+            from = Some(GeoFrom::LonLat(args.next_float()?, args.next_float()?));
+        } else if args.eat("BYRADIUS") {
+            if shape.is_some() {
+                return Err(CommandError::InvalidOption(
+                    "exactly one of BYRADIUS and BYBOX can be specified for geosearch",
+                ));
+            }
+            let radius = args.next_float()?;
+            if radius < 0.0 {
+                return Err(CommandError::InvalidOption("radius cannot be negative"));
+            }
+            shape = Some(GeoShape::Radius(radius));
+            unit = parse_unit(&mut args)?;
+        } else if args.eat("BYBOX") {
+            if shape.is_some() {
+                return Err(CommandError::InvalidOption(
+                    "exactly one of BYRADIUS and BYBOX can be specified for geosearch",
+                ));
+            }
+            let width = args.next_float()?;
+            let height = args.next_float()?;
+            if width < 0.0 || height < 0.0 {
+                return Err(CommandError::InvalidOption(
+                    "height or width cannot be negative",
+                ));
+            }
+            shape = Some(GeoShape::Box { width, height });
+            unit = parse_unit(&mut args)?;
+        } else if args.eat("ASC") {
+            sort = Some(GeoSort::Asc);
+        } else if args.eat("DESC") {
+            sort = Some(GeoSort::Desc);
+        } else if args.eat("COUNT") {
+            let n = args.next_int::<i64>()?;
+            if n <= 0 {
+                return Err(CommandError::InvalidOption("COUNT must be > 0"));
+            }
+            count = Some(n as usize);
+            any = args.eat("ANY");
+        } else if !store && args.eat("WITHCOORD") {
+            with_coord = true;
+        } else if !store && args.eat("WITHDIST") {
+            with_dist = true;
+        } else if !store && args.eat("WITHHASH") {
+            with_hash = true;
+        } else if store && args.eat("STOREDIST") {
+            store_dist = true;
+        } else {
+            return Err(CommandError::Syntax);
+        }
+    }
+
+    let from = from.ok_or(CommandError::InvalidOption(
+        "exactly one of FROMMEMBER or FROMLONLAT can be specified for geosearch",
+    ))?;
+    let shape = shape.ok_or(CommandError::InvalidOption(
+        "exactly one of BYRADIUS and BYBOX can be specified for geosearch",
+    ))?;
+    let query = GeoQuery {
+        from,
+        shape,
+        unit,
+        sort,
+        count,
+        any,
+    };
+
+    Ok(match destination {
+        Some(destination) => Command::GeoSearchStore {
+            destination,
+            source: key,
+            query,
+            store_dist,
+        },
+        None => Command::GeoSearch {
+            key,
+            query,
+            with_coord,
+            with_dist,
+            with_hash,
+        },
+    })
+}
+
+fn parse_unit(args: &mut Args) -> Result<GeoUnit, CommandError> {
+    match args.next_bytes()?.to_ascii_lowercase().as_slice() {
+        b"m" => Ok(GeoUnit::Meters),
+        b"km" => Ok(GeoUnit::Kilometers),
+        b"mi" => Ok(GeoUnit::Miles),
+        b"ft" => Ok(GeoUnit::Feet),
+        _ => Err(CommandError::InvalidOption(
+            "unsupported unit provided. please use M, KM, FT, MI",
+        )),
+    }
+}
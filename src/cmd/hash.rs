@@ -0,0 +1,45 @@
+use super::{Args, Command, CommandError};
+use crate::Frame;
+
+// HSET key field value [field value ...]
+pub(super) fn parse_hset(frames: &[Frame]) -> Result<Command, CommandError> {
+    let mut args = Args::new("hset", frames);
+    let key = args.next_bytes()?;
+    if args.remaining() == 0 || !args.remaining().is_multiple_of(2) {
+        return Err(CommandError::WrongArity("hset"));
+    }
+    let mut pairs = Vec::with_capacity(args.remaining() / 2);
+    while args.remaining() > 0 {
+        pairs.push((args.next_bytes()?, args.next_bytes()?));
+    }
+    Ok(Command::HSet { key, pairs })
+}
+
+pub(super) fn parse_hget(frames: &[Frame]) -> Result<Command, CommandError> {
+    let mut args = Args::new("hget", frames);
+    let key = args.next_bytes()?;
+    let field = args.next_bytes()?;
+    args.finish()?;
+    Ok(Command::HGet { key, field })
+}
+
+pub(super) fn parse_hdel(frames: &[Frame]) -> Result<Command, CommandError> {
+    let mut args = Args::new("hdel", frames);
+    let key = args.next_bytes()?;
+    let fields = args.rest()?;
+    Ok(Command::HDel { key, fields })
+}
+
+pub(super) fn parse_hgetall(frames: &[Frame]) -> Result<Command, CommandError> {
+    let mut args = Args::new("hgetall", frames);
+    let key = args.next_bytes()?;
+    args.finish()?;
+    Ok(Command::HGetAll { key })
+}
+
+pub(super) fn parse_hlen(frames: &[Frame]) -> Result<Command, CommandError> {
+    let mut args = Args::new("hlen", frames);
+    let key = args.next_bytes()?;
+    args.finish()?;
+    Ok(Command::HLen { key })
+}
@@ -0,0 +1,34 @@
+use super::{Args, Command, CommandError};
+use crate::Frame;
+
+// PFADD key [element ...]
+pub(super) fn parse_pfadd(frames: &[Frame]) -> Result<Command, CommandError> {
+    let mut args = Args::new("pfadd", frames);
+    let key = args.next_bytes()?;
+    let elements = if args.remaining() > 0 {
+        args.rest()?
+    } else {
+        Vec::new()
+    };
+    Ok(Command::PfAdd { key, elements })
+}
+
+pub(super) fn parse_pfcount(frames: &[Frame]) -> Result<Command, CommandError> {
+    let keys = Args::new("pfcount", frames).rest()?;
+    Ok(Command::PfCount { keys })
+}
+
+// PFMERGE destkey [sourcekey ...]
+pub(super) fn parse_pfmerge(frames: &[Frame]) -> Result<Command, CommandError> {
+    let mut args = Args::new("pfmerge", frames);
+    let destination = args.next_bytes()?;
+    let sources = if args.remaining() > 0 {
+        args.rest()?
+    } else {
+        Vec::new()
+    };
+    Ok(Command::PfMerge {
+        destination,
+        sources,
+    })
+}
@@ -0,0 +1,194 @@
+use super::{Args, Command, CommandError};
+use crate::Frame;
+use crate::db::{JsonCondition, JsonFormat, JsonPath};
+use bytes::Bytes;
+use serde_json::Value as Json;
+
+// JSON.SET key path value [NX | XX]
+pub(super) fn parse_json_set(frames: &[Frame]) -> Result<Command, CommandError> {
+    let mut args = Args::new("json.set", frames);
+    let key = args.next_bytes()?;
+    let path = parse_path(&args.next_bytes()?)?;
+    let value = parse_json(&args.next_bytes()?)?;
+    let condition = if args.eat("NX") {
+        Some(JsonCondition::Nx)
+    } else if args.eat("XX") {
+        Some(JsonCondition::Xx)
+    } else {
+        None
+    };
+    args.finish()?;
+    Ok(Command::JsonSet {
+        key,
+        path,
+        value,
+        condition,
+    })
+}
+
+// JSON.GET key [INDENT indent] [NEWLINE newline] [SPACE space] [path ...]
+pub(super) fn parse_json_get(frames: &[Frame]) -> Result<Command, CommandError> {
+    let mut args = Args::new("json.get", frames);
+    let key = args.next_bytes()?;
+    let mut format = JsonFormat::default();
+    let mut paths = Vec::new();
+    while args.remaining() > 0 {
+        let text = |bytes: Bytes| String::from_utf8_lossy(&bytes).into_owned();
+        if args.eat("INDENT") {
+            format.indent = text(args.next_bytes()?);
+        } else if args.eat("NEWLINE") {
+            format.newline = text(args.next_bytes()?);
+        } else if args.eat("SPACE") {
+            format.space = text(args.next_bytes()?);
+        } else {
+            paths.push(parse_path(&args.next_bytes()?)?);
+        }
+    }
+    Ok(Command::JsonGet { key, paths, format })
+}
+
+// JSON.MGET key [key ...] path
+pub(super) fn parse_json_mget(frames: &[Frame]) -> Result<Command, CommandError> {
+    let mut args = Args::new("json.mget", frames);
+    let mut keys = args.rest()?;
+    if keys.len() < 2 {
+        return Err(CommandError::WrongArity("json.mget"));
+    }
+    let path = keys
+        .pop()
+        .map_or(Ok(JsonPath::root()), |p| parse_path(&p))?;
+    Ok(Command::JsonMGet { keys, path })
+}
+
+// JSON.DEL and JSON.FORGET key [path]
+pub(super) fn parse_json_del(
+    frames: &[Frame],
+    name: &'static str,
+) -> Result<Command, CommandError> {
+    let (key, path) = key_and_path(frames, name)?;
+    Ok(Command::JsonDel { key, path })
+}
+
+// JSON.TYPE key [path]
+pub(super) fn parse_json_type(frames: &[Frame]) -> Result<Command, CommandError> {
+    let (key, path) = key_and_path(frames, "json.type")?;
+    Ok(Command::JsonType { key, path })
+}
+
+// JSON.OBJKEYS key [path]
+pub(super) fn parse_json_objkeys(frames: &[Frame]) -> Result<Command, CommandError> {
+    let (key, path) = key_and_path(frames, "json.objkeys")?;
+    Ok(Command::JsonObjKeys { key, path })
+}
+
+// JSON.NUMINCRBY key path value
+pub(super) fn parse_json_numincrby(frames: &[Frame]) -> Result<Command, CommandError> {
+    let mut args = Args::new("json.numincrby", frames);
+    let key = args.next_bytes()?;
+    let path = parse_path(&args.next_bytes()?)?;
+    let Json::Number(by) = parse_json(&args.next_bytes()?)? else {
+        return Err(CommandError::InvalidFloat);
+    };
+    args.finish()?;
+    Ok(Command::JsonNumIncrBy { key, path, by })
+}
+
+// JSON.STRAPPEND key [path] value, where value is a JSON string
+pub(super) fn parse_json_strappend(frames: &[Frame]) -> Result<Command, CommandError> {
+    let mut args = Args::new("json.strappend", frames);
+    let key = args.next_bytes()?;
+    let path = if args.remaining() > 1 {
+        parse_path(&args.next_bytes()?)?
+    } else {
+        JsonPath::root()
+    };
+    let Json::String(value) = parse_json(&args.next_bytes()?)? else {
+        return Err(CommandError::InvalidJson("expected a JSON string".into()));
+    };
+    args.finish()?;
+    Ok(Command::JsonStrAppend { key, path, value })
+}
+
+// JSON.ARRAPPEND key path value [value ...]
+pub(super) fn parse_json_arrappend(frames: &[Frame]) -> Result<Command, CommandError> {
+    let mut args = Args::new("json.arrappend", frames);
+    let key = args.next_bytes()?;
+    let path = parse_path(&args.next_bytes()?)?;
+    let values = parse_values(args.rest()?)?;
+    Ok(Command::JsonArrAppend { key, path, values })
+}
+
+// JSON.ARRINSERT key path index value [value ...]
+pub(super) fn parse_json_arrinsert(frames: &[Frame]) -> Result<Command, CommandError> {
+    let mut args = Args::new("json.arrinsert", frames);
+    let key = args.next_bytes()?;
+    let path = parse_path(&args.next_bytes()?)?;
+    let index = args.next_int()?;
+    let values = parse_values(args.rest()?)?;
+    Ok(Command::JsonArrInsert {
+        key,
+        path,
+        index,
+        values,
+    })
+}
+
+// JSON.ARRPOP key [path [index]]
+pub(super) fn parse_json_arrpop(frames: &[Frame]) -> Result<Command, CommandError> {
+    let mut args = Args::new("json.arrpop", frames);
+    let key = args.next_bytes()?;
+    let path = if args.remaining() > 0 {
+        parse_path(&args.next_bytes()?)?
+    } else {
+        JsonPath::root()
+    };
+    let index = if args.remaining() > 0 {
+        args.next_int()?
+    } else {
+        -1
+    };
+    args.finish()?;
+    Ok(Command::JsonArrPop { key, path, index })
+}
+
+// JSON.ARRTRIM key path start stop
+pub(super) fn parse_json_arrtrim(frames: &[Frame]) -> Result<Command, CommandError> {
+    let mut args = Args::new("json.arrtrim", frames);
+    let key = args.next_bytes()?;
+    let path = parse_path(&args.next_bytes()?)?;
+    let start = args.next_int()?;
+    let stop = args.next_int()?;
+    args.finish()?;
+    Ok(Command::JsonArrTrim {
+        key,
+        path,
+        start,
+        stop,
+    })
+}
+
+// A key and an optional path, which defaults to the root
+fn key_and_path(frames: &[Frame], name: &'static str) -> Result<(Bytes, JsonPath), CommandError> {
+    let mut args = Args::new(name, frames);
+    let key = args.next_bytes()?;
+    let path = if args.remaining() > 0 {
+        parse_path(&args.next_bytes()?)?
+    } else {
+        JsonPath::root()
+    };
+    args.finish()?;
+    Ok((key, path))
+}
+
+fn parse_path(bytes: &[u8]) -> Result<JsonPath, CommandError> {
+    let text = String::from_utf8_lossy(bytes);
+    JsonPath::parse(&text).ok_or_else(|| CommandError::InvalidJsonPath(text.into_owned()))
+}
+
+fn parse_json(bytes: &[u8]) -> Result<Json, CommandError> {
+    serde_json::from_slice(bytes).map_err(|e| CommandError::InvalidJson(e.to_string()))
+}
+
+fn parse_values(values: Vec<Bytes>) -> Result<Vec<Json>, CommandError> {
+    values.iter().map(|v| parse_json(v)).collect()
+}
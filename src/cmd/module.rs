@@ -0,0 +1,38 @@
+use super::{Args, Command, CommandError};
+use crate::Frame;
+use crate::db::ModuleCommand;
+
+// MODULE LOAD path | UNLOAD name | LIST
+pub(super) fn parse_module(frames: &[Frame]) -> Result<Command, CommandError> {
+    let mut args = Args::new("module", frames);
+    let sub = args.next_bytes()?.to_ascii_uppercase();
+    let cmd = match sub.as_slice() {
+        b"LOAD" => Command::ModuleLoad {
+            path: args.next_bytes()?,
+        },
+        b"UNLOAD" => Command::ModuleUnload {
+            name: args.next_bytes()?,
+        },
+        b"LIST" => Command::ModuleList,
+        _ => return Err(CommandError::Syntax),
+    };
+    args.finish()?;
+    Ok(cmd)
+}
+
+// A module's command takes any arguments, which its handler checks
+pub(super) fn parse_module_call(
+    command: ModuleCommand,
+    frames: &[Frame],
+) -> Result<Command, CommandError> {
+    let mut args = Args::new("module", frames);
+    let mut rest = Vec::with_capacity(args.remaining());
+    while args.remaining() > 0 {
+        rest.push(args.next_bytes()?);
+    }
+    Ok(Command::ModuleCall {
+        name: command.name,
+        args: rest,
+        read_only: command.read_only,
+    })
+}
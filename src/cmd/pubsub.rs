@@ -0,0 +1,110 @@
+use super::{Args, Command, CommandError};
+use crate::Frame;
+use crate::db::key_slot;
+use bytes::Bytes;
+
+pub(super) fn parse_subscribe(frames: &[Frame]) -> Result<Command, CommandError> {
+    let channels = Args::new("subscribe", frames).rest()?;
+    Ok(Command::Subscribe { channels })
+}
+
+// UNSUBSCRIBE [channel ...], where no channels means all of them
+pub(super) fn parse_unsubscribe(frames: &[Frame]) -> Result<Command, CommandError> {
+    let mut args = Args::new("unsubscribe", frames);
+    let mut channels = Vec::with_capacity(args.remaining());
+    while args.remaining() > 0 {
+        channels.push(args.next_bytes()?);
+    }
+    Ok(Command::Unsubscribe { channels })
+}
+
+pub(super) fn parse_psubscribe(frames: &[Frame]) -> Result<Command, CommandError> {
+    let patterns = Args::new("psubscribe", frames).rest()?;
+    Ok(Command::PSubscribe { patterns })
+}
+
+// PUNSUBSCRIBE [pattern ...], where no patterns means all of them
+pub(super) fn parse_punsubscribe(frames: &[Frame]) -> Result<Command, CommandError> {
+    let mut args = Args::new("punsubscribe", frames);
+    let mut patterns = Vec::with_capacity(args.remaining());
+    while args.remaining() > 0 {
+        patterns.push(args.next_bytes()?);
+    }
+    Ok(Command::PUnsubscribe { patterns })
+}
+
+pub(super) fn parse_ssubscribe(frames: &[Frame]) -> Result<Command, CommandError> {
+    let channels = Args::new("ssubscribe", frames).rest()?;
+    same_slot(&channels)?;
+    Ok(Command::SSubscribe { channels })
+}
+
+// SUNSUBSCRIBE [shardchannel ...], where no channels means all of them
+pub(super) fn parse_sunsubscribe(frames: &[Frame]) -> Result<Command, CommandError> {
+    let mut args = Args::new("sunsubscribe", frames);
+    let mut channels = Vec::with_capacity(args.remaining());
+    while args.remaining() > 0 {
+        channels.push(args.next_bytes()?);
+    }
+    same_slot(&channels)?;
+    Ok(Command::SUnsubscribe { channels })
+}
+
+// A cluster node only owns some slots, so shard channels in one command must share a slot
+fn same_slot(channels: &[Bytes]) -> Result<(), CommandError> {
+    match channels.split_first() {
+        Some((first, rest)) if rest.iter().any(|c| key_slot(c) != key_slot(first)) => {
+            Err(CommandError::CrossSlot)
+        }
+        _ => Ok(()),
+    }
+}
+
+pub(super) fn parse_publish(frames: &[Frame]) -> Result<Command, CommandError> {
+    let mut args = Args::new("publish", frames);
+    let channel = args.next_bytes()?;
+    let message = args.next_bytes()?;
+    args.finish()?;
+    Ok(Command::Publish { channel, message })
+}
+
+pub(super) fn parse_spublish(frames: &[Frame]) -> Result<Command, CommandError> {
+    let mut args = Args::new("spublish", frames);
+    let channel = args.next_bytes()?;
+    let message = args.next_bytes()?;
+    args.finish()?;
+    Ok(Command::SPublish { channel, message })
+}
+
+// PUBSUB CHANNELS [pattern] | NUMSUB [channel ...] | NUMPAT | SHARDCHANNELS [pattern]
+//     | SHARDNUMSUB [shardchannel ...]
+pub(super) fn parse_pubsub(frames: &[Frame]) -> Result<Command, CommandError> {
+    let mut args = Args::new("pubsub", frames);
+    let sub = args.next_bytes()?.to_ascii_uppercase();
+    let mut channels = Vec::with_capacity(args.remaining());
+    while args.remaining() > 0 {
+        channels.push(args.next_bytes()?);
+    }
+    let pattern = || match channels.len() {
+        0 | 1 => Ok(channels.first().cloned()),
+        _ => Err(CommandError::WrongArity("pubsub")),
+    };
+    match sub.as_slice() {
+        b"CHANNELS" => Ok(Command::PubSubChannels {
+            pattern: pattern()?,
+        }),
+        b"SHARDCHANNELS" => Ok(Command::PubSubShardChannels {
+            pattern: pattern()?,
+        }),
+        b"NUMPAT" if channels.is_empty() => Ok(Command::PubSubNumPat),
+        b"NUMPAT" => Err(CommandError::WrongArity("pubsub")),
+        b"NUMSUB" => Ok(Command::PubSubNumSub { channels }),
+        b"SHARDNUMSUB" => Ok(Command::PubSubShardNumSub { channels }),
+        _ => Err(CommandError::Syntax),
+    }
+}
+
+pub(super) fn parse_quit(frames: &[Frame]) -> Result<Command, CommandError> {
+    Args::new("quit", frames).finish()?;
+    Ok(Command::Quit)
+}
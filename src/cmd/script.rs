@@ -0,0 +1,135 @@
+use super::{Args, Command, CommandError};
+use crate::Frame;
+use crate::db::RestorePolicy;
+use bytes::Bytes;
+
+// EVAL script numkeys [key ...] [arg ...]
+pub(super) fn parse_eval(frames: &[Frame]) -> Result<Command, CommandError> {
+    let mut args = Args::new("eval", frames);
+    let script = args.next_bytes()?;
+    let (keys, args) = keys_and_args(&mut args)?;
+    Ok(Command::Eval { script, keys, args })
+}
+
+// EVALSHA sha1 numkeys [key ...] [arg ...]
+pub(super) fn parse_evalsha(frames: &[Frame]) -> Result<Command, CommandError> {
+    let mut args = Args::new("evalsha", frames);
+    let sha = args.next_bytes()?;
+    let (keys, args) = keys_and_args(&mut args)?;
+    Ok(Command::EvalSha { sha, keys, args })
+}
+
+// SCRIPT LOAD script | EXISTS sha1 [sha1 ...] | FLUSH [ASYNC | SYNC] | KILL
+pub(super) fn parse_script(frames: &[Frame]) -> Result<Command, CommandError> {
+    let mut args = Args::new("script", frames);
+    let sub = args.next_bytes()?.to_ascii_uppercase();
+    let cmd = match sub.as_slice() {
+        b"LOAD" => Command::ScriptLoad {
+            script: args.next_bytes()?,
+        },
+        b"EXISTS" => Command::ScriptExists { shas: args.rest()? },
+        b"FLUSH" => {
+            let _ = args.eat("ASYNC") || args.eat("SYNC");
+            Command::ScriptFlush
+        }
+        b"KILL" => Command::ScriptKill,
+        _ => return Err(CommandError::Syntax),
+    };
+    args.finish()?;
+    Ok(cmd)
+}
+
+// FCALL function numkeys [key ...] [arg ...], and FCALL_RO with `read_only`
+pub(super) fn parse_fcall(
+    frames: &[Frame],
+    name: &'static str,
+    read_only: bool,
+) -> Result<Command, CommandError> {
+    let mut args = Args::new(name, frames);
+    let function = args.next_bytes()?;
+    let (keys, args) = keys_and_args(&mut args)?;
+    Ok(Command::FCall {
+        function,
+        keys,
+        args,
+        read_only,
+    })
+}
+
+// FUNCTION LOAD [REPLACE] code | LIST [LIBRARYNAME pattern] [WITHCODE] | DELETE library
+// | DUMP | RESTORE payload [FLUSH | APPEND | REPLACE] | FLUSH [ASYNC | SYNC] | KILL
+pub(super) fn parse_function(frames: &[Frame]) -> Result<Command, CommandError> {
+    let mut args = Args::new("function", frames);
+    let sub = args.next_bytes()?.to_ascii_uppercase();
+    let cmd = match sub.as_slice() {
+        b"LOAD" => {
+            let replace = args.eat("REPLACE");
+            Command::FunctionLoad {
+                code: args.next_bytes()?,
+                replace,
+            }
+        }
+        b"LIST" => {
+            let mut pattern = None;
+            let mut with_code = false;
+            while args.remaining() > 0 {
+                if args.eat("WITHCODE") {
+                    with_code = true;
+                } else if args.eat("LIBRARYNAME") {
+                    pattern = Some(args.next_bytes()?);
+                } else {
+                    return Err(CommandError::Syntax);
+                }
+            }
+            Command::FunctionList { pattern, with_code }
+        }
+        b"DELETE" => Command::FunctionDelete {
+            library: args.next_bytes()?,
+        },
+        b"DUMP" => Command::FunctionDump,
+        b"RESTORE" => {
+            let payload = args.next_bytes()?;
+            let policy = if args.eat("FLUSH") {
+                RestorePolicy::Flush
+            } else if args.eat("REPLACE") {
+                RestorePolicy::Replace
+            } else {
+                let _ = args.eat("APPEND");
+                RestorePolicy::Append
+            };
+            Command::FunctionRestore { payload, policy }
+        }
+        b"FLUSH" => {
+            let _ = args.eat("ASYNC") || args.eat("SYNC");
+            Command::FunctionFlush
+        }
+        b"KILL" => Command::FunctionKill,
+        _ => return Err(CommandError::Syntax),
+    };
+    args.finish()?;
+    Ok(cmd)
+}
+
+// The numkeys keys, then the remaining arguments
+fn keys_and_args(args: &mut Args) -> Result<(Vec<Bytes>, Vec<Bytes>), CommandError> {
+    let numkeys: i64 = args.next_int()?;
+    if numkeys < 0 {
+        return Err(CommandError::InvalidOption(
+            "Number of keys can't be negative",
+        ));
+    }
+    if numkeys as usize > args.remaining() {
+        return Err(CommandError::InvalidOption(
+            "Number of keys can't be greater than number of args",
+        ));
+    }
+    let mut keys = Vec::with_capacity(numkeys as usize);
+    for _ in 0..numkeys {
+        keys.push(args.next_bytes()?);
+    }
+    let mut rest = Vec::with_capacity(args.remaining());
+    while args.remaining() > 0 {
+        rest.push(args.next_bytes()?);
+    }
+    Ok((keys, rest))
+}
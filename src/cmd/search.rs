@@ -0,0 +1,186 @@
+use super::{Args, Command, CommandError, parse_float};
+use crate::Frame;
+use crate::db::{FieldSpec, FieldType, FtQuery, FtSearchOptions, IndexSpec};
+use bytes::Bytes;
+
+// FT.CREATE index [ON HASH] [PREFIX count prefix ...] SCHEMA field [AS alias]
+//     (TEXT [WEIGHT weight] [NOSTEM] | TAG [SEPARATOR sep] [CASESENSITIVE] | NUMERIC)
+//     [SORTABLE] ...
+pub(super) fn parse_ft_create(frames: &[Frame]) -> Result<Command, CommandError> {
+    let mut args = Args::new("ft.create", frames);
+    let name = args.next_bytes()?;
+
+    let mut prefixes = Vec::new();
+    loop {
+        if args.eat("ON") {
+            if !args.eat("HASH") {
+                return Err(CommandError::InvalidOption(
+                    "only HASH indexes are supported",
+                ));
+            }
+        } else if args.eat("PREFIX") {
+            let count = args.next_int::<usize>()?;
+            if count > args.remaining() {
+                return Err(CommandError::WrongArity("ft.create"));
+            }
+            for _ in 0..count {
+                prefixes.push(args.next_bytes()?);
+            }
+        } else if args.eat("SCHEMA") {
+            break;
+        } else if args.remaining() == 0 {
+            return Err(CommandError::WrongArity("ft.create"));
+        } else {
+            return Err(CommandError::Syntax);
+        }
+    }
+    if prefixes.is_empty() {
+        prefixes.push(Bytes::new());
+    }
+
+    let mut schema: Vec<FieldSpec> = Vec::new();
+    while args.remaining() > 0 {
+        let field = parse_field(&mut args)?;
+        if schema.iter().any(|f| f.attribute() == field.attribute()) {
+            return Err(CommandError::InvalidOption("Duplicate field in schema"));
+        }
+        schema.push(field);
+    }
+    if schema.is_empty() {
+        return Err(CommandError::InvalidOption("Fields arguments are missing"));
+    }
+    Ok(Command::FtCreate {
+        name,
+        spec: IndexSpec { prefixes, schema },
+    })
+}
+
+fn parse_field(args: &mut Args) -> Result<FieldSpec, CommandError> {
+    let name = args.next_bytes()?;
+    let alias = match args.eat("AS") {
+        true => Some(args.next_bytes()?),
+        false => None,
+    };
+    let mut kind = if args.eat("TEXT") {
+        FieldType::Text { weight: 1.0 }
+    } else if args.eat("TAG") {
+        FieldType::Tag {
+            separator: ',',
+            case_sensitive: false,
+        }
+    } else if args.eat("NUMERIC") {
+        FieldType::Numeric
+    } else {
+        return Err(CommandError::InvalidOption("Invalid field type"));
+    };
+
+    let mut sortable = false;
+    loop {
+        if args.eat("SORTABLE") {
+            sortable = true;
+        } else if let FieldType::Text { weight } = &mut kind
+            && args.eat("WEIGHT")
+        {
+            *weight = parse_float(&args.next_bytes()?)
+                .ok()
+                .filter(|w| w.is_finite() && *w >= 0.0)
+                .ok_or(CommandError::InvalidOption("Invalid WEIGHT"))?;
+        } else if let FieldType::Text { .. } = kind
+            && args.eat("NOSTEM")
+        {
+            // Terms are never stemmed
+        } else if let FieldType::Tag { separator, .. } = &mut kind
+            && args.eat("SEPARATOR")
+        {
+            let sep = args.next_bytes()?;
+            *separator = match std::str::from_utf8(&sep).map(|s| s.chars().collect::<Vec<_>>()) {
+                Ok(chars) if chars.len() == 1 => chars[0],
+                _ => {
+                    return Err(CommandError::InvalidOption(
+                        "Tag separator must be a single character",
+                    ));
+                }
+            };
+        } else if let FieldType::Tag { case_sensitive, .. } = &mut kind
+            && args.eat("CASESENSITIVE")
+        {
+            *case_sensitive = true;
+        } else {
+            break;
+        }
+    }
+    Ok(FieldSpec {
+        name,
+        alias,
+        kind,
+        sortable,
+    })
+}
+
+// FT.SEARCH index query [NOCONTENT] [WITHSCORES] [RETURN count field ...]
+//     [SORTBY field [ASC | DESC]] [LIMIT offset num] [DIALECT version]
+pub(super) fn parse_ft_search(frames: &[Frame]) -> Result<Command, CommandError> {
+    let mut args = Args::new("ft.search", frames);
+    let name = args.next_bytes()?;
+    let query = args.next_bytes()?;
+    let query = std::str::from_utf8(&query)
+        .ok()
+        .and_then(FtQuery::parse)
+        .ok_or(CommandError::InvalidOption("Syntax error in query"))?;
+
+    let mut options = FtSearchOptions::default();
+    while args.remaining() > 0 {
+        if args.eat("NOCONTENT") {
+            options.no_content = true;
+        } else if args.eat("WITHSCORES") {
+            options.with_scores = true;
+        } else if args.eat("RETURN") {
+            let count = args.next_int::<usize>()?;
+            if count > args.remaining() {
+                return Err(CommandError::WrongArity("ft.search"));
+            }
+            let fields = (0..count)
+                .map(|_| args.next_bytes())
+                .collect::<Result<_, _>>()?;
+            // RETURN 0 is the same as NOCONTENT
+            options.no_content |= count == 0;
+            options.return_fields = Some(fields);
+        } else if args.eat("SORTBY") {
+            let field = args.next_bytes()?;
+            let descending = args.eat("DESC");
+            if !descending {
+                args.eat("ASC");
+            }
+            options.sort_by = Some((field, descending));
+        } else if args.eat("LIMIT") {
+            options.offset = args.next_int()?;
+            options.limit = args.next_int()?;
+        } else if args.eat("DIALECT") {
+            // Every dialect is parsed the same way
+            args.next_int::<u32>()?;
+        } else {
+            return Err(CommandError::Syntax);
+        }
+    }
+    Ok(Command::FtSearch {
+        name,
+        query,
+        options,
+    })
+}
+
+pub(super) fn parse_ft_info(frames: &[Frame]) -> Result<Command, CommandError> {
+    let mut args = Args::new("ft.info", frames);
+    let name = args.next_bytes()?;
+    args.finish()?;
+    Ok(Command::FtInfo { name })
+}
+
+// FT.DROPINDEX index [DD], where DD deletes the indexed hashes too
+pub(super) fn parse_ft_dropindex(frames: &[Frame]) -> Result<Command, CommandError> {
+    let mut args = Args::new("ft.dropindex", frames);
+    let name = args.next_bytes()?;
+    let delete_docs = args.eat("DD");
+    args.finish()?;
+    Ok(Command::FtDropIndex { name, delete_docs })
+}
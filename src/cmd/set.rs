@@ -0,0 +1,137 @@
+use super::{Args, Command, CommandError};
+use crate::Frame;
+use crate::db::SetOp;
+
+pub(super) fn parse_sadd(frames: &[Frame]) -> Result<Command, CommandError> {
+    let mut args = Args::new("sadd", frames);
+    let key = args.next_bytes()?;
+    let members = args.rest()?;
+    Ok(Command::SAdd { key, members })
+}
+
+pub(super) fn parse_srem(frames: &[Frame]) -> Result<Command, CommandError> {
+    let mut args = Args::new("srem", frames);
+    let key = args.next_bytes()?;
+    let members = args.rest()?;
+    Ok(Command::SRem { key, members })
+}
+
+pub(super) fn parse_sismember(frames: &[Frame]) -> Result<Command, CommandError> {
+    let mut args = Args::new("sismember", frames);
+    let key = args.next_bytes()?;
+    let member = args.next_bytes()?;
+    args.finish()?;
+    Ok(Command::SIsMember { key, member })
+}
+
+pub(super) fn parse_smismember(frames: &[Frame]) -> Result<Command, CommandError> {
+    let mut args = Args::new("smismember", frames);
+    let key = args.next_bytes()?;
+    let members = args.rest()?;
+    Ok(Command::SMIsMember { key, members })
+}
+
+pub(super) fn parse_smembers(frames: &[Frame]) -> Result<Command, CommandError> {
+    let mut args = Args::new("smembers", frames);
+    let key = args.next_bytes()?;
+    args.finish()?;
+    Ok(Command::SMembers { key })
+}
+
+pub(super) fn parse_scard(frames: &[Frame]) -> Result<Command, CommandError> {
+    let mut args = Args::new("scard", frames);
+    let key = args.next_bytes()?;
+    args.finish()?;
+    Ok(Command::SCard { key })
+}
+
+pub(super) fn parse_spop(frames: &[Frame]) -> Result<Command, CommandError> {
+    let mut args = Args::new("spop", frames);
+    let key = args.next_bytes()?;
+    let count = match args.remaining() {
+        0 => None,
+        _ => Some(args.next_int()?),
+    };
+    args.finish()?;
+    Ok(Command::SPop { key, count })
+}
+
+pub(super) fn parse_srandmember(frames: &[Frame]) -> Result<Command, CommandError> {
+    let mut args = Args::new("srandmember", frames);
+    let key = args.next_bytes()?;
+    let count = match args.remaining() {
+        0 => None,
+        _ => Some(random_count(&mut args)?),
+    };
+    args.finish()?;
+    Ok(Command::SRandMember { key, count })
+}
+
+pub(super) fn parse_set_op(
+    frames: &[Frame],
+    op: SetOp,
+    name: &'static str,
+) -> Result<Command, CommandError> {
+    let keys = Args::new(name, frames).rest()?;
+    Ok(Command::SetOp { op, keys })
+}
+
+pub(super) fn parse_set_op_store(
+    frames: &[Frame],
+    op: SetOp,
+    name: &'static str,
+) -> Result<Command, CommandError> {
+    let mut args = Args::new(name, frames);
+    let destination = args.next_bytes()?;
+    let keys = args.rest()?;
+    Ok(Command::SetOpStore {
+        op,
+        destination,
+        keys,
+    })
+}
+
+pub(super) fn parse_sintercard(frames: &[Frame]) -> Result<Command, CommandError> {
+    let mut args = Args::new("sintercard", frames);
+    let numkeys: usize = args.next_int()?;
+    if numkeys == 0 || numkeys > args.remaining() {
+        return Err(CommandError::Syntax);
+    }
+
+    let keys = (0..numkeys)
+        .map(|_| args.next_bytes())
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let limit = if args.eat("LIMIT") {
+        args.next_int()?
+    } else {
+        0
+    };
+    args.finish()?;
+
+    Ok(Command::SInterCard { keys, limit })
+}
+
+pub(super) fn parse_smove(frames: &[Frame]) -> Result<Command, CommandError> {
+    let mut args = Args::new("smove", frames);
+    let source = args.next_bytes()?;
+    let destination = args.next_bytes()?;
+    let member = args.next_bytes()?;
+    args.finish()?;
+    Ok(Command::SMove {
+        source,
+        destination,
+        member,
+    })
+}
+
+// A negative count may repeat members, so its reply grows with the count rather than the set
+const MAX_REPEATS: u64 = 1 << 24;
+
+pub(super) fn random_count(args: &mut Args) -> Result<i64, CommandError> {
+    let count: i64 = args.next_int()?;
+    if count < 0 && count.unsigned_abs() > MAX_REPEATS {
+        return Err(CommandError::InvalidOption("value is out of range"));
+    }
+    Ok(count)
+}
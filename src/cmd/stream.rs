@@ -0,0 +1,519 @@
+use super::{Args, Command, CommandError, parse_int};
+use crate::Frame;
+use crate::db::{
+    ClaimOptions, GroupReadFrom, GroupStart, PendingFilter, StreamId, StreamTrim, TrimStrategy,
+    XAddId, XReadFrom,
+};
+use bytes::Bytes;
+use std::time::Duration;
+
+// XADD key [NOMKSTREAM] [MAXLEN|MINID [=|~] threshold [LIMIT count]] *|id field value ...
+pub(super) fn parse_xadd(frames: &[Frame]) -> Result<Command, CommandError> {
+    let mut args = Args::new("xadd", frames);
+    let key = args.next_bytes()?;
+
+    let mut nomkstream = false;
+    let mut trim = None;
+    loop {
+        if args.eat("NOMKSTREAM") {
+            nomkstream = true;
+        } else if let Some(parsed) = parse_trim(&mut args)? {
+            trim = Some(parsed);
+        } else {
+            break;
+        }
+    }
+
+    let id = parse_xadd_id(&args.next_bytes()?)?;
+    let rest = args.rest()?;
+    if !rest.len().is_multiple_of(2) {
+        return Err(CommandError::WrongArity("xadd"));
+    }
+    let fields = rest
+        .chunks_exact(2)
+        .map(|pair| (pair[0].clone(), pair[1].clone()))
+        .collect();
+
+    Ok(Command::XAdd {
+        key,
+        id,
+        fields,
+        nomkstream,
+        trim,
+    })
+}
+
+// XTRIM key MAXLEN|MINID [=|~] threshold [LIMIT count]
+pub(super) fn parse_xtrim(frames: &[Frame]) -> Result<Command, CommandError> {
+    let mut args = Args::new("xtrim", frames);
+    let key = args.next_bytes()?;
+    let trim = parse_trim(&mut args)?.ok_or(CommandError::Syntax)?;
+    args.finish()?;
+    Ok(Command::XTrim { key, trim })
+}
+
+// The trimming options shared by XADD and XTRIM, if the next argument starts them
+fn parse_trim(args: &mut Args) -> Result<Option<StreamTrim>, CommandError> {
+    let max_len = if args.eat("MAXLEN") {
+        true
+    } else if args.eat("MINID") {
+        false
+    } else {
+        return Ok(None);
+    };
+
+    let approx = args.eat("~");
+    if !approx {
+        args.eat("=");
+    }
+
+    let threshold = args.next_bytes()?;
+    let strategy = if max_len {
+        let max = parse_int::<i64>(&threshold)?;
+        let max = usize::try_from(max)
+            .map_err(|_| CommandError::InvalidOption("The MAXLEN argument must be >= 0."))?;
+        TrimStrategy::MaxLen(max)
+    } else {
+        TrimStrategy::MinId(parse_stream_id(&threshold, 0)?)
+    };
+
+    let limit = if args.eat("LIMIT") {
+        if !approx {
+            return Err(CommandError::InvalidOption(
+                "syntax error, LIMIT cannot be used without the special ~ option",
+            ));
+        }
+        let limit = args.next_int::<i64>()?;
+        Some(
+            usize::try_from(limit)
+                .map_err(|_| CommandError::InvalidOption("The LIMIT argument must be >= 0."))?,
+        )
+    } else {
+        None
+    };
+
+    Ok(Some(StreamTrim {
+        strategy,
+        approx,
+        limit,
+    }))
+}
+
+// XRANGE key start end [COUNT count], with XREVRANGE taking end before start
+pub(super) fn parse_xrange(
+    frames: &[Frame],
+    rev: bool,
+    name: &'static str,
+) -> Result<Command, CommandError> {
+    let mut args = Args::new(name, frames);
+    let key = args.next_bytes()?;
+    let (start, end) = if rev {
+        let end = args.next_bytes()?;
+        (args.next_bytes()?, end)
+    } else {
+        (args.next_bytes()?, args.next_bytes()?)
+    };
+    let start = parse_range_id(&start, false)?;
+    let end = parse_range_id(&end, true)?;
+
+    let count = if args.eat("COUNT") {
+        Some(args.next_int::<i64>()?.max(0) as usize)
+    } else {
+        None
+    };
+    args.finish()?;
+
+    Ok(Command::XRange {
+        key,
+        start,
+        end,
+        count,
+        rev,
+    })
+}
+
+pub(super) fn parse_xlen(frames: &[Frame]) -> Result<Command, CommandError> {
+    let mut args = Args::new("xlen", frames);
+    let key = args.next_bytes()?;
+    args.finish()?;
+    Ok(Command::XLen { key })
+}
+
+pub(super) fn parse_xdel(frames: &[Frame]) -> Result<Command, CommandError> {
+    let mut args = Args::new("xdel", frames);
+    let key = args.next_bytes()?;
+    let ids = parse_ids(&args.rest()?)?;
+    Ok(Command::XDel { key, ids })
+}
+
+// XREAD [COUNT count] [BLOCK milliseconds] STREAMS key [key ...] id [id ...]
+pub(super) fn parse_xread(frames: &[Frame]) -> Result<Command, CommandError> {
+    let mut args = Args::new("xread", frames);
+    let mut count = None;
+    let mut block = false;
+    let mut timeout = None;
+    loop {
+        if args.eat("COUNT") {
+            // COUNT 0 reads everything, like leaving it out
+            count = Some(args.next_int::<i64>()?.max(0) as usize).filter(|&n| n > 0);
+        } else if args.eat("BLOCK") {
+            block = true;
+            timeout = parse_block(&mut args)?;
+        } else if args.eat("STREAMS") {
+            break;
+        } else {
+            return Err(CommandError::Syntax);
+        }
+    }
+
+    let streams = parse_streams(&mut args, "xread", |id| {
+        Ok(match id {
+            b"$" => XReadFrom::New,
+            b"+" => XReadFrom::Last,
+            id => XReadFrom::After(parse_stream_id(id, 0)?),
+        })
+    })?;
+
+    Ok(Command::XRead {
+        streams,
+        count,
+        block,
+        timeout,
+    })
+}
+
+// XREADGROUP GROUP group consumer [COUNT count] [BLOCK milliseconds] [NOACK] STREAMS key ... id ...
+pub(super) fn parse_xreadgroup(frames: &[Frame]) -> Result<Command, CommandError> {
+    let mut args = Args::new("xreadgroup", frames);
+    if !args.eat("GROUP") {
+        return Err(CommandError::Syntax);
+    }
+    let group = args.next_bytes()?;
+    let consumer = args.next_bytes()?;
+
+    let mut count = None;
+    let mut block = false;
+    let mut timeout = None;
+    let mut noack = false;
+    loop {
+        if args.eat("COUNT") {
+            count = Some(args.next_int::<i64>()?.max(0) as usize).filter(|&n| n > 0);
+        } else if args.eat("BLOCK") {
+            block = true;
+            timeout = parse_block(&mut args)?;
+        } else if args.eat("NOACK") {
+            noack = true;
+        } else if args.eat("STREAMS") {
+            break;
+        } else {
+            return Err(CommandError::Syntax);
+        }
+    }
+
+    let streams = parse_streams(&mut args, "xreadgroup", |id| {
+        Ok(match id {
+            b">" => GroupReadFrom::Undelivered,
+            id => GroupReadFrom::Pending(parse_stream_id(id, 0)?),
+        })
+    })?;
+
+    Ok(Command::XReadGroup {
+        group,
+        consumer,
+        streams,
+        count,
+        noack,
+        block,
+        timeout,
+    })
+}
+
+// XGROUP CREATE|SETID|DESTROY|CREATECONSUMER|DELCONSUMER key group ...
+pub(super) fn parse_xgroup(frames: &[Frame]) -> Result<Command, CommandError> {
+    let mut args = Args::new("xgroup", frames);
+    let sub = args.next_bytes()?.to_ascii_uppercase();
+    let key = args.next_bytes()?;
+    let group = args.next_bytes()?;
+
+    let cmd = match sub.as_slice() {
+        b"CREATE" | b"SETID" => {
+            let start = match args.next_bytes()?.as_ref() {
+                b"$" => GroupStart::Last,
+                id => GroupStart::Id(parse_stream_id(id, 0)?),
+            };
+            let mut mkstream = false;
+            let mut entries_read = None;
+            loop {
+                if sub.as_slice() == b"CREATE" && args.eat("MKSTREAM") {
+                    mkstream = true;
+                } else if args.eat("ENTRIESREAD") {
+                    // -1 means the number read is unknown
+                    entries_read = match args.next_int::<i64>()? {
+                        -1 => None,
+                        n => Some(u64::try_from(n).map_err(|_| {
+                            CommandError::InvalidOption(
+                                "value for ENTRIESREAD must be positive or -1",
+                            )
+                        })?),
+                    };
+                } else {
+                    break;
+                }
+            }
+            if sub.as_slice() == b"CREATE" {
+                Command::XGroupCreate {
+                    key,
+                    group,
+                    start,
+                    mkstream,
+                    entries_read,
+                }
+            } else {
+                Command::XGroupSetId {
+                    key,
+                    group,
+                    start,
+                    entries_read,
+                }
+            }
+        }
+        b"DESTROY" => Command::XGroupDestroy { key, group },
+        b"CREATECONSUMER" => Command::XGroupCreateConsumer {
+            key,
+            group,
+            consumer: args.next_bytes()?,
+        },
+        b"DELCONSUMER" => Command::XGroupDelConsumer {
+            key,
+            group,
+            consumer: args.next_bytes()?,
+        },
+        _ => return Err(CommandError::Syntax),
+    };
+    args.finish()?;
+    Ok(cmd)
+}
+
+pub(super) fn parse_xack(frames: &[Frame]) -> Result<Command, CommandError> {
+    let mut args = Args::new("xack", frames);
+    let key = args.next_bytes()?;
+    let group = args.next_bytes()?;
+    let ids = parse_ids(&args.rest()?)?;
+    Ok(Command::XAck { key, group, ids })
+}
+
+// XPENDING key group [[IDLE min-idle-time] start end count [consumer]]
+pub(super) fn parse_xpending(frames: &[Frame]) -> Result<Command, CommandError> {
+    let mut args = Args::new("xpending", frames);
+    let key = args.next_bytes()?;
+    let group = args.next_bytes()?;
+    if args.remaining() == 0 {
+        return Ok(Command::XPending {
+            key,
+            group,
+            filter: None,
+        });
+    }
+
+    let min_idle = if args.eat("IDLE") {
+        Some(args.next_int::<i64>()?.max(0) as u64)
+    } else {
+        None
+    };
+    let start = parse_range_id(&args.next_bytes()?, false)?;
+    let end = parse_range_id(&args.next_bytes()?, true)?;
+    let count = args.next_int::<i64>()?.max(0) as usize;
+    let consumer = if args.remaining() > 0 {
+        Some(args.next_bytes()?)
+    } else {
+        None
+    };
+    args.finish()?;
+
+    Ok(Command::XPending {
+        key,
+        group,
+        filter: Some(PendingFilter {
+            min_idle,
+            start,
+            end,
+            count,
+            consumer,
+        }),
+    })
+}
+
+// XCLAIM key group consumer min-idle-time id [id ...] [IDLE ms] [TIME unix-time-ms]
+// [RETRYCOUNT count] [FORCE] [JUSTID] [LASTID lastid]
+pub(super) fn parse_xclaim(frames: &[Frame]) -> Result<Command, CommandError> {
+    let mut args = Args::new("xclaim", frames);
+    let key = args.next_bytes()?;
+    let group = args.next_bytes()?;
+    let consumer = args.next_bytes()?;
+    let min_idle = args.next_int::<i64>()?.max(0) as u64;
+
+    // IDs run until the first option
+    let mut ids = vec![parse_stream_id(&args.next_bytes()?, 0)?];
+    let mut opts = ClaimOptions::default();
+    while args.remaining() > 0 {
+        if args.eat("IDLE") {
+            opts.idle = Some(args.next_int::<i64>()?.max(0) as u64);
+        } else if args.eat("TIME") {
+            opts.time = Some(args.next_int::<i64>()?.max(0) as u64);
+        } else if args.eat("RETRYCOUNT") {
+            opts.retry_count = Some(args.next_int::<i64>()?.max(0) as u64);
+        } else if args.eat("FORCE") {
+            opts.force = true;
+        } else if args.eat("JUSTID") {
+            opts.just_id = true;
+        } else if args.eat("LASTID") {
+            opts.last_id = Some(parse_stream_id(&args.next_bytes()?, 0)?);
+        } else if opts == ClaimOptions::default() {
+            ids.push(parse_stream_id(&args.next_bytes()?, 0)?);
+        } else {
+            return Err(CommandError::Syntax);
+        }
+    }
+
+    Ok(Command::XClaim {
+        key,
+        group,
+        consumer,
+        min_idle,
+        ids,
+        opts,
+    })
+}
+
+// XAUTOCLAIM key group consumer min-idle-time start [COUNT count] [JUSTID]
+pub(super) fn parse_xautoclaim(frames: &[Frame]) -> Result<Command, CommandError> {
+    let mut args = Args::new("xautoclaim", frames);
+    let key = args.next_bytes()?;
+    let group = args.next_bytes()?;
+    let consumer = args.next_bytes()?;
+    let min_idle = args.next_int::<i64>()?.max(0) as u64;
+    let start = parse_range_id(&args.next_bytes()?, false)?;
+
+    let mut count = 100;
+    let mut just_id = false;
+    while args.remaining() > 0 {
+        if args.eat("COUNT") {
+            count = usize::try_from(args.next_int::<i64>()?)
+                .ok()
+                .filter(|&n| n > 0)
+                .ok_or(CommandError::InvalidOption("COUNT must be > 0"))?;
+        } else if args.eat("JUSTID") {
+            just_id = true;
+        } else {
+            return Err(CommandError::Syntax);
+        }
+    }
+
+    Ok(Command::XAutoClaim {
+        key,
+        group,
+        consumer,
+        min_idle,
+        start,
+        count,
+        just_id,
+    })
+}
+
+// XINFO STREAM key | GROUPS key | CONSUMERS key group
+pub(super) fn parse_xinfo(frames: &[Frame]) -> Result<Command, CommandError> {
+    let mut args = Args::new("xinfo", frames);
+    let sub = args.next_bytes()?.to_ascii_uppercase();
+    let key = args.next_bytes()?;
+    let cmd = match sub.as_slice() {
+        b"STREAM" => Command::XInfoStream { key },
+        b"GROUPS" => Command::XInfoGroups { key },
+        b"CONSUMERS" => Command::XInfoConsumers {
+            key,
+            group: args.next_bytes()?,
+        },
+        _ => return Err(CommandError::Syntax),
+    };
+    args.finish()?;
+    Ok(cmd)
+}
+
+// BLOCK milliseconds, where 0 blocks forever
+fn parse_block(args: &mut Args) -> Result<Option<Duration>, CommandError> {
+    let ms = args.next_int::<i64>()?;
+    if ms < 0 {
+        return Err(CommandError::InvalidOption("timeout is negative"));
+    }
+    Ok((ms > 0).then(|| Duration::from_millis(ms as u64)))
+}
+
+// The keys and IDs after STREAMS, split evenly
+fn parse_streams<T>(
+    args: &mut Args,
+    name: &'static str,
+    parse_id: impl Fn(&[u8]) -> Result<T, CommandError>,
+) -> Result<Vec<(Bytes, T)>, CommandError> {
+    let rest = args.rest()?;
+    if !rest.len().is_multiple_of(2) {
+        return Err(CommandError::UnbalancedStreams(name));
+    }
+    let (keys, ids) = rest.split_at(rest.len() / 2);
+    keys.iter()
+        .zip(ids)
+        .map(|(key, id)| Ok((key.clone(), parse_id(id)?)))
+        .collect()
+}
+
+fn parse_ids(ids: &[Bytes]) -> Result<Vec<StreamId>, CommandError> {
+    ids.iter().map(|id| parse_stream_id(id, 0)).collect()
+}
+
+fn parse_xadd_id(bytes: &[u8]) -> Result<XAddId, CommandError> {
+    if bytes == b"*" {
+        return Ok(XAddId::Auto);
+    }
+    match bytes.strip_suffix(b"-*") {
+        Some(ms) => Ok(XAddId::Partial(
+            parse_int(ms).map_err(|_| CommandError::InvalidStreamId)?,
+        )),
+        None => Ok(XAddId::Explicit(parse_stream_id(bytes, 0)?)),
+    }
+}
+
+// An XRANGE bound: `-` and `+` are the smallest and largest IDs, `(` makes it exclusive, and
+// a bare ms covers every seq in that millisecond
+fn parse_range_id(bytes: &[u8], end: bool) -> Result<StreamId, CommandError> {
+    match bytes {
+        b"-" => return Ok(StreamId::MIN),
+        b"+" => return Ok(StreamId::MAX),
+        _ => {}
+    }
+    let default_seq = if end { u64::MAX } else { 0 };
+    match bytes.strip_prefix(b"(") {
+        Some(id) if end => {
+            parse_stream_id(id, default_seq)?
+                .prev()
+                .ok_or(CommandError::InvalidOption(
+                    "invalid end ID for the interval",
+                ))
+        }
+        Some(id) => parse_stream_id(id, default_seq)?
+            .next()
+            .ok_or(CommandError::InvalidOption(
+                "invalid start ID for the interval",
+            )),
+        None => parse_stream_id(bytes, default_seq),
+    }
+}
+
+// `ms-seq`, or just `ms` with `default_seq`
+fn parse_stream_id(bytes: &[u8], default_seq: u64) -> Result<StreamId, CommandError> {
+    let invalid = |_| CommandError::InvalidStreamId;
+    let mut parts = bytes.splitn(2, |&b| b == b'-');
+    let ms = parse_int(parts.next().unwrap_or_default()).map_err(invalid)?;
+    let seq = match parts.next() {
+        Some(seq) => parse_int(seq).map_err(invalid)?,
+        None => default_seq,
+    };
+    Ok(StreamId { ms, seq })
+}
@@ -0,0 +1,151 @@
+use super::{Args, Command, CommandError, parse_float};
+use crate::Frame;
+use crate::db::DEFAULT_COMPRESSION;
+use bytes::Bytes;
+
+// TDIGEST.CREATE key [COMPRESSION compression]
+pub(super) fn parse_tdigest_create(frames: &[Frame]) -> Result<Command, CommandError> {
+    let mut args = Args::new("tdigest.create", frames);
+    let key = args.next_bytes()?;
+    let compression = if args.eat("COMPRESSION") {
+        parse_compression(&mut args)?
+    } else {
+        DEFAULT_COMPRESSION
+    };
+    args.finish()?;
+    Ok(Command::TDigestCreate { key, compression })
+}
+
+// TDIGEST.ADD key value [value ...]
+pub(super) fn parse_tdigest_add(frames: &[Frame]) -> Result<Command, CommandError> {
+    let mut args = Args::new("tdigest.add", frames);
+    let key = args.next_bytes()?;
+    let values = floats(args.rest()?, "T-Digest: error parsing val parameter")?;
+    Ok(Command::TDigestAdd { key, values })
+}
+
+// TDIGEST.QUANTILE key quantile [quantile ...]
+pub(super) fn parse_tdigest_quantile(frames: &[Frame]) -> Result<Command, CommandError> {
+    let mut args = Args::new("tdigest.quantile", frames);
+    let key = args.next_bytes()?;
+    let quantiles = floats(args.rest()?, "T-Digest: error parsing quantile")?;
+    if quantiles.iter().any(|q| !(0.0..=1.0).contains(q)) {
+        return Err(CommandError::InvalidOption(
+            "T-Digest: quantile should be in [0,1]",
+        ));
+    }
+    Ok(Command::TDigestQuantile { key, quantiles })
+}
+
+// TDIGEST.CDF key value [value ...]
+pub(super) fn parse_tdigest_cdf(frames: &[Frame]) -> Result<Command, CommandError> {
+    let mut args = Args::new("tdigest.cdf", frames);
+    let key = args.next_bytes()?;
+    let values = floats(args.rest()?, "T-Digest: error parsing cdf")?;
+    Ok(Command::TDigestCdf { key, values })
+}
+
+// TDIGEST.RANK and TDIGEST.REVRANK key value [value ...]
+pub(super) fn parse_tdigest_rank(
+    frames: &[Frame],
+    rev: bool,
+    name: &'static str,
+) -> Result<Command, CommandError> {
+    let mut args = Args::new(name, frames);
+    let key = args.next_bytes()?;
+    let values = floats(args.rest()?, "T-Digest: error parsing value")?;
+    Ok(Command::TDigestRank { key, values, rev })
+}
+
+// TDIGEST.TRIMMED_MEAN key low_cut_quantile high_cut_quantile
+pub(super) fn parse_tdigest_trimmed_mean(frames: &[Frame]) -> Result<Command, CommandError> {
+    let mut args = Args::new("tdigest.trimmed_mean", frames);
+    let key = args.next_bytes()?;
+    let low = args.next_float()?;
+    let high = args.next_float()?;
+    args.finish()?;
+    if !(0.0..=1.0).contains(&low) || !(0.0..=1.0).contains(&high) {
+        return Err(CommandError::InvalidOption(
+            "T-Digest: low_cut_percentile and high_cut_percentile should be in [0,1]",
+        ));
+    }
+    if low >= high {
+        return Err(CommandError::InvalidOption(
+            "T-Digest: low_cut_percentile should be lower than high_cut_percentile",
+        ));
+    }
+    Ok(Command::TDigestTrimmedMean { key, low, high })
+}
+
+// TDIGEST.MIN and TDIGEST.MAX key
+pub(super) fn parse_tdigest_min_max(
+    frames: &[Frame],
+    max: bool,
+    name: &'static str,
+) -> Result<Command, CommandError> {
+    let mut args = Args::new(name, frames);
+    let key = args.next_bytes()?;
+    args.finish()?;
+    Ok(Command::TDigestMinMax { key, max })
+}
+
+// TDIGEST.MERGE destination numkeys source [source ...] [COMPRESSION compression] [OVERRIDE]
+pub(super) fn parse_tdigest_merge(frames: &[Frame]) -> Result<Command, CommandError> {
+    let mut args = Args::new("tdigest.merge", frames);
+    let destination = args.next_bytes()?;
+    let numkeys = args.next_int::<usize>()?;
+    if numkeys == 0 {
+        return Err(CommandError::InvalidOption(
+            "T-Digest: numkeys needs to be a positive integer",
+        ));
+    }
+    if numkeys > args.remaining() {
+        return Err(CommandError::WrongArity("tdigest.merge"));
+    }
+    let sources: Vec<Bytes> = (0..numkeys)
+        .map(|_| args.next_bytes())
+        .collect::<Result<_, _>>()?;
+
+    let mut compression = None;
+    let mut override_dest = false;
+    loop {
+        if args.eat("COMPRESSION") {
+            compression = Some(parse_compression(&mut args)?);
+        } else if args.eat("OVERRIDE") {
+            override_dest = true;
+        } else {
+            break;
+        }
+    }
+    args.finish()?;
+
+    Ok(Command::TDigestMerge {
+        destination,
+        sources,
+        compression,
+        override_dest,
+    })
+}
+
+pub(super) fn parse_tdigest_info(frames: &[Frame]) -> Result<Command, CommandError> {
+    let mut args = Args::new("tdigest.info", frames);
+    let key = args.next_bytes()?;
+    args.finish()?;
+    Ok(Command::TDigestInfo { key })
+}
+
+fn parse_compression(args: &mut Args) -> Result<u64, CommandError> {
+    match args.next_int::<u64>() {
+        Ok(n) if n > 0 => Ok(n),
+        _ => Err(CommandError::InvalidOption(
+            "T-Digest: compression parameter needs to be a positive integer",
+        )),
+    }
+}
+
+fn floats(values: Vec<Bytes>, error: &'static str) -> Result<Vec<f64>, CommandError> {
+    values
+        .iter()
+        .map(|v| parse_float(v).map_err(|_| CommandError::InvalidOption(error)))
+        .collect()
+}
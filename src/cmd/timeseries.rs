@@ -0,0 +1,286 @@
+use super::{Args, Command, CommandError, parse_float, parse_int};
+use crate::Frame;
+use crate::db::{Aggregation, Aggregator, DuplicatePolicy, LabelFilter, TsOptions, TsRangeQuery};
+use bytes::Bytes;
+
+// TS.CREATE key [RETENTION ms] [DUPLICATE_POLICY policy] [LABELS label value ...]
+pub(super) fn parse_ts_create(frames: &[Frame]) -> Result<Command, CommandError> {
+    let mut args = Args::new("ts.create", frames);
+    let key = args.next_bytes()?;
+    let (options, _) = parse_options(&mut args, false)?;
+    Ok(Command::TsCreate { key, options })
+}
+
+// TS.ADD key timestamp|* value [RETENTION ms] [DUPLICATE_POLICY policy]
+//     [ON_DUPLICATE policy] [LABELS label value ...]
+pub(super) fn parse_ts_add(frames: &[Frame]) -> Result<Command, CommandError> {
+    let mut args = Args::new("ts.add", frames);
+    let key = args.next_bytes()?;
+    let timestamp = args.next_bytes()?;
+    let timestamp = match &timestamp[..] {
+        b"*" => None,
+        ts => Some(parse_timestamp(ts)?),
+    };
+    let value = parse_float(&args.next_bytes()?)
+        .map_err(|_| CommandError::InvalidOption("TSDB: invalid value"))?;
+    let (options, on_duplicate) = parse_options(&mut args, true)?;
+    Ok(Command::TsAdd {
+        key,
+        timestamp,
+        value,
+        options,
+        on_duplicate,
+    })
+}
+
+pub(super) fn parse_ts_get(frames: &[Frame]) -> Result<Command, CommandError> {
+    let mut args = Args::new("ts.get", frames);
+    let key = args.next_bytes()?;
+    args.finish()?;
+    Ok(Command::TsGet { key })
+}
+
+// TS.RANGE and TS.REVRANGE key from to [FILTER_BY_VALUE min max] [COUNT count]
+//     [AGGREGATION aggregator bucketDuration]
+pub(super) fn parse_ts_range(
+    frames: &[Frame],
+    rev: bool,
+    name: &'static str,
+) -> Result<Command, CommandError> {
+    let mut args = Args::new(name, frames);
+    let key = args.next_bytes()?;
+    let (query, _, _) = parse_range(&mut args, rev, false)?;
+    Ok(Command::TsRange { key, query })
+}
+
+// TS.MRANGE and TS.MREVRANGE from to [FILTER_BY_VALUE min max] [WITHLABELS] [COUNT count]
+//     [AGGREGATION aggregator bucketDuration] FILTER filter ...
+pub(super) fn parse_ts_mrange(
+    frames: &[Frame],
+    rev: bool,
+    name: &'static str,
+) -> Result<Command, CommandError> {
+    let mut args = Args::new(name, frames);
+    let (query, with_labels, filters) = parse_range(&mut args, rev, true)?;
+    if filters.is_empty() {
+        return Err(CommandError::InvalidOption("TSDB: missing FILTER argument"));
+    }
+    if !filters
+        .iter()
+        .any(|f| f.equal && f.values.iter().any(|v| !v.is_empty()))
+    {
+        return Err(CommandError::InvalidOption(
+            "TSDB: please provide at least one matcher",
+        ));
+    }
+    Ok(Command::TsMRange {
+        query,
+        filters,
+        with_labels,
+    })
+}
+
+// TS.CREATERULE source destination AGGREGATION aggregator bucketDuration [alignTimestamp]
+pub(super) fn parse_ts_createrule(frames: &[Frame]) -> Result<Command, CommandError> {
+    let mut args = Args::new("ts.createrule", frames);
+    let source = args.next_bytes()?;
+    let destination = args.next_bytes()?;
+    if !args.eat("AGGREGATION") {
+        return Err(CommandError::Syntax);
+    }
+    let aggregation = parse_aggregation(&mut args)?;
+    let align = if args.remaining() > 0 {
+        parse_timestamp(&args.next_bytes()?)?
+    } else {
+        0
+    };
+    args.finish()?;
+    Ok(Command::TsCreateRule {
+        source,
+        destination,
+        aggregation,
+        align,
+    })
+}
+
+pub(super) fn parse_ts_deleterule(frames: &[Frame]) -> Result<Command, CommandError> {
+    let mut args = Args::new("ts.deleterule", frames);
+    let source = args.next_bytes()?;
+    let destination = args.next_bytes()?;
+    args.finish()?;
+    Ok(Command::TsDeleteRule {
+        source,
+        destination,
+    })
+}
+
+pub(super) fn parse_ts_info(frames: &[Frame]) -> Result<Command, CommandError> {
+    let mut args = Args::new("ts.info", frames);
+    let key = args.next_bytes()?;
+    args.finish()?;
+    Ok(Command::TsInfo { key })
+}
+
+// Options for a new series, and ON_DUPLICATE where TS.ADD allows it. LABELS takes the rest.
+fn parse_options(
+    args: &mut Args,
+    on_duplicate_allowed: bool,
+) -> Result<(TsOptions, Option<DuplicatePolicy>), CommandError> {
+    let mut options = TsOptions::default();
+    let mut on_duplicate = None;
+    while args.remaining() > 0 {
+        if args.eat("RETENTION") {
+            options.retention = args
+                .next_int()
+                .map_err(|_| CommandError::InvalidOption("TSDB: Couldn't parse RETENTION"))?;
+        } else if args.eat("DUPLICATE_POLICY") {
+            options.duplicate_policy = parse_policy(args)?;
+        } else if on_duplicate_allowed && args.eat("ON_DUPLICATE") {
+            on_duplicate = Some(parse_policy(args)?);
+        } else if args.eat("LABELS") {
+            let rest = args.rest()?;
+            if rest.len() % 2 != 0 {
+                return Err(CommandError::InvalidOption("TSDB: Couldn't parse LABELS"));
+            }
+            options.labels = rest
+                .chunks(2)
+                .map(|pair| (pair[0].clone(), pair[1].clone()))
+                .collect();
+        } else {
+            return Err(CommandError::Syntax);
+        }
+    }
+    Ok((options, on_duplicate))
+}
+
+fn parse_policy(args: &mut Args) -> Result<DuplicatePolicy, CommandError> {
+    let policy = args.next_bytes()?;
+    Ok(match policy.to_ascii_uppercase().as_slice() {
+        b"BLOCK" => DuplicatePolicy::Block,
+        b"FIRST" => DuplicatePolicy::First,
+        b"LAST" => DuplicatePolicy::Last,
+        b"MIN" => DuplicatePolicy::Min,
+        b"MAX" => DuplicatePolicy::Max,
+        b"SUM" => DuplicatePolicy::Sum,
+        _ => {
+            return Err(CommandError::InvalidOption(
+                "TSDB: Unknown DUPLICATE_POLICY",
+            ));
+        }
+    })
+}
+
+// The range and its options, then WITHLABELS and the FILTER matchers when `multi`
+fn parse_range(
+    args: &mut Args,
+    rev: bool,
+    multi: bool,
+) -> Result<(TsRangeQuery, bool, Vec<LabelFilter>), CommandError> {
+    let from = parse_range_bound(&args.next_bytes()?)?;
+    let to = parse_range_bound(&args.next_bytes()?)?;
+    let mut query = TsRangeQuery {
+        from,
+        to,
+        rev,
+        filter_by_value: None,
+        count: None,
+        aggregation: None,
+    };
+    let mut with_labels = false;
+    let mut filters = Vec::new();
+    while args.remaining() > 0 {
+        if args.eat("FILTER_BY_VALUE") {
+            let invalid = |_| CommandError::InvalidOption("TSDB: Couldn't parse MIN or MAX");
+            let min = args.next_float().map_err(invalid)?;
+            let max = args.next_float().map_err(invalid)?;
+            query.filter_by_value = Some((min, max));
+        } else if args.eat("COUNT") {
+            query.count = Some(
+                args.next_int()
+                    .map_err(|_| CommandError::InvalidOption("TSDB: Couldn't parse COUNT"))?,
+            );
+        } else if args.eat("AGGREGATION") {
+            query.aggregation = Some(parse_aggregation(args)?);
+        } else if multi && args.eat("WITHLABELS") {
+            with_labels = true;
+        } else if multi && args.eat("FILTER") {
+            filters = args
+                .rest()?
+                .iter()
+                .map(parse_filter)
+                .collect::<Result<_, _>>()?;
+        } else {
+            return Err(CommandError::Syntax);
+        }
+    }
+    Ok((query, with_labels, filters))
+}
+
+fn parse_aggregation(args: &mut Args) -> Result<Aggregation, CommandError> {
+    let aggregator = args.next_bytes()?;
+    let aggregator = match aggregator.to_ascii_uppercase().as_slice() {
+        b"AVG" => Aggregator::Avg,
+        b"SUM" => Aggregator::Sum,
+        b"MIN" => Aggregator::Min,
+        b"MAX" => Aggregator::Max,
+        b"COUNT" => Aggregator::Count,
+        b"FIRST" => Aggregator::First,
+        b"LAST" => Aggregator::Last,
+        b"RANGE" => Aggregator::Range,
+        _ => {
+            return Err(CommandError::InvalidOption(
+                "TSDB: Unknown aggregation type",
+            ));
+        }
+    };
+    match args.next_int::<u64>() {
+        Ok(bucket) if bucket > 0 => Ok(Aggregation { aggregator, bucket }),
+        _ => Err(CommandError::InvalidOption(
+            "TSDB: bucketDuration must be greater than zero",
+        )),
+    }
+}
+
+// `-` and `+` stand for the earliest and latest possible timestamps
+fn parse_range_bound(bound: &[u8]) -> Result<u64, CommandError> {
+    match bound {
+        b"-" => Ok(0),
+        b"+" => Ok(u64::MAX),
+        ts => parse_timestamp(ts),
+    }
+}
+
+// Like RedisTimeSeries, timestamps are signed 64-bit milliseconds that can't be negative
+fn parse_timestamp(ts: &[u8]) -> Result<u64, CommandError> {
+    match parse_int::<i64>(ts) {
+        Ok(ts) if ts >= 0 => Ok(ts as u64),
+        _ => Err(CommandError::InvalidOption("TSDB: invalid timestamp")),
+    }
+}
+
+// label=value, label!=value, label=(v1,v2,...), label!=(v1,v2,...), label= or label!=
+fn parse_filter(filter: &Bytes) -> Result<LabelFilter, CommandError> {
+    let invalid = || CommandError::InvalidOption("TSDB: failed parsing labels");
+    let eq = filter.iter().position(|&c| c == b'=').ok_or_else(invalid)?;
+    let (label, equal) = match filter[..eq].strip_suffix(b"!") {
+        Some(label) => (label, false),
+        None => (&filter[..eq], true),
+    };
+    if label.is_empty() {
+        return Err(invalid());
+    }
+
+    let value = filter.slice(eq + 1..);
+    let values = match value.strip_prefix(b"(").and_then(|v| v.strip_suffix(b")")) {
+        Some(list) => list
+            .split(|&c| c == b',')
+            .map(|v| Bytes::copy_from_slice(v.trim_ascii()))
+            .collect(),
+        None => vec![value],
+    };
+    Ok(LabelFilter {
+        label: Bytes::copy_from_slice(label),
+        values,
+        equal,
+    })
+}
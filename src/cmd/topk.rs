@@ -0,0 +1,99 @@
+use super::{Args, Command, CommandError};
+use crate::Frame;
+use crate::db::{MAX_TOPK_BUCKETS, MAX_TOPK_K, TopKOptions};
+
+// The most one TOPK.INCRBY can add to an item
+const MAX_INCREMENT: u64 = 100_000;
+
+// TOPK.RESERVE key topk [width depth decay]
+pub(super) fn parse_topk_reserve(frames: &[Frame]) -> Result<Command, CommandError> {
+    let mut args = Args::new("topk.reserve", frames);
+    let key = args.next_bytes()?;
+    let k = args.next_int::<usize>()?;
+    if k == 0 || k > MAX_TOPK_K {
+        return Err(CommandError::InvalidOption("TopK: invalid k"));
+    }
+
+    let mut options = TopKOptions::new(k);
+    if args.remaining() > 0 {
+        options.width = args.next_int()?;
+        if options.width == 0 {
+            return Err(CommandError::InvalidOption("TopK: invalid width"));
+        }
+        options.depth = args.next_int()?;
+        if options.depth == 0 {
+            return Err(CommandError::InvalidOption("TopK: invalid depth"));
+        }
+        options.decay = args.next_float()?;
+        if !(options.decay > 0.0 && options.decay <= 1.0) {
+            return Err(CommandError::InvalidOption(
+                "TopK: invalid decay value. must be '<= 1' & '> 0'",
+            ));
+        }
+    }
+    args.finish()?;
+    if options
+        .width
+        .checked_mul(options.depth)
+        .is_none_or(|buckets| buckets > MAX_TOPK_BUCKETS)
+    {
+        return Err(CommandError::InvalidOption(
+            "TopK: width * depth is too large",
+        ));
+    }
+
+    Ok(Command::TopKReserve { key, options })
+}
+
+// TOPK.ADD key item [item ...], the same as incrementing each by one
+pub(super) fn parse_topk_add(frames: &[Frame]) -> Result<Command, CommandError> {
+    let mut args = Args::new("topk.add", frames);
+    let key = args.next_bytes()?;
+    let items = args.rest()?.into_iter().map(|item| (item, 1)).collect();
+    Ok(Command::TopKIncrBy { key, items })
+}
+
+// TOPK.INCRBY key item increment [item increment ...]
+pub(super) fn parse_topk_incrby(frames: &[Frame]) -> Result<Command, CommandError> {
+    let mut args = Args::new("topk.incrby", frames);
+    let key = args.next_bytes()?;
+    if args.remaining() == 0 || !args.remaining().is_multiple_of(2) {
+        return Err(CommandError::WrongArity("topk.incrby"));
+    }
+    let mut items = Vec::with_capacity(args.remaining() / 2);
+    while args.remaining() > 0 {
+        let item = args.next_bytes()?;
+        match args.next_int::<u64>() {
+            Ok(by) if (1..=MAX_INCREMENT).contains(&by) => items.push((item, by)),
+            _ => {
+                return Err(CommandError::InvalidOption(
+                    "TopK: increment must be an integer greater or equal to 1 and less than or equal to 100,000",
+                ));
+            }
+        }
+    }
+    Ok(Command::TopKIncrBy { key, items })
+}
+
+pub(super) fn parse_topk_query(frames: &[Frame]) -> Result<Command, CommandError> {
+    let mut args = Args::new("topk.query", frames);
+    let key = args.next_bytes()?;
+    let items = args.rest()?;
+    Ok(Command::TopKQuery { key, items })
+}
+
+// TOPK.LIST key [WITHCOUNT]
+pub(super) fn parse_topk_list(frames: &[Frame]) -> Result<Command, CommandError> {
+    let mut args = Args::new("topk.list", frames);
+    let key = args.next_bytes()?;
+    let with_count = args.eat("WITHCOUNT");
+    args.finish()?;
+    Ok(Command::TopKList { key, with_count })
+}
+
+pub(super) fn parse_topk_info(frames: &[Frame]) -> Result<Command, CommandError> {
+    let mut args = Args::new("topk.info", frames);
+    let key = args.next_bytes()?;
+    args.finish()?;
+    Ok(Command::TopKInfo { key })
+}
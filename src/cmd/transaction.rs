@@ -0,0 +1,27 @@
+use super::{Args, Command, CommandError};
+use crate::Frame;
+
+pub(super) fn parse_multi(frames: &[Frame]) -> Result<Command, CommandError> {
+    Args::new("multi", frames).finish()?;
+    Ok(Command::Multi)
+}
+
+pub(super) fn parse_exec(frames: &[Frame]) -> Result<Command, CommandError> {
+    Args::new("exec", frames).finish()?;
+    Ok(Command::Exec)
+}
+
+pub(super) fn parse_discard(frames: &[Frame]) -> Result<Command, CommandError> {
+    Args::new("discard", frames).finish()?;
+    Ok(Command::Discard)
+}
+
+pub(super) fn parse_watch(frames: &[Frame]) -> Result<Command, CommandError> {
+    let keys = Args::new("watch", frames).rest()?;
+    Ok(Command::Watch { keys })
+}
+
+pub(super) fn parse_unwatch(frames: &[Frame]) -> Result<Command, CommandError> {
+    Args::new("unwatch", frames).finish()?;
+    Ok(Command::Unwatch)
+}
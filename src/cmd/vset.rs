@@ -0,0 +1,199 @@
+use super::{Args, Command, CommandError, parse_float};
+use crate::Frame;
+use crate::db::{Quantization, VAddOptions, VFilter, VSimQuery, VSimTarget};
+use bytes::Bytes;
+use serde_json::Value as Json;
+
+const DEFAULT_COUNT: usize = 10;
+
+// VADD key (FP32 blob | VALUES num value ...) element [CAS] [NOQUANT | Q8 | BIN] [EF ef]
+//     [SETATTR attributes] [M links]
+pub(super) fn parse_vadd(frames: &[Frame]) -> Result<Command, CommandError> {
+    let mut args = Args::new("vadd", frames);
+    let key = args.next_bytes()?;
+    if args.eat("REDUCE") {
+        return Err(CommandError::InvalidOption("REDUCE is not supported"));
+    }
+    let values = parse_vector(&mut args)?;
+    let element = args.next_bytes()?;
+
+    let mut options = VAddOptions::default();
+    while args.remaining() > 0 {
+        if args.eat("NOQUANT") {
+            options.quantization = Quantization::NoQuant;
+        } else if args.eat("Q8") {
+            options.quantization = Quantization::Q8;
+        } else if args.eat("BIN") {
+            options.quantization = Quantization::Bin;
+        } else if args.eat("EF") {
+            options.ef = Some(positive(&mut args, "invalid EF")?);
+        } else if args.eat("M") {
+            options.m = Some(positive(&mut args, "invalid M")?);
+        } else if args.eat("SETATTR") {
+            options.attributes = parse_attributes(&args.next_bytes()?)?;
+        } else if args.eat("CAS") {
+            // Insertion is never threaded here, so there is nothing to check and set
+        } else {
+            return Err(CommandError::Syntax);
+        }
+    }
+    Ok(Command::VAdd {
+        key,
+        values,
+        element,
+        options,
+    })
+}
+
+// VSIM key (ELE element | FP32 blob | VALUES num value ...) [WITHSCORES] [WITHATTRIBS]
+//     [COUNT num] [EF ef] [FILTER expression] [FILTER-EF effort] [TRUTH] [NOTHREAD]
+pub(super) fn parse_vsim(frames: &[Frame]) -> Result<Command, CommandError> {
+    let mut args = Args::new("vsim", frames);
+    let key = args.next_bytes()?;
+    let target = if args.eat("ELE") {
+        VSimTarget::Element(args.next_bytes()?)
+    } else {
+        VSimTarget::Vector(parse_vector(&mut args)?)
+    };
+
+    let mut query = VSimQuery {
+        target,
+        count: DEFAULT_COUNT,
+        ef: None,
+        filter: None,
+        filter_ef: None,
+        truth: false,
+    };
+    let mut with_scores = false;
+    let mut with_attribs = false;
+    while args.remaining() > 0 {
+        if args.eat("WITHSCORES") {
+            with_scores = true;
+        } else if args.eat("WITHATTRIBS") {
+            with_attribs = true;
+        } else if args.eat("COUNT") {
+            query.count = positive(&mut args, "invalid COUNT")?;
+        } else if args.eat("EF") {
+            query.ef = Some(positive(&mut args, "invalid EF")?);
+        } else if args.eat("FILTER") {
+            let expression = args.next_bytes()?;
+            query.filter = Some(
+                std::str::from_utf8(&expression)
+                    .ok()
+                    .and_then(VFilter::parse)
+                    .ok_or(CommandError::InvalidOption(
+                        "syntax error in FILTER expression",
+                    ))?,
+            );
+        } else if args.eat("FILTER-EF") {
+            query.filter_ef = Some(positive(&mut args, "invalid FILTER-EF")?);
+        } else if args.eat("TRUTH") {
+            query.truth = true;
+        } else if args.eat("NOTHREAD") {
+            // Searches always run on the connection's task
+        } else {
+            return Err(CommandError::Syntax);
+        }
+    }
+    Ok(Command::VSim {
+        key,
+        query,
+        with_scores,
+        with_attribs,
+    })
+}
+
+pub(super) fn parse_vrem(frames: &[Frame]) -> Result<Command, CommandError> {
+    let (key, element) = key_element(frames, "vrem")?;
+    Ok(Command::VRem { key, element })
+}
+
+pub(super) fn parse_vcard(frames: &[Frame]) -> Result<Command, CommandError> {
+    let mut args = Args::new("vcard", frames);
+    let key = args.next_bytes()?;
+    args.finish()?;
+    Ok(Command::VCard { key })
+}
+
+pub(super) fn parse_vdim(frames: &[Frame]) -> Result<Command, CommandError> {
+    let mut args = Args::new("vdim", frames);
+    let key = args.next_bytes()?;
+    args.finish()?;
+    Ok(Command::VDim { key })
+}
+
+pub(super) fn parse_vemb(frames: &[Frame]) -> Result<Command, CommandError> {
+    let (key, element) = key_element(frames, "vemb")?;
+    Ok(Command::VEmb { key, element })
+}
+
+// VSETATTR key element attributes, where an empty string removes them
+pub(super) fn parse_vsetattr(frames: &[Frame]) -> Result<Command, CommandError> {
+    let mut args = Args::new("vsetattr", frames);
+    let key = args.next_bytes()?;
+    let element = args.next_bytes()?;
+    let attributes = parse_attributes(&args.next_bytes()?)?;
+    args.finish()?;
+    Ok(Command::VSetAttr {
+        key,
+        element,
+        attributes,
+    })
+}
+
+pub(super) fn parse_vgetattr(frames: &[Frame]) -> Result<Command, CommandError> {
+    let (key, element) = key_element(frames, "vgetattr")?;
+    Ok(Command::VGetAttr { key, element })
+}
+
+fn key_element(frames: &[Frame], name: &'static str) -> Result<(Bytes, Bytes), CommandError> {
+    let mut args = Args::new(name, frames);
+    let key = args.next_bytes()?;
+    let element = args.next_bytes()?;
+    args.finish()?;
+    Ok((key, element))
+}
+
+// FP32 followed by little endian floats packed in one argument, or VALUES with a count
+fn parse_vector(args: &mut Args) -> Result<Vec<f32>, CommandError> {
+    let invalid = || CommandError::InvalidOption("invalid vector specification");
+    let values = if args.eat("FP32") {
+        let blob = args.next_bytes()?;
+        if blob.len() % 4 != 0 {
+            return Err(invalid());
+        }
+        blob.chunks_exact(4)
+            .map(|c| f32::from_le_bytes([c[0], c[1], c[2], c[3]]))
+            .collect()
+    } else if args.eat("VALUES") {
+        let count = args.next_int::<usize>().map_err(|_| invalid())?;
+        if count > args.remaining() {
+            return Err(invalid());
+        }
+        (0..count)
+            .map(|_| Ok(parse_float(&args.next_bytes()?).map_err(|_| invalid())? as f32))
+            .collect::<Result<Vec<f32>, CommandError>>()?
+    } else {
+        return Err(invalid());
+    };
+    if values.is_empty() || values.iter().any(|v| !v.is_finite()) {
+        return Err(invalid());
+    }
+    Ok(values)
+}
+
+fn parse_attributes(attributes: &[u8]) -> Result<Option<Json>, CommandError> {
+    if attributes.is_empty() {
+        return Ok(None);
+    }
+    serde_json::from_slice(attributes)
+        .map(Some)
+        .map_err(|_| CommandError::InvalidOption("invalid JSON attributes"))
+}
+
+fn positive(args: &mut Args, error: &'static str) -> Result<usize, CommandError> {
+    match args.next_int::<usize>() {
+        Ok(n) if n > 0 => Ok(n),
+        _ => Err(CommandError::InvalidOption(error)),
+    }
+}
@@ -0,0 +1,463 @@
+use super::{Args, Command, CommandError, parse_float, parse_int, parse_timeout, set};
+use crate::Frame;
+use crate::db::{
+    Aggregate, LexBound, RangeBy, ScoreBound, SetOp, ZAddComparison, ZAddCondition, ZAddFlags,
+    ZRange,
+};
+use bytes::Bytes;
+
+// Which kind of bounds a range command takes
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(super) enum RangeKind {
+    Rank,
+    Score,
+    Lex,
+}
+
+pub(super) fn parse_zadd(frames: &[Frame]) -> Result<Command, CommandError> {
+    let mut args = Args::new("zadd", frames);
+    let key = args.next_bytes()?;
+
+    let mut flags = ZAddFlags::default();
+    let mut nx = false;
+    let mut xx = false;
+    let mut gt = false;
+    let mut lt = false;
+    let mut incr = false;
+    loop {
+        if args.eat("NX") {
+            nx = true;
+        } else if args.eat("XX") {
+            xx = true;
+        } else if args.eat("GT") {
+            gt = true;
+        } else if args.eat("LT") {
+            lt = true;
+        } else if args.eat("CH") {
+            flags.ch = true;
+        } else if args.eat("INCR") {
+            incr = true;
+        } else {
+            break;
+        }
+    }
+
+    if nx && xx {
+        return Err(CommandError::InvalidOption(
+            "XX and NX options at the same time are not compatible",
+        ));
+    }
+    if (gt && lt) || ((gt || lt) && nx) {
+        return Err(CommandError::InvalidOption(
+            "GT, LT, and/or NX options at the same time are not compatible",
+        ));
+    }
+    flags.condition = match (nx, xx) {
+        (true, _) => Some(ZAddCondition::Nx),
+        (_, true) => Some(ZAddCondition::Xx),
+        _ => None,
+    };
+    flags.comparison = match (gt, lt) {
+        (true, _) => Some(ZAddComparison::Gt),
+        (_, true) => Some(ZAddComparison::Lt),
+        _ => None,
+    };
+
+    if args.remaining() == 0 || !args.remaining().is_multiple_of(2) {
+        return Err(CommandError::Syntax);
+    }
+    if incr && args.remaining() != 2 {
+        return Err(CommandError::InvalidOption(
+            "INCR option supports a single increment-element pair",
+        ));
+    }
+
+    let mut pairs = Vec::with_capacity(args.remaining() / 2);
+    while args.remaining() > 0 {
+        let score = args.next_float()?;
+        let member = args.next_bytes()?;
+        pairs.push((score, member));
+    }
+
+    Ok(Command::ZAdd {
+        key,
+        flags,
+        incr,
+        pairs,
+    })
+}
+
+pub(super) fn parse_zincrby(frames: &[Frame]) -> Result<Command, CommandError> {
+    let mut args = Args::new("zincrby", frames);
+    let key = args.next_bytes()?;
+    let delta = args.next_float()?;
+    let member = args.next_bytes()?;
+    args.finish()?;
+    Ok(Command::ZIncrBy { key, delta, member })
+}
+
+pub(super) fn parse_zrem(frames: &[Frame]) -> Result<Command, CommandError> {
+    let mut args = Args::new("zrem", frames);
+    let key = args.next_bytes()?;
+    let members = args.rest()?;
+    Ok(Command::ZRem { key, members })
+}
+
+pub(super) fn parse_zscore(frames: &[Frame]) -> Result<Command, CommandError> {
+    let mut args = Args::new("zscore", frames);
+    let key = args.next_bytes()?;
+    let member = args.next_bytes()?;
+    args.finish()?;
+    Ok(Command::ZScore { key, member })
+}
+
+pub(super) fn parse_zmscore(frames: &[Frame]) -> Result<Command, CommandError> {
+    let mut args = Args::new("zmscore", frames);
+    let key = args.next_bytes()?;
+    let members = args.rest()?;
+    Ok(Command::ZMScore { key, members })
+}
+
+pub(super) fn parse_zcard(frames: &[Frame]) -> Result<Command, CommandError> {
+    let mut args = Args::new("zcard", frames);
+    let key = args.next_bytes()?;
+    args.finish()?;
+    Ok(Command::ZCard { key })
+}
+
+pub(super) fn parse_zrank(
+    frames: &[Frame],
+    rev: bool,
+    name: &'static str,
+) -> Result<Command, CommandError> {
+    let mut args = Args::new(name, frames);
+    let key = args.next_bytes()?;
+    let member = args.next_bytes()?;
+    let with_score = args.eat("WITHSCORE");
+    args.finish()?;
+    Ok(Command::ZRank {
+        key,
+        member,
+        rev,
+        with_score,
+    })
+}
+
+pub(super) fn parse_zrange(
+    frames: &[Frame],
+    fixed: Option<(RangeKind, bool)>,
+    name: &'static str,
+) -> Result<Command, CommandError> {
+    let mut args = Args::new(name, frames);
+    let key = args.next_bytes()?;
+    let (range, with_scores) = parse_range_spec(&mut args, fixed)?;
+    Ok(Command::ZRange {
+        key,
+        range,
+        with_scores,
+    })
+}
+
+pub(super) fn parse_zrangestore(frames: &[Frame]) -> Result<Command, CommandError> {
+    let mut args = Args::new("zrangestore", frames);
+    let destination = args.next_bytes()?;
+    let source = args.next_bytes()?;
+    let (range, with_scores) = parse_range_spec(&mut args, None)?;
+    if with_scores {
+        return Err(CommandError::Syntax);
+    }
+    Ok(Command::ZRangeStore {
+        destination,
+        source,
+        range,
+    })
+}
+
+// Parses `start stop [options]`. `fixed` is the range kind and direction implied by the older
+// commands; ZRANGE and ZRANGESTORE take them from BYSCORE/BYLEX/REV instead
+fn parse_range_spec(
+    args: &mut Args,
+    fixed: Option<(RangeKind, bool)>,
+) -> Result<(ZRange, bool), CommandError> {
+    let start = args.next_bytes()?;
+    let stop = args.next_bytes()?;
+
+    let (mut kind, mut rev) = fixed.unwrap_or((RangeKind::Rank, false));
+    let mut limit = None;
+    let mut with_scores = false;
+    while args.remaining() > 0 {
+        if args.eat("WITHSCORES") {
+            with_scores = true;
+        } else if args.eat("LIMIT") {
+            limit = Some((args.next_int()?, args.next_int()?));
+        } else if fixed.is_none() && args.eat("BYSCORE") && kind == RangeKind::Rank {
+            kind = RangeKind::Score;
+        } else if fixed.is_none() && args.eat("BYLEX") && kind == RangeKind::Rank {
+            kind = RangeKind::Lex;
+        } else if fixed.is_none() && args.eat("REV") {
+            rev = true;
+        } else {
+            return Err(CommandError::Syntax);
+        }
+    }
+
+    if kind == RangeKind::Lex && with_scores {
+        return Err(CommandError::InvalidOption(
+            "Syntax error, WITHSCORES not supported in combination with BYLEX",
+        ));
+    }
+    if kind == RangeKind::Rank && limit.is_some() {
+        return Err(CommandError::InvalidOption(
+            "Syntax error, LIMIT is only supported in combination with either BYSCORE or BYLEX",
+        ));
+    }
+
+    // Reversed score and lex ranges are given highest bound first
+    let (min, max) = if rev && kind != RangeKind::Rank {
+        (stop, start)
+    } else {
+        (start, stop)
+    };
+
+    let range = ZRange {
+        by: parse_range_by(kind, &min, &max)?,
+        rev,
+        limit,
+    };
+    Ok((range, with_scores))
+}
+
+// ZUNION, ZINTER and ZDIFF, plus their STORE variants which take a destination first
+pub(super) fn parse_zset_op(
+    frames: &[Frame],
+    op: SetOp,
+    store: bool,
+    name: &'static str,
+) -> Result<Command, CommandError> {
+    let mut args = Args::new(name, frames);
+    let destination = if store {
+        Some(args.next_bytes()?)
+    } else {
+        None
+    };
+
+    let numkeys: usize = args.next_int()?;
+    if numkeys == 0 {
+        return Err(CommandError::NoKeys(name));
+    }
+    if numkeys > args.remaining() {
+        return Err(CommandError::Syntax);
+    }
+    let keys = (0..numkeys)
+        .map(|_| args.next_bytes())
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut weights = None;
+    let mut aggregate = Aggregate::default();
+    let mut with_scores = false;
+    while args.remaining() > 0 {
+        if op != SetOp::Diff && args.eat("WEIGHTS") {
+            if args.remaining() < numkeys {
+                return Err(CommandError::Syntax);
+            }
+            weights = Some(
+                (0..numkeys)
+                    .map(|_| args.next_float())
+                    .collect::<Result<Vec<_>, _>>()?,
+            );
+        } else if op != SetOp::Diff && args.eat("AGGREGATE") {
+            aggregate = if args.eat("SUM") {
+                Aggregate::Sum
+            } else if args.eat("MIN") {
+                Aggregate::Min
+            } else if args.eat("MAX") {
+                Aggregate::Max
+            } else {
+                return Err(CommandError::Syntax);
+            };
+        } else if !store && args.eat("WITHSCORES") {
+            with_scores = true;
+        } else {
+            return Err(CommandError::Syntax);
+        }
+    }
+
+    Ok(match destination {
+        Some(destination) => Command::ZSetOpStore {
+            op,
+            destination,
+            keys,
+            weights,
+            aggregate,
+        },
+        None => Command::ZSetOp {
+            op,
+            keys,
+            weights,
+            aggregate,
+            with_scores,
+        },
+    })
+}
+
+pub(super) fn parse_zcount(
+    frames: &[Frame],
+    kind: RangeKind,
+    name: &'static str,
+) -> Result<Command, CommandError> {
+    let mut args = Args::new(name, frames);
+    let key = args.next_bytes()?;
+    let min = args.next_bytes()?;
+    let max = args.next_bytes()?;
+    args.finish()?;
+    Ok(Command::ZCount {
+        key,
+        by: parse_range_by(kind, &min, &max)?,
+    })
+}
+
+pub(super) fn parse_zpop(
+    frames: &[Frame],
+    max: bool,
+    name: &'static str,
+) -> Result<Command, CommandError> {
+    let mut args = Args::new(name, frames);
+    let key = args.next_bytes()?;
+    let count = match args.remaining() {
+        0 => None,
+        _ => Some(args.next_int()?),
+    };
+    args.finish()?;
+    Ok(Command::ZPop { key, count, max })
+}
+
+pub(super) fn parse_zrandmember(frames: &[Frame]) -> Result<Command, CommandError> {
+    let mut args = Args::new("zrandmember", frames);
+    let key = args.next_bytes()?;
+    let count = match args.remaining() {
+        0 => None,
+        _ => Some(set::random_count(&mut args)?),
+    };
+    let with_scores = count.is_some() && args.eat("WITHSCORES");
+    args.finish()?;
+    Ok(Command::ZRandMember {
+        key,
+        count,
+        with_scores,
+    })
+}
+
+pub(super) fn parse_range_by(
+    kind: RangeKind,
+    min: &Bytes,
+    max: &Bytes,
+) -> Result<RangeBy, CommandError> {
+    Ok(match kind {
+        RangeKind::Rank => RangeBy::Rank {
+            start: parse_int(min)?,
+            stop: parse_int(max)?,
+        },
+        RangeKind::Score => RangeBy::Score {
+            min: parse_score_bound(min)?,
+            max: parse_score_bound(max)?,
+        },
+        RangeKind::Lex => RangeBy::Lex {
+            min: parse_lex_bound(min)?,
+            max: parse_lex_bound(max)?,
+        },
+    })
+}
+
+// A score, or `(score` for an exclusive bound
+fn parse_score_bound(bound: &Bytes) -> Result<ScoreBound, CommandError> {
+    let parsed = match bound.strip_prefix(b"(") {
+        Some(score) => parse_float(score).map(ScoreBound::Exclusive),
+        None => parse_float(bound).map(ScoreBound::Inclusive),
+    };
+    parsed.map_err(|_| CommandError::InvalidScoreRange)
+}
+
+// `-`, `+`, `[member` or `(member`
+fn parse_lex_bound(bound: &Bytes) -> Result<LexBound, CommandError> {
+    match bound.first() {
+        Some(b'-') if bound.len() == 1 => Ok(LexBound::Min),
+        Some(b'+') if bound.len() == 1 => Ok(LexBound::Max),
+        Some(b'[') => Ok(LexBound::Inclusive(bound.slice(1..))),
+        Some(b'(') => Ok(LexBound::Exclusive(bound.slice(1..))),
+        _ => Err(CommandError::InvalidLexRange),
+    }
+}
+
+// BZPOPMIN and BZPOPMAX take their keys followed by a timeout
+pub(super) fn parse_bzpop(
+    frames: &[Frame],
+    max: bool,
+    name: &'static str,
+) -> Result<Command, CommandError> {
+    let mut args = Args::new(name, frames);
+    let mut keys = args.rest()?;
+    if keys.len() < 2 {
+        return Err(CommandError::WrongArity(name));
+    }
+    let timeout = parse_timeout(&keys.pop().unwrap_or_default())?;
+    Ok(Command::BZPop { keys, max, timeout })
+}
+
+// ZMPOP numkeys key [key ...] MIN|MAX [COUNT count], with BZMPOP taking a timeout first
+pub(super) fn parse_zmpop(
+    frames: &[Frame],
+    blocking: bool,
+    name: &'static str,
+) -> Result<Command, CommandError> {
+    let mut args = Args::new(name, frames);
+    let timeout = if blocking {
+        Some(parse_timeout(&args.next_bytes()?)?)
+    } else {
+        None
+    };
+
+    let numkeys: usize = args.next_int()?;
+    if numkeys == 0 {
+        return Err(CommandError::InvalidOption(
+            "numkeys should be greater than 0",
+        ));
+    }
+    if numkeys >= args.remaining() {
+        return Err(CommandError::Syntax);
+    }
+    let keys = (0..numkeys)
+        .map(|_| args.next_bytes())
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let max = if args.eat("MIN") {
+        false
+    } else if args.eat("MAX") {
+        true
+    } else {
+        return Err(CommandError::Syntax);
+    };
+
+    let count = if args.eat("COUNT") {
+        match args.next_int()? {
+            0 => {
+                return Err(CommandError::InvalidOption(
+                    "count should be greater than 0",
+                ));
+            }
+            count => count,
+        }
+    } else {
+        1
+    };
+    args.finish()?;
+
+    Ok(match timeout {
+        Some(timeout) => Command::BZMPop {
+            keys,
+            max,
+            count,
+            timeout,
+        },
+        None => Command::ZMPop { keys, max, count },
+    })
+}
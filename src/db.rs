@@ -1,26 +1,315 @@
 use bytes::Bytes;
-use std::collections::HashMap;
-use std::sync::{Arc, Mutex};
+use std::collections::{HashMap, HashSet};
+use std::ops::{Deref, DerefMut};
+use std::sync::{Arc, Mutex, MutexGuard};
 use std::time::{Duration, Instant};
 
+mod blocking;
+mod bloom;
+mod cms;
+mod config;
+mod cuckoo;
+mod ftquery;
+mod function;
+mod geo;
+mod geohash;
+mod glob;
+mod group;
+mod hash;
+mod hnsw;
+mod hyperloglog;
+mod json;
+mod jsonpath;
+mod module;
+mod notify;
+mod pubsub;
+mod script;
+mod search;
+mod set;
+mod skiplist;
+mod slot;
+mod stream;
+mod tdigest;
+mod timeseries;
+mod topk;
+mod tracking;
+mod transaction;
+mod vfilter;
+mod vset;
+mod watch;
+mod zset;
+
+pub use blocking::Popped;
+use bloom::BloomFilter;
+pub use bloom::{BloomInfo, BloomInfoField};
+use cms::CountMinSketch;
+pub use cms::{CmsInfo, MAX_CMS_COUNTERS};
+use cuckoo::CuckooFilter;
+pub use cuckoo::CuckooOptions;
+pub use ftquery::FtQuery;
+use function::Functions;
+pub use function::{FunctionInfo, Library, RestorePolicy, dump_codes};
+pub use geo::{GeoFrom, GeoMatch, GeoQuery, GeoShape, GeoSort, GeoUnit};
+pub use group::{
+    AutoClaimed, ClaimOptions, ConsumerInfo, GroupEntry, GroupInfo, GroupReadFrom, GroupStart,
+    PendingFilter, PendingInfo, PendingSummary,
+};
+pub use json::{JsonCondition, JsonFormat};
+pub use jsonpath::JsonPath;
+use module::Modules;
+pub use module::{MAX_WASM_FUEL, ModuleCommand, WasmLimits, WasmModule};
+pub use notify::NotifyEvents;
+use notify::{Class, Notifications};
+pub use pubsub::{Delivery, Message, SUBSCRIBER_QUEUE, Subscriber};
+use script::Scripts;
+pub use script::{ScriptRun, sha1_hex};
+pub use search::{FieldSpec, FieldType, FtDoc, FtInfo, FtResults, FtSearchOptions, IndexSpec};
+pub use set::SetOp;
+pub use slot::{SLOTS, key_slot};
+use stream::Stream;
+pub use stream::{StreamEntry, StreamId, StreamInfo, StreamTrim, TrimStrategy, XAddId, XReadFrom};
+use tdigest::TDigest;
+pub use tdigest::{DEFAULT_COMPRESSION, TDigestInfo};
+use timeseries::TimeSeries;
+pub use timeseries::{
+    Aggregation, Aggregator, DuplicatePolicy, LabelFilter, Sample, TsInfo, TsMatch, TsOptions,
+    TsRangeQuery,
+};
+use topk::TopK;
+pub use topk::{MAX_TOPK_BUCKETS, MAX_TOPK_K, TopKOptions};
+pub use tracking::TrackingOptions;
+use tracking::{Caller, Tracking};
+pub use vfilter::VFilter;
+use vset::VectorSet;
+pub use vset::{Quantization, VAddOptions, VSimMatch, VSimQuery, VSimTarget};
+use watch::Watches;
+use zset::SortedSet;
+pub use zset::{
+    Aggregate, LexBound, RangeBy, ScoreBound, ZAddComparison, ZAddCondition, ZAddFlags, ZRange,
+};
+
 #[derive(Clone)]
 pub struct Db {
     shared: Arc<Shared>,
+    // The client commands are made as, on handles from `for_client`
+    caller: Option<Caller>,
 }
 
 struct Shared {
+    // Taken shared by the server around each command, and exclusively around a transaction or
+    // script so no other client's command runs until it's done
+    gate: Arc<tokio::sync::RwLock<()>>,
     state: Mutex<State>,
+    pubsub: Mutex<pubsub::Registry>,
+    scripts: Mutex<Scripts>,
+    functions: Mutex<Functions>,
+    modules: Mutex<Modules>,
 }
 
 struct State {
     entries: HashMap<Bytes, Entry>,
+    blocked: blocking::Blocked,
+    indexes: search::Indexes,
+    notifications: Notifications,
+    tracking: Tracking,
+    watches: Watches,
+    // Set while a read-only command holds the lock, so missing keys fire key miss events and
+    // tracking clients have their reads remembered
+    reading: bool,
+    // The client whose command holds the lock, if it was made through a client handle
+    client: Option<Caller>,
 }
 
+// Serves any blocked clients whose keys were written to, publishes keyspace notifications and
+// sends tracking invalidations before the lock is released
+struct StateGuard<'a>(MutexGuard<'a, State>, &'a Shared);
+
 struct Entry {
-    value: Bytes,
+    value: Value,
     expires_at: Option<Instant>,
 }
 
+// The data types a key can hold
+enum Value {
+    String(Bytes),
+    Hash(HashMap<Bytes, Bytes>),
+    Set(HashSet<Bytes>),
+    SortedSet(SortedSet),
+    Stream(Stream),
+    Bloom(BloomFilter),
+    Cuckoo(CuckooFilter),
+    Cms(CountMinSketch),
+    TopK(TopK),
+    TDigest(TDigest),
+    Json(serde_json::Value),
+    TimeSeries(TimeSeries),
+    VectorSet(VectorSet),
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum DbError {
+    #[error("WRONGTYPE Operation against a key holding the wrong kind of value")]
+    WrongType,
+    #[error("Resulting score is not a number (NaN)")]
+    NanScore,
+    #[error("The ID specified in XADD is equal or smaller than the target stream top item")]
+    StreamIdTooSmall,
+    #[error("The ID specified in XADD must be greater than 0-0")]
+    StreamIdZero,
+    #[error("The stream has exhausted the last possible ID, unable to add more items")]
+    StreamExhausted,
+    #[error("no such key")]
+    NoSuchKey,
+    #[error("NOGROUP No such key or consumer group")]
+    NoGroup,
+    #[error("BUSYGROUP Consumer Group name already exists")]
+    BusyGroup,
+    #[error(
+        "The XGROUP subcommand requires the key to exist. Note that for CREATE you may want to use the MKSTREAM option to create an empty stream automatically."
+    )]
+    XGroupNeedsKey,
+    #[error("WRONGTYPE Key is not a valid HyperLogLog string value.")]
+    InvalidHll,
+    #[error("INVALIDOBJ Corrupted HLL object detected")]
+    CorruptHll,
+    #[error("could not decode requested zset member")]
+    GeoMemberMissing,
+    #[error("invalid longitude,latitude pair {0:.6},{1:.6}")]
+    InvalidLonLat(f64, f64),
+    #[error("item exists")]
+    ItemExists,
+    #[error("not found")]
+    NotFound,
+    #[error("non scaling filter is full")]
+    FilterFull,
+    #[error("Filter is full")]
+    CuckooFull,
+    #[error("could not create filter, it would exceed the maximum size")]
+    FilterTooBig,
+    #[error("CMS: key already exists")]
+    CmsExists,
+    #[error("CMS: key does not exist")]
+    CmsMissing,
+    #[error("CMS: width/depth is not equal")]
+    CmsMismatch,
+    #[error("CMS: width * depth is too large")]
+    CmsTooBig,
+    #[error("TopK: key already exists")]
+    TopKExists,
+    #[error("TopK: key does not exist")]
+    TopKMissing,
+    #[error("TopK: sketch is too large")]
+    TopKTooBig,
+    #[error("T-Digest: key already exists")]
+    TDigestExists,
+    #[error("T-Digest: key does not exist")]
+    TDigestMissing,
+    #[error("new objects must be created at the root")]
+    JsonNewAtRoot,
+    #[error("could not perform this operation on a key that doesn't exist")]
+    JsonNoKey,
+    #[error("Path '{0}' does not exist")]
+    JsonNoPath(String),
+    #[error("WRONGTYPE wrong type of path value - expected {0} but found {1}")]
+    JsonWrongType(&'static str, &'static str),
+    #[error("index out of bounds")]
+    JsonIndexOutOfBounds,
+    #[error("result is not a number")]
+    JsonNotANumber,
+    #[error("TSDB: key already exists")]
+    TsKeyExists,
+    #[error("TSDB: the key does not exist")]
+    TsMissing,
+    #[error("TSDB: Timestamp is older than retention")]
+    TsOlderThanRetention,
+    #[error(
+        "TSDB: Error at upsert, update is not supported when DUPLICATE_POLICY is set to BLOCK mode"
+    )]
+    TsDuplicateBlocked,
+    #[error("TSDB: the source key and destination key should be different")]
+    TsSameKey,
+    #[error("TSDB: the source key already has a source rule")]
+    TsSourceHasSource,
+    #[error("TSDB: the destination key already has a src rule")]
+    TsDestinationHasSource,
+    #[error("TSDB: the destination key already has a dst rule")]
+    TsDestinationHasRules,
+    #[error("TSDB: compaction rule does not exist")]
+    TsRuleMissing,
+    #[error("Vector dimension mismatch - got {0} but set has {1}")]
+    VectorDimMismatch(usize, usize),
+    #[error("asked quantization mismatch with existing vector set")]
+    QuantizationMismatch,
+    #[error("key does not exist")]
+    VectorSetMissing,
+    #[error("element not found in set")]
+    VectorElementMissing,
+    #[error("Index already exists")]
+    FtIndexExists,
+    #[error("Unknown index name")]
+    FtUnknownIndex,
+    #[error("Unknown field `{0}`")]
+    FtUnknownField(String),
+    #[error("Field `{0}` is not a {1} field")]
+    FtFieldType(String, &'static str),
+    #[error("Unknown option or number of arguments for CONFIG SET - '{0}'")]
+    UnknownConfig(String),
+    #[error("CONFIG SET failed (possibly related to argument '{0}') - {1}")]
+    InvalidConfigValue(&'static str, &'static str),
+    #[error("The client ID you want redirect to does not exist")]
+    RedirectMissing,
+    #[error(
+        "You can't switch BCAST mode on/off before disabling tracking for this client, and then re-enabling it with a different mode."
+    )]
+    TrackingBcastSwitch,
+    #[error(
+        "You can't switch OPTIN/OPTOUT mode before disabling tracking for this client, and then re-enabling it with a different mode."
+    )]
+    TrackingOptSwitch,
+    #[error(
+        "Prefix '{0}' overlaps with an existing prefix '{1}'. Prefixes for a single client must not overlap."
+    )]
+    PrefixOverlap(String, String),
+    #[error(
+        "CLIENT CACHING can be called only when the client is in tracking mode with OPTIN or OPTOUT mode enabled"
+    )]
+    CachingNeedsTracking,
+    #[error("CLIENT CACHING YES is only valid when tracking is enabled in OPTIN mode.")]
+    CachingNeedsOptin,
+    #[error("CLIENT CACHING NO is only valid when tracking is enabled in OPTOUT mode.")]
+    CachingNeedsOptout,
+    #[error("BUSY padis is busy running a script. You can only call FUNCTION KILL or SCRIPT KILL.")]
+    Busy,
+    #[error("NOTBUSY No scripts in execution right now.")]
+    NotBusy,
+    #[error(
+        "UNKILLABLE Sorry the script already executed write commands against the dataset. You can either wait the script termination or kill the server in a hard way using the SHUTDOWN NOSAVE command."
+    )]
+    Unkillable,
+    #[error("NOSCRIPT No matching script. Please use EVAL.")]
+    NoScript,
+    #[error("Library '{0}' already exists")]
+    LibraryExists(String),
+    #[error("Function {0} already exists")]
+    FunctionExists(String),
+    #[error("Library not found")]
+    LibraryNotFound,
+    #[error("Function not found")]
+    FunctionNotFound,
+    #[error("Can not execute a script with write flag using *_ro command.")]
+    WriteFunctionReadOnly,
+    #[error("payload version or checksum are wrong")]
+    BadPayload,
+    #[error("given type is not a function")]
+    NotFunctionPayload,
+    #[error("Module '{0}' is already loaded")]
+    ModuleExists(String),
+    #[error("Command '{0}' is already registered")]
+    CommandExists(String),
+    #[error("Error unloading module: no such module with that name")]
+    ModuleNotFound,
+}
+
 impl Default for Db {
     fn default() -> Self {
         Self::new()
@@ -31,57 +320,214 @@ impl Db {
     pub fn new() -> Db {
         Db {
             shared: Arc::new(Shared {
+                gate: Default::default(),
                 state: Mutex::new(State {
                     entries: HashMap::new(),
+                    blocked: Default::default(),
+                    indexes: Default::default(),
+                    notifications: Default::default(),
+                    tracking: Default::default(),
+                    watches: Default::default(),
+                    reading: false,
+                    client: None,
                 }),
+                pubsub: Default::default(),
+                scripts: Default::default(),
+                functions: Default::default(),
+                modules: Default::default(),
             }),
+            caller: None,
         }
     }
 
+    // Lock the state, recovering it if another thread panicked while holding the lock
+    fn lock(&self) -> StateGuard<'_> {
+        let mut state = match self.shared.state.lock() {
+            Ok(guard) => StateGuard(guard, &self.shared),
+            Err(poisoned) => StateGuard(poisoned.into_inner(), &self.shared),
+        };
+        state.client = self.caller;
+        state
+    }
+
+    // Lock the state for a command that only reads
+    fn lock_read(&self) -> StateGuard<'_> {
+        let mut state = self.lock();
+        state.reading = true;
+        state
+    }
+
+    fn pubsub(&self) -> MutexGuard<'_, pubsub::Registry> {
+        self.shared.pubsub()
+    }
+
+    // Returns None if the key is missing or doesn't hold a string
     pub fn get(&self, key: &Bytes) -> Option<Bytes> {
-        let mut state = self.shared.state.lock().unwrap();
+        self.get_string(key).ok().flatten()
+    }
 
-        let expired = state
-            .entries
-            .get(key)
-            .map(|e| e.expires_at.is_some_and(|exp| Instant::now() > exp))
-            .unwrap_or(false);
+    pub fn get_string(&self, key: &Bytes) -> Result<Option<Bytes>, DbError> {
+        let mut state = self.lock_read();
 
-        if expired {
-            state.entries.remove(key);
-            return None;
+        match state.live(key) {
+            Some(Entry {
+                value: Value::String(s),
+                ..
+            }) => Ok(Some(s.clone())),
+            Some(_) => Err(DbError::WrongType),
+            None => Ok(None),
         }
-
-        state.entries.get(key).map(|e| e.value.clone())
     }
 
     pub fn set(&self, key: &Bytes, value: Bytes, expiry: Option<Duration>) {
-        let mut hm = match self.shared.state.lock() {
-            Ok(guard) => guard,
-            Err(poisoned) => poisoned.into_inner(),
-        };
+        let mut hm = self.lock();
         let expires_at = expiry.map(|d| Instant::now() + d);
-        hm.entries.insert(key.clone(), Entry { value, expires_at });
+        hm.insert(
+            key.clone(),
+            Entry {
+                value: Value::String(value),
+                expires_at,
+            },
+        );
+        hm.reindex(key);
+        hm.notify(Class::String, "set", key);
+        if expiry.is_some() {
+            hm.notify(Class::Generic, "expire", key);
+        }
     }
 
     pub fn del(&self, key: &Bytes) -> bool {
-        let mut hm = match self.shared.state.lock() {
-            Ok(guard) => guard,
-            Err(poisoned) => poisoned.into_inner(),
-        };
-        hm.entries.remove(key).is_some()
+        let mut hm = self.lock();
+        let removed = hm.live(key).is_some() && hm.entries.remove(key).is_some();
+        hm.reindex(key);
+        if removed {
+            hm.notify(Class::Generic, "del", key);
+        }
+        removed
+    }
+
+    // FLUSHDB and FLUSHALL, there being the one database
+    pub fn flush(&self) {
+        let mut state = self.lock();
+        let keys: Vec<Bytes> = state.entries.drain().map(|(key, _)| key).collect();
+        for key in &keys {
+            state.reindex(key);
+            state.changed(key);
+        }
     }
 
     pub fn keys(&self) -> Vec<Bytes> {
-        let mut hm = match self.shared.state.lock() {
+        let mut hm = self.lock();
+        let now = Instant::now();
+        let expired: Vec<Bytes> = hm
+            .entries
+            .iter()
+            .filter(|(_, entry)| entry.expires_at.is_some_and(|expiry| now > expiry))
+            .map(|(key, _)| key.clone())
+            .collect();
+        for key in &expired {
+            hm.entries.remove(key);
+            hm.reindex(key);
+            hm.notify(Class::Expired, "expired", key);
+        }
+        hm.entries.keys().cloned().collect()
+    }
+}
+
+impl Deref for StateGuard<'_> {
+    type Target = State;
+
+    fn deref(&self) -> &State {
+        &self.0
+    }
+}
+
+impl DerefMut for StateGuard<'_> {
+    fn deref_mut(&mut self) -> &mut State {
+        &mut self.0
+    }
+}
+
+impl Drop for StateGuard<'_> {
+    fn drop(&mut self) {
+        self.0.serve_blocked();
+        self.0.reading = false;
+        self.0.client = None;
+        if self.0.has_notifications() || self.0.has_invalidations() {
+            let mut registry = self.1.pubsub();
+            self.0.publish_notifications(&mut registry);
+            self.0.send_invalidations(&mut registry);
+        }
+    }
+}
+
+impl Shared {
+    fn pubsub(&self) -> MutexGuard<'_, pubsub::Registry> {
+        match self.pubsub.lock() {
             Ok(guard) => guard,
             Err(poisoned) => poisoned.into_inner(),
-        };
-        hm.entries.retain(|_, entry| {
-            entry
-                .expires_at
-                .is_none_or(|expiry| Instant::now() <= expiry)
-        });
-        hm.entries.keys().cloned().collect()
+        }
+    }
+}
+
+impl State {
+    // Look up a key, lazily removing it if it has expired
+    fn live(&mut self, key: &Bytes) -> Option<&mut Entry> {
+        let expired = self
+            .entries
+            .get(key)
+            .map(|e| e.expires_at.is_some_and(|exp| Instant::now() > exp))
+            .unwrap_or(false);
+
+        if expired {
+            self.entries.remove(key);
+            self.reindex(key);
+            self.notify(Class::Expired, "expired", key);
+        }
+        if self.reading {
+            if !self.entries.contains_key(key) {
+                self.notify(Class::KeyMiss, "keymiss", key);
+            }
+            self.track_read(key);
+        }
+
+        self.entries.get_mut(key)
+    }
+
+    // Store a value under the key, replacing any old one
+    fn insert(&mut self, key: Bytes, entry: Entry) -> Option<Entry> {
+        if !self.entries.contains_key(&key) {
+            self.notify(Class::New, "new", &key);
+        }
+        self.entries.insert(key, entry)
+    }
+
+    // Collections are deleted once their last element is removed
+    fn remove_if_empty(&mut self, key: &Bytes) {
+        if self.entries.get(key).is_some_and(|e| e.value.is_empty()) {
+            self.entries.remove(key);
+            self.notify(Class::Generic, "del", key);
+        }
+    }
+}
+
+impl Value {
+    fn is_empty(&self) -> bool {
+        match self {
+            Value::String(_) => false,
+            Value::Hash(hash) => hash.is_empty(),
+            Value::Set(set) => set.is_empty(),
+            Value::SortedSet(zset) => zset.len() == 0,
+            Value::VectorSet(set) => set.len() == 0,
+            // Like Redis, a stream outlives its last entry
+            Value::Stream(_) => false,
+            Value::Bloom(_)
+            | Value::Cuckoo(_)
+            | Value::Cms(_)
+            | Value::TopK(_)
+            | Value::TDigest(_)
+            | Value::Json(_)
+            | Value::TimeSeries(_) => false,
+        }
     }
 }
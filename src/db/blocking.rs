@@ -0,0 +1,216 @@
+// Clients blocked in BZPOPMIN, BZPOPMAX and BZMPOP. Each key keeps a FIFO queue of waiters so
+// the client that blocked first is served first, the way Redis does it. XREAD BLOCK doesn't
+// consume anything, so its readers are simply all woken when a stream gets a new entry.
+use super::zset::pop_event;
+use super::{Class, Db, DbError, State};
+use bytes::Bytes;
+use std::collections::{HashMap, VecDeque};
+use std::time::Duration;
+use tokio::sync::{mpsc, oneshot};
+
+// The key that was popped from and the elements taken from it
+pub type Popped = (Bytes, Vec<(Bytes, f64)>);
+
+#[derive(Default)]
+pub(super) struct Blocked {
+    next_id: u64,
+    queues: HashMap<Bytes, VecDeque<u64>>,
+    clients: HashMap<u64, BlockedPop>,
+    // Keys written to since the lock was taken that have clients waiting on them
+    ready: VecDeque<Bytes>,
+    readers: HashMap<Bytes, Vec<mpsc::Sender<()>>>,
+}
+
+struct BlockedPop {
+    keys: Vec<Bytes>,
+    max: bool,
+    count: usize,
+    tx: oneshot::Sender<Popped>,
+}
+
+impl Blocked {
+    fn register(&mut self, client: BlockedPop) -> u64 {
+        let id = self.next_id;
+        self.next_id += 1;
+        for key in &client.keys {
+            self.queues.entry(key.clone()).or_default().push_back(id);
+        }
+        self.clients.insert(id, client);
+        id
+    }
+
+    fn unregister(&mut self, id: u64) -> Option<BlockedPop> {
+        let client = self.clients.remove(&id)?;
+        for key in &client.keys {
+            if let Some(queue) = self.queues.get_mut(key) {
+                queue.retain(|&waiting| waiting != id);
+                if queue.is_empty() {
+                    self.queues.remove(key);
+                }
+            }
+        }
+        Some(client)
+    }
+}
+
+impl State {
+    // Wake every XREAD BLOCK client waiting on a stream
+    pub(super) fn wake_readers(&mut self, key: &Bytes) {
+        for tx in self.blocked.readers.remove(key).unwrap_or_default() {
+            let _ = tx.try_send(());
+        }
+    }
+
+    // Signals `tx` when any of the keys gets a new stream entry
+    pub(super) fn wait_for_entries(&mut self, keys: &[Bytes], tx: &mpsc::Sender<()>) {
+        for key in keys {
+            let readers = self.blocked.readers.entry(key.clone()).or_default();
+            // Still there from an earlier wait if another key did the waking
+            if !readers.iter().any(|reader| reader.same_channel(tx)) {
+                readers.push(tx.clone());
+            }
+        }
+    }
+
+    // Called whenever a sorted set may have gained elements
+    pub(super) fn signal_ready(&mut self, key: &Bytes) {
+        if self.blocked.queues.contains_key(key) && !self.blocked.ready.contains(key) {
+            self.blocked.ready.push_back(key.clone());
+        }
+    }
+
+    // Hand elements of every ready key to the clients waiting on it, keys in the order they
+    // became ready and clients oldest first
+    pub(super) fn serve_blocked(&mut self) {
+        while let Some(key) = self.blocked.ready.pop_front() {
+            while let Some(&id) = self.blocked.queues.get(&key).and_then(|q| q.front()) {
+                if !matches!(self.zset_mut(&key), Ok(Some(zset)) if zset.len() > 0) {
+                    break;
+                }
+                let Some(client) = self.blocked.unregister(id) else {
+                    break;
+                };
+                let Ok(Some(zset)) = self.zset_mut(&key) else {
+                    break;
+                };
+
+                let popped = zset.pop(client.count, client.max);
+                // The client went away before it could be served, so put the elements back
+                match client.tx.send((key.clone(), popped)) {
+                    Ok(()) => self.notify(Class::ZSet, pop_event(client.max), &key),
+                    Err((_, popped)) => {
+                        for (member, score) in popped {
+                            zset.insert(member, score);
+                        }
+                    }
+                }
+                self.remove_if_empty(&key);
+            }
+        }
+    }
+
+    // Pop from the first key holding a non-empty sorted set
+    fn zmpop(
+        &mut self,
+        keys: &[Bytes],
+        max: bool,
+        count: usize,
+    ) -> Result<Option<Popped>, DbError> {
+        for key in keys {
+            if let Some(zset) = self.zset_mut(key)? {
+                let popped = zset.pop(count, max);
+                if !popped.is_empty() {
+                    self.notify(Class::ZSet, pop_event(max), key);
+                }
+                self.remove_if_empty(key);
+                return Ok(Some((key.clone(), popped)));
+            }
+        }
+        Ok(None)
+    }
+}
+
+// Removes a blocked client from the queues if it stops waiting before being served
+struct Unblock<'a> {
+    db: &'a Db,
+    id: u64,
+}
+
+impl Drop for Unblock<'_> {
+    fn drop(&mut self) {
+        self.db.lock().blocked.unregister(self.id);
+    }
+}
+
+// Takes an XREAD BLOCK client off every key it waited on, however the wait ends
+pub(super) struct StopReading<'a> {
+    pub(super) db: &'a Db,
+    pub(super) keys: Vec<Bytes>,
+    pub(super) tx: mpsc::Sender<()>,
+}
+
+impl Drop for StopReading<'_> {
+    fn drop(&mut self) {
+        let mut state = self.db.lock();
+        for key in &self.keys {
+            if let Some(readers) = state.blocked.readers.get_mut(key) {
+                readers.retain(|reader| !reader.same_channel(&self.tx));
+                if readers.is_empty() {
+                    state.blocked.readers.remove(key);
+                }
+            }
+        }
+    }
+}
+
+impl Db {
+    // ZMPOP, and the non-blocking form of the blocking pops
+    pub fn zmpop(
+        &self,
+        keys: &[Bytes],
+        max: bool,
+        count: usize,
+    ) -> Result<Option<Popped>, DbError> {
+        self.lock().zmpop(keys, max, count)
+    }
+
+    // Pop from the first non-empty key, otherwise wait until one of them is written to.
+    // A timeout of None waits forever.
+    pub async fn bzmpop(
+        &self,
+        keys: &[Bytes],
+        max: bool,
+        count: usize,
+        timeout: Option<Duration>,
+    ) -> Result<Option<Popped>, DbError> {
+        let (id, mut rx) = {
+            let _gate = self.enter().await?;
+            let mut state = self.lock();
+            if let Some(popped) = state.zmpop(keys, max, count)? {
+                return Ok(Some(popped));
+            }
+
+            let (tx, rx) = oneshot::channel();
+            let id = state.blocked.register(BlockedPop {
+                keys: keys.to_vec(),
+                max,
+                count,
+                tx,
+            });
+            (id, rx)
+        };
+
+        let unblock = Unblock { db: self, id };
+        let served = match timeout {
+            Some(timeout) => tokio::time::timeout(timeout, &mut rx).await.ok(),
+            None => Some((&mut rx).await),
+        };
+        drop(unblock);
+
+        match served {
+            Some(Ok(popped)) => Ok(Some(popped)),
+            // We may have been served between timing out and leaving the queues
+            _ => Ok(rx.try_recv().ok()),
+        }
+    }
+}
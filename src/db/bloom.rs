@@ -0,0 +1,226 @@
+// Scalable Bloom filters, following RedisBloom. Once a filter holds as many items as it was
+// sized for, a new sub-filter is added that is `expansion` times bigger with half the error
+// rate, so the overall error rate stays under the one asked for.
+use super::hyperloglog::murmur_hash64a;
+use super::{Class, Db, DbError, Entry, State, Value};
+use bytes::Bytes;
+use std::f64::consts::LN_2;
+
+// What BF.ADD creates when the key doesn't exist
+const DEFAULT_ERROR_RATE: f64 = 0.01;
+const DEFAULT_CAPACITY: u64 = 100;
+const DEFAULT_EXPANSION: u64 = 2;
+const ERROR_TIGHTENING_RATIO: f64 = 0.5;
+const HASH_SEED: u64 = 0xc6a4a7935bd1e995;
+// The most bytes one sub-filter, Bloom or Cuckoo, may take
+pub(super) const MAX_FILTER_SIZE: usize = 1 << 30;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BloomInfo {
+    pub capacity: u64,
+    // Bytes used by the bit arrays
+    pub size: usize,
+    pub filters: usize,
+    pub items: u64,
+    // None for a non-scaling filter
+    pub expansion: Option<u64>,
+}
+
+// The single field BF.INFO can be asked for
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BloomInfoField {
+    Capacity,
+    Size,
+    Filters,
+    Items,
+    Expansion,
+}
+
+pub(super) struct BloomFilter {
+    links: Vec<Link>,
+    // Zero when the filter doesn't scale
+    expansion: u64,
+}
+
+// One fixed size sub-filter
+struct Link {
+    bits: Vec<u8>,
+    hashes: u32,
+    capacity: u64,
+    error_rate: f64,
+    items: u64,
+}
+
+impl Link {
+    fn new(capacity: u64, error_rate: f64) -> Result<Link, DbError> {
+        let bits_per_entry = -error_rate.ln() / (LN_2 * LN_2);
+        let bytes = (capacity as f64 * bits_per_entry / 8.0).ceil();
+        if bytes > MAX_FILTER_SIZE as f64 {
+            return Err(DbError::FilterTooBig);
+        }
+        Ok(Link {
+            bits: vec![0; (bytes as usize).max(1)],
+            hashes: (LN_2 * bits_per_entry).ceil() as u32,
+            capacity,
+            error_rate,
+            items: 0,
+        })
+    }
+
+    // Double hashing: bit i is a + i * b
+    fn positions(&self, hash: (u64, u64)) -> impl Iterator<Item = usize> + '_ {
+        let bits = self.bits.len() as u64 * 8;
+        (0..u64::from(self.hashes))
+            .map(move |i| (hash.0.wrapping_add(i.wrapping_mul(hash.1)) % bits) as usize)
+    }
+
+    fn contains(&self, hash: (u64, u64)) -> bool {
+        self.positions(hash)
+            .all(|bit| self.bits[bit / 8] & (1 << (bit % 8)) != 0)
+    }
+
+    fn insert(&mut self, hash: (u64, u64)) {
+        let positions: Vec<usize> = self.positions(hash).collect();
+        for bit in positions {
+            self.bits[bit / 8] |= 1 << (bit % 8);
+        }
+        self.items += 1;
+    }
+}
+
+fn hash(item: &[u8]) -> (u64, u64) {
+    let a = murmur_hash64a(item, HASH_SEED);
+    (a, murmur_hash64a(item, a))
+}
+
+impl BloomFilter {
+    fn new(error_rate: f64, capacity: u64, expansion: u64) -> Result<BloomFilter, DbError> {
+        Ok(BloomFilter {
+            links: vec![Link::new(capacity, error_rate)?],
+            expansion,
+        })
+    }
+
+    fn contains(&self, item: &[u8]) -> bool {
+        let hash = hash(item);
+        self.links.iter().any(|link| link.contains(hash))
+    }
+
+    // Returns false if the item may already have been added
+    fn add(&mut self, item: &[u8]) -> Result<bool, DbError> {
+        let hash = hash(item);
+        if self.links.iter().any(|link| link.contains(hash)) {
+            return Ok(false);
+        }
+
+        let Some(last) = self.links.last() else {
+            return Err(DbError::FilterFull);
+        };
+        if last.items >= last.capacity {
+            if self.expansion == 0 {
+                return Err(DbError::FilterFull);
+            }
+            let capacity = last
+                .capacity
+                .checked_mul(self.expansion)
+                .ok_or(DbError::FilterTooBig)?;
+            let link = Link::new(capacity, last.error_rate * ERROR_TIGHTENING_RATIO)?;
+            self.links.push(link);
+        }
+        if let Some(last) = self.links.last_mut() {
+            last.insert(hash);
+        }
+        Ok(true)
+    }
+
+    fn info(&self) -> BloomInfo {
+        BloomInfo {
+            capacity: self.links.iter().map(|l| l.capacity).sum(),
+            size: self.links.iter().map(|l| l.bits.len()).sum(),
+            filters: self.links.len(),
+            items: self.links.iter().map(|l| l.items).sum(),
+            expansion: (self.expansion > 0).then_some(self.expansion),
+        }
+    }
+}
+
+impl Db {
+    // An expansion of None makes a filter that refuses items once full
+    pub fn bf_reserve(
+        &self,
+        key: &Bytes,
+        error_rate: f64,
+        capacity: u64,
+        expansion: Option<u64>,
+    ) -> Result<(), DbError> {
+        let mut state = self.lock();
+        if state.live(key).is_some() {
+            return Err(DbError::ItemExists);
+        }
+        let filter = BloomFilter::new(error_rate, capacity, expansion.unwrap_or(0))?;
+        state.insert(
+            key.clone(),
+            Entry {
+                value: Value::Bloom(filter),
+                expires_at: None,
+            },
+        );
+        state.notify(Class::Generic, "bf.reserve", key);
+        Ok(())
+    }
+
+    // Each item's result is whether it was newly added, or an error if the filter is full
+    pub fn bf_add(
+        &self,
+        key: &Bytes,
+        items: &[Bytes],
+    ) -> Result<Vec<Result<bool, DbError>>, DbError> {
+        let mut state = self.lock();
+        let filter = state.bloom_or_insert(key)?;
+        let added: Vec<_> = items.iter().map(|item| filter.add(item)).collect();
+        if added.iter().any(|added| matches!(added, Ok(true))) {
+            state.notify(Class::Generic, "bf.add", key);
+        }
+        Ok(added)
+    }
+
+    pub fn bf_exists(&self, key: &Bytes, items: &[Bytes]) -> Result<Vec<bool>, DbError> {
+        let mut state = self.lock_read();
+        Ok(match state.bloom_mut(key)? {
+            Some(filter) => items.iter().map(|item| filter.contains(item)).collect(),
+            None => vec![false; items.len()],
+        })
+    }
+
+    pub fn bf_info(&self, key: &Bytes) -> Result<BloomInfo, DbError> {
+        let mut state = self.lock_read();
+        Ok(state.bloom_mut(key)?.ok_or(DbError::NotFound)?.info())
+    }
+}
+
+impl State {
+    fn bloom_mut(&mut self, key: &Bytes) -> Result<Option<&mut BloomFilter>, DbError> {
+        match self.live(key) {
+            Some(Entry {
+                value: Value::Bloom(filter),
+                ..
+            }) => Ok(Some(filter)),
+            Some(_) => Err(DbError::WrongType),
+            None => Ok(None),
+        }
+    }
+
+    fn bloom_or_insert(&mut self, key: &Bytes) -> Result<&mut BloomFilter, DbError> {
+        if self.live(key).is_none() {
+            let filter = BloomFilter::new(DEFAULT_ERROR_RATE, DEFAULT_CAPACITY, DEFAULT_EXPANSION)?;
+            self.insert(
+                key.clone(),
+                Entry {
+                    value: Value::Bloom(filter),
+                    expires_at: None,
+                },
+            );
+        }
+        self.bloom_mut(key)?.ok_or(DbError::WrongType)
+    }
+}
@@ -0,0 +1,152 @@
+// Count-Min Sketches, following RedisBloom. Each of `depth` rows has `width` counters and an
+// item bumps one counter per row; its count is the smallest of those, which can overestimate
+// but never underestimates.
+use super::hyperloglog::murmur_hash64a;
+use super::{Class, Db, DbError, Entry, State, Value};
+use bytes::Bytes;
+
+// The most counters a sketch may have, a GiB of them
+pub const MAX_CMS_COUNTERS: usize = 1 << 27;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CmsInfo {
+    pub width: usize,
+    pub depth: usize,
+    // Total of all increments
+    pub count: u64,
+}
+
+pub(super) struct CountMinSketch {
+    width: usize,
+    depth: usize,
+    count: u64,
+    // Row major, depth * width
+    counters: Vec<u64>,
+}
+
+impl CountMinSketch {
+    fn new(width: usize, depth: usize) -> Result<CountMinSketch, DbError> {
+        let counters = width
+            .checked_mul(depth)
+            .filter(|&n| n <= MAX_CMS_COUNTERS)
+            .ok_or(DbError::CmsTooBig)?;
+        Ok(CountMinSketch {
+            width,
+            depth,
+            count: 0,
+            counters: vec![0; counters],
+        })
+    }
+
+    // The counter for the item in each row, seeding the hash with the row number
+    fn slots(&self, item: &[u8]) -> Vec<usize> {
+        (0..self.depth)
+            .map(|row| {
+                row * self.width + (murmur_hash64a(item, row as u64) % self.width as u64) as usize
+            })
+            .collect()
+    }
+
+    fn query(&self, item: &[u8]) -> u64 {
+        self.slots(item)
+            .into_iter()
+            .map(|slot| self.counters[slot])
+            .min()
+            .unwrap_or(0)
+    }
+
+    fn incr(&mut self, item: &[u8], by: u64) -> u64 {
+        for slot in self.slots(item) {
+            self.counters[slot] = self.counters[slot].saturating_add(by);
+        }
+        self.count = self.count.saturating_add(by);
+        self.query(item)
+    }
+}
+
+impl Db {
+    pub fn cms_init(&self, key: &Bytes, width: usize, depth: usize) -> Result<(), DbError> {
+        let mut state = self.lock();
+        if state.live(key).is_some() {
+            return Err(DbError::CmsExists);
+        }
+        state.insert(
+            key.clone(),
+            Entry {
+                value: Value::Cms(CountMinSketch::new(width, depth)?),
+                expires_at: None,
+            },
+        );
+        state.notify(Class::Generic, "cms.init", key);
+        Ok(())
+    }
+
+    // Returns each item's estimated count after the increment
+    pub fn cms_incrby(&self, key: &Bytes, items: &[(Bytes, u64)]) -> Result<Vec<u64>, DbError> {
+        let mut state = self.lock();
+        let cms = state.cms_mut(key)?.ok_or(DbError::CmsMissing)?;
+        let counts = items.iter().map(|(item, by)| cms.incr(item, *by)).collect();
+        state.notify(Class::Generic, "cms.incrby", key);
+        Ok(counts)
+    }
+
+    pub fn cms_query(&self, key: &Bytes, items: &[Bytes]) -> Result<Vec<u64>, DbError> {
+        let mut state = self.lock_read();
+        let cms = state.cms_mut(key)?.ok_or(DbError::CmsMissing)?;
+        Ok(items.iter().map(|item| cms.query(item)).collect())
+    }
+
+    // Overwrites the destination, which must already exist, with the weighted sum of the
+    // sources. Negative weights can subtract, but counters don't go below zero.
+    pub fn cms_merge(&self, destination: &Bytes, sources: &[(Bytes, i64)]) -> Result<(), DbError> {
+        let mut state = self.lock();
+        let (width, depth) = {
+            let dest = state.cms_mut(destination)?.ok_or(DbError::CmsMissing)?;
+            (dest.width, dest.depth)
+        };
+
+        let mut counters = vec![0i128; width * depth];
+        let mut count = 0i128;
+        for (key, weight) in sources {
+            let source = state.cms_mut(key)?.ok_or(DbError::CmsMissing)?;
+            if source.width != width || source.depth != depth {
+                return Err(DbError::CmsMismatch);
+            }
+            let weight = i128::from(*weight);
+            for (sum, &counter) in counters.iter_mut().zip(&source.counters) {
+                *sum += i128::from(counter) * weight;
+            }
+            count += i128::from(source.count) * weight;
+        }
+
+        let clamp = |n: i128| n.clamp(0, i128::from(u64::MAX)) as u64;
+        let dest = state.cms_mut(destination)?.ok_or(DbError::CmsMissing)?;
+        dest.counters = counters.into_iter().map(clamp).collect();
+        dest.count = clamp(count);
+        state.notify(Class::Generic, "cms.merge", destination);
+        Ok(())
+    }
+
+    pub fn cms_info(&self, key: &Bytes) -> Result<CmsInfo, DbError> {
+        let mut state = self.lock_read();
+        let cms = state.cms_mut(key)?.ok_or(DbError::CmsMissing)?;
+        Ok(CmsInfo {
+            width: cms.width,
+            depth: cms.depth,
+            count: cms.count,
+        })
+    }
+}
+
+impl State {
+    fn cms_mut(&mut self, key: &Bytes) -> Result<Option<&mut CountMinSketch>, DbError> {
+        match self.live(key) {
+            Some(Entry {
+                value: Value::Cms(cms),
+                ..
+            }) => Ok(Some(cms)),
+            Some(_) => Err(DbError::WrongType),
+            None => Ok(None),
+        }
+    }
+}
@@ -0,0 +1,116 @@
+// Runtime configuration for CONFIG GET and CONFIG SET. Parameter names are case-insensitive
+// and reported in lowercase.
+use super::glob::Glob;
+use super::{Db, DbError, MAX_WASM_FUEL, NotifyEvents};
+use bytes::Bytes;
+use std::time::Duration;
+
+const NOTIFY_KEYSPACE_EVENTS: &str = "notify-keyspace-events";
+const BUSY_REPLY_THRESHOLD: &str = "busy-reply-threshold";
+const WASM_FUEL_LIMIT: &str = "wasm-fuel-limit";
+const WASM_TIME_LIMIT: &str = "wasm-time-limit";
+const WASM_MEMORY_LIMIT: &str = "wasm-memory-limit";
+
+const PARAMETERS: [&str; 5] = [
+    NOTIFY_KEYSPACE_EVENTS,
+    BUSY_REPLY_THRESHOLD,
+    WASM_FUEL_LIMIT,
+    WASM_TIME_LIMIT,
+    WASM_MEMORY_LIMIT,
+];
+
+impl Db {
+    // Every parameter matching one of the patterns, once each
+    pub fn config_get(&self, patterns: &[Bytes]) -> Vec<(Bytes, Bytes)> {
+        let globs: Vec<Glob> = patterns
+            .iter()
+            .map(|p| Glob::new(&p.to_ascii_lowercase()))
+            .collect();
+        PARAMETERS
+            .iter()
+            .filter(|name| globs.iter().any(|g| g.matches(name.as_bytes())))
+            .map(|name| (Bytes::from_static(name.as_bytes()), self.config_value(name)))
+            .collect()
+    }
+
+    // Checks every pair before applying any, so a bad one leaves the config unchanged
+    pub fn config_set(&self, pairs: &[(Bytes, Bytes)]) -> Result<(), DbError> {
+        let mut events = None;
+        let mut busy_threshold = None;
+        let mut limits = self.wasm_limits();
+        for (name, value) in pairs {
+            match name.to_ascii_lowercase().as_slice() {
+                b"notify-keyspace-events" => {
+                    events = Some(NotifyEvents::parse(value).ok_or(
+                        DbError::InvalidConfigValue(
+                            NOTIFY_KEYSPACE_EVENTS,
+                            "Invalid event class character. Use 'Ag$lshzxeKEtmn'.",
+                        ),
+                    )?);
+                }
+                // In milliseconds
+                b"busy-reply-threshold" => {
+                    let millis = integer(BUSY_REPLY_THRESHOLD, value)?;
+                    busy_threshold = Some(Duration::from_millis(millis));
+                }
+                b"wasm-fuel-limit" => {
+                    limits.fuel = integer(WASM_FUEL_LIMIT, value)?;
+                    if limits.fuel > MAX_WASM_FUEL {
+                        return Err(DbError::InvalidConfigValue(
+                            WASM_FUEL_LIMIT,
+                            "argument must be between 0 and 1000000000 inclusive",
+                        ));
+                    }
+                }
+                // In milliseconds
+                b"wasm-time-limit" => {
+                    limits.time = Duration::from_millis(integer(WASM_TIME_LIMIT, value)?);
+                }
+                // In bytes
+                b"wasm-memory-limit" => {
+                    limits.memory = integer(WASM_MEMORY_LIMIT, value)? as usize;
+                }
+                _ => {
+                    return Err(DbError::UnknownConfig(
+                        String::from_utf8_lossy(name).into_owned(),
+                    ));
+                }
+            }
+        }
+        if let Some(events) = events {
+            self.lock().notifications.events = events;
+        }
+        if let Some(busy_threshold) = busy_threshold {
+            self.scripts().busy_threshold = busy_threshold;
+        }
+        self.modules().limits = limits;
+        Ok(())
+    }
+
+    fn config_value(&self, name: &str) -> Bytes {
+        match name {
+            NOTIFY_KEYSPACE_EVENTS => self.lock().notifications.events.to_string().into(),
+            BUSY_REPLY_THRESHOLD => {
+                let millis = self.scripts().busy_threshold.as_millis();
+                millis.to_string().into()
+            }
+            WASM_FUEL_LIMIT => self.wasm_limits().fuel.to_string().into(),
+            WASM_TIME_LIMIT => {
+                let millis = self.wasm_limits().time.as_millis();
+                millis.to_string().into()
+            }
+            WASM_MEMORY_LIMIT => self.wasm_limits().memory.to_string().into(),
+            _ => unreachable!("unlisted parameter"),
+        }
+    }
+}
+
+fn integer(name: &'static str, value: &[u8]) -> Result<u64, DbError> {
+    std::str::from_utf8(value)
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .ok_or(DbError::InvalidConfigValue(
+            name,
+            "argument couldn't be parsed into an integer",
+        ))
+}
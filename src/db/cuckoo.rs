@@ -0,0 +1,308 @@
+// Cuckoo filters, following RedisBloom. Each item is reduced to an 8-bit fingerprint that lives
+// in one of two buckets, so unlike a Bloom filter items can be counted and deleted. When a
+// filter can't make room for an item a new one `expansion` times bigger is added.
+use super::bloom::MAX_FILTER_SIZE;
+use super::hyperloglog::murmur_hash64a;
+use super::{Class, Db, DbError, Entry, State, Value};
+use bytes::Bytes;
+
+// What CF.ADD creates when the key doesn't exist
+const DEFAULT_CAPACITY: u64 = 1024;
+const DEFAULT_BUCKET_SIZE: u8 = 2;
+const DEFAULT_MAX_ITERATIONS: u16 = 20;
+const DEFAULT_EXPANSION: u16 = 1;
+// Fingerprint 0 marks an empty slot
+const EMPTY: u8 = 0;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CuckooOptions {
+    pub capacity: u64,
+    pub bucket_size: u8,
+    pub max_iterations: u16,
+    // Zero makes a filter that refuses items once full
+    pub expansion: u16,
+}
+
+impl Default for CuckooOptions {
+    fn default() -> Self {
+        CuckooOptions {
+            capacity: DEFAULT_CAPACITY,
+            bucket_size: DEFAULT_BUCKET_SIZE,
+            max_iterations: DEFAULT_MAX_ITERATIONS,
+            expansion: DEFAULT_EXPANSION,
+        }
+    }
+}
+
+pub(super) struct CuckooFilter {
+    filters: Vec<SubFilter>,
+    options: CuckooOptions,
+    // Rotates which slot gets kicked out, so inserts don't keep evicting the same fingerprint
+    victim: usize,
+}
+
+struct SubFilter {
+    // num_buckets * bucket_size fingerprints
+    slots: Vec<u8>,
+    bucket_size: usize,
+    // Always a power of two, so the alternate bucket can be found from either bucket
+    num_buckets: u64,
+}
+
+// Where an item may live: its fingerprint and 64-bit hash
+#[derive(Clone, Copy)]
+struct Lookup {
+    fp: u8,
+    hash: u64,
+}
+
+impl Lookup {
+    fn new(item: &[u8]) -> Lookup {
+        let hash = murmur_hash64a(item, 0);
+        Lookup {
+            fp: (hash % 255 + 1) as u8,
+            hash,
+        }
+    }
+}
+
+impl SubFilter {
+    fn new(capacity: u64, bucket_size: u8) -> Result<SubFilter, DbError> {
+        let bucket_size = usize::from(bucket_size);
+        let num_buckets = capacity
+            .div_ceil(bucket_size as u64)
+            .checked_next_power_of_two()
+            .ok_or(DbError::FilterTooBig)?;
+        let slots = usize::try_from(num_buckets)
+            .ok()
+            .and_then(|n| n.checked_mul(bucket_size))
+            .filter(|&slots| slots <= MAX_FILTER_SIZE)
+            .ok_or(DbError::FilterTooBig)?;
+        Ok(SubFilter {
+            slots: vec![EMPTY; slots],
+            bucket_size,
+            num_buckets,
+        })
+    }
+
+    fn primary(&self, lookup: Lookup) -> usize {
+        (lookup.hash & (self.num_buckets - 1)) as usize
+    }
+
+    fn alternate(&self, bucket: usize, fp: u8) -> usize {
+        ((bucket as u64 ^ u64::from(fp).wrapping_mul(0x5bd1e995)) & (self.num_buckets - 1)) as usize
+    }
+
+    fn bucket(&self, bucket: usize) -> &[u8] {
+        &self.slots[bucket * self.bucket_size..(bucket + 1) * self.bucket_size]
+    }
+
+    fn bucket_mut(&mut self, bucket: usize) -> &mut [u8] {
+        &mut self.slots[bucket * self.bucket_size..(bucket + 1) * self.bucket_size]
+    }
+
+    fn buckets(&self, lookup: Lookup) -> [usize; 2] {
+        let first = self.primary(lookup);
+        [first, self.alternate(first, lookup.fp)]
+    }
+
+    fn count(&self, lookup: Lookup) -> usize {
+        let [first, second] = self.buckets(lookup);
+        let matches = |b| self.bucket(b).iter().filter(|&&fp| fp == lookup.fp).count();
+        // Both hashes can land on the same bucket
+        if first == second {
+            matches(first)
+        } else {
+            matches(first) + matches(second)
+        }
+    }
+
+    fn try_insert(&mut self, bucket: usize, fp: u8) -> bool {
+        match self
+            .bucket_mut(bucket)
+            .iter_mut()
+            .find(|slot| **slot == EMPTY)
+        {
+            Some(slot) => {
+                *slot = fp;
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn remove(&mut self, lookup: Lookup) -> bool {
+        for bucket in self.buckets(lookup) {
+            if let Some(slot) = self
+                .bucket_mut(bucket)
+                .iter_mut()
+                .find(|s| **s == lookup.fp)
+            {
+                *slot = EMPTY;
+                return true;
+            }
+        }
+        false
+    }
+
+    // Make room by moving fingerprints to their alternate buckets. The moves are undone if
+    // no room turns up within max_iterations, so a failed insert leaves the filter untouched.
+    fn kick_insert(&mut self, lookup: Lookup, max_iterations: u16, victim: &mut usize) -> bool {
+        let mut bucket = self.buckets(lookup)[*victim % 2];
+        let mut fp = lookup.fp;
+        let mut moves = Vec::new();
+
+        for _ in 0..max_iterations {
+            let slot = bucket * self.bucket_size + *victim % self.bucket_size;
+            *victim = victim.wrapping_add(1);
+            std::mem::swap(&mut fp, &mut self.slots[slot]);
+            moves.push(slot);
+
+            bucket = self.alternate(bucket, fp);
+            if self.try_insert(bucket, fp) {
+                return true;
+            }
+        }
+
+        for slot in moves.into_iter().rev() {
+            std::mem::swap(&mut fp, &mut self.slots[slot]);
+        }
+        false
+    }
+}
+
+impl CuckooFilter {
+    fn new(options: CuckooOptions) -> Result<CuckooFilter, DbError> {
+        Ok(CuckooFilter {
+            filters: vec![SubFilter::new(options.capacity, options.bucket_size)?],
+            options,
+            victim: 0,
+        })
+    }
+
+    fn count(&self, item: &[u8]) -> usize {
+        let lookup = Lookup::new(item);
+        self.filters.iter().map(|f| f.count(lookup)).sum()
+    }
+
+    fn add(&mut self, item: &[u8]) -> Result<(), DbError> {
+        let lookup = Lookup::new(item);
+
+        // Newest filters are the least full, so try them first
+        for filter in self.filters.iter_mut().rev() {
+            let [first, second] = filter.buckets(lookup);
+            if filter.try_insert(first, lookup.fp) || filter.try_insert(second, lookup.fp) {
+                return Ok(());
+            }
+        }
+
+        let max_iterations = self.options.max_iterations;
+        if let Some(last) = self.filters.last_mut()
+            && last.kick_insert(lookup, max_iterations, &mut self.victim)
+        {
+            return Ok(());
+        }
+
+        if self.options.expansion == 0 {
+            return Err(DbError::CuckooFull);
+        }
+        let capacity = u32::try_from(self.filters.len())
+            .ok()
+            .and_then(|n| u64::from(self.options.expansion).checked_pow(n))
+            .and_then(|growth| self.options.capacity.checked_mul(growth))
+            .ok_or(DbError::FilterTooBig)?;
+        let mut filter = SubFilter::new(capacity, self.options.bucket_size)?;
+        filter.try_insert(filter.primary(lookup), lookup.fp);
+        self.filters.push(filter);
+        Ok(())
+    }
+
+    fn remove(&mut self, item: &[u8]) -> bool {
+        let lookup = Lookup::new(item);
+        self.filters.iter_mut().rev().any(|f| f.remove(lookup))
+    }
+}
+
+impl Db {
+    pub fn cf_reserve(&self, key: &Bytes, options: CuckooOptions) -> Result<(), DbError> {
+        let mut state = self.lock();
+        if state.live(key).is_some() {
+            return Err(DbError::ItemExists);
+        }
+        state.insert(
+            key.clone(),
+            Entry {
+                value: Value::Cuckoo(CuckooFilter::new(options)?),
+                expires_at: None,
+            },
+        );
+        state.notify(Class::Generic, "cf.reserve", key);
+        Ok(())
+    }
+
+    // With nx the item is only added if it doesn't seem to be there already. Returns whether it
+    // was added.
+    pub fn cf_add(&self, key: &Bytes, item: &Bytes, nx: bool) -> Result<bool, DbError> {
+        let mut state = self.lock();
+        let filter = state.cuckoo_or_insert(key)?;
+        if nx && filter.count(item) > 0 {
+            return Ok(false);
+        }
+        filter.add(item)?;
+        state.notify(Class::Generic, "cf.add", key);
+        Ok(true)
+    }
+
+    // Removes one copy of the item
+    pub fn cf_del(&self, key: &Bytes, item: &Bytes) -> Result<bool, DbError> {
+        let mut state = self.lock();
+        let filter = state.cuckoo_mut(key)?.ok_or(DbError::NotFound)?;
+        let removed = filter.remove(item);
+        if removed {
+            state.notify(Class::Generic, "cf.del", key);
+        }
+        Ok(removed)
+    }
+
+    pub fn cf_exists(&self, key: &Bytes, items: &[Bytes]) -> Result<Vec<bool>, DbError> {
+        let mut state = self.lock_read();
+        Ok(match state.cuckoo_mut(key)? {
+            Some(filter) => items.iter().map(|item| filter.count(item) > 0).collect(),
+            None => vec![false; items.len()],
+        })
+    }
+
+    // Can overcount when other items share the fingerprint, but never undercounts
+    pub fn cf_count(&self, key: &Bytes, item: &Bytes) -> Result<usize, DbError> {
+        let mut state = self.lock_read();
+        Ok(state
+            .cuckoo_mut(key)?
+            .map_or(0, |filter| filter.count(item)))
+    }
+}
+
+impl State {
+    fn cuckoo_mut(&mut self, key: &Bytes) -> Result<Option<&mut CuckooFilter>, DbError> {
+        match self.live(key) {
+            Some(Entry {
+                value: Value::Cuckoo(filter),
+                ..
+            }) => Ok(Some(filter)),
+            Some(_) => Err(DbError::WrongType),
+            None => Ok(None),
+        }
+    }
+
+    fn cuckoo_or_insert(&mut self, key: &Bytes) -> Result<&mut CuckooFilter, DbError> {
+        if self.live(key).is_none() {
+            self.insert(
+                key.clone(),
+                Entry {
+                    value: Value::Cuckoo(CuckooFilter::new(CuckooOptions::default())?),
+                    expires_at: None,
+                },
+            );
+        }
+        self.cuckoo_mut(key)?.ok_or(DbError::WrongType)
+    }
+}
@@ -0,0 +1,258 @@
+// FT.SEARCH queries, a subset of RediSearch's dialect. Words are full text terms matched
+// against every TEXT field, and `term*` matches any term with that prefix. `@field:` scopes a
+// term, a `(...)` group, `{tag | tag}` alternatives or a `[min max]` numeric range, where `(`
+// before a bound makes it exclusive. Juxtaposition intersects, `|` unions (binding looser) and
+// a leading `-` negates. `*` on its own matches every document.
+use std::ops::Bound;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FtQuery {
+    pub(super) node: Node,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub(super) enum Node {
+    All,
+    // Lowercased, as the index stores them
+    Term {
+        field: Option<String>,
+        term: String,
+        prefix: bool,
+    },
+    Tag {
+        field: String,
+        tags: Vec<(String, bool)>,
+    },
+    Numeric {
+        field: String,
+        min: Bound<f64>,
+        max: Bound<f64>,
+    },
+    And(Vec<Node>),
+    Or(Vec<Node>),
+    Not(Box<Node>),
+}
+
+impl FtQuery {
+    // None if the query doesn't parse
+    pub fn parse(text: &str) -> Option<FtQuery> {
+        let mut parser = Parser {
+            chars: text.chars().collect(),
+            pos: 0,
+        };
+        parser.skip_space();
+        if parser.chars[parser.pos..] == ['*'] {
+            return Some(FtQuery { node: Node::All });
+        }
+        let node = parser.union(None)?;
+        parser.skip_space();
+        (parser.pos == parser.chars.len()).then_some(FtQuery { node })
+    }
+
+    // The positive full text terms, which are what results are scored on
+    pub(super) fn terms(&self) -> Vec<(Option<&str>, &str, bool)> {
+        let mut terms = Vec::new();
+        collect_terms(&self.node, &mut terms);
+        terms
+    }
+}
+
+fn collect_terms<'a>(node: &'a Node, out: &mut Vec<(Option<&'a str>, &'a str, bool)>) {
+    match node {
+        Node::Term {
+            field,
+            term,
+            prefix,
+        } => out.push((field.as_deref(), term, *prefix)),
+        Node::And(nodes) | Node::Or(nodes) => {
+            for node in nodes {
+                collect_terms(node, out);
+            }
+        }
+        Node::All | Node::Tag { .. } | Node::Numeric { .. } | Node::Not(_) => {}
+    }
+}
+
+// How TEXT field values and query words are split into terms
+pub(super) fn is_term_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+}
+
+impl Parser {
+    fn skip_space(&mut self) {
+        while self.peek().is_some_and(char::is_whitespace) {
+            self.pos += 1;
+        }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        self.skip_space();
+        if self.peek() == Some(c) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    // Intersections separated by `|`; `field` scopes the bare words inside
+    fn union(&mut self, field: Option<&str>) -> Option<Node> {
+        let mut nodes = vec![self.intersect(field)?];
+        while self.eat('|') {
+            nodes.push(self.intersect(field)?);
+        }
+        Some(match nodes.len() {
+            1 => nodes.pop()?,
+            _ => Node::Or(nodes),
+        })
+    }
+
+    fn intersect(&mut self, field: Option<&str>) -> Option<Node> {
+        let mut nodes = Vec::new();
+        loop {
+            self.skip_space();
+            match self.peek() {
+                None | Some(')' | '|') => break,
+                _ => nodes.push(self.unary(field)?),
+            }
+        }
+        match nodes.len() {
+            0 => None,
+            1 => nodes.pop(),
+            _ => Some(Node::And(nodes)),
+        }
+    }
+
+    fn unary(&mut self, field: Option<&str>) -> Option<Node> {
+        if self.eat('-') {
+            return Some(Node::Not(Box::new(self.unary(field)?)));
+        }
+        self.atom(field)
+    }
+
+    fn atom(&mut self, field: Option<&str>) -> Option<Node> {
+        self.skip_space();
+        match self.peek()? {
+            '(' => {
+                self.pos += 1;
+                let inner = self.union(field)?;
+                self.eat(')').then_some(inner)
+            }
+            '@' => {
+                self.pos += 1;
+                let name = self.word()?;
+                if !self.eat(':') {
+                    return None;
+                }
+                self.skip_space();
+                match self.peek()? {
+                    '{' => self.tags(name),
+                    '[' => self.range(name),
+                    _ => self.atom(Some(&name)),
+                }
+            }
+            _ => {
+                let term = self.word()?.to_lowercase();
+                let prefix = self.peek() == Some('*');
+                if prefix {
+                    self.pos += 1;
+                }
+                Some(Node::Term {
+                    field: field.map(str::to_string),
+                    term,
+                    prefix,
+                })
+            }
+        }
+    }
+
+    // A run of term characters, where a backslash escapes any character
+    fn word(&mut self) -> Option<String> {
+        let mut out = String::new();
+        while let Some(c) = self.peek() {
+            if c == '\\' {
+                out.push(*self.chars.get(self.pos + 1)?);
+                self.pos += 2;
+            } else if is_term_char(c) {
+                out.push(c);
+                self.pos += 1;
+            } else {
+                break;
+            }
+        }
+        (!out.is_empty()).then_some(out)
+    }
+
+    // `{a | b\ c | d*}`, with whitespace around each tag trimmed
+    fn tags(&mut self, field: String) -> Option<Node> {
+        self.pos += 1;
+        let mut tags = Vec::new();
+        let mut tag = String::new();
+        loop {
+            match self.peek()? {
+                '\\' => {
+                    tag.push(*self.chars.get(self.pos + 1)?);
+                    self.pos += 2;
+                    continue;
+                }
+                c @ ('|' | '}') => {
+                    let trimmed = tag.trim();
+                    let (text, prefix) = match trimmed.strip_suffix('*') {
+                        Some(text) => (text, true),
+                        None => (trimmed, false),
+                    };
+                    if text.is_empty() {
+                        return None;
+                    }
+                    tags.push((text.to_string(), prefix));
+                    tag.clear();
+                    self.pos += 1;
+                    if c == '}' {
+                        return Some(Node::Tag { field, tags });
+                    }
+                }
+                c => {
+                    tag.push(c);
+                    self.pos += 1;
+                }
+            }
+        }
+    }
+
+    // `[min max]`, where either bound may be `(`-prefixed for exclusive or +/-inf
+    fn range(&mut self, field: String) -> Option<Node> {
+        self.pos += 1;
+        let min = self.bound()?;
+        self.eat(',');
+        let max = self.bound()?;
+        self.eat(']').then_some(Node::Numeric { field, min, max })
+    }
+
+    fn bound(&mut self) -> Option<Bound<f64>> {
+        let exclusive = self.eat('(');
+        self.skip_space();
+        let start = self.pos;
+        while self
+            .peek()
+            .is_some_and(|c| !c.is_whitespace() && !matches!(c, ',' | ']'))
+        {
+            self.pos += 1;
+        }
+        let text: String = self.chars[start..self.pos].iter().collect();
+        let value = text.parse::<f64>().ok().filter(|v| !v.is_nan())?;
+        Some(if exclusive {
+            Bound::Excluded(value)
+        } else {
+            Bound::Included(value)
+        })
+    }
+}
@@ -0,0 +1,372 @@
+// The function libraries behind FUNCTION and FCALL. A library is kept as the code it was
+// loaded from along with what its functions registered; running one loads the code into a
+// fresh interpreter again, so only the names and flags live here.
+//
+// FUNCTION DUMP writes the libraries the way Redis does: each library's code after an RDB
+// function opcode, then the RDB version and a CRC64 of everything before it, so payloads move
+// between padis and Redis in either direction. The same payload is what keeps libraries in
+// their file across restarts, rewritten after every change once `function_save_to` names one.
+use super::glob::Glob;
+use super::{Db, DbError};
+use bytes::{BufMut, Bytes, BytesMut};
+use std::collections::{BTreeMap, HashMap};
+use std::io;
+use std::path::PathBuf;
+use std::sync::{MutexGuard, PoisonError};
+
+// RDB_OPCODE_FUNCTION2, which starts each library in a payload
+const FUNCTION_OPCODE: u8 = 245;
+// The RDB version payloads are written with, and the newest one read
+const RDB_VERSION: u16 = 11;
+// CRC-64/Jones, reflected for the bitwise loop
+const CRC64_POLY: u64 = 0xad93d23594c935a9u64.reverse_bits();
+// The most LZF output a byte of input can make: a 3-byte back reference copies 264 bytes
+const LZF_MAX_EXPANSION: usize = 88;
+
+#[derive(Clone, Default)]
+pub(super) struct Functions {
+    libraries: BTreeMap<String, Library>,
+    // Function name -> the library that registered it
+    owners: HashMap<String, String>,
+    // Where the libraries are saved, if anywhere
+    file: Option<PathBuf>,
+}
+
+#[derive(Clone)]
+pub struct Library {
+    pub name: String,
+    pub code: Bytes,
+    pub functions: BTreeMap<String, FunctionInfo>,
+}
+
+#[derive(Clone)]
+pub struct FunctionInfo {
+    pub description: Option<Bytes>,
+    pub flags: Vec<String>,
+}
+
+// What FUNCTION RESTORE does with libraries already loaded
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RestorePolicy {
+    // Fail if any restored library or function already exists
+    Append,
+    // Restored libraries replace those with the same name
+    Replace,
+    // Every loaded library goes first
+    Flush,
+}
+
+impl FunctionInfo {
+    // Functions flagged no-writes can't write and are the only ones FCALL_RO runs
+    pub fn no_writes(&self) -> bool {
+        self.flags.iter().any(|flag| flag == "no-writes")
+    }
+}
+
+impl Functions {
+    // Checks that none of the libraries' functions belong to a library outside them
+    fn check(&self, libraries: &[Library]) -> Result<(), DbError> {
+        for library in libraries {
+            for name in library.functions.keys() {
+                match self.owners.get(name) {
+                    Some(owner) if libraries.iter().all(|l| &l.name != owner) => {
+                        return Err(DbError::FunctionExists(name.clone()));
+                    }
+                    _ => {}
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn insert(&mut self, library: Library) {
+        self.remove(&library.name);
+        for name in library.functions.keys() {
+            self.owners.insert(name.clone(), library.name.clone());
+        }
+        self.libraries.insert(library.name.clone(), library);
+    }
+
+    fn remove(&mut self, name: &str) -> bool {
+        let Some(library) = self.libraries.remove(name) else {
+            return false;
+        };
+        for name in library.functions.keys() {
+            self.owners.remove(name);
+        }
+        true
+    }
+
+    fn dump(&self) -> Bytes {
+        let mut out = BytesMut::new();
+        for library in self.libraries.values() {
+            out.put_u8(FUNCTION_OPCODE);
+            put_length(&mut out, library.code.len());
+            out.put_slice(&library.code);
+        }
+        out.put_u16_le(RDB_VERSION);
+        out.put_u64_le(crc64(&out));
+        out.freeze()
+    }
+
+    // Writes the libraries to their file through a temporary one, so a crash part way through
+    // leaves the last copy whole
+    fn save(&self) -> io::Result<()> {
+        let Some(path) = &self.file else {
+            return Ok(());
+        };
+        let temp = path.with_extension("tmp");
+        std::fs::write(&temp, self.dump())?;
+        std::fs::rename(temp, path)
+    }
+
+    // Like in Redis, a change that fails to save still stands
+    fn saved(&self) {
+        if let Err(e) = self.save() {
+            eprintln!("Failed to save function libraries: {}", e);
+        }
+    }
+}
+
+impl Db {
+    fn functions(&self) -> MutexGuard<'_, Functions> {
+        self.shared
+            .functions
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    // Adds a library, or swaps one of the same name for it with `replace`
+    pub fn function_load(&self, library: Library, replace: bool) -> Result<String, DbError> {
+        let mut functions = self.functions();
+        if !replace && functions.libraries.contains_key(&library.name) {
+            return Err(DbError::LibraryExists(library.name));
+        }
+        functions.check(std::slice::from_ref(&library))?;
+        let name = library.name.clone();
+        functions.insert(library);
+        functions.saved();
+        Ok(name)
+    }
+
+    // The code of the library a function is in, and the function's registration
+    pub fn function_get(&self, name: &[u8]) -> Option<(Bytes, FunctionInfo)> {
+        let functions = self.functions();
+        let name = std::str::from_utf8(name).ok()?;
+        let library = &functions.libraries[functions.owners.get(name)?];
+        Some((library.code.clone(), library.functions[name].clone()))
+    }
+
+    // Libraries in name order, those whose name matches `pattern` if there is one
+    pub fn function_list(&self, pattern: Option<&[u8]>) -> Vec<Library> {
+        let glob = pattern.map(Glob::new);
+        self.functions()
+            .libraries
+            .values()
+            .filter(|library| {
+                glob.as_ref()
+                    .is_none_or(|glob| glob.matches(library.name.as_bytes()))
+            })
+            .cloned()
+            .collect()
+    }
+
+    pub fn function_delete(&self, name: &[u8]) -> Result<(), DbError> {
+        let name = String::from_utf8_lossy(name);
+        let mut functions = self.functions();
+        if !functions.remove(&name) {
+            return Err(DbError::LibraryNotFound);
+        }
+        functions.saved();
+        Ok(())
+    }
+
+    pub fn function_flush(&self) {
+        let mut functions = self.functions();
+        functions.libraries.clear();
+        functions.owners.clear();
+        functions.saved();
+    }
+
+    pub fn function_dump(&self) -> Bytes {
+        self.functions().dump()
+    }
+
+    // Saves the libraries to `path` now and after every change from here on
+    pub fn function_save_to(&self, path: PathBuf) -> io::Result<()> {
+        let mut functions = self.functions();
+        functions.file = Some(path);
+        functions.save()
+    }
+
+    // Adds restored libraries all together, or none of them if one can't be
+    pub fn function_restore(
+        &self,
+        libraries: Vec<Library>,
+        policy: RestorePolicy,
+    ) -> Result<(), DbError> {
+        let mut functions = self.functions();
+        let mut restored = match policy {
+            RestorePolicy::Flush => Functions {
+                file: functions.file.clone(),
+                ..Functions::default()
+            },
+            _ => functions.clone(),
+        };
+        for (i, library) in libraries.iter().enumerate() {
+            if libraries[..i].iter().any(|l| l.name == library.name)
+                || (policy == RestorePolicy::Append
+                    && restored.libraries.contains_key(&library.name))
+            {
+                return Err(DbError::LibraryExists(library.name.clone()));
+            }
+        }
+        restored.check(&libraries)?;
+        for library in libraries {
+            restored.insert(library);
+        }
+        *functions = restored;
+        functions.saved();
+        Ok(())
+    }
+}
+
+// The code of each library in a FUNCTION DUMP payload, after checking its version and checksum
+pub fn dump_codes(payload: &[u8]) -> Result<Vec<Bytes>, DbError> {
+    let Some(body_len) = payload.len().checked_sub(10) else {
+        return Err(DbError::BadPayload);
+    };
+    let (data, checksum) = payload.split_at(payload.len() - 8);
+    let version = u16::from_le_bytes([payload[body_len], payload[body_len + 1]]);
+    if version > RDB_VERSION || crc64(data) != u64::from_le_bytes(checksum.try_into().unwrap()) {
+        return Err(DbError::BadPayload);
+    }
+
+    let mut input = &payload[..body_len];
+    let mut codes = Vec::new();
+    while let Some((&opcode, rest)) = input.split_first() {
+        if opcode != FUNCTION_OPCODE {
+            return Err(DbError::NotFunctionPayload);
+        }
+        input = rest;
+        codes.push(read_string(&mut input).ok_or(DbError::BadPayload)?);
+    }
+    Ok(codes)
+}
+
+// RDB's length encoding: 6 bits, 14 bits, then 32 or 64 bits big endian after a marker
+fn put_length(out: &mut BytesMut, len: usize) {
+    match len {
+        0..0x40 => out.put_u8(len as u8),
+        0x40..0x4000 => out.put_u16(0x4000 | len as u16),
+        _ => match u32::try_from(len) {
+            Ok(len) => {
+                out.put_u8(0x80);
+                out.put_u32(len);
+            }
+            Err(_) => {
+                out.put_u8(0x81);
+                out.put_u64(len as u64);
+            }
+        },
+    }
+}
+
+// A length, or with `Err` the kind of a specially encoded string
+fn read_length(input: &mut &[u8]) -> Option<Result<usize, u8>> {
+    let (&first, rest) = input.split_first()?;
+    *input = rest;
+    let len = match first >> 6 {
+        0 => (first & 0x3f) as usize,
+        1 => ((first & 0x3f) as usize) << 8 | take(input, 1)?[0] as usize,
+        2 => match first {
+            0x80 => u32::from_be_bytes(take(input, 4)?.try_into().ok()?) as usize,
+            0x81 => u64::from_be_bytes(take(input, 8)?.try_into().ok()?) as usize,
+            _ => return None,
+        },
+        _ => return Some(Err(first & 0x3f)),
+    };
+    Some(Ok(len))
+}
+
+// A string as RDB writes it: raw, as an integer, or LZF compressed
+fn read_string(input: &mut &[u8]) -> Option<Bytes> {
+    let bytes = match read_length(input)? {
+        Ok(len) => take(input, len)?.to_vec(),
+        Err(0) => (take(input, 1)?[0] as i8).to_string().into_bytes(),
+        Err(1) => i16::from_le_bytes(take(input, 2)?.try_into().ok()?)
+            .to_string()
+            .into_bytes(),
+        Err(2) => i32::from_le_bytes(take(input, 4)?.try_into().ok()?)
+            .to_string()
+            .into_bytes(),
+        Err(3) => {
+            let compressed = read_length(input)?.ok()?;
+            let len = read_length(input)?.ok()?;
+            lzf_decompress(take(input, compressed)?, len)?
+        }
+        Err(_) => return None,
+    };
+    Some(Bytes::from(bytes))
+}
+
+fn take<'a>(input: &mut &'a [u8], n: usize) -> Option<&'a [u8]> {
+    if input.len() < n {
+        return None;
+    }
+    let (taken, rest) = input.split_at(n);
+    *input = rest;
+    Some(taken)
+}
+
+// LZF as Redis compresses long strings with: literal runs, and back references into the output.
+// The length comes from the payload, so it's checked against what the input could expand to
+// before anything is allocated for it.
+fn lzf_decompress(input: &[u8], len: usize) -> Option<Vec<u8>> {
+    if len > input.len().saturating_mul(LZF_MAX_EXPANSION) {
+        return None;
+    }
+    let mut out = Vec::with_capacity(len);
+    let mut i = 0;
+    while i < input.len() {
+        let ctrl = input[i] as usize;
+        i += 1;
+        if ctrl < 32 {
+            if out.len() + ctrl + 1 > len {
+                return None;
+            }
+            out.extend_from_slice(input.get(i..i + ctrl + 1)?);
+            i += ctrl + 1;
+            continue;
+        }
+        let mut run = ctrl >> 5;
+        if run == 7 {
+            run += *input.get(i)? as usize;
+            i += 1;
+        }
+        let back = ((ctrl & 0x1f) << 8) + *input.get(i)? as usize + 1;
+        i += 1;
+        let start = out.len().checked_sub(back)?;
+        if out.len() + run + 2 > len {
+            return None;
+        }
+        for k in 0..run + 2 {
+            out.push(out[start + k]);
+        }
+    }
+    (out.len() == len).then_some(out)
+}
+
+fn crc64(data: &[u8]) -> u64 {
+    let mut crc = 0u64;
+    for &byte in data {
+        crc ^= byte as u64;
+        for _ in 0..8 {
+            crc = match crc & 1 {
+                1 => (crc >> 1) ^ CRC64_POLY,
+                _ => crc >> 1,
+            };
+        }
+    }
+    crc
+}
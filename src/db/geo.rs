@@ -0,0 +1,318 @@
+// Geo commands store points in a sorted set, scored by their 52 bit geohash. Searches work
+// like Redis' do: pick a geohash precision whose cells are about the size of the search, scan
+// the score ranges of the cell holding the center and its eight neighbours, then filter the
+// candidates by their exact distance.
+use super::geohash::{self, Area, LAT_MAX, LAT_MIN, STEP_MAX};
+use super::zset::{RangeBy, ScoreBound, SortedSet, ZAddFlags, ZRange};
+use super::{Db, DbError};
+use bytes::Bytes;
+use std::collections::HashSet;
+
+const GEOHASH_ALPHABET: &[u8] = b"0123456789bcdefghjkmnpqrstuvwxyz";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GeoUnit {
+    Meters,
+    Kilometers,
+    Miles,
+    Feet,
+}
+
+impl GeoUnit {
+    fn meters(self) -> f64 {
+        match self {
+            GeoUnit::Meters => 1.0,
+            GeoUnit::Kilometers => 1000.0,
+            GeoUnit::Miles => 1609.34,
+            GeoUnit::Feet => 0.3048,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum GeoFrom {
+    Member(Bytes),
+    LonLat(f64, f64),
+}
+
+// Sizes are in the query's unit
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GeoShape {
+    Radius(f64),
+    Box { width: f64, height: f64 },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GeoSort {
+    Asc,
+    Desc,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct GeoQuery {
+    pub from: GeoFrom,
+    pub shape: GeoShape,
+    pub unit: GeoUnit,
+    pub sort: Option<GeoSort>,
+    pub count: Option<usize>,
+    // With ANY the search stops as soon as `count` matches are found
+    pub any: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct GeoMatch {
+    pub member: Bytes,
+    // In the query's unit
+    pub dist: f64,
+    pub hash: u64,
+    pub lon: f64,
+    pub lat: f64,
+}
+
+impl Db {
+    // Points are (longitude, latitude, member)
+    pub fn geoadd(
+        &self,
+        key: &Bytes,
+        flags: ZAddFlags,
+        points: Vec<(f64, f64, Bytes)>,
+    ) -> Result<usize, DbError> {
+        if let Some(&(lon, lat, _)) = points
+            .iter()
+            .find(|(lon, lat, _)| !geohash::valid_lon_lat(*lon, *lat))
+        {
+            return Err(DbError::InvalidLonLat(lon, lat));
+        }
+        let pairs = points
+            .into_iter()
+            .map(|(lon, lat, member)| (geohash::encode_score(lon, lat), member))
+            .collect();
+        self.zadd(key, flags, pairs)
+    }
+
+    pub fn geopos(
+        &self,
+        key: &Bytes,
+        members: &[Bytes],
+    ) -> Result<Vec<Option<(f64, f64)>>, DbError> {
+        Ok(self
+            .zmscore(key, members)?
+            .into_iter()
+            .map(|score| score.map(geohash::decode_score))
+            .collect())
+    }
+
+    pub fn geodist(
+        &self,
+        key: &Bytes,
+        a: &Bytes,
+        b: &Bytes,
+        unit: GeoUnit,
+    ) -> Result<Option<f64>, DbError> {
+        let scores = self.zmscore(key, &[a.clone(), b.clone()])?;
+        let (Some(a), Some(b)) = (scores[0], scores[1]) else {
+            return Ok(None);
+        };
+        let (lon1, lat1) = geohash::decode_score(a);
+        let (lon2, lat2) = geohash::decode_score(b);
+        Ok(Some(
+            geohash::distance(lon1, lat1, lon2, lat2) / unit.meters(),
+        ))
+    }
+
+    // Standard 11 character geohashes, which use -90..90 for latitude rather than the
+    // Mercator range the scores use
+    pub fn geohash(&self, key: &Bytes, members: &[Bytes]) -> Result<Vec<Option<String>>, DbError> {
+        Ok(self
+            .zmscore(key, members)?
+            .into_iter()
+            .map(|score| {
+                let (lon, lat) = geohash::decode_score(score?);
+                let bits = geohash::encode(lon, lat, STEP_MAX, (-90.0, 90.0));
+                let hash = (0..11)
+                    .map(|i| {
+                        // 52 bits only fill ten characters
+                        let idx = if i == 10 {
+                            0
+                        } else {
+                            (bits >> (52 - (i + 1) * 5)) & 0x1f
+                        };
+                        GEOHASH_ALPHABET[idx as usize] as char
+                    })
+                    .collect();
+                Some(hash)
+            })
+            .collect())
+    }
+
+    pub fn geosearch(&self, key: &Bytes, query: &GeoQuery) -> Result<Vec<GeoMatch>, DbError> {
+        let mut state = self.lock_read();
+        match state.zset_mut(key)? {
+            Some(zset) => search(zset, query),
+            None => Ok(Vec::new()),
+        }
+    }
+
+    // Stores the matches scored by geohash, or by distance with `store_dist`
+    pub fn geosearchstore(
+        &self,
+        destination: &Bytes,
+        source: &Bytes,
+        query: &GeoQuery,
+        store_dist: bool,
+    ) -> Result<usize, DbError> {
+        let mut state = self.lock();
+        let matches = match state.zset_mut(source)? {
+            Some(zset) => search(zset, query)?,
+            None => Vec::new(),
+        };
+        let members = matches.into_iter().map(|m| {
+            let score = if store_dist { m.dist } else { m.hash as f64 };
+            (m.member, score)
+        });
+        Ok(state.store_zset(destination, "geosearchstore", members))
+    }
+}
+
+fn search(zset: &SortedSet, query: &GeoQuery) -> Result<Vec<GeoMatch>, DbError> {
+    let (lon, lat) = match &query.from {
+        GeoFrom::LonLat(lon, lat) if geohash::valid_lon_lat(*lon, *lat) => (*lon, *lat),
+        GeoFrom::LonLat(lon, lat) => return Err(DbError::InvalidLonLat(*lon, *lat)),
+        GeoFrom::Member(member) => {
+            geohash::decode_score(zset.score(member).ok_or(DbError::GeoMemberMissing)?)
+        }
+    };
+
+    let to_meters = query.unit.meters();
+    let (half_width, half_height) = match query.shape {
+        GeoShape::Radius(radius) => (radius * to_meters, radius * to_meters),
+        GeoShape::Box { width, height } => (width * to_meters / 2.0, height * to_meters / 2.0),
+    };
+    let bounds = geohash::bounding_box(lon, lat, half_width, half_height);
+    let search_radius = match query.shape {
+        GeoShape::Radius(_) => half_width,
+        GeoShape::Box { .. } => half_width.hypot(half_height),
+    };
+
+    let limit = query.count.filter(|_| query.any);
+    let mut matches = Vec::new();
+    'cells: for (min, max) in cell_ranges(lon, lat, search_radius, &bounds) {
+        let range = ZRange {
+            by: RangeBy::Score {
+                min: ScoreBound::Inclusive(min),
+                max: ScoreBound::Exclusive(max),
+            },
+            rev: false,
+            limit: None,
+        };
+        for (member, score) in zset.range(&range) {
+            let (plon, plat) = geohash::decode_score(score);
+            let Some(dist) = within(query.shape, to_meters, lon, lat, plon, plat) else {
+                continue;
+            };
+            matches.push(GeoMatch {
+                member,
+                dist: dist / to_meters,
+                hash: score as u64,
+                lon: plon,
+                lat: plat,
+            });
+            if limit.is_some_and(|limit| matches.len() >= limit) {
+                break 'cells;
+            }
+        }
+    }
+
+    // COUNT without ANY wants the nearest matches
+    let sort = match (query.sort, query.count) {
+        (None, Some(_)) if !query.any => Some(GeoSort::Asc),
+        (sort, _) => sort,
+    };
+    match sort {
+        Some(GeoSort::Asc) => matches.sort_by(|a, b| a.dist.total_cmp(&b.dist)),
+        Some(GeoSort::Desc) => matches.sort_by(|a, b| b.dist.total_cmp(&a.dist)),
+        None => {}
+    }
+    if let Some(count) = query.count {
+        matches.truncate(count);
+    }
+    Ok(matches)
+}
+
+// The distance in meters from the center to a point inside the shape
+fn within(
+    shape: GeoShape,
+    to_meters: f64,
+    lon: f64,
+    lat: f64,
+    plon: f64,
+    plat: f64,
+) -> Option<f64> {
+    match shape {
+        GeoShape::Radius(radius) => {
+            let dist = geohash::distance(lon, lat, plon, plat);
+            (dist <= radius * to_meters).then_some(dist)
+        }
+        GeoShape::Box { width, height } => {
+            // Latitude distance is cheaper, so rule points out with it first
+            if geohash::lat_distance(plat, lat) > height * to_meters / 2.0 {
+                return None;
+            }
+            if geohash::distance(plon, plat, lon, plat) > width * to_meters / 2.0 {
+                return None;
+            }
+            Some(geohash::distance(lon, lat, plon, plat))
+        }
+    }
+}
+
+// Score ranges, each [min, max), of the cells that can hold points inside `bounds`
+fn cell_ranges(lon: f64, lat: f64, radius: f64, bounds: &Area) -> Vec<(f64, f64)> {
+    let merc = (LAT_MIN, LAT_MAX);
+    let mut step = geohash::estimate_step(radius, lat);
+    let mut center = geohash::encode(lon, lat, step, merc);
+
+    // Near the edge of a cell the estimated precision may leave part of the search outside
+    // the neighbours, so go one step coarser
+    let side = |center: u64, step: u32, dlat: i64, dlon: i64| {
+        geohash::decode(geohash::neighbor(center, step, dlat, dlon), step, merc)
+    };
+    let too_small = side(center, step, 1, 0).lat_max < bounds.lat_max
+        || side(center, step, -1, 0).lat_min > bounds.lat_min
+        || side(center, step, 0, 1).lon_max < bounds.lon_max
+        || side(center, step, 0, -1).lon_min > bounds.lon_min;
+    if step > 1 && too_small {
+        step -= 1;
+        center = geohash::encode(lon, lat, step, merc);
+    }
+    let area = geohash::decode(center, step, merc);
+
+    // Center, north, south, east, west, then the corners, in the order Redis scans them
+    let offsets = [
+        (0, 0),
+        (1, 0),
+        (-1, 0),
+        (0, 1),
+        (0, -1),
+        (1, 1),
+        (1, -1),
+        (-1, 1),
+        (-1, -1),
+    ];
+    let shift = 52 - step * 2;
+    let mut seen = HashSet::new();
+    offsets
+        .into_iter()
+        .filter(|&(dlat, dlon)| {
+            // Neighbours wholly outside the bounding box can be skipped
+            step < 2
+                || !((dlat < 0 && area.lat_min < bounds.lat_min)
+                    || (dlat > 0 && area.lat_max > bounds.lat_max)
+                    || (dlon < 0 && area.lon_min < bounds.lon_min)
+                    || (dlon > 0 && area.lon_max > bounds.lon_max))
+        })
+        .map(|(dlat, dlon)| geohash::neighbor(center, step, dlat, dlon))
+        .filter(|&cell| seen.insert(cell))
+        .map(|cell| ((cell << shift) as f64, ((cell + 1) << shift) as f64))
+        .collect()
+}
@@ -0,0 +1,170 @@
+// Geohash encoding and the spherical maths behind the geo commands, following Redis'
+// geohash.c and geohash_helper.c so scores, distances and search areas come out the same.
+use std::f64::consts::PI;
+
+// Web Mercator can't represent the poles, so neither can Redis
+pub(super) const LAT_MIN: f64 = -85.05112878;
+pub(super) const LAT_MAX: f64 = 85.05112878;
+pub(super) const LON_MIN: f64 = -180.0;
+pub(super) const LON_MAX: f64 = 180.0;
+
+// 26 bits each of latitude and longitude fit exactly in a sorted set score
+pub(super) const STEP_MAX: u32 = 26;
+const EARTH_RADIUS: f64 = 6372797.560856;
+const MERCATOR_MAX: f64 = 20037726.37;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(super) struct Area {
+    pub(super) lat_min: f64,
+    pub(super) lat_max: f64,
+    pub(super) lon_min: f64,
+    pub(super) lon_max: f64,
+}
+
+impl Area {
+    // The middle of the cell, which is what a stored point decodes to
+    pub(super) fn center(&self) -> (f64, f64) {
+        let lon = ((self.lon_min + self.lon_max) / 2.0).clamp(LON_MIN, LON_MAX);
+        let lat = ((self.lat_min + self.lat_max) / 2.0).clamp(LAT_MIN, LAT_MAX);
+        (lon, lat)
+    }
+}
+
+pub(super) fn valid_lon_lat(lon: f64, lat: f64) -> bool {
+    (LON_MIN..=LON_MAX).contains(&lon) && (LAT_MIN..=LAT_MAX).contains(&lat)
+}
+
+// Interleave `step` bits of each coordinate, latitude in the even bits and longitude in the
+// odd ones. `lat_range` is Redis' Mercator range for scores and -90..90 for GEOHASH strings.
+pub(super) fn encode(lon: f64, lat: f64, step: u32, lat_range: (f64, f64)) -> u64 {
+    let cells = (1u64 << step) as f64;
+    let lat_offset = (lat - lat_range.0) / (lat_range.1 - lat_range.0) * cells;
+    let lon_offset = (lon - LON_MIN) / (LON_MAX - LON_MIN) * cells;
+    interleave(lat_offset as u32, lon_offset as u32)
+}
+
+pub(super) fn decode(bits: u64, step: u32, lat_range: (f64, f64)) -> Area {
+    let (lat_cell, lon_cell) = deinterleave(bits);
+    let cells = (1u64 << step) as f64;
+    let lat_scale = lat_range.1 - lat_range.0;
+    let lon_scale = LON_MAX - LON_MIN;
+    Area {
+        lat_min: lat_range.0 + (f64::from(lat_cell) / cells) * lat_scale,
+        lat_max: lat_range.0 + ((f64::from(lat_cell) + 1.0) / cells) * lat_scale,
+        lon_min: LON_MIN + (f64::from(lon_cell) / cells) * lon_scale,
+        lon_max: LON_MIN + ((f64::from(lon_cell) + 1.0) / cells) * lon_scale,
+    }
+}
+
+// The full precision cell a sorted set score stands for
+pub(super) fn decode_score(score: f64) -> (f64, f64) {
+    decode(score as u64, STEP_MAX, (LAT_MIN, LAT_MAX)).center()
+}
+
+pub(super) fn encode_score(lon: f64, lat: f64) -> f64 {
+    encode(lon, lat, STEP_MAX, (LAT_MIN, LAT_MAX)) as f64
+}
+
+// The cell next to `bits`, `dlat` rows north and `dlon` columns east. Both wrap around.
+pub(super) fn neighbor(bits: u64, step: u32, dlat: i64, dlon: i64) -> u64 {
+    let mask = (1i64 << step) - 1;
+    let (lat, lon) = deinterleave(bits);
+    let lat = (i64::from(lat) + dlat) & mask;
+    let lon = (i64::from(lon) + dlon) & mask;
+    interleave(lat as u32, lon as u32)
+}
+
+// Spread the bits of `x` out to the even positions
+fn spread(x: u32) -> u64 {
+    let mut x = u64::from(x);
+    x = (x | (x << 16)) & 0x0000_FFFF_0000_FFFF;
+    x = (x | (x << 8)) & 0x00FF_00FF_00FF_00FF;
+    x = (x | (x << 4)) & 0x0F0F_0F0F_0F0F_0F0F;
+    x = (x | (x << 2)) & 0x3333_3333_3333_3333;
+    (x | (x << 1)) & 0x5555_5555_5555_5555
+}
+
+fn squash(x: u64) -> u32 {
+    let mut x = x & 0x5555_5555_5555_5555;
+    x = (x | (x >> 1)) & 0x3333_3333_3333_3333;
+    x = (x | (x >> 2)) & 0x0F0F_0F0F_0F0F_0F0F;
+    x = (x | (x >> 4)) & 0x00FF_00FF_00FF_00FF;
+    x = (x | (x >> 8)) & 0x0000_FFFF_0000_FFFF;
+    ((x | (x >> 16)) & 0x0000_0000_FFFF_FFFF) as u32
+}
+
+fn interleave(lat: u32, lon: u32) -> u64 {
+    spread(lat) | (spread(lon) << 1)
+}
+
+// Returns (latitude, longitude) cells
+fn deinterleave(bits: u64) -> (u32, u32) {
+    (squash(bits), squash(bits >> 1))
+}
+
+fn deg_rad(deg: f64) -> f64 {
+    deg * (PI / 180.0)
+}
+
+fn rad_deg(rad: f64) -> f64 {
+    rad / (PI / 180.0)
+}
+
+pub(super) fn lat_distance(lat1: f64, lat2: f64) -> f64 {
+    EARTH_RADIUS * (deg_rad(lat2) - deg_rad(lat1)).abs()
+}
+
+// Haversine distance in meters
+pub(super) fn distance(lon1: f64, lat1: f64, lon2: f64, lat2: f64) -> f64 {
+    let v = ((deg_rad(lon2) - deg_rad(lon1)) / 2.0).sin();
+    // Same longitude, so the cheaper latitude distance is exact
+    if v == 0.0 {
+        return lat_distance(lat1, lat2);
+    }
+    let lat1 = deg_rad(lat1);
+    let lat2 = deg_rad(lat2);
+    let u = ((lat2 - lat1) / 2.0).sin();
+    let a = u * u + lat1.cos() * lat2.cos() * v * v;
+    2.0 * EARTH_RADIUS * a.sqrt().asin()
+}
+
+// The coarsest precision whose cells are still about as big as the search radius
+pub(super) fn estimate_step(mut radius: f64, lat: f64) -> u32 {
+    if radius == 0.0 {
+        return STEP_MAX;
+    }
+    let mut step: i32 = 1;
+    while radius < MERCATOR_MAX {
+        radius *= 2.0;
+        step += 1;
+    }
+    // Make sure the range is covered in most cases
+    step -= 2;
+    // Cells get narrower towards the poles
+    if !(-66.0..=66.0).contains(&lat) {
+        step -= 1;
+        if !(-80.0..=80.0).contains(&lat) {
+            step -= 1;
+        }
+    }
+    step.clamp(1, STEP_MAX as i32) as u32
+}
+
+// The lon/lat box enclosing a search of `half_width` by `half_height` meters around a point
+pub(super) fn bounding_box(lon: f64, lat: f64, half_width: f64, half_height: f64) -> Area {
+    let lat_delta = rad_deg(half_height / EARTH_RADIUS);
+    let lon_delta_top = rad_deg(half_width / EARTH_RADIUS / deg_rad(lat + lat_delta).cos());
+    let lon_delta_bottom = rad_deg(half_width / EARTH_RADIUS / deg_rad(lat - lat_delta).cos());
+    // The box is widest on the side nearer the equator
+    let lon_delta = if lat < 0.0 {
+        lon_delta_bottom
+    } else {
+        lon_delta_top
+    };
+    Area {
+        lat_min: lat - lat_delta,
+        lat_max: lat + lat_delta,
+        lon_min: lon - lon_delta,
+        lon_max: lon + lon_delta,
+    }
+}
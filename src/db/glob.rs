@@ -0,0 +1,125 @@
+// Redis glob patterns, as KEYS and PSUBSCRIBE take them. `*` matches any run of bytes, `?` any
+// single byte and `[...]` a byte from a set of bytes and `a-z` ranges, negated by a leading `^`.
+// A backslash makes the next byte literal, both outside and inside a set. An unclosed set runs
+// to the end of the pattern, like Redis' stringmatchlen.
+
+#[derive(Debug, Clone, PartialEq)]
+pub(super) struct Glob {
+    tokens: Vec<Token>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Byte(u8),
+    Any,
+    Star,
+    Set { negate: bool, ranges: Vec<(u8, u8)> },
+}
+
+impl Glob {
+    pub(super) fn new(pattern: &[u8]) -> Glob {
+        let mut tokens = Vec::new();
+        let mut i = 0;
+        while i < pattern.len() {
+            let token = match pattern[i] {
+                b'*' => {
+                    // Consecutive stars match the same as one
+                    if tokens.last() == Some(&Token::Star) {
+                        i += 1;
+                        continue;
+                    }
+                    Token::Star
+                }
+                b'?' => Token::Any,
+                b'\\' if i + 1 < pattern.len() => {
+                    i += 1;
+                    Token::Byte(pattern[i])
+                }
+                b'[' => {
+                    i += 1;
+                    let negate = pattern.get(i) == Some(&b'^');
+                    if negate {
+                        i += 1;
+                    }
+                    let mut ranges = Vec::new();
+                    while i < pattern.len() && pattern[i] != b']' {
+                        let mut start = pattern[i];
+                        if start == b'\\' && i + 1 < pattern.len() {
+                            i += 1;
+                            start = pattern[i];
+                        }
+                        if pattern.get(i + 1) == Some(&b'-') && i + 2 < pattern.len() {
+                            let end = pattern[i + 2];
+                            ranges.push((start.min(end), start.max(end)));
+                            i += 3;
+                        } else {
+                            ranges.push((start, start));
+                            i += 1;
+                        }
+                    }
+                    Token::Set { negate, ranges }
+                }
+                byte => Token::Byte(byte),
+            };
+            tokens.push(token);
+            i += 1;
+        }
+        Glob { tokens }
+    }
+
+    // The bytes every match starts with
+    pub(super) fn literal_prefix(&self) -> Vec<u8> {
+        self.tokens
+            .iter()
+            .map_while(|token| match token {
+                Token::Byte(byte) => Some(*byte),
+                _ => None,
+            })
+            .collect()
+    }
+
+    // Backtracks only to the last star, so a match is linear in the pattern times the input
+    pub(super) fn matches(&self, input: &[u8]) -> bool {
+        let (mut t, mut i) = (0, 0);
+        let mut star: Option<(usize, usize)> = None;
+        while i < input.len() {
+            match self.tokens.get(t) {
+                Some(Token::Star) => {
+                    star = Some((t, i));
+                    t += 1;
+                    continue;
+                }
+                Some(token) if token.matches(input[i]) => {
+                    t += 1;
+                    i += 1;
+                    continue;
+                }
+                _ => {}
+            }
+            // Let the last star swallow one more byte and retry from there
+            let Some((star_t, star_i)) = star else {
+                return false;
+            };
+            star = Some((star_t, star_i + 1));
+            t = star_t + 1;
+            i = star_i + 1;
+        }
+        self.tokens[t..].iter().all(|token| *token == Token::Star)
+    }
+}
+
+impl Token {
+    fn matches(&self, byte: u8) -> bool {
+        match self {
+            Token::Byte(b) => *b == byte,
+            Token::Any => true,
+            Token::Star => unreachable!("stars are handled by Glob::matches"),
+            Token::Set { negate, ranges } => {
+                ranges
+                    .iter()
+                    .any(|(start, end)| (*start..=*end).contains(&byte))
+                    != *negate
+            }
+        }
+    }
+}
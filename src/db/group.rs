@@ -0,0 +1,683 @@
+// Consumer groups. Each group remembers the last ID it handed out and keeps a pending entries
+// list (PEL) of everything delivered but not yet acknowledged, which XCLAIM and XAUTOCLAIM
+// use to move stuck messages to another consumer.
+use super::blocking::StopReading;
+use super::stream::{Stream, StreamEntry, StreamId, now_ms};
+use super::{Class, Db, DbError, Entry, State, Value};
+use bytes::Bytes;
+use std::collections::{BTreeMap, BTreeSet};
+use std::time::Duration;
+use tokio::sync::mpsc;
+
+// Where XGROUP CREATE and SETID start a group
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GroupStart {
+    Id(StreamId),
+    // `$`
+    Last,
+}
+
+// Where XREADGROUP reads a stream from
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GroupReadFrom {
+    // `>`: entries never delivered to the group
+    Undelivered,
+    // The consumer's own pending entries after this ID
+    Pending(StreamId),
+}
+
+// An entry read by XREADGROUP. Pending entries deleted from the stream have no fields.
+pub type GroupEntry = (StreamId, Option<Vec<(Bytes, Bytes)>>);
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PendingSummary {
+    pub count: usize,
+    pub min: Option<StreamId>,
+    pub max: Option<StreamId>,
+    pub consumers: Vec<(Bytes, usize)>,
+}
+
+// The extended form of XPENDING
+#[derive(Debug, Clone, PartialEq)]
+pub struct PendingFilter {
+    pub min_idle: Option<u64>,
+    pub start: StreamId,
+    pub end: StreamId,
+    pub count: usize,
+    pub consumer: Option<Bytes>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PendingInfo {
+    pub id: StreamId,
+    pub consumer: Bytes,
+    pub idle: u64,
+    pub deliveries: u64,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ClaimOptions {
+    // Set the idle time instead of resetting it
+    pub idle: Option<u64>,
+    // Set the delivery time as a Unix time in milliseconds
+    pub time: Option<u64>,
+    pub retry_count: Option<u64>,
+    // Claim IDs that aren't pending, as long as they're in the stream
+    pub force: bool,
+    // Don't count the claim as a delivery
+    pub just_id: bool,
+    pub last_id: Option<StreamId>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct AutoClaimed {
+    // Where the next XAUTOCLAIM should start, 0-0 once the whole PEL has been scanned
+    pub next: StreamId,
+    pub claimed: Vec<StreamEntry>,
+    // Pending entries that were no longer in the stream, now dropped from the PEL
+    pub deleted: Vec<StreamId>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct GroupInfo {
+    pub name: Bytes,
+    pub consumers: usize,
+    pub pending: usize,
+    pub last_delivered_id: StreamId,
+    pub entries_read: Option<u64>,
+    pub lag: usize,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConsumerInfo {
+    pub name: Bytes,
+    pub pending: usize,
+    // Since the consumer last tried to read
+    pub idle: u64,
+    // Since the consumer last got something, None if it never has
+    pub inactive: Option<u64>,
+}
+
+pub(super) struct ConsumerGroup {
+    last_delivered: StreamId,
+    entries_read: Option<u64>,
+    pending: BTreeMap<StreamId, Pending>,
+    consumers: BTreeMap<Bytes, Consumer>,
+}
+
+struct Pending {
+    consumer: Bytes,
+    delivered_at: u64,
+    deliveries: u64,
+}
+
+struct Consumer {
+    pending: BTreeSet<StreamId>,
+    seen_at: u64,
+    active_at: Option<u64>,
+}
+
+impl Consumer {
+    fn new(now: u64) -> Consumer {
+        Consumer {
+            pending: BTreeSet::new(),
+            seen_at: now,
+            active_at: None,
+        }
+    }
+}
+
+impl ConsumerGroup {
+    fn new(last_delivered: StreamId, entries_read: Option<u64>) -> ConsumerGroup {
+        ConsumerGroup {
+            last_delivered,
+            entries_read,
+            pending: BTreeMap::new(),
+            consumers: BTreeMap::new(),
+        }
+    }
+
+    fn consumer(&mut self, name: &Bytes, now: u64) -> &mut Consumer {
+        self.consumers
+            .entry(name.clone())
+            .or_insert_with(|| Consumer::new(now))
+    }
+
+    // Record a delivery of `id` to `consumer`, taking it from whoever had it before
+    fn assign(&mut self, id: StreamId, consumer: &Bytes, delivered_at: u64, deliveries: u64) {
+        if let Some(old) = self.pending.insert(
+            id,
+            Pending {
+                consumer: consumer.clone(),
+                delivered_at,
+                deliveries,
+            },
+        ) && let Some(owner) = self.consumers.get_mut(&old.consumer)
+        {
+            owner.pending.remove(&id);
+        }
+        self.consumer(consumer, delivered_at).pending.insert(id);
+    }
+
+    fn unassign(&mut self, id: StreamId) -> bool {
+        let Some(pending) = self.pending.remove(&id) else {
+            return false;
+        };
+        if let Some(owner) = self.consumers.get_mut(&pending.consumer) {
+            owner.pending.remove(&id);
+        }
+        true
+    }
+
+    // Live entries the group hasn't been given yet
+    fn lag(&self, stream: &Stream) -> usize {
+        match self.last_delivered.next() {
+            Some(start) => stream.range(start, StreamId::MAX, None, false).len(),
+            None => 0,
+        }
+    }
+}
+
+fn resolve_start(stream: &Stream, start: GroupStart) -> StreamId {
+    match start {
+        GroupStart::Id(id) => id,
+        GroupStart::Last => stream.last_id(),
+    }
+}
+
+// How many entries a group starting at `id` has already read, when that can be known
+fn entries_read_at(stream: &Stream, id: StreamId, given: Option<u64>) -> Option<u64> {
+    given.or(if id == StreamId::MIN {
+        Some(0)
+    } else if id == stream.last_id() {
+        Some(stream.entries_added())
+    } else {
+        None
+    })
+}
+
+impl Db {
+    pub fn xgroup_create(
+        &self,
+        key: &Bytes,
+        group: &Bytes,
+        start: GroupStart,
+        mkstream: bool,
+        entries_read: Option<u64>,
+    ) -> Result<(), DbError> {
+        let mut state = self.lock();
+        if state.stream_mut(key)?.is_none() {
+            if !mkstream {
+                return Err(DbError::XGroupNeedsKey);
+            }
+            state.insert(
+                key.clone(),
+                Entry {
+                    value: Value::Stream(Stream::default()),
+                    expires_at: None,
+                },
+            );
+        }
+        let stream = state.stream_mut(key)?.ok_or(DbError::NoSuchKey)?;
+        if stream.groups.contains_key(group) {
+            return Err(DbError::BusyGroup);
+        }
+
+        let id = resolve_start(stream, start);
+        let entries_read = entries_read_at(stream, id, entries_read);
+        stream
+            .groups
+            .insert(group.clone(), ConsumerGroup::new(id, entries_read));
+        state.notify(Class::Stream, "xgroup-create", key);
+        Ok(())
+    }
+
+    pub fn xgroup_setid(
+        &self,
+        key: &Bytes,
+        group: &Bytes,
+        start: GroupStart,
+        entries_read: Option<u64>,
+    ) -> Result<(), DbError> {
+        let mut state = self.lock();
+        let stream = state.stream_mut(key)?.ok_or(DbError::XGroupNeedsKey)?;
+        let id = resolve_start(stream, start);
+        let entries_read = entries_read_at(stream, id, entries_read);
+        let group = stream.groups.get_mut(group).ok_or(DbError::NoGroup)?;
+        group.last_delivered = id;
+        group.entries_read = entries_read;
+        state.notify(Class::Stream, "xgroup-setid", key);
+        Ok(())
+    }
+
+    pub fn xgroup_destroy(&self, key: &Bytes, group: &Bytes) -> Result<bool, DbError> {
+        let mut state = self.lock();
+        let stream = state.stream_mut(key)?.ok_or(DbError::XGroupNeedsKey)?;
+        let destroyed = stream.groups.remove(group).is_some();
+        if destroyed {
+            state.notify(Class::Stream, "xgroup-destroy", key);
+        }
+        Ok(destroyed)
+    }
+
+    pub fn xgroup_createconsumer(
+        &self,
+        key: &Bytes,
+        group: &Bytes,
+        consumer: &Bytes,
+    ) -> Result<bool, DbError> {
+        let mut state = self.lock();
+        let group = state.group_mut(key, group)?;
+        if group.consumers.contains_key(consumer) {
+            return Ok(false);
+        }
+        group.consumer(consumer, now_ms());
+        state.notify(Class::Stream, "xgroup-createconsumer", key);
+        Ok(true)
+    }
+
+    // Returns how many pending entries the consumer had, which are dropped with it
+    pub fn xgroup_delconsumer(
+        &self,
+        key: &Bytes,
+        group: &Bytes,
+        consumer: &Bytes,
+    ) -> Result<usize, DbError> {
+        let mut state = self.lock();
+        let group = state.group_mut(key, group)?;
+        let Some(removed) = group.consumers.remove(consumer) else {
+            return Ok(0);
+        };
+        for id in &removed.pending {
+            group.pending.remove(id);
+        }
+        state.notify(Class::Stream, "xgroup-delconsumer", key);
+        Ok(removed.pending.len())
+    }
+
+    pub fn xreadgroup(
+        &self,
+        group: &Bytes,
+        consumer: &Bytes,
+        streams: &[(Bytes, GroupReadFrom)],
+        count: Option<usize>,
+        noack: bool,
+    ) -> Result<Vec<(Bytes, Vec<GroupEntry>)>, DbError> {
+        self.lock()
+            .xreadgroup(group, consumer, streams, count, noack)
+    }
+
+    // XREADGROUP BLOCK. Only reads of undelivered entries wait; reading a consumer's pending
+    // entries always replies straight away. A timeout of None waits forever.
+    pub async fn xreadgroup_block(
+        &self,
+        group: &Bytes,
+        consumer: &Bytes,
+        streams: &[(Bytes, GroupReadFrom)],
+        count: Option<usize>,
+        noack: bool,
+        timeout: Option<Duration>,
+    ) -> Result<Vec<(Bytes, Vec<GroupEntry>)>, DbError> {
+        let deadline = timeout.map(|t| tokio::time::Instant::now() + t);
+        let keys: Vec<Bytes> = streams.iter().map(|(key, _)| key.clone()).collect();
+        let (tx, mut rx) = mpsc::channel(1);
+        let _reading = StopReading {
+            db: self,
+            keys: keys.clone(),
+            tx: tx.clone(),
+        };
+        loop {
+            {
+                let _gate = self.enter().await?;
+                let mut state = self.lock();
+                let found = state.xreadgroup(group, consumer, streams, count, noack)?;
+                let waits = streams
+                    .iter()
+                    .all(|(_, from)| *from == GroupReadFrom::Undelivered);
+                if !found.is_empty() || !waits {
+                    return Ok(found);
+                }
+                state.wait_for_entries(&keys, &tx);
+            }
+
+            match deadline {
+                Some(deadline) => {
+                    if tokio::time::timeout_at(deadline, rx.recv()).await.is_err() {
+                        return Ok(Vec::new());
+                    }
+                }
+                None => {
+                    rx.recv().await;
+                }
+            }
+        }
+    }
+
+    pub fn xack(&self, key: &Bytes, group: &Bytes, ids: &[StreamId]) -> Result<usize, DbError> {
+        let mut state = self.lock();
+        let Some(stream) = state.stream_mut(key)? else {
+            return Ok(0);
+        };
+        let Some(group) = stream.groups.get_mut(group) else {
+            return Ok(0);
+        };
+        Ok(ids.iter().filter(|id| group.unassign(**id)).count())
+    }
+
+    pub fn xpending_summary(&self, key: &Bytes, group: &Bytes) -> Result<PendingSummary, DbError> {
+        let mut state = self.lock_read();
+        let group = state.group_mut(key, group)?;
+        Ok(PendingSummary {
+            count: group.pending.len(),
+            min: group.pending.keys().next().copied(),
+            max: group.pending.keys().next_back().copied(),
+            consumers: group
+                .consumers
+                .iter()
+                .filter(|(_, c)| !c.pending.is_empty())
+                .map(|(name, c)| (name.clone(), c.pending.len()))
+                .collect(),
+        })
+    }
+
+    pub fn xpending(
+        &self,
+        key: &Bytes,
+        group: &Bytes,
+        filter: &PendingFilter,
+    ) -> Result<Vec<PendingInfo>, DbError> {
+        let mut state = self.lock_read();
+        let group = state.group_mut(key, group)?;
+        if filter.start > filter.end {
+            return Ok(Vec::new());
+        }
+        let now = now_ms();
+        Ok(group
+            .pending
+            .range(filter.start..=filter.end)
+            .filter(|(_, p)| filter.consumer.as_ref().is_none_or(|c| *c == p.consumer))
+            .map(|(id, p)| PendingInfo {
+                id: *id,
+                consumer: p.consumer.clone(),
+                idle: now.saturating_sub(p.delivered_at),
+                deliveries: p.deliveries,
+            })
+            .filter(|info| filter.min_idle.is_none_or(|min| info.idle >= min))
+            .take(filter.count)
+            .collect())
+    }
+
+    pub fn xclaim(
+        &self,
+        key: &Bytes,
+        group: &Bytes,
+        consumer: &Bytes,
+        min_idle: u64,
+        ids: &[StreamId],
+        opts: &ClaimOptions,
+    ) -> Result<Vec<StreamEntry>, DbError> {
+        let mut state = self.lock();
+        let stream = state.stream_mut(key)?.ok_or(DbError::NoGroup)?;
+        let entries: Vec<_> = ids.iter().map(|&id| (id, stream.get(id))).collect();
+        let group = stream.groups.get_mut(group).ok_or(DbError::NoGroup)?;
+
+        let now = now_ms();
+        let delivered_at = match (opts.idle, opts.time) {
+            (Some(idle), _) => now.saturating_sub(idle),
+            (None, Some(time)) => time,
+            (None, None) => now,
+        };
+        let created = !group.consumers.contains_key(consumer);
+        group.consumer(consumer, now).seen_at = now;
+
+        let mut claimed = Vec::new();
+        for (id, fields) in entries {
+            let Some(fields) = fields else {
+                // Entries deleted from the stream can't be claimed, so stop tracking them
+                group.unassign(id);
+                continue;
+            };
+            let deliveries = match group.pending.get(&id) {
+                Some(p) if now.saturating_sub(p.delivered_at) < min_idle => continue,
+                Some(p) => p.deliveries,
+                None if opts.force => 0,
+                None => continue,
+            };
+            let deliveries = match opts.retry_count {
+                Some(n) => n,
+                None if opts.just_id => deliveries,
+                None => deliveries + 1,
+            };
+            group.assign(id, consumer, delivered_at, deliveries);
+            claimed.push((id, fields));
+        }
+
+        if !claimed.is_empty() {
+            group.consumer(consumer, now).active_at = Some(now);
+        }
+        if let Some(last_id) = opts.last_id {
+            group.last_delivered = group.last_delivered.max(last_id);
+        }
+        if created {
+            state.notify(Class::Stream, "xgroup-createconsumer", key);
+        }
+        Ok(claimed)
+    }
+
+    // Scan the PEL from `start`, claiming up to `count` entries idle for at least `min_idle`
+    #[allow(clippy::too_many_arguments)]
+    pub fn xautoclaim(
+        &self,
+        key: &Bytes,
+        group: &Bytes,
+        consumer: &Bytes,
+        min_idle: u64,
+        start: StreamId,
+        count: usize,
+        just_id: bool,
+    ) -> Result<AutoClaimed, DbError> {
+        let mut state = self.lock();
+        let stream = state.stream_mut(key)?.ok_or(DbError::NoGroup)?;
+        let pending: Vec<StreamId> = stream
+            .groups
+            .get(group)
+            .ok_or(DbError::NoGroup)?
+            .pending
+            .range(start..)
+            .map(|(id, _)| *id)
+            .collect();
+
+        // Like Redis, bound the work done for a sparse PEL
+        let mut attempts = count.saturating_mul(10);
+        let mut scanned = 0;
+        let mut candidates = Vec::new();
+        for &id in &pending {
+            if attempts == 0 || candidates.len() >= count {
+                break;
+            }
+            attempts -= 1;
+            scanned += 1;
+            candidates.push((id, stream.get(id)));
+        }
+        let next = pending.get(scanned).copied().unwrap_or_default();
+
+        let group = stream.groups.get_mut(group).ok_or(DbError::NoGroup)?;
+        let now = now_ms();
+        let created = !group.consumers.contains_key(consumer);
+        group.consumer(consumer, now).seen_at = now;
+
+        let mut claimed = Vec::new();
+        let mut deleted = Vec::new();
+        for (id, fields) in candidates {
+            let Some(fields) = fields else {
+                group.unassign(id);
+                deleted.push(id);
+                continue;
+            };
+            let Some(p) = group.pending.get(&id) else {
+                continue;
+            };
+            if now.saturating_sub(p.delivered_at) < min_idle {
+                continue;
+            }
+            let deliveries = if just_id {
+                p.deliveries
+            } else {
+                p.deliveries + 1
+            };
+            group.assign(id, consumer, now, deliveries);
+            claimed.push((id, fields));
+        }
+        if !claimed.is_empty() {
+            group.consumer(consumer, now).active_at = Some(now);
+        }
+        if created {
+            state.notify(Class::Stream, "xgroup-createconsumer", key);
+        }
+
+        Ok(AutoClaimed {
+            next,
+            claimed,
+            deleted,
+        })
+    }
+
+    pub fn xinfo_groups(&self, key: &Bytes) -> Result<Vec<GroupInfo>, DbError> {
+        let mut state = self.lock_read();
+        let stream = state.stream_mut(key)?.ok_or(DbError::NoSuchKey)?;
+        Ok(stream
+            .groups
+            .iter()
+            .map(|(name, group)| GroupInfo {
+                name: name.clone(),
+                consumers: group.consumers.len(),
+                pending: group.pending.len(),
+                last_delivered_id: group.last_delivered,
+                entries_read: group.entries_read,
+                lag: group.lag(stream),
+            })
+            .collect())
+    }
+
+    pub fn xinfo_consumers(
+        &self,
+        key: &Bytes,
+        group: &Bytes,
+    ) -> Result<Vec<ConsumerInfo>, DbError> {
+        let mut state = self.lock_read();
+        let group = state.group_mut(key, group)?;
+        let now = now_ms();
+        Ok(group
+            .consumers
+            .iter()
+            .map(|(name, c)| ConsumerInfo {
+                name: name.clone(),
+                pending: c.pending.len(),
+                idle: now.saturating_sub(c.seen_at),
+                inactive: c.active_at.map(|at| now.saturating_sub(at)),
+            })
+            .collect())
+    }
+}
+
+impl State {
+    fn group_mut(&mut self, key: &Bytes, group: &Bytes) -> Result<&mut ConsumerGroup, DbError> {
+        self.stream_mut(key)?
+            .and_then(|stream| stream.groups.get_mut(group))
+            .ok_or(DbError::NoGroup)
+    }
+
+    fn xreadgroup(
+        &mut self,
+        group: &Bytes,
+        consumer: &Bytes,
+        streams: &[(Bytes, GroupReadFrom)],
+        count: Option<usize>,
+        noack: bool,
+    ) -> Result<Vec<(Bytes, Vec<GroupEntry>)>, DbError> {
+        // Every group must exist before anything is delivered
+        for (key, _) in streams {
+            self.group_mut(key, group)?;
+        }
+
+        let now = now_ms();
+        let mut out = Vec::new();
+        let mut joined = Vec::new();
+        for (key, from) in streams {
+            let Some(stream) = self.stream_mut(key)? else {
+                continue;
+            };
+            let entries: Vec<GroupEntry> = match from {
+                GroupReadFrom::Undelivered => {
+                    let start = stream
+                        .groups
+                        .get(group)
+                        .and_then(|g| g.last_delivered.next());
+                    start
+                        .map(|start| stream.range(start, StreamId::MAX, count, false))
+                        .unwrap_or_default()
+                        .into_iter()
+                        .map(|(id, fields)| (id, Some(fields)))
+                        .collect()
+                }
+                GroupReadFrom::Pending(after) => {
+                    let ids: Vec<StreamId> = stream
+                        .groups
+                        .get(group)
+                        .and_then(|g| g.consumers.get(consumer))
+                        .map(|c| {
+                            c.pending
+                                .range((
+                                    std::ops::Bound::Excluded(*after),
+                                    std::ops::Bound::Unbounded,
+                                ))
+                                .take(count.unwrap_or(usize::MAX))
+                                .copied()
+                                .collect()
+                        })
+                        .unwrap_or_default();
+                    ids.into_iter().map(|id| (id, stream.get(id))).collect()
+                }
+            };
+
+            let group = stream.groups.get_mut(group).ok_or(DbError::NoGroup)?;
+            if !group.consumers.contains_key(consumer) {
+                joined.push(key.clone());
+            }
+            group.consumer(consumer, now).seen_at = now;
+            if entries.is_empty() {
+                // A history read still replies with the stream, just with no entries
+                if matches!(from, GroupReadFrom::Pending(_)) {
+                    out.push((key.clone(), entries));
+                }
+                continue;
+            }
+            group.consumer(consumer, now).active_at = Some(now);
+
+            for (id, _) in &entries {
+                match from {
+                    GroupReadFrom::Undelivered => {
+                        group.last_delivered = *id;
+                        if let Some(read) = group.entries_read.as_mut() {
+                            *read += 1;
+                        }
+                        if !noack {
+                            group.assign(*id, consumer, now, 1);
+                        }
+                    }
+                    GroupReadFrom::Pending(_) => {
+                        if let Some(p) = group.pending.get_mut(id) {
+                            p.delivered_at = now;
+                            p.deliveries += 1;
+                        }
+                    }
+                }
+            }
+            out.push((key.clone(), entries));
+        }
+        for key in &joined {
+            self.notify(Class::Stream, "xgroup-createconsumer", key);
+        }
+        Ok(out)
+    }
+}
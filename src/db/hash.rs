@@ -0,0 +1,87 @@
+// Hashes map fields to values under one key. Writes reindex the key so the FT indexes
+// covering it stay current.
+use super::{Class, Db, DbError, Entry, State, Value};
+use bytes::Bytes;
+use std::collections::HashMap;
+
+impl Db {
+    // Returns how many of the fields are new
+    pub fn hset(&self, key: &Bytes, pairs: Vec<(Bytes, Bytes)>) -> Result<usize, DbError> {
+        let mut state = self.lock();
+        let hash = state.hash_or_insert(key)?;
+        let added = pairs
+            .into_iter()
+            .filter(|(field, value)| hash.insert(field.clone(), value.clone()).is_none())
+            .count();
+        state.reindex(key);
+        state.notify(Class::Hash, "hset", key);
+        Ok(added)
+    }
+
+    pub fn hget(&self, key: &Bytes, field: &Bytes) -> Result<Option<Bytes>, DbError> {
+        let mut state = self.lock_read();
+        Ok(state
+            .hash_mut(key)?
+            .and_then(|hash| hash.get(field).cloned()))
+    }
+
+    pub fn hdel(&self, key: &Bytes, fields: &[Bytes]) -> Result<usize, DbError> {
+        let mut state = self.lock();
+        let Some(hash) = state.hash_mut(key)? else {
+            return Ok(0);
+        };
+        let removed = fields.iter().filter(|f| hash.remove(*f).is_some()).count();
+        if removed > 0 {
+            state.notify(Class::Hash, "hdel", key);
+        }
+        state.remove_if_empty(key);
+        state.reindex(key);
+        Ok(removed)
+    }
+
+    pub fn hgetall(&self, key: &Bytes) -> Result<Vec<(Bytes, Bytes)>, DbError> {
+        let mut state = self.lock_read();
+        Ok(state
+            .hash_mut(key)?
+            .map(|hash| {
+                hash.iter()
+                    .map(|(field, value)| (field.clone(), value.clone()))
+                    .collect()
+            })
+            .unwrap_or_default())
+    }
+
+    pub fn hlen(&self, key: &Bytes) -> Result<usize, DbError> {
+        let mut state = self.lock_read();
+        Ok(state.hash_mut(key)?.map_or(0, |hash| hash.len()))
+    }
+}
+
+impl State {
+    pub(super) fn hash_mut(
+        &mut self,
+        key: &Bytes,
+    ) -> Result<Option<&mut HashMap<Bytes, Bytes>>, DbError> {
+        match self.live(key) {
+            Some(Entry {
+                value: Value::Hash(hash),
+                ..
+            }) => Ok(Some(hash)),
+            Some(_) => Err(DbError::WrongType),
+            None => Ok(None),
+        }
+    }
+
+    fn hash_or_insert(&mut self, key: &Bytes) -> Result<&mut HashMap<Bytes, Bytes>, DbError> {
+        if self.live(key).is_none() {
+            self.insert(
+                key.clone(),
+                Entry {
+                    value: Value::Hash(HashMap::new()),
+                    expires_at: None,
+                },
+            );
+        }
+        self.hash_mut(key)?.ok_or(DbError::WrongType)
+    }
+}
@@ -0,0 +1,384 @@
+// A Hierarchical Navigable Small World graph for approximate nearest neighbour search, as
+// used by Redis' vector sets. Every node sits on layer 0 and on each layer above it with
+// probability 1/M; searches walk greedily down from the entry point on the top layer and
+// then explore layer 0 best first. Links are kept in both directions so a removed node can
+// be unhooked from its neighbours, which are then relinked among themselves.
+//
+// Vectors are normalized before they get here, so cosine distance is 1 - dot product.
+use super::vset::Quantization;
+use std::cmp::{Ordering, Reverse};
+use std::collections::{BinaryHeap, HashSet};
+
+const MAX_LEVEL: usize = 16;
+
+// A normalized vector, quantized the way its set asks for
+pub(super) enum Vector {
+    F32(Vec<f32>),
+    // Each component is value * 127 / scale, where scale is the largest magnitude
+    Q8 { values: Vec<i8>, scale: f32 },
+    // One sign bit per component, set when it is positive
+    Bin { bits: Vec<u64>, dim: usize },
+}
+
+impl Vector {
+    pub(super) fn new(values: &[f32], quantization: Quantization) -> Vector {
+        match quantization {
+            Quantization::NoQuant => Vector::F32(values.to_vec()),
+            Quantization::Q8 => {
+                let scale = values.iter().fold(0.0f32, |max, v| max.max(v.abs()));
+                let values = values
+                    .iter()
+                    .map(|v| match scale {
+                        0.0 => 0,
+                        _ => (v / scale * 127.0).round() as i8,
+                    })
+                    .collect();
+                Vector::Q8 { values, scale }
+            }
+            Quantization::Bin => {
+                let mut bits = vec![0u64; values.len().div_ceil(64)];
+                for (i, v) in values.iter().enumerate() {
+                    if *v > 0.0 {
+                        bits[i / 64] |= 1 << (i % 64);
+                    }
+                }
+                Vector::Bin {
+                    bits,
+                    dim: values.len(),
+                }
+            }
+        }
+    }
+
+    // Both vectors come from the same set, so share a quantization
+    fn dot(&self, other: &Vector) -> f32 {
+        match (self, other) {
+            (Vector::F32(a), Vector::F32(b)) => a.iter().zip(b).map(|(x, y)| x * y).sum(),
+            (
+                Vector::Q8 {
+                    values: a,
+                    scale: sa,
+                },
+                Vector::Q8 {
+                    values: b,
+                    scale: sb,
+                },
+            ) => {
+                let dot: i64 = a.iter().zip(b).map(|(&x, &y)| x as i64 * y as i64).sum();
+                dot as f32 * (sa / 127.0) * (sb / 127.0)
+            }
+            (Vector::Bin { bits: a, dim }, Vector::Bin { bits: b, .. }) => {
+                let differing: u32 = a.iter().zip(b).map(|(x, y)| (x ^ y).count_ones()).sum();
+                (*dim as f32 - 2.0 * differing as f32) / *dim as f32
+            }
+            _ => 0.0,
+        }
+    }
+
+    pub(super) fn distance(&self, other: &Vector) -> f32 {
+        1.0 - self.dot(other)
+    }
+
+    // The normalized vector this approximates
+    pub(super) fn to_f32(&self) -> Vec<f32> {
+        match self {
+            Vector::F32(values) => values.clone(),
+            Vector::Q8 { values, scale } => {
+                values.iter().map(|&v| v as f32 * scale / 127.0).collect()
+            }
+            Vector::Bin { bits, dim } => {
+                let unit = 1.0 / (*dim as f32).sqrt();
+                (0..*dim)
+                    .map(|i| match bits[i / 64] & (1 << (i % 64)) {
+                        0 => -unit,
+                        _ => unit,
+                    })
+                    .collect()
+            }
+        }
+    }
+}
+
+pub(super) struct Hnsw {
+    nodes: Vec<Option<Node>>,
+    free: Vec<usize>,
+    entry: Option<usize>,
+    // Links per node on layers above 0, which gets twice as many
+    m: usize,
+    ef_construction: usize,
+}
+
+struct Node {
+    vector: Vector,
+    // Neighbours on each layer the node is on, from layer 0 up
+    links: Vec<Vec<usize>>,
+}
+
+// Orders distances so they can go in a heap
+#[derive(Clone, Copy, PartialEq)]
+struct Distance(f32);
+
+impl Eq for Distance {}
+
+impl PartialOrd for Distance {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Distance {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0.total_cmp(&other.0)
+    }
+}
+
+impl Hnsw {
+    pub(super) fn new(m: usize, ef_construction: usize) -> Hnsw {
+        Hnsw {
+            nodes: Vec::new(),
+            free: Vec::new(),
+            entry: None,
+            m,
+            ef_construction,
+        }
+    }
+
+    pub(super) fn vector(&self, id: usize) -> &Vector {
+        &self.node(id).vector
+    }
+
+    fn node(&self, id: usize) -> &Node {
+        self.nodes[id].as_ref().expect("linked node exists")
+    }
+
+    fn node_mut(&mut self, id: usize) -> &mut Node {
+        self.nodes[id].as_mut().expect("linked node exists")
+    }
+
+    fn top_layer(&self, id: usize) -> usize {
+        self.node(id).links.len() - 1
+    }
+
+    fn max_links(&self, layer: usize) -> usize {
+        if layer == 0 { self.m * 2 } else { self.m }
+    }
+
+    fn random_level(&self) -> usize {
+        let scale = 1.0 / (self.m.max(2) as f64).ln();
+        let level = -rand::random::<f64>().max(f64::MIN_POSITIVE).ln() * scale;
+        (level as usize).min(MAX_LEVEL)
+    }
+
+    // Returns the new node's id
+    pub(super) fn insert(&mut self, vector: Vector) -> usize {
+        let level = self.random_level();
+        let mut links = vec![Vec::new(); level + 1];
+
+        if let Some(entry) = self.entry {
+            let top = self.top_layer(entry);
+            let mut nearest = vec![entry];
+            for layer in (level + 1..=top).rev() {
+                nearest = vec![self.greedy(&vector, nearest[0], layer)];
+            }
+            for layer in (0..=level.min(top)).rev() {
+                let found = self.search_layer(
+                    &vector,
+                    &nearest,
+                    self.ef_construction,
+                    layer,
+                    usize::MAX,
+                    |_| true,
+                );
+                links[layer] = found
+                    .iter()
+                    .take(self.max_links(layer))
+                    .map(|&(_, id)| id)
+                    .collect();
+                nearest = found.into_iter().map(|(_, id)| id).collect();
+            }
+        }
+
+        let id = match self.free.pop() {
+            Some(id) => id,
+            None => {
+                self.nodes.push(None);
+                self.nodes.len() - 1
+            }
+        };
+        self.nodes[id] = Some(Node {
+            vector,
+            links: links.clone(),
+        });
+        for (layer, neighbours) in links.into_iter().enumerate() {
+            for neighbour in neighbours {
+                self.link(neighbour, id, layer);
+            }
+        }
+
+        if self.entry.is_none_or(|entry| level > self.top_layer(entry)) {
+            self.entry = Some(id);
+        }
+        id
+    }
+
+    // Links `from` back to `to`, dropping `from`'s farthest links, both ways, if it has
+    // too many
+    fn link(&mut self, from: usize, to: usize, layer: usize) {
+        self.node_mut(from).links[layer].push(to);
+        let max = self.max_links(layer);
+        if self.node(from).links[layer].len() <= max {
+            return;
+        }
+
+        let node = self.node(from);
+        let mut ranked: Vec<(Distance, usize)> = node.links[layer]
+            .iter()
+            .map(|&n| (Distance(node.vector.distance(self.vector(n))), n))
+            .collect();
+        ranked.sort();
+        let dropped: Vec<usize> = ranked[max..].iter().map(|&(_, n)| n).collect();
+        self.node_mut(from).links[layer] = ranked[..max].iter().map(|&(_, n)| n).collect();
+        for n in dropped {
+            self.node_mut(n).links[layer].retain(|&l| l != from);
+        }
+    }
+
+    pub(super) fn remove(&mut self, id: usize) {
+        let node = self.nodes[id].take().expect("removed node exists");
+        self.free.push(id);
+
+        for (layer, neighbours) in node.links.iter().enumerate() {
+            for &n in neighbours {
+                self.node_mut(n).links[layer].retain(|&l| l != id);
+            }
+            // Give each orphaned neighbour the closest of the others that still have room
+            for &n in neighbours {
+                let mut candidates: Vec<(Distance, usize)> = neighbours
+                    .iter()
+                    .filter(|&&c| c != n && !self.node(n).links[layer].contains(&c))
+                    .map(|&c| (Distance(self.vector(n).distance(self.vector(c))), c))
+                    .collect();
+                candidates.sort();
+                for (_, c) in candidates {
+                    let max = self.max_links(layer);
+                    if self.node(n).links[layer].len() >= max {
+                        break;
+                    }
+                    if self.node(c).links[layer].len() < max {
+                        self.node_mut(n).links[layer].push(c);
+                        self.node_mut(c).links[layer].push(n);
+                    }
+                }
+            }
+        }
+
+        if self.entry == Some(id) {
+            self.entry = (0..self.nodes.len())
+                .filter(|&i| self.nodes[i].is_some())
+                .max_by_key(|&i| self.top_layer(i));
+        }
+    }
+
+    // The `count` nodes nearest to `query` that pass `accept`, closest first. At most
+    // `effort` nodes are visited looking for them.
+    pub(super) fn search(
+        &self,
+        query: &Vector,
+        count: usize,
+        ef: usize,
+        effort: usize,
+        accept: impl Fn(usize) -> bool,
+    ) -> Vec<(f32, usize)> {
+        let Some(entry) = self.entry else {
+            return Vec::new();
+        };
+        let mut nearest = entry;
+        for layer in (1..=self.top_layer(entry)).rev() {
+            nearest = self.greedy(query, nearest, layer);
+        }
+        let mut found = self.search_layer(query, &[nearest], ef.max(count), 0, effort, accept);
+        found.truncate(count);
+        found.into_iter().map(|(d, id)| (d.0, id)).collect()
+    }
+
+    // Every node passing `accept` by distance, for checking the graph's answers
+    pub(super) fn scan(
+        &self,
+        query: &Vector,
+        count: usize,
+        accept: impl Fn(usize) -> bool,
+    ) -> Vec<(f32, usize)> {
+        let mut found: Vec<(Distance, usize)> = (0..self.nodes.len())
+            .filter(|&id| self.nodes[id].is_some() && accept(id))
+            .map(|id| (Distance(query.distance(self.vector(id))), id))
+            .collect();
+        found.sort();
+        found.truncate(count);
+        found.into_iter().map(|(d, id)| (d.0, id)).collect()
+    }
+
+    // Follows the closest link on one layer until no neighbour is nearer
+    fn greedy(&self, query: &Vector, start: usize, layer: usize) -> usize {
+        let mut current = start;
+        let mut best = query.distance(self.vector(current));
+        loop {
+            let closer = self.node(current).links[layer]
+                .iter()
+                .map(|&n| (query.distance(self.vector(n)), n))
+                .filter(|&(d, _)| d < best)
+                .min_by(|a, b| a.0.total_cmp(&b.0));
+            match closer {
+                Some((d, n)) => (best, current) = (d, n),
+                None => return current,
+            }
+        }
+    }
+
+    // Best first search of one layer, returning up to `ef` accepted nodes closest first
+    fn search_layer(
+        &self,
+        query: &Vector,
+        entries: &[usize],
+        ef: usize,
+        layer: usize,
+        effort: usize,
+        accept: impl Fn(usize) -> bool,
+    ) -> Vec<(Distance, usize)> {
+        let mut visited: HashSet<usize> = entries.iter().copied().collect();
+        let mut candidates = BinaryHeap::new();
+        let mut results: BinaryHeap<(Distance, usize)> = BinaryHeap::new();
+        for &id in entries {
+            let d = Distance(query.distance(self.vector(id)));
+            candidates.push(Reverse((d, id)));
+            if accept(id) {
+                results.push((d, id));
+            }
+        }
+        while results.len() > ef {
+            results.pop();
+        }
+
+        while let Some(Reverse((d, id))) = candidates.pop() {
+            if results.len() >= ef && results.peek().is_some_and(|worst| d > worst.0) {
+                break;
+            }
+            for &n in &self.node(id).links[layer] {
+                if visited.len() >= effort || !visited.insert(n) {
+                    continue;
+                }
+                let dn = Distance(query.distance(self.vector(n)));
+                let worst = results.peek().map(|w| w.0);
+                if results.len() < ef || worst.is_some_and(|w| dn < w) {
+                    candidates.push(Reverse((dn, n)));
+                    if accept(n) {
+                        results.push((dn, n));
+                        if results.len() > ef {
+                            results.pop();
+                        }
+                    }
+                }
+            }
+        }
+        results.into_sorted_vec()
+    }
+}
@@ -0,0 +1,550 @@
+// HyperLogLog cardinality estimation, stored as a string in the same `HYLL` format Redis uses
+// so the raw value can be moved between padis and Redis with GET and SET. A 16 byte header is
+// followed by either 16384 packed 6 bit registers (dense, 12 KB) or a run-length encoding of
+// them (sparse), which small HLLs use until they outgrow it.
+//
+// Like Redis, PFADD changes the stored value in place: a dense register is rewritten where it's
+// packed, and a sparse one by splitting just the opcode that covers it. PFCOUNT answers from the
+// cardinality cached in the header while it's still valid, and PFMERGE and counts over several
+// keys work on every register decoded.
+use super::{Class, Db, DbError, Entry, State, Value};
+use bytes::Bytes;
+
+const P: u32 = 14;
+const REGISTERS: usize = 1 << P;
+const Q: u32 = 64 - P;
+const BITS: usize = 6;
+const REGISTER_MAX: u16 = (1 << BITS) - 1;
+const HEADER_LEN: usize = 16;
+const DENSE_LEN: usize = HEADER_LEN + (REGISTERS * BITS).div_ceil(8);
+const MAGIC: &[u8] = b"HYLL";
+const DENSE: u8 = 0;
+const SPARSE: u8 = 1;
+// Redis' default hll-sparse-max-bytes
+const SPARSE_MAX_BYTES: usize = 3000;
+const SPARSE_VAL_MAX: u8 = 32;
+const SEED: u64 = 0xadc83b19;
+const ALPHA_INF: f64 = 0.721_347_520_444_481_7;
+
+// Sparse opcodes: ZERO is 00xxxxxx, XZERO is 01xxxxxx yyyyyyyy, VAL is 1vvvvvxx
+const XZERO: u8 = 0x40;
+const VAL: u8 = 0x80;
+const ZERO_MAX_LEN: usize = 64;
+const XZERO_MAX_LEN: usize = 16384;
+const VAL_MAX_LEN: usize = 4;
+
+struct Hll {
+    header: [u8; HEADER_LEN],
+    registers: Vec<u8>,
+}
+
+impl Hll {
+    fn new() -> Hll {
+        let mut header = [0; HEADER_LEN];
+        header[..4].copy_from_slice(MAGIC);
+        header[4] = SPARSE;
+        Hll {
+            header,
+            registers: vec![0; REGISTERS],
+        }
+    }
+
+    fn decode(bytes: &[u8]) -> Result<Hll, DbError> {
+        check(bytes)?;
+        let mut header = [0; HEADER_LEN];
+        header.copy_from_slice(&bytes[..HEADER_LEN]);
+        Ok(Hll {
+            header,
+            registers: registers(bytes)?,
+        })
+    }
+
+    // Sparse HLLs switch to dense for good once a register won't fit or they get too big
+    fn encode(&mut self) -> Bytes {
+        if self.header[4] == SPARSE {
+            if let Some(body) = sparse_encode(&self.registers) {
+                return [&self.header[..], &body].concat().into();
+            }
+            self.header[4] = DENSE;
+        }
+
+        let mut out = vec![0; DENSE_LEN];
+        out[..HEADER_LEN].copy_from_slice(&self.header);
+        for (i, &value) in self.registers.iter().enumerate() {
+            dense_set(&mut out[HEADER_LEN..], i, value);
+        }
+        out.into()
+    }
+
+    fn is_dense(&self) -> bool {
+        self.header[4] == DENSE
+    }
+
+    fn invalidate(&mut self) {
+        invalidate(&mut self.header);
+    }
+
+    fn merge(&mut self, other: &Hll) {
+        for (reg, &theirs) in self.registers.iter_mut().zip(&other.registers) {
+            *reg = (*reg).max(theirs);
+        }
+    }
+}
+
+// Whether a string is a HYLL value. Like in Redis a sparse body is only found to be corrupt
+// when it's read.
+fn check(hll: &[u8]) -> Result<(), DbError> {
+    if hll.len() < HEADER_LEN || &hll[..4] != MAGIC {
+        return Err(DbError::InvalidHll);
+    }
+    match hll[4] {
+        DENSE if hll.len() == DENSE_LEN => Ok(()),
+        SPARSE => Ok(()),
+        _ => Err(DbError::InvalidHll),
+    }
+}
+
+fn is_dense(hll: &[u8]) -> bool {
+    hll[4] == DENSE
+}
+
+// The cardinality is cached in the header, little endian, with the top bit marking it stale
+fn cached(hll: &[u8]) -> Option<u64> {
+    if hll[15] & 0x80 != 0 {
+        return None;
+    }
+    let mut card = [0; 8];
+    card.copy_from_slice(&hll[8..16]);
+    Some(u64::from_le_bytes(card))
+}
+
+fn set_cached(hll: &mut [u8], card: u64) {
+    hll[8..16].copy_from_slice(&card.to_le_bytes());
+}
+
+fn invalidate(hll: &mut [u8]) {
+    hll[15] |= 0x80;
+}
+
+// Every register of a checked value
+fn registers(hll: &[u8]) -> Result<Vec<u8>, DbError> {
+    let body = &hll[HEADER_LEN..];
+    match is_dense(hll) {
+        true => Ok((0..REGISTERS).map(|i| dense_get(body, i)).collect()),
+        false => sparse_decode(body).ok_or(DbError::CorruptHll),
+    }
+}
+
+// How many registers hold each value, all the estimate needs. Sparse values are counted a run
+// at a time.
+fn histogram(hll: &[u8]) -> Result<[u32; 64], DbError> {
+    let mut histogram = [0u32; 64];
+    let body = &hll[HEADER_LEN..];
+    if is_dense(hll) {
+        for i in 0..REGISTERS {
+            histogram[usize::from(dense_get(body, i))] += 1;
+        }
+        return Ok(histogram);
+    }
+    let mut pos = 0;
+    let mut seen = 0;
+    while pos < body.len() {
+        let (value, len, size) = sparse_op(body, pos).ok_or(DbError::CorruptHll)?;
+        histogram[usize::from(value)] += len as u32;
+        seen += len;
+        pos += size;
+    }
+    match seen {
+        REGISTERS => Ok(histogram),
+        _ => Err(DbError::CorruptHll),
+    }
+}
+
+// Raises the register to `count` if it's lower, returning whether it changed. A sparse value
+// that can't hold the count, or outgrows the sparse limit, is made dense first.
+fn set_register(hll: &mut Vec<u8>, index: usize, count: u8) -> Result<bool, DbError> {
+    if !is_dense(hll) {
+        if usize::from(count) <= usize::from(SPARSE_VAL_MAX) {
+            let changed = sparse_set(hll, index, count)?;
+            if hll.len() <= SPARSE_MAX_BYTES {
+                return Ok(changed);
+            }
+        }
+        promote(hll)?;
+        if usize::from(count) <= usize::from(SPARSE_VAL_MAX) {
+            return Ok(true);
+        }
+    }
+    let body = &mut hll[HEADER_LEN..];
+    if dense_get(body, index) >= count {
+        return Ok(false);
+    }
+    dense_set(body, index, count);
+    Ok(true)
+}
+
+// Rewrites a sparse value as dense, keeping its header
+fn promote(hll: &mut Vec<u8>) -> Result<(), DbError> {
+    let registers = registers(hll)?;
+    hll.truncate(HEADER_LEN);
+    hll.resize(DENSE_LEN, 0);
+    hll[4] = DENSE;
+    for (i, &value) in registers.iter().enumerate() {
+        dense_set(&mut hll[HEADER_LEN..], i, value);
+    }
+    Ok(())
+}
+
+fn dense_get(body: &[u8], index: usize) -> u8 {
+    let bit = index * BITS;
+    let (byte, fb) = (bit / 8, bit % 8);
+    let b0 = u16::from(body[byte]);
+    let b1 = u16::from(body.get(byte + 1).copied().unwrap_or(0));
+    (((b0 >> fb) | (b1 << (8 - fb))) & REGISTER_MAX) as u8
+}
+
+fn dense_set(body: &mut [u8], index: usize, value: u8) {
+    let bit = index * BITS;
+    let (byte, fb) = (bit / 8, bit % 8);
+    let value = u16::from(value);
+    body[byte] &= !((REGISTER_MAX << fb) as u8);
+    body[byte] |= (value << fb) as u8;
+    // The last register ends exactly on a byte boundary
+    if let Some(next) = body.get_mut(byte + 1) {
+        *next &= !((REGISTER_MAX >> (8 - fb)) as u8);
+        *next |= (value >> (8 - fb)) as u8;
+    }
+}
+
+// The value, run length and size in bytes of the opcode at `pos`
+fn sparse_op(body: &[u8], pos: usize) -> Option<(u8, usize, usize)> {
+    let op = body[pos];
+    if op & 0xc0 == 0 {
+        Some((0, usize::from(op & 0x3f) + 1, 1))
+    } else if op & 0xc0 == XZERO {
+        let low = *body.get(pos + 1)?;
+        Some((0, (usize::from(op & 0x3f) << 8 | usize::from(low)) + 1, 2))
+    } else {
+        Some((((op >> 2) & 0x1f) + 1, usize::from(op & 0x3) + 1, 1))
+    }
+}
+
+fn sparse_decode(body: &[u8]) -> Option<Vec<u8>> {
+    let mut registers = Vec::with_capacity(REGISTERS);
+    let mut pos = 0;
+    while pos < body.len() {
+        let (value, len, size) = sparse_op(body, pos)?;
+        registers.resize(registers.len() + len, value);
+        pos += size;
+        if registers.len() > REGISTERS {
+            return None;
+        }
+    }
+    (registers.len() == REGISTERS).then_some(registers)
+}
+
+// None when the registers can't be, or shouldn't be, sparse
+fn sparse_encode(registers: &[u8]) -> Option<Vec<u8>> {
+    let mut out = Vec::new();
+    let mut i = 0;
+    while i < registers.len() {
+        let value = registers[i];
+        let run = registers[i..].iter().take_while(|&&r| r == value).count();
+        i += run;
+        if value > SPARSE_VAL_MAX {
+            return None;
+        }
+        push_run(&mut out, value, run);
+    }
+    (HEADER_LEN + out.len() <= SPARSE_MAX_BYTES).then_some(out)
+}
+
+// The opcodes for `len` registers holding `value`, which must fit a VAL opcode
+fn push_run(out: &mut Vec<u8>, value: u8, mut len: usize) {
+    while len > 0 {
+        if value == 0 && len > ZERO_MAX_LEN {
+            let run = len.min(XZERO_MAX_LEN);
+            out.push(XZERO | ((run - 1) >> 8) as u8);
+            out.push(((run - 1) & 0xff) as u8);
+            len -= run;
+        } else if value == 0 {
+            out.push((len - 1) as u8);
+            len = 0;
+        } else {
+            let run = len.min(VAL_MAX_LEN);
+            out.push(VAL | ((value - 1) << 2) | (run - 1) as u8);
+            len -= run;
+        }
+    }
+}
+
+// Sets one register of a sparse value to `count`, no more than SPARSE_VAL_MAX, if it's lower.
+// The opcode covering the register is split into the runs before and after it around a VAL for
+// it, which then merges with VALs next to it holding the same value.
+fn sparse_set(hll: &mut Vec<u8>, index: usize, count: u8) -> Result<bool, DbError> {
+    let body = &hll[HEADER_LEN..];
+    let (mut pos, mut prev, mut first) = (0, None, 0);
+    let (value, len, size) = loop {
+        if pos >= body.len() {
+            return Err(DbError::CorruptHll);
+        }
+        let (value, len, size) = sparse_op(body, pos).ok_or(DbError::CorruptHll)?;
+        if index < first + len {
+            break (value, len, size);
+        }
+        prev = Some(pos);
+        first += len;
+        pos += size;
+    };
+    if value >= count {
+        return Ok(false);
+    }
+
+    let mut ops = Vec::with_capacity(5);
+    push_run(&mut ops, value, index - first);
+    push_run(&mut ops, count, 1);
+    push_run(&mut ops, value, first + len - index - 1);
+    let at = HEADER_LEN + pos;
+    hll.splice(at..at + size, ops);
+    sparse_merge(hll, HEADER_LEN + prev.unwrap_or(pos));
+    Ok(true)
+}
+
+// Joins neighbouring VALs of the same value from `pos` on, as far as the few opcodes a change
+// can have split
+fn sparse_merge(hll: &mut Vec<u8>, mut pos: usize) {
+    for _ in 0..5 {
+        let (Some(&op), Some(&next)) = (hll.get(pos), hll.get(pos + 1)) else {
+            return;
+        };
+        let same_value = op & VAL != 0 && next & VAL != 0 && (op ^ next) & 0x7c == 0;
+        let len = usize::from(op & 0x3) + usize::from(next & 0x3) + 2;
+        if same_value && len <= VAL_MAX_LEN {
+            hll[pos] = (op & !0x3) | (len - 1) as u8;
+            hll.remove(pos + 1);
+            continue;
+        }
+        pos += if op & 0xc0 == XZERO { 2 } else { 1 };
+    }
+}
+
+// The register an element lands in and the run of zeros its hash starts with, plus one
+fn hash_element(element: &[u8]) -> (usize, u8) {
+    let hash = murmur_hash64a(element, SEED);
+    let index = (hash & (REGISTERS as u64 - 1)) as usize;
+    // Setting bit Q guarantees the loop Redis uses terminates, so the count is at most Q + 1
+    let hash = (hash >> P) | (1 << Q);
+    (index, hash.trailing_zeros() as u8 + 1)
+}
+
+// MurmurHash64A as Redis uses it, reading 8 byte blocks little endian
+pub(super) fn murmur_hash64a(key: &[u8], seed: u64) -> u64 {
+    const M: u64 = 0xc6a4a7935bd1e995;
+    const R: u32 = 47;
+
+    let mut h = seed ^ (key.len() as u64).wrapping_mul(M);
+    let mut chunks = key.chunks_exact(8);
+    for chunk in &mut chunks {
+        let mut k = u64::from_le_bytes(chunk.try_into().unwrap_or_default());
+        k = k.wrapping_mul(M);
+        k ^= k >> R;
+        k = k.wrapping_mul(M);
+        h ^= k;
+        h = h.wrapping_mul(M);
+    }
+
+    let tail = chunks.remainder();
+    if !tail.is_empty() {
+        for (i, &byte) in tail.iter().enumerate() {
+            h ^= u64::from(byte) << (8 * i);
+        }
+        h = h.wrapping_mul(M);
+    }
+
+    h ^= h >> R;
+    h = h.wrapping_mul(M);
+    h ^= h >> R;
+    h
+}
+
+// Otmar Ertl's improved estimator, which Redis has used since 5.0
+fn estimate(registers: &[u8]) -> u64 {
+    let mut histogram = [0u32; 64];
+    for &reg in registers {
+        histogram[usize::from(reg)] += 1;
+    }
+    estimate_histogram(&histogram)
+}
+
+fn estimate_histogram(histogram: &[u32; 64]) -> u64 {
+    let m = REGISTERS as f64;
+    let q = Q as usize;
+    let mut z = m * tau((m - f64::from(histogram[q + 1])) / m);
+    for &count in histogram[1..=q].iter().rev() {
+        z += f64::from(count);
+        z *= 0.5;
+    }
+    z += m * sigma(f64::from(histogram[0]) / m);
+    (ALPHA_INF * m * m / z).round() as u64
+}
+
+fn sigma(mut x: f64) -> f64 {
+    if x == 1.0 {
+        return f64::INFINITY;
+    }
+    let mut y = 1.0;
+    let mut z = x;
+    loop {
+        x *= x;
+        let prev = z;
+        z += x * y;
+        y += y;
+        if prev == z {
+            return z;
+        }
+    }
+}
+
+fn tau(mut x: f64) -> f64 {
+    if x == 0.0 || x == 1.0 {
+        return 0.0;
+    }
+    let mut y = 1.0;
+    let mut z = 1.0 - x;
+    loop {
+        x = x.sqrt();
+        let prev = z;
+        y *= 0.5;
+        z -= (1.0 - x).powi(2) * y;
+        if prev == z {
+            return z / 3.0;
+        }
+    }
+}
+
+impl Db {
+    // Returns true if the estimate may have changed, including when the key was created
+    pub fn pfadd(&self, key: &Bytes, elements: &[Bytes]) -> Result<bool, DbError> {
+        let mut state = self.lock();
+        let created = state.hll_value(key)?.is_none();
+        if created {
+            state.store_hll(key, &mut Hll::new());
+        }
+        let Some(value) = state.hll_value(key)? else {
+            unreachable!("the key was just stored");
+        };
+        let (changed, result) = edit(value, |hll| {
+            let mut changed = created;
+            for element in elements {
+                let (index, count) = hash_element(element);
+                match set_register(hll, index, count) {
+                    Ok(set) => changed |= set,
+                    Err(e) => return (changed, Err(e)),
+                }
+            }
+            if changed {
+                invalidate(hll);
+            }
+            (changed, Ok(changed))
+        });
+        if changed {
+            state.notify(Class::String, "pfadd", key);
+        }
+        result
+    }
+
+    // The estimated size of the union of the keys. A single key caches its estimate.
+    pub fn pfcount(&self, keys: &[Bytes]) -> Result<u64, DbError> {
+        let mut state = self.lock_read();
+        if let [key] = keys {
+            let Some(value) = state.hll_value(key)? else {
+                return Ok(0);
+            };
+            if let Some(card) = cached(value) {
+                return Ok(card);
+            }
+            let card = estimate_histogram(&histogram(value)?);
+            edit(value, |hll| set_cached(hll, card));
+            return Ok(card);
+        }
+
+        let mut union = Hll::new();
+        for key in keys {
+            if let Some(hll) = state.hll(key)? {
+                union.merge(&hll);
+            }
+        }
+        Ok(estimate(&union.registers))
+    }
+
+    // Merges the sources into `dest`, which counts as a source too if it exists. The result is
+    // dense if any input was.
+    pub fn pfmerge(&self, dest: &Bytes, sources: &[Bytes]) -> Result<(), DbError> {
+        let mut state = self.lock();
+        let mut merged = state.hll(dest)?.unwrap_or_else(Hll::new);
+        let mut dense = merged.is_dense();
+        for key in sources {
+            if let Some(hll) = state.hll(key)? {
+                dense |= hll.is_dense();
+                merged.merge(&hll);
+            }
+        }
+
+        if dense {
+            merged.header[4] = DENSE;
+        }
+        merged.invalidate();
+        state.store_hll(dest, &mut merged);
+        state.notify(Class::String, "pfadd", dest);
+        Ok(())
+    }
+}
+
+// Changes a value in place, which only copies it if a reply still holds it
+fn edit<R>(value: &mut Bytes, f: impl FnOnce(&mut Vec<u8>) -> R) -> R {
+    let mut hll = Vec::from(std::mem::take(value));
+    let result = f(&mut hll);
+    *value = Bytes::from(hll);
+    result
+}
+
+impl State {
+    fn hll(&mut self, key: &Bytes) -> Result<Option<Hll>, DbError> {
+        match self.hll_value(key)? {
+            Some(value) => Hll::decode(value).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    // The string at the key, once its header shows it's a HYLL value
+    fn hll_value(&mut self, key: &Bytes) -> Result<Option<&mut Bytes>, DbError> {
+        match self.live(key) {
+            Some(Entry {
+                value: Value::String(s),
+                ..
+            }) => {
+                check(s)?;
+                Ok(Some(s))
+            }
+            Some(_) => Err(DbError::WrongType),
+            None => Ok(None),
+        }
+    }
+
+    // Keeps any expiry the key already had
+    fn store_hll(&mut self, key: &Bytes, hll: &mut Hll) {
+        let value = Value::String(hll.encode());
+        match self.live(key) {
+            Some(entry) => entry.value = value,
+            None => {
+                self.insert(
+                    key.clone(),
+                    Entry {
+                        value,
+                        expires_at: None,
+                    },
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,470 @@
+// JSON documents, following RedisJSON. Commands find their targets with a JsonPath and
+// modify the document in place, so a single field can be patched without rewriting the rest.
+use super::jsonpath::{JsonPath, Step};
+use super::{Class, Db, DbError, Entry, State, Value};
+use bytes::Bytes;
+use serde_json::{Number, Value as Json};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum JsonCondition {
+    Nx,
+    Xx,
+}
+
+// JSON.GET's INDENT, NEWLINE and SPACE; all empty is compact
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct JsonFormat {
+    pub indent: String,
+    pub newline: String,
+    pub space: String,
+}
+
+impl JsonFormat {
+    pub fn format(&self, value: &Json) -> String {
+        let mut out = String::new();
+        self.write(value, 0, &mut out);
+        out
+    }
+
+    fn write(&self, value: &Json, depth: usize, out: &mut String) {
+        match value {
+            Json::Array(items) if !items.is_empty() => {
+                out.push('[');
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        out.push(',');
+                    }
+                    self.line(depth + 1, out);
+                    self.write(item, depth + 1, out);
+                }
+                self.line(depth, out);
+                out.push(']');
+            }
+            Json::Object(map) if !map.is_empty() => {
+                out.push('{');
+                for (i, (key, item)) in map.iter().enumerate() {
+                    if i > 0 {
+                        out.push(',');
+                    }
+                    self.line(depth + 1, out);
+                    out.push_str(&Json::String(key.clone()).to_string());
+                    out.push(':');
+                    out.push_str(&self.space);
+                    self.write(item, depth + 1, out);
+                }
+                self.line(depth, out);
+                out.push('}');
+            }
+            _ => out.push_str(&value.to_string()),
+        }
+    }
+
+    fn line(&self, depth: usize, out: &mut String) {
+        out.push_str(&self.newline);
+        for _ in 0..depth {
+            out.push_str(&self.indent);
+        }
+    }
+}
+
+// The names JSON.TYPE replies with
+fn type_name(value: &Json) -> &'static str {
+    match value {
+        Json::Null => "null",
+        Json::Bool(_) => "boolean",
+        Json::Number(n) if n.is_f64() => "number",
+        Json::Number(_) => "integer",
+        Json::String(_) => "string",
+        Json::Array(_) => "array",
+        Json::Object(_) => "object",
+    }
+}
+
+fn at_mut<'a>(root: &'a mut Json, at: &[Step]) -> Option<&'a mut Json> {
+    at.iter().try_fold(root, |value, step| match step {
+        Step::Key(key) => value.get_mut(key.as_str()),
+        Step::Index(index) => value.get_mut(*index),
+    })
+}
+
+// Integers stay integers unless the sum overflows
+fn add_numbers(a: &Number, b: &Number) -> Result<Number, DbError> {
+    if let (Some(a), Some(b)) = (a.as_i64(), b.as_i64())
+        && let Some(sum) = a.checked_add(b)
+    {
+        return Ok(sum.into());
+    }
+    let sum = a.as_f64().unwrap_or(f64::NAN) + b.as_f64().unwrap_or(f64::NAN);
+    Number::from_f64(sum).ok_or(DbError::JsonNotANumber)
+}
+
+// Python style bounds: negative counts from the end, then clamped to the array
+fn clamp_index(index: i64, len: usize) -> usize {
+    let len = len as i64;
+    (if index < 0 { len + index } else { index }).clamp(0, len) as usize
+}
+
+impl Db {
+    // Returns false if the NX/XX condition failed or there was nowhere to put the value
+    pub fn json_set(
+        &self,
+        key: &Bytes,
+        path: &JsonPath,
+        value: Json,
+        condition: Option<JsonCondition>,
+    ) -> Result<bool, DbError> {
+        let mut state = self.lock();
+        let Some(doc) = state.json_mut(key)? else {
+            if !path.is_root() {
+                return Err(DbError::JsonNewAtRoot);
+            }
+            if condition == Some(JsonCondition::Xx) {
+                return Ok(false);
+            }
+            state.insert(
+                key.clone(),
+                Entry {
+                    value: Value::Json(value),
+                    expires_at: None,
+                },
+            );
+            state.notify(Class::Generic, "json.set", key);
+            return Ok(true);
+        };
+
+        let locations = path.locate(doc);
+        if !locations.is_empty() {
+            if condition == Some(JsonCondition::Nx) {
+                return Ok(false);
+            }
+            for at in locations {
+                if let Some(target) = at_mut(doc, &at) {
+                    *target = value.clone();
+                }
+            }
+            state.notify(Class::Generic, "json.set", key);
+            return Ok(true);
+        }
+
+        if condition == Some(JsonCondition::Xx) {
+            return Ok(false);
+        }
+        let Some((parent, name)) = path.parent() else {
+            return Ok(false);
+        };
+        let mut created = false;
+        for at in parent.locate(doc) {
+            if let Some(Json::Object(map)) = at_mut(doc, &at) {
+                map.insert(name.to_string(), value.clone());
+                created = true;
+            }
+        }
+        if created {
+            state.notify(Class::Generic, "json.set", key);
+        }
+        Ok(created)
+    }
+
+    // The document itself with no paths. Legacy paths give their first match and JSONPaths
+    // an array of matches; several paths give an object keyed by path.
+    pub fn json_get(&self, key: &Bytes, paths: &[JsonPath]) -> Result<Option<Json>, DbError> {
+        let mut state = self.lock_read();
+        let Some(doc) = state.json_mut(key)? else {
+            return Ok(None);
+        };
+        let doc = &*doc;
+        if paths.is_empty() {
+            return Ok(Some(doc.clone()));
+        }
+
+        let legacy = paths.iter().all(JsonPath::is_legacy);
+        let result = |path: &JsonPath| {
+            let matches = path.select(doc);
+            if legacy {
+                matches
+                    .first()
+                    .map(|&value| value.clone())
+                    .ok_or_else(|| DbError::JsonNoPath(path.to_string()))
+            } else {
+                Ok(Json::Array(matches.into_iter().cloned().collect()))
+            }
+        };
+
+        if let [path] = paths {
+            return result(path).map(Some);
+        }
+        let mut results = serde_json::Map::new();
+        for path in paths {
+            results.insert(path.to_string(), result(path)?);
+        }
+        Ok(Some(Json::Object(results)))
+    }
+
+    // Missing keys, and keys that aren't JSON, give None
+    pub fn json_mget(&self, keys: &[Bytes], path: &JsonPath) -> Vec<Option<Json>> {
+        let mut state = self.lock_read();
+        keys.iter()
+            .map(|key| {
+                let doc = state.json_mut(key).ok().flatten()?;
+                let matches = path.select(doc);
+                if path.is_legacy() {
+                    matches.first().map(|&value| value.clone())
+                } else {
+                    Some(Json::Array(matches.into_iter().cloned().collect()))
+                }
+            })
+            .collect()
+    }
+
+    // Deleting the root deletes the key
+    pub fn json_del(&self, key: &Bytes, path: &JsonPath) -> Result<usize, DbError> {
+        let mut state = self.lock();
+        let Some(doc) = state.json_mut(key)? else {
+            return Ok(0);
+        };
+        let mut locations = path.locate(doc);
+        if locations.iter().any(Vec::is_empty) {
+            state.entries.remove(key);
+            state.notify(Class::Generic, "json.del", key);
+            return Ok(1);
+        }
+
+        // Later array elements first, so earlier indices stay valid
+        locations.sort();
+        locations.dedup();
+        let mut deleted = 0;
+        for at in locations.iter().rev() {
+            let Some((last, parent)) = at.split_last() else {
+                continue;
+            };
+            let removed = match (at_mut(doc, parent), last) {
+                (Some(Json::Object(map)), Step::Key(key)) => map.shift_remove(key).is_some(),
+                (Some(Json::Array(items)), Step::Index(index)) if *index < items.len() => {
+                    items.remove(*index);
+                    true
+                }
+                _ => false,
+            };
+            deleted += removed as usize;
+        }
+        if deleted > 0 {
+            state.notify(Class::Generic, "json.del", key);
+        }
+        Ok(deleted)
+    }
+
+    // None for a missing key
+    pub fn json_type(
+        &self,
+        key: &Bytes,
+        path: &JsonPath,
+    ) -> Result<Option<Vec<&'static str>>, DbError> {
+        self.json_query(key, path, "any", |value| Some(type_name(value)))
+            .map(|types| types.map(|types| types.into_iter().flatten().collect()))
+    }
+
+    pub fn json_objkeys(
+        &self,
+        key: &Bytes,
+        path: &JsonPath,
+    ) -> Result<Option<Vec<Option<Vec<String>>>>, DbError> {
+        self.json_query(key, path, "object", |value| {
+            value.as_object().map(|map| map.keys().cloned().collect())
+        })
+    }
+
+    pub fn json_numincrby(
+        &self,
+        key: &Bytes,
+        path: &JsonPath,
+        by: &Number,
+    ) -> Result<Vec<Option<Number>>, DbError> {
+        self.json_update(key, path, "json.numincrby", "number", |value| match value {
+            Json::Number(n) => {
+                *n = add_numbers(n, by)?;
+                Ok(Some(n.clone()))
+            }
+            _ => Ok(None),
+        })
+    }
+
+    // Each result is the new string length
+    pub fn json_strappend(
+        &self,
+        key: &Bytes,
+        path: &JsonPath,
+        suffix: &str,
+    ) -> Result<Vec<Option<usize>>, DbError> {
+        self.json_update(key, path, "json.strappend", "string", |value| match value {
+            Json::String(s) => {
+                s.push_str(suffix);
+                Ok(Some(s.len()))
+            }
+            _ => Ok(None),
+        })
+    }
+
+    // Each result is the new array length
+    pub fn json_arrappend(
+        &self,
+        key: &Bytes,
+        path: &JsonPath,
+        values: &[Json],
+    ) -> Result<Vec<Option<usize>>, DbError> {
+        self.json_update(key, path, "json.arrappend", "array", |value| match value {
+            Json::Array(items) => {
+                items.extend_from_slice(values);
+                Ok(Some(items.len()))
+            }
+            _ => Ok(None),
+        })
+    }
+
+    // Inserts before index, where negative indices count from the end
+    pub fn json_arrinsert(
+        &self,
+        key: &Bytes,
+        path: &JsonPath,
+        index: i64,
+        values: &[Json],
+    ) -> Result<Vec<Option<usize>>, DbError> {
+        self.json_update(key, path, "json.arrinsert", "array", |value| match value {
+            Json::Array(items) => {
+                let len = items.len() as i64;
+                let at = if index < 0 { len + index } else { index };
+                if !(0..=len).contains(&at) {
+                    return Err(DbError::JsonIndexOutOfBounds);
+                }
+                let at = at as usize;
+                items.splice(at..at, values.iter().cloned());
+                Ok(Some(items.len()))
+            }
+            _ => Ok(None),
+        })
+    }
+
+    // Out of range indices pop the nearest end. Empty arrays and non-arrays give None.
+    pub fn json_arrpop(
+        &self,
+        key: &Bytes,
+        path: &JsonPath,
+        index: i64,
+    ) -> Result<Vec<Option<Json>>, DbError> {
+        let popped = self.json_update(key, path, "json.arrpop", "array", |value| match value {
+            Json::Array(items) if items.is_empty() => Ok(Some(None)),
+            Json::Array(items) => {
+                let at = clamp_index(index, items.len()).min(items.len() - 1);
+                Ok(Some(Some(items.remove(at))))
+            }
+            _ => Ok(None),
+        })?;
+        Ok(popped.into_iter().map(Option::flatten).collect())
+    }
+
+    // Keeps start..=stop, LTRIM style, and gives the new length
+    pub fn json_arrtrim(
+        &self,
+        key: &Bytes,
+        path: &JsonPath,
+        start: i64,
+        stop: i64,
+    ) -> Result<Vec<Option<usize>>, DbError> {
+        self.json_update(key, path, "json.arrtrim", "array", |value| match value {
+            Json::Array(items) => {
+                let start = clamp_index(start, items.len());
+                let end = clamp_index(stop, items.len())
+                    .saturating_add(1)
+                    .min(items.len());
+                if start >= end {
+                    items.clear();
+                } else {
+                    items.truncate(end);
+                    items.drain(..start);
+                }
+                Ok(Some(items.len()))
+            }
+            _ => Ok(None),
+        })
+    }
+
+    // Runs update on each match, where None marks a value of the wrong type. A legacy path
+    // only touches its first match, and errors instead of giving None. The event fires if
+    // anything was updated.
+    fn json_update<T>(
+        &self,
+        key: &Bytes,
+        path: &JsonPath,
+        event: &'static str,
+        expected: &'static str,
+        mut update: impl FnMut(&mut Json) -> Result<Option<T>, DbError>,
+    ) -> Result<Vec<Option<T>>, DbError> {
+        let mut state = self.lock();
+        let doc = state.json_mut(key)?.ok_or(DbError::JsonNoKey)?;
+        let mut locations = path.locate(doc);
+        if path.is_legacy() {
+            if locations.is_empty() {
+                return Err(DbError::JsonNoPath(path.to_string()));
+            }
+            locations.truncate(1);
+        }
+
+        let mut results = Vec::with_capacity(locations.len());
+        for at in locations {
+            let Some(value) = at_mut(doc, &at) else {
+                results.push(None);
+                continue;
+            };
+            let result = update(value)?;
+            if result.is_none() && path.is_legacy() {
+                return Err(DbError::JsonWrongType(expected, type_name(value)));
+            }
+            results.push(result);
+        }
+        if results.iter().any(Option::is_some) {
+            state.notify(Class::Generic, event, key);
+        }
+        Ok(results)
+    }
+
+    // Like json_update without modifying anything, and a missing key is None. A legacy path
+    // that matches nothing gives no results.
+    fn json_query<T>(
+        &self,
+        key: &Bytes,
+        path: &JsonPath,
+        expected: &'static str,
+        query: impl Fn(&Json) -> Option<T>,
+    ) -> Result<Option<Vec<Option<T>>>, DbError> {
+        let mut state = self.lock_read();
+        let Some(doc) = state.json_mut(key)? else {
+            return Ok(None);
+        };
+        let mut matches = path.select(doc);
+        if path.is_legacy() {
+            matches.truncate(1);
+        }
+
+        let mut results = Vec::with_capacity(matches.len());
+        for value in matches {
+            let result = query(value);
+            if result.is_none() && path.is_legacy() {
+                return Err(DbError::JsonWrongType(expected, type_name(value)));
+            }
+            results.push(result);
+        }
+        Ok(Some(results))
+    }
+}
+
+impl State {
+    fn json_mut(&mut self, key: &Bytes) -> Result<Option<&mut Json>, DbError> {
+        match self.live(key) {
+            Some(Entry {
+                value: Value::Json(doc),
+                ..
+            }) => Ok(Some(doc)),
+            Some(_) => Err(DbError::WrongType),
+            None => Ok(None),
+        }
+    }
+}
@@ -0,0 +1,495 @@
+// JSONPath as RedisJSON takes it. Paths starting with `$` are JSONPath proper and commands
+// reply with one result per match. Anything else is a legacy path like `.a.b` or `a[0]`,
+// where commands act on the first match and reply with that alone.
+//
+// Supported: `.name`, `['name']`, `*`, `[index]` (negative from the end), `[start:end:step]`,
+// unions like `[0,2]`, `..` descent, and filters like `[?(@.price < 10 && @.tag)]`.
+use serde_json::Value as Json;
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct JsonPath {
+    text: String,
+    segments: Vec<Segment>,
+    legacy: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Segment {
+    // `..` applies the selectors to every descendant as well
+    descendant: bool,
+    selectors: Vec<Selector>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Selector {
+    Name(String),
+    Wildcard,
+    Index(i64),
+    Slice {
+        start: Option<i64>,
+        end: Option<i64>,
+        step: i64,
+    },
+    // Alternatives of conditions that must all hold, so && binds tighter than ||
+    Filter(Vec<Vec<Condition>>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Condition {
+    // A path relative to @ that must match something
+    Exists(JsonPath),
+    Compare(Operand, Comparison, Operand),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Operand {
+    Path(JsonPath),
+    Literal(Json),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Comparison {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+// One step from a value to a child, so a match can be found again to modify it
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub(super) enum Step {
+    Key(String),
+    Index(usize),
+}
+
+impl JsonPath {
+    // None if the path doesn't parse
+    pub fn parse(text: &str) -> Option<JsonPath> {
+        let (legacy, body) = match text.strip_prefix('$') {
+            Some(body) => (false, body.to_string()),
+            None if text == "." => (true, String::new()),
+            None if text.starts_with('.') || text.starts_with('[') => (true, text.to_string()),
+            None => (true, format!(".{}", text)),
+        };
+
+        let mut parser = Parser {
+            bytes: body.as_bytes(),
+            pos: 0,
+        };
+        let segments = parser.segments()?;
+        if parser.pos != body.len() {
+            return None;
+        }
+        Some(JsonPath {
+            text: text.to_string(),
+            segments,
+            legacy,
+        })
+    }
+
+    // The legacy root path, which commands default to
+    pub fn root() -> JsonPath {
+        JsonPath {
+            text: ".".to_string(),
+            segments: Vec::new(),
+            legacy: true,
+        }
+    }
+
+    pub fn is_legacy(&self) -> bool {
+        self.legacy
+    }
+
+    pub(super) fn is_root(&self) -> bool {
+        self.segments.is_empty()
+    }
+
+    // Where to create the value when the path matches nothing: the path to the parent
+    // objects and the key, if the path ends in a plain name
+    pub(super) fn parent(&self) -> Option<(JsonPath, &str)> {
+        let (last, rest) = self.segments.split_last()?;
+        match last.selectors.as_slice() {
+            [Selector::Name(name)] if !last.descendant => Some((
+                JsonPath {
+                    text: String::new(),
+                    segments: rest.to_vec(),
+                    legacy: self.legacy,
+                },
+                name,
+            )),
+            _ => None,
+        }
+    }
+
+    pub(super) fn select<'a>(&self, root: &'a Json) -> Vec<&'a Json> {
+        self.matches(root).into_iter().map(|(_, v)| v).collect()
+    }
+
+    pub(super) fn locate(&self, root: &Json) -> Vec<Vec<Step>> {
+        self.matches(root).into_iter().map(|(at, _)| at).collect()
+    }
+
+    // Everything the path matches, in document order
+    fn matches<'a>(&self, root: &'a Json) -> Vec<(Vec<Step>, &'a Json)> {
+        let mut current = vec![(Vec::new(), root)];
+        for segment in &self.segments {
+            let mut next = Vec::new();
+            for (at, value) in current {
+                if segment.descendant {
+                    let mut nodes = Vec::new();
+                    descendants(at, value, &mut nodes);
+                    for (at, value) in nodes {
+                        segment.apply(&at, value, &mut next);
+                    }
+                } else {
+                    segment.apply(&at, value, &mut next);
+                }
+            }
+            current = next;
+        }
+        current
+    }
+}
+
+impl fmt::Display for JsonPath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.text)
+    }
+}
+
+// The value itself and everything under it, parents first
+fn descendants<'a>(at: Vec<Step>, value: &'a Json, out: &mut Vec<(Vec<Step>, &'a Json)>) {
+    let children = children(&at, value);
+    out.push((at, value));
+    for (at, child) in children {
+        descendants(at, child, out);
+    }
+}
+
+fn children<'a>(at: &[Step], value: &'a Json) -> Vec<(Vec<Step>, &'a Json)> {
+    let child = |step| {
+        let mut at = at.to_vec();
+        at.push(step);
+        at
+    };
+    match value {
+        Json::Array(items) => items
+            .iter()
+            .enumerate()
+            .map(|(i, item)| (child(Step::Index(i)), item))
+            .collect(),
+        Json::Object(map) => map
+            .iter()
+            .map(|(key, item)| (child(Step::Key(key.clone())), item))
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+impl Segment {
+    fn apply<'a>(&self, at: &[Step], value: &'a Json, out: &mut Vec<(Vec<Step>, &'a Json)>) {
+        let child = |step| {
+            let mut at = at.to_vec();
+            at.push(step);
+            at
+        };
+        for selector in &self.selectors {
+            match (selector, value) {
+                (Selector::Name(name), Json::Object(map)) => {
+                    if let Some(item) = map.get(name) {
+                        out.push((child(Step::Key(name.clone())), item));
+                    }
+                }
+                (Selector::Wildcard, _) => out.extend(children(at, value)),
+                (Selector::Index(index), Json::Array(items)) => {
+                    let len = items.len() as i64;
+                    let index = if *index < 0 { len + index } else { *index };
+                    if (0..len).contains(&index) {
+                        let index = index as usize;
+                        out.push((child(Step::Index(index)), &items[index]));
+                    }
+                }
+                (Selector::Slice { start, end, step }, Json::Array(items)) => {
+                    let len = items.len() as i64;
+                    let bound = |n: Option<i64>, default: i64| {
+                        let n = n.unwrap_or(default);
+                        (if n < 0 { len + n } else { n }).clamp(0, len)
+                    };
+                    let (start, end) = (bound(*start, 0), bound(*end, len));
+                    if *step > 0 {
+                        for index in (start..end).step_by(*step as usize) {
+                            let index = index as usize;
+                            out.push((child(Step::Index(index)), &items[index]));
+                        }
+                    }
+                }
+                (Selector::Filter(alternatives), _) => {
+                    for (at, item) in children(at, value) {
+                        if alternatives
+                            .iter()
+                            .any(|all| all.iter().all(|c| c.holds(item)))
+                        {
+                            out.push((at, item));
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+impl Condition {
+    fn holds(&self, current: &Json) -> bool {
+        match self {
+            Condition::Exists(path) => !path.matches(current).is_empty(),
+            Condition::Compare(left, op, right) => {
+                let (Some(left), Some(right)) = (left.resolve(current), right.resolve(current))
+                else {
+                    return false;
+                };
+                let ordering = match (left, right) {
+                    (Json::Number(a), Json::Number(b)) => a.as_f64().partial_cmp(&b.as_f64()),
+                    (Json::String(a), Json::String(b)) => Some(a.cmp(b)),
+                    _ => None,
+                };
+                match op {
+                    Comparison::Eq => ordering.map_or(left == right, |o| o.is_eq()),
+                    Comparison::Ne => ordering.map_or(left != right, |o| o.is_ne()),
+                    Comparison::Lt => ordering.is_some_and(|o| o.is_lt()),
+                    Comparison::Le => ordering.is_some_and(|o| o.is_le()),
+                    Comparison::Gt => ordering.is_some_and(|o| o.is_gt()),
+                    Comparison::Ge => ordering.is_some_and(|o| o.is_ge()),
+                }
+            }
+        }
+    }
+}
+
+impl Operand {
+    fn resolve<'a>(&'a self, current: &'a Json) -> Option<&'a Json> {
+        match self {
+            Operand::Path(path) => path.select(current).into_iter().next(),
+            Operand::Literal(value) => Some(value),
+        }
+    }
+}
+
+struct Parser<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<u8> {
+        self.bytes.get(self.pos).copied()
+    }
+
+    fn eat(&mut self, token: &str) -> bool {
+        if self.bytes[self.pos..].starts_with(token.as_bytes()) {
+            self.pos += token.len();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn skip_spaces(&mut self) {
+        while self.peek().is_some_and(|b| b.is_ascii_whitespace()) {
+            self.pos += 1;
+        }
+    }
+
+    fn segments(&mut self) -> Option<Vec<Segment>> {
+        let mut segments = Vec::new();
+        loop {
+            let descendant = if self.eat("..") {
+                true
+            } else if self.eat(".") || self.peek() == Some(b'[') {
+                false
+            } else {
+                return Some(segments);
+            };
+
+            let selectors = if self.peek() == Some(b'[') {
+                self.bracket()?
+            } else if self.eat("*") {
+                vec![Selector::Wildcard]
+            } else {
+                vec![Selector::Name(self.name()?)]
+            };
+            segments.push(Segment {
+                descendant,
+                selectors,
+            });
+        }
+    }
+
+    // A name after a dot runs until the next step or filter syntax
+    fn name(&mut self) -> Option<String> {
+        let start = self.pos;
+        while self
+            .peek()
+            .is_some_and(|b| !b".[]()=!<>&|,'\"".contains(&b) && !b.is_ascii_whitespace())
+        {
+            self.pos += 1;
+        }
+        if self.pos == start {
+            return None;
+        }
+        String::from_utf8(self.bytes[start..self.pos].to_vec()).ok()
+    }
+
+    fn bracket(&mut self) -> Option<Vec<Selector>> {
+        self.eat("[");
+        let mut selectors = Vec::new();
+        loop {
+            self.skip_spaces();
+            selectors.push(self.selector()?);
+            self.skip_spaces();
+            if self.eat("]") {
+                return Some(selectors);
+            }
+            if !self.eat(",") {
+                return None;
+            }
+        }
+    }
+
+    fn selector(&mut self) -> Option<Selector> {
+        match self.peek()? {
+            b'\'' | b'"' => Some(Selector::Name(self.quoted()?)),
+            b'*' => {
+                self.pos += 1;
+                Some(Selector::Wildcard)
+            }
+            b'?' => {
+                self.pos += 1;
+                self.skip_spaces();
+                if !self.eat("(") {
+                    return None;
+                }
+                let filter = self.filter()?;
+                self.skip_spaces();
+                self.eat(")").then_some(filter)
+            }
+            _ => {
+                let start = self.int();
+                if !self.eat(":") {
+                    return start.map(Selector::Index);
+                }
+                let end = self.int();
+                let step = if self.eat(":") { self.int()? } else { 1 };
+                Some(Selector::Slice { start, end, step })
+            }
+        }
+    }
+
+    fn int(&mut self) -> Option<i64> {
+        let start = self.pos;
+        self.eat("-");
+        while self.peek().is_some_and(|b| b.is_ascii_digit()) {
+            self.pos += 1;
+        }
+        let parsed = std::str::from_utf8(&self.bytes[start..self.pos])
+            .ok()?
+            .parse()
+            .ok();
+        if parsed.is_none() {
+            self.pos = start;
+        }
+        parsed
+    }
+
+    // A single or double quoted string, with backslash escaping the next character
+    fn quoted(&mut self) -> Option<String> {
+        let quote = self.peek()?;
+        self.pos += 1;
+        let mut out = Vec::new();
+        loop {
+            match self.peek()? {
+                b if b == quote => {
+                    self.pos += 1;
+                    return String::from_utf8(out).ok();
+                }
+                b'\\' => {
+                    out.push(*self.bytes.get(self.pos + 1)?);
+                    self.pos += 2;
+                }
+                b => {
+                    out.push(b);
+                    self.pos += 1;
+                }
+            }
+        }
+    }
+
+    fn filter(&mut self) -> Option<Selector> {
+        let mut alternatives = Vec::new();
+        loop {
+            let mut all = vec![self.condition()?];
+            while self.eat("&&") {
+                all.push(self.condition()?);
+            }
+            alternatives.push(all);
+            if !self.eat("||") {
+                return Some(Selector::Filter(alternatives));
+            }
+        }
+    }
+
+    fn condition(&mut self) -> Option<Condition> {
+        let left = self.operand()?;
+        self.skip_spaces();
+        let op = [
+            ("==", Comparison::Eq),
+            ("!=", Comparison::Ne),
+            ("<=", Comparison::Le),
+            (">=", Comparison::Ge),
+            ("<", Comparison::Lt),
+            (">", Comparison::Gt),
+        ]
+        .into_iter()
+        .find(|(token, _)| self.eat(token))
+        .map(|(_, op)| op);
+
+        let condition = match (op, left) {
+            (Some(op), left) => Condition::Compare(left, op, self.operand()?),
+            (None, Operand::Path(path)) => Condition::Exists(path),
+            (None, Operand::Literal(_)) => return None,
+        };
+        self.skip_spaces();
+        Some(condition)
+    }
+
+    fn operand(&mut self) -> Option<Operand> {
+        self.skip_spaces();
+        match self.peek()? {
+            b'@' => {
+                self.pos += 1;
+                Some(Operand::Path(JsonPath {
+                    text: String::new(),
+                    segments: self.segments()?,
+                    legacy: false,
+                }))
+            }
+            b'\'' | b'"' => Some(Operand::Literal(Json::String(self.quoted()?))),
+            _ => {
+                // Numbers, true, false and null
+                let start = self.pos;
+                while self
+                    .peek()
+                    .is_some_and(|b| b.is_ascii_alphanumeric() || b"+-.".contains(&b))
+                {
+                    self.pos += 1;
+                }
+                serde_json::from_slice(&self.bytes[start..self.pos])
+                    .ok()
+                    .map(Operand::Literal)
+            }
+        }
+    }
+}
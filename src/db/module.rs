@@ -0,0 +1,122 @@
+// The WASM modules MODULE LOAD has loaded, the commands they registered, and the limits those
+// commands run under. A module is kept compiled and instantiated afresh for each command, so
+// nothing a command leaves in its memory is there for the next.
+use super::{Db, DbError};
+use bytes::Bytes;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, MutexGuard, PoisonError};
+use std::time::Duration;
+
+pub(super) struct Modules {
+    modules: BTreeMap<String, WasmModule>,
+    // Uppercase command name -> the module that registered it
+    commands: HashMap<Bytes, String>,
+    pub(super) limits: WasmLimits,
+}
+
+#[derive(Clone)]
+pub struct WasmModule {
+    pub name: String,
+    pub path: String,
+    pub module: Arc<wasmi::Module>,
+    pub commands: Vec<ModuleCommand>,
+}
+
+#[derive(Clone)]
+pub struct ModuleCommand {
+    // Uppercase, as the command table matches it
+    pub name: Bytes,
+    // The export that runs it
+    pub handler: String,
+    pub read_only: bool,
+}
+
+// The most fuel a module command may be given, about two seconds of computing. The time limit
+// is only checked when a command calls back into padis, so this is what bounds one that computes
+// on its own while it holds the gate.
+pub const MAX_WASM_FUEL: u64 = 1_000_000_000;
+
+#[derive(Clone, Copy, Debug)]
+pub struct WasmLimits {
+    // Roughly one unit per instruction
+    pub fuel: u64,
+    // Checked whenever the command calls back into padis
+    pub time: Duration,
+    // Bytes of linear memory
+    pub memory: usize,
+}
+
+impl Default for Modules {
+    fn default() -> Self {
+        Modules {
+            modules: BTreeMap::new(),
+            commands: HashMap::new(),
+            limits: WasmLimits {
+                fuel: 100_000_000,
+                time: Duration::from_secs(1),
+                memory: 64 << 20,
+            },
+        }
+    }
+}
+
+impl Db {
+    pub(super) fn modules(&self) -> MutexGuard<'_, Modules> {
+        self.shared
+            .modules
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    pub fn module_load(&self, module: WasmModule) -> Result<(), DbError> {
+        let mut modules = self.modules();
+        if modules.modules.contains_key(&module.name) {
+            return Err(DbError::ModuleExists(module.name));
+        }
+        if let Some(command) = module
+            .commands
+            .iter()
+            .find(|command| modules.commands.contains_key(&command.name))
+        {
+            return Err(DbError::CommandExists(
+                String::from_utf8_lossy(&command.name).into_owned(),
+            ));
+        }
+        for command in &module.commands {
+            modules
+                .commands
+                .insert(command.name.clone(), module.name.clone());
+        }
+        modules.modules.insert(module.name.clone(), module);
+        Ok(())
+    }
+
+    pub fn module_unload(&self, name: &[u8]) -> Result<(), DbError> {
+        let mut modules = self.modules();
+        let Some(module) = modules.modules.remove(&*String::from_utf8_lossy(name)) else {
+            return Err(DbError::ModuleNotFound);
+        };
+        for command in &module.commands {
+            modules.commands.remove(&command.name);
+        }
+        Ok(())
+    }
+
+    // Modules in name order
+    pub fn module_list(&self) -> Vec<WasmModule> {
+        self.modules().modules.values().cloned().collect()
+    }
+
+    // The module that registered a command, and the command, matching its name in any case
+    pub fn module_command(&self, name: &[u8]) -> Option<(Arc<wasmi::Module>, ModuleCommand)> {
+        let name = name.to_ascii_uppercase();
+        let modules = self.modules();
+        let module = &modules.modules[modules.commands.get(name.as_slice())?];
+        let command = module.commands.iter().find(|c| c.name == name)?;
+        Some((module.module.clone(), command.clone()))
+    }
+
+    pub fn wasm_limits(&self) -> WasmLimits {
+        self.modules().limits
+    }
+}
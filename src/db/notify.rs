@@ -0,0 +1,147 @@
+// Keyspace notifications, configured by `notify-keyspace-events`. Writes queue an event for
+// each key they touch while they hold the state lock, and the guard publishes the queue to
+// `__keyspace@0__:<key>` and `__keyevent@0__:<event>` when it is dropped. That still happens
+// under the state lock, so subscribers see events in the order the writes were made. The
+// lock order is always state, then pubsub.
+use super::State;
+use super::pubsub::Registry;
+use bytes::{BufMut, Bytes, BytesMut};
+use std::fmt;
+
+// The classes events are filtered by, each with its flag character. Nothing fires `List` or
+// `Evicted` yet, but their flags are accepted so settings written for Redis still apply.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(super) enum Class {
+    Generic,
+    String,
+    List,
+    Set,
+    Hash,
+    ZSet,
+    Expired,
+    Evicted,
+    Stream,
+    KeyMiss,
+    New,
+}
+
+const CLASSES: [(Class, char); 11] = [
+    (Class::Generic, 'g'),
+    (Class::String, '$'),
+    (Class::List, 'l'),
+    (Class::Set, 's'),
+    (Class::Hash, 'h'),
+    (Class::ZSet, 'z'),
+    (Class::Expired, 'x'),
+    (Class::Evicted, 'e'),
+    (Class::Stream, 't'),
+    (Class::KeyMiss, 'm'),
+    (Class::New, 'n'),
+];
+
+const KEYSPACE: u16 = 1 << 11;
+const KEYEVENT: u16 = 1 << 12;
+// `A`, which like in Redis leaves out key miss and new key events
+const ALL: u16 = (1 << 9) - 1;
+
+impl Class {
+    fn bit(self) -> u16 {
+        1 << self as u16
+    }
+}
+
+// Which events are published and on which of the two channels
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct NotifyEvents(u16);
+
+impl NotifyEvents {
+    // None if a character isn't a known flag
+    pub fn parse(flags: &[u8]) -> Option<NotifyEvents> {
+        let mut bits = 0;
+        for flag in flags {
+            bits |= match *flag as char {
+                'A' => ALL,
+                'K' => KEYSPACE,
+                'E' => KEYEVENT,
+                c => CLASSES.iter().find(|(_, f)| *f == c)?.0.bit(),
+            };
+        }
+        Some(NotifyEvents(bits))
+    }
+
+    fn enabled(self, class: Class) -> bool {
+        self.0 & class.bit() != 0 && self.0 & (KEYSPACE | KEYEVENT) != 0
+    }
+}
+
+// The flags as CONFIG GET shows them, with `A` standing in for the classes it covers
+impl fmt::Display for NotifyEvents {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut out = String::new();
+        if self.0 & ALL == ALL {
+            out.push('A');
+        }
+        for (class, flag) in CLASSES {
+            let in_all = class.bit() & ALL != 0;
+            if self.0 & class.bit() != 0 && !(in_all && self.0 & ALL == ALL) {
+                out.push(flag);
+            }
+        }
+        for (bit, flag) in [(KEYSPACE, 'K'), (KEYEVENT, 'E')] {
+            if self.0 & bit != 0 {
+                out.push(flag);
+            }
+        }
+        f.write_str(&out)
+    }
+}
+
+#[derive(Default)]
+pub(super) struct Notifications {
+    pub(super) events: NotifyEvents,
+    pending: Vec<(&'static str, Bytes)>,
+}
+
+impl State {
+    // Queue an event on the key, if its class is enabled. Every change to a key comes through
+    // here, so it also counts as a change to the key.
+    pub(super) fn notify(&mut self, class: Class, event: &'static str, key: &Bytes) {
+        if !matches!(class, Class::KeyMiss | Class::New) {
+            self.changed(key);
+        }
+        if self.notifications.events.enabled(class) {
+            self.notifications.pending.push((event, key.clone()));
+        }
+    }
+
+    // Invalidates the key for tracking clients and breaks WATCHes on it. FLUSHDB and FLUSHALL
+    // come straight here for each key, since like in Redis they publish no keyspace events.
+    pub(super) fn changed(&mut self, key: &Bytes) {
+        self.invalidate(key);
+        self.touch(key);
+    }
+
+    pub(super) fn publish_notifications(&mut self, registry: &mut Registry) {
+        let events = self.notifications.events;
+        for (event, key) in self.notifications.pending.drain(..) {
+            let event = Bytes::from_static(event.as_bytes());
+            if events.0 & KEYSPACE != 0 {
+                registry.publish(&channel(b"__keyspace@0__:", &key), &event);
+            }
+            if events.0 & KEYEVENT != 0 {
+                registry.publish(&channel(b"__keyevent@0__:", &event), &key);
+            }
+        }
+    }
+
+    pub(super) fn has_notifications(&self) -> bool {
+        !self.notifications.pending.is_empty()
+    }
+}
+
+fn channel(prefix: &[u8], name: &[u8]) -> Bytes {
+    let mut channel = BytesMut::with_capacity(prefix.len() + name.len());
+    channel.put_slice(prefix);
+    channel.put_slice(name);
+    channel.freeze()
+}
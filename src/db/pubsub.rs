@@ -0,0 +1,487 @@
+// Pub/Sub channels. The registry sits next to the keyspace in `Db` under its own lock, so
+// publishing never waits on data commands. Each subscribed connection gets a bounded queue
+// that PUBLISH only ever tries to push to; a subscriber that lets it fill up is dropped,
+// like Redis does to pubsub clients over their output buffer limit, rather than slowing the
+// publisher down.
+//
+// Pattern subscriptions are grouped by the literal prefix of their glob, so a publish only
+// tests the patterns filed under one of the channel's own prefixes, plus those starting with
+// a wildcard.
+//
+// Shard channels (SSUBSCRIBE/SPUBLISH) are kept apart from the global ones and filed under
+// their key slot, the way a cluster node would own them.
+//
+// Every connection registers, subscribed or not, and its registry ID is its client ID. Client
+// tracking invalidations reach it through a second queue that is dropped the same way.
+use super::Db;
+use super::glob::Glob;
+use super::slot::key_slot;
+use bytes::Bytes;
+use std::collections::{HashMap, HashSet};
+use tokio::sync::mpsc;
+use tokio::sync::mpsc::error::TrySendError;
+
+// Messages a subscriber may have queued before it is dropped for falling behind
+pub const SUBSCRIBER_QUEUE: usize = 1024;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Message {
+    // The subscribed pattern the channel matched, for pattern subscriptions
+    pub pattern: Option<Bytes>,
+    pub channel: Bytes,
+    pub payload: Bytes,
+    // Sent with SPUBLISH to a shard channel
+    pub sharded: bool,
+}
+
+// Whatever reaches a connection outside of its replies
+#[derive(Debug, Clone, PartialEq)]
+pub enum Delivery {
+    Message(Message),
+    Invalidation(Vec<Bytes>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Kind {
+    Channel,
+    Pattern,
+    Shard,
+}
+
+#[derive(Default)]
+pub(super) struct Registry {
+    next_id: u64,
+    clients: HashMap<u64, Client>,
+    channels: HashMap<Bytes, HashSet<u64>>,
+    // Literal prefix -> pattern -> subscribers
+    patterns: HashMap<Bytes, HashMap<Bytes, PatternSubscribers>>,
+    // Key slot -> shard channel -> subscribers
+    shards: HashMap<u16, HashMap<Bytes, HashSet<u64>>>,
+}
+
+struct Client {
+    tx: mpsc::Sender<Message>,
+    invalidations: mpsc::Sender<Vec<Bytes>>,
+    channels: HashSet<Bytes>,
+    patterns: HashSet<Bytes>,
+    shard_channels: HashSet<Bytes>,
+}
+
+impl Client {
+    fn subscriptions(&mut self, kind: Kind) -> &mut HashSet<Bytes> {
+        match kind {
+            Kind::Channel => &mut self.channels,
+            Kind::Pattern => &mut self.patterns,
+            Kind::Shard => &mut self.shard_channels,
+        }
+    }
+}
+
+struct PatternSubscribers {
+    glob: Glob,
+    ids: HashSet<u64>,
+}
+
+impl Registry {
+    // IDs start at 1, as 0 means no client to CLIENT GETREDIR
+    fn register(
+        &mut self,
+        tx: mpsc::Sender<Message>,
+        invalidations: mpsc::Sender<Vec<Bytes>>,
+    ) -> u64 {
+        self.next_id += 1;
+        let id = self.next_id;
+        let client = Client {
+            tx,
+            invalidations,
+            channels: HashSet::new(),
+            patterns: HashSet::new(),
+            shard_channels: HashSet::new(),
+        };
+        self.clients.insert(id, client);
+        id
+    }
+
+    // Forgets the client and its subscriptions. Dropping its sender ends its queue.
+    fn unregister(&mut self, id: u64) {
+        let Some(mut client) = self.clients.remove(&id) else {
+            return;
+        };
+        for kind in [Kind::Channel, Kind::Pattern, Kind::Shard] {
+            for name in std::mem::take(client.subscriptions(kind)) {
+                self.unindex(kind, &name, id);
+            }
+        }
+    }
+
+    fn join(&mut self, kind: Kind, name: &Bytes, id: u64) {
+        let Some(client) = self.clients.get_mut(&id) else {
+            return;
+        };
+        if !client.subscriptions(kind).insert(name.clone()) {
+            return;
+        }
+        let ids = match kind {
+            Kind::Channel => self.channels.entry(name.clone()).or_default(),
+            Kind::Pattern => {
+                let glob = Glob::new(name);
+                &mut self
+                    .patterns
+                    .entry(Bytes::from(glob.literal_prefix()))
+                    .or_default()
+                    .entry(name.clone())
+                    .or_insert_with(|| PatternSubscribers {
+                        glob,
+                        ids: HashSet::new(),
+                    })
+                    .ids
+            }
+            Kind::Shard => self
+                .shards
+                .entry(key_slot(name))
+                .or_default()
+                .entry(name.clone())
+                .or_default(),
+        };
+        ids.insert(id);
+    }
+
+    fn part(&mut self, kind: Kind, name: &Bytes, id: u64) {
+        if let Some(client) = self.clients.get_mut(&id)
+            && client.subscriptions(kind).remove(name)
+        {
+            self.unindex(kind, name, id);
+        }
+    }
+
+    fn unindex(&mut self, kind: Kind, name: &Bytes, id: u64) {
+        match kind {
+            Kind::Channel => remove_id(&mut self.channels, name, id),
+            Kind::Pattern => {
+                let prefix = Glob::new(name).literal_prefix();
+                let Some(group) = self.patterns.get_mut(prefix.as_slice()) else {
+                    return;
+                };
+                if let Some(subscribers) = group.get_mut(name) {
+                    subscribers.ids.remove(&id);
+                    if subscribers.ids.is_empty() {
+                        group.remove(name);
+                    }
+                }
+                if group.is_empty() {
+                    self.patterns.remove(prefix.as_slice());
+                }
+            }
+            Kind::Shard => {
+                let slot = key_slot(name);
+                let Some(channels) = self.shards.get_mut(&slot) else {
+                    return;
+                };
+                remove_id(channels, name, id);
+                if channels.is_empty() {
+                    self.shards.remove(&slot);
+                }
+            }
+        }
+    }
+
+    // What (P|S)SUBSCRIBE replies report: channels and patterns count together, as in Redis,
+    // and shard channels on their own
+    fn count(&self, kind: Kind, id: u64) -> usize {
+        self.clients.get(&id).map_or(0, |client| match kind {
+            Kind::Channel | Kind::Pattern => client.channels.len() + client.patterns.len(),
+            Kind::Shard => client.shard_channels.len(),
+        })
+    }
+
+    // The client's subscriptions of one kind, sorted
+    fn subscriptions(&mut self, kind: Kind, id: u64) -> Vec<Bytes> {
+        let Some(client) = self.clients.get_mut(&id) else {
+            return Vec::new();
+        };
+        let mut all: Vec<Bytes> = client.subscriptions(kind).iter().cloned().collect();
+        all.sort();
+        all
+    }
+
+    pub(super) fn publish(&mut self, channel: &Bytes, payload: &Bytes) -> usize {
+        let mut deliveries: Vec<(u64, Option<Bytes>)> = Vec::new();
+        if let Some(ids) = self.channels.get(channel) {
+            deliveries.extend(ids.iter().map(|id| (*id, None)));
+        }
+        for len in 0..=channel.len() {
+            let Some(group) = self.patterns.get(&channel[..len]) else {
+                continue;
+            };
+            for (pattern, subscribers) in group {
+                if subscribers.glob.matches(channel) {
+                    deliveries.extend(
+                        subscribers
+                            .ids
+                            .iter()
+                            .map(|id| (*id, Some(pattern.clone()))),
+                    );
+                }
+            }
+        }
+        self.deliver(deliveries, channel, payload, false)
+    }
+
+    fn spublish(&mut self, channel: &Bytes, payload: &Bytes) -> usize {
+        let deliveries = match self
+            .shards
+            .get(&key_slot(channel))
+            .and_then(|channels| channels.get(channel))
+        {
+            Some(ids) => ids.iter().map(|id| (*id, None)).collect(),
+            None => Vec::new(),
+        };
+        self.deliver(deliveries, channel, payload, true)
+    }
+
+    fn deliver(
+        &mut self,
+        deliveries: Vec<(u64, Option<Bytes>)>,
+        channel: &Bytes,
+        payload: &Bytes,
+        sharded: bool,
+    ) -> usize {
+        let mut receivers = 0;
+        let mut lagging = Vec::new();
+        for (id, pattern) in deliveries {
+            let message = Message {
+                pattern,
+                channel: channel.clone(),
+                payload: payload.clone(),
+                sharded,
+            };
+            // An earlier delivery may already have found the client lagging
+            let Some(client) = self.clients.get(&id) else {
+                continue;
+            };
+            match client.tx.try_send(message) {
+                Ok(()) => receivers += 1,
+                Err(TrySendError::Full(_)) => lagging.push(id),
+                // The subscriber is being dropped and will unregister itself
+                Err(TrySendError::Closed(_)) => {}
+            }
+        }
+        for id in lagging {
+            self.unregister(id);
+        }
+        receivers
+    }
+
+    pub(super) fn has_client(&self, id: u64) -> bool {
+        self.clients.contains_key(&id)
+    }
+
+    // Sends tracking invalidations, dropping a client that has fallen behind
+    pub(super) fn invalidate(&mut self, id: u64, keys: Vec<Bytes>) {
+        let Some(client) = self.clients.get(&id) else {
+            return;
+        };
+        if let Err(TrySendError::Full(_)) = client.invalidations.try_send(keys) {
+            self.unregister(id);
+        }
+    }
+
+    // Channels with subscribers, optionally filtered by a glob, sorted
+    fn active_channels(&self, kind: Kind, pattern: Option<&[u8]>) -> Vec<Bytes> {
+        let glob = pattern.map(Glob::new);
+        let mut names: Vec<Bytes> = match kind {
+            Kind::Shard => self
+                .shards
+                .values()
+                .flat_map(|c| c.keys())
+                .cloned()
+                .collect(),
+            _ => self.channels.keys().cloned().collect(),
+        };
+        names.retain(|name| glob.as_ref().is_none_or(|glob| glob.matches(name)));
+        names.sort();
+        names
+    }
+
+    fn subscriber_count(&self, kind: Kind, channel: &Bytes) -> usize {
+        let ids = match kind {
+            Kind::Shard => self
+                .shards
+                .get(&key_slot(channel))
+                .and_then(|channels| channels.get(channel)),
+            _ => self.channels.get(channel),
+        };
+        ids.map_or(0, HashSet::len)
+    }
+}
+
+fn remove_id(index: &mut HashMap<Bytes, HashSet<u64>>, name: &Bytes, id: u64) {
+    if let Some(ids) = index.get_mut(name) {
+        ids.remove(&id);
+        if ids.is_empty() {
+            index.remove(name);
+        }
+    }
+}
+
+// A connection's subscriptions. Dropping it unsubscribes from everything.
+pub struct Subscriber {
+    id: u64,
+    db: Db,
+    rx: mpsc::Receiver<Message>,
+    invalidations: mpsc::Receiver<Vec<Bytes>>,
+}
+
+impl Subscriber {
+    // The client ID, which CLIENT TRACKING REDIRECT refers to
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    // The number of subscriptions after each channel is added
+    pub fn subscribe(&mut self, channels: &[Bytes]) -> Vec<(Bytes, usize)> {
+        self.join(Kind::Channel, channels)
+    }
+
+    // With no channels, leaves every channel. The channel is None when there were none to
+    // leave.
+    pub fn unsubscribe(&mut self, channels: &[Bytes]) -> Vec<(Option<Bytes>, usize)> {
+        self.leave(Kind::Channel, channels)
+    }
+
+    pub fn psubscribe(&mut self, patterns: &[Bytes]) -> Vec<(Bytes, usize)> {
+        self.join(Kind::Pattern, patterns)
+    }
+
+    pub fn punsubscribe(&mut self, patterns: &[Bytes]) -> Vec<(Option<Bytes>, usize)> {
+        self.leave(Kind::Pattern, patterns)
+    }
+
+    // Counts only shard channels, like Redis
+    pub fn ssubscribe(&mut self, channels: &[Bytes]) -> Vec<(Bytes, usize)> {
+        self.join(Kind::Shard, channels)
+    }
+
+    pub fn sunsubscribe(&mut self, channels: &[Bytes]) -> Vec<(Option<Bytes>, usize)> {
+        self.leave(Kind::Shard, channels)
+    }
+
+    fn join(&mut self, kind: Kind, names: &[Bytes]) -> Vec<(Bytes, usize)> {
+        let mut registry = self.db.pubsub();
+        names
+            .iter()
+            .map(|name| {
+                registry.join(kind, name, self.id);
+                (name.clone(), registry.count(kind, self.id))
+            })
+            .collect()
+    }
+
+    fn leave(&mut self, kind: Kind, names: &[Bytes]) -> Vec<(Option<Bytes>, usize)> {
+        let mut registry = self.db.pubsub();
+        let names = match names {
+            [] => registry.subscriptions(kind, self.id),
+            names => names.to_vec(),
+        };
+        if names.is_empty() {
+            return vec![(None, registry.count(kind, self.id))];
+        }
+        names
+            .into_iter()
+            .map(|name| {
+                registry.part(kind, &name, self.id);
+                let count = registry.count(kind, self.id);
+                (Some(name), count)
+            })
+            .collect()
+    }
+
+    // Every subscription, shard channels included; the connection stays in subscribed mode
+    // while this is non-zero
+    pub fn count(&self) -> usize {
+        let registry = self.db.pubsub();
+        registry.count(Kind::Channel, self.id) + registry.count(Kind::Shard, self.id)
+    }
+
+    // None once the subscriber has been dropped for falling behind
+    pub async fn recv(&mut self) -> Option<Message> {
+        self.rx.recv().await
+    }
+
+    // Keys written since this client, or one redirecting to it, cached them. None once the
+    // client has been dropped for falling behind.
+    pub async fn recv_invalidation(&mut self) -> Option<Vec<Bytes>> {
+        self.invalidations.recv().await
+    }
+
+    // Either of the above, whichever comes first
+    pub async fn next(&mut self) -> Option<Delivery> {
+        tokio::select! {
+            message = self.rx.recv() => message.map(Delivery::Message),
+            keys = self.invalidations.recv() => keys.map(Delivery::Invalidation),
+        }
+    }
+}
+
+impl Drop for Subscriber {
+    fn drop(&mut self) {
+        self.db.untrack(self.id);
+        self.db.unwatch(self.id);
+        self.db.pubsub().unregister(self.id);
+    }
+}
+
+impl Db {
+    pub fn subscriber(&self) -> Subscriber {
+        let (tx, rx) = mpsc::channel(SUBSCRIBER_QUEUE);
+        let (invalidations_tx, invalidations) = mpsc::channel(SUBSCRIBER_QUEUE);
+        let id = self.pubsub().register(tx, invalidations_tx);
+        Subscriber {
+            id,
+            db: self.clone(),
+            rx,
+            invalidations,
+        }
+    }
+
+    // Returns how many subscribers received the message
+    pub fn publish(&self, channel: &Bytes, payload: &Bytes) -> usize {
+        self.pubsub().publish(channel, payload)
+    }
+
+    // Publishes to a shard channel, which only SSUBSCRIBE subscribers receive
+    pub fn spublish(&self, channel: &Bytes, payload: &Bytes) -> usize {
+        self.pubsub().spublish(channel, payload)
+    }
+
+    // PUBSUB CHANNELS: channels with at least one subscriber. Pattern subscriptions don't count.
+    pub fn pubsub_channels(&self, pattern: Option<&[u8]>) -> Vec<Bytes> {
+        self.pubsub().active_channels(Kind::Channel, pattern)
+    }
+
+    pub fn pubsub_shard_channels(&self, pattern: Option<&[u8]>) -> Vec<Bytes> {
+        self.pubsub().active_channels(Kind::Shard, pattern)
+    }
+
+    // PUBSUB NUMSUB: subscribers of each channel
+    pub fn pubsub_numsub(&self, channels: &[Bytes]) -> Vec<(Bytes, usize)> {
+        let registry = self.pubsub();
+        channels
+            .iter()
+            .map(|c| (c.clone(), registry.subscriber_count(Kind::Channel, c)))
+            .collect()
+    }
+
+    pub fn pubsub_shard_numsub(&self, channels: &[Bytes]) -> Vec<(Bytes, usize)> {
+        let registry = self.pubsub();
+        channels
+            .iter()
+            .map(|c| (c.clone(), registry.subscriber_count(Kind::Shard, c)))
+            .collect()
+    }
+
+    // PUBSUB NUMPAT: distinct patterns subscribed to by any client
+    pub fn pubsub_numpat(&self) -> usize {
+        self.pubsub().patterns.values().map(HashMap::len).sum()
+    }
+}
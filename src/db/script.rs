@@ -0,0 +1,118 @@
+// The Lua script cache behind EVAL, EVALSHA and SCRIPT, and the script running at the moment.
+// Both sit under their own lock next to the keyspace: the server holds the gate exclusively for
+// a script's whole run, so other clients must be able to see it's busy and kill it without
+// waiting on the gate.
+use super::{Db, DbError};
+use bytes::Bytes;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, MutexGuard, PoisonError};
+use std::time::{Duration, Instant};
+
+// Redis' default for busy-reply-threshold
+const BUSY_REPLY_THRESHOLD: Duration = Duration::from_secs(5);
+
+pub(super) struct Scripts {
+    // SHA1 in lowercase hex -> body
+    cache: HashMap<String, Bytes>,
+    running: Option<Arc<ScriptRun>>,
+    // How long a script runs before other clients are told the server is busy
+    pub(super) busy_threshold: Duration,
+}
+
+// A script in progress, shared with the interpreter running it
+pub struct ScriptRun {
+    started: Instant,
+    wrote: AtomicBool,
+    killed: AtomicBool,
+}
+
+impl Default for Scripts {
+    fn default() -> Self {
+        Scripts {
+            cache: HashMap::new(),
+            running: None,
+            busy_threshold: BUSY_REPLY_THRESHOLD,
+        }
+    }
+}
+
+impl ScriptRun {
+    // Called before each write command, after which the script can't be killed
+    pub fn wrote(&self) {
+        self.wrote.store(true, Ordering::Relaxed);
+    }
+
+    pub fn killed(&self) -> bool {
+        self.killed.load(Ordering::Relaxed)
+    }
+}
+
+impl Db {
+    pub(super) fn scripts(&self) -> MutexGuard<'_, Scripts> {
+        self.shared
+            .scripts
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    // Caches the body, returning its SHA1
+    pub fn script_load(&self, body: Bytes) -> String {
+        let sha = sha1_hex(&body);
+        self.scripts().cache.insert(sha.clone(), body);
+        sha
+    }
+
+    pub fn script_get(&self, sha: &[u8]) -> Option<Bytes> {
+        let sha = String::from_utf8_lossy(sha).to_ascii_lowercase();
+        self.scripts().cache.get(&sha).cloned()
+    }
+
+    pub fn script_exists(&self, shas: &[Bytes]) -> Vec<bool> {
+        shas.iter()
+            .map(|sha| self.script_get(sha).is_some())
+            .collect()
+    }
+
+    pub fn script_flush(&self) {
+        self.scripts().cache.clear();
+    }
+
+    // Runs a script where SCRIPT KILL can stop it
+    pub fn script_run<R>(&self, run: impl FnOnce(&Db, &Arc<ScriptRun>) -> R) -> R {
+        let script = Arc::new(ScriptRun {
+            started: Instant::now(),
+            wrote: AtomicBool::new(false),
+            killed: AtomicBool::new(false),
+        });
+        self.scripts().running = Some(script.clone());
+        let result = run(self, &script);
+        self.scripts().running = None;
+        result
+    }
+
+    // Whether a script has run past busy-reply-threshold, so other commands are refused
+    pub fn script_busy(&self) -> bool {
+        let scripts = self.scripts();
+        scripts
+            .running
+            .as_ref()
+            .is_some_and(|script| script.started.elapsed() >= scripts.busy_threshold)
+    }
+
+    pub fn script_kill(&self) -> Result<(), DbError> {
+        let scripts = self.scripts();
+        let Some(script) = &scripts.running else {
+            return Err(DbError::NotBusy);
+        };
+        if script.wrote.load(Ordering::Relaxed) {
+            return Err(DbError::Unkillable);
+        }
+        script.killed.store(true, Ordering::Relaxed);
+        Ok(())
+    }
+}
+
+pub fn sha1_hex(data: &[u8]) -> String {
+    sha1_smol::Sha1::from(data).digest().to_string()
+}
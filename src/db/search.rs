@@ -0,0 +1,662 @@
+// Secondary indexes over hashes, following RediSearch's FT.* commands. An index covers the
+// hashes whose keys start with one of its prefixes and is kept current by reindexing a key
+// whenever it is written, deleted or expires. TEXT and TAG fields get inverted indexes and
+// NUMERIC fields a sorted one, so queries are answered without walking the keyspace.
+use super::ftquery::{FtQuery, Node, is_term_char};
+use super::{Class, Db, DbError, Entry, State, Value};
+use bytes::Bytes;
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::ops::Bound;
+use std::time::Instant;
+
+const DEFAULT_LIMIT: usize = 10;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum FieldType {
+    Text {
+        weight: f64,
+    },
+    Tag {
+        separator: char,
+        case_sensitive: bool,
+    },
+    Numeric,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FieldSpec {
+    // The hash field that is indexed
+    pub name: Bytes,
+    // What queries call the field, when that isn't its name
+    pub alias: Option<Bytes>,
+    pub kind: FieldType,
+    // Accepted for compatibility; every field can be sorted by
+    pub sortable: bool,
+}
+
+impl FieldSpec {
+    pub fn attribute(&self) -> &Bytes {
+        self.alias.as_ref().unwrap_or(&self.name)
+    }
+}
+
+// An empty prefix covers every key
+#[derive(Debug, Clone, PartialEq)]
+pub struct IndexSpec {
+    pub prefixes: Vec<Bytes>,
+    pub schema: Vec<FieldSpec>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FtSearchOptions {
+    pub no_content: bool,
+    pub with_scores: bool,
+    // Attributes or hash fields to reply with instead of the whole hash
+    pub return_fields: Option<Vec<Bytes>>,
+    // The attribute and whether to sort descending
+    pub sort_by: Option<(Bytes, bool)>,
+    pub offset: usize,
+    pub limit: usize,
+}
+
+impl Default for FtSearchOptions {
+    fn default() -> Self {
+        FtSearchOptions {
+            no_content: false,
+            with_scores: false,
+            return_fields: None,
+            sort_by: None,
+            offset: 0,
+            limit: DEFAULT_LIMIT,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FtResults {
+    // Every match, not just the page of `docs`
+    pub total: usize,
+    pub docs: Vec<FtDoc>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FtDoc {
+    pub key: Bytes,
+    pub score: f64,
+    pub fields: Vec<(Bytes, Bytes)>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FtInfo {
+    pub spec: IndexSpec,
+    pub num_docs: usize,
+    pub max_doc_id: u64,
+    // Distinct TEXT terms, and entries across every field's index
+    pub num_terms: usize,
+    pub num_records: usize,
+    // Hashes left out because a NUMERIC field didn't hold a number
+    pub failures: usize,
+}
+
+#[derive(Default)]
+pub(super) struct Indexes {
+    by_name: HashMap<Bytes, Index>,
+}
+
+struct Index {
+    spec: IndexSpec,
+    docs: HashMap<Bytes, Doc>,
+    next_id: u64,
+    failures: usize,
+    // One per schema field, in the same order
+    fields: Vec<FieldIndex>,
+}
+
+enum FieldIndex {
+    Text(BTreeMap<String, HashSet<Bytes>>),
+    Tag(BTreeMap<String, HashSet<Bytes>>),
+    Numeric(BTreeSet<(Number, Bytes)>),
+}
+
+struct Doc {
+    // Ties in the result order go to the document indexed first
+    id: u64,
+    values: Vec<Option<Indexed>>,
+}
+
+enum Indexed {
+    // Each term with how often it occurs
+    Text(HashMap<String, u32>),
+    Tag(Vec<String>),
+    Numeric(f64),
+}
+
+// Orders numbers so they can key a BTreeSet
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Number(f64);
+
+impl Eq for Number {}
+
+impl PartialOrd for Number {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Number {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0.total_cmp(&other.0)
+    }
+}
+
+fn tokenize(value: &[u8]) -> HashMap<String, u32> {
+    let mut terms = HashMap::new();
+    for term in String::from_utf8_lossy(value)
+        .split(|c: char| !is_term_char(c))
+        .filter(|term| !term.is_empty())
+    {
+        *terms.entry(term.to_lowercase()).or_default() += 1;
+    }
+    terms
+}
+
+fn split_tags(value: &[u8], separator: char, case_sensitive: bool) -> Vec<String> {
+    let mut tags: Vec<String> = String::from_utf8_lossy(value)
+        .split(separator)
+        .map(str::trim)
+        .filter(|tag| !tag.is_empty())
+        .map(|tag| match case_sensitive {
+            true => tag.to_string(),
+            false => tag.to_lowercase(),
+        })
+        .collect();
+    tags.sort();
+    tags.dedup();
+    tags
+}
+
+// The keys under `term`, or under every term it prefixes
+fn postings(index: &BTreeMap<String, HashSet<Bytes>>, term: &str, prefix: bool) -> HashSet<Bytes> {
+    if !prefix {
+        return index.get(term).cloned().unwrap_or_default();
+    }
+    index
+        .range::<str, _>((Bound::Included(term), Bound::Unbounded))
+        .take_while(|(t, _)| t.starts_with(term))
+        .flat_map(|(_, keys)| keys.iter().cloned())
+        .collect()
+}
+
+fn unlink(index: &mut BTreeMap<String, HashSet<Bytes>>, term: &str, key: &Bytes) {
+    if let Some(keys) = index.get_mut(term) {
+        keys.remove(key);
+        if keys.is_empty() {
+            index.remove(term);
+        }
+    }
+}
+
+impl Index {
+    fn new(spec: IndexSpec) -> Index {
+        let fields = spec
+            .schema
+            .iter()
+            .map(|field| match field.kind {
+                FieldType::Text { .. } => FieldIndex::Text(BTreeMap::new()),
+                FieldType::Tag { .. } => FieldIndex::Tag(BTreeMap::new()),
+                FieldType::Numeric => FieldIndex::Numeric(BTreeSet::new()),
+            })
+            .collect();
+        Index {
+            spec,
+            docs: HashMap::new(),
+            next_id: 0,
+            failures: 0,
+            fields,
+        }
+    }
+
+    fn covers(&self, key: &Bytes) -> bool {
+        self.spec.prefixes.iter().any(|p| key.starts_with(p))
+    }
+
+    fn update(&mut self, key: &Bytes, hash: Option<&HashMap<Bytes, Bytes>>) {
+        if !self.covers(key) {
+            return;
+        }
+        self.remove(key);
+        if let Some(hash) = hash {
+            self.add(key, hash);
+        }
+    }
+
+    fn add(&mut self, key: &Bytes, hash: &HashMap<Bytes, Bytes>) {
+        let mut values = Vec::with_capacity(self.spec.schema.len());
+        for field in &self.spec.schema {
+            let Some(value) = hash.get(&field.name) else {
+                values.push(None);
+                continue;
+            };
+            values.push(Some(match field.kind {
+                FieldType::Text { .. } => Indexed::Text(tokenize(value)),
+                FieldType::Tag {
+                    separator,
+                    case_sensitive,
+                } => Indexed::Tag(split_tags(value, separator, case_sensitive)),
+                FieldType::Numeric => {
+                    let number = std::str::from_utf8(value)
+                        .ok()
+                        .and_then(|s| s.trim().parse::<f64>().ok())
+                        .filter(|n| !n.is_nan());
+                    match number {
+                        Some(number) => Indexed::Numeric(number),
+                        None => {
+                            self.failures += 1;
+                            return;
+                        }
+                    }
+                }
+            }));
+        }
+
+        for (value, index) in values.iter().zip(&mut self.fields) {
+            match (value, index) {
+                (Some(Indexed::Text(terms)), FieldIndex::Text(index)) => {
+                    for term in terms.keys() {
+                        index.entry(term.clone()).or_default().insert(key.clone());
+                    }
+                }
+                (Some(Indexed::Tag(tags)), FieldIndex::Tag(index)) => {
+                    for tag in tags {
+                        index.entry(tag.clone()).or_default().insert(key.clone());
+                    }
+                }
+                (Some(Indexed::Numeric(n)), FieldIndex::Numeric(index)) => {
+                    index.insert((Number(*n), key.clone()));
+                }
+                _ => {}
+            }
+        }
+        self.docs.insert(
+            key.clone(),
+            Doc {
+                id: self.next_id,
+                values,
+            },
+        );
+        self.next_id += 1;
+    }
+
+    fn remove(&mut self, key: &Bytes) {
+        let Some(doc) = self.docs.remove(key) else {
+            return;
+        };
+        for (value, index) in doc.values.iter().zip(&mut self.fields) {
+            match (value, index) {
+                (Some(Indexed::Text(terms)), FieldIndex::Text(index)) => {
+                    for term in terms.keys() {
+                        unlink(index, term, key);
+                    }
+                }
+                (Some(Indexed::Tag(tags)), FieldIndex::Tag(index)) => {
+                    for tag in tags {
+                        unlink(index, tag, key);
+                    }
+                }
+                (Some(Indexed::Numeric(n)), FieldIndex::Numeric(index)) => {
+                    index.remove(&(Number(*n), key.clone()));
+                }
+                _ => {}
+            }
+        }
+    }
+
+    // The schema position of a field, by the name queries use for it
+    fn resolve(&self, attribute: &[u8]) -> Result<usize, DbError> {
+        self.spec
+            .schema
+            .iter()
+            .position(|field| field.attribute() == attribute)
+            .ok_or_else(|| DbError::FtUnknownField(String::from_utf8_lossy(attribute).into()))
+    }
+
+    fn eval(&self, node: &Node) -> Result<HashSet<Bytes>, DbError> {
+        Ok(match node {
+            Node::All => self.docs.keys().cloned().collect(),
+            Node::Term {
+                field,
+                term,
+                prefix,
+            } => {
+                let positions = match field {
+                    Some(field) => {
+                        let position = self.resolve(field.as_bytes())?;
+                        if !matches!(self.fields[position], FieldIndex::Text(_)) {
+                            return Err(DbError::FtFieldType(field.clone(), "TEXT"));
+                        }
+                        vec![position]
+                    }
+                    None => (0..self.fields.len()).collect(),
+                };
+                let mut keys = HashSet::new();
+                for position in positions {
+                    if let FieldIndex::Text(index) = &self.fields[position] {
+                        keys.extend(postings(index, term, *prefix));
+                    }
+                }
+                keys
+            }
+            Node::Tag { field, tags } => {
+                let position = self.resolve(field.as_bytes())?;
+                let (FieldIndex::Tag(index), FieldType::Tag { case_sensitive, .. }) =
+                    (&self.fields[position], &self.spec.schema[position].kind)
+                else {
+                    return Err(DbError::FtFieldType(field.clone(), "TAG"));
+                };
+                let mut keys = HashSet::new();
+                for (tag, prefix) in tags {
+                    let tag = match case_sensitive {
+                        true => tag.clone(),
+                        false => tag.to_lowercase(),
+                    };
+                    keys.extend(postings(index, &tag, *prefix));
+                }
+                keys
+            }
+            Node::Numeric { field, min, max } => {
+                let position = self.resolve(field.as_bytes())?;
+                let FieldIndex::Numeric(index) = &self.fields[position] else {
+                    return Err(DbError::FtFieldType(field.clone(), "NUMERIC"));
+                };
+                let start = match min {
+                    Bound::Included(n) | Bound::Excluded(n) => Number(*n),
+                    Bound::Unbounded => Number(f64::NEG_INFINITY),
+                };
+                index
+                    .range((start, Bytes::new())..)
+                    .filter(|(n, _)| !matches!(min, Bound::Excluded(m) if n.0 == *m))
+                    .take_while(|(n, _)| match max {
+                        Bound::Included(m) => n.0 <= *m,
+                        Bound::Excluded(m) => n.0 < *m,
+                        Bound::Unbounded => true,
+                    })
+                    .map(|(_, key)| key.clone())
+                    .collect()
+            }
+            Node::And(nodes) => {
+                let mut keys = self.eval(&nodes[0])?;
+                for node in &nodes[1..] {
+                    let other = self.eval(node)?;
+                    keys.retain(|key| other.contains(key));
+                }
+                keys
+            }
+            Node::Or(nodes) => {
+                let mut keys = HashSet::new();
+                for node in nodes {
+                    keys.extend(self.eval(node)?);
+                }
+                keys
+            }
+            Node::Not(inner) => {
+                let excluded = self.eval(inner)?;
+                self.docs
+                    .keys()
+                    .filter(|key| !excluded.contains(*key))
+                    .cloned()
+                    .collect()
+            }
+        })
+    }
+
+    // TF-IDF over the query's terms and the TEXT fields they may match, weighted per field.
+    // Queries without any terms score every document 1.
+    fn score(&self, key: &Bytes, terms: &[(Option<&str>, &str, bool)]) -> f64 {
+        if terms.is_empty() {
+            return 1.0;
+        }
+        let doc = &self.docs[key];
+        let total = self.docs.len() as f64;
+        let mut score = 0.0;
+        for &(field, term, prefix) in terms {
+            for (position, spec) in self.spec.schema.iter().enumerate() {
+                let (
+                    FieldType::Text { weight },
+                    Some(Indexed::Text(counts)),
+                    FieldIndex::Text(index),
+                ) = (&spec.kind, &doc.values[position], &self.fields[position])
+                else {
+                    continue;
+                };
+                if field.is_some_and(|f| spec.attribute() != f.as_bytes()) {
+                    continue;
+                }
+                for (t, count) in counts {
+                    if t == term || (prefix && t.starts_with(term)) {
+                        let frequency = index.get(t).map_or(1, |keys| keys.len()) as f64;
+                        score += weight * f64::from(*count) * (1.0 + total / frequency).ln();
+                    }
+                }
+            }
+        }
+        score
+    }
+
+    // What SORTBY compares: numbers for NUMERIC fields, lowercased values for the others
+    fn sort_value(
+        &self,
+        key: &Bytes,
+        position: usize,
+        hash: Option<&HashMap<Bytes, Bytes>>,
+    ) -> SortValue {
+        match &self.docs[key].values[position] {
+            Some(Indexed::Numeric(n)) => SortValue::Number(*n),
+            Some(_) => hash
+                .and_then(|hash| hash.get(&self.spec.schema[position].name))
+                .map_or(SortValue::Missing, |value| {
+                    SortValue::Text(value.to_ascii_lowercase())
+                }),
+            None => SortValue::Missing,
+        }
+    }
+
+    fn info(&self) -> FtInfo {
+        let mut num_terms = 0;
+        let mut num_records = 0;
+        for index in &self.fields {
+            match index {
+                FieldIndex::Text(index) => {
+                    num_terms += index.len();
+                    num_records += index.values().map(HashSet::len).sum::<usize>();
+                }
+                FieldIndex::Tag(index) => {
+                    num_records += index.values().map(HashSet::len).sum::<usize>();
+                }
+                FieldIndex::Numeric(index) => num_records += index.len(),
+            }
+        }
+        FtInfo {
+            spec: self.spec.clone(),
+            num_docs: self.docs.len(),
+            max_doc_id: self.next_id,
+            num_terms,
+            num_records,
+            failures: self.failures,
+        }
+    }
+}
+
+// Missing values sort after everything else, whichever the direction
+#[derive(PartialEq, PartialOrd)]
+enum SortValue {
+    Number(f64),
+    Text(Vec<u8>),
+    Missing,
+}
+
+impl Indexes {
+    fn update(&mut self, key: &Bytes, hash: Option<&HashMap<Bytes, Bytes>>) {
+        for index in self.by_name.values_mut() {
+            index.update(key, hash);
+        }
+    }
+}
+
+impl State {
+    // Brings every index covering `key` up to date with what it now holds
+    pub(super) fn reindex(&mut self, key: &Bytes) {
+        if self.indexes.by_name.is_empty() {
+            return;
+        }
+        let hash = match self.entries.get(key) {
+            Some(Entry {
+                value: Value::Hash(hash),
+                expires_at,
+            }) if expires_at.is_none_or(|exp| Instant::now() <= exp) => Some(hash),
+            _ => None,
+        };
+        self.indexes.update(key, hash);
+    }
+
+    fn index(&self, name: &Bytes) -> Result<&Index, DbError> {
+        self.indexes
+            .by_name
+            .get(name)
+            .ok_or(DbError::FtUnknownIndex)
+    }
+}
+
+impl Db {
+    // Indexes the hashes that already exist as well as later ones
+    pub fn ft_create(&self, name: &Bytes, spec: IndexSpec) -> Result<(), DbError> {
+        let mut state = self.lock();
+        if state.indexes.by_name.contains_key(name) {
+            return Err(DbError::FtIndexExists);
+        }
+        let mut index = Index::new(spec);
+        let now = Instant::now();
+        for (key, entry) in &state.entries {
+            if let Value::Hash(hash) = &entry.value
+                && entry.expires_at.is_none_or(|exp| now <= exp)
+                && index.covers(key)
+            {
+                index.add(key, hash);
+            }
+        }
+        state.indexes.by_name.insert(name.clone(), index);
+        Ok(())
+    }
+
+    pub fn ft_search(
+        &self,
+        name: &Bytes,
+        query: &FtQuery,
+        options: &FtSearchOptions,
+    ) -> Result<FtResults, DbError> {
+        let mut state = self.lock();
+        let matched = state.index(name)?.eval(&query.node)?;
+        // Expired hashes are only dropped from the index once something looks at them
+        let live: Vec<Bytes> = matched
+            .into_iter()
+            .filter(|key| state.live(key).is_some())
+            .collect();
+
+        let index = state.index(name)?;
+        let terms = query.terms();
+        let mut ranked: Vec<(Bytes, f64, SortValue, u64)> = Vec::with_capacity(live.len());
+        let sort_by = match &options.sort_by {
+            Some((attribute, descending)) => Some((index.resolve(attribute)?, *descending)),
+            None => None,
+        };
+        for key in live {
+            let hash = match state.entries.get(&key) {
+                Some(Entry {
+                    value: Value::Hash(hash),
+                    ..
+                }) => Some(hash),
+                _ => None,
+            };
+            let sort_value = match sort_by {
+                Some((position, _)) => index.sort_value(&key, position, hash),
+                None => SortValue::Missing,
+            };
+            let score = index.score(&key, &terms);
+            let id = index.docs[&key].id;
+            ranked.push((key, score, sort_value, id));
+        }
+
+        match sort_by {
+            Some((_, descending)) => ranked.sort_by(|a, b| {
+                let order = match (&a.2, &b.2) {
+                    (SortValue::Missing, SortValue::Missing) => Ordering::Equal,
+                    (SortValue::Missing, _) => Ordering::Greater,
+                    (_, SortValue::Missing) => Ordering::Less,
+                    (x, y) if descending => y.partial_cmp(x).unwrap_or(Ordering::Equal),
+                    (x, y) => x.partial_cmp(y).unwrap_or(Ordering::Equal),
+                };
+                order.then(a.3.cmp(&b.3))
+            }),
+            None => ranked.sort_by(|a, b| b.1.total_cmp(&a.1).then(a.3.cmp(&b.3))),
+        }
+
+        let total = ranked.len();
+        let docs = ranked
+            .into_iter()
+            .skip(options.offset)
+            .take(options.limit)
+            .map(|(key, score, _, _)| {
+                let hash = match state.entries.get(&key) {
+                    Some(Entry {
+                        value: Value::Hash(hash),
+                        ..
+                    }) => Some(hash),
+                    _ => None,
+                };
+                let fields = match (&options.return_fields, hash) {
+                    _ if options.no_content => Vec::new(),
+                    (_, None) => Vec::new(),
+                    (Some(names), Some(hash)) => names
+                        .iter()
+                        .filter_map(|name| {
+                            let field = index
+                                .resolve(name)
+                                .map_or(name, |p| &index.spec.schema[p].name);
+                            hash.get(field).map(|value| (name.clone(), value.clone()))
+                        })
+                        .collect(),
+                    (None, Some(hash)) => hash
+                        .iter()
+                        .map(|(field, value)| (field.clone(), value.clone()))
+                        .collect(),
+                };
+                FtDoc { key, score, fields }
+            })
+            .collect();
+        Ok(FtResults { total, docs })
+    }
+
+    pub fn ft_info(&self, name: &Bytes) -> Result<FtInfo, DbError> {
+        let state = self.lock();
+        Ok(state.index(name)?.info())
+    }
+
+    // With `delete_docs` the indexed hashes are deleted too
+    pub fn ft_dropindex(&self, name: &Bytes, delete_docs: bool) -> Result<(), DbError> {
+        let mut state = self.lock();
+        let index = state
+            .indexes
+            .by_name
+            .remove(name)
+            .ok_or(DbError::FtUnknownIndex)?;
+        if delete_docs {
+            for key in index.docs.keys() {
+                if state.entries.remove(key).is_some() {
+                    state.notify(Class::Generic, "del", key);
+                }
+                state.reindex(key);
+            }
+        }
+        Ok(())
+    }
+}
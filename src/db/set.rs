@@ -0,0 +1,292 @@
+use super::{Class, Db, DbError, Entry, State, Value};
+use bytes::Bytes;
+use rand::seq::{IndexedRandom, IteratorRandom};
+use std::collections::HashSet;
+
+// The set algebra operations shared by SINTER, SUNION, SDIFF and their STORE variants
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SetOp {
+    Inter,
+    Union,
+    Diff,
+}
+
+impl SetOp {
+    fn store_event(self) -> &'static str {
+        match self {
+            SetOp::Inter => "sinterstore",
+            SetOp::Union => "sunionstore",
+            SetOp::Diff => "sdiffstore",
+        }
+    }
+}
+
+impl Db {
+    pub fn sadd(&self, key: &Bytes, members: Vec<Bytes>) -> Result<usize, DbError> {
+        let mut state = self.lock();
+        let set = state.set_or_insert(key)?;
+        let added = members
+            .into_iter()
+            .filter(|m| set.insert(m.clone()))
+            .count();
+        if added > 0 {
+            state.notify(Class::Set, "sadd", key);
+        }
+        Ok(added)
+    }
+
+    pub fn srem(&self, key: &Bytes, members: &[Bytes]) -> Result<usize, DbError> {
+        let mut state = self.lock();
+        let Some(set) = state.set_mut(key)? else {
+            return Ok(0);
+        };
+        let removed = members.iter().filter(|m| set.remove(*m)).count();
+        if removed > 0 {
+            state.notify(Class::Set, "srem", key);
+        }
+        state.remove_if_empty(key);
+        Ok(removed)
+    }
+
+    pub fn sismember(&self, key: &Bytes, member: &Bytes) -> Result<bool, DbError> {
+        let mut state = self.lock_read();
+        Ok(state.set_mut(key)?.is_some_and(|set| set.contains(member)))
+    }
+
+    pub fn smismember(&self, key: &Bytes, members: &[Bytes]) -> Result<Vec<bool>, DbError> {
+        let mut state = self.lock_read();
+        let set = state.set_mut(key)?;
+        Ok(members
+            .iter()
+            .map(|m| set.as_ref().is_some_and(|set| set.contains(m)))
+            .collect())
+    }
+
+    pub fn smembers(&self, key: &Bytes) -> Result<Vec<Bytes>, DbError> {
+        let mut state = self.lock_read();
+        Ok(state
+            .set_mut(key)?
+            .map(|set| set.iter().cloned().collect())
+            .unwrap_or_default())
+    }
+
+    pub fn scard(&self, key: &Bytes) -> Result<usize, DbError> {
+        let mut state = self.lock_read();
+        Ok(state.set_mut(key)?.map_or(0, |set| set.len()))
+    }
+
+    // Remove and return up to `count` random members
+    pub fn spop(&self, key: &Bytes, count: usize) -> Result<Vec<Bytes>, DbError> {
+        let mut state = self.lock();
+        let Some(set) = state.set_mut(key)? else {
+            return Ok(Vec::new());
+        };
+
+        // Sampling allocates for `count` up front, so never ask for more than there is
+        let count = count.min(set.len());
+        let popped = set.iter().cloned().choose_multiple(&mut rand::rng(), count);
+        for member in &popped {
+            set.remove(member);
+        }
+
+        if !popped.is_empty() {
+            state.notify(Class::Set, "spop", key);
+        }
+        state.remove_if_empty(key);
+        Ok(popped)
+    }
+
+    // A positive count returns distinct members, a negative count may repeat them
+    pub fn srandmember(&self, key: &Bytes, count: i64) -> Result<Vec<Bytes>, DbError> {
+        let mut state = self.lock_read();
+        let Some(set) = state.set_mut(key)? else {
+            return Ok(Vec::new());
+        };
+        let mut rng = rand::rng();
+
+        if count >= 0 {
+            let count = (count as usize).min(set.len());
+            return Ok(set.iter().cloned().choose_multiple(&mut rng, count));
+        }
+
+        let members: Vec<&Bytes> = set.iter().collect();
+        Ok((0..count.unsigned_abs())
+            .filter_map(|_| members.choose(&mut rng).map(|m| (*m).clone()))
+            .collect())
+    }
+
+    pub fn set_op(&self, op: SetOp, keys: &[Bytes]) -> Result<Vec<Bytes>, DbError> {
+        let mut state = self.lock_read();
+        let sets = state.sets(keys)?;
+        Ok(combine(op, &sets).into_iter().collect())
+    }
+
+    // Store the result in `destination`, replacing whatever it held before
+    pub fn set_op_store(
+        &self,
+        op: SetOp,
+        destination: &Bytes,
+        keys: &[Bytes],
+    ) -> Result<usize, DbError> {
+        let mut state = self.lock();
+        let result = combine(op, &state.sets(keys)?);
+        let len = result.len();
+
+        if result.is_empty() {
+            if state.entries.remove(destination).is_some() {
+                state.notify(Class::Generic, "del", destination);
+            }
+        } else {
+            state.insert(
+                destination.clone(),
+                Entry {
+                    value: Value::Set(result),
+                    expires_at: None,
+                },
+            );
+            state.notify(Class::Set, op.store_event(), destination);
+        }
+        state.reindex(destination);
+
+        Ok(len)
+    }
+
+    // Cardinality of the intersection, stopping early once `limit` is reached (0 means no limit)
+    pub fn sintercard(&self, keys: &[Bytes], limit: usize) -> Result<usize, DbError> {
+        let mut state = self.lock_read();
+        let sets = state.sets(keys)?;
+        let Some(sets) = sets.into_iter().collect::<Option<Vec<_>>>() else {
+            return Ok(0);
+        };
+        let Some((smallest, others)) = smallest_first(sets) else {
+            return Ok(0);
+        };
+
+        let mut count = 0;
+        for member in smallest {
+            if others.iter().all(|s| s.contains(member)) {
+                count += 1;
+                if count == limit {
+                    break;
+                }
+            }
+        }
+        Ok(count)
+    }
+
+    pub fn smove(
+        &self,
+        source: &Bytes,
+        destination: &Bytes,
+        member: &Bytes,
+    ) -> Result<bool, DbError> {
+        let mut state = self.lock();
+
+        // Both keys must hold sets before anything is moved
+        state.set_mut(destination)?;
+        let Some(set) = state.set_mut(source)? else {
+            return Ok(false);
+        };
+        if !set.contains(member) {
+            return Ok(false);
+        }
+        if source == destination {
+            return Ok(true);
+        }
+
+        set.remove(member);
+        state.notify(Class::Set, "srem", source);
+        state.remove_if_empty(source);
+        if state.set_or_insert(destination)?.insert(member.clone()) {
+            state.notify(Class::Set, "sadd", destination);
+        }
+        Ok(true)
+    }
+}
+
+impl State {
+    fn set_mut(&mut self, key: &Bytes) -> Result<Option<&mut HashSet<Bytes>>, DbError> {
+        match self.live(key) {
+            Some(Entry {
+                value: Value::Set(set),
+                ..
+            }) => Ok(Some(set)),
+            Some(_) => Err(DbError::WrongType),
+            None => Ok(None),
+        }
+    }
+
+    fn set_or_insert(&mut self, key: &Bytes) -> Result<&mut HashSet<Bytes>, DbError> {
+        if self.live(key).is_none() {
+            self.insert(
+                key.clone(),
+                Entry {
+                    value: Value::Set(HashSet::new()),
+                    expires_at: None,
+                },
+            );
+        }
+        self.set_mut(key)?.ok_or(DbError::WrongType)
+    }
+
+    // Look up several sets at once; missing keys are None
+    fn sets(&mut self, keys: &[Bytes]) -> Result<Vec<Option<&HashSet<Bytes>>>, DbError> {
+        for key in keys {
+            self.live(key);
+        }
+
+        keys.iter()
+            .map(|key| match self.entries.get(key) {
+                Some(Entry {
+                    value: Value::Set(set),
+                    ..
+                }) => Ok(Some(set)),
+                Some(_) => Err(DbError::WrongType),
+                None => Ok(None),
+            })
+            .collect()
+    }
+}
+
+fn combine(op: SetOp, sets: &[Option<&HashSet<Bytes>>]) -> HashSet<Bytes> {
+    match op {
+        SetOp::Inter => {
+            // A missing key is an empty set, so the intersection is empty too
+            let Some(sets) = sets.iter().copied().collect::<Option<Vec<_>>>() else {
+                return HashSet::new();
+            };
+            let Some((smallest, others)) = smallest_first(sets) else {
+                return HashSet::new();
+            };
+            smallest
+                .iter()
+                .filter(|m| others.iter().all(|s| s.contains(*m)))
+                .cloned()
+                .collect()
+        }
+        SetOp::Union => sets
+            .iter()
+            .flatten()
+            .flat_map(|s| s.iter().cloned())
+            .collect(),
+        SetOp::Diff => {
+            let Some(Some(first)) = sets.first() else {
+                return HashSet::new();
+            };
+            first
+                .iter()
+                .filter(|m| sets[1..].iter().flatten().all(|s| !s.contains(*m)))
+                .cloned()
+                .collect()
+        }
+    }
+}
+
+// Split off the smallest set so intersections only walk the fewest members
+fn smallest_first(
+    mut sets: Vec<&HashSet<Bytes>>,
+) -> Option<(&HashSet<Bytes>, Vec<&HashSet<Bytes>>)> {
+    let (idx, _) = sets.iter().enumerate().min_by_key(|(_, s)| s.len())?;
+    let smallest = sets.swap_remove(idx);
+    Some((smallest, sets))
+}
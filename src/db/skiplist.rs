@@ -0,0 +1,291 @@
+// A skiplist ordered by (score, member), modelled on the one inside Redis' zset.
+// Each forward link records its span so rank lookups stay O(log n).
+use bytes::Bytes;
+use std::cmp::Ordering;
+
+const MAX_LEVEL: usize = 32;
+const P: f64 = 0.25;
+
+// The header node always lives in the first slot of the arena
+const HEAD: usize = 0;
+
+pub(super) struct SkipList {
+    nodes: Vec<Node>,
+    free: Vec<usize>,
+    tail: Option<usize>,
+    len: usize,
+    level: usize,
+}
+
+struct Node {
+    member: Bytes,
+    score: f64,
+    backward: Option<usize>,
+    levels: Vec<Level>,
+}
+
+#[derive(Clone, Copy)]
+struct Level {
+    forward: Option<usize>,
+    span: usize,
+}
+
+impl Default for SkipList {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SkipList {
+    pub(super) fn new() -> SkipList {
+        SkipList {
+            nodes: vec![Node {
+                member: Bytes::new(),
+                score: 0.0,
+                backward: None,
+                levels: vec![
+                    Level {
+                        forward: None,
+                        span: 0,
+                    };
+                    MAX_LEVEL
+                ],
+            }],
+            free: Vec::new(),
+            tail: None,
+            len: 0,
+            level: 1,
+        }
+    }
+
+    // The caller must make sure the member isn't already in the list
+    pub(super) fn insert(&mut self, score: f64, member: Bytes) {
+        let mut update = [HEAD; MAX_LEVEL];
+        let mut rank = [0usize; MAX_LEVEL];
+
+        let mut x = HEAD;
+        for i in (0..self.level).rev() {
+            rank[i] = if i == self.level - 1 { 0 } else { rank[i + 1] };
+            while let Some(next) = self.nodes[x].levels[i].forward {
+                if self.cmp_node(next, score, &member) != Ordering::Less {
+                    break;
+                }
+                rank[i] += self.nodes[x].levels[i].span;
+                x = next;
+            }
+            update[i] = x;
+        }
+
+        let level = random_level();
+        if level > self.level {
+            for i in self.level..level {
+                rank[i] = 0;
+                update[i] = HEAD;
+                self.nodes[HEAD].levels[i].span = self.len;
+            }
+            self.level = level;
+        }
+
+        let x = self.alloc(Node {
+            member,
+            score,
+            backward: None,
+            levels: vec![
+                Level {
+                    forward: None,
+                    span: 0,
+                };
+                level
+            ],
+        });
+
+        for i in 0..level {
+            let prev = update[i];
+            self.nodes[x].levels[i].forward = self.nodes[prev].levels[i].forward;
+            self.nodes[prev].levels[i].forward = Some(x);
+            self.nodes[x].levels[i].span = self.nodes[prev].levels[i].span - (rank[0] - rank[i]);
+            self.nodes[prev].levels[i].span = rank[0] - rank[i] + 1;
+        }
+        for (i, &prev) in update.iter().enumerate().take(self.level).skip(level) {
+            self.nodes[prev].levels[i].span += 1;
+        }
+
+        self.nodes[x].backward = (update[0] != HEAD).then_some(update[0]);
+        match self.nodes[x].levels[0].forward {
+            Some(next) => self.nodes[next].backward = Some(x),
+            None => self.tail = Some(x),
+        }
+        self.len += 1;
+    }
+
+    pub(super) fn remove(&mut self, score: f64, member: &Bytes) -> bool {
+        let mut update = [HEAD; MAX_LEVEL];
+
+        let mut x = HEAD;
+        for i in (0..self.level).rev() {
+            while let Some(next) = self.nodes[x].levels[i].forward {
+                if self.cmp_node(next, score, member) != Ordering::Less {
+                    break;
+                }
+                x = next;
+            }
+            update[i] = x;
+        }
+
+        let Some(x) = self.nodes[x].levels[0].forward else {
+            return false;
+        };
+        if self.cmp_node(x, score, member) != Ordering::Equal {
+            return false;
+        }
+
+        for (i, &prev) in update.iter().enumerate().take(self.level) {
+            if self.nodes[prev].levels[i].forward == Some(x) {
+                self.nodes[prev].levels[i].span += self.nodes[x].levels[i].span;
+                self.nodes[prev].levels[i].span -= 1;
+                self.nodes[prev].levels[i].forward = self.nodes[x].levels[i].forward;
+            } else {
+                self.nodes[prev].levels[i].span -= 1;
+            }
+        }
+
+        match self.nodes[x].levels[0].forward {
+            Some(next) => self.nodes[next].backward = self.nodes[x].backward,
+            None => self.tail = self.nodes[x].backward,
+        }
+        while self.level > 1 && self.nodes[HEAD].levels[self.level - 1].forward.is_none() {
+            self.level -= 1;
+        }
+
+        self.release(x);
+        self.len -= 1;
+        true
+    }
+
+    // 0-based rank of an element
+    pub(super) fn rank(&self, score: f64, member: &Bytes) -> Option<usize> {
+        let mut rank = 0;
+        let mut x = HEAD;
+        for i in (0..self.level).rev() {
+            while let Some(next) = self.nodes[x].levels[i].forward {
+                if self.cmp_node(next, score, member) == Ordering::Greater {
+                    break;
+                }
+                rank += self.nodes[x].levels[i].span;
+                x = next;
+            }
+            if x != HEAD && self.cmp_node(x, score, member) == Ordering::Equal {
+                return Some(rank - 1);
+            }
+        }
+        None
+    }
+
+    // The node at a 0-based rank
+    pub(super) fn by_rank(&self, rank: usize) -> Option<usize> {
+        let target = rank + 1;
+        let mut traversed = 0;
+        let mut x = HEAD;
+        for i in (0..self.level).rev() {
+            while let Some(next) = self.nodes[x].levels[i].forward {
+                if traversed + self.nodes[x].levels[i].span > target {
+                    break;
+                }
+                traversed += self.nodes[x].levels[i].span;
+                x = next;
+            }
+            if traversed == target {
+                return Some(x);
+            }
+        }
+        None
+    }
+
+    // The first node for which `before` is false, where `before` holds for a prefix of the list
+    pub(super) fn first_where(&self, before: impl Fn(f64, &Bytes) -> bool) -> Option<usize> {
+        let mut x = HEAD;
+        for i in (0..self.level).rev() {
+            while let Some(next) = self.nodes[x].levels[i].forward {
+                if !before(self.nodes[next].score, &self.nodes[next].member) {
+                    break;
+                }
+                x = next;
+            }
+        }
+        self.nodes[x].levels[0].forward
+    }
+
+    // The last node for which `after` is false, where `after` holds for a suffix of the list
+    pub(super) fn last_where(&self, after: impl Fn(f64, &Bytes) -> bool) -> Option<usize> {
+        let mut x = HEAD;
+        for i in (0..self.level).rev() {
+            while let Some(next) = self.nodes[x].levels[i].forward {
+                if after(self.nodes[next].score, &self.nodes[next].member) {
+                    break;
+                }
+                x = next;
+            }
+        }
+        (x != HEAD).then_some(x)
+    }
+
+    pub(super) fn first(&self) -> Option<usize> {
+        self.nodes[HEAD].levels[0].forward
+    }
+
+    pub(super) fn last(&self) -> Option<usize> {
+        self.tail
+    }
+
+    pub(super) fn next(&self, node: usize) -> Option<usize> {
+        self.nodes[node].levels[0].forward
+    }
+
+    pub(super) fn prev(&self, node: usize) -> Option<usize> {
+        self.nodes[node].backward
+    }
+
+    pub(super) fn score(&self, node: usize) -> f64 {
+        self.nodes[node].score
+    }
+
+    pub(super) fn member(&self, node: usize) -> &Bytes {
+        &self.nodes[node].member
+    }
+
+    fn cmp_node(&self, node: usize, score: f64, member: &Bytes) -> Ordering {
+        let node = &self.nodes[node];
+        node.score
+            .partial_cmp(&score)
+            .unwrap_or(Ordering::Equal)
+            .then_with(|| node.member.cmp(member))
+    }
+
+    fn alloc(&mut self, node: Node) -> usize {
+        match self.free.pop() {
+            Some(idx) => {
+                self.nodes[idx] = node;
+                idx
+            }
+            None => {
+                self.nodes.push(node);
+                self.nodes.len() - 1
+            }
+        }
+    }
+
+    fn release(&mut self, node: usize) {
+        self.nodes[node].member = Bytes::new();
+        self.nodes[node].levels = Vec::new();
+        self.nodes[node].backward = None;
+        self.free.push(node);
+    }
+}
+
+fn random_level() -> usize {
+    let mut level = 1;
+    while level < MAX_LEVEL && rand::random::<f64>() < P {
+        level += 1;
+    }
+    level
+}
@@ -0,0 +1,652 @@
+// Streams keep their entries packed into nodes of up to NODE_MAX_ENTRIES entries, like the
+// listpacks in Redis' radix tree, so appending an entry doesn't allocate for it.
+use super::blocking::StopReading;
+use super::group::ConsumerGroup;
+use super::{Class, Db, DbError, Entry, State, Value};
+use bytes::Bytes;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::sync::mpsc;
+
+const NODE_MAX_ENTRIES: usize = 100;
+const NODE_MAX_BYTES: usize = 4096;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct StreamId {
+    pub ms: u64,
+    pub seq: u64,
+}
+
+pub type StreamEntry = (StreamId, Vec<(Bytes, Bytes)>);
+
+// The ID given to XADD
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum XAddId {
+    // `*`
+    Auto,
+    // `ms-*`
+    Partial(u64),
+    Explicit(StreamId),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TrimStrategy {
+    MaxLen(usize),
+    MinId(StreamId),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct StreamTrim {
+    pub strategy: TrimStrategy,
+    // With `~` only whole nodes are removed, so slightly more entries may be kept
+    pub approx: bool,
+    pub limit: Option<usize>,
+}
+
+// What XINFO STREAM reports
+#[derive(Debug, Clone, PartialEq)]
+pub struct StreamInfo {
+    pub length: usize,
+    pub nodes: usize,
+    pub last_generated_id: StreamId,
+    pub max_deleted_id: StreamId,
+    pub entries_added: u64,
+    pub groups: usize,
+    pub first_entry: Option<StreamEntry>,
+    pub last_entry: Option<StreamEntry>,
+}
+
+// Where XREAD starts reading a stream from
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum XReadFrom {
+    // Entries after this ID
+    After(StreamId),
+    // `$`: only entries added after the read started
+    New,
+    // `+`: the last entry
+    Last,
+}
+
+impl StreamId {
+    pub const MIN: StreamId = StreamId { ms: 0, seq: 0 };
+    pub const MAX: StreamId = StreamId {
+        ms: u64::MAX,
+        seq: u64::MAX,
+    };
+
+    pub fn next(self) -> Option<StreamId> {
+        match self.seq.checked_add(1) {
+            Some(seq) => Some(StreamId { ms: self.ms, seq }),
+            None => Some(StreamId {
+                ms: self.ms.checked_add(1)?,
+                seq: 0,
+            }),
+        }
+    }
+
+    pub fn prev(self) -> Option<StreamId> {
+        match self.seq.checked_sub(1) {
+            Some(seq) => Some(StreamId { ms: self.ms, seq }),
+            None => Some(StreamId {
+                ms: self.ms.checked_sub(1)?,
+                seq: u64::MAX,
+            }),
+        }
+    }
+}
+
+impl fmt::Display for StreamId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}-{}", self.ms, self.seq)
+    }
+}
+
+// A run of entries packed back to back. Each entry is its ms as a delta from the node's first
+// ID, its seq, a deleted flag, the field count, then each field and value with its length.
+struct Node {
+    master: StreamId,
+    last: StreamId,
+    data: Vec<u8>,
+    live: usize,
+    total: usize,
+}
+
+// An entry decoded from a node, with the offset of its deleted flag
+struct Packed {
+    id: StreamId,
+    flag_at: usize,
+    deleted: bool,
+    fields: Vec<(Bytes, Bytes)>,
+}
+
+impl Node {
+    fn new(master: StreamId) -> Node {
+        Node {
+            master,
+            last: master,
+            data: Vec::new(),
+            live: 0,
+            total: 0,
+        }
+    }
+
+    fn is_full(&self) -> bool {
+        self.total >= NODE_MAX_ENTRIES || self.data.len() >= NODE_MAX_BYTES
+    }
+
+    fn push(&mut self, id: StreamId, fields: &[(Bytes, Bytes)]) {
+        put_varint(&mut self.data, id.ms - self.master.ms);
+        put_varint(&mut self.data, id.seq);
+        self.data.push(0);
+        put_varint(&mut self.data, fields.len() as u64);
+        for (field, value) in fields {
+            put_varint(&mut self.data, field.len() as u64);
+            self.data.extend_from_slice(field);
+            put_varint(&mut self.data, value.len() as u64);
+            self.data.extend_from_slice(value);
+        }
+        self.last = id;
+        self.live += 1;
+        self.total += 1;
+    }
+
+    fn entries(&self) -> impl Iterator<Item = Packed> + '_ {
+        let mut pos = 0;
+        std::iter::from_fn(move || {
+            if pos >= self.data.len() {
+                return None;
+            }
+            let ms = self.master.ms + get_varint(&self.data, &mut pos);
+            let seq = get_varint(&self.data, &mut pos);
+            let flag_at = pos;
+            let deleted = self.data[pos] != 0;
+            pos += 1;
+
+            let count = get_varint(&self.data, &mut pos) as usize;
+            let mut fields = Vec::with_capacity(count);
+            for _ in 0..count {
+                let field = get_bytes(&self.data, &mut pos);
+                let value = get_bytes(&self.data, &mut pos);
+                fields.push((field, value));
+            }
+
+            Some(Packed {
+                id: StreamId { ms, seq },
+                flag_at,
+                deleted,
+                fields,
+            })
+        })
+    }
+
+    fn live_entries(&self) -> impl Iterator<Item = Packed> + '_ {
+        self.entries().filter(|e| !e.deleted)
+    }
+
+    // Mark an entry deleted in place, returning false if it wasn't there
+    fn delete(&mut self, id: StreamId) -> bool {
+        let Some(flag_at) = self.live_entries().find(|e| e.id == id).map(|e| e.flag_at) else {
+            return false;
+        };
+        self.data[flag_at] = 1;
+        self.live -= 1;
+        true
+    }
+}
+
+fn put_varint(buf: &mut Vec<u8>, mut n: u64) {
+    while n >= 0x80 {
+        buf.push((n as u8) | 0x80);
+        n >>= 7;
+    }
+    buf.push(n as u8);
+}
+
+fn get_varint(buf: &[u8], pos: &mut usize) -> u64 {
+    let mut n = 0;
+    let mut shift = 0;
+    loop {
+        let byte = buf[*pos];
+        *pos += 1;
+        n |= u64::from(byte & 0x7f) << shift;
+        if byte & 0x80 == 0 {
+            return n;
+        }
+        shift += 7;
+    }
+}
+
+fn get_bytes(buf: &[u8], pos: &mut usize) -> Bytes {
+    let len = get_varint(buf, pos) as usize;
+    let bytes = Bytes::copy_from_slice(&buf[*pos..*pos + len]);
+    *pos += len;
+    bytes
+}
+
+#[derive(Default)]
+pub(super) struct Stream {
+    // Keyed by the first ID in each node
+    nodes: BTreeMap<StreamId, Node>,
+    len: usize,
+    last_id: StreamId,
+    // Everything XINFO STREAM reports that can't be worked out from the entries
+    entries_added: u64,
+    max_deleted_id: StreamId,
+    pub(super) groups: BTreeMap<Bytes, ConsumerGroup>,
+}
+
+// Milliseconds since the Unix epoch, which stream IDs and idle times are measured in
+pub(super) fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_millis() as u64)
+}
+
+impl Stream {
+    pub(super) fn len(&self) -> usize {
+        self.len
+    }
+
+    pub(super) fn last_id(&self) -> StreamId {
+        self.last_id
+    }
+
+    pub(super) fn entries_added(&self) -> u64 {
+        self.entries_added
+    }
+
+    pub(super) fn info(&self) -> StreamInfo {
+        StreamInfo {
+            length: self.len,
+            nodes: self.nodes.len(),
+            last_generated_id: self.last_id,
+            max_deleted_id: self.max_deleted_id,
+            entries_added: self.entries_added,
+            groups: self.groups.len(),
+            first_entry: self.first_entry(),
+            last_entry: self.last_entry(),
+        }
+    }
+
+    // Work out the ID for a new entry, which must be greater than every ID used so far
+    fn next_id(&self, id: XAddId) -> Result<StreamId, DbError> {
+        let last = self.last_id;
+        let id = match id {
+            XAddId::Explicit(id) => {
+                if id == StreamId::MIN {
+                    return Err(DbError::StreamIdZero);
+                }
+                id
+            }
+            XAddId::Partial(ms) if ms == last.ms => StreamId {
+                ms,
+                seq: last.seq.checked_add(1).ok_or(DbError::StreamIdTooSmall)?,
+            },
+            XAddId::Partial(ms) => StreamId {
+                ms,
+                seq: if ms == 0 { 1 } else { 0 },
+            },
+            XAddId::Auto => {
+                let now = now_ms();
+                if now > last.ms {
+                    StreamId { ms: now, seq: 0 }
+                } else {
+                    last.next().ok_or(DbError::StreamExhausted)?
+                }
+            }
+        };
+
+        if id <= last {
+            return Err(DbError::StreamIdTooSmall);
+        }
+        Ok(id)
+    }
+
+    fn push(&mut self, id: StreamId, fields: &[(Bytes, Bytes)]) {
+        if self
+            .nodes
+            .last_key_value()
+            .is_none_or(|(_, node)| node.is_full())
+        {
+            self.nodes.insert(id, Node::new(id));
+        }
+        if let Some(mut last) = self.nodes.last_entry() {
+            last.get_mut().push(id, fields);
+        }
+        self.len += 1;
+        self.last_id = id;
+        self.entries_added += 1;
+    }
+
+    // Entries with IDs in [start, end], oldest first unless `rev` is set
+    pub(super) fn range(
+        &self,
+        start: StreamId,
+        end: StreamId,
+        count: Option<usize>,
+        rev: bool,
+    ) -> Vec<StreamEntry> {
+        if start > end {
+            return Vec::new();
+        }
+        let count = count.unwrap_or(usize::MAX);
+        let mut out = Vec::new();
+
+        // The node holding `start` may begin before it
+        let first = self
+            .nodes
+            .range(..=start)
+            .next_back()
+            .map_or(start, |(master, _)| *master);
+        let nodes = self.nodes.range(first..=end);
+
+        if rev {
+            for (_, node) in nodes.rev() {
+                let mut entries: Vec<Packed> = node.live_entries().collect();
+                while let Some(entry) = entries.pop() {
+                    if out.len() >= count || entry.id < start {
+                        return out;
+                    }
+                    if entry.id <= end {
+                        out.push((entry.id, entry.fields));
+                    }
+                }
+            }
+        } else {
+            for (_, node) in nodes {
+                for entry in node.live_entries() {
+                    if out.len() >= count || entry.id > end {
+                        return out;
+                    }
+                    if entry.id >= start {
+                        out.push((entry.id, entry.fields));
+                    }
+                }
+            }
+        }
+        out
+    }
+
+    pub(super) fn get(&self, id: StreamId) -> Option<Vec<(Bytes, Bytes)>> {
+        self.range(id, id, Some(1), false)
+            .pop()
+            .map(|(_, fields)| fields)
+    }
+
+    pub(super) fn first_entry(&self) -> Option<StreamEntry> {
+        self.range(StreamId::MIN, StreamId::MAX, Some(1), false)
+            .pop()
+    }
+
+    pub(super) fn last_entry(&self) -> Option<StreamEntry> {
+        self.range(StreamId::MIN, StreamId::MAX, Some(1), true)
+            .pop()
+    }
+
+    pub(super) fn delete(&mut self, id: StreamId) -> bool {
+        let Some((&master, node)) = self.nodes.range_mut(..=id).next_back() else {
+            return false;
+        };
+        if !node.delete(id) {
+            return false;
+        }
+        if node.live == 0 {
+            self.nodes.remove(&master);
+        }
+        self.len -= 1;
+        self.max_deleted_id = self.max_deleted_id.max(id);
+        true
+    }
+
+    // Returns the number of entries removed
+    pub(super) fn trim(&mut self, trim: &StreamTrim) -> usize {
+        let limit = trim.limit.unwrap_or(usize::MAX);
+        let mut removed = 0;
+
+        while let Some(mut first) = self.nodes.first_entry() {
+            let node = first.get();
+            let whole_node = match trim.strategy {
+                TrimStrategy::MaxLen(max) => self.len - node.live >= max,
+                TrimStrategy::MinId(min) => node.last < min,
+            };
+
+            if whole_node {
+                if trim.approx && removed + node.live > limit {
+                    break;
+                }
+                removed += node.live;
+                self.len -= node.live;
+                self.max_deleted_id = self.max_deleted_id.max(node.last);
+                first.remove();
+                continue;
+            }
+            if trim.approx {
+                break;
+            }
+
+            // Exact trimming deletes individual entries from the front of the node
+            let doomed: Vec<StreamId> = match trim.strategy {
+                TrimStrategy::MaxLen(max) => node
+                    .live_entries()
+                    .take(self.len.saturating_sub(max))
+                    .map(|e| e.id)
+                    .collect(),
+                TrimStrategy::MinId(min) => node
+                    .live_entries()
+                    .map(|e| e.id)
+                    .take_while(|&id| id < min)
+                    .collect(),
+            };
+            let node = first.get_mut();
+            for id in &doomed {
+                node.delete(*id);
+            }
+            removed += doomed.len();
+            self.len -= doomed.len();
+            if let Some(&last) = doomed.last() {
+                self.max_deleted_id = self.max_deleted_id.max(last);
+            }
+            break;
+        }
+        removed
+    }
+}
+
+impl Db {
+    // Returns None when NOMKSTREAM was given and the stream doesn't exist
+    pub fn xadd(
+        &self,
+        key: &Bytes,
+        id: XAddId,
+        fields: &[(Bytes, Bytes)],
+        nomkstream: bool,
+        trim: Option<&StreamTrim>,
+    ) -> Result<Option<StreamId>, DbError> {
+        let mut state = self.lock();
+        if nomkstream && state.stream_mut(key)?.is_none() {
+            return Ok(None);
+        }
+
+        let stream = match state.stream_mut(key)? {
+            Some(stream) => stream,
+            None => {
+                let stream = Stream::default();
+                // Validate before creating the key so a bad ID leaves nothing behind
+                stream.next_id(id)?;
+                state.insert(
+                    key.clone(),
+                    Entry {
+                        value: Value::Stream(stream),
+                        expires_at: None,
+                    },
+                );
+                state.stream_mut(key)?.ok_or(DbError::WrongType)?
+            }
+        };
+
+        let id = stream.next_id(id)?;
+        stream.push(id, fields);
+        let trimmed = trim.map_or(0, |trim| stream.trim(trim));
+
+        state.wake_readers(key);
+        state.notify(Class::Stream, "xadd", key);
+        if trimmed > 0 {
+            state.notify(Class::Stream, "xtrim", key);
+        }
+        Ok(Some(id))
+    }
+
+    pub fn xrange(
+        &self,
+        key: &Bytes,
+        start: StreamId,
+        end: StreamId,
+        count: Option<usize>,
+        rev: bool,
+    ) -> Result<Vec<StreamEntry>, DbError> {
+        let mut state = self.lock_read();
+        Ok(state
+            .stream_mut(key)?
+            .map(|stream| stream.range(start, end, count, rev))
+            .unwrap_or_default())
+    }
+
+    pub fn xlen(&self, key: &Bytes) -> Result<usize, DbError> {
+        let mut state = self.lock_read();
+        Ok(state.stream_mut(key)?.map_or(0, |stream| stream.len()))
+    }
+
+    pub fn xdel(&self, key: &Bytes, ids: &[StreamId]) -> Result<usize, DbError> {
+        let mut state = self.lock();
+        let Some(stream) = state.stream_mut(key)? else {
+            return Ok(0);
+        };
+        let deleted = ids.iter().filter(|id| stream.delete(**id)).count();
+        if deleted > 0 {
+            state.notify(Class::Stream, "xdel", key);
+        }
+        Ok(deleted)
+    }
+
+    pub fn xtrim(&self, key: &Bytes, trim: &StreamTrim) -> Result<usize, DbError> {
+        let mut state = self.lock();
+        let trimmed = state.stream_mut(key)?.map_or(0, |stream| stream.trim(trim));
+        if trimmed > 0 {
+            state.notify(Class::Stream, "xtrim", key);
+        }
+        Ok(trimmed)
+    }
+
+    pub fn xinfo_stream(&self, key: &Bytes) -> Result<StreamInfo, DbError> {
+        let mut state = self.lock_read();
+        let stream = state.stream_mut(key)?.ok_or(DbError::NoSuchKey)?;
+        Ok(stream.info())
+    }
+
+    // Streams with new entries, skipping those with nothing to read
+    pub fn xread(
+        &self,
+        streams: &[(Bytes, XReadFrom)],
+        count: Option<usize>,
+    ) -> Result<Vec<(Bytes, Vec<StreamEntry>)>, DbError> {
+        self.lock_read().xread(streams, count)
+    }
+
+    // XREAD BLOCK: read, or wait until one of the streams gets a new entry. `$` is resolved
+    // to each stream's last ID when the read starts. A timeout of None waits forever.
+    pub async fn xread_block(
+        &self,
+        streams: &[(Bytes, XReadFrom)],
+        count: Option<usize>,
+        timeout: Option<std::time::Duration>,
+    ) -> Result<Vec<(Bytes, Vec<StreamEntry>)>, DbError> {
+        let deadline = timeout.map(|t| tokio::time::Instant::now() + t);
+
+        let mut streams = streams.to_vec();
+        let keys: Vec<Bytes> = streams.iter().map(|(key, _)| key.clone()).collect();
+        let (tx, mut rx) = mpsc::channel(1);
+        let _reading = StopReading {
+            db: self,
+            keys: keys.clone(),
+            tx: tx.clone(),
+        };
+        loop {
+            {
+                let _gate = self.enter().await?;
+                let mut state = self.lock();
+                for (key, from) in streams.iter_mut() {
+                    let last = state.stream_mut(key)?.map(|s| s.last_id());
+                    *from = match (*from, last) {
+                        (XReadFrom::New, last) => XReadFrom::After(last.unwrap_or_default()),
+                        (XReadFrom::Last, None) => XReadFrom::After(StreamId::MIN),
+                        (from, _) => from,
+                    };
+                }
+
+                let found = state.xread(&streams, count)?;
+                if !found.is_empty() {
+                    return Ok(found);
+                }
+                // `+` only ever reads the entry that was last when the read started, so with
+                // none there it waits for entries after the last ID as it stands now
+                for (key, from) in streams.iter_mut() {
+                    if *from == XReadFrom::Last {
+                        let last = state.stream_mut(key)?.map(|s| s.last_id());
+                        *from = XReadFrom::After(last.unwrap_or_default());
+                    }
+                }
+                state.wait_for_entries(&keys, &tx);
+            }
+
+            match deadline {
+                Some(deadline) => {
+                    if tokio::time::timeout_at(deadline, rx.recv()).await.is_err() {
+                        return Ok(Vec::new());
+                    }
+                }
+                None => {
+                    rx.recv().await;
+                }
+            }
+        }
+    }
+}
+
+impl State {
+    pub(super) fn stream_mut(&mut self, key: &Bytes) -> Result<Option<&mut Stream>, DbError> {
+        match self.live(key) {
+            Some(Entry {
+                value: Value::Stream(stream),
+                ..
+            }) => Ok(Some(stream)),
+            Some(_) => Err(DbError::WrongType),
+            None => Ok(None),
+        }
+    }
+
+    fn xread(
+        &mut self,
+        streams: &[(Bytes, XReadFrom)],
+        count: Option<usize>,
+    ) -> Result<Vec<(Bytes, Vec<StreamEntry>)>, DbError> {
+        let mut out = Vec::new();
+        for (key, from) in streams {
+            let Some(stream) = self.stream_mut(key)? else {
+                continue;
+            };
+            let entries = match from {
+                XReadFrom::After(id) => match id.next() {
+                    Some(start) => stream.range(start, StreamId::MAX, count, false),
+                    None => Vec::new(),
+                },
+                XReadFrom::Last => stream.last_entry().into_iter().collect(),
+                XReadFrom::New => Vec::new(),
+            };
+            if !entries.is_empty() {
+                out.push((key.clone(), entries));
+            }
+        }
+        Ok(out)
+    }
+}
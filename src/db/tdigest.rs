@@ -0,0 +1,466 @@
+// T-Digest quantile sketches, using the merging digest from RedisBloom. Added values collect
+// in an unmerged buffer; once the buffer fills up (or before any query) everything is sorted
+// and neighbouring centroids are merged, keeping centroids small near the tails so extreme
+// quantiles stay accurate.
+use super::{Class, Db, DbError, Entry, State, Value};
+use bytes::Bytes;
+use std::f64::consts::PI;
+
+pub const DEFAULT_COMPRESSION: u64 = 100;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TDigestInfo {
+    pub compression: u64,
+    pub capacity: usize,
+    pub merged_nodes: usize,
+    pub unmerged_nodes: usize,
+    pub merged_weight: f64,
+    pub unmerged_weight: f64,
+    pub observations: f64,
+    pub total_compressions: u64,
+    pub memory_usage: usize,
+}
+
+#[derive(Clone)]
+pub(super) struct TDigest {
+    compression: u64,
+    // The most centroids, merged and unmerged, held before compressing
+    capacity: usize,
+    // (mean, weight), the first merged_nodes sorted and merged, the rest as added
+    nodes: Vec<(f64, f64)>,
+    merged_nodes: usize,
+    merged_weight: f64,
+    unmerged_weight: f64,
+    min: f64,
+    max: f64,
+    total_compressions: u64,
+}
+
+// The k1 scale function, mapping quantiles onto 0..=compression
+fn scale(compression: f64, q: f64) -> f64 {
+    compression * ((2.0 * q - 1.0).asin() + PI / 2.0) / PI
+}
+
+fn scale_inverse(compression: f64, k: f64) -> f64 {
+    ((k.min(compression) * PI / compression - PI / 2.0).sin() + 1.0) / 2.0
+}
+
+// Interpolates between x1 and x2, never leaving the range between them
+fn weighted_average(x1: f64, w1: f64, x2: f64, w2: f64) -> f64 {
+    let (lo, hi) = if x1 <= x2 { (x1, x2) } else { (x2, x1) };
+    ((x1 * w1 + x2 * w2) / (w1 + w2)).clamp(lo, hi)
+}
+
+impl TDigest {
+    fn new(compression: u64) -> TDigest {
+        let capacity = (compression as usize).saturating_mul(6).saturating_add(10);
+        TDigest {
+            compression,
+            capacity,
+            nodes: Vec::new(),
+            merged_nodes: 0,
+            merged_weight: 0.0,
+            unmerged_weight: 0.0,
+            min: f64::INFINITY,
+            max: f64::NEG_INFINITY,
+            total_compressions: 0,
+        }
+    }
+
+    fn total(&self) -> f64 {
+        self.merged_weight + self.unmerged_weight
+    }
+
+    fn add(&mut self, value: f64, weight: f64) {
+        if self.nodes.len() >= self.capacity {
+            self.compress();
+        }
+        self.min = self.min.min(value);
+        self.max = self.max.max(value);
+        self.nodes.push((value, weight));
+        self.unmerged_weight += weight;
+    }
+
+    // Folds the other digest's centroids in as unmerged nodes
+    fn merge_from(&mut self, other: &TDigest) {
+        for &(mean, weight) in &other.nodes {
+            self.add(mean, weight);
+        }
+        self.min = self.min.min(other.min);
+        self.max = self.max.max(other.max);
+    }
+
+    fn compress(&mut self) {
+        if self.nodes.len() == self.merged_nodes {
+            return;
+        }
+        self.nodes.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+        // Each centroid may cover at most one unit of the scale function, which keeps them
+        // small near q = 0 and q = 1
+        let total = self.total();
+        let compression = self.compression as f64;
+        let mut merged: Vec<(f64, f64)> = Vec::new();
+        let mut weight_so_far = 0.0;
+        let mut weight_limit = 0.0;
+        for &(mean, weight) in &self.nodes {
+            match merged.last_mut() {
+                Some(last) if weight_so_far + weight <= weight_limit => {
+                    last.1 += weight;
+                    last.0 += (mean - last.0) * weight / last.1;
+                }
+                _ => {
+                    let k = scale(compression, weight_so_far / total);
+                    weight_limit = total * scale_inverse(compression, k + 1.0);
+                    merged.push((mean, weight));
+                }
+            }
+            weight_so_far += weight;
+        }
+
+        self.merged_nodes = merged.len();
+        self.nodes = merged;
+        self.merged_weight = total;
+        self.unmerged_weight = 0.0;
+        self.total_compressions += 1;
+    }
+
+    fn quantile(&mut self, q: f64) -> f64 {
+        self.compress();
+        let nodes = &self.nodes;
+        let n = nodes.len();
+        if n == 0 {
+            return f64::NAN;
+        }
+        if n == 1 {
+            return nodes[0].0;
+        }
+
+        let total = self.total();
+        let index = q * total;
+        if index < 1.0 {
+            return self.min;
+        }
+        // One sample sits at min, so interpolate within the first centroid
+        let (first_mean, first_weight) = nodes[0];
+        if first_weight > 1.0 && index < first_weight / 2.0 {
+            return self.min + (index - 1.0) / (first_weight / 2.0 - 1.0) * (first_mean - self.min);
+        }
+        if index > total - 1.0 {
+            return self.max;
+        }
+        let (last_mean, last_weight) = nodes[n - 1];
+        if last_weight > 1.0 && total - index <= last_weight / 2.0 {
+            return self.max
+                - (total - index - 1.0) / (last_weight / 2.0 - 1.0) * (self.max - last_mean);
+        }
+
+        let mut weight_so_far = first_weight / 2.0;
+        for pair in nodes.windows(2) {
+            let ((left_mean, left_weight), (right_mean, right_weight)) = (pair[0], pair[1]);
+            let dw = (left_weight + right_weight) / 2.0;
+            if weight_so_far + dw > index {
+                // Singletons are exact, so don't smear them
+                let mut left_unit = 0.0;
+                if left_weight == 1.0 {
+                    if index - weight_so_far < 0.5 {
+                        return left_mean;
+                    }
+                    left_unit = 0.5;
+                }
+                let mut right_unit = 0.0;
+                if right_weight == 1.0 {
+                    if weight_so_far + dw - index <= 0.5 {
+                        return right_mean;
+                    }
+                    right_unit = 0.5;
+                }
+                let z1 = index - weight_so_far - left_unit;
+                let z2 = weight_so_far + dw - index - right_unit;
+                return weighted_average(left_mean, z2, right_mean, z1);
+            }
+            weight_so_far += dw;
+        }
+
+        let z1 = index - total - last_weight / 2.0;
+        let z2 = last_weight / 2.0 - z1;
+        weighted_average(last_mean, z1, self.max, z2)
+    }
+
+    // The fraction of observations below x, counting half of those equal to it
+    fn cdf(&mut self, x: f64) -> f64 {
+        self.compress();
+        let nodes = &self.nodes;
+        let n = nodes.len();
+        if n == 0 {
+            return f64::NAN;
+        }
+        if x < self.min {
+            return 0.0;
+        }
+        if x > self.max {
+            return 1.0;
+        }
+        if n == 1 {
+            let width = self.max - self.min;
+            return if width == 0.0 {
+                0.5
+            } else {
+                (x - self.min) / width
+            };
+        }
+
+        let total = self.total();
+        let (first_mean, first_weight) = nodes[0];
+        if x < first_mean {
+            if first_mean - self.min <= 0.0 {
+                return 0.0;
+            }
+            if x == self.min {
+                return 0.5 / total;
+            }
+            return (1.0 + (x - self.min) / (first_mean - self.min) * (first_weight / 2.0 - 1.0))
+                / total;
+        }
+        let (last_mean, last_weight) = nodes[n - 1];
+        if x > last_mean {
+            if self.max - last_mean <= 0.0 {
+                return 1.0;
+            }
+            if x == self.max {
+                return 1.0 - 0.5 / total;
+            }
+            let dq =
+                (1.0 + (self.max - x) / (self.max - last_mean) * (last_weight / 2.0 - 1.0)) / total;
+            return 1.0 - dq;
+        }
+
+        let mut weight_so_far = 0.0;
+        let mut i = 0;
+        while i < n - 1 {
+            let (mean, weight) = nodes[i];
+            if mean == x {
+                // Take in every centroid at exactly x
+                let mut dw = 0.0;
+                while i < n && nodes[i].0 == x {
+                    dw += nodes[i].1;
+                    i += 1;
+                }
+                return (weight_so_far + dw / 2.0) / total;
+            }
+            let (next_mean, next_weight) = nodes[i + 1];
+            if mean <= x && x < next_mean {
+                let dw = (weight + next_weight) / 2.0;
+                if next_mean - mean <= 0.0 {
+                    return (weight_so_far + dw) / total;
+                }
+                let mut left_excluded = 0.0;
+                let mut right_excluded = 0.0;
+                if weight == 1.0 {
+                    if next_weight == 1.0 {
+                        return (weight_so_far + 1.0) / total;
+                    }
+                    left_excluded = 0.5;
+                } else if next_weight == 1.0 {
+                    right_excluded = 0.5;
+                }
+                let base = weight_so_far + weight / 2.0 + left_excluded;
+                let span = dw - left_excluded - right_excluded;
+                return (base + span * (x - mean) / (next_mean - mean)) / total;
+            }
+            weight_so_far += weight;
+            i += 1;
+        }
+
+        if x == last_mean {
+            1.0 - 0.5 / total
+        } else {
+            1.0
+        }
+    }
+
+    // Mean of the observations between the two quantiles
+    fn trimmed_mean(&mut self, low: f64, high: f64) -> f64 {
+        self.compress();
+        let total = self.total();
+        let from = (total * low).floor();
+        let to = (total * high).ceil();
+
+        let mut done = 0.0;
+        let mut sum = 0.0;
+        let mut count = 0.0;
+        for &(mean, weight) in &self.nodes {
+            let overlap = ((done + weight).min(to) - done.max(from)).max(0.0);
+            sum += mean * overlap;
+            count += overlap;
+            done += weight;
+        }
+        if count == 0.0 { f64::NAN } else { sum / count }
+    }
+
+    // Observations below value plus half those equal to it, or from the top when rev.
+    // -2 for an empty digest, -1 when value is outside it on the near side.
+    fn rank(&mut self, value: f64, rev: bool) -> i64 {
+        let total = self.total();
+        if total == 0.0 {
+            return -2;
+        }
+        let (below, above) = if rev {
+            (value > self.max, value < self.min)
+        } else {
+            (value < self.min, value > self.max)
+        };
+        if below {
+            return -1;
+        }
+        if above {
+            return total as i64;
+        }
+        let smaller = self.cdf(value) * total;
+        (if rev { total - smaller } else { smaller }).round() as i64
+    }
+
+    fn info(&self) -> TDigestInfo {
+        TDigestInfo {
+            compression: self.compression,
+            capacity: self.capacity,
+            merged_nodes: self.merged_nodes,
+            unmerged_nodes: self.nodes.len() - self.merged_nodes,
+            merged_weight: self.merged_weight,
+            unmerged_weight: self.unmerged_weight,
+            observations: self.total(),
+            total_compressions: self.total_compressions,
+            memory_usage: size_of::<TDigest>() + self.nodes.capacity() * size_of::<(f64, f64)>(),
+        }
+    }
+}
+
+impl Db {
+    pub fn tdigest_create(&self, key: &Bytes, compression: u64) -> Result<(), DbError> {
+        let mut state = self.lock();
+        if state.live(key).is_some() {
+            return Err(DbError::TDigestExists);
+        }
+        state.insert(
+            key.clone(),
+            Entry {
+                value: Value::TDigest(TDigest::new(compression)),
+                expires_at: None,
+            },
+        );
+        state.notify(Class::Generic, "tdigest.create", key);
+        Ok(())
+    }
+
+    pub fn tdigest_add(&self, key: &Bytes, values: &[f64]) -> Result<(), DbError> {
+        let mut state = self.lock();
+        let digest = state.tdigest_mut(key)?.ok_or(DbError::TDigestMissing)?;
+        for &value in values {
+            digest.add(value, 1.0);
+        }
+        state.notify(Class::Generic, "tdigest.add", key);
+        Ok(())
+    }
+
+    // Empty digests give NaN for every quantile
+    pub fn tdigest_quantile(&self, key: &Bytes, quantiles: &[f64]) -> Result<Vec<f64>, DbError> {
+        let mut state = self.lock_read();
+        let digest = state.tdigest_mut(key)?.ok_or(DbError::TDigestMissing)?;
+        Ok(quantiles.iter().map(|&q| digest.quantile(q)).collect())
+    }
+
+    pub fn tdigest_cdf(&self, key: &Bytes, values: &[f64]) -> Result<Vec<f64>, DbError> {
+        let mut state = self.lock_read();
+        let digest = state.tdigest_mut(key)?.ok_or(DbError::TDigestMissing)?;
+        Ok(values.iter().map(|&x| digest.cdf(x)).collect())
+    }
+
+    pub fn tdigest_rank(
+        &self,
+        key: &Bytes,
+        values: &[f64],
+        rev: bool,
+    ) -> Result<Vec<i64>, DbError> {
+        let mut state = self.lock_read();
+        let digest = state.tdigest_mut(key)?.ok_or(DbError::TDigestMissing)?;
+        Ok(values.iter().map(|&x| digest.rank(x, rev)).collect())
+    }
+
+    pub fn tdigest_trimmed_mean(&self, key: &Bytes, low: f64, high: f64) -> Result<f64, DbError> {
+        let mut state = self.lock_read();
+        let digest = state.tdigest_mut(key)?.ok_or(DbError::TDigestMissing)?;
+        Ok(digest.trimmed_mean(low, high))
+    }
+
+    // (min, max), both NaN when empty
+    pub fn tdigest_min_max(&self, key: &Bytes) -> Result<(f64, f64), DbError> {
+        let mut state = self.lock_read();
+        let digest = state.tdigest_mut(key)?.ok_or(DbError::TDigestMissing)?;
+        if digest.total() == 0.0 {
+            return Ok((f64::NAN, f64::NAN));
+        }
+        Ok((digest.min, digest.max))
+    }
+
+    pub fn tdigest_info(&self, key: &Bytes) -> Result<TDigestInfo, DbError> {
+        let mut state = self.lock_read();
+        Ok(state
+            .tdigest_mut(key)?
+            .ok_or(DbError::TDigestMissing)?
+            .info())
+    }
+
+    // Merges the sources into the destination, which keeps its own observations unless
+    // overriding. Without a compression the largest of the inputs' is used.
+    pub fn tdigest_merge(
+        &self,
+        destination: &Bytes,
+        sources: &[Bytes],
+        compression: Option<u64>,
+        override_dest: bool,
+    ) -> Result<(), DbError> {
+        let mut state = self.lock();
+        let mut inputs = Vec::with_capacity(sources.len() + 1);
+        for key in sources {
+            let digest = state.tdigest_mut(key)?.ok_or(DbError::TDigestMissing)?;
+            inputs.push(digest.clone());
+        }
+        let expires_at = state.live(destination).and_then(|e| e.expires_at);
+        if !override_dest && let Some(dest) = state.tdigest_mut(destination)? {
+            inputs.push(dest.clone());
+        }
+
+        let compression = compression
+            .or_else(|| inputs.iter().map(|d| d.compression).max())
+            .unwrap_or(DEFAULT_COMPRESSION);
+        let mut merged = TDigest::new(compression);
+        for input in &inputs {
+            merged.merge_from(input);
+        }
+        merged.compress();
+
+        state.insert(
+            destination.clone(),
+            Entry {
+                value: Value::TDigest(merged),
+                expires_at,
+            },
+        );
+        state.reindex(destination);
+        state.notify(Class::Generic, "tdigest.merge", destination);
+        Ok(())
+    }
+}
+
+impl State {
+    fn tdigest_mut(&mut self, key: &Bytes) -> Result<Option<&mut TDigest>, DbError> {
+        match self.live(key) {
+            Some(Entry {
+                value: Value::TDigest(digest),
+                ..
+            }) => Ok(Some(digest)),
+            Some(_) => Err(DbError::WrongType),
+            None => Ok(None),
+        }
+    }
+}
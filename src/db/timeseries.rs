@@ -0,0 +1,481 @@
+// Time series, following RedisTimeSeries. Samples are kept ordered by their millisecond
+// timestamp; anything older than the retention window behind the newest sample is dropped.
+// Compaction rules downsample a source series into a destination one bucket at a time: once
+// a sample lands past the open bucket, that bucket is aggregated and written to the
+// destination, and samples written into already closed buckets rewrite them.
+use super::stream::now_ms;
+use super::{Class, Db, DbError, Entry, State, Value};
+use bytes::Bytes;
+use std::collections::BTreeMap;
+
+// A timestamp in milliseconds and its value
+pub type Sample = (u64, f64);
+
+// What to do when a sample is added at a timestamp that already has one
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DuplicatePolicy {
+    #[default]
+    Block,
+    First,
+    Last,
+    Min,
+    Max,
+    Sum,
+}
+
+impl DuplicatePolicy {
+    pub fn name(self) -> &'static str {
+        match self {
+            DuplicatePolicy::Block => "block",
+            DuplicatePolicy::First => "first",
+            DuplicatePolicy::Last => "last",
+            DuplicatePolicy::Min => "min",
+            DuplicatePolicy::Max => "max",
+            DuplicatePolicy::Sum => "sum",
+        }
+    }
+}
+
+// Settings for a new series. A retention of 0 keeps every sample.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TsOptions {
+    pub retention: u64,
+    pub duplicate_policy: DuplicatePolicy,
+    pub labels: Vec<(Bytes, Bytes)>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Aggregator {
+    Avg,
+    Sum,
+    Min,
+    Max,
+    Count,
+    First,
+    Last,
+    Range,
+}
+
+impl Aggregator {
+    pub fn name(self) -> &'static str {
+        match self {
+            Aggregator::Avg => "AVG",
+            Aggregator::Sum => "SUM",
+            Aggregator::Min => "MIN",
+            Aggregator::Max => "MAX",
+            Aggregator::Count => "COUNT",
+            Aggregator::First => "FIRST",
+            Aggregator::Last => "LAST",
+            Aggregator::Range => "RANGE",
+        }
+    }
+
+    // Reduces the values of one bucket, which is never empty
+    fn apply(self, values: &[f64]) -> f64 {
+        let min = || values.iter().copied().fold(f64::INFINITY, f64::min);
+        let max = || values.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+        match self {
+            Aggregator::Avg => values.iter().sum::<f64>() / values.len() as f64,
+            Aggregator::Sum => values.iter().sum(),
+            Aggregator::Min => min(),
+            Aggregator::Max => max(),
+            Aggregator::Count => values.len() as f64,
+            Aggregator::First => values[0],
+            Aggregator::Last => values[values.len() - 1],
+            Aggregator::Range => max() - min(),
+        }
+    }
+}
+
+// Groups samples into buckets of `bucket` milliseconds, each labelled by its start
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Aggregation {
+    pub aggregator: Aggregator,
+    pub bucket: u64,
+}
+
+impl Aggregation {
+    // The start of the bucket holding `timestamp`, with buckets starting at `align`
+    fn bucket_start(&self, timestamp: u64, align: u64) -> u64 {
+        let offset =
+            (i128::from(timestamp) - i128::from(align)).rem_euclid(i128::from(self.bucket));
+        timestamp - offset as u64
+    }
+
+    // Samples must be in timestamp order
+    fn apply(&self, samples: impl IntoIterator<Item = Sample>, align: u64) -> Vec<Sample> {
+        let mut out = Vec::new();
+        let mut current: Option<(u64, Vec<f64>)> = None;
+        for (timestamp, value) in samples {
+            let start = self.bucket_start(timestamp, align);
+            match &mut current {
+                Some((bucket, values)) if *bucket == start => values.push(value),
+                _ => {
+                    if let Some((bucket, values)) = current.take() {
+                        out.push((bucket, self.aggregator.apply(&values)));
+                    }
+                    current = Some((start, vec![value]));
+                }
+            }
+        }
+        if let Some((bucket, values)) = current {
+            out.push((bucket, self.aggregator.apply(&values)));
+        }
+        out
+    }
+}
+
+// TS.RANGE and TS.REVRANGE, and the same for each series TS.MRANGE matches
+#[derive(Debug, Clone, PartialEq)]
+pub struct TsRangeQuery {
+    pub from: u64,
+    pub to: u64,
+    pub rev: bool,
+    pub filter_by_value: Option<(f64, f64)>,
+    pub count: Option<usize>,
+    pub aggregation: Option<Aggregation>,
+}
+
+// One matcher of a TS.MRANGE FILTER. A missing label compares as the empty string, so
+// `label=` matches series without it and `label!=` those with it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LabelFilter {
+    pub label: Bytes,
+    pub values: Vec<Bytes>,
+    pub equal: bool,
+}
+
+impl LabelFilter {
+    fn matches(&self, labels: &[(Bytes, Bytes)]) -> bool {
+        let value = labels
+            .iter()
+            .find(|(name, _)| *name == self.label)
+            .map_or(&b""[..], |(_, value)| value);
+        self.values.iter().any(|v| v == value) == self.equal
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TsMatch {
+    pub key: Bytes,
+    pub labels: Vec<(Bytes, Bytes)>,
+    pub samples: Vec<Sample>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TsInfo {
+    pub total_samples: usize,
+    pub first_timestamp: u64,
+    pub last_timestamp: u64,
+    pub retention: u64,
+    pub duplicate_policy: DuplicatePolicy,
+    pub labels: Vec<(Bytes, Bytes)>,
+    pub source_key: Option<Bytes>,
+    // Destination, aggregation and alignment of each compaction rule
+    pub rules: Vec<(Bytes, Aggregation, u64)>,
+}
+
+pub(super) struct TimeSeries {
+    options: TsOptions,
+    samples: BTreeMap<u64, f64>,
+    rules: Vec<Rule>,
+    // The series this one is compacted from
+    source: Option<Bytes>,
+}
+
+struct Rule {
+    destination: Bytes,
+    aggregation: Aggregation,
+    align: u64,
+    // Start of the bucket still being filled
+    open: Option<u64>,
+}
+
+impl TimeSeries {
+    fn new(options: TsOptions) -> TimeSeries {
+        TimeSeries {
+            options,
+            samples: BTreeMap::new(),
+            rules: Vec::new(),
+            source: None,
+        }
+    }
+
+    fn last_timestamp(&self) -> Option<u64> {
+        self.samples.last_key_value().map(|(&ts, _)| ts)
+    }
+
+    // Returns the value stored, which a duplicate policy may have merged with an older one
+    fn upsert(
+        &mut self,
+        timestamp: u64,
+        value: f64,
+        policy: DuplicatePolicy,
+    ) -> Result<f64, DbError> {
+        let retention = self.options.retention;
+        if let Some(last) = self.last_timestamp()
+            && retention > 0
+            && timestamp < last.saturating_sub(retention)
+        {
+            return Err(DbError::TsOlderThanRetention);
+        }
+
+        let stored = match self.samples.get(&timestamp) {
+            None => value,
+            Some(&old) => match policy {
+                DuplicatePolicy::Block => return Err(DbError::TsDuplicateBlocked),
+                DuplicatePolicy::First => old,
+                DuplicatePolicy::Last => value,
+                DuplicatePolicy::Min => old.min(value),
+                DuplicatePolicy::Max => old.max(value),
+                DuplicatePolicy::Sum => old + value,
+            },
+        };
+        self.samples.insert(timestamp, stored);
+        self.trim();
+        Ok(stored)
+    }
+
+    fn trim(&mut self) {
+        let retention = self.options.retention;
+        if let Some(last) = self.last_timestamp()
+            && retention > 0
+        {
+            self.samples = self.samples.split_off(&last.saturating_sub(retention));
+        }
+    }
+
+    fn range(&self, query: &TsRangeQuery) -> Vec<Sample> {
+        if query.from > query.to {
+            return Vec::new();
+        }
+        let samples = self
+            .samples
+            .range(query.from..=query.to)
+            .map(|(&ts, &value)| (ts, value))
+            .filter(|(_, value)| {
+                query
+                    .filter_by_value
+                    .is_none_or(|(min, max)| (min..=max).contains(value))
+            });
+        let mut samples: Vec<Sample> = match query.aggregation {
+            Some(aggregation) => aggregation.apply(samples, 0),
+            None => samples.collect(),
+        };
+        if query.rev {
+            samples.reverse();
+        }
+        if let Some(count) = query.count {
+            samples.truncate(count);
+        }
+        samples
+    }
+
+    // Advances each rule past `timestamp`, returning the buckets to write to destinations
+    fn compactions(&mut self, timestamp: u64) -> Vec<(Bytes, Sample)> {
+        let mut out = Vec::new();
+        for rule in &mut self.rules {
+            let start = rule.aggregation.bucket_start(timestamp, rule.align);
+            let closed = match rule.open {
+                None => None,
+                Some(open) if start == open => None,
+                // A sample in an older bucket rewrites it
+                Some(open) if start < open => Some(start),
+                Some(open) => {
+                    rule.open = Some(start);
+                    Some(open)
+                }
+            };
+            rule.open.get_or_insert(start);
+            let Some(bucket) = closed else {
+                continue;
+            };
+            let end = bucket.saturating_add(rule.aggregation.bucket);
+            let samples = self.samples.range(bucket..end).map(|(&ts, &v)| (ts, v));
+            if let Some(&(_, value)) = rule.aggregation.apply(samples, rule.align).first() {
+                out.push((rule.destination.clone(), (bucket, value)));
+            }
+        }
+        out
+    }
+}
+
+impl Db {
+    pub fn ts_create(&self, key: &Bytes, options: TsOptions) -> Result<(), DbError> {
+        let mut state = self.lock();
+        if state.live(key).is_some() {
+            return Err(DbError::TsKeyExists);
+        }
+        state.insert(
+            key.clone(),
+            Entry {
+                value: Value::TimeSeries(TimeSeries::new(options)),
+                expires_at: None,
+            },
+        );
+        state.notify(Class::Generic, "ts.create", key);
+        Ok(())
+    }
+
+    // Adds a sample, at the current time if no timestamp is given, creating the series with
+    // `options` if needed. Returns the sample's timestamp.
+    pub fn ts_add(
+        &self,
+        key: &Bytes,
+        timestamp: Option<u64>,
+        value: f64,
+        options: &TsOptions,
+        on_duplicate: Option<DuplicatePolicy>,
+    ) -> Result<u64, DbError> {
+        let mut state = self.lock();
+        let timestamp = timestamp.unwrap_or_else(now_ms);
+        if state.ts_mut(key)?.is_none() {
+            state.insert(
+                key.clone(),
+                Entry {
+                    value: Value::TimeSeries(TimeSeries::new(options.clone())),
+                    expires_at: None,
+                },
+            );
+        }
+
+        let series = state.ts_mut(key)?.ok_or(DbError::TsMissing)?;
+        let policy = on_duplicate.unwrap_or(series.options.duplicate_policy);
+        series.upsert(timestamp, value, policy)?;
+        let compactions = series.compactions(timestamp);
+        state.notify(Class::Generic, "ts.add", key);
+
+        // Destinations that have since been deleted or replaced are skipped
+        for (destination, (bucket, value)) in compactions {
+            if let Ok(Some(series)) = state.ts_mut(&destination)
+                && series.upsert(bucket, value, DuplicatePolicy::Last).is_ok()
+            {
+                state.notify(Class::Generic, "ts.add", &destination);
+            }
+        }
+        Ok(timestamp)
+    }
+
+    pub fn ts_get(&self, key: &Bytes) -> Result<Option<Sample>, DbError> {
+        let mut state = self.lock_read();
+        let series = state.ts_mut(key)?.ok_or(DbError::TsMissing)?;
+        Ok(series
+            .samples
+            .last_key_value()
+            .map(|(&ts, &value)| (ts, value)))
+    }
+
+    pub fn ts_range(&self, key: &Bytes, query: &TsRangeQuery) -> Result<Vec<Sample>, DbError> {
+        let mut state = self.lock_read();
+        let series = state.ts_mut(key)?.ok_or(DbError::TsMissing)?;
+        Ok(series.range(query))
+    }
+
+    // Every series whose labels match all the filters, ordered by key
+    pub fn ts_mrange(&self, filters: &[LabelFilter], query: &TsRangeQuery) -> Vec<TsMatch> {
+        let mut state = self.lock_read();
+        let mut keys: Vec<Bytes> = state.entries.keys().cloned().collect();
+        keys.sort();
+
+        let mut matches = Vec::new();
+        for key in keys {
+            let Ok(Some(series)) = state.ts_mut(&key) else {
+                continue;
+            };
+            if filters.iter().all(|f| f.matches(&series.options.labels)) {
+                matches.push(TsMatch {
+                    labels: series.options.labels.clone(),
+                    samples: series.range(query),
+                    key,
+                });
+            }
+        }
+        matches
+    }
+
+    // Compacts `source` into `destination`. A destination has one source and can't itself
+    // be compacted, so rules never chain.
+    pub fn ts_create_rule(
+        &self,
+        source: &Bytes,
+        destination: &Bytes,
+        aggregation: Aggregation,
+        align: u64,
+    ) -> Result<(), DbError> {
+        if source == destination {
+            return Err(DbError::TsSameKey);
+        }
+        let mut state = self.lock();
+        let src = state.ts_mut(source)?.ok_or(DbError::TsMissing)?;
+        if src.source.is_some() {
+            return Err(DbError::TsSourceHasSource);
+        }
+        let dest = state.ts_mut(destination)?.ok_or(DbError::TsMissing)?;
+        if dest.source.is_some() {
+            return Err(DbError::TsDestinationHasSource);
+        }
+        if !dest.rules.is_empty() {
+            return Err(DbError::TsDestinationHasRules);
+        }
+        dest.source = Some(source.clone());
+
+        let src = state.ts_mut(source)?.ok_or(DbError::TsMissing)?;
+        src.rules.push(Rule {
+            destination: destination.clone(),
+            aggregation,
+            align,
+            open: None,
+        });
+        state.notify(Class::Generic, "ts.createrule", source);
+        Ok(())
+    }
+
+    pub fn ts_delete_rule(&self, source: &Bytes, destination: &Bytes) -> Result<(), DbError> {
+        let mut state = self.lock();
+        let src = state.ts_mut(source)?.ok_or(DbError::TsMissing)?;
+        let index = src
+            .rules
+            .iter()
+            .position(|rule| rule.destination == *destination)
+            .ok_or(DbError::TsRuleMissing)?;
+        src.rules.remove(index);
+
+        if let Ok(Some(dest)) = state.ts_mut(destination) {
+            dest.source = None;
+        }
+        state.notify(Class::Generic, "ts.deleterule", source);
+        Ok(())
+    }
+
+    pub fn ts_info(&self, key: &Bytes) -> Result<TsInfo, DbError> {
+        let mut state = self.lock_read();
+        let series = state.ts_mut(key)?.ok_or(DbError::TsMissing)?;
+        Ok(TsInfo {
+            total_samples: series.samples.len(),
+            first_timestamp: series.samples.first_key_value().map_or(0, |(&ts, _)| ts),
+            last_timestamp: series.last_timestamp().unwrap_or(0),
+            retention: series.options.retention,
+            duplicate_policy: series.options.duplicate_policy,
+            labels: series.options.labels.clone(),
+            source_key: series.source.clone(),
+            rules: series
+                .rules
+                .iter()
+                .map(|rule| (rule.destination.clone(), rule.aggregation, rule.align))
+                .collect(),
+        })
+    }
+}
+
+impl State {
+    fn ts_mut(&mut self, key: &Bytes) -> Result<Option<&mut TimeSeries>, DbError> {
+        match self.live(key) {
+            Some(Entry {
+                value: Value::TimeSeries(series),
+                ..
+            }) => Ok(Some(series)),
+            Some(_) => Err(DbError::WrongType),
+            None => Ok(None),
+        }
+    }
+}
@@ -0,0 +1,198 @@
+// Top-K tracking with HeavyKeeper, following RedisBloom. A sketch of `depth` rows by `width`
+// buckets counts items by fingerprint; an item that collides with another's bucket decays
+// that count with probability decay^count, so heavy hitters keep their buckets and light
+// ones get evicted. The k heaviest items seen are kept alongside the sketch.
+use super::hyperloglog::murmur_hash64a;
+use super::{Class, Db, DbError, Entry, State, Value};
+use bytes::Bytes;
+
+const DEFAULT_WIDTH: usize = 8;
+const DEFAULT_DEPTH: usize = 7;
+const DEFAULT_DECAY: f64 = 0.9;
+// Decay stops growing weaker past this count
+const DECAY_LIMIT: u64 = 255;
+const FINGERPRINT_SEED: u64 = 1919;
+
+// Bounds on what TOPK.RESERVE allocates up front
+pub const MAX_TOPK_K: usize = 1 << 20;
+pub const MAX_TOPK_BUCKETS: usize = 1 << 26;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TopKOptions {
+    pub k: usize,
+    pub width: usize,
+    pub depth: usize,
+    pub decay: f64,
+}
+
+impl TopKOptions {
+    // The sketch size RedisBloom uses when only k is given
+    pub fn new(k: usize) -> TopKOptions {
+        TopKOptions {
+            k,
+            width: DEFAULT_WIDTH,
+            depth: DEFAULT_DEPTH,
+            decay: DEFAULT_DECAY,
+        }
+    }
+}
+
+pub(super) struct TopK {
+    options: TopKOptions,
+    // Row major, depth * width
+    buckets: Vec<Bucket>,
+    // At most k items, unordered
+    top: Vec<(Bytes, u64)>,
+}
+
+#[derive(Clone, Copy, Default)]
+struct Bucket {
+    fingerprint: u32,
+    count: u64,
+}
+
+impl TopK {
+    fn new(options: TopKOptions) -> Result<TopK, DbError> {
+        let buckets = options
+            .width
+            .checked_mul(options.depth)
+            .filter(|&n| n <= MAX_TOPK_BUCKETS && options.k <= MAX_TOPK_K)
+            .ok_or(DbError::TopKTooBig)?;
+        Ok(TopK {
+            options,
+            buckets: vec![Bucket::default(); buckets],
+            top: Vec::with_capacity(options.k),
+        })
+    }
+
+    // Returns the item pushed out of the top k to make room, if any
+    fn add(&mut self, item: &Bytes, by: u64) -> Option<Bytes> {
+        let TopKOptions {
+            width,
+            depth,
+            decay,
+            ..
+        } = self.options;
+        let fingerprint = murmur_hash64a(item, FINGERPRINT_SEED) as u32;
+        let mut max_count = 0;
+
+        for row in 0..depth {
+            let slot = row * width + (murmur_hash64a(item, row as u64) % width as u64) as usize;
+            let bucket = &mut self.buckets[slot];
+            if bucket.count == 0 {
+                bucket.fingerprint = fingerprint;
+                bucket.count = by;
+            } else if bucket.fingerprint == fingerprint {
+                bucket.count = bucket.count.saturating_add(by);
+            } else {
+                // Each unit of the increment gets a chance to decay the other item's count,
+                // and whatever's left over claims the bucket if it reaches zero
+                for left in (1..=by).rev() {
+                    let chance = decay.powi(bucket.count.min(DECAY_LIMIT) as i32);
+                    if rand::random::<f64>() < chance {
+                        bucket.count -= 1;
+                        if bucket.count == 0 {
+                            bucket.fingerprint = fingerprint;
+                            bucket.count = left;
+                            break;
+                        }
+                    }
+                }
+            }
+            if bucket.fingerprint == fingerprint {
+                max_count = max_count.max(bucket.count);
+            }
+        }
+
+        if let Some(entry) = self.top.iter_mut().find(|(top, _)| top == item) {
+            entry.1 = entry.1.max(max_count);
+            return None;
+        }
+        if self.top.len() < self.options.k {
+            if max_count > 0 {
+                self.top.push((item.clone(), max_count));
+            }
+            return None;
+        }
+        let (min, _) = self
+            .top
+            .iter()
+            .enumerate()
+            .min_by_key(|(_, (_, count))| *count)?;
+        if max_count > self.top[min].1 {
+            let (expelled, _) = std::mem::replace(&mut self.top[min], (item.clone(), max_count));
+            return Some(expelled);
+        }
+        None
+    }
+
+    fn list(&self) -> Vec<(Bytes, u64)> {
+        let mut top = self.top.clone();
+        top.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        top
+    }
+}
+
+impl Db {
+    pub fn topk_reserve(&self, key: &Bytes, options: TopKOptions) -> Result<(), DbError> {
+        let mut state = self.lock();
+        if state.live(key).is_some() {
+            return Err(DbError::TopKExists);
+        }
+        state.insert(
+            key.clone(),
+            Entry {
+                value: Value::TopK(TopK::new(options)?),
+                expires_at: None,
+            },
+        );
+        state.notify(Class::Generic, "topk.reserve", key);
+        Ok(())
+    }
+
+    // For each item, whatever it pushed out of the top k
+    pub fn topk_incrby(
+        &self,
+        key: &Bytes,
+        items: &[(Bytes, u64)],
+    ) -> Result<Vec<Option<Bytes>>, DbError> {
+        let mut state = self.lock();
+        let topk = state.topk_mut(key)?.ok_or(DbError::TopKMissing)?;
+        let expelled = items.iter().map(|(item, by)| topk.add(item, *by)).collect();
+        state.notify(Class::Generic, "topk.incrby", key);
+        Ok(expelled)
+    }
+
+    pub fn topk_query(&self, key: &Bytes, items: &[Bytes]) -> Result<Vec<bool>, DbError> {
+        let mut state = self.lock_read();
+        let topk = state.topk_mut(key)?.ok_or(DbError::TopKMissing)?;
+        Ok(items
+            .iter()
+            .map(|item| topk.top.iter().any(|(top, _)| top == item))
+            .collect())
+    }
+
+    // Heaviest first
+    pub fn topk_list(&self, key: &Bytes) -> Result<Vec<(Bytes, u64)>, DbError> {
+        let mut state = self.lock_read();
+        Ok(state.topk_mut(key)?.ok_or(DbError::TopKMissing)?.list())
+    }
+
+    pub fn topk_info(&self, key: &Bytes) -> Result<TopKOptions, DbError> {
+        let mut state = self.lock_read();
+        Ok(state.topk_mut(key)?.ok_or(DbError::TopKMissing)?.options)
+    }
+}
+
+impl State {
+    fn topk_mut(&mut self, key: &Bytes) -> Result<Option<&mut TopK>, DbError> {
+        match self.live(key) {
+            Some(Entry {
+                value: Value::TopK(topk),
+                ..
+            }) => Ok(Some(topk)),
+            Some(_) => Err(DbError::WrongType),
+            None => Ok(None),
+        }
+    }
+}
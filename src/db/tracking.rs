@@ -0,0 +1,267 @@
+// Client side caching (CLIENT TRACKING). In the default mode the keys a client reads are
+// remembered, and the next write to one of them invalidates it for those clients, who have
+// to read it again to hear about later writes. In BCAST mode nothing is remembered: clients
+// hear about every write under their prefixes, which are filed like pattern subscriptions so
+// a write only looks up the prefixes of its own key.
+//
+// Reads are seen through `live` while a reading command holds the lock, and writes through
+// the keyspace notification hook. Invalidations are queued per recipient, the tracking
+// client or the one it redirects to, and sent when the lock is released. Keys remembered for
+// a client that has since stopped tracking are dropped the next time they are written.
+use super::pubsub::Registry;
+use super::{Db, DbError, State};
+use bytes::Bytes;
+use std::collections::{HashMap, HashSet};
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TrackingOptions {
+    // Client to send the invalidations to instead
+    pub redirect: Option<u64>,
+    pub bcast: bool,
+    pub prefixes: Vec<Bytes>,
+    // Only track reads after CLIENT CACHING YES
+    pub optin: bool,
+    // Track reads unless after CLIENT CACHING NO
+    pub optout: bool,
+    // Skip invalidations for the client's own writes
+    pub noloop: bool,
+}
+
+// The client running a command, and what CLIENT CACHING said about it
+#[derive(Debug, Clone, Copy)]
+pub(super) struct Caller {
+    pub(super) id: u64,
+    caching: Option<bool>,
+}
+
+#[derive(Default)]
+pub(super) struct Tracking {
+    clients: HashMap<u64, TrackingOptions>,
+    // Key -> clients that read it since it was last invalidated
+    keys: HashMap<Bytes, HashSet<u64>>,
+    // BCAST prefix -> clients
+    prefixes: HashMap<Bytes, HashSet<u64>>,
+    // Recipient -> keys to invalidate once the lock is released
+    pending: HashMap<u64, Vec<Bytes>>,
+}
+
+impl Tracking {
+    fn forget(&mut self, id: u64) {
+        let Some(options) = self.clients.remove(&id) else {
+            return;
+        };
+        for prefix in options.prefixes {
+            if let Some(ids) = self.prefixes.get_mut(&prefix) {
+                ids.remove(&id);
+                if ids.is_empty() {
+                    self.prefixes.remove(&prefix);
+                }
+            }
+        }
+    }
+}
+
+impl State {
+    // Remembers a key read by the calling client, if it tracks this command's reads
+    pub(super) fn track_read(&mut self, key: &Bytes) {
+        let Some(caller) = self.client else {
+            return;
+        };
+        let Some(options) = self.tracking.clients.get(&caller.id) else {
+            return;
+        };
+        let tracked = if options.bcast {
+            false
+        } else if options.optin {
+            caller.caching == Some(true)
+        } else if options.optout {
+            caller.caching != Some(false)
+        } else {
+            true
+        };
+        if tracked {
+            self.tracking
+                .keys
+                .entry(key.clone())
+                .or_default()
+                .insert(caller.id);
+        }
+    }
+
+    // Queues the key's invalidation for every client caching it. Like Redis, a NOLOOP client's
+    // own write isn't reported back but still stops the key being tracked.
+    pub(super) fn invalidate(&mut self, key: &Bytes) {
+        if self.tracking.clients.is_empty() {
+            return;
+        }
+        let mut ids: Vec<u64> = self
+            .tracking
+            .keys
+            .remove(key)
+            .into_iter()
+            .flatten()
+            .collect();
+        for len in 0..=key.len() {
+            if let Some(subscribers) = self.tracking.prefixes.get(&key[..len]) {
+                ids.extend(subscribers);
+            }
+        }
+
+        let writer = self.client.map(|caller| caller.id);
+        for id in ids {
+            let Some(options) = self.tracking.clients.get(&id) else {
+                continue;
+            };
+            if options.noloop && writer == Some(id) {
+                continue;
+            }
+            let keys = self
+                .tracking
+                .pending
+                .entry(options.redirect.unwrap_or(id))
+                .or_default();
+            if !keys.contains(key) {
+                keys.push(key.clone());
+            }
+        }
+    }
+
+    pub(super) fn send_invalidations(&mut self, registry: &mut Registry) {
+        for (id, keys) in self.tracking.pending.drain() {
+            registry.invalidate(id, keys);
+        }
+    }
+
+    pub(super) fn has_invalidations(&self) -> bool {
+        !self.tracking.pending.is_empty()
+    }
+}
+
+impl Caller {
+    pub(super) fn new(id: u64) -> Caller {
+        Caller { id, caching: None }
+    }
+}
+
+impl Db {
+    // A handle whose commands are made as the client, so the keys it reads can be tracked
+    pub fn for_client(&self, id: u64) -> Db {
+        Db {
+            shared: self.shared.clone(),
+            caller: Some(Caller::new(id)),
+        }
+    }
+
+    // CLIENT TRACKING. Turning it on again keeps the mode, adds any new prefixes and
+    // replaces the other options.
+    pub fn client_tracking(
+        &self,
+        id: u64,
+        on: bool,
+        options: TrackingOptions,
+    ) -> Result<(), DbError> {
+        let mut state = self.lock();
+        if !on {
+            state.tracking.forget(id);
+            return Ok(());
+        }
+        if let Some(redirect) = options.redirect
+            && !self.pubsub().has_client(redirect)
+        {
+            return Err(DbError::RedirectMissing);
+        }
+
+        let mut prefixes = match options.bcast && options.prefixes.is_empty() {
+            true => vec![Bytes::new()],
+            false => options.prefixes.clone(),
+        };
+        let mut existing: &[Bytes] = &[];
+        if let Some(current) = state.tracking.clients.get(&id) {
+            if current.bcast != options.bcast {
+                return Err(DbError::TrackingBcastSwitch);
+            }
+            if (current.optin, current.optout) != (options.optin, options.optout) {
+                return Err(DbError::TrackingOptSwitch);
+            }
+            prefixes.retain(|p| !current.prefixes.contains(p));
+            existing = &current.prefixes;
+        }
+        check_overlap(existing, &prefixes)?;
+
+        for prefix in &prefixes {
+            state
+                .tracking
+                .prefixes
+                .entry(prefix.clone())
+                .or_default()
+                .insert(id);
+        }
+        let tracking = state.tracking.clients.entry(id).or_default();
+        let mut all = std::mem::take(&mut tracking.prefixes);
+        all.extend(prefixes);
+        *tracking = TrackingOptions {
+            prefixes: all,
+            ..options
+        };
+        Ok(())
+    }
+
+    // CLIENT CACHING, which applies to the next command made through this handle
+    pub fn client_caching(&mut self, yes: bool) -> Result<(), DbError> {
+        let state = self.lock();
+        let options = self
+            .caller
+            .and_then(|caller| state.tracking.clients.get(&caller.id));
+        match options {
+            Some(options) if yes && options.optin => {}
+            Some(options) if !yes && options.optout => {}
+            Some(options) if options.optin || options.optout => {
+                return Err(match yes {
+                    true => DbError::CachingNeedsOptin,
+                    false => DbError::CachingNeedsOptout,
+                });
+            }
+            _ => return Err(DbError::CachingNeedsTracking),
+        }
+        drop(state);
+        if let Some(caller) = &mut self.caller {
+            caller.caching = Some(yes);
+        }
+        Ok(())
+    }
+
+    // Forgets CLIENT CACHING once the command after it has run
+    pub fn reset_caching(&mut self) {
+        if let Some(caller) = &mut self.caller {
+            caller.caching = None;
+        }
+    }
+
+    // CLIENT GETREDIR: -1 when not tracking, 0 without a redirect
+    pub fn client_getredir(&self, id: u64) -> i64 {
+        match self.lock().tracking.clients.get(&id) {
+            Some(options) => options.redirect.map_or(0, |r| r as i64),
+            None => -1,
+        }
+    }
+
+    pub(super) fn untrack(&self, id: u64) {
+        self.lock().tracking.forget(id);
+    }
+}
+
+// A client's prefixes can't overlap, or a write would be reported twice
+fn check_overlap(existing: &[Bytes], added: &[Bytes]) -> Result<(), DbError> {
+    for (i, prefix) in added.iter().enumerate() {
+        let others = existing.iter().chain(&added[..i]);
+        for other in others {
+            if prefix.starts_with(other) || other.starts_with(prefix) {
+                return Err(DbError::PrefixOverlap(
+                    String::from_utf8_lossy(prefix).into_owned(),
+                    String::from_utf8_lossy(other).into_owned(),
+                ));
+            }
+        }
+    }
+    Ok(())
+}
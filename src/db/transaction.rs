@@ -0,0 +1,44 @@
+// The gate that keeps other clients' commands out of a transaction or script. The server takes
+// it shared around every command, whether or not the command touches the keyspace, and
+// exclusively around EXEC, scripts and module commands, so none of them lands in the middle of
+// another. Blocking commands take it around each attempt rather than while they wait.
+//
+// Clients wait for the gate without holding up a thread, and keep checking whether the script
+// holding it has run past busy-reply-threshold, at which point they're told the server is busy
+// rather than waiting on.
+use super::{Db, DbError};
+use std::pin::pin;
+use std::time::Duration;
+use tokio::sync::{OwnedRwLockReadGuard, OwnedRwLockWriteGuard};
+
+// How often a client waiting at the gate checks whether a script has become busy
+const BUSY_CHECK_INTERVAL: Duration = Duration::from_millis(10);
+
+impl Db {
+    // Waits until no transaction or script is running, and keeps them from starting until the
+    // guard is dropped
+    pub async fn enter(&self) -> Result<OwnedRwLockReadGuard<()>, DbError> {
+        self.wait_at_gate(self.shared.gate.clone().read_owned())
+            .await
+    }
+
+    // Waits until no other command is running, and keeps every other command out until the
+    // guard is dropped
+    pub async fn enter_exclusive(&self) -> Result<OwnedRwLockWriteGuard<()>, DbError> {
+        self.wait_at_gate(self.shared.gate.clone().write_owned())
+            .await
+    }
+
+    // Keeps its place in the queue for the gate between checks
+    async fn wait_at_gate<G>(&self, gate: impl Future<Output = G>) -> Result<G, DbError> {
+        let mut gate = pin!(gate);
+        loop {
+            if self.script_busy() {
+                return Err(DbError::Busy);
+            }
+            if let Ok(guard) = tokio::time::timeout(BUSY_CHECK_INTERVAL, &mut gate).await {
+                return Ok(guard);
+            }
+        }
+    }
+}
@@ -0,0 +1,373 @@
+// FILTER expressions for VSIM, evaluated against each candidate's JSON attributes, as in
+// Redis' vector sets. `.field` selects a top level attribute; there are number, string and
+// array literals, arithmetic (`+ - * / % **`), comparisons, `in` for array membership or
+// substrings, and `and`/`or`/`not` (or `&& || !`). An element whose attributes are missing
+// or don't have the fields the expression needs never matches.
+use serde_json::Value as Json;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct VFilter {
+    expr: Expr,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Expr {
+    Number(f64),
+    String(String),
+    Array(Vec<Expr>),
+    Field(String),
+    Not(Box<Expr>),
+    Negate(Box<Expr>),
+    Binary(Op, Box<Expr>, Box<Expr>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Op {
+    Or,
+    And,
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    In,
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Rem,
+    Pow,
+}
+
+impl Op {
+    // Higher binds tighter
+    fn precedence(self) -> u8 {
+        match self {
+            Op::Or => 0,
+            Op::And => 1,
+            Op::Eq | Op::Ne | Op::Lt | Op::Le | Op::Gt | Op::Ge | Op::In => 2,
+            Op::Add | Op::Sub => 3,
+            Op::Mul | Op::Div | Op::Rem => 4,
+            Op::Pow => 5,
+        }
+    }
+}
+
+// What an expression evaluates to
+#[derive(Debug, Clone, PartialEq)]
+enum Operand {
+    Number(f64),
+    String(String),
+    Array(Vec<Operand>),
+}
+
+impl Operand {
+    fn from_json(value: &Json) -> Option<Operand> {
+        match value {
+            Json::Number(n) => n.as_f64().map(Operand::Number),
+            Json::String(s) => Some(Operand::String(s.clone())),
+            Json::Bool(b) => Some(Operand::Number(*b as u8 as f64)),
+            Json::Array(items) => items
+                .iter()
+                .map(Operand::from_json)
+                .collect::<Option<_>>()
+                .map(Operand::Array),
+            Json::Null | Json::Object(_) => None,
+        }
+    }
+
+    fn truthy(&self) -> bool {
+        match self {
+            Operand::Number(n) => *n != 0.0,
+            Operand::String(s) => !s.is_empty(),
+            Operand::Array(items) => !items.is_empty(),
+        }
+    }
+
+    fn number(&self) -> Option<f64> {
+        match self {
+            Operand::Number(n) => Some(*n),
+            _ => None,
+        }
+    }
+}
+
+fn boolean(b: bool) -> Operand {
+    Operand::Number(b as u8 as f64)
+}
+
+impl VFilter {
+    // None if the expression doesn't parse
+    pub fn parse(text: &str) -> Option<VFilter> {
+        let mut parser = Parser {
+            bytes: text.as_bytes(),
+            pos: 0,
+        };
+        let expr = parser.expr(0)?;
+        parser.skip_space();
+        (parser.pos == parser.bytes.len()).then_some(VFilter { expr })
+    }
+
+    pub(super) fn matches(&self, attributes: Option<&Json>) -> bool {
+        attributes
+            .and_then(|attributes| eval(&self.expr, attributes))
+            .is_some_and(|result| result.truthy())
+    }
+}
+
+fn eval(expr: &Expr, attributes: &Json) -> Option<Operand> {
+    Some(match expr {
+        Expr::Number(n) => Operand::Number(*n),
+        Expr::String(s) => Operand::String(s.clone()),
+        Expr::Array(items) => Operand::Array(
+            items
+                .iter()
+                .map(|item| eval(item, attributes))
+                .collect::<Option<_>>()?,
+        ),
+        Expr::Field(name) => Operand::from_json(attributes.get(name)?)?,
+        Expr::Not(inner) => boolean(!eval(inner, attributes)?.truthy()),
+        Expr::Negate(inner) => Operand::Number(-eval(inner, attributes)?.number()?),
+        Expr::Binary(Op::And, left, right) => {
+            boolean(eval(left, attributes)?.truthy() && eval(right, attributes)?.truthy())
+        }
+        Expr::Binary(Op::Or, left, right) => {
+            boolean(eval(left, attributes)?.truthy() || eval(right, attributes)?.truthy())
+        }
+        Expr::Binary(op, left, right) => {
+            let left = eval(left, attributes)?;
+            let right = eval(right, attributes)?;
+            binary(*op, left, right)?
+        }
+    })
+}
+
+fn binary(op: Op, left: Operand, right: Operand) -> Option<Operand> {
+    let ordering = || match (&left, &right) {
+        (Operand::Number(a), Operand::Number(b)) => a.partial_cmp(b),
+        (Operand::String(a), Operand::String(b)) => Some(a.cmp(b)),
+        _ => None,
+    };
+    Some(match op {
+        Op::Eq => boolean(left == right),
+        Op::Ne => boolean(left != right),
+        Op::Lt => boolean(ordering()?.is_lt()),
+        Op::Le => boolean(ordering()?.is_le()),
+        Op::Gt => boolean(ordering()?.is_gt()),
+        Op::Ge => boolean(ordering()?.is_ge()),
+        Op::In => match (&left, &right) {
+            (_, Operand::Array(items)) => boolean(items.contains(&left)),
+            (Operand::String(needle), Operand::String(haystack)) => {
+                boolean(haystack.contains(needle.as_str()))
+            }
+            _ => return None,
+        },
+        Op::Add | Op::Sub | Op::Mul | Op::Div | Op::Rem | Op::Pow => {
+            let (a, b) = (left.number()?, right.number()?);
+            Operand::Number(match op {
+                Op::Add => a + b,
+                Op::Sub => a - b,
+                Op::Mul => a * b,
+                Op::Div => a / b,
+                Op::Rem => a % b,
+                _ => a.powf(b),
+            })
+        }
+        Op::And | Op::Or => unreachable!("logical operators short circuit in eval"),
+    })
+}
+
+struct Parser<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl Parser<'_> {
+    fn skip_space(&mut self) {
+        while self
+            .bytes
+            .get(self.pos)
+            .is_some_and(u8::is_ascii_whitespace)
+        {
+            self.pos += 1;
+        }
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.bytes.get(self.pos).copied()
+    }
+
+    fn eat(&mut self, token: &str) -> bool {
+        self.skip_space();
+        if self.bytes[self.pos..].starts_with(token.as_bytes()) {
+            self.pos += token.len();
+            true
+        } else {
+            false
+        }
+    }
+
+    // A keyword operator, which mustn't run into a following identifier
+    fn eat_word(&mut self, word: &str) -> bool {
+        self.skip_space();
+        let end = self.pos + word.len();
+        let is_word = self.bytes[self.pos..].starts_with(word.as_bytes())
+            && !self.bytes.get(end).is_some_and(|&c| is_ident(c));
+        if is_word {
+            self.pos = end;
+        }
+        is_word
+    }
+
+    // Precedence climbing over binary operators that bind at least as tightly as `min`
+    fn expr(&mut self, min: u8) -> Option<Expr> {
+        let mut left = self.unary()?;
+        loop {
+            let start = self.pos;
+            let Some(op) = self.binary_op() else {
+                self.pos = start;
+                return Some(left);
+            };
+            if op.precedence() < min {
+                self.pos = start;
+                return Some(left);
+            }
+            // `**` is right associative, everything else left
+            let next = if op == Op::Pow {
+                op.precedence()
+            } else {
+                op.precedence() + 1
+            };
+            let right = self.expr(next)?;
+            left = Expr::Binary(op, Box::new(left), Box::new(right));
+        }
+    }
+
+    fn binary_op(&mut self) -> Option<Op> {
+        // Longer operators before their prefixes
+        let symbols = [
+            ("**", Op::Pow),
+            ("&&", Op::And),
+            ("||", Op::Or),
+            ("==", Op::Eq),
+            ("!=", Op::Ne),
+            ("<=", Op::Le),
+            (">=", Op::Ge),
+            ("<", Op::Lt),
+            (">", Op::Gt),
+            ("+", Op::Add),
+            ("-", Op::Sub),
+            ("*", Op::Mul),
+            ("/", Op::Div),
+            ("%", Op::Rem),
+        ];
+        for (symbol, op) in symbols {
+            if self.eat(symbol) {
+                return Some(op);
+            }
+        }
+        let words = [("and", Op::And), ("or", Op::Or), ("in", Op::In)];
+        words
+            .into_iter()
+            .find(|(word, _)| self.eat_word(word))
+            .map(|(_, op)| op)
+    }
+
+    fn unary(&mut self) -> Option<Expr> {
+        if self.eat("!") || self.eat_word("not") {
+            return Some(Expr::Not(Box::new(self.unary()?)));
+        }
+        if self.eat("-") {
+            return Some(Expr::Negate(Box::new(self.unary()?)));
+        }
+        self.primary()
+    }
+
+    fn primary(&mut self) -> Option<Expr> {
+        self.skip_space();
+        match self.peek()? {
+            b'(' => {
+                self.pos += 1;
+                let inner = self.expr(0)?;
+                self.eat(")").then_some(inner)
+            }
+            b'[' => {
+                self.pos += 1;
+                let mut items = Vec::new();
+                if self.eat("]") {
+                    return Some(Expr::Array(items));
+                }
+                loop {
+                    items.push(self.expr(0)?);
+                    if self.eat("]") {
+                        return Some(Expr::Array(items));
+                    }
+                    if !self.eat(",") {
+                        return None;
+                    }
+                }
+            }
+            quote @ (b'"' | b'\'') => self.string(quote).map(Expr::String),
+            b'.' if self
+                .bytes
+                .get(self.pos + 1)
+                .is_some_and(|&c| c.is_ascii_alphabetic() || c == b'_') =>
+            {
+                self.pos += 1;
+                let start = self.pos;
+                while self.peek().is_some_and(is_ident) {
+                    self.pos += 1;
+                }
+                let name = std::str::from_utf8(&self.bytes[start..self.pos]).ok()?;
+                Some(Expr::Field(name.to_string()))
+            }
+            c if c.is_ascii_digit() || c == b'.' => self.number().map(Expr::Number),
+            _ if self.eat_word("true") => Some(Expr::Number(1.0)),
+            _ if self.eat_word("false") => Some(Expr::Number(0.0)),
+            _ => None,
+        }
+    }
+
+    fn number(&mut self) -> Option<f64> {
+        let start = self.pos;
+        while let Some(c) = self.peek() {
+            let exponent_sign =
+                matches!(c, b'+' | b'-') && matches!(self.bytes[self.pos - 1], b'e' | b'E');
+            if !(c.is_ascii_digit() || matches!(c, b'.' | b'e' | b'E') || exponent_sign) {
+                break;
+            }
+            self.pos += 1;
+        }
+        std::str::from_utf8(&self.bytes[start..self.pos])
+            .ok()?
+            .parse()
+            .ok()
+    }
+
+    // A quoted string, where a backslash escapes the next character
+    fn string(&mut self, quote: u8) -> Option<String> {
+        self.pos += 1;
+        let mut out = Vec::new();
+        loop {
+            match self.peek()? {
+                c if c == quote => {
+                    self.pos += 1;
+                    return String::from_utf8(out).ok();
+                }
+                b'\\' => {
+                    out.push(*self.bytes.get(self.pos + 1)?);
+                    self.pos += 2;
+                }
+                c => {
+                    out.push(c);
+                    self.pos += 1;
+                }
+            }
+        }
+    }
+}
+
+fn is_ident(c: u8) -> bool {
+    c.is_ascii_alphanumeric() || c == b'_'
+}
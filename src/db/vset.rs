@@ -0,0 +1,306 @@
+// Vector sets, following Redis 8. Each element has a vector, indexed in an HNSW graph for
+// cosine similarity search, and optional JSON attributes that VSIM can filter on. Vectors
+// are normalized on the way in, keeping their length so VEMB can scale them back.
+use super::hnsw::{Hnsw, Vector};
+use super::vfilter::VFilter;
+use super::{Class, Db, DbError, Entry, State, Value};
+use bytes::Bytes;
+use serde_json::Value as Json;
+use std::collections::HashMap;
+
+const DEFAULT_M: usize = 16;
+const DEFAULT_EF_CONSTRUCTION: usize = 200;
+const DEFAULT_EF: usize = 100;
+// FILTER-EF, per result asked for
+const DEFAULT_FILTER_EFFORT: usize = 100;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Quantization {
+    NoQuant,
+    #[default]
+    Q8,
+    Bin,
+}
+
+// VADD's options. Quantization, M and EF only matter when the set is created, but a
+// quantization that differs from the set's is an error.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct VAddOptions {
+    pub quantization: Quantization,
+    pub ef: Option<usize>,
+    pub m: Option<usize>,
+    pub attributes: Option<Json>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum VSimTarget {
+    Element(Bytes),
+    Vector(Vec<f32>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct VSimQuery {
+    pub target: VSimTarget,
+    pub count: usize,
+    pub ef: Option<usize>,
+    pub filter: Option<VFilter>,
+    pub filter_ef: Option<usize>,
+    // Scan every element instead of searching the graph
+    pub truth: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct VSimMatch {
+    pub element: Bytes,
+    // From 0 for opposite vectors to 1 for identical ones
+    pub score: f64,
+    pub attributes: Option<Json>,
+}
+
+pub(super) struct VectorSet {
+    dim: usize,
+    quantization: Quantization,
+    index: Hnsw,
+    ids: HashMap<Bytes, usize>,
+    elements: HashMap<usize, Element>,
+}
+
+struct Element {
+    name: Bytes,
+    // Length before normalizing
+    norm: f32,
+    attributes: Option<Json>,
+}
+
+impl VectorSet {
+    fn new(dim: usize, options: &VAddOptions) -> VectorSet {
+        VectorSet {
+            dim,
+            quantization: options.quantization,
+            index: Hnsw::new(
+                options.m.unwrap_or(DEFAULT_M),
+                options.ef.unwrap_or(DEFAULT_EF_CONSTRUCTION),
+            ),
+            ids: HashMap::new(),
+            elements: HashMap::new(),
+        }
+    }
+
+    pub(super) fn len(&self) -> usize {
+        self.ids.len()
+    }
+
+    fn check_dim(&self, values: &[f32]) -> Result<(), DbError> {
+        if values.len() != self.dim {
+            return Err(DbError::VectorDimMismatch(values.len(), self.dim));
+        }
+        Ok(())
+    }
+
+    fn vector(&self, values: &[f32]) -> (Vector, f32) {
+        let norm = values.iter().map(|v| v * v).sum::<f32>().sqrt();
+        let normalized: Vec<f32> = match norm {
+            0.0 => values.to_vec(),
+            _ => values.iter().map(|v| v / norm).collect(),
+        };
+        (Vector::new(&normalized, self.quantization), norm)
+    }
+
+    fn search(&self, query: &VSimQuery) -> Result<Vec<VSimMatch>, DbError> {
+        let owned;
+        let target = match &query.target {
+            VSimTarget::Element(element) => {
+                let id = self.ids.get(element).ok_or(DbError::VectorElementMissing)?;
+                self.index.vector(*id)
+            }
+            VSimTarget::Vector(values) => {
+                self.check_dim(values)?;
+                owned = self.vector(values).0;
+                &owned
+            }
+        };
+
+        let accept = |id: usize| {
+            query
+                .filter
+                .as_ref()
+                .is_none_or(|filter| filter.matches(self.elements[&id].attributes.as_ref()))
+        };
+        let found = if query.truth {
+            self.index.scan(target, query.count, accept)
+        } else {
+            let effort = match &query.filter {
+                Some(_) => query
+                    .filter_ef
+                    .unwrap_or(query.count.saturating_mul(DEFAULT_FILTER_EFFORT)),
+                None => usize::MAX,
+            };
+            let ef = query.ef.unwrap_or(DEFAULT_EF);
+            self.index.search(target, query.count, ef, effort, accept)
+        };
+
+        Ok(found
+            .into_iter()
+            .map(|(distance, id)| {
+                let element = &self.elements[&id];
+                VSimMatch {
+                    element: element.name.clone(),
+                    score: (1.0 - f64::from(distance) / 2.0).clamp(0.0, 1.0),
+                    attributes: element.attributes.clone(),
+                }
+            })
+            .collect())
+    }
+}
+
+impl Db {
+    // Adds an element or replaces its vector, returning whether it is new. Attributes are
+    // only changed when given.
+    pub fn vadd(
+        &self,
+        key: &Bytes,
+        values: &[f32],
+        element: &Bytes,
+        options: VAddOptions,
+    ) -> Result<bool, DbError> {
+        let mut state = self.lock();
+        if state.vset_mut(key)?.is_none() {
+            state.insert(
+                key.clone(),
+                Entry {
+                    value: Value::VectorSet(VectorSet::new(values.len(), &options)),
+                    expires_at: None,
+                },
+            );
+        }
+        let set = state.vset_mut(key)?.ok_or(DbError::VectorSetMissing)?;
+        set.check_dim(values)?;
+        if set.quantization != options.quantization {
+            return Err(DbError::QuantizationMismatch);
+        }
+
+        let (vector, norm) = set.vector(values);
+        let mut attributes = options.attributes;
+        let added = match set.ids.remove(element) {
+            Some(old) => {
+                set.index.remove(old);
+                let previous = set.elements.remove(&old).and_then(|e| e.attributes);
+                attributes = attributes.or(previous);
+                false
+            }
+            None => true,
+        };
+        let id = set.index.insert(vector);
+        set.ids.insert(element.clone(), id);
+        set.elements.insert(
+            id,
+            Element {
+                name: element.clone(),
+                norm,
+                attributes,
+            },
+        );
+        state.notify(Class::Generic, "vadd", key);
+        Ok(added)
+    }
+
+    pub fn vsim(&self, key: &Bytes, query: &VSimQuery) -> Result<Vec<VSimMatch>, DbError> {
+        let mut state = self.lock_read();
+        match state.vset_mut(key)? {
+            Some(set) => set.search(query),
+            None => Ok(Vec::new()),
+        }
+    }
+
+    pub fn vrem(&self, key: &Bytes, element: &Bytes) -> Result<bool, DbError> {
+        let mut state = self.lock();
+        let Some(set) = state.vset_mut(key)? else {
+            return Ok(false);
+        };
+        let Some(id) = set.ids.remove(element) else {
+            return Ok(false);
+        };
+        set.index.remove(id);
+        set.elements.remove(&id);
+        state.notify(Class::Generic, "vrem", key);
+        state.remove_if_empty(key);
+        Ok(true)
+    }
+
+    pub fn vcard(&self, key: &Bytes) -> Result<usize, DbError> {
+        let mut state = self.lock_read();
+        Ok(state.vset_mut(key)?.map_or(0, |set| set.len()))
+    }
+
+    pub fn vdim(&self, key: &Bytes) -> Result<usize, DbError> {
+        let mut state = self.lock_read();
+        let set = state.vset_mut(key)?.ok_or(DbError::VectorSetMissing)?;
+        Ok(set.dim)
+    }
+
+    // The element's vector as stored, so approximated by any quantization
+    pub fn vemb(&self, key: &Bytes, element: &Bytes) -> Result<Option<Vec<f32>>, DbError> {
+        let mut state = self.lock_read();
+        let Some(set) = state.vset_mut(key)? else {
+            return Ok(None);
+        };
+        Ok(set.ids.get(element).map(|&id| {
+            let norm = set.elements[&id].norm;
+            set.index
+                .vector(id)
+                .to_f32()
+                .into_iter()
+                .map(|v| v * norm)
+                .collect()
+        }))
+    }
+
+    // Replaces the element's attributes, or removes them when None. Returns whether the
+    // element exists.
+    pub fn vsetattr(
+        &self,
+        key: &Bytes,
+        element: &Bytes,
+        attributes: Option<Json>,
+    ) -> Result<bool, DbError> {
+        let mut state = self.lock();
+        let Some(set) = state.vset_mut(key)? else {
+            return Ok(false);
+        };
+        match set.ids.get(element) {
+            Some(id) => {
+                set.elements
+                    .get_mut(id)
+                    .expect("indexed element")
+                    .attributes = attributes;
+                state.notify(Class::Generic, "vsetattr", key);
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    pub fn vgetattr(&self, key: &Bytes, element: &Bytes) -> Result<Option<Json>, DbError> {
+        let mut state = self.lock_read();
+        let Some(set) = state.vset_mut(key)? else {
+            return Ok(None);
+        };
+        Ok(set
+            .ids
+            .get(element)
+            .and_then(|id| set.elements[id].attributes.clone()))
+    }
+}
+
+impl State {
+    fn vset_mut(&mut self, key: &Bytes) -> Result<Option<&mut VectorSet>, DbError> {
+        match self.live(key) {
+            Some(Entry {
+                value: Value::VectorSet(set),
+                ..
+            }) => Ok(Some(set)),
+            Some(_) => Err(DbError::WrongType),
+            None => Ok(None),
+        }
+    }
+}
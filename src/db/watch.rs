@@ -0,0 +1,78 @@
+// WATCH. Every change to a key comes through `notify`, which marks the clients watching it as
+// dirty, and EXEC doesn't run for a dirty client. A key that was live when watched but has
+// since passed its expiry counts as changed too, even if nothing has removed it yet.
+use super::{Db, State};
+use bytes::Bytes;
+use std::collections::{HashMap, HashSet};
+use std::time::Instant;
+
+#[derive(Default)]
+pub(super) struct Watches {
+    // Key -> clients watching it
+    keys: HashMap<Bytes, HashSet<u64>>,
+    // Client -> the keys it watches, with when each was due to expire at the time
+    clients: HashMap<u64, Vec<(Bytes, Option<Instant>)>>,
+    // Clients with a watched key changed since it was watched
+    dirty: HashSet<u64>,
+}
+
+impl State {
+    pub(super) fn touch(&mut self, key: &Bytes) {
+        if let Some(ids) = self.watches.keys.get(key) {
+            self.watches.dirty.extend(ids);
+        }
+    }
+}
+
+impl Db {
+    pub fn watch(&self, id: u64, keys: &[Bytes]) {
+        let mut state = self.lock();
+        for key in keys {
+            // Expire it first, so a key already past its expiry isn't seen changing later
+            let expires_at = state.live(key).and_then(|entry| entry.expires_at);
+            let watched = state.watches.clients.entry(id).or_default();
+            if watched.iter().any(|(k, _)| k == key) {
+                continue;
+            }
+            watched.push((key.clone(), expires_at));
+            state
+                .watches
+                .keys
+                .entry(key.clone())
+                .or_default()
+                .insert(id);
+        }
+    }
+
+    // Whether any of the client's watched keys changed, so its EXEC mustn't run
+    pub fn watched_changed(&self, id: u64) -> bool {
+        let state = self.lock();
+        if state.watches.dirty.contains(&id) {
+            return true;
+        }
+        let now = Instant::now();
+        state
+            .watches
+            .clients
+            .get(&id)
+            .into_iter()
+            .flatten()
+            .any(|(_, expires_at)| expires_at.is_some_and(|exp| now > exp))
+    }
+
+    pub fn unwatch(&self, id: u64) {
+        let mut state = self.lock();
+        state.watches.dirty.remove(&id);
+        let Some(watched) = state.watches.clients.remove(&id) else {
+            return;
+        };
+        for (key, _) in watched {
+            if let Some(ids) = state.watches.keys.get_mut(&key) {
+                ids.remove(&id);
+                if ids.is_empty() {
+                    state.watches.keys.remove(&key);
+                }
+            }
+        }
+    }
+}
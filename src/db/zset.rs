@@ -0,0 +1,726 @@
+use super::skiplist::SkipList;
+use super::{Class, Db, DbError, Entry, SetOp, State, Value};
+use bytes::Bytes;
+use rand::seq::{IndexedRandom, IteratorRandom};
+use std::collections::{HashMap, HashSet};
+
+// A member→score map for O(1) lookups alongside a skiplist for ordered and ranked access
+#[derive(Default)]
+pub(super) struct SortedSet {
+    scores: HashMap<Bytes, f64>,
+    list: SkipList,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ZAddCondition {
+    Nx,
+    Xx,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ZAddComparison {
+    Gt,
+    Lt,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ZAddFlags {
+    pub condition: Option<ZAddCondition>,
+    pub comparison: Option<ZAddComparison>,
+    // Count changed scores as well as new members
+    pub ch: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ScoreBound {
+    Inclusive(f64),
+    Exclusive(f64),
+}
+
+// `-` and `+` are the lowest and highest possible members
+#[derive(Debug, Clone, PartialEq)]
+pub enum LexBound {
+    Min,
+    Max,
+    Inclusive(Bytes),
+    Exclusive(Bytes),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum RangeBy {
+    Rank { start: i64, stop: i64 },
+    Score { min: ScoreBound, max: ScoreBound },
+    Lex { min: LexBound, max: LexBound },
+}
+
+// The range understood by ZRANGE and its older BYSCORE/BYLEX/REV variants
+#[derive(Debug, Clone, PartialEq)]
+pub struct ZRange {
+    pub by: RangeBy,
+    pub rev: bool,
+    pub limit: Option<(i64, i64)>,
+}
+
+impl ScoreBound {
+    fn below_min(&self, score: f64) -> bool {
+        match self {
+            ScoreBound::Inclusive(min) => score < *min,
+            ScoreBound::Exclusive(min) => score <= *min,
+        }
+    }
+
+    fn above_max(&self, score: f64) -> bool {
+        match self {
+            ScoreBound::Inclusive(max) => score > *max,
+            ScoreBound::Exclusive(max) => score >= *max,
+        }
+    }
+}
+
+impl LexBound {
+    fn below_min(&self, member: &Bytes) -> bool {
+        match self {
+            LexBound::Min => false,
+            LexBound::Max => true,
+            LexBound::Inclusive(min) => member < min,
+            LexBound::Exclusive(min) => member <= min,
+        }
+    }
+
+    fn above_max(&self, member: &Bytes) -> bool {
+        match self {
+            LexBound::Min => true,
+            LexBound::Max => false,
+            LexBound::Inclusive(max) => member > max,
+            LexBound::Exclusive(max) => member >= max,
+        }
+    }
+}
+
+impl SortedSet {
+    pub(super) fn len(&self) -> usize {
+        self.scores.len()
+    }
+
+    pub(super) fn score(&self, member: &Bytes) -> Option<f64> {
+        self.scores.get(member).copied()
+    }
+
+    // Returns true if the member is new
+    pub(super) fn insert(&mut self, member: Bytes, score: f64) -> bool {
+        match self.scores.insert(member.clone(), score) {
+            Some(old) if old == score => false,
+            Some(old) => {
+                self.list.remove(old, &member);
+                self.list.insert(score, member);
+                false
+            }
+            None => {
+                self.list.insert(score, member);
+                true
+            }
+        }
+    }
+
+    pub(super) fn remove(&mut self, member: &Bytes) -> bool {
+        match self.scores.remove(member) {
+            Some(score) => self.list.remove(score, member),
+            None => false,
+        }
+    }
+
+    pub(super) fn rank(&self, member: &Bytes, rev: bool) -> Option<usize> {
+        let score = self.score(member)?;
+        let rank = self.list.rank(score, member)?;
+        Some(if rev { self.len() - 1 - rank } else { rank })
+    }
+
+    pub(super) fn iter(&self) -> impl Iterator<Item = (&Bytes, f64)> {
+        self.scores.iter().map(|(m, s)| (m, *s))
+    }
+
+    pub(super) fn range(&self, range: &ZRange) -> Vec<(Bytes, f64)> {
+        let list = &self.list;
+
+        let (start, step): (Option<usize>, Step) = match (&range.by, range.rev) {
+            (RangeBy::Rank { start, stop }, rev) => {
+                let Some((start, stop)) = normalize_ranks(*start, *stop, self.len()) else {
+                    return Vec::new();
+                };
+                let first = if rev { self.len() - 1 - start } else { start };
+                let step = if rev { SkipList::prev } else { SkipList::next };
+                return walk(list, list.by_rank(first), step)
+                    .take(stop - start + 1)
+                    .map(|node| (list.member(node).clone(), list.score(node)))
+                    .collect();
+            }
+            (RangeBy::Score { min, .. }, false) => {
+                (list.first_where(|s, _| min.below_min(s)), SkipList::next)
+            }
+            (RangeBy::Score { max, .. }, true) => {
+                (list.last_where(|s, _| max.above_max(s)), SkipList::prev)
+            }
+            (RangeBy::Lex { min, .. }, false) => {
+                (list.first_where(|_, m| min.below_min(m)), SkipList::next)
+            }
+            (RangeBy::Lex { max, .. }, true) => {
+                (list.last_where(|_, m| max.above_max(m)), SkipList::prev)
+            }
+        };
+
+        let in_range = |node: usize| match &range.by {
+            RangeBy::Score { min, max } => {
+                let score = list.score(node);
+                !min.below_min(score) && !max.above_max(score)
+            }
+            RangeBy::Lex { min, max } => {
+                let member = list.member(node);
+                !min.below_min(member) && !max.above_max(member)
+            }
+            RangeBy::Rank { .. } => true,
+        };
+
+        let (offset, count) = range.limit.unwrap_or((0, -1));
+        if offset < 0 {
+            return Vec::new();
+        }
+        let count = usize::try_from(count).unwrap_or(usize::MAX);
+
+        walk(list, start, step)
+            .take_while(|&node| in_range(node))
+            .skip(offset as usize)
+            .take(count)
+            .map(|node| (list.member(node).clone(), list.score(node)))
+            .collect()
+    }
+
+    // Number of elements in a score or lex range, found from the ranks of its two ends
+    pub(super) fn count(&self, by: &RangeBy) -> usize {
+        let list = &self.list;
+        let ends = match by {
+            RangeBy::Score { min, max } => (
+                list.first_where(|s, _| min.below_min(s)),
+                list.last_where(|s, _| max.above_max(s)),
+            ),
+            RangeBy::Lex { min, max } => (
+                list.first_where(|_, m| min.below_min(m)),
+                list.last_where(|_, m| max.above_max(m)),
+            ),
+            RangeBy::Rank { start, stop } => {
+                return normalize_ranks(*start, *stop, self.len())
+                    .map_or(0, |(start, stop)| stop - start + 1);
+            }
+        };
+
+        let (Some(first), Some(last)) = ends else {
+            return 0;
+        };
+        let rank_of = |node| list.rank(list.score(node), list.member(node));
+        match (rank_of(first), rank_of(last)) {
+            (Some(first), Some(last)) if first <= last => last - first + 1,
+            _ => 0,
+        }
+    }
+
+    // Remove up to `count` elements from the low or high end
+    pub(super) fn pop(&mut self, count: usize, max: bool) -> Vec<(Bytes, f64)> {
+        let mut popped = Vec::with_capacity(count.min(self.len()));
+        while popped.len() < count {
+            let node = if max {
+                self.list.last()
+            } else {
+                self.list.first()
+            };
+            let Some(node) = node else {
+                break;
+            };
+            let member = self.list.member(node).clone();
+            let score = self.list.score(node);
+            self.remove(&member);
+            popped.push((member, score));
+        }
+        popped
+    }
+}
+
+// Clamp a start/stop pair that may count back from the end, returning None for an empty range
+fn normalize_ranks(start: i64, stop: i64, len: usize) -> Option<(usize, usize)> {
+    let len = len as i64;
+    let start = if start < 0 {
+        (len + start).max(0)
+    } else {
+        start
+    };
+    let stop = if stop < 0 {
+        len + stop
+    } else {
+        stop.min(len - 1)
+    };
+    if start > stop || start >= len {
+        return None;
+    }
+    Some((start as usize, stop as usize))
+}
+
+// Moves to the next or previous node depending on the range direction
+type Step = fn(&SkipList, usize) -> Option<usize>;
+
+fn walk(list: &SkipList, start: Option<usize>, step: Step) -> impl Iterator<Item = usize> + '_ {
+    std::iter::successors(start, move |&node| step(list, node))
+}
+
+impl Db {
+    pub fn zadd(
+        &self,
+        key: &Bytes,
+        flags: ZAddFlags,
+        pairs: Vec<(f64, Bytes)>,
+    ) -> Result<usize, DbError> {
+        let mut state = self.lock();
+        if flags.condition == Some(ZAddCondition::Xx) && state.zset_mut(key)?.is_none() {
+            return Ok(0);
+        }
+        let zset = state.zset_or_insert(key)?;
+
+        let mut added = 0;
+        let mut changed = 0;
+        for (score, member) in pairs {
+            match zset.score(&member) {
+                Some(current) => {
+                    if flags.condition == Some(ZAddCondition::Nx)
+                        || !passes_comparison(flags.comparison, current, score)
+                        || current == score
+                    {
+                        continue;
+                    }
+                    zset.insert(member, score);
+                    changed += 1;
+                }
+                None => {
+                    if flags.condition == Some(ZAddCondition::Xx) {
+                        continue;
+                    }
+                    zset.insert(member, score);
+                    added += 1;
+                }
+            }
+        }
+
+        if added + changed > 0 {
+            state.notify(Class::ZSet, "zadd", key);
+        }
+        state.remove_if_empty(key);
+        Ok(if flags.ch { added + changed } else { added })
+    }
+
+    // ZINCRBY and ZADD INCR; None when the flags prevented the update
+    pub fn zincrby(
+        &self,
+        key: &Bytes,
+        flags: ZAddFlags,
+        delta: f64,
+        member: &Bytes,
+    ) -> Result<Option<f64>, DbError> {
+        let mut state = self.lock();
+        if flags.condition == Some(ZAddCondition::Xx) && state.zset_mut(key)?.is_none() {
+            return Ok(None);
+        }
+        let zset = state.zset_or_insert(key)?;
+
+        let current = zset.score(member);
+        let score = match current {
+            Some(_) if flags.condition == Some(ZAddCondition::Nx) => None,
+            None if flags.condition == Some(ZAddCondition::Xx) => None,
+            Some(current) => Some(current + delta),
+            None => Some(delta),
+        };
+
+        let result = match score {
+            Some(score) if score.is_nan() => Err(DbError::NanScore),
+            Some(score)
+                if current.is_none_or(|c| passes_comparison(flags.comparison, c, score)) =>
+            {
+                zset.insert(member.clone(), score);
+                state.notify(Class::ZSet, "zincr", key);
+                Ok(Some(score))
+            }
+            _ => Ok(None),
+        };
+
+        state.remove_if_empty(key);
+        result
+    }
+
+    pub fn zrem(&self, key: &Bytes, members: &[Bytes]) -> Result<usize, DbError> {
+        let mut state = self.lock();
+        let Some(zset) = state.zset_mut(key)? else {
+            return Ok(0);
+        };
+        let removed = members.iter().filter(|m| zset.remove(m)).count();
+        if removed > 0 {
+            state.notify(Class::ZSet, "zrem", key);
+        }
+        state.remove_if_empty(key);
+        Ok(removed)
+    }
+
+    pub fn zscore(&self, key: &Bytes, member: &Bytes) -> Result<Option<f64>, DbError> {
+        let mut state = self.lock_read();
+        Ok(state.zset_mut(key)?.and_then(|zset| zset.score(member)))
+    }
+
+    pub fn zmscore(&self, key: &Bytes, members: &[Bytes]) -> Result<Vec<Option<f64>>, DbError> {
+        let mut state = self.lock_read();
+        let zset = state.zset_mut(key)?;
+        Ok(members
+            .iter()
+            .map(|m| zset.as_ref().and_then(|zset| zset.score(m)))
+            .collect())
+    }
+
+    pub fn zcard(&self, key: &Bytes) -> Result<usize, DbError> {
+        let mut state = self.lock_read();
+        Ok(state.zset_mut(key)?.map_or(0, |zset| zset.len()))
+    }
+
+    // The member's rank and score, counting from the highest score when `rev` is set
+    pub fn zrank(
+        &self,
+        key: &Bytes,
+        member: &Bytes,
+        rev: bool,
+    ) -> Result<Option<(usize, f64)>, DbError> {
+        let mut state = self.lock_read();
+        Ok(state.zset_mut(key)?.and_then(|zset| {
+            let rank = zset.rank(member, rev)?;
+            Some((rank, zset.score(member)?))
+        }))
+    }
+
+    pub fn zrange(&self, key: &Bytes, range: &ZRange) -> Result<Vec<(Bytes, f64)>, DbError> {
+        let mut state = self.lock_read();
+        Ok(state
+            .zset_mut(key)?
+            .map(|zset| zset.range(range))
+            .unwrap_or_default())
+    }
+
+    // ZCOUNT and ZLEXCOUNT
+    pub fn zcount(&self, key: &Bytes, by: &RangeBy) -> Result<usize, DbError> {
+        let mut state = self.lock_read();
+        Ok(state.zset_mut(key)?.map_or(0, |zset| zset.count(by)))
+    }
+
+    pub fn zpop(&self, key: &Bytes, count: usize, max: bool) -> Result<Vec<(Bytes, f64)>, DbError> {
+        let mut state = self.lock();
+        let Some(zset) = state.zset_mut(key)? else {
+            return Ok(Vec::new());
+        };
+        let popped = zset.pop(count, max);
+        if !popped.is_empty() {
+            state.notify(Class::ZSet, pop_event(max), key);
+        }
+        state.remove_if_empty(key);
+        Ok(popped)
+    }
+
+    // A positive count returns distinct members, a negative count may repeat them
+    pub fn zrandmember(&self, key: &Bytes, count: i64) -> Result<Vec<(Bytes, f64)>, DbError> {
+        let mut state = self.lock_read();
+        let Some(zset) = state.zset_mut(key)? else {
+            return Ok(Vec::new());
+        };
+        let mut rng = rand::rng();
+
+        if count >= 0 {
+            // Sampling allocates for `count` up front, so never ask for more than there is
+            let count = (count as usize).min(zset.len());
+            return Ok(zset
+                .iter()
+                .map(|(m, s)| (m.clone(), s))
+                .choose_multiple(&mut rng, count));
+        }
+
+        let members: Vec<(&Bytes, f64)> = zset.iter().collect();
+        Ok((0..count.unsigned_abs())
+            .filter_map(|_| members.choose(&mut rng).map(|(m, s)| ((*m).clone(), *s)))
+            .collect())
+    }
+}
+
+pub(super) fn pop_event(max: bool) -> &'static str {
+    match max {
+        true => "zpopmax",
+        false => "zpopmin",
+    }
+}
+
+fn passes_comparison(comparison: Option<ZAddComparison>, current: f64, new: f64) -> bool {
+    match comparison {
+        Some(ZAddComparison::Gt) => new > current,
+        Some(ZAddComparison::Lt) => new < current,
+        None => true,
+    }
+}
+
+impl State {
+    pub(super) fn zset_mut(&mut self, key: &Bytes) -> Result<Option<&mut SortedSet>, DbError> {
+        match self.live(key) {
+            Some(Entry {
+                value: Value::SortedSet(zset),
+                ..
+            }) => Ok(Some(zset)),
+            Some(_) => Err(DbError::WrongType),
+            None => Ok(None),
+        }
+    }
+
+    pub(super) fn zset_or_insert(&mut self, key: &Bytes) -> Result<&mut SortedSet, DbError> {
+        if self.live(key).is_none() {
+            self.insert(
+                key.clone(),
+                Entry {
+                    value: Value::SortedSet(SortedSet::default()),
+                    expires_at: None,
+                },
+            );
+        }
+        self.signal_ready(key);
+        self.zset_mut(key)?.ok_or(DbError::WrongType)
+    }
+    // Look up several sets or sorted sets at once; missing keys are None
+    fn zsources(&mut self, keys: &[Bytes]) -> Result<Vec<Option<ZSource<'_>>>, DbError> {
+        for key in keys {
+            self.live(key);
+        }
+
+        keys.iter()
+            .map(|key| match self.entries.get(key) {
+                Some(Entry {
+                    value: Value::Set(set),
+                    ..
+                }) => Ok(Some(ZSource::Set(set))),
+                Some(Entry {
+                    value: Value::SortedSet(zset),
+                    ..
+                }) => Ok(Some(ZSource::SortedSet(zset))),
+                Some(_) => Err(DbError::WrongType),
+                None => Ok(None),
+            })
+            .collect()
+    }
+
+    // Replace `key` with a sorted set of `members`, deleting it if there are none. `event` is
+    // the notification for the stored set.
+    pub(super) fn store_zset(
+        &mut self,
+        key: &Bytes,
+        event: &'static str,
+        members: impl IntoIterator<Item = (Bytes, f64)>,
+    ) -> usize {
+        let mut zset = SortedSet::default();
+        for (member, score) in members {
+            zset.insert(member, score);
+        }
+        let len = zset.len();
+
+        if len == 0 {
+            if self.entries.remove(key).is_some() {
+                self.notify(Class::Generic, "del", key);
+            }
+        } else {
+            self.insert(
+                key.clone(),
+                Entry {
+                    value: Value::SortedSet(zset),
+                    expires_at: None,
+                },
+            );
+            self.signal_ready(key);
+            self.notify(Class::ZSet, event, key);
+        }
+        self.reindex(key);
+        len
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum Aggregate {
+    #[default]
+    Sum,
+    Min,
+    Max,
+}
+
+// Plain sets take part in sorted set aggregation with every score set to 1
+enum ZSource<'a> {
+    Set(&'a HashSet<Bytes>),
+    SortedSet(&'a SortedSet),
+}
+
+impl ZSource<'_> {
+    fn len(&self) -> usize {
+        match self {
+            ZSource::Set(set) => set.len(),
+            ZSource::SortedSet(zset) => zset.len(),
+        }
+    }
+
+    fn score(&self, member: &Bytes) -> Option<f64> {
+        match self {
+            ZSource::Set(set) => set.contains(member).then_some(1.0),
+            ZSource::SortedSet(zset) => zset.score(member),
+        }
+    }
+
+    fn iter(&self) -> Box<dyn Iterator<Item = (&Bytes, f64)> + '_> {
+        match self {
+            ZSource::Set(set) => Box::new(set.iter().map(|m| (m, 1.0))),
+            ZSource::SortedSet(zset) => Box::new(zset.iter()),
+        }
+    }
+}
+
+impl Aggregate {
+    fn apply(self, acc: f64, score: f64) -> f64 {
+        match self {
+            // inf + -inf is NaN, which Redis turns into 0
+            Aggregate::Sum => {
+                let sum = acc + score;
+                if sum.is_nan() { 0.0 } else { sum }
+            }
+            Aggregate::Min => acc.min(score),
+            Aggregate::Max => acc.max(score),
+        }
+    }
+}
+
+// Scales a score by its key's weight, treating 0 * inf as 0 like Redis
+fn weighted(score: f64, weight: f64) -> f64 {
+    let score = score * weight;
+    if score.is_nan() { 0.0 } else { score }
+}
+
+fn aggregate(
+    op: SetOp,
+    sources: &[Option<ZSource>],
+    weights: Option<&[f64]>,
+    aggregate: Aggregate,
+) -> HashMap<Bytes, f64> {
+    let weight = |i: usize| weights.map_or(1.0, |w| w[i]);
+
+    match op {
+        SetOp::Union => {
+            let mut out: HashMap<Bytes, f64> = HashMap::new();
+            for (i, source) in sources.iter().enumerate() {
+                let Some(source) = source else {
+                    continue;
+                };
+                for (member, score) in source.iter() {
+                    let score = weighted(score, weight(i));
+                    out.entry(member.clone())
+                        .and_modify(|acc| *acc = aggregate.apply(*acc, score))
+                        .or_insert(score);
+                }
+            }
+            out
+        }
+        SetOp::Inter => {
+            // A missing key is empty, so the intersection is empty too
+            let Some(present) = sources
+                .iter()
+                .map(Option::as_ref)
+                .collect::<Option<Vec<_>>>()
+            else {
+                return HashMap::new();
+            };
+            let Some(smallest) = (0..present.len()).min_by_key(|&i| present[i].len()) else {
+                return HashMap::new();
+            };
+
+            let mut out = HashMap::new();
+            'members: for (member, _) in present[smallest].iter() {
+                let mut acc = None;
+                for (i, source) in present.iter().enumerate() {
+                    let Some(score) = source.score(member) else {
+                        continue 'members;
+                    };
+                    let score = weighted(score, weight(i));
+                    acc = Some(acc.map_or(score, |acc| aggregate.apply(acc, score)));
+                }
+                if let Some(acc) = acc {
+                    out.insert(member.clone(), acc);
+                }
+            }
+            out
+        }
+        SetOp::Diff => {
+            let Some(Some(first)) = sources.first() else {
+                return HashMap::new();
+            };
+            first
+                .iter()
+                .filter(|(member, _)| {
+                    sources[1..]
+                        .iter()
+                        .flatten()
+                        .all(|s| s.score(member).is_none())
+                })
+                .map(|(member, score)| (member.clone(), score))
+                .collect()
+        }
+    }
+}
+
+impl Db {
+    // ZUNION, ZINTER and ZDIFF, ordered by score then member
+    pub fn zset_op(
+        &self,
+        op: SetOp,
+        keys: &[Bytes],
+        weights: Option<&[f64]>,
+        aggregate_by: Aggregate,
+    ) -> Result<Vec<(Bytes, f64)>, DbError> {
+        let mut state = self.lock_read();
+        let sources = state.zsources(keys)?;
+        let mut out: Vec<(Bytes, f64)> = aggregate(op, &sources, weights, aggregate_by)
+            .into_iter()
+            .collect();
+        out.sort_by(|(am, a), (bm, b)| a.total_cmp(b).then_with(|| am.cmp(bm)));
+        Ok(out)
+    }
+
+    // ZUNIONSTORE, ZINTERSTORE and ZDIFFSTORE, replacing whatever `destination` held
+    pub fn zset_op_store(
+        &self,
+        op: SetOp,
+        destination: &Bytes,
+        keys: &[Bytes],
+        weights: Option<&[f64]>,
+        aggregate_by: Aggregate,
+    ) -> Result<usize, DbError> {
+        let mut state = self.lock();
+        let sources = state.zsources(keys)?;
+        let result = aggregate(op, &sources, weights, aggregate_by);
+        let event = match op {
+            SetOp::Inter => "zinterstore",
+            SetOp::Union => "zunionstore",
+            SetOp::Diff => "zdiffstore",
+        };
+        Ok(state.store_zset(destination, event, result))
+    }
+
+    pub fn zrangestore(
+        &self,
+        destination: &Bytes,
+        source: &Bytes,
+        range: &ZRange,
+    ) -> Result<usize, DbError> {
+        let mut state = self.lock();
+        let result = state
+            .zset_mut(source)?
+            .map(|zset| zset.range(range))
+            .unwrap_or_default();
+        Ok(state.store_zset(destination, "zrangestore", result))
+    }
+}
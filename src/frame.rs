@@ -11,7 +11,12 @@ pub enum Frame {
     Integer(i64),
     BulkString(Bytes),
     Null,
+    // The nil reply some commands give instead of an array, such as an EXEC that didn't run
+    NullArray,
     Array(Vec<Frame>),
+    // RESP3 only: out of band data such as invalidation messages, and maps of replies
+    Push(Vec<Frame>),
+    Map(Vec<(Frame, Frame)>),
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -20,8 +25,6 @@ pub enum ParseError {
     Incomplete,
     #[error("Invalid Integer")]
     InvalidInteger,
-    #[error("Invalid String")]
-    InvalidString,
     #[error("Invalid Frame Ending")]
     InvalidEnd,
     #[error("Unknown Frame Type")]
@@ -33,7 +36,7 @@ pub enum ParseError {
 }
 
 impl Frame {
-    // Parse RESP2 data from a buffer
+    // Parse RESP2 data, and the RESP3 push and map types, from a buffer
     pub fn parse(buf: &mut Cursor<&[u8]>) -> Result<Frame, ParseError> {
         match get_u8(buf)? {
             b'+' => {
@@ -75,10 +78,6 @@ impl Frame {
 
                     let (data, rest) = chunk.split_at(len);
 
-                    if data.iter().any(|&b| b == b'\r' || b == b'\n') {
-                        return Err(ParseError::InvalidString);
-                    }
-
                     if rest.len() < 2 || rest[0] != b'\r' || rest[1] != b'\n' {
                         return Err(ParseError::Incomplete);
                     }
@@ -102,6 +101,9 @@ impl Frame {
             b'*' => {
                 // Array
                 let line = get_line(buf)?;
+                if line == b"-1" {
+                    return Ok(Frame::NullArray);
+                }
                 let len = get_integer(line)? as usize;
                 let mut out = Vec::with_capacity(len);
 
@@ -111,6 +113,31 @@ impl Frame {
 
                 Ok(Frame::Array(out))
             }
+            b'>' => {
+                // Push, which is laid out like an array
+                let line = get_line(buf)?;
+                let len = get_integer(line)? as usize;
+                let mut out = Vec::with_capacity(len);
+
+                for _ in 0..len {
+                    out.push(Frame::parse(buf)?);
+                }
+
+                Ok(Frame::Push(out))
+            }
+            b'%' => {
+                // Map, with a length counting key value pairs
+                let line = get_line(buf)?;
+                let len = get_integer(line)? as usize;
+                let mut out = Vec::with_capacity(len);
+
+                for _ in 0..len {
+                    let key = Frame::parse(buf)?;
+                    out.push((key, Frame::parse(buf)?));
+                }
+
+                Ok(Frame::Map(out))
+            }
             _ => Err(ParseError::UnknownType),
         }
     }
@@ -139,7 +166,27 @@ impl Frame {
                 }
                 out.into()
             }
+            Frame::Push(a) => {
+                let header = Bytes::from(format!(">{}\r\n", a.len()));
+                let mut out = BytesMut::new();
+                out.put(header);
+                for frame in a {
+                    out.put(frame.to_bytes());
+                }
+                out.into()
+            }
+            Frame::Map(pairs) => {
+                let header = Bytes::from(format!("%{}\r\n", pairs.len()));
+                let mut out = BytesMut::new();
+                out.put(header);
+                for (key, value) in pairs {
+                    out.put(key.to_bytes());
+                    out.put(value.to_bytes());
+                }
+                out.into()
+            }
             Frame::Null => Bytes::from("$-1\r\n"),
+            Frame::NullArray => Bytes::from("*-1\r\n"),
         }
     }
 }
@@ -2,7 +2,9 @@ pub mod cmd;
 pub mod connection;
 pub mod db;
 pub mod frame;
+pub mod script;
 pub mod server;
+pub mod wasm;
 
 pub use cmd::Command;
 pub use connection::Connection;
@@ -1,10 +1,15 @@
-use padis::{Db, run_server};
+use padis::{Db, run_server, script};
+use std::path::Path;
 use tokio::net::TcpListener;
 
+// Where function libraries are kept between restarts
+const FUNCTIONS_FILE: &str = "functions.dump";
+
 #[tokio::main]
 async fn main() {
     let listener = TcpListener::bind("127.0.0.1:6379").await.unwrap();
     let db = Db::new();
+    script::function_persist(&db, Path::new(FUNCTIONS_FILE)).unwrap();
 
     println!("Listening on port 6379");
     run_server(listener, db).await;
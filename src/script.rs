@@ -0,0 +1,303 @@
+// Lua scripting for EVAL and EVALSHA, and for FCALL through `function`. Each run gets a fresh
+// Lua 5.1 state with the base, table, string and math libraries, `cjson` and `struct`, the KEYS
+// and ARGV tables, and a `redis` table whose `call` and `pcall` parse their arguments into a
+// `Command` and run it through the connection's executor. The server holds the gate exclusively
+// for the whole script, so no other client's command lands in the middle of it.
+//
+// Replies reach Lua the way Redis converts them: integers become numbers, bulk strings
+// strings, arrays tables, status replies {ok = ...}, errors {err = ...} and nils false. The
+// value the script returns goes back the other way, with numbers truncated to integers, true
+// as 1, false and nil as a nil reply, and tables read as arrays up to their first nil unless
+// they hold an `err` or `ok` field.
+use crate::cmd::CommandError;
+use crate::db::{DbError, ScriptRun, sha1_hex};
+use crate::server::execute;
+use crate::{Command, Db, Frame};
+use bytes::Bytes;
+use mlua::{HookTriggers, Lua, LuaOptions, StdLib, Table, Value, Variadic};
+use std::sync::Arc;
+
+mod cjson;
+mod function;
+mod pack;
+
+pub use function::{fcall, function_load, function_persist, function_restore};
+
+// VM instructions between checks for SCRIPT KILL or a library taking too long to load
+const KILL_CHECK_INTERVAL: u32 = 1000;
+
+// EVAL, which also caches the script for EVALSHA
+pub fn eval(db: &Db, body: Bytes, keys: Vec<Bytes>, args: Vec<Bytes>) -> Frame {
+    db.script_load(body.clone());
+    run(db, &body, keys, args)
+}
+
+pub fn evalsha(db: &Db, sha: &[u8], keys: Vec<Bytes>, args: Vec<Bytes>) -> Frame {
+    match db.script_get(sha) {
+        Some(body) => run(db, &body, keys, args),
+        None => Frame::SimpleError(DbError::NoScript.to_string()),
+    }
+}
+
+fn run(db: &Db, body: &[u8], keys: Vec<Bytes>, args: Vec<Bytes>) -> Frame {
+    db.script_run(|db, script| match call(db, script, body, keys, args) {
+        Ok(frame) => frame,
+        Err(e) => Frame::SimpleError(error_message(&e)),
+    })
+}
+
+fn call(
+    db: &Db,
+    script: &Arc<ScriptRun>,
+    body: &[u8],
+    keys: Vec<Bytes>,
+    args: Vec<Bytes>,
+) -> mlua::Result<Frame> {
+    let lua = interpreter(killable(script))?;
+    let globals = lua.globals();
+    globals.set("KEYS", strings(&lua, &keys)?)?;
+    globals.set("ARGV", strings(&lua, &args)?)?;
+    with_commands(&lua, db, script, false, || {
+        lua.load(body).set_name("@user_script").call(())
+    })
+}
+
+// A fresh interpreter with the libraries every script gets, which `check` can stop by
+// returning an error
+fn interpreter(check: impl Fn() -> mlua::Result<()> + Send + 'static) -> mlua::Result<Lua> {
+    let lua = Lua::new_with(
+        StdLib::TABLE | StdLib::STRING | StdLib::MATH,
+        LuaOptions::default(),
+    )?;
+    lua.set_hook(
+        HookTriggers::new().every_nth_instruction(KILL_CHECK_INTERVAL),
+        move |_, _| check(),
+    );
+
+    {
+        let globals = lua.globals();
+        // Scripts can't reach the filesystem
+        globals.set("dofile", Value::Nil)?;
+        globals.set("loadfile", Value::Nil)?;
+        globals.set("cjson", cjson::library(&lua)?)?;
+        globals.set("struct", pack::library(&lua)?)?;
+        globals.set("redis", library(&lua)?)?;
+    }
+    Ok(lua)
+}
+
+// Stops the script once SCRIPT KILL asks it to
+fn killable(script: &Arc<ScriptRun>) -> impl Fn() -> mlua::Result<()> + Send + 'static {
+    let script = script.clone();
+    move || match script.killed() {
+        true => Err(mlua::Error::RuntimeError(
+            "Script killed by user with SCRIPT KILL...".into(),
+        )),
+        false => Ok(()),
+    }
+}
+
+// Runs `main` with redis.call and redis.pcall in place, turning what it returns into a reply.
+// With `read_only`, commands that write are refused.
+fn with_commands<'lua>(
+    lua: &'lua Lua,
+    db: &Db,
+    script: &ScriptRun,
+    read_only: bool,
+    main: impl FnOnce() -> mlua::Result<Value<'lua>>,
+) -> mlua::Result<Frame> {
+    let redis: Table = lua.globals().get("redis")?;
+    lua.scope(|scope| {
+        redis.set(
+            "call",
+            scope.create_function(|lua, args: Variadic<Value>| {
+                match dispatch(db, script, read_only, lua, args)? {
+                    Frame::SimpleError(e) => Err(mlua::Error::RuntimeError(e)),
+                    frame => to_lua(lua, frame),
+                }
+            })?,
+        )?;
+        redis.set(
+            "pcall",
+            scope.create_function(|lua, args: Variadic<Value>| {
+                to_lua(lua, dispatch(db, script, read_only, lua, args)?)
+            })?,
+        )?;
+        Ok(from_lua(main()?))
+    })
+}
+
+// The `redis` table's helpers that don't run commands
+fn library(lua: &Lua) -> mlua::Result<Table<'_>> {
+    let redis = lua.create_table()?;
+    redis.set(
+        "error_reply",
+        lua.create_function(|lua, msg: mlua::String| reply_table(lua, "err", msg))?,
+    )?;
+    redis.set(
+        "status_reply",
+        lua.create_function(|lua, msg: mlua::String| reply_table(lua, "ok", msg))?,
+    )?;
+    redis.set(
+        "sha1hex",
+        lua.create_function(|_, data: mlua::String| Ok(sha1_hex(data.as_bytes())))?,
+    )?;
+    // Written to stderr along with the server's own messages
+    redis.set(
+        "log",
+        lua.create_function(|_, (_level, parts): (i64, Variadic<mlua::String>)| {
+            let parts: Vec<_> = parts.iter().map(|part| part.to_string_lossy()).collect();
+            eprintln!("{}", parts.join(" "));
+            Ok(())
+        })?,
+    )?;
+    for (name, level) in [
+        ("LOG_DEBUG", 0),
+        ("LOG_VERBOSE", 1),
+        ("LOG_NOTICE", 2),
+        ("LOG_WARNING", 3),
+    ] {
+        redis.set(name, level)?;
+    }
+    Ok(redis)
+}
+
+// Runs the command a `redis.call` or `redis.pcall` names, with failures as error replies.
+// Only bad arguments to the call itself raise a Lua error.
+fn dispatch(
+    db: &Db,
+    script: &ScriptRun,
+    read_only: bool,
+    lua: &Lua,
+    args: Variadic<Value>,
+) -> mlua::Result<Frame> {
+    if args.is_empty() {
+        return Err(mlua::Error::RuntimeError(
+            "Please specify at least one argument for this redis lib call".into(),
+        ));
+    }
+    let mut frames = Vec::with_capacity(args.len());
+    for arg in args.iter() {
+        let arg = match arg {
+            Value::String(_) | Value::Integer(_) | Value::Number(_) => {
+                lua.coerce_string(arg.clone())?
+            }
+            _ => None,
+        };
+        let Some(arg) = arg else {
+            return Err(mlua::Error::RuntimeError(
+                "Lua redis lib command arguments must be strings or integers".into(),
+            ));
+        };
+        frames.push(Frame::BulkString(Bytes::copy_from_slice(arg.as_bytes())));
+    }
+
+    let cmd = match Command::from_frame_in(Frame::Array(frames), db) {
+        Ok(cmd) => cmd,
+        Err(CommandError::Unknown(_)) => {
+            return Ok(Frame::SimpleError(
+                "Unknown Redis command called from script".into(),
+            ));
+        }
+        Err(e) => return Ok(Frame::SimpleError(e.to_string())),
+    };
+    if cmd.is_denied_in_script() {
+        return Ok(Frame::SimpleError(
+            "This Redis command is not allowed from script".into(),
+        ));
+    }
+    if cmd.is_write() {
+        if read_only {
+            return Ok(Frame::SimpleError(
+                "Write commands are not allowed from read-only scripts.".into(),
+            ));
+        }
+        script.wrote();
+    }
+    Ok(execute(cmd, db))
+}
+
+fn to_lua(lua: &Lua, frame: Frame) -> mlua::Result<Value<'_>> {
+    Ok(match frame {
+        Frame::Integer(n) => Value::Integer(n as mlua::Integer),
+        Frame::BulkString(s) => Value::String(lua.create_string(&s)?),
+        Frame::SimpleString(s) => Value::Table(reply_table(lua, "ok", lua.create_string(&s)?)?),
+        Frame::SimpleError(e) => Value::Table(reply_table(lua, "err", lua.create_string(&e)?)?),
+        Frame::Null | Frame::NullArray => Value::Boolean(false),
+        Frame::Array(items) | Frame::Push(items) => {
+            let table = lua.create_table_with_capacity(items.len(), 0)?;
+            for item in items {
+                table.raw_push(to_lua(lua, item)?)?;
+            }
+            Value::Table(table)
+        }
+        // As RESP2 sends it, keys and values in turn
+        Frame::Map(pairs) => {
+            let table = lua.create_table_with_capacity(pairs.len() * 2, 0)?;
+            for (key, value) in pairs {
+                table.raw_push(to_lua(lua, key)?)?;
+                table.raw_push(to_lua(lua, value)?)?;
+            }
+            Value::Table(table)
+        }
+    })
+}
+
+fn from_lua(value: Value) -> Frame {
+    match value {
+        Value::Boolean(true) => Frame::Integer(1),
+        Value::Integer(n) => Frame::Integer(n),
+        Value::Number(n) => Frame::Integer(n as i64),
+        Value::String(s) => Frame::BulkString(Bytes::copy_from_slice(s.as_bytes())),
+        Value::Table(table) => {
+            if let Ok(Value::String(e)) = table.raw_get("err") {
+                return Frame::SimpleError(e.to_string_lossy().into_owned());
+            }
+            if let Ok(Value::String(s)) = table.raw_get("ok") {
+                return Frame::SimpleString(s.to_string_lossy().into_owned());
+            }
+            let mut items = Vec::new();
+            for i in 1.. {
+                match table.raw_get(i) {
+                    Ok(Value::Nil) | Err(_) => break,
+                    Ok(value) => items.push(from_lua(value)),
+                }
+            }
+            Frame::Array(items)
+        }
+        Value::Error(e) => Frame::SimpleError(error_message(&e)),
+        _ => Frame::Null,
+    }
+}
+
+fn reply_table<'lua>(
+    lua: &'lua Lua,
+    field: &str,
+    msg: mlua::String<'lua>,
+) -> mlua::Result<Table<'lua>> {
+    let table = lua.create_table()?;
+    table.set(field, msg)?;
+    Ok(table)
+}
+
+fn strings<'lua>(lua: &'lua Lua, items: &[Bytes]) -> mlua::Result<Table<'lua>> {
+    lua.create_sequence_from(
+        items
+            .iter()
+            .map(|item| lua.create_string(item))
+            .collect::<mlua::Result<Vec<_>>>()?,
+    )
+}
+
+// What a script's failure tells the client: a failed `redis.call` passes the command's error
+// on as it was
+fn error_message(e: &mlua::Error) -> String {
+    match e {
+        mlua::Error::CallbackError { cause, .. } => error_message(cause),
+        mlua::Error::RuntimeError(msg) => msg.clone(),
+        mlua::Error::SyntaxError { message, .. } => {
+            format!("Error compiling script (new function): {}", message)
+        }
+        e => e.to_string(),
+    }
+}
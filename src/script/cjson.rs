@@ -0,0 +1,117 @@
+// The `cjson` library scripts use to build and read JSON, through serde_json. A table whose
+// keys are exactly 1..n encodes as an array and any other as an object, with an empty table
+// an object like lua-cjson does. `cjson.null` stands for JSON's null in both directions.
+use mlua::{Lua, Table, Value};
+
+// lua-cjson's default limit on nested tables
+const MAX_DEPTH: usize = 1000;
+
+pub(super) fn library(lua: &Lua) -> mlua::Result<Table<'_>> {
+    let cjson = lua.create_table()?;
+    cjson.set(
+        "encode",
+        lua.create_function(|_, value: Value| {
+            let json = to_json(value, 1)?;
+            Ok(serde_json::to_string(&json).expect("JSON values serialise"))
+        })?,
+    )?;
+    cjson.set(
+        "decode",
+        lua.create_function(|lua, text: mlua::String| {
+            let json: serde_json::Value = serde_json::from_slice(text.as_bytes())
+                .map_err(|e| mlua::Error::RuntimeError(e.to_string()))?;
+            to_lua(lua, json)
+        })?,
+    )?;
+    cjson.set("null", Value::NULL)?;
+    Ok(cjson)
+}
+
+fn to_json(value: Value, depth: usize) -> mlua::Result<serde_json::Value> {
+    Ok(match value {
+        Value::Nil => serde_json::Value::Null,
+        Value::LightUserData(ud) if ud.0.is_null() => serde_json::Value::Null,
+        Value::Boolean(b) => serde_json::Value::Bool(b),
+        Value::Integer(n) => serde_json::Value::from(n),
+        Value::Number(n) => serde_json::Number::from_f64(n)
+            .map(serde_json::Value::Number)
+            .ok_or_else(|| fail("Cannot serialise number: must not be NaN or Inf"))?,
+        Value::String(s) => serde_json::Value::String(s.to_string_lossy().into_owned()),
+        Value::Table(table) => {
+            if depth > MAX_DEPTH {
+                return Err(fail(&format!(
+                    "Cannot serialise, excessive nesting ({})",
+                    depth
+                )));
+            }
+            table_to_json(table, depth)?
+        }
+        value => {
+            return Err(fail(&format!(
+                "Cannot serialise {}: type not supported",
+                value.type_name()
+            )));
+        }
+    })
+}
+
+fn table_to_json(table: Table, depth: usize) -> mlua::Result<serde_json::Value> {
+    let len = table.raw_len();
+    let pairs = table
+        .clone()
+        .pairs::<Value, Value>()
+        .collect::<mlua::Result<Vec<_>>>()?;
+    if len > 0 && pairs.len() == len {
+        let items = table
+            .sequence_values::<Value>()
+            .map(|value| to_json(value?, depth + 1))
+            .collect::<mlua::Result<Vec<_>>>()?;
+        return Ok(serde_json::Value::Array(items));
+    }
+
+    let mut object = serde_json::Map::new();
+    for (key, value) in pairs {
+        let key = match key {
+            Value::String(s) => s.to_string_lossy().into_owned(),
+            Value::Integer(n) => n.to_string(),
+            Value::Number(n) => n.to_string(),
+            _ => {
+                return Err(fail(
+                    "Cannot serialise table: table key must be a number or string",
+                ));
+            }
+        };
+        object.insert(key, to_json(value, depth + 1)?);
+    }
+    Ok(serde_json::Value::Object(object))
+}
+
+fn to_lua(lua: &Lua, json: serde_json::Value) -> mlua::Result<Value<'_>> {
+    Ok(match json {
+        serde_json::Value::Null => Value::NULL,
+        serde_json::Value::Bool(b) => Value::Boolean(b),
+        serde_json::Value::Number(n) => match n.as_i64() {
+            Some(n) => Value::Integer(n as mlua::Integer),
+            None => Value::Number(n.as_f64().unwrap_or(f64::NAN)),
+        },
+        serde_json::Value::String(s) => Value::String(lua.create_string(&s)?),
+        serde_json::Value::Array(items) => {
+            let table = lua.create_table_with_capacity(items.len(), 0)?;
+            for item in items {
+                table.raw_push(to_lua(lua, item)?)?;
+            }
+            Value::Table(table)
+        }
+        serde_json::Value::Object(object) => {
+            let table = lua.create_table_with_capacity(0, object.len())?;
+            for (key, value) in object {
+                table.raw_set(key, to_lua(lua, value)?)?;
+            }
+            Value::Table(table)
+        }
+    })
+}
+
+fn fail(msg: &str) -> mlua::Error {
+    mlua::Error::RuntimeError(msg.into())
+}
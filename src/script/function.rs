@@ -0,0 +1,275 @@
+// Redis Functions. A library is Lua code under a `#!lua name=<library>` line; FUNCTION LOAD
+// runs it once with `redis.register_function` in place to learn the functions it registers,
+// and hands those to the registry. FCALL loads the library again into a fresh interpreter and
+// calls the one function with its keys and arguments as two tables, rather than KEYS and ARGV.
+use super::{error_message, interpreter, killable, strings, with_commands};
+use crate::db::{DbError, FunctionInfo, Library, RestorePolicy, ScriptRun, dump_codes};
+use crate::{Db, Frame};
+use bytes::Bytes;
+use mlua::{Lua, Table, Value, Variadic};
+use std::collections::BTreeMap;
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+// How long a library's code may run while it loads
+const LOAD_TIMEOUT: Duration = Duration::from_millis(500);
+// The registry table `redis.register_function` collects functions in while a library loads
+const REGISTERED: &str = "padis.registered";
+const FLAGS: [&str; 5] = [
+    "no-writes",
+    "allow-oom",
+    "allow-stale",
+    "no-cluster",
+    "allow-cross-slot-keys",
+];
+
+pub fn function_load(db: &Db, code: Bytes, replace: bool) -> Frame {
+    let library = match library(code) {
+        Ok(library) => library,
+        Err(e) => return Frame::SimpleError(e),
+    };
+    match db.function_load(library, replace) {
+        Ok(name) => Frame::BulkString(name.into()),
+        Err(e) => Frame::SimpleError(e.to_string()),
+    }
+}
+
+pub fn function_restore(db: &Db, payload: &[u8], policy: RestorePolicy) -> Frame {
+    let codes = match dump_codes(payload) {
+        Ok(codes) => codes,
+        Err(e) => return Frame::SimpleError(e.to_string()),
+    };
+    let libraries = match codes.into_iter().map(library).collect() {
+        Ok(libraries) => libraries,
+        Err(e) => return Frame::SimpleError(e),
+    };
+    match db.function_restore(libraries, policy) {
+        Ok(()) => Frame::SimpleString("OK".into()),
+        Err(e) => Frame::SimpleError(e.to_string()),
+    }
+}
+
+// Restores the libraries saved at `path`, if there are any, and saves them there after every
+// change from then on, so they survive a restart
+pub fn function_persist(db: &Db, path: &Path) -> io::Result<()> {
+    match std::fs::read(path) {
+        Ok(payload) => {
+            if let Frame::SimpleError(e) = function_restore(db, &payload, RestorePolicy::Flush) {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, e));
+            }
+        }
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+        Err(e) => return Err(e),
+    }
+    db.function_save_to(path.to_path_buf())
+}
+
+// FCALL, or FCALL_RO with `read_only`, which only runs functions flagged no-writes
+pub fn fcall(db: &Db, name: &[u8], keys: Vec<Bytes>, args: Vec<Bytes>, read_only: bool) -> Frame {
+    let Some((code, function)) = db.function_get(name) else {
+        return Frame::SimpleError(DbError::FunctionNotFound.to_string());
+    };
+    if read_only && !function.no_writes() {
+        return Frame::SimpleError(DbError::WriteFunctionReadOnly.to_string());
+    }
+    db.script_run(|db, script| {
+        match call(db, script, &code, name, keys, args, function.no_writes()) {
+            Ok(frame) => frame,
+            Err(e) => Frame::SimpleError(error_message(&e)),
+        }
+    })
+}
+
+fn call(
+    db: &Db,
+    script: &Arc<ScriptRun>,
+    code: &[u8],
+    name: &[u8],
+    keys: Vec<Bytes>,
+    args: Vec<Bytes>,
+    read_only: bool,
+) -> mlua::Result<Frame> {
+    let (_, body) = metadata(code).map_err(mlua::Error::RuntimeError)?;
+    let lua = interpreter(killable(script))?;
+    let registered = register(&lua, body)?;
+    let function: Table = registered.get(lua.create_string(name)?)?;
+    let callback: mlua::Function = function.get("callback")?;
+    let keys = strings(&lua, &keys)?;
+    let args = strings(&lua, &args)?;
+    with_commands(&lua, db, script, read_only, || callback.call((keys, args)))
+}
+
+// Loads a library's code to check it and find out what it registers
+fn library(code: Bytes) -> Result<Library, String> {
+    let (name, body) = metadata(&code)?;
+    let started = Instant::now();
+    let lua = interpreter(move || match started.elapsed() > LOAD_TIMEOUT {
+        true => Err(mlua::Error::RuntimeError("FUNCTION LOAD timeout".into())),
+        false => Ok(()),
+    })
+    .map_err(|e| error_message(&e))?;
+    let registered = register(&lua, body).map_err(|e| match e {
+        mlua::Error::SyntaxError { message, .. } => {
+            format!("Error compiling function: {}", message)
+        }
+        e => format!("Error registering functions: {}", error_message(&e)),
+    })?;
+
+    let mut functions = BTreeMap::new();
+    for pair in registered.pairs::<String, Table>() {
+        let (function, registration) = pair.map_err(|e| error_message(&e))?;
+        let description: Option<mlua::String> = registration
+            .get("description")
+            .map_err(|e| error_message(&e))?;
+        let flags: Vec<String> = registration.get("flags").map_err(|e| error_message(&e))?;
+        functions.insert(
+            function,
+            FunctionInfo {
+                description: description.map(|s| Bytes::copy_from_slice(s.as_bytes())),
+                flags,
+            },
+        );
+    }
+    if functions.is_empty() {
+        return Err("No functions registered".into());
+    }
+    Ok(Library {
+        name,
+        code,
+        functions,
+    })
+}
+
+// The library name from the `#!lua name=<library>` line, and the code after it. The code keeps
+// the line's newline so error messages count lines from the top of the library.
+fn metadata(code: &[u8]) -> Result<(String, &[u8]), String> {
+    let Some(rest) = code.strip_prefix(b"#!") else {
+        return Err("Missing library metadata".into());
+    };
+    let end = rest.iter().position(|&b| b == b'\n').unwrap_or(rest.len());
+    let line = String::from_utf8_lossy(&rest[..end]);
+    let mut parts = line.split_whitespace();
+    let engine = parts.next().unwrap_or_default();
+    if !engine.eq_ignore_ascii_case("lua") {
+        return Err(format!("Engine '{}' not found", engine));
+    }
+    let mut name = None;
+    for part in parts {
+        match part.split_once('=') {
+            Some(("name", value)) => name = Some(value),
+            _ => return Err(format!("Invalid metadata value given: {}", part)),
+        }
+    }
+    let Some(name) = name else {
+        return Err("Library name was not given".into());
+    };
+    if !valid_name(name.as_bytes()) {
+        return Err(
+            "Library names can only contain letters, numbers, or underscores(_) and must be at least one character long"
+                .into(),
+        );
+    }
+    Ok((name.to_string(), &rest[end..]))
+}
+
+// Runs a library's code with `redis.register_function` in place, returning the table of what
+// it registered by function name
+fn register<'lua>(lua: &'lua Lua, body: &[u8]) -> mlua::Result<Table<'lua>> {
+    lua.set_named_registry_value(REGISTERED, lua.create_table()?)?;
+    let redis: Table = lua.globals().get("redis")?;
+    redis.set("register_function", lua.create_function(register_function)?)?;
+    lua.load(body).set_name("@user_function").exec()?;
+    // Only the library's top level can register functions
+    redis.set("register_function", Value::Nil)?;
+    lua.named_registry_value(REGISTERED)
+}
+
+// redis.register_function(name, callback) or
+// redis.register_function{function_name=..., callback=..., flags={...}, description=...}
+fn register_function(lua: &Lua, args: Variadic<Value>) -> mlua::Result<()> {
+    let (name, callback, description, flags) = match args.as_slice() {
+        [Value::Table(table)] => {
+            for pair in table.clone().pairs::<Value, Value>() {
+                let (key, _) = pair?;
+                let known = match &key {
+                    Value::String(key) => matches!(
+                        key.as_bytes(),
+                        b"function_name" | b"callback" | b"flags" | b"description"
+                    ),
+                    _ => false,
+                };
+                if !known {
+                    return Err(fail("unknown argument given to redis.register_function"));
+                }
+            }
+            (
+                table.get("function_name")?,
+                table.get("callback")?,
+                table.get("description")?,
+                table.get("flags")?,
+            )
+        }
+        [name, callback] => (name.clone(), callback.clone(), Value::Nil, Value::Nil),
+        _ => return Err(fail("wrong number of arguments to redis.register_function")),
+    };
+
+    let name = match name {
+        Value::String(name) if valid_name(name.as_bytes()) => name,
+        _ => {
+            return Err(fail(
+                "Function names can only contain letters, numbers, or underscores(_) and must be at least one character long",
+            ));
+        }
+    };
+    let Value::Function(callback) = callback else {
+        return Err(fail(
+            "callback argument given to redis.register_function must be a function",
+        ));
+    };
+    let description = match description {
+        Value::Nil | Value::String(_) => description,
+        _ => {
+            return Err(fail(
+                "description argument given to redis.register_function must be a string",
+            ));
+        }
+    };
+    let flags = match flags {
+        Value::Nil => lua.create_table()?,
+        Value::Table(flags) => {
+            for flag in flags.clone().sequence_values::<Value>() {
+                match flag? {
+                    Value::String(flag)
+                        if FLAGS.iter().any(|f| f.as_bytes() == flag.as_bytes()) => {}
+                    _ => return Err(fail("unknown flag given")),
+                }
+            }
+            flags
+        }
+        _ => {
+            return Err(fail(
+                "flags argument to redis.register_function must be a table representing function flags",
+            ));
+        }
+    };
+
+    let registered: Table = lua.named_registry_value(REGISTERED)?;
+    if registered.contains_key(name.clone())? {
+        return Err(fail("Function already exists in the library"));
+    }
+    let registration = lua.create_table()?;
+    registration.set("callback", callback)?;
+    registration.set("description", description)?;
+    registration.set("flags", flags)?;
+    registered.set(name, registration)
+}
+
+fn valid_name(name: &[u8]) -> bool {
+    !name.is_empty() && name.iter().all(|&c| c.is_ascii_alphanumeric() || c == b'_')
+}
+
+fn fail(msg: &str) -> mlua::Error {
+    mlua::Error::RuntimeError(msg.into())
+}
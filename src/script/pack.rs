@@ -0,0 +1,334 @@
+// The `struct` library for packing numbers and strings into binary strings, following
+// Roberto Ierusalimschy's struct 0.3 that Redis bundles:
+//
+//   > <      big or little endian, little by default
+//   ![n]     align to n bytes, or 8 without n; no alignment until then
+//   x        a zero byte of padding
+//   b B      signed and unsigned char
+//   h H      short, 2 bytes
+//   l L T    long and size_t, 8 bytes
+//   i[n] I[n] int of n bytes, 4 by default
+//   f d      float and double
+//   s        zero terminated string
+//   c[n]     n characters; c0 packs the whole string, and unpacks as many as the number
+//            unpacked before it, which it replaces
+//
+// struct.pack(fmt, ...) returns the string, struct.unpack(fmt, s [, pos]) the values then the
+// position after them, and struct.size(fmt) the packed size.
+use mlua::{Lua, Table, Value, Variadic};
+
+// The widest integer the library packs
+const MAX_INT_SIZE: usize = 8;
+
+// What a format asks for next
+enum Item {
+    Pad,
+    Int { size: usize, signed: bool },
+    Float,
+    Double,
+    String,
+    Chars(usize),
+}
+
+struct Format<'a> {
+    fmt: &'a [u8],
+    pos: usize,
+    little: bool,
+    align: usize,
+}
+
+pub(super) fn library(lua: &Lua) -> mlua::Result<Table<'_>> {
+    let library = lua.create_table()?;
+    library.set(
+        "pack",
+        lua.create_function(|lua, (fmt, values): (mlua::String, Variadic<Value>)| {
+            lua.create_string(pack(fmt.as_bytes(), &values)?)
+        })?,
+    )?;
+    library.set(
+        "unpack",
+        lua.create_function(
+            |lua, (fmt, data, init): (mlua::String, mlua::String, Option<i64>)| {
+                unpack(lua, fmt.as_bytes(), data.as_bytes(), init.unwrap_or(1))
+            },
+        )?,
+    )?;
+    library.set(
+        "size",
+        lua.create_function(|_, fmt: mlua::String| size(fmt.as_bytes()))?,
+    )?;
+    Ok(library)
+}
+
+fn pack(fmt: &[u8], values: &[Value]) -> mlua::Result<Vec<u8>> {
+    let mut format = Format::new(fmt);
+    let mut out = Vec::new();
+    let mut values = values.iter();
+    while let Some(item) = format.next()? {
+        out.resize(out.len() + format.padding(out.len(), &item), 0);
+        match item {
+            Item::Pad => out.push(0),
+            Item::Int { size, .. } => {
+                let n = number(values.next())?;
+                // Negative numbers wrap, as the C casts do
+                let n = if n < 0.0 { n as i64 as u64 } else { n as u64 };
+                format.put(&mut out, &n.to_le_bytes()[..size]);
+            }
+            Item::Float => format.put(&mut out, &(number(values.next())? as f32).to_le_bytes()),
+            Item::Double => format.put(&mut out, &number(values.next())?.to_le_bytes()),
+            Item::String => {
+                let s = string(values.next())?;
+                if s.contains(&0) {
+                    return Err(fail("string contains zeros"));
+                }
+                out.extend_from_slice(&s);
+                out.push(0);
+            }
+            Item::Chars(n) => {
+                let s = string(values.next())?;
+                let n = if n == 0 { s.len() } else { n };
+                if s.len() < n {
+                    return Err(fail("string too short"));
+                }
+                out.extend_from_slice(&s[..n]);
+            }
+        }
+    }
+    Ok(out)
+}
+
+fn unpack<'lua>(
+    lua: &'lua Lua,
+    fmt: &[u8],
+    data: &[u8],
+    init: i64,
+) -> mlua::Result<Variadic<Value<'lua>>> {
+    if init < 1 || init as usize > data.len() + 1 {
+        return Err(fail("offset must be 1 or greater"));
+    }
+    let mut format = Format::new(fmt);
+    let mut pos = init as usize - 1;
+    let mut values = Variadic::new();
+    while let Some(item) = format.next()? {
+        pos += format.padding(pos, &item);
+        let size = match item {
+            Item::String => match data
+                .get(pos..)
+                .and_then(|rest| rest.iter().position(|&b| b == 0))
+            {
+                Some(len) => len + 1,
+                None => return Err(fail("unfinished string in data")),
+            },
+            Item::Chars(0) => match values.pop() {
+                Some(Value::Integer(n)) if n >= 0 => n as usize,
+                Some(Value::Number(n)) if n >= 0.0 => n as usize,
+                _ => return Err(fail("format 'c0' needs a previous size")),
+            },
+            ref item => item_size(item),
+        };
+        let Some(bytes) = data.get(pos..pos + size) else {
+            return Err(fail("data string too short"));
+        };
+        pos += size;
+        match item {
+            Item::Pad => {}
+            Item::Int { size, signed } => {
+                let mut le = [0; MAX_INT_SIZE];
+                le[..size].copy_from_slice(&format.ordered(bytes));
+                let mut n = u64::from_le_bytes(le);
+                // Sign extend from the top bit of the packed size
+                if signed && size < MAX_INT_SIZE && n >> (size * 8 - 1) & 1 == 1 {
+                    n |= u64::MAX << (size * 8);
+                }
+                values.push(match signed {
+                    true => Value::Number(n as i64 as f64),
+                    false => Value::Number(n as f64),
+                });
+            }
+            Item::Float => {
+                let le: [u8; 4] = format.ordered(bytes).try_into().expect("4 bytes");
+                values.push(Value::Number(f32::from_le_bytes(le) as f64));
+            }
+            Item::Double => {
+                let le: [u8; 8] = format.ordered(bytes).try_into().expect("8 bytes");
+                values.push(Value::Number(f64::from_le_bytes(le)));
+            }
+            Item::String => values.push(Value::String(lua.create_string(&bytes[..size - 1])?)),
+            Item::Chars(_) => values.push(Value::String(lua.create_string(bytes)?)),
+        }
+    }
+    values.push(Value::Integer((pos + 1) as mlua::Integer));
+    Ok(values)
+}
+
+fn size(fmt: &[u8]) -> mlua::Result<usize> {
+    let mut format = Format::new(fmt);
+    let mut len = 0;
+    while let Some(item) = format.next()? {
+        if matches!(item, Item::String | Item::Chars(0)) {
+            return Err(fail("options 'c0' - 's' have undefined sizes"));
+        }
+        len += format.padding(len, &item) + item_size(&item);
+    }
+    Ok(len)
+}
+
+impl Format<'_> {
+    fn new(fmt: &[u8]) -> Format<'_> {
+        Format {
+            fmt,
+            pos: 0,
+            little: true,
+            align: 1,
+        }
+    }
+
+    // The next item, after applying any options before it
+    fn next(&mut self) -> mlua::Result<Option<Item>> {
+        while let Some(&c) = self.fmt.get(self.pos) {
+            self.pos += 1;
+            let item = match c {
+                b' ' => continue,
+                b'>' => {
+                    self.little = false;
+                    continue;
+                }
+                b'<' | b'=' => {
+                    self.little = true;
+                    continue;
+                }
+                b'!' => {
+                    let align = self.number(MAX_INT_SIZE);
+                    if !align.is_power_of_two() {
+                        return Err(fail(&format!("alignment {} is not a power of 2", align)));
+                    }
+                    self.align = align;
+                    continue;
+                }
+                b'x' => Item::Pad,
+                b'b' | b'B' => Item::Int {
+                    size: 1,
+                    signed: c == b'b',
+                },
+                b'h' | b'H' => Item::Int {
+                    size: 2,
+                    signed: c == b'h',
+                },
+                b'l' | b'L' => Item::Int {
+                    size: 8,
+                    signed: c == b'l',
+                },
+                b'T' => Item::Int {
+                    size: 8,
+                    signed: false,
+                },
+                b'i' | b'I' => {
+                    let size = self.number(4);
+                    if size == 0 || size > MAX_INT_SIZE {
+                        return Err(fail(&format!(
+                            "integral size {} is larger than limit of {}",
+                            size, MAX_INT_SIZE
+                        )));
+                    }
+                    Item::Int {
+                        size,
+                        signed: c == b'i',
+                    }
+                }
+                b'f' => Item::Float,
+                b'd' => Item::Double,
+                b's' => Item::String,
+                b'c' => Item::Chars(self.number(1)),
+                c => {
+                    return Err(fail(&format!("invalid format option '{}'", char::from(c))));
+                }
+            };
+            return Ok(Some(item));
+        }
+        Ok(None)
+    }
+
+    // The digits at the current position, or `default` if there are none
+    fn number(&mut self, default: usize) -> usize {
+        let digits = self.fmt[self.pos..]
+            .iter()
+            .take_while(|c| c.is_ascii_digit())
+            .count();
+        if digits == 0 {
+            return default;
+        }
+        let n = self.fmt[self.pos..self.pos + digits]
+            .iter()
+            .fold(0usize, |n, &d| {
+                n.saturating_mul(10).saturating_add((d - b'0') as usize)
+            });
+        self.pos += digits;
+        n
+    }
+
+    // Bytes of padding before an item starting at `len`
+    fn padding(&self, len: usize, item: &Item) -> usize {
+        let size = match item {
+            Item::Pad | Item::String | Item::Chars(_) => return 0,
+            item => item_size(item),
+        };
+        let align = size.min(self.align);
+        if align <= 1 {
+            return 0;
+        }
+        (align - (len & (align - 1))) & (align - 1)
+    }
+
+    // Writes little endian bytes in the format's order
+    fn put(&self, out: &mut Vec<u8>, le: &[u8]) {
+        match self.little {
+            true => out.extend_from_slice(le),
+            false => out.extend(le.iter().rev()),
+        }
+    }
+
+    // Packed bytes in little endian order
+    fn ordered(&self, bytes: &[u8]) -> Vec<u8> {
+        match self.little {
+            true => bytes.to_vec(),
+            false => bytes.iter().rev().copied().collect(),
+        }
+    }
+}
+
+fn item_size(item: &Item) -> usize {
+    match item {
+        Item::Pad => 1,
+        Item::Int { size, .. } => *size,
+        Item::Float => 4,
+        Item::Double => 8,
+        Item::String => 0,
+        Item::Chars(n) => *n,
+    }
+}
+
+fn number(value: Option<&Value>) -> mlua::Result<f64> {
+    match value {
+        Some(Value::Integer(n)) => Ok(*n as f64),
+        Some(Value::Number(n)) => Ok(*n),
+        Some(Value::String(s)) => s
+            .to_str()
+            .ok()
+            .and_then(|s| s.trim().parse().ok())
+            .ok_or_else(|| fail("number expected")),
+        _ => Err(fail("number expected")),
+    }
+}
+
+fn string(value: Option<&Value>) -> mlua::Result<Vec<u8>> {
+    match value {
+        Some(Value::String(s)) => Ok(s.as_bytes().to_vec()),
+        Some(Value::Integer(n)) => Ok(n.to_string().into_bytes()),
+        Some(Value::Number(n)) => Ok(n.to_string().into_bytes()),
+        _ => Err(fail("string expected")),
+    }
+}
+
+fn fail(msg: &str) -> mlua::Error {
+    mlua::Error::RuntimeError(msg.into())
+}
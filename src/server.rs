@@ -1,4 +1,10 @@
-use crate::{Command, Connection, Db, Frame};
+use crate::db::{
+    AutoClaimed, BloomInfo, BloomInfoField, ConsumerInfo, DbError, Delivery, FieldType, FtInfo,
+    FtResults, GeoMatch, GroupEntry, GroupInfo, Library, Message, PendingInfo, PendingSummary,
+    Popped, Sample, StreamEntry, StreamId, StreamInfo, WasmModule, ZAddFlags,
+};
+use crate::{Command, Connection, Db, Frame, script, wasm};
+use bytes::Bytes;
 use tokio::net::{TcpListener, TcpStream};
 
 pub async fn run_server(listener: TcpListener, db: Db) {
@@ -20,39 +26,322 @@ pub async fn run_server(listener: TcpListener, db: Db) {
 
 async fn handle_connection(socket: TcpStream, db: Db) {
     let mut conn = Connection::new(socket);
+    // Registered for the whole connection, which is in subscribed mode while it has
+    // subscriptions
+    let mut subscriber = db.subscriber();
+    let id = subscriber.id();
+    let mut db = db.for_client(id);
+    // Switched on by HELLO 3, after which messages and invalidations are pushes and
+    // subscribed mode doesn't limit the commands. Replies keep their RESP2 types.
+    let mut resp3 = false;
+    // Set between MULTI and EXEC or DISCARD
+    let mut transaction: Option<Transaction> = None;
 
     loop {
-        let frame = match conn.read_frame().await {
-            Ok(Some(frame)) => frame,
-            Ok(None) => {
-                return;
+        let frame = tokio::select! {
+            frame = conn.read_frame() => match frame {
+                Ok(Some(frame)) => frame,
+                Ok(None) => {
+                    return;
+                }
+                Err(e) => {
+                    eprintln!("Connection error: {}", e);
+                    return;
+                }
+            },
+            delivery = subscriber.next() => {
+                // The subscriber fell too far behind and was dropped
+                let frame = match delivery {
+                    Some(Delivery::Message(message)) => out_of_band(message_frame(message), resp3),
+                    // RESP2 clients can only be told on the invalidation channel, if subscribed
+                    Some(Delivery::Invalidation(keys)) => {
+                        match invalidation_frame(keys, resp3, subscriber.count() > 0) {
+                            Some(frame) => frame,
+                            None => continue,
+                        }
+                    }
+                    None => return,
+                };
+                if let Err(e) = conn.write_frame(&frame).await {
+                    eprintln!("Failed to write message: {}", e);
+                    return;
+                }
+                continue;
             }
-            Err(e) => {
-                eprintln!("Connection error: {}", e);
+        };
+
+        let name = command_name(&frame);
+        let cmd = Command::from_frame_in(frame, &db);
+        // CLIENT CACHING only applies to the command after it
+        let keeps_caching = matches!(cmd, Ok(Command::ClientCaching { .. }));
+        let subscribed = subscriber.count() > 0 && !resp3;
+        // Held while the command runs, so it can't land in the middle of another client's
+        // transaction or script. Those take the gate exclusively, blocking commands take it
+        // around each attempt, and what's allowed while a script is busy goes around it.
+        let gate = match &cmd {
+            Ok(cmd)
+                if transaction.is_none()
+                    && !matches!(cmd, Command::Exec)
+                    && !cmd.is_script()
+                    && !cmd.is_blocking()
+                    && !cmd.allowed_when_busy() =>
+            {
+                Some(db.enter().await)
+            }
+            _ => None,
+        };
+        let responses = match cmd {
+            Ok(cmd) if subscribed && !cmd.allowed_when_subscribed() => {
+                vec![Frame::SimpleError(format!(
+                    "Can't execute '{}': only (P|S)SUBSCRIBE / (P|S)UNSUBSCRIBE / PING / QUIT / RESET are allowed in this context",
+                    name
+                ))]
+            }
+            Ok(cmd) if !cmd.allowed_when_busy() && db.script_busy() => {
+                vec![Frame::SimpleError(DbError::Busy.to_string())]
+            }
+            // A script holding the gate became busy while the command waited for it
+            Ok(_) if let Some(Err(e)) = &gate => vec![Frame::SimpleError(e.to_string())],
+            Err(e) if let Some(transaction) = &mut transaction => {
+                transaction.aborted = true;
+                vec![Frame::SimpleError(e.to_string())]
+            }
+            Ok(Command::Multi) if transaction.is_some() => {
+                vec![Frame::SimpleError("MULTI calls can not be nested".into())]
+            }
+            Ok(Command::Multi) => {
+                transaction = Some(Transaction::default());
+                vec![Frame::SimpleString("OK".into())]
+            }
+            Ok(Command::Exec) => match transaction.take() {
+                Some(transaction) => {
+                    let reply = execute_transaction(transaction, id, &db).await;
+                    db.unwatch(id);
+                    vec![reply]
+                }
+                None => vec![Frame::SimpleError("EXEC without MULTI".into())],
+            },
+            Ok(Command::Discard) => match transaction.take() {
+                Some(_) => {
+                    db.unwatch(id);
+                    vec![Frame::SimpleString("OK".into())]
+                }
+                None => vec![Frame::SimpleError("DISCARD without MULTI".into())],
+            },
+            Ok(Command::Watch { .. }) if transaction.is_some() => {
+                vec![Frame::SimpleError(
+                    "WATCH inside MULTI is not allowed".into(),
+                )]
+            }
+            Ok(Command::Watch { keys }) => {
+                db.watch(id, &keys);
+                vec![Frame::SimpleString("OK".into())]
+            }
+            Ok(Command::Quit) => {
+                let _ = conn.write_frame(&Frame::SimpleString("OK".into())).await;
                 return;
             }
+            Ok(cmd) if let Some(transaction) = &mut transaction => vec![transaction.queue(cmd)],
+            Ok(Command::Subscribe { channels }) => {
+                subscribed_replies("subscribe", subscriber.subscribe(&channels), resp3)
+            }
+            Ok(Command::PSubscribe { patterns }) => {
+                subscribed_replies("psubscribe", subscriber.psubscribe(&patterns), resp3)
+            }
+            Ok(Command::SSubscribe { channels }) => {
+                subscribed_replies("ssubscribe", subscriber.ssubscribe(&channels), resp3)
+            }
+            Ok(Command::Unsubscribe { channels }) => {
+                unsubscribed_replies("unsubscribe", subscriber.unsubscribe(&channels), resp3)
+            }
+            Ok(Command::PUnsubscribe { patterns }) => {
+                unsubscribed_replies("punsubscribe", subscriber.punsubscribe(&patterns), resp3)
+            }
+            Ok(Command::SUnsubscribe { channels }) => {
+                unsubscribed_replies("sunsubscribe", subscriber.sunsubscribe(&channels), resp3)
+            }
+            // Subscribed connections get a pong they can tell apart from a message
+            Ok(Command::Ping { msg }) if subscribed => vec![Frame::Array(vec![
+                Frame::BulkString(Bytes::from_static(b"pong")),
+                Frame::BulkString(msg.unwrap_or_default()),
+            ])],
+            Ok(Command::Hello { protover }) => {
+                if let Some(protover) = protover {
+                    resp3 = protover == 3;
+                }
+                vec![hello_frame(id, resp3)]
+            }
+            Ok(Command::Unwatch) => {
+                db.unwatch(id);
+                vec![Frame::SimpleString("OK".into())]
+            }
+            Ok(Command::ClientId) => vec![Frame::Integer(id as i64)],
+            Ok(Command::ClientGetRedir) => vec![Frame::Integer(db.client_getredir(id))],
+            Ok(Command::ClientTracking { on, options }) => {
+                vec![ok_or_error(db.client_tracking(id, on, options))]
+            }
+            Ok(Command::ClientCaching { yes }) => vec![ok_or_error(db.client_caching(yes))],
+            Ok(cmd) if cmd.is_blocking() => vec![execute_blocking(cmd, &db).await],
+            Ok(cmd) if cmd.is_script() => vec![execute_script(cmd, &db).await],
+            Ok(cmd) => vec![execute(cmd, &db)],
+            Err(e) => vec![Frame::SimpleError(e.to_string())],
         };
+        drop(gate);
+        if !keeps_caching {
+            db.reset_caching();
+        }
 
-        let response = match Command::from_frame(frame) {
-            Ok(cmd) => execute(cmd, &db),
-            Err(e) => Frame::SimpleError(e.to_string()),
-        };
+        for response in responses {
+            if let Err(e) = conn.write_frame(&response).await {
+                eprintln!("Failed to write response: {}", e);
+                return;
+            }
+        }
+    }
+}
+
+// Commands queued since MULTI. One that couldn't be queued aborts the transaction, and EXEC
+// then runs none of them.
+#[derive(Default)]
+struct Transaction {
+    commands: Vec<Command>,
+    aborted: bool,
+}
+
+impl Transaction {
+    fn queue(&mut self, cmd: Command) -> Frame {
+        if cmd.is_connection_state() {
+            self.aborted = true;
+            return Frame::SimpleError("Command not allowed inside a transaction".into());
+        }
+        self.commands.push(cmd);
+        Frame::SimpleString("QUEUED".into())
+    }
 
-        if let Err(e) = conn.write_frame(&response).await {
-            eprintln!("Failed to write response: {}", e);
-            return;
+    // Run with the gate held exclusively. Blocking commands don't wait here, like everywhere
+    // outside `execute_blocking`. Nothing runs if a key the client watched has changed, which
+    // is checked under the same gate.
+    fn exec(self, id: u64, db: &Db) -> Frame {
+        if self.aborted {
+            return Frame::SimpleError(
+                "EXECABORT Transaction discarded because of previous errors.".into(),
+            );
+        }
+        if db.watched_changed(id) {
+            return Frame::NullArray;
         }
+        Frame::Array(
+            self.commands
+                .into_iter()
+                .map(|cmd| execute(cmd, db))
+                .collect(),
+        )
+    }
+}
+
+// The lowercased command name, for errors that need it before the command is parsed
+fn command_name(frame: &Frame) -> String {
+    match frame {
+        Frame::Array(frames) => match frames.first() {
+            Some(Frame::BulkString(name)) => String::from_utf8_lossy(name).to_lowercase(),
+            _ => String::new(),
+        },
+        _ => String::new(),
+    }
+}
+
+// Blocking commands wait here for a write from another connection. Everywhere else, such as
+// `execute`, they behave like their non-blocking forms.
+async fn execute_blocking(cmd: Command, db: &Db) -> Frame {
+    let result = match cmd {
+        Command::BZPop { keys, max, timeout } => {
+            db.bzmpop(&keys, max, 1, timeout).await.map(bzpop_reply)
+        }
+        Command::BZMPop {
+            keys,
+            max,
+            count,
+            timeout,
+        } => db.bzmpop(&keys, max, count, timeout).await.map(zmpop_reply),
+        Command::XRead {
+            streams,
+            count,
+            timeout,
+            ..
+        } => db
+            .xread_block(&streams, count, timeout)
+            .await
+            .map(xread_reply),
+        Command::XReadGroup {
+            group,
+            consumer,
+            streams,
+            count,
+            noack,
+            timeout,
+            ..
+        } => db
+            .xreadgroup_block(&group, &consumer, &streams, count, noack, timeout)
+            .await
+            .map(xreadgroup_reply),
+        cmd => return execute(cmd, db),
+    };
+    match result {
+        Ok(frame) => frame,
+        Err(e) => Frame::SimpleError(e.to_string()),
+    }
+}
+
+// Scripts hold the gate exclusively until they finish, so they run on a blocking thread rather
+// than holding up the connections sharing this one
+async fn execute_script(cmd: Command, db: &Db) -> Frame {
+    let db = db.clone();
+    let gate = match db.enter_exclusive().await {
+        Ok(gate) => gate,
+        Err(e) => return Frame::SimpleError(e.to_string()),
+    };
+    let run = move || {
+        let _gate = gate;
+        execute(cmd, &db)
+    };
+    match tokio::task::spawn_blocking(run).await {
+        Ok(frame) => frame,
+        Err(e) => Frame::SimpleError(format!("Script failed: {}", e)),
+    }
+}
+
+// EXEC holds the gate the same way, and may run a script as one of its commands
+async fn execute_transaction(transaction: Transaction, id: u64, db: &Db) -> Frame {
+    let db = db.clone();
+    let gate = match db.enter_exclusive().await {
+        Ok(gate) => gate,
+        Err(e) => return Frame::SimpleError(e.to_string()),
+    };
+    let run = move || {
+        let _gate = gate;
+        transaction.exec(id, &db)
+    };
+    match tokio::task::spawn_blocking(run).await {
+        Ok(frame) => frame,
+        Err(e) => Frame::SimpleError(format!("Transaction failed: {}", e)),
+    }
+}
+
+pub(crate) fn execute(cmd: Command, db: &Db) -> Frame {
+    match try_execute(cmd, db) {
+        Ok(frame) => frame,
+        Err(e) => Frame::SimpleError(e.to_string()),
     }
 }
 
-fn execute(cmd: Command, db: &Db) -> Frame {
-    match cmd {
+fn try_execute(cmd: Command, db: &Db) -> Result<Frame, DbError> {
+    let frame = match cmd {
         Command::Ping { msg } => match msg {
             None => Frame::SimpleString("PONG".into()),
             Some(m) => Frame::BulkString(m),
         },
         Command::Echo { msg } => Frame::BulkString(msg),
-        Command::Get { key } => match db.get(&key) {
+        Command::Get { key } => match db.get_string(&key)? {
             Some(v) => Frame::BulkString(v),
             _ => Frame::Null,
         },
@@ -60,5 +349,1417 @@ fn execute(cmd: Command, db: &Db) -> Frame {
             db.set(&key, value, expiry);
             Frame::SimpleString("OK".into())
         }
+        Command::SAdd { key, members } => Frame::Integer(db.sadd(&key, members)? as i64),
+        Command::SRem { key, members } => Frame::Integer(db.srem(&key, &members)? as i64),
+        Command::SIsMember { key, member } => Frame::Integer(db.sismember(&key, &member)? as i64),
+        Command::SMIsMember { key, members } => bool_array(db.smismember(&key, &members)?),
+        Command::SMembers { key } => bulk_array(db.smembers(&key)?),
+        Command::SCard { key } => Frame::Integer(db.scard(&key)? as i64),
+        Command::SPop { key, count } => match count {
+            Some(count) => bulk_array(db.spop(&key, count)?),
+            None => bulk_or_null(db.spop(&key, 1)?.pop()),
+        },
+        Command::SRandMember { key, count } => match count {
+            Some(count) => bulk_array(db.srandmember(&key, count)?),
+            None => bulk_or_null(db.srandmember(&key, 1)?.pop()),
+        },
+        Command::SetOp { op, keys } => bulk_array(db.set_op(op, &keys)?),
+        Command::SetOpStore {
+            op,
+            destination,
+            keys,
+        } => Frame::Integer(db.set_op_store(op, &destination, &keys)? as i64),
+        Command::SInterCard { keys, limit } => Frame::Integer(db.sintercard(&keys, limit)? as i64),
+        Command::SMove {
+            source,
+            destination,
+            member,
+        } => Frame::Integer(db.smove(&source, &destination, &member)? as i64),
+        Command::HSet { key, pairs } => Frame::Integer(db.hset(&key, pairs)? as i64),
+        Command::HGet { key, field } => bulk_or_null(db.hget(&key, &field)?),
+        Command::HDel { key, fields } => Frame::Integer(db.hdel(&key, &fields)? as i64),
+        Command::HGetAll { key } => field_values(db.hgetall(&key)?),
+        Command::HLen { key } => Frame::Integer(db.hlen(&key)? as i64),
+        Command::ZAdd {
+            key,
+            flags,
+            incr: true,
+            mut pairs,
+        } => match pairs.pop() {
+            Some((delta, member)) => score_or_null(db.zincrby(&key, flags, delta, &member)?),
+            None => Frame::Null,
+        },
+        Command::ZAdd {
+            key, flags, pairs, ..
+        } => Frame::Integer(db.zadd(&key, flags, pairs)? as i64),
+        Command::ZIncrBy { key, delta, member } => {
+            score_or_null(db.zincrby(&key, ZAddFlags::default(), delta, &member)?)
+        }
+        Command::ZRem { key, members } => Frame::Integer(db.zrem(&key, &members)? as i64),
+        Command::ZScore { key, member } => score_or_null(db.zscore(&key, &member)?),
+        Command::ZMScore { key, members } => Frame::Array(
+            db.zmscore(&key, &members)?
+                .into_iter()
+                .map(score_or_null)
+                .collect(),
+        ),
+        Command::ZCard { key } => Frame::Integer(db.zcard(&key)? as i64),
+        Command::ZRank {
+            key,
+            member,
+            rev,
+            with_score,
+        } => match db.zrank(&key, &member, rev)? {
+            Some((rank, score)) if with_score => {
+                Frame::Array(vec![Frame::Integer(rank as i64), score_frame(score)])
+            }
+            Some((rank, _)) => Frame::Integer(rank as i64),
+            None => Frame::Null,
+        },
+        Command::ZRange {
+            key,
+            range,
+            with_scores,
+        } => scored_array(db.zrange(&key, &range)?, with_scores),
+        Command::ZCount { key, by } => Frame::Integer(db.zcount(&key, &by)? as i64),
+        Command::ZPop { key, count, max } => {
+            scored_array(db.zpop(&key, count.unwrap_or(1), max)?, true)
+        }
+        Command::ZRandMember {
+            key,
+            count: Some(count),
+            with_scores,
+        } => scored_array(db.zrandmember(&key, count)?, with_scores),
+        Command::ZSetOp {
+            op,
+            keys,
+            weights,
+            aggregate,
+            with_scores,
+        } => scored_array(
+            db.zset_op(op, &keys, weights.as_deref(), aggregate)?,
+            with_scores,
+        ),
+        Command::ZSetOpStore {
+            op,
+            destination,
+            keys,
+            weights,
+            aggregate,
+        } => Frame::Integer(db.zset_op_store(
+            op,
+            &destination,
+            &keys,
+            weights.as_deref(),
+            aggregate,
+        )? as i64),
+        Command::ZRangeStore {
+            destination,
+            source,
+            range,
+        } => Frame::Integer(db.zrangestore(&destination, &source, &range)? as i64),
+        Command::BZPop { keys, max, .. } => bzpop_reply(db.zmpop(&keys, max, 1)?),
+        Command::ZMPop { keys, max, count }
+        | Command::BZMPop {
+            keys, max, count, ..
+        } => zmpop_reply(db.zmpop(&keys, max, count)?),
+        Command::ZRandMember { key, .. } => {
+            bulk_or_null(db.zrandmember(&key, 1)?.pop().map(|(member, _)| member))
+        }
+        Command::XAdd {
+            key,
+            id,
+            fields,
+            nomkstream,
+            trim,
+        } => match db.xadd(&key, id, &fields, nomkstream, trim.as_ref())? {
+            Some(id) => Frame::BulkString(Bytes::from(id.to_string())),
+            None => Frame::Null,
+        },
+        Command::XRange {
+            key,
+            start,
+            end,
+            count,
+            rev,
+        } => entries_array(db.xrange(&key, start, end, count, rev)?),
+        Command::XLen { key } => Frame::Integer(db.xlen(&key)? as i64),
+        Command::XDel { key, ids } => Frame::Integer(db.xdel(&key, &ids)? as i64),
+        Command::XTrim { key, trim } => Frame::Integer(db.xtrim(&key, &trim)? as i64),
+        Command::XRead { streams, count, .. } => xread_reply(db.xread(&streams, count)?),
+        Command::XGroupCreate {
+            key,
+            group,
+            start,
+            mkstream,
+            entries_read,
+        } => {
+            db.xgroup_create(&key, &group, start, mkstream, entries_read)?;
+            Frame::SimpleString("OK".into())
+        }
+        Command::XGroupSetId {
+            key,
+            group,
+            start,
+            entries_read,
+        } => {
+            db.xgroup_setid(&key, &group, start, entries_read)?;
+            Frame::SimpleString("OK".into())
+        }
+        Command::XGroupDestroy { key, group } => {
+            Frame::Integer(db.xgroup_destroy(&key, &group)? as i64)
+        }
+        Command::XGroupCreateConsumer {
+            key,
+            group,
+            consumer,
+        } => Frame::Integer(db.xgroup_createconsumer(&key, &group, &consumer)? as i64),
+        Command::XGroupDelConsumer {
+            key,
+            group,
+            consumer,
+        } => Frame::Integer(db.xgroup_delconsumer(&key, &group, &consumer)? as i64),
+        Command::XReadGroup {
+            group,
+            consumer,
+            streams,
+            count,
+            noack,
+            ..
+        } => xreadgroup_reply(db.xreadgroup(&group, &consumer, &streams, count, noack)?),
+        Command::XAck { key, group, ids } => Frame::Integer(db.xack(&key, &group, &ids)? as i64),
+        Command::XPending { key, group, filter } => match filter {
+            Some(filter) => pending_array(db.xpending(&key, &group, &filter)?),
+            None => pending_summary(db.xpending_summary(&key, &group)?),
+        },
+        Command::XClaim {
+            key,
+            group,
+            consumer,
+            min_idle,
+            ids,
+            opts,
+        } => {
+            let claimed = db.xclaim(&key, &group, &consumer, min_idle, &ids, &opts)?;
+            claimed_frame(claimed, opts.just_id)
+        }
+        Command::XAutoClaim {
+            key,
+            group,
+            consumer,
+            min_idle,
+            start,
+            count,
+            just_id,
+        } => autoclaim_reply(
+            db.xautoclaim(&key, &group, &consumer, min_idle, start, count, just_id)?,
+            just_id,
+        ),
+        Command::XInfoStream { key } => stream_info_reply(db.xinfo_stream(&key)?),
+        Command::XInfoGroups { key } => Frame::Array(
+            db.xinfo_groups(&key)?
+                .into_iter()
+                .map(group_info_frame)
+                .collect(),
+        ),
+        Command::XInfoConsumers { key, group } => Frame::Array(
+            db.xinfo_consumers(&key, &group)?
+                .into_iter()
+                .map(consumer_info_frame)
+                .collect(),
+        ),
+        Command::PfAdd { key, elements } => Frame::Integer(db.pfadd(&key, &elements)? as i64),
+        Command::PfCount { keys } => Frame::Integer(db.pfcount(&keys)? as i64),
+        Command::PfMerge {
+            destination,
+            sources,
+        } => {
+            db.pfmerge(&destination, &sources)?;
+            Frame::SimpleString("OK".into())
+        }
+        Command::GeoAdd { key, flags, points } => {
+            Frame::Integer(db.geoadd(&key, flags, points)? as i64)
+        }
+        Command::GeoPos { key, members } => Frame::Array(
+            db.geopos(&key, &members)?
+                .into_iter()
+                .map(|pos| pos.map_or(Frame::Null, |(lon, lat)| coord_frame(lon, lat)))
+                .collect(),
+        ),
+        Command::GeoDist { key, a, b, unit } => match db.geodist(&key, &a, &b, unit)? {
+            Some(dist) => distance_frame(dist),
+            None => Frame::Null,
+        },
+        Command::GeoHash { key, members } => Frame::Array(
+            db.geohash(&key, &members)?
+                .into_iter()
+                .map(|hash| bulk_or_null(hash.map(Bytes::from)))
+                .collect(),
+        ),
+        Command::GeoSearch {
+            key,
+            query,
+            with_coord,
+            with_dist,
+            with_hash,
+        } => {
+            let matches = db.geosearch(&key, &query)?;
+            geosearch_reply(matches, with_coord, with_dist, with_hash)
+        }
+        Command::GeoSearchStore {
+            destination,
+            source,
+            query,
+            store_dist,
+        } => Frame::Integer(db.geosearchstore(&destination, &source, &query, store_dist)? as i64),
+        Command::BfReserve {
+            key,
+            error_rate,
+            capacity,
+            expansion,
+        } => {
+            db.bf_reserve(&key, error_rate, capacity, expansion)?;
+            Frame::SimpleString("OK".into())
+        }
+        Command::BfAdd { key, item } => {
+            let added = db.bf_add(&key, &[item])?.pop().unwrap_or(Ok(false))?;
+            Frame::Integer(added as i64)
+        }
+        // A full filter fails only the items that didn't fit
+        Command::BfMAdd { key, items } => Frame::Array(
+            db.bf_add(&key, &items)?
+                .into_iter()
+                .map(|added| match added {
+                    Ok(added) => Frame::Integer(added as i64),
+                    Err(e) => Frame::SimpleError(e.to_string()),
+                })
+                .collect(),
+        ),
+        Command::BfExists { key, item } => {
+            Frame::Integer(db.bf_exists(&key, &[item])?.contains(&true) as i64)
+        }
+        Command::BfMExists { key, items } => bool_array(db.bf_exists(&key, &items)?),
+        Command::BfInfo { key, field } => bloom_info_reply(db.bf_info(&key)?, field),
+        Command::CfReserve { key, options } => {
+            db.cf_reserve(&key, options)?;
+            Frame::SimpleString("OK".into())
+        }
+        Command::CfAdd { key, item, nx } => Frame::Integer(db.cf_add(&key, &item, nx)? as i64),
+        Command::CfDel { key, item } => Frame::Integer(db.cf_del(&key, &item)? as i64),
+        Command::CfExists { key, item } => {
+            Frame::Integer(db.cf_exists(&key, &[item])?.contains(&true) as i64)
+        }
+        Command::CfMExists { key, items } => bool_array(db.cf_exists(&key, &items)?),
+        Command::CfCount { key, item } => Frame::Integer(db.cf_count(&key, &item)? as i64),
+        Command::CmsInit { key, width, depth } => {
+            db.cms_init(&key, width, depth)?;
+            Frame::SimpleString("OK".into())
+        }
+        Command::CmsIncrBy { key, items } => int_array(db.cms_incrby(&key, &items)?),
+        Command::CmsQuery { key, items } => int_array(db.cms_query(&key, &items)?),
+        Command::CmsMerge {
+            destination,
+            sources,
+        } => {
+            db.cms_merge(&destination, &sources)?;
+            Frame::SimpleString("OK".into())
+        }
+        Command::CmsInfo { key } => {
+            let info = db.cms_info(&key)?;
+            info_map(vec![
+                ("width", Frame::Integer(info.width as i64)),
+                ("depth", Frame::Integer(info.depth as i64)),
+                ("count", Frame::Integer(info.count as i64)),
+            ])
+        }
+        Command::TopKReserve { key, options } => {
+            db.topk_reserve(&key, options)?;
+            Frame::SimpleString("OK".into())
+        }
+        Command::TopKIncrBy { key, items } => Frame::Array(
+            db.topk_incrby(&key, &items)?
+                .into_iter()
+                .map(bulk_or_null)
+                .collect(),
+        ),
+        Command::TopKQuery { key, items } => bool_array(db.topk_query(&key, &items)?),
+        Command::TopKList { key, with_count } => {
+            let top = db.topk_list(&key)?;
+            if with_count {
+                Frame::Array(
+                    top.into_iter()
+                        .flat_map(|(item, count)| {
+                            [Frame::BulkString(item), Frame::Integer(count as i64)]
+                        })
+                        .collect(),
+                )
+            } else {
+                bulk_array(top.into_iter().map(|(item, _)| item).collect())
+            }
+        }
+        Command::TopKInfo { key } => {
+            let options = db.topk_info(&key)?;
+            info_map(vec![
+                ("k", Frame::Integer(options.k as i64)),
+                ("width", Frame::Integer(options.width as i64)),
+                ("depth", Frame::Integer(options.depth as i64)),
+                ("decay", score_frame(options.decay)),
+            ])
+        }
+        Command::TDigestCreate { key, compression } => {
+            db.tdigest_create(&key, compression)?;
+            Frame::SimpleString("OK".into())
+        }
+        Command::TDigestAdd { key, values } => {
+            db.tdigest_add(&key, &values)?;
+            Frame::SimpleString("OK".into())
+        }
+        Command::TDigestQuantile { key, quantiles } => {
+            double_array(db.tdigest_quantile(&key, &quantiles)?)
+        }
+        Command::TDigestCdf { key, values } => double_array(db.tdigest_cdf(&key, &values)?),
+        Command::TDigestRank { key, values, rev } => Frame::Array(
+            db.tdigest_rank(&key, &values, rev)?
+                .into_iter()
+                .map(Frame::Integer)
+                .collect(),
+        ),
+        Command::TDigestTrimmedMean { key, low, high } => {
+            double_frame(db.tdigest_trimmed_mean(&key, low, high)?)
+        }
+        Command::TDigestMinMax { key, max } => {
+            let (min_value, max_value) = db.tdigest_min_max(&key)?;
+            double_frame(if max { max_value } else { min_value })
+        }
+        Command::TDigestMerge {
+            destination,
+            sources,
+            compression,
+            override_dest,
+        } => {
+            db.tdigest_merge(&destination, &sources, compression, override_dest)?;
+            Frame::SimpleString("OK".into())
+        }
+        Command::TDigestInfo { key } => {
+            let info = db.tdigest_info(&key)?;
+            info_map(vec![
+                ("Compression", Frame::Integer(info.compression as i64)),
+                ("Capacity", Frame::Integer(info.capacity as i64)),
+                ("Merged nodes", Frame::Integer(info.merged_nodes as i64)),
+                ("Unmerged nodes", Frame::Integer(info.unmerged_nodes as i64)),
+                ("Merged weight", Frame::Integer(info.merged_weight as i64)),
+                (
+                    "Unmerged weight",
+                    Frame::Integer(info.unmerged_weight as i64),
+                ),
+                ("Observations", Frame::Integer(info.observations as i64)),
+                (
+                    "Total compressions",
+                    Frame::Integer(info.total_compressions as i64),
+                ),
+                ("Memory usage", Frame::Integer(info.memory_usage as i64)),
+            ])
+        }
+        Command::JsonSet {
+            key,
+            path,
+            value,
+            condition,
+        } => match db.json_set(&key, &path, value, condition)? {
+            true => Frame::SimpleString("OK".into()),
+            false => Frame::Null,
+        },
+        Command::JsonGet { key, paths, format } => match db.json_get(&key, &paths)? {
+            Some(value) => Frame::BulkString(Bytes::from(format.format(&value))),
+            None => Frame::Null,
+        },
+        Command::JsonMGet { keys, path } => Frame::Array(
+            db.json_mget(&keys, &path)
+                .into_iter()
+                .map(|value| bulk_or_null(value.map(|v| Bytes::from(v.to_string()))))
+                .collect(),
+        ),
+        Command::JsonDel { key, path } => Frame::Integer(db.json_del(&key, &path)? as i64),
+        Command::JsonType { key, path } => match db.json_type(&key, &path)? {
+            None => Frame::Null,
+            Some(types) if path.is_legacy() => types
+                .first()
+                .map_or(Frame::Null, |t| Frame::SimpleString(t.to_string())),
+            Some(types) => Frame::Array(
+                types
+                    .into_iter()
+                    .map(|t| Frame::BulkString(Bytes::from_static(t.as_bytes())))
+                    .collect(),
+            ),
+        },
+        Command::JsonObjKeys { key, path } => {
+            let keys_frame = |keys: Option<Vec<String>>| match keys {
+                Some(keys) => bulk_array(keys.into_iter().map(Bytes::from).collect()),
+                None => Frame::Null,
+            };
+            match db.json_objkeys(&key, &path)? {
+                None => Frame::Null,
+                Some(results) if path.is_legacy() => {
+                    results.into_iter().next().map_or(Frame::Null, keys_frame)
+                }
+                Some(results) => Frame::Array(results.into_iter().map(keys_frame).collect()),
+            }
+        }
+        Command::JsonNumIncrBy { key, path, by } => {
+            let results = db.json_numincrby(&key, &path, &by)?;
+            let reply = if path.is_legacy() {
+                results
+                    .into_iter()
+                    .flatten()
+                    .next()
+                    .map_or(serde_json::Value::Null, Into::into)
+            } else {
+                results
+                    .into_iter()
+                    .map(|n| n.map_or(serde_json::Value::Null, Into::into))
+                    .collect()
+            };
+            Frame::BulkString(Bytes::from(reply.to_string()))
+        }
+        Command::JsonStrAppend { key, path, value } => {
+            json_lengths(db.json_strappend(&key, &path, &value)?, path.is_legacy())
+        }
+        Command::JsonArrAppend { key, path, values } => {
+            json_lengths(db.json_arrappend(&key, &path, &values)?, path.is_legacy())
+        }
+        Command::JsonArrInsert {
+            key,
+            path,
+            index,
+            values,
+        } => json_lengths(
+            db.json_arrinsert(&key, &path, index, &values)?,
+            path.is_legacy(),
+        ),
+        Command::JsonArrPop { key, path, index } => {
+            let popped: Vec<Frame> = db
+                .json_arrpop(&key, &path, index)?
+                .into_iter()
+                .map(|value| bulk_or_null(value.map(|v| Bytes::from(v.to_string()))))
+                .collect();
+            if path.is_legacy() {
+                popped.into_iter().next().unwrap_or(Frame::Null)
+            } else {
+                Frame::Array(popped)
+            }
+        }
+        Command::JsonArrTrim {
+            key,
+            path,
+            start,
+            stop,
+        } => json_lengths(db.json_arrtrim(&key, &path, start, stop)?, path.is_legacy()),
+        Command::TsCreate { key, options } => {
+            db.ts_create(&key, options)?;
+            Frame::SimpleString("OK".into())
+        }
+        Command::TsAdd {
+            key,
+            timestamp,
+            value,
+            options,
+            on_duplicate,
+        } => Frame::Integer(db.ts_add(&key, timestamp, value, &options, on_duplicate)? as i64),
+        Command::TsGet { key } => match db.ts_get(&key)? {
+            Some(sample) => sample_frame(sample),
+            None => Frame::Array(vec![]),
+        },
+        Command::TsRange { key, query } => samples_array(db.ts_range(&key, &query)?),
+        Command::TsMRange {
+            query,
+            filters,
+            with_labels,
+        } => Frame::Array(
+            db.ts_mrange(&filters, &query)
+                .into_iter()
+                .map(|m| {
+                    let labels = if with_labels { m.labels } else { vec![] };
+                    Frame::Array(vec![
+                        Frame::BulkString(m.key),
+                        labels_array(labels),
+                        samples_array(m.samples),
+                    ])
+                })
+                .collect(),
+        ),
+        Command::TsCreateRule {
+            source,
+            destination,
+            aggregation,
+            align,
+        } => {
+            db.ts_create_rule(&source, &destination, aggregation, align)?;
+            Frame::SimpleString("OK".into())
+        }
+        Command::TsDeleteRule {
+            source,
+            destination,
+        } => {
+            db.ts_delete_rule(&source, &destination)?;
+            Frame::SimpleString("OK".into())
+        }
+        Command::TsInfo { key } => {
+            let info = db.ts_info(&key)?;
+            let rules = info
+                .rules
+                .into_iter()
+                .map(|(destination, aggregation, align)| {
+                    Frame::Array(vec![
+                        Frame::BulkString(destination),
+                        Frame::Integer(aggregation.bucket as i64),
+                        Frame::SimpleString(aggregation.aggregator.name().into()),
+                        Frame::Integer(align as i64),
+                    ])
+                })
+                .collect();
+            info_map(vec![
+                ("totalSamples", Frame::Integer(info.total_samples as i64)),
+                (
+                    "firstTimestamp",
+                    Frame::Integer(info.first_timestamp as i64),
+                ),
+                ("lastTimestamp", Frame::Integer(info.last_timestamp as i64)),
+                ("retentionTime", Frame::Integer(info.retention as i64)),
+                (
+                    "duplicatePolicy",
+                    Frame::BulkString(Bytes::from_static(info.duplicate_policy.name().as_bytes())),
+                ),
+                ("labels", labels_array(info.labels)),
+                ("sourceKey", bulk_or_null(info.source_key)),
+                ("rules", Frame::Array(rules)),
+            ])
+        }
+        Command::VAdd {
+            key,
+            values,
+            element,
+            options,
+        } => Frame::Integer(db.vadd(&key, &values, &element, options)? as i64),
+        Command::VSim {
+            key,
+            query,
+            with_scores,
+            with_attribs,
+        } => {
+            let mut reply = Vec::new();
+            for m in db.vsim(&key, &query)? {
+                reply.push(Frame::BulkString(m.element));
+                if with_scores {
+                    reply.push(score_frame(m.score));
+                }
+                if with_attribs {
+                    reply.push(bulk_or_null(
+                        m.attributes.map(|a| Bytes::from(a.to_string())),
+                    ));
+                }
+            }
+            Frame::Array(reply)
+        }
+        Command::VRem { key, element } => Frame::Integer(db.vrem(&key, &element)? as i64),
+        Command::VCard { key } => Frame::Integer(db.vcard(&key)? as i64),
+        Command::VDim { key } => Frame::Integer(db.vdim(&key)? as i64),
+        Command::VEmb { key, element } => match db.vemb(&key, &element)? {
+            Some(values) => Frame::Array(
+                values
+                    .into_iter()
+                    .map(|v| score_frame(f64::from(v)))
+                    .collect(),
+            ),
+            None => Frame::Null,
+        },
+        Command::VSetAttr {
+            key,
+            element,
+            attributes,
+        } => Frame::Integer(db.vsetattr(&key, &element, attributes)? as i64),
+        Command::VGetAttr { key, element } => bulk_or_null(
+            db.vgetattr(&key, &element)?
+                .map(|a| Bytes::from(a.to_string())),
+        ),
+        Command::FtCreate { name, spec } => {
+            db.ft_create(&name, spec)?;
+            Frame::SimpleString("OK".into())
+        }
+        Command::FtSearch {
+            name,
+            query,
+            options,
+        } => ft_search_reply(
+            db.ft_search(&name, &query, &options)?,
+            options.with_scores,
+            options.no_content,
+        ),
+        Command::FtInfo { name } => {
+            let info = db.ft_info(&name)?;
+            ft_info_reply(name, info)
+        }
+        Command::FtDropIndex { name, delete_docs } => {
+            db.ft_dropindex(&name, delete_docs)?;
+            Frame::SimpleString("OK".into())
+        }
+        Command::Publish { channel, message } => {
+            Frame::Integer(db.publish(&channel, &message) as i64)
+        }
+        Command::SPublish { channel, message } => {
+            Frame::Integer(db.spublish(&channel, &message) as i64)
+        }
+        Command::PubSubChannels { pattern } => bulk_array(db.pubsub_channels(pattern.as_deref())),
+        Command::PubSubShardChannels { pattern } => {
+            bulk_array(db.pubsub_shard_channels(pattern.as_deref()))
+        }
+        Command::PubSubNumSub { channels } => numsub_frame(db.pubsub_numsub(&channels)),
+        Command::PubSubShardNumSub { channels } => numsub_frame(db.pubsub_shard_numsub(&channels)),
+        Command::PubSubNumPat => Frame::Integer(db.pubsub_numpat() as i64),
+        Command::Flush => {
+            db.flush();
+            Frame::SimpleString("OK".into())
+        }
+        Command::Eval { script, keys, args } => script::eval(db, script, keys, args),
+        Command::EvalSha { sha, keys, args } => script::evalsha(db, &sha, keys, args),
+        Command::ScriptLoad { script } => Frame::BulkString(db.script_load(script).into()),
+        Command::ScriptExists { shas } => Frame::Array(
+            db.script_exists(&shas)
+                .into_iter()
+                .map(|exists| Frame::Integer(exists as i64))
+                .collect(),
+        ),
+        Command::ScriptFlush => {
+            db.script_flush();
+            Frame::SimpleString("OK".into())
+        }
+        Command::ScriptKill | Command::FunctionKill => {
+            db.script_kill()?;
+            Frame::SimpleString("OK".into())
+        }
+        Command::FCall {
+            function,
+            keys,
+            args,
+            read_only,
+        } => script::fcall(db, &function, keys, args, read_only),
+        Command::FunctionLoad { code, replace } => script::function_load(db, code, replace),
+        Command::FunctionList { pattern, with_code } => Frame::Array(
+            db.function_list(pattern.as_deref())
+                .into_iter()
+                .map(|library| library_frame(library, with_code))
+                .collect(),
+        ),
+        Command::FunctionDelete { library } => {
+            db.function_delete(&library)?;
+            Frame::SimpleString("OK".into())
+        }
+        Command::FunctionDump => Frame::BulkString(db.function_dump()),
+        Command::FunctionRestore { payload, policy } => {
+            script::function_restore(db, &payload, policy)
+        }
+        Command::FunctionFlush => {
+            db.function_flush();
+            Frame::SimpleString("OK".into())
+        }
+        Command::ModuleLoad { path } => {
+            match wasm::load_file(db, &String::from_utf8_lossy(&path)) {
+                Ok(()) => Frame::SimpleString("OK".into()),
+                Err(e) => Frame::SimpleError(e),
+            }
+        }
+        Command::ModuleUnload { name } => {
+            db.module_unload(&name)?;
+            Frame::SimpleString("OK".into())
+        }
+        Command::ModuleList => {
+            Frame::Array(db.module_list().into_iter().map(module_frame).collect())
+        }
+        Command::ModuleCall { name, args, .. } => wasm::call(db, &name, args),
+        Command::ConfigGet { patterns } => Frame::Array(
+            db.config_get(&patterns)
+                .into_iter()
+                .flat_map(|(name, value)| [Frame::BulkString(name), Frame::BulkString(value)])
+                .collect(),
+        ),
+        Command::ConfigSet { pairs } => {
+            db.config_set(&pairs)?;
+            Frame::SimpleString("OK".into())
+        }
+        Command::Subscribe { .. }
+        | Command::Unsubscribe { .. }
+        | Command::PSubscribe { .. }
+        | Command::PUnsubscribe { .. }
+        | Command::SSubscribe { .. }
+        | Command::SUnsubscribe { .. }
+        | Command::Quit
+        | Command::Hello { .. }
+        | Command::ClientId
+        | Command::ClientGetRedir
+        | Command::ClientCaching { .. }
+        | Command::ClientTracking { .. }
+        | Command::Multi
+        | Command::Exec
+        | Command::Discard
+        | Command::Watch { .. }
+        | Command::Unwatch => {
+            unreachable!("connection state commands are handled by handle_connection")
+        }
+    };
+    Ok(frame)
+}
+
+fn bulk_array(items: Vec<Bytes>) -> Frame {
+    Frame::Array(items.into_iter().map(Frame::BulkString).collect())
+}
+
+fn bulk_or_null(item: Option<Bytes>) -> Frame {
+    item.map_or(Frame::Null, Frame::BulkString)
+}
+
+fn int_array(items: Vec<u64>) -> Frame {
+    Frame::Array(
+        items
+            .into_iter()
+            .map(|n| Frame::Integer(n as i64))
+            .collect(),
+    )
+}
+
+fn bool_array(items: Vec<bool>) -> Frame {
+    Frame::Array(
+        items
+            .into_iter()
+            .map(|b| Frame::Integer(b as i64))
+            .collect(),
+    )
+}
+
+// Members interleaved with their scores when `with_scores` is set, as RESP2 replies them
+fn scored_array(items: Vec<(Bytes, f64)>, with_scores: bool) -> Frame {
+    let mut out = Vec::with_capacity(if with_scores {
+        items.len() * 2
+    } else {
+        items.len()
+    });
+    for (member, score) in items {
+        out.push(Frame::BulkString(member));
+        if with_scores {
+            out.push(score_frame(score));
+        }
+    }
+    Frame::Array(out)
+}
+
+// [key, member, score] for BZPOPMIN and BZPOPMAX
+fn bzpop_reply(popped: Option<Popped>) -> Frame {
+    match popped {
+        Some((key, mut items)) if !items.is_empty() => {
+            let (member, score) = items.remove(0);
+            Frame::Array(vec![
+                Frame::BulkString(key),
+                Frame::BulkString(member),
+                score_frame(score),
+            ])
+        }
+        _ => Frame::NullArray,
+    }
+}
+
+// [key, [[member, score], ...]] for ZMPOP and BZMPOP
+fn zmpop_reply(popped: Option<Popped>) -> Frame {
+    match popped {
+        Some((key, items)) => Frame::Array(vec![
+            Frame::BulkString(key),
+            Frame::Array(
+                items
+                    .into_iter()
+                    .map(|(member, score)| {
+                        Frame::Array(vec![Frame::BulkString(member), score_frame(score)])
+                    })
+                    .collect(),
+            ),
+        ]),
+        None => Frame::NullArray,
+    }
+}
+
+// [[id, [field, value, ...]], ...] for XRANGE and friends
+fn entries_array(entries: Vec<StreamEntry>) -> Frame {
+    Frame::Array(
+        entries
+            .into_iter()
+            .map(|(id, fields)| entry_frame(id, Some(fields)))
+            .collect(),
+    )
+}
+
+// A deleted entry still pending in a consumer group has no fields
+fn entry_frame(id: StreamId, fields: Option<Vec<(Bytes, Bytes)>>) -> Frame {
+    let fields = fields.map_or(Frame::Null, field_values);
+    Frame::Array(vec![id_frame(id), fields])
+}
+
+// Alternating fields and values, as stream entries and hashes are sent
+fn field_values(fields: Vec<(Bytes, Bytes)>) -> Frame {
+    Frame::Array(
+        fields
+            .into_iter()
+            .flat_map(|(field, value)| [Frame::BulkString(field), Frame::BulkString(value)])
+            .collect(),
+    )
+}
+
+fn id_frame(id: StreamId) -> Frame {
+    Frame::BulkString(Bytes::from(id.to_string()))
+}
+
+fn id_array(ids: impl IntoIterator<Item = StreamId>) -> Frame {
+    Frame::Array(ids.into_iter().map(id_frame).collect())
+}
+
+// [[key, entries], ...] for XREAD, or null when no stream had anything new
+fn xread_reply(streams: Vec<(Bytes, Vec<StreamEntry>)>) -> Frame {
+    if streams.is_empty() {
+        return Frame::Null;
+    }
+    Frame::Array(
+        streams
+            .into_iter()
+            .map(|(key, entries)| {
+                Frame::Array(vec![Frame::BulkString(key), entries_array(entries)])
+            })
+            .collect(),
+    )
+}
+
+fn xreadgroup_reply(streams: Vec<(Bytes, Vec<GroupEntry>)>) -> Frame {
+    if streams.is_empty() {
+        return Frame::Null;
+    }
+    Frame::Array(
+        streams
+            .into_iter()
+            .map(|(key, entries)| {
+                let entries = entries
+                    .into_iter()
+                    .map(|(id, fields)| entry_frame(id, fields))
+                    .collect();
+                Frame::Array(vec![Frame::BulkString(key), Frame::Array(entries)])
+            })
+            .collect(),
+    )
+}
+
+// [count, min, max, [[consumer, count], ...]], with nulls for an empty PEL
+fn pending_summary(summary: PendingSummary) -> Frame {
+    if summary.count == 0 {
+        return Frame::Array(vec![
+            Frame::Integer(0),
+            Frame::Null,
+            Frame::Null,
+            Frame::Null,
+        ]);
+    }
+    let consumers = summary
+        .consumers
+        .into_iter()
+        .map(|(name, count)| {
+            Frame::Array(vec![
+                Frame::BulkString(name),
+                Frame::BulkString(Bytes::from(count.to_string())),
+            ])
+        })
+        .collect();
+    Frame::Array(vec![
+        Frame::Integer(summary.count as i64),
+        summary.min.map_or(Frame::Null, id_frame),
+        summary.max.map_or(Frame::Null, id_frame),
+        Frame::Array(consumers),
+    ])
+}
+
+// [[id, consumer, idle, deliveries], ...]
+fn pending_array(pending: Vec<PendingInfo>) -> Frame {
+    Frame::Array(
+        pending
+            .into_iter()
+            .map(|p| {
+                Frame::Array(vec![
+                    id_frame(p.id),
+                    Frame::BulkString(p.consumer),
+                    Frame::Integer(p.idle as i64),
+                    Frame::Integer(p.deliveries as i64),
+                ])
+            })
+            .collect(),
+    )
+}
+
+fn claimed_frame(claimed: Vec<StreamEntry>, just_id: bool) -> Frame {
+    if just_id {
+        id_array(claimed.into_iter().map(|(id, _)| id))
+    } else {
+        entries_array(claimed)
+    }
+}
+
+// [next start, claimed, deleted IDs]
+fn autoclaim_reply(result: AutoClaimed, just_id: bool) -> Frame {
+    Frame::Array(vec![
+        id_frame(result.next),
+        claimed_frame(result.claimed, just_id),
+        id_array(result.deleted),
+    ])
+}
+
+// XINFO replies are flat lists of alternating names and values
+fn info_map(pairs: Vec<(&str, Frame)>) -> Frame {
+    Frame::Array(
+        pairs
+            .into_iter()
+            .flat_map(|(name, value)| [Frame::BulkString(Bytes::from(name.to_string())), value])
+            .collect(),
+    )
+}
+
+fn stream_info_reply(info: StreamInfo) -> Frame {
+    let entry = |entry: Option<StreamEntry>| {
+        entry.map_or(Frame::Null, |(id, fields)| entry_frame(id, Some(fields)))
+    };
+    let first_id = info
+        .first_entry
+        .as_ref()
+        .map_or(StreamId::MIN, |(id, _)| *id);
+    info_map(vec![
+        ("length", Frame::Integer(info.length as i64)),
+        ("radix-tree-keys", Frame::Integer(info.nodes as i64)),
+        ("last-generated-id", id_frame(info.last_generated_id)),
+        ("max-deleted-entry-id", id_frame(info.max_deleted_id)),
+        ("entries-added", Frame::Integer(info.entries_added as i64)),
+        ("recorded-first-entry-id", id_frame(first_id)),
+        ("groups", Frame::Integer(info.groups as i64)),
+        ("first-entry", entry(info.first_entry)),
+        ("last-entry", entry(info.last_entry)),
+    ])
+}
+
+fn group_info_frame(info: GroupInfo) -> Frame {
+    info_map(vec![
+        ("name", Frame::BulkString(info.name)),
+        ("consumers", Frame::Integer(info.consumers as i64)),
+        ("pending", Frame::Integer(info.pending as i64)),
+        ("last-delivered-id", id_frame(info.last_delivered_id)),
+        (
+            "entries-read",
+            info.entries_read
+                .map_or(Frame::Null, |n| Frame::Integer(n as i64)),
+        ),
+        ("lag", Frame::Integer(info.lag as i64)),
+    ])
+}
+
+fn consumer_info_frame(info: ConsumerInfo) -> Frame {
+    info_map(vec![
+        ("name", Frame::BulkString(info.name)),
+        ("pending", Frame::Integer(info.pending as i64)),
+        ("idle", Frame::Integer(info.idle as i64)),
+        (
+            "inactive",
+            Frame::Integer(info.inactive.map_or(-1, |ms| ms as i64)),
+        ),
+    ])
+}
+
+// Members alone, or [member, dist, hash, [lon, lat]] with whichever extras were asked for
+fn geosearch_reply(
+    matches: Vec<GeoMatch>,
+    with_coord: bool,
+    with_dist: bool,
+    with_hash: bool,
+) -> Frame {
+    let plain = !(with_coord || with_dist || with_hash);
+    Frame::Array(
+        matches
+            .into_iter()
+            .map(|m| {
+                if plain {
+                    return Frame::BulkString(m.member);
+                }
+                let mut item = vec![Frame::BulkString(m.member)];
+                if with_dist {
+                    item.push(distance_frame(m.dist));
+                }
+                if with_hash {
+                    item.push(Frame::Integer(m.hash as i64));
+                }
+                if with_coord {
+                    item.push(coord_frame(m.lon, m.lat));
+                }
+                Frame::Array(item)
+            })
+            .collect(),
+    )
+}
+
+// Distances are sent with four decimal places
+fn distance_frame(dist: f64) -> Frame {
+    Frame::BulkString(Bytes::from(format!("{:.4}", dist)))
+}
+
+// Coordinates are sent with 17 decimal places, less any trailing zeros, like Redis
+fn coord_frame(lon: f64, lat: f64) -> Frame {
+    let human = |x: f64| {
+        let s = format!("{:.17}", x);
+        let s = s.trim_end_matches('0').trim_end_matches('.');
+        Frame::BulkString(Bytes::from(s.to_string()))
+    };
+    Frame::Array(vec![human(lon), human(lat)])
+}
+
+fn score_or_null(score: Option<f64>) -> Frame {
+    score.map_or(Frame::Null, score_frame)
+}
+
+// Scores are sent as bulk strings in their shortest round-trippable form
+fn score_frame(score: f64) -> Frame {
+    Frame::BulkString(Bytes::from(format_score(score)))
+}
+
+// Like score_frame, but spelling NaN the way RedisBloom does
+fn double_frame(value: f64) -> Frame {
+    if value.is_nan() {
+        Frame::BulkString(Bytes::from_static(b"nan"))
+    } else {
+        score_frame(value)
+    }
+}
+
+fn double_array(values: Vec<f64>) -> Frame {
+    Frame::Array(values.into_iter().map(double_frame).collect())
+}
+
+fn format_score(score: f64) -> String {
+    let abs = score.abs();
+    if !score.is_finite() || abs == 0.0 || (1e-5..1e17).contains(&abs) {
+        return score.to_string();
+    }
+
+    // Switch to exponent form for very large or small scores, the way Redis does
+    let formatted = format!("{:e}", score);
+    match formatted.split_once('e') {
+        Some((mantissa, exp)) if !exp.starts_with('-') => format!("{}e+{}", mantissa, exp),
+        _ => formatted,
+    }
+}
+
+// The whole summary, or just the asked for field wrapped in an array as RedisBloom does
+fn bloom_info_reply(info: BloomInfo, field: Option<BloomInfoField>) -> Frame {
+    let expansion = info
+        .expansion
+        .map_or(Frame::Null, |n| Frame::Integer(n as i64));
+    match field {
+        None => info_map(vec![
+            ("Capacity", Frame::Integer(info.capacity as i64)),
+            ("Size", Frame::Integer(info.size as i64)),
+            ("Number of filters", Frame::Integer(info.filters as i64)),
+            (
+                "Number of items inserted",
+                Frame::Integer(info.items as i64),
+            ),
+            ("Expansion rate", expansion),
+        ]),
+        Some(field) => Frame::Array(vec![match field {
+            BloomInfoField::Capacity => Frame::Integer(info.capacity as i64),
+            BloomInfoField::Size => Frame::Integer(info.size as i64),
+            BloomInfoField::Filters => Frame::Integer(info.filters as i64),
+            BloomInfoField::Items => Frame::Integer(info.items as i64),
+            BloomInfoField::Expansion => expansion,
+        }]),
+    }
+}
+
+// A legacy path replies with its one length, a JSONPath with one per match, null where the
+// match had the wrong type
+fn json_lengths(lengths: Vec<Option<usize>>, legacy: bool) -> Frame {
+    let frames: Vec<Frame> = lengths
+        .into_iter()
+        .map(|len| len.map_or(Frame::Null, |len| Frame::Integer(len as i64)))
+        .collect();
+    if legacy {
+        frames.into_iter().next().unwrap_or(Frame::Null)
+    } else {
+        Frame::Array(frames)
+    }
+}
+
+// Timestamps are integers and values simple strings, as RedisTimeSeries sends them
+fn sample_frame((timestamp, value): Sample) -> Frame {
+    Frame::Array(vec![
+        Frame::Integer(timestamp as i64),
+        Frame::SimpleString(format_score(value)),
+    ])
+}
+
+fn samples_array(samples: Vec<Sample>) -> Frame {
+    Frame::Array(samples.into_iter().map(sample_frame).collect())
+}
+
+fn labels_array(labels: Vec<(Bytes, Bytes)>) -> Frame {
+    Frame::Array(
+        labels
+            .into_iter()
+            .map(|(name, value)| {
+                Frame::Array(vec![Frame::BulkString(name), Frame::BulkString(value)])
+            })
+            .collect(),
+    )
+}
+
+// [total, key, [score], [field, value, ...], ...] with the score and fields as asked for
+fn ft_search_reply(results: FtResults, with_scores: bool, no_content: bool) -> Frame {
+    let mut reply = vec![Frame::Integer(results.total as i64)];
+    for doc in results.docs {
+        reply.push(Frame::BulkString(doc.key));
+        if with_scores {
+            reply.push(Frame::BulkString(Bytes::from(format_score(doc.score))));
+        }
+        if !no_content {
+            reply.push(field_values(doc.fields));
+        }
+    }
+    Frame::Array(reply)
+}
+
+fn ft_info_reply(name: Bytes, info: FtInfo) -> Frame {
+    let bulk = |s: &str| Frame::BulkString(Bytes::from(s.to_string()));
+    let attributes = info
+        .spec
+        .schema
+        .iter()
+        .map(|field| {
+            let mut attribute = vec![
+                bulk("identifier"),
+                Frame::BulkString(field.name.clone()),
+                bulk("attribute"),
+                Frame::BulkString(field.attribute().clone()),
+                bulk("type"),
+            ];
+            match &field.kind {
+                FieldType::Text { weight } => {
+                    attribute.extend([bulk("TEXT"), bulk("WEIGHT"), bulk(&format_score(*weight))]);
+                }
+                FieldType::Tag {
+                    separator,
+                    case_sensitive,
+                } => {
+                    attribute.extend([
+                        bulk("TAG"),
+                        bulk("SEPARATOR"),
+                        bulk(&separator.to_string()),
+                    ]);
+                    if *case_sensitive {
+                        attribute.push(bulk("CASESENSITIVE"));
+                    }
+                }
+                FieldType::Numeric => attribute.push(bulk("NUMERIC")),
+            }
+            if field.sortable {
+                attribute.push(bulk("SORTABLE"));
+            }
+            Frame::Array(attribute)
+        })
+        .collect();
+    info_map(vec![
+        ("index_name", Frame::BulkString(name)),
+        ("index_options", Frame::Array(vec![])),
+        (
+            "index_definition",
+            Frame::Array(vec![
+                bulk("key_type"),
+                bulk("HASH"),
+                bulk("prefixes"),
+                bulk_array(info.spec.prefixes),
+                bulk("default_score"),
+                bulk("1"),
+            ]),
+        ),
+        ("attributes", Frame::Array(attributes)),
+        ("num_docs", Frame::Integer(info.num_docs as i64)),
+        ("max_doc_id", Frame::Integer(info.max_doc_id as i64)),
+        ("num_terms", Frame::Integer(info.num_terms as i64)),
+        ("num_records", Frame::Integer(info.num_records as i64)),
+        (
+            "hash_indexing_failures",
+            Frame::Integer(info.failures as i64),
+        ),
+    ])
+}
+
+// [kind, channel or pattern, subscription count], replying to (P|S)SUBSCRIBE and (P|S)UNSUBSCRIBE
+// Pushed to RESP3 clients, like messages
+fn subscription_frame(kind: &'static str, channel: Frame, count: usize, resp3: bool) -> Frame {
+    let frame = Frame::Array(vec![
+        Frame::BulkString(Bytes::from_static(kind.as_bytes())),
+        channel,
+        Frame::Integer(count as i64),
+    ]);
+    out_of_band(frame, resp3)
+}
+
+fn subscribed_replies(kind: &'static str, counts: Vec<(Bytes, usize)>, resp3: bool) -> Vec<Frame> {
+    counts
+        .into_iter()
+        .map(|(name, count)| subscription_frame(kind, Frame::BulkString(name), count, resp3))
+        .collect()
+}
+
+// The channel is None when there were no subscriptions to leave
+fn unsubscribed_replies(
+    kind: &'static str,
+    left: Vec<(Option<Bytes>, usize)>,
+    resp3: bool,
+) -> Vec<Frame> {
+    left.into_iter()
+        .map(|(name, count)| subscription_frame(kind, bulk_or_null(name), count, resp3))
+        .collect()
+}
+
+// Arrays sent outside of a reply become pushes for RESP3 clients
+fn out_of_band(frame: Frame, resp3: bool) -> Frame {
+    match frame {
+        Frame::Array(frames) if resp3 => Frame::Push(frames),
+        frame => frame,
+    }
+}
+
+// ["invalidate", keys] as a push, or as a message on `__redis__:invalidate` when RESP2
+// clients are subscribed. None when the client can't be told.
+fn invalidation_frame(keys: Vec<Bytes>, resp3: bool, subscribed: bool) -> Option<Frame> {
+    let keys = bulk_array(keys);
+    if resp3 {
+        Some(Frame::Push(vec![
+            Frame::BulkString(Bytes::from_static(b"invalidate")),
+            keys,
+        ]))
+    } else if subscribed {
+        Some(Frame::Array(vec![
+            Frame::BulkString(Bytes::from_static(b"message")),
+            Frame::BulkString(Bytes::from_static(b"__redis__:invalidate")),
+            keys,
+        ]))
+    } else {
+        None
+    }
+}
+
+// A map for RESP3 and a flat array of pairs for RESP2
+fn hello_frame(id: u64, resp3: bool) -> Frame {
+    let bulk = |s: &'static str| Frame::BulkString(Bytes::from_static(s.as_bytes()));
+    let pairs = vec![
+        (bulk("server"), bulk("padis")),
+        (bulk("version"), bulk(env!("CARGO_PKG_VERSION"))),
+        (bulk("proto"), Frame::Integer(if resp3 { 3 } else { 2 })),
+        (bulk("id"), Frame::Integer(id as i64)),
+        (bulk("mode"), bulk("standalone")),
+        (bulk("role"), bulk("master")),
+        (bulk("modules"), Frame::Array(Vec::new())),
+    ];
+    match resp3 {
+        true => Frame::Map(pairs),
+        false => Frame::Array(pairs.into_iter().flat_map(|(k, v)| [k, v]).collect()),
+    }
+}
+
+// A FUNCTION LIST entry, as RESP2 flattens Redis' maps
+fn library_frame(library: Library, with_code: bool) -> Frame {
+    let bulk = |s: &'static str| Frame::BulkString(Bytes::from_static(s.as_bytes()));
+    let functions = library
+        .functions
+        .into_iter()
+        .map(|(name, function)| {
+            Frame::Array(vec![
+                bulk("name"),
+                Frame::BulkString(name.into()),
+                bulk("description"),
+                bulk_or_null(function.description),
+                bulk("flags"),
+                Frame::Array(
+                    function
+                        .flags
+                        .into_iter()
+                        .map(|flag| Frame::BulkString(flag.into()))
+                        .collect(),
+                ),
+            ])
+        })
+        .collect();
+    let mut frame = vec![
+        bulk("library_name"),
+        Frame::BulkString(library.name.into()),
+        bulk("engine"),
+        bulk("LUA"),
+        bulk("functions"),
+        Frame::Array(functions),
+    ];
+    if with_code {
+        frame.push(bulk("library_code"));
+        frame.push(Frame::BulkString(library.code));
+    }
+    Frame::Array(frame)
+}
+
+// A MODULE LIST entry: its name, the file it came from and the commands it added
+fn module_frame(module: WasmModule) -> Frame {
+    let bulk = |s: &'static str| Frame::BulkString(Bytes::from_static(s.as_bytes()));
+    Frame::Array(vec![
+        bulk("name"),
+        Frame::BulkString(module.name.into()),
+        bulk("path"),
+        Frame::BulkString(module.path.into()),
+        bulk("commands"),
+        Frame::Array(
+            module
+                .commands
+                .into_iter()
+                .map(|command| Frame::BulkString(command.name))
+                .collect(),
+        ),
+    ])
+}
+
+fn ok_or_error(result: Result<(), DbError>) -> Frame {
+    match result {
+        Ok(()) => Frame::SimpleString("OK".into()),
+        Err(e) => Frame::SimpleError(e.to_string()),
+    }
+}
+
+// Flat [channel, subscribers, ...] pairs
+fn numsub_frame(counts: Vec<(Bytes, usize)>) -> Frame {
+    Frame::Array(
+        counts
+            .into_iter()
+            .flat_map(|(channel, count)| [Frame::BulkString(channel), Frame::Integer(count as i64)])
+            .collect(),
+    )
+}
+
+fn message_frame(message: Message) -> Frame {
+    let mut frames = Vec::with_capacity(4);
+    match message.pattern {
+        Some(pattern) => {
+            frames.push(Frame::BulkString(Bytes::from_static(b"pmessage")));
+            frames.push(Frame::BulkString(pattern));
+        }
+        None => {
+            let kind: &'static [u8] = match message.sharded {
+                true => b"smessage",
+                false => b"message",
+            };
+            frames.push(Frame::BulkString(Bytes::from_static(kind)));
+        }
     }
+    frames.push(Frame::BulkString(message.channel));
+    frames.push(Frame::BulkString(message.payload));
+    Frame::Array(frames)
 }
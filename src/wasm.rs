@@ -0,0 +1,350 @@
+// User-defined commands written in WebAssembly. MODULE LOAD compiles a module and calls its
+// `padis_init` export, which names the module's commands with `register_command`; from then on
+// the command table sends those names here, and each call runs the command's handler in a fresh
+// instance with no other client's command in between.
+//
+// A module exports:
+//
+//   memory                                  where arguments and replies pass through
+//   padis_alloc(len: i32) -> i32            room for padis to copy `len` bytes into
+//   padis_init()                            registers the module's commands
+//   <handler>(ptr: i32, len: i32) -> i64    one for each command
+//
+// and may import from "padis":
+//
+//   register_command(name_ptr, name_len, handler_ptr, handler_len, flags: i32)
+//                                           only from padis_init; flags bit 0 is READONLY
+//   get(key_ptr, key_len) -> i64            the string at a key, -1 if there's none or -2 if it
+//                                           holds another type
+//   set(key_ptr, key_len, value_ptr, value_len)
+//   call(ptr, len) -> i64                   runs a RESP encoded command, returning its reply
+//   log(ptr, len)                           writes a line to the server's stderr
+//
+// A handler gets its arguments as a RESP array of bulk strings and returns its reply RESP
+// encoded. Returned i64s hold a pointer into the module's memory in their high 32 bits and a
+// length in their low ones. Read-only commands can't write, through `set` or `call`.
+//
+// Fuel bounds the instructions a command runs and the memory limit how far its memory grows,
+// while the time limit is only checked as it calls back into padis: wasmi can't interrupt a
+// command that's computing, so the fuel limit is capped to keep that bounded too.
+use crate::cmd::CommandError;
+use crate::db::{ModuleCommand, WasmLimits, WasmModule};
+use crate::server::execute;
+use crate::{Command, Db, Frame};
+use bytes::Bytes;
+use std::io::Cursor;
+use std::path::Path;
+use std::sync::{Arc, OnceLock};
+use std::time::Instant;
+use wasmi::core::{TrapCode, ValType};
+use wasmi::{
+    AsContext, AsContextMut, Caller, Config, Engine, Error, Instance, Linker, Memory, Module,
+    Store, StoreLimits, StoreLimitsBuilder, TypedFunc,
+};
+
+const READONLY: i32 = 1;
+
+// What host functions can reach while a module runs
+struct Host {
+    // None while the module registers its commands
+    db: Option<Db>,
+    read_only: bool,
+    deadline: Instant,
+    exports: Option<Exports>,
+    registered: Vec<ModuleCommand>,
+    limits: StoreLimits,
+}
+
+#[derive(Clone, Copy)]
+struct Exports {
+    memory: Memory,
+    alloc: TypedFunc<i32, i32>,
+}
+
+// MODULE LOAD, naming the module after its file
+pub fn load_file(db: &Db, path: &str) -> Result<(), String> {
+    let wasm = std::fs::read(path).map_err(|e| format!("Error loading the extension: {}", e))?;
+    let name = Path::new(path)
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    load(db, &name, path, &wasm)
+}
+
+pub fn load(db: &Db, name: &str, path: &str, wasm: &[u8]) -> Result<(), String> {
+    let error = |e: Error| format!("Error loading the extension: {}", message(&e));
+    let module = Module::new(engine(), wasm).map_err(error)?;
+    let limits = db.wasm_limits();
+    let mut store = store(None, false, limits).map_err(error)?;
+    let (instance, _) = instantiate(&mut store, &module).map_err(error)?;
+    let init = instance
+        .get_typed_func::<(), ()>(&store, "padis_init")
+        .map_err(error)?;
+    init.call(&mut store, ()).map_err(error)?;
+
+    let commands = store.into_data().registered;
+    if commands.is_empty() {
+        return Err("Error loading the extension: the module registered no commands".into());
+    }
+    for command in &commands {
+        let handler = module.get_export(&command.handler);
+        let typed = handler.as_ref().and_then(|ty| ty.func()).is_some_and(|ty| {
+            ty.params() == [ValType::I32, ValType::I32] && ty.results() == [ValType::I64]
+        });
+        if !typed {
+            return Err(format!(
+                "Error loading the extension: handler '{}' must be exported as (i32, i32) -> i64",
+                command.handler
+            ));
+        }
+    }
+    db.module_load(WasmModule {
+        name: name.to_string(),
+        path: path.to_string(),
+        module: Arc::new(module),
+        commands,
+    })
+    .map_err(|e| e.to_string())
+}
+
+// Runs a command a module registered
+pub fn call(db: &Db, name: &[u8], args: Vec<Bytes>) -> Frame {
+    let Some((module, command)) = db.module_command(name) else {
+        return Frame::SimpleError(
+            CommandError::Unknown(String::from_utf8_lossy(name).into()).to_string(),
+        );
+    };
+    let limits = db.wasm_limits();
+    match run(db, &module, &command, args, limits) {
+        Ok(frame) => frame,
+        Err(e) => Frame::SimpleError(message(&e)),
+    }
+}
+
+fn run(
+    db: &Db,
+    module: &Module,
+    command: &ModuleCommand,
+    args: Vec<Bytes>,
+    limits: WasmLimits,
+) -> Result<Frame, Error> {
+    let mut store = store(Some(db.clone()), command.read_only, limits)?;
+    let (instance, exports) = instantiate(&mut store, module)?;
+    let handler = instance.get_typed_func::<(i32, i32), i64>(&store, &command.handler)?;
+    let input = Frame::Array(args.into_iter().map(Frame::BulkString).collect()).to_bytes();
+    let (ptr, len) = unpack(exports.give(&mut store, &input)?);
+    let (ptr, len) = unpack(handler.call(&mut store, (ptr, len))?);
+    let reply = exports.take(&store, ptr, len)?;
+    Frame::parse(&mut Cursor::new(&reply[..]))
+        .map_err(|_| Error::new("module command returned an invalid reply"))
+}
+
+// One engine compiles every module, counting fuel as commands run
+fn engine() -> &'static Engine {
+    static ENGINE: OnceLock<Engine> = OnceLock::new();
+    ENGINE.get_or_init(|| {
+        let mut config = Config::default();
+        config.consume_fuel(true);
+        Engine::new(&config)
+    })
+}
+
+fn store(db: Option<Db>, read_only: bool, limits: WasmLimits) -> Result<Store<Host>, Error> {
+    let host = Host {
+        db,
+        read_only,
+        deadline: Instant::now() + limits.time,
+        exports: None,
+        registered: Vec::new(),
+        limits: StoreLimitsBuilder::new()
+            .memory_size(limits.memory)
+            .instances(1)
+            .memories(1)
+            .build(),
+    };
+    let mut store = Store::new(engine(), host);
+    store.limiter(|host| &mut host.limits);
+    store.set_fuel(limits.fuel)?;
+    Ok(store)
+}
+
+fn instantiate(store: &mut Store<Host>, module: &Module) -> Result<(Instance, Exports), Error> {
+    let instance = linker()
+        .instantiate(&mut *store, module)?
+        .start(&mut *store)?;
+    let exports = Exports {
+        memory: instance
+            .get_memory(&*store, "memory")
+            .ok_or_else(|| Error::new("the module doesn't export its memory"))?,
+        alloc: instance.get_typed_func(&*store, "padis_alloc")?,
+    };
+    store.data_mut().exports = Some(exports);
+    Ok((instance, exports))
+}
+
+fn linker() -> Linker<Host> {
+    let mut linker = Linker::new(engine());
+    linker
+        .func_wrap("padis", "register_command", register_command)
+        .and_then(|linker| linker.func_wrap("padis", "get", get))
+        .and_then(|linker| linker.func_wrap("padis", "set", set))
+        .and_then(|linker| linker.func_wrap("padis", "call", call_command))
+        .and_then(|linker| linker.func_wrap("padis", "log", log))
+        .expect("host functions have distinct names");
+    linker
+}
+
+fn register_command(
+    mut caller: Caller<'_, Host>,
+    name_ptr: i32,
+    name_len: i32,
+    handler_ptr: i32,
+    handler_len: i32,
+    flags: i32,
+) -> Result<(), Error> {
+    if caller.data().db.is_some() {
+        return Err(Error::new(
+            "register_command can only be called from padis_init",
+        ));
+    }
+    let exports = exports(&caller)?;
+    let name = exports.take(&caller, name_ptr, name_len)?;
+    let handler = exports.take(&caller, handler_ptr, handler_len)?;
+    let handler =
+        String::from_utf8(handler).map_err(|_| Error::new("handler names must be UTF-8"))?;
+    if name.is_empty() || name.iter().any(|c| c.is_ascii_whitespace()) {
+        return Err(Error::new("command names must be a single word"));
+    }
+    let name = Bytes::from(name.to_ascii_uppercase());
+    // Modules can add commands but not replace padis' own
+    let builtin = !matches!(
+        Command::from_frame(Frame::Array(vec![Frame::BulkString(name.clone())])),
+        Err(CommandError::Unknown(_))
+    );
+    let host = caller.data_mut();
+    if builtin || host.registered.iter().any(|c| c.name == name) {
+        return Err(Error::new(format!(
+            "Command '{}' is already registered",
+            String::from_utf8_lossy(&name)
+        )));
+    }
+    host.registered.push(ModuleCommand {
+        name,
+        handler,
+        read_only: flags & READONLY != 0,
+    });
+    Ok(())
+}
+
+fn get(mut caller: Caller<'_, Host>, key_ptr: i32, key_len: i32) -> Result<i64, Error> {
+    let db = db(&caller)?;
+    let exports = exports(&caller)?;
+    let key = Bytes::from(exports.take(&caller, key_ptr, key_len)?);
+    match db.get_string(&key) {
+        Ok(Some(value)) => exports.give(&mut caller, &value),
+        Ok(None) => Ok(-1),
+        Err(_) => Ok(-2),
+    }
+}
+
+fn set(
+    caller: Caller<'_, Host>,
+    key_ptr: i32,
+    key_len: i32,
+    value_ptr: i32,
+    value_len: i32,
+) -> Result<(), Error> {
+    let db = db(&caller)?;
+    if caller.data().read_only {
+        return Err(Error::new(
+            "Write commands are not allowed from read-only module commands.",
+        ));
+    }
+    let exports = exports(&caller)?;
+    let key = Bytes::from(exports.take(&caller, key_ptr, key_len)?);
+    let value = Bytes::from(exports.take(&caller, value_ptr, value_len)?);
+    db.set(&key, value, None);
+    Ok(())
+}
+
+// Commands that fail come back as error replies, for the module to handle
+fn call_command(mut caller: Caller<'_, Host>, ptr: i32, len: i32) -> Result<i64, Error> {
+    let db = db(&caller)?;
+    let exports = exports(&caller)?;
+    let input = exports.take(&caller, ptr, len)?;
+    let reply = match Frame::parse(&mut Cursor::new(&input[..])) {
+        Err(_) => Frame::SimpleError("Invalid RESP command".into()),
+        Ok(frame) => match Command::from_frame(frame) {
+            Err(e) => Frame::SimpleError(e.to_string()),
+            Ok(cmd) if cmd.is_denied_in_script() => {
+                Frame::SimpleError("This Redis command is not allowed from module commands".into())
+            }
+            Ok(cmd) if cmd.is_write() && caller.data().read_only => Frame::SimpleError(
+                "Write commands are not allowed from read-only module commands.".into(),
+            ),
+            Ok(cmd) => execute(cmd, &db),
+        },
+    };
+    exports.give(&mut caller, &reply.to_bytes())
+}
+
+fn log(caller: Caller<'_, Host>, ptr: i32, len: i32) -> Result<(), Error> {
+    let line = exports(&caller)?.take(&caller, ptr, len)?;
+    eprintln!("{}", String::from_utf8_lossy(&line));
+    Ok(())
+}
+
+// The keyspace, once the module is running a command and it's still within its time limit
+fn db(caller: &Caller<'_, Host>) -> Result<Db, Error> {
+    let host = caller.data();
+    let Some(db) = &host.db else {
+        return Err(Error::new("the keyspace can't be used from padis_init"));
+    };
+    if Instant::now() > host.deadline {
+        return Err(Error::new("module exceeded wasm-time-limit"));
+    }
+    Ok(db.clone())
+}
+
+fn exports(caller: &Caller<'_, Host>) -> Result<Exports, Error> {
+    caller
+        .data()
+        .exports
+        .ok_or_else(|| Error::new("the module called padis while it was being instantiated"))
+}
+
+impl Exports {
+    // Copies `data` into memory the module allocates, returning where it went
+    fn give(&self, mut ctx: impl AsContextMut<Data = Host>, data: &[u8]) -> Result<i64, Error> {
+        let len = i32::try_from(data.len()).map_err(|_| Error::new("reply too large"))?;
+        let ptr = self.alloc.call(&mut ctx, len)?;
+        self.memory.write(&mut ctx, ptr as u32 as usize, data)?;
+        Ok(pack(ptr, len))
+    }
+
+    // Copies `len` bytes out of the module's memory
+    fn take(&self, ctx: impl AsContext<Data = Host>, ptr: i32, len: i32) -> Result<Vec<u8>, Error> {
+        let start = ptr as u32 as usize;
+        self.memory
+            .data(&ctx)
+            .get(start..start + len as u32 as usize)
+            .map(<[u8]>::to_vec)
+            .ok_or_else(|| Error::new("out of bounds memory access"))
+    }
+}
+
+fn pack(ptr: i32, len: i32) -> i64 {
+    ((ptr as u32 as i64) << 32) | len as u32 as i64
+}
+
+fn unpack(packed: i64) -> (i32, i32) {
+    ((packed >> 32) as i32, packed as i32)
+}
+
+// What a failure tells the client, naming the limit it ran into
+fn message(e: &Error) -> String {
+    match e.as_trap_code() {
+        Some(TrapCode::OutOfFuel) => "module exceeded wasm-fuel-limit".into(),
+        _ => e.to_string(),
+    }
+}
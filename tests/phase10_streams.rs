@@ -0,0 +1,504 @@
+use bytes::Bytes;
+use padis::db::{StreamId, StreamTrim, TrimStrategy, XAddId, XReadFrom};
+use padis::{Command, Db, Frame, run_server};
+use std::time::Duration;
+use tokio::net::TcpListener;
+
+fn b(s: &str) -> Bytes {
+    Bytes::copy_from_slice(s.as_bytes())
+}
+
+fn id(ms: u64, seq: u64) -> StreamId {
+    StreamId { ms, seq }
+}
+
+fn add(db: &Db, key: &str, ms: u64, seq: u64) -> StreamId {
+    db.xadd(
+        &b(key),
+        XAddId::Explicit(id(ms, seq)),
+        &[(b("n"), b(&ms.to_string()))],
+        false,
+        None,
+    )
+    .unwrap()
+    .unwrap()
+}
+
+fn ids(db: &Db, key: &str) -> Vec<StreamId> {
+    db.xrange(&b(key), StreamId::MIN, StreamId::MAX, None, false)
+        .unwrap()
+        .into_iter()
+        .map(|(id, _)| id)
+        .collect()
+}
+
+// Helper to build a command frame
+fn cmd_frame(args: &[&str]) -> Frame {
+    Frame::Array(
+        args.iter()
+            .map(|s| Frame::BulkString(Bytes::copy_from_slice(s.as_bytes())))
+            .collect(),
+    )
+}
+
+// === Db ===
+
+#[test]
+fn ids_must_increase() {
+    let db = Db::new();
+    add(&db, "s", 5, 1);
+    let key = b("s");
+    let fields = [(b("f"), b("v"))];
+
+    assert!(
+        db.xadd(&key, XAddId::Explicit(id(5, 1)), &fields, false, None)
+            .is_err()
+    );
+    assert!(
+        db.xadd(&key, XAddId::Explicit(id(4, 9)), &fields, false, None)
+            .is_err()
+    );
+    assert!(
+        db.xadd(&b("t"), XAddId::Explicit(id(0, 0)), &fields, false, None)
+            .is_err()
+    );
+    // A rejected first entry doesn't create the key
+    assert!(!db.keys().contains(&b("t")));
+
+    let partial = db.xadd(&key, XAddId::Partial(5), &fields, false, None);
+    assert_eq!(partial.unwrap(), Some(id(5, 2)));
+    let partial = db.xadd(&key, XAddId::Partial(9), &fields, false, None);
+    assert_eq!(partial.unwrap(), Some(id(9, 0)));
+    let auto = db.xadd(&key, XAddId::Auto, &fields, false, None).unwrap();
+    assert!(auto.unwrap() > id(9, 0));
+}
+
+#[test]
+fn auto_ids_keep_increasing_within_a_millisecond() {
+    let db = Db::new();
+    let key = b("s");
+    let fields = [(b("f"), b("v"))];
+    // An explicit ID far in the future forces auto IDs to bump the seq
+    db.xadd(
+        &key,
+        XAddId::Explicit(id(u64::MAX - 1, 0)),
+        &fields,
+        false,
+        None,
+    )
+    .unwrap();
+    let next = db.xadd(&key, XAddId::Auto, &fields, false, None).unwrap();
+    assert_eq!(next, Some(id(u64::MAX - 1, 1)));
+}
+
+#[test]
+fn nomkstream_skips_missing_key() {
+    let db = Db::new();
+    let added = db
+        .xadd(&b("s"), XAddId::Auto, &[(b("f"), b("v"))], true, None)
+        .unwrap();
+    assert_eq!(added, None);
+    assert!(db.keys().is_empty());
+}
+
+#[test]
+fn range_spans_many_nodes() {
+    let db = Db::new();
+    for ms in 1..=250 {
+        add(&db, "s", ms, 0);
+    }
+    assert_eq!(db.xlen(&b("s")).unwrap(), 250);
+
+    let entries = db
+        .xrange(&b("s"), id(99, 0), id(102, 0), None, false)
+        .unwrap();
+    let got: Vec<StreamId> = entries.iter().map(|(id, _)| *id).collect();
+    assert_eq!(got, vec![id(99, 0), id(100, 0), id(101, 0), id(102, 0)]);
+    assert_eq!(entries[0].1, vec![(b("n"), b("99"))]);
+
+    let rev = db
+        .xrange(&b("s"), id(150, 0), StreamId::MAX, Some(3), true)
+        .unwrap();
+    let got: Vec<StreamId> = rev.iter().map(|(id, _)| *id).collect();
+    assert_eq!(got, vec![id(250, 0), id(249, 0), id(248, 0)]);
+}
+
+#[test]
+fn delete_hides_entries() {
+    let db = Db::new();
+    for ms in 1..=3 {
+        add(&db, "s", ms, 0);
+    }
+    assert_eq!(
+        db.xdel(&b("s"), &[id(2, 0), id(2, 0), id(7, 0)]).unwrap(),
+        1
+    );
+    assert_eq!(ids(&db, "s"), vec![id(1, 0), id(3, 0)]);
+    assert_eq!(db.xlen(&b("s")).unwrap(), 2);
+
+    // Deleting every entry keeps the stream and its last ID
+    db.xdel(&b("s"), &[id(1, 0), id(3, 0)]).unwrap();
+    assert_eq!(db.xlen(&b("s")).unwrap(), 0);
+    assert!(db.keys().contains(&b("s")));
+    assert!(
+        db.xadd(&b("s"), XAddId::Explicit(id(3, 0)), &[], false, None)
+            .is_err()
+    );
+}
+
+#[test]
+fn exact_and_approximate_trimming() {
+    let db = Db::new();
+    for ms in 1..=250 {
+        add(&db, "s", ms, 0);
+    }
+
+    let approx = StreamTrim {
+        strategy: TrimStrategy::MaxLen(120),
+        approx: true,
+        limit: None,
+    };
+    // Only whole nodes go, so the first node of 100 entries is removed and no more
+    assert_eq!(db.xtrim(&b("s"), &approx).unwrap(), 100);
+    assert_eq!(db.xlen(&b("s")).unwrap(), 150);
+
+    let exact = StreamTrim {
+        strategy: TrimStrategy::MaxLen(120),
+        approx: false,
+        limit: None,
+    };
+    assert_eq!(db.xtrim(&b("s"), &exact).unwrap(), 30);
+    assert_eq!(ids(&db, "s")[0], id(131, 0));
+
+    let min_id = StreamTrim {
+        strategy: TrimStrategy::MinId(id(245, 0)),
+        approx: false,
+        limit: None,
+    };
+    assert_eq!(db.xtrim(&b("s"), &min_id).unwrap(), 114);
+    assert_eq!(ids(&db, "s")[0], id(245, 0));
+}
+
+#[test]
+fn trim_limit_caps_removed_nodes() {
+    let db = Db::new();
+    for ms in 1..=300 {
+        add(&db, "s", ms, 0);
+    }
+    let trim = StreamTrim {
+        strategy: TrimStrategy::MaxLen(0),
+        approx: true,
+        limit: Some(150),
+    };
+    assert_eq!(db.xtrim(&b("s"), &trim).unwrap(), 100);
+}
+
+#[test]
+fn xread_from_ids() {
+    let db = Db::new();
+    add(&db, "a", 1, 0);
+    add(&db, "a", 2, 0);
+    add(&db, "b", 1, 0);
+
+    let read = db
+        .xread(
+            &[
+                (b("a"), XReadFrom::After(id(1, 0))),
+                (b("b"), XReadFrom::After(id(1, 0))),
+                (b("c"), XReadFrom::After(id(0, 0))),
+            ],
+            None,
+        )
+        .unwrap();
+    assert_eq!(read.len(), 1);
+    assert_eq!(read[0].0, b("a"));
+    assert_eq!(read[0].1[0].0, id(2, 0));
+
+    let last = db.xread(&[(b("a"), XReadFrom::Last)], None).unwrap();
+    assert_eq!(last[0].1[0].0, id(2, 0));
+    assert!(
+        db.xread(&[(b("a"), XReadFrom::New)], None)
+            .unwrap()
+            .is_empty()
+    );
+}
+
+#[tokio::test]
+async fn blocked_read_wakes_on_add() {
+    let db = Db::new();
+    add(&db, "s", 1, 0);
+    let reader = {
+        let db = db.clone();
+        tokio::spawn(async move {
+            db.xread_block(&[(b("s"), XReadFrom::New)], None, None)
+                .await
+        })
+    };
+    tokio::time::sleep(Duration::from_millis(20)).await;
+
+    add(&db, "s", 2, 0);
+    let read = reader.await.unwrap().unwrap();
+    assert_eq!(read[0].1.len(), 1);
+    assert_eq!(read[0].1[0].0, id(2, 0));
+}
+
+#[tokio::test]
+async fn blocked_last_read_on_empty_stream_gets_next_entry() {
+    let db = Db::new();
+    add(&db, "s", 1, 0);
+    db.xdel(&b("s"), &[id(1, 0)]).unwrap();
+    let reader = {
+        let db = db.clone();
+        tokio::spawn(async move {
+            db.xread_block(
+                &[(b("s"), XReadFrom::Last)],
+                None,
+                Some(Duration::from_secs(1)),
+            )
+            .await
+        })
+    };
+    tokio::time::sleep(Duration::from_millis(20)).await;
+
+    // The entry that wakes the reader is the one it reads
+    add(&db, "s", 2, 0);
+    let read = reader.await.unwrap().unwrap();
+    assert_eq!(read[0].1.len(), 1);
+    assert_eq!(read[0].1[0].0, id(2, 0));
+}
+
+#[tokio::test]
+async fn blocked_read_times_out() {
+    let db = Db::new();
+    let read = db
+        .xread_block(
+            &[(b("s"), XReadFrom::New)],
+            None,
+            Some(Duration::from_millis(20)),
+        )
+        .await
+        .unwrap();
+    assert!(read.is_empty());
+}
+
+#[test]
+fn wrong_type() {
+    let db = Db::new();
+    db.set(&b("k"), b("v"), None);
+    assert!(db.xlen(&b("k")).is_err());
+    assert!(
+        db.xadd(&b("k"), XAddId::Auto, &[(b("f"), b("v"))], false, None)
+            .is_err()
+    );
+}
+
+// === Parsing ===
+
+#[test]
+fn parse_xadd_options() {
+    let frame = cmd_frame(&[
+        "XADD",
+        "s",
+        "NOMKSTREAM",
+        "MAXLEN",
+        "~",
+        "1000",
+        "LIMIT",
+        "10",
+        "5-*",
+        "f",
+        "v",
+    ]);
+    let cmd = Command::from_frame(frame).unwrap();
+    assert!(matches!(
+        cmd,
+        Command::XAdd {
+            id: XAddId::Partial(5),
+            nomkstream: true,
+            trim: Some(StreamTrim {
+                strategy: TrimStrategy::MaxLen(1000),
+                approx: true,
+                limit: Some(10),
+            }),
+            ..
+        }
+    ));
+
+    let cmd = Command::from_frame(cmd_frame(&["XADD", "s", "MINID", "7", "*", "f", "v"])).unwrap();
+    assert!(matches!(
+        cmd,
+        Command::XAdd {
+            id: XAddId::Auto,
+            trim: Some(StreamTrim {
+                strategy: TrimStrategy::MinId(StreamId { ms: 7, seq: 0 }),
+                approx: false,
+                ..
+            }),
+            ..
+        }
+    ));
+}
+
+#[test]
+fn parse_rejects_bad_streams() {
+    assert!(Command::from_frame(cmd_frame(&["XADD", "s", "*", "f"])).is_err());
+    assert!(Command::from_frame(cmd_frame(&["XADD", "s", "1-x", "f", "v"])).is_err());
+    assert!(
+        Command::from_frame(cmd_frame(&[
+            "XADD", "s", "MAXLEN", "5", "LIMIT", "1", "*", "f", "v"
+        ]))
+        .is_err()
+    );
+    assert!(Command::from_frame(cmd_frame(&["XTRIM", "s", "5"])).is_err());
+    assert!(Command::from_frame(cmd_frame(&["XREAD", "STREAMS", "a", "b", "0"])).is_err());
+    assert!(
+        Command::from_frame(cmd_frame(&["XREAD", "BLOCK", "-1", "STREAMS", "a", "0"])).is_err()
+    );
+}
+
+#[test]
+fn parse_range_bounds() {
+    let cmd = Command::from_frame(cmd_frame(&["XRANGE", "s", "(5-3", "7"])).unwrap();
+    assert!(matches!(
+        cmd,
+        Command::XRange {
+            start: StreamId { ms: 5, seq: 4 },
+            end: StreamId {
+                ms: 7,
+                seq: u64::MAX
+            },
+            ..
+        }
+    ));
+
+    let cmd = Command::from_frame(cmd_frame(&["XREVRANGE", "s", "+", "-", "COUNT", "2"])).unwrap();
+    assert!(matches!(
+        cmd,
+        Command::XRange {
+            start: StreamId::MIN,
+            end: StreamId::MAX,
+            count: Some(2),
+            rev: true,
+            ..
+        }
+    ));
+}
+
+#[test]
+fn parse_xread_block() {
+    let frame = cmd_frame(&[
+        "XREAD", "COUNT", "2", "BLOCK", "0", "STREAMS", "a", "b", "$", "+",
+    ]);
+    let cmd = Command::from_frame(frame).unwrap();
+    assert!(cmd.is_blocking());
+    assert!(matches!(
+        &cmd,
+        Command::XRead { streams, count: Some(2), timeout: None, .. }
+            if streams == &vec![(b("a"), XReadFrom::New), (b("b"), XReadFrom::Last)]
+    ));
+
+    let plain = Command::from_frame(cmd_frame(&["XREAD", "STREAMS", "a", "0"])).unwrap();
+    assert!(!plain.is_blocking());
+}
+
+// === Integration ===
+
+#[tokio::test]
+async fn streams_over_tcp() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let db = Db::new();
+    tokio::spawn(async move { run_server(listener, db).await });
+
+    let client = redis::Client::open(format!("redis://127.0.0.1:{}", port)).unwrap();
+    let mut con = client.get_multiplexed_async_connection().await.unwrap();
+
+    let first: String = redis::cmd("XADD")
+        .arg("events")
+        .arg("1-1")
+        .arg("kind")
+        .arg("login")
+        .query_async(&mut con)
+        .await
+        .unwrap();
+    assert_eq!(first, "1-1");
+
+    let second: String = redis::cmd("XADD")
+        .arg("events")
+        .arg("1-*")
+        .arg("kind")
+        .arg("logout")
+        .query_async(&mut con)
+        .await
+        .unwrap();
+    assert_eq!(second, "1-2");
+
+    let range: Vec<(String, Vec<String>)> = redis::cmd("XRANGE")
+        .arg("events")
+        .arg("-")
+        .arg("+")
+        .query_async(&mut con)
+        .await
+        .unwrap();
+    assert_eq!(
+        range,
+        vec![
+            ("1-1".into(), vec!["kind".into(), "login".into()]),
+            ("1-2".into(), vec!["kind".into(), "logout".into()]),
+        ]
+    );
+
+    let err = redis::cmd("XADD")
+        .arg("events")
+        .arg("1-1")
+        .arg("kind")
+        .arg("late")
+        .query_async::<String>(&mut con)
+        .await;
+    assert!(err.is_err());
+
+    // A blocked XREAD on another connection is woken by the next XADD
+    let mut reader = client.get_multiplexed_async_connection().await.unwrap();
+    let blocked = tokio::spawn(async move {
+        redis::cmd("XREAD")
+            .arg("BLOCK")
+            .arg(0)
+            .arg("STREAMS")
+            .arg("events")
+            .arg("$")
+            .query_async::<Vec<(String, Vec<(String, Vec<String>)>)>>(&mut reader)
+            .await
+    });
+    tokio::time::sleep(Duration::from_millis(50)).await;
+
+    let _: String = redis::cmd("XADD")
+        .arg("events")
+        .arg("2-0")
+        .arg("kind")
+        .arg("signup")
+        .query_async(&mut con)
+        .await
+        .unwrap();
+
+    let read = blocked.await.unwrap().unwrap();
+    assert_eq!(read[0].0, "events");
+    assert_eq!(read[0].1[0].0, "2-0");
+
+    let len: i64 = redis::cmd("XLEN")
+        .arg("events")
+        .query_async(&mut con)
+        .await
+        .unwrap();
+    assert_eq!(len, 3);
+
+    let none: Option<Vec<String>> = redis::cmd("XREAD")
+        .arg("BLOCK")
+        .arg(10)
+        .arg("STREAMS")
+        .arg("events")
+        .arg("$")
+        .query_async(&mut con)
+        .await
+        .unwrap();
+    assert!(none.is_none());
+}
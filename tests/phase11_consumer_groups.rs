@@ -0,0 +1,554 @@
+use bytes::Bytes;
+use padis::db::{ClaimOptions, GroupReadFrom, GroupStart, PendingFilter, StreamId, XAddId};
+use padis::{Command, Db, Frame, run_server};
+use std::time::Duration;
+use tokio::net::TcpListener;
+
+fn b(s: &str) -> Bytes {
+    Bytes::copy_from_slice(s.as_bytes())
+}
+
+fn id(ms: u64, seq: u64) -> StreamId {
+    StreamId { ms, seq }
+}
+
+fn add(db: &Db, key: &str, ms: u64) {
+    db.xadd(
+        &b(key),
+        XAddId::Explicit(id(ms, 0)),
+        &[(b("job"), b(&ms.to_string()))],
+        false,
+        None,
+    )
+    .unwrap();
+}
+
+// A stream of three jobs with a group that starts at the beginning
+fn jobs() -> Db {
+    let db = Db::new();
+    for ms in 1..=3 {
+        add(&db, "jobs", ms);
+    }
+    db.xgroup_create(
+        &b("jobs"),
+        &b("workers"),
+        GroupStart::Id(StreamId::MIN),
+        false,
+        None,
+    )
+    .unwrap();
+    db
+}
+
+fn read_new(db: &Db, consumer: &str, count: Option<usize>) -> Vec<StreamId> {
+    db.xreadgroup(
+        &b("workers"),
+        &b(consumer),
+        &[(b("jobs"), GroupReadFrom::Undelivered)],
+        count,
+        false,
+    )
+    .unwrap()
+    .into_iter()
+    .flat_map(|(_, entries)| entries.into_iter().map(|(id, _)| id))
+    .collect()
+}
+
+fn all_pending() -> PendingFilter {
+    PendingFilter {
+        min_idle: None,
+        start: StreamId::MIN,
+        end: StreamId::MAX,
+        count: 100,
+        consumer: None,
+    }
+}
+
+// An entry as the redis client decodes it
+type Entry = (String, Vec<String>);
+
+// Helper to build a command frame
+fn cmd_frame(args: &[&str]) -> Frame {
+    Frame::Array(
+        args.iter()
+            .map(|s| Frame::BulkString(Bytes::copy_from_slice(s.as_bytes())))
+            .collect(),
+    )
+}
+
+// === Db ===
+
+#[test]
+fn create_needs_stream_unless_mkstream() {
+    let db = Db::new();
+    let key = b("s");
+    let group = b("g");
+    assert!(
+        db.xgroup_create(&key, &group, GroupStart::Last, false, None)
+            .is_err()
+    );
+    db.xgroup_create(&key, &group, GroupStart::Last, true, None)
+        .unwrap();
+    assert_eq!(db.xlen(&key).unwrap(), 0);
+    // The name is taken now
+    assert!(
+        db.xgroup_create(&key, &group, GroupStart::Last, false, None)
+            .is_err()
+    );
+    assert!(db.xgroup_destroy(&key, &group).unwrap());
+    assert!(!db.xgroup_destroy(&key, &group).unwrap());
+}
+
+#[test]
+fn consumers_share_new_entries() {
+    let db = jobs();
+    assert_eq!(read_new(&db, "alice", Some(2)), vec![id(1, 0), id(2, 0)]);
+    assert_eq!(read_new(&db, "bob", None), vec![id(3, 0)]);
+    assert!(read_new(&db, "alice", None).is_empty());
+
+    let summary = db.xpending_summary(&b("jobs"), &b("workers")).unwrap();
+    assert_eq!(summary.count, 3);
+    assert_eq!(summary.min, Some(id(1, 0)));
+    assert_eq!(summary.max, Some(id(3, 0)));
+    assert_eq!(summary.consumers, vec![(b("alice"), 2), (b("bob"), 1)]);
+}
+
+#[test]
+fn history_reads_return_own_pending() {
+    let db = jobs();
+    read_new(&db, "alice", Some(2));
+    read_new(&db, "bob", None);
+    db.xack(&b("jobs"), &b("workers"), &[id(1, 0)]).unwrap();
+    db.xdel(&b("jobs"), &[id(2, 0)]).unwrap();
+
+    let history = db
+        .xreadgroup(
+            &b("workers"),
+            &b("alice"),
+            &[(b("jobs"), GroupReadFrom::Pending(StreamId::MIN))],
+            None,
+            false,
+        )
+        .unwrap();
+    // Deleted entries come back without fields
+    assert_eq!(history, vec![(b("jobs"), vec![(id(2, 0), None)])]);
+
+    let pending = db
+        .xpending(&b("jobs"), &b("workers"), &all_pending())
+        .unwrap();
+    assert_eq!(pending[0].id, id(2, 0));
+    assert_eq!(pending[0].deliveries, 2);
+}
+
+#[test]
+fn noack_skips_the_pel() {
+    let db = jobs();
+    db.xreadgroup(
+        &b("workers"),
+        &b("alice"),
+        &[(b("jobs"), GroupReadFrom::Undelivered)],
+        None,
+        true,
+    )
+    .unwrap();
+    let summary = db.xpending_summary(&b("jobs"), &b("workers")).unwrap();
+    assert_eq!(summary.count, 0);
+}
+
+#[test]
+fn ack_removes_pending() {
+    let db = jobs();
+    read_new(&db, "alice", None);
+    let acked = db
+        .xack(&b("jobs"), &b("workers"), &[id(1, 0), id(1, 0), id(9, 0)])
+        .unwrap();
+    assert_eq!(acked, 1);
+    let pending = db
+        .xpending(&b("jobs"), &b("workers"), &all_pending())
+        .unwrap();
+    let ids: Vec<StreamId> = pending.iter().map(|p| p.id).collect();
+    assert_eq!(ids, vec![id(2, 0), id(3, 0)]);
+}
+
+#[test]
+fn missing_group_is_an_error() {
+    let db = jobs();
+    let read = db.xreadgroup(
+        &b("nope"),
+        &b("alice"),
+        &[(b("jobs"), GroupReadFrom::Undelivered)],
+        None,
+        false,
+    );
+    assert!(read.is_err());
+    assert!(db.xpending_summary(&b("jobs"), &b("nope")).is_err());
+    assert_eq!(db.xack(&b("jobs"), &b("nope"), &[id(1, 0)]).unwrap(), 0);
+}
+
+#[test]
+fn claim_respects_min_idle() {
+    let db = jobs();
+    read_new(&db, "alice", None);
+
+    // Nothing has been idle for an hour
+    let claimed = db
+        .xclaim(
+            &b("jobs"),
+            &b("workers"),
+            &b("bob"),
+            3_600_000,
+            &[id(1, 0)],
+            &ClaimOptions::default(),
+        )
+        .unwrap();
+    assert!(claimed.is_empty());
+
+    let claimed = db
+        .xclaim(
+            &b("jobs"),
+            &b("workers"),
+            &b("bob"),
+            0,
+            &[id(1, 0), id(9, 0)],
+            &ClaimOptions::default(),
+        )
+        .unwrap();
+    assert_eq!(claimed, vec![(id(1, 0), vec![(b("job"), b("1"))])]);
+
+    let pending = db
+        .xpending(&b("jobs"), &b("workers"), &all_pending())
+        .unwrap();
+    assert_eq!(pending[0].consumer, b("bob"));
+    assert_eq!(pending[0].deliveries, 2);
+}
+
+#[test]
+fn claim_options() {
+    let db = jobs();
+    read_new(&db, "alice", Some(1));
+
+    let opts = ClaimOptions {
+        idle: Some(5000),
+        retry_count: Some(7),
+        just_id: true,
+        ..ClaimOptions::default()
+    };
+    db.xclaim(&b("jobs"), &b("workers"), &b("bob"), 0, &[id(1, 0)], &opts)
+        .unwrap();
+    let pending = db
+        .xpending(&b("jobs"), &b("workers"), &all_pending())
+        .unwrap();
+    assert_eq!(pending[0].deliveries, 7);
+    assert!(pending[0].idle >= 5000);
+
+    // FORCE claims an entry nobody was given
+    let forced = ClaimOptions {
+        force: true,
+        ..ClaimOptions::default()
+    };
+    let claimed = db
+        .xclaim(
+            &b("jobs"),
+            &b("workers"),
+            &b("bob"),
+            0,
+            &[id(3, 0)],
+            &forced,
+        )
+        .unwrap();
+    assert_eq!(claimed.len(), 1);
+}
+
+#[test]
+fn autoclaim_pages_and_drops_deleted() {
+    let db = jobs();
+    read_new(&db, "alice", None);
+    db.xdel(&b("jobs"), &[id(2, 0)]).unwrap();
+
+    let first = db
+        .xautoclaim(
+            &b("jobs"),
+            &b("workers"),
+            &b("bob"),
+            0,
+            StreamId::MIN,
+            1,
+            false,
+        )
+        .unwrap();
+    assert_eq!(first.claimed.len(), 1);
+    assert_eq!(first.next, id(2, 0));
+
+    let second = db
+        .xautoclaim(
+            &b("jobs"),
+            &b("workers"),
+            &b("bob"),
+            0,
+            first.next,
+            10,
+            true,
+        )
+        .unwrap();
+    assert_eq!(second.deleted, vec![id(2, 0)]);
+    assert_eq!(second.claimed[0].0, id(3, 0));
+    assert_eq!(second.next, StreamId::MIN);
+
+    let summary = db.xpending_summary(&b("jobs"), &b("workers")).unwrap();
+    assert_eq!(summary.consumers, vec![(b("bob"), 2)]);
+}
+
+#[test]
+fn info_reports_groups_and_consumers() {
+    let db = jobs();
+    read_new(&db, "alice", Some(1));
+    db.xgroup_createconsumer(&b("jobs"), &b("workers"), &b("bob"))
+        .unwrap();
+
+    let stream = db.xinfo_stream(&b("jobs")).unwrap();
+    assert_eq!(stream.length, 3);
+    assert_eq!(stream.groups, 1);
+    assert_eq!(stream.last_generated_id, id(3, 0));
+    assert_eq!(stream.first_entry.unwrap().0, id(1, 0));
+
+    let groups = db.xinfo_groups(&b("jobs")).unwrap();
+    assert_eq!(groups[0].consumers, 2);
+    assert_eq!(groups[0].pending, 1);
+    assert_eq!(groups[0].last_delivered_id, id(1, 0));
+    assert_eq!(groups[0].entries_read, Some(1));
+    assert_eq!(groups[0].lag, 2);
+
+    let consumers = db.xinfo_consumers(&b("jobs"), &b("workers")).unwrap();
+    assert_eq!(consumers[0].name, b("alice"));
+    assert_eq!(consumers[0].pending, 1);
+    assert!(consumers[0].inactive.is_some());
+    assert_eq!(consumers[1].inactive, None);
+
+    assert_eq!(
+        db.xgroup_delconsumer(&b("jobs"), &b("workers"), &b("alice"))
+            .unwrap(),
+        1
+    );
+    let summary = db.xpending_summary(&b("jobs"), &b("workers")).unwrap();
+    assert_eq!(summary.count, 0);
+}
+
+#[test]
+fn setid_rewinds_the_group() {
+    let db = jobs();
+    read_new(&db, "alice", None);
+    db.xgroup_setid(&b("jobs"), &b("workers"), GroupStart::Id(id(1, 0)), None)
+        .unwrap();
+    assert_eq!(read_new(&db, "bob", None), vec![id(2, 0), id(3, 0)]);
+}
+
+#[tokio::test]
+async fn blocked_group_read_wakes_on_add() {
+    let db = jobs();
+    read_new(&db, "alice", None);
+    let reader = {
+        let db = db.clone();
+        tokio::spawn(async move {
+            db.xreadgroup_block(
+                &b("workers"),
+                &b("bob"),
+                &[(b("jobs"), GroupReadFrom::Undelivered)],
+                None,
+                false,
+                None,
+            )
+            .await
+        })
+    };
+    tokio::time::sleep(Duration::from_millis(20)).await;
+
+    add(&db, "jobs", 4);
+    let read = reader.await.unwrap().unwrap();
+    assert_eq!(read[0].1[0].0, id(4, 0));
+}
+
+// === Parsing ===
+
+#[test]
+fn parse_xreadgroup() {
+    let frame = cmd_frame(&[
+        "XREADGROUP",
+        "GROUP",
+        "g",
+        "c",
+        "COUNT",
+        "5",
+        "BLOCK",
+        "100",
+        "NOACK",
+        "STREAMS",
+        "a",
+        "b",
+        ">",
+        "0",
+    ]);
+    let cmd = Command::from_frame(frame).unwrap();
+    assert!(cmd.is_blocking());
+    assert!(matches!(
+        &cmd,
+        Command::XReadGroup { count: Some(5), noack: true, timeout: Some(_), streams, .. }
+            if streams == &vec![
+                (b("a"), GroupReadFrom::Undelivered),
+                (b("b"), GroupReadFrom::Pending(StreamId::MIN)),
+            ]
+    ));
+    assert!(
+        Command::from_frame(cmd_frame(&["XREADGROUP", "g", "c", "STREAMS", "a", ">"])).is_err()
+    );
+}
+
+#[test]
+fn parse_xclaim_options() {
+    let frame = cmd_frame(&[
+        "XCLAIM",
+        "s",
+        "g",
+        "c",
+        "1000",
+        "1-0",
+        "2-0",
+        "IDLE",
+        "5",
+        "RETRYCOUNT",
+        "3",
+        "JUSTID",
+    ]);
+    let cmd = Command::from_frame(frame).unwrap();
+    assert!(matches!(
+        &cmd,
+        Command::XClaim { min_idle: 1000, ids, opts, .. }
+            if ids.len() == 2 && opts.idle == Some(5) && opts.retry_count == Some(3) && opts.just_id
+    ));
+    // IDs can't follow options
+    assert!(
+        Command::from_frame(cmd_frame(&[
+            "XCLAIM", "s", "g", "c", "0", "1-0", "FORCE", "2-0"
+        ]))
+        .is_err()
+    );
+}
+
+#[test]
+fn parse_xgroup_and_xpending() {
+    let frame = cmd_frame(&[
+        "XGROUP",
+        "CREATE",
+        "s",
+        "g",
+        "$",
+        "MKSTREAM",
+        "ENTRIESREAD",
+        "-1",
+    ]);
+    assert!(matches!(
+        Command::from_frame(frame).unwrap(),
+        Command::XGroupCreate {
+            start: GroupStart::Last,
+            mkstream: true,
+            entries_read: None,
+            ..
+        }
+    ));
+    assert!(
+        Command::from_frame(cmd_frame(&["XGROUP", "SETID", "s", "g", "$", "MKSTREAM"])).is_err()
+    );
+    assert!(Command::from_frame(cmd_frame(&["XGROUP", "NOPE", "s", "g"])).is_err());
+
+    let frame = cmd_frame(&["XPENDING", "s", "g", "IDLE", "10", "-", "+", "5", "alice"]);
+    assert!(matches!(
+        Command::from_frame(frame).unwrap(),
+        Command::XPending {
+            filter: Some(PendingFilter {
+                min_idle: Some(10),
+                count: 5,
+                consumer: Some(_),
+                ..
+            }),
+            ..
+        }
+    ));
+}
+
+// === Integration ===
+
+#[tokio::test]
+async fn consumer_groups_over_tcp() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    tokio::spawn(async move { run_server(listener, jobs()).await });
+
+    let client = redis::Client::open(format!("redis://127.0.0.1:{}", port)).unwrap();
+    let mut con = client.get_multiplexed_async_connection().await.unwrap();
+
+    let read: Vec<(String, Vec<Entry>)> = redis::cmd("XREADGROUP")
+        .arg("GROUP")
+        .arg("workers")
+        .arg("alice")
+        .arg("COUNT")
+        .arg(2)
+        .arg("STREAMS")
+        .arg("jobs")
+        .arg(">")
+        .query_async(&mut con)
+        .await
+        .unwrap();
+    assert_eq!(read[0].1.len(), 2);
+    assert_eq!(read[0].1[0], ("1-0".into(), vec!["job".into(), "1".into()]));
+
+    let (count, min, max, consumers): (i64, String, String, Vec<(String, String)>) =
+        redis::cmd("XPENDING")
+            .arg("jobs")
+            .arg("workers")
+            .query_async(&mut con)
+            .await
+            .unwrap();
+    assert_eq!((count, min.as_str(), max.as_str()), (2, "1-0", "2-0"));
+    assert_eq!(consumers, vec![("alice".into(), "2".into())]);
+
+    let claimed: Vec<String> = redis::cmd("XCLAIM")
+        .arg("jobs")
+        .arg("workers")
+        .arg("bob")
+        .arg(0)
+        .arg("1-0")
+        .arg("JUSTID")
+        .query_async(&mut con)
+        .await
+        .unwrap();
+    assert_eq!(claimed, vec!["1-0".to_string()]);
+
+    let acked: i64 = redis::cmd("XACK")
+        .arg("jobs")
+        .arg("workers")
+        .arg("1-0")
+        .arg("2-0")
+        .query_async(&mut con)
+        .await
+        .unwrap();
+    assert_eq!(acked, 2);
+
+    let groups: Vec<redis::Value> = redis::cmd("XINFO")
+        .arg("GROUPS")
+        .arg("jobs")
+        .query_async(&mut con)
+        .await
+        .unwrap();
+    assert_eq!(groups.len(), 1);
+
+    let err = redis::cmd("XREADGROUP")
+        .arg("GROUP")
+        .arg("missing")
+        .arg("alice")
+        .arg("STREAMS")
+        .arg("jobs")
+        .arg(">")
+        .query_async::<redis::Value>(&mut con)
+        .await
+        .unwrap_err();
+    assert_eq!(err.code(), Some("NOGROUP"));
+}
@@ -0,0 +1,377 @@
+use bytes::Bytes;
+use padis::{Command, Db, Frame, run_server};
+use tokio::net::TcpListener;
+
+fn b(s: &str) -> Bytes {
+    Bytes::copy_from_slice(s.as_bytes())
+}
+
+fn elements(prefix: &str, n: usize) -> Vec<Bytes> {
+    (0..n).map(|i| b(&format!("{}{}", prefix, i))).collect()
+}
+
+fn raw(db: &Db, key: &str) -> Bytes {
+    db.get_string(&b(key)).unwrap().unwrap()
+}
+
+// Helper to build a command frame
+fn cmd_frame(args: &[&str]) -> Frame {
+    Frame::Array(
+        args.iter()
+            .map(|s| Frame::BulkString(Bytes::copy_from_slice(s.as_bytes())))
+            .collect(),
+    )
+}
+
+const HEADER_LEN: usize = 16;
+
+// An empty sparse HLL: the header, then one XZERO opcode covering all 16384 registers
+fn empty_sparse() -> Vec<u8> {
+    let mut hll = b"HYLL\x01\0\0\0\0\0\0\0\0\0\0\0".to_vec();
+    hll.extend_from_slice(&[0x7f, 0xff]);
+    hll
+}
+
+// A sparse HLL as Redis writes it with registers 14 and 26 set, whose ZERO opcodes are the
+// bytes \r and \n. The cached cardinality is marked stale, as PFADD leaves it
+fn sparse_with_line_breaks() -> Vec<u8> {
+    let mut hll = b"HYLL\x01\0\0\0\0\0\0\0\0\0\0\x80".to_vec();
+    hll.extend_from_slice(&[0x0d, 0x80, 0x0a, 0x84, 0x7f, 0xe4]);
+    hll
+}
+
+// A dense HLL packing each register in 6 bits, least significant first, with a stale cache
+fn dense(registers: &[u8]) -> Vec<u8> {
+    let mut hll = b"HYLL\0\0\0\0\0\0\0\0\0\0\0\x80".to_vec();
+    let mut bits = vec![0u8; 16384 * 6 / 8];
+    for (i, &register) in registers.iter().enumerate() {
+        let (byte, shift) = (i * 6 / 8, i * 6 % 8);
+        bits[byte] |= register << shift;
+        if shift > 2 {
+            bits[byte + 1] |= register >> (8 - shift);
+        }
+    }
+    hll.extend_from_slice(&bits);
+    hll
+}
+
+// === Db ===
+
+#[test]
+fn counts_small_sets_exactly() {
+    let db = Db::new();
+    let added = db
+        .pfadd(&b("hll"), &["a", "b", "c", "d", "e", "f", "g"].map(b))
+        .unwrap();
+    assert!(added);
+    assert_eq!(db.pfcount(&[b("hll")]).unwrap(), 7);
+
+    // Nothing new, so no register changes
+    assert!(!db.pfadd(&b("hll"), &[b("a"), b("b")]).unwrap());
+    assert_eq!(db.pfcount(&[b("hll")]).unwrap(), 7);
+}
+
+#[test]
+fn pfadd_without_elements_creates_key() {
+    let db = Db::new();
+    assert!(db.pfadd(&b("hll"), &[]).unwrap());
+    assert!(!db.pfadd(&b("hll"), &[]).unwrap());
+    // Same as Redis, creating the key marks the cached count stale
+    let value = raw(&db, "hll");
+    assert_eq!(value[..15], empty_sparse()[..15]);
+    assert_eq!(value[HEADER_LEN..], empty_sparse()[HEADER_LEN..]);
+    assert_eq!(db.pfcount(&[b("hll")]).unwrap(), 0);
+}
+
+#[test]
+fn large_counts_within_standard_error() {
+    let db = Db::new();
+    for chunk in elements("visitor:", 100_000).chunks(1000) {
+        db.pfadd(&b("hll"), chunk).unwrap();
+    }
+    let count = db.pfcount(&[b("hll")]).unwrap() as f64;
+    // The standard error is 0.81%, so 3% is far outside what should ever happen
+    assert!((count - 100_000.0).abs() / 100_000.0 < 0.03, "{}", count);
+}
+
+#[test]
+fn sparse_promotes_to_dense() {
+    let db = Db::new();
+    db.pfadd(&b("hll"), &elements("x", 100)).unwrap();
+    let small = raw(&db, "hll");
+    assert_eq!(&small[..4], b"HYLL");
+    assert_eq!(small[4], 1);
+    assert!(small.len() < 3000);
+
+    db.pfadd(&b("hll"), &elements("y", 20_000)).unwrap();
+    let big = raw(&db, "hll");
+    assert_eq!(big[4], 0);
+    assert_eq!(big.len(), 12304);
+}
+
+#[test]
+fn cached_cardinality_lives_in_header() {
+    let db = Db::new();
+    db.pfadd(&b("hll"), &elements("x", 10)).unwrap();
+    // PFADD marks the cache stale
+    assert_ne!(raw(&db, "hll")[15] & 0x80, 0);
+
+    let count = db.pfcount(&[b("hll")]).unwrap();
+    let value = raw(&db, "hll");
+    assert_eq!(value[15] & 0x80, 0);
+    assert_eq!(u64::from_le_bytes(value[8..16].try_into().unwrap()), count);
+}
+
+#[test]
+fn values_move_with_get_and_set() {
+    let db = Db::new();
+    db.pfadd(&b("sparse"), &elements("x", 50)).unwrap();
+    db.pfadd(&b("dense"), &elements("y", 20_000)).unwrap();
+
+    for key in ["sparse", "dense"] {
+        let copy = format!("{}-copy", key);
+        db.set(&b(&copy), raw(&db, key), None);
+        assert_eq!(
+            db.pfcount(&[b(&copy)]).unwrap(),
+            db.pfcount(&[b(key)]).unwrap()
+        );
+    }
+
+    // A hand built Redis value reads back fine
+    db.set(&b("external"), Bytes::from(empty_sparse()), None);
+    assert!(db.pfadd(&b("external"), &[b("a")]).unwrap());
+    assert_eq!(db.pfcount(&[b("external")]).unwrap(), 1);
+}
+
+#[test]
+fn union_count_and_merge() {
+    let db = Db::new();
+    db.pfadd(&b("a"), &elements("shared", 500)).unwrap();
+    db.pfadd(&b("a"), &elements("a", 500)).unwrap();
+    db.pfadd(&b("b"), &elements("shared", 500)).unwrap();
+    db.pfadd(&b("b"), &elements("b", 500)).unwrap();
+
+    let union = db.pfcount(&[b("a"), b("b"), b("missing")]).unwrap();
+    assert!((union as f64 - 1500.0).abs() < 45.0, "{}", union);
+
+    db.pfmerge(&b("both"), &[b("a"), b("b")]).unwrap();
+    assert_eq!(db.pfcount(&[b("both")]).unwrap(), union);
+
+    // The destination counts as a source
+    db.pfmerge(&b("a"), &[b("b")]).unwrap();
+    assert_eq!(db.pfcount(&[b("a")]).unwrap(), union);
+}
+
+#[test]
+fn merge_goes_dense_with_any_dense_input() {
+    let db = Db::new();
+    db.pfadd(&b("small"), &elements("x", 10)).unwrap();
+    db.pfadd(&b("big"), &elements("y", 20_000)).unwrap();
+
+    db.pfmerge(&b("out"), &[b("small")]).unwrap();
+    assert_eq!(raw(&db, "out")[4], 1);
+    db.pfmerge(&b("out"), &[b("big")]).unwrap();
+    assert_eq!(raw(&db, "out")[4], 0);
+}
+
+#[test]
+fn pfcount_answers_from_a_valid_cache() {
+    let db = Db::new();
+    // Like Redis, the body isn't read while the header's cached count is valid
+    let mut hll = empty_sparse();
+    hll.truncate(HEADER_LEN);
+    hll.push(0x00);
+    hll[8] = 42;
+    db.set(&b("k"), Bytes::from(hll), None);
+    assert_eq!(db.pfcount(&[b("k")]).unwrap(), 42);
+}
+
+#[test]
+fn pfadd_splits_only_the_sparse_opcode_it_changes() {
+    let db = Db::new();
+    db.set(&b("k"), Bytes::from(empty_sparse()), None);
+    assert!(db.pfadd(&b("k"), &[b("a")]).unwrap());
+
+    // The XZERO covering every register becomes the zeros before it, a VAL and the zeros after
+    let hll = raw(&db, "k");
+    let body = &hll[HEADER_LEN..];
+    let (mut pos, mut registers, mut vals) = (0, 0, 0);
+    while pos < body.len() {
+        let op = body[pos];
+        if op & 0x80 != 0 {
+            registers += usize::from(op & 0x3) + 1;
+            vals += 1;
+            pos += 1;
+        } else if op & 0x40 != 0 {
+            registers += (usize::from(op & 0x3f) << 8 | usize::from(body[pos + 1])) + 1;
+            pos += 2;
+        } else {
+            registers += usize::from(op & 0x3f) + 1;
+            pos += 1;
+        }
+    }
+    assert_eq!(registers, 16384);
+    assert_eq!(vals, 1);
+    assert!(body.len() <= 5);
+    assert_eq!(db.pfcount(&[b("k")]).unwrap(), 1);
+
+    // The same element again leaves the value as it was
+    assert!(!db.pfadd(&b("k"), &[b("a")]).unwrap());
+    assert_eq!(raw(&db, "k")[HEADER_LEN..], hll[HEADER_LEN..]);
+}
+
+#[test]
+fn pfadd_promotes_a_sparse_value_that_cant_hold_a_register() {
+    let db = Db::new();
+    // Register 0 at 32, the most a sparse VAL can hold, then 16383 zeros
+    let mut hll = empty_sparse();
+    hll.truncate(HEADER_LEN);
+    hll[15] = 0x80;
+    hll.extend_from_slice(&[0xfc, 0x7f, 0xfe]);
+    db.set(&b("k"), Bytes::from(hll), None);
+    let before = db.pfcount(&[b("k")]).unwrap();
+
+    // One at a time, so each element goes through the in-place update
+    for element in elements("e", 3000) {
+        db.pfadd(&b("k"), &[element]).unwrap();
+    }
+    let hll = raw(&db, "k");
+    assert_eq!(hll[4], 0);
+    assert_eq!(hll.len(), 12304);
+    // Register 0 kept its value through the promotion
+    assert_eq!(hll[HEADER_LEN] & 0x3f, 32);
+    assert!(db.pfcount(&[b("k")]).unwrap() > before);
+
+    // The count matches a fresh encoding of the same registers
+    db.pfmerge(&b("copy"), &[b("k")]).unwrap();
+    assert_eq!(
+        db.pfcount(&[b("copy")]).unwrap(),
+        db.pfcount(&[b("k")]).unwrap()
+    );
+}
+
+#[test]
+fn rejects_non_hll_values() {
+    let db = Db::new();
+    db.set(&b("plain"), b("hello"), None);
+    assert!(db.pfadd(&b("plain"), &[b("a")]).is_err());
+    assert!(db.pfcount(&[b("plain")]).is_err());
+    assert!(db.pfmerge(&b("out"), &[b("plain")]).is_err());
+
+    // A sparse body that doesn't cover every register
+    let mut corrupt = empty_sparse();
+    corrupt.truncate(HEADER_LEN);
+    corrupt.push(0x00);
+    corrupt[15] = 0x80;
+    db.set(&b("corrupt"), Bytes::from(corrupt), None);
+    assert!(db.pfcount(&[b("corrupt")]).is_err());
+
+    db.sadd(&b("set"), vec![b("a")]).unwrap();
+    assert!(db.pfadd(&b("set"), &[b("a")]).is_err());
+}
+
+// === Parsing ===
+
+#[test]
+fn parse_hll_commands() {
+    assert!(matches!(
+        Command::from_frame(cmd_frame(&["PFADD", "k"])).unwrap(),
+        Command::PfAdd { elements, .. } if elements.is_empty()
+    ));
+    assert!(matches!(
+        Command::from_frame(cmd_frame(&["PFMERGE", "d"])).unwrap(),
+        Command::PfMerge { sources, .. } if sources.is_empty()
+    ));
+    assert!(Command::from_frame(cmd_frame(&["PFCOUNT"])).is_err());
+    assert!(Command::from_frame(cmd_frame(&["PFADD"])).is_err());
+}
+
+// === Integration ===
+
+#[tokio::test]
+async fn hyperloglog_over_tcp() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    tokio::spawn(async move { run_server(listener, Db::new()).await });
+
+    let client = redis::Client::open(format!("redis://127.0.0.1:{}", port)).unwrap();
+    let mut con = client.get_multiplexed_async_connection().await.unwrap();
+
+    let added: i64 = redis::cmd("PFADD")
+        .arg("page:home")
+        .arg(&["alice", "bob", "carol"])
+        .query_async(&mut con)
+        .await
+        .unwrap();
+    assert_eq!(added, 1);
+
+    let _: i64 = redis::cmd("PFADD")
+        .arg("page:about")
+        .arg(&["bob", "dave"])
+        .query_async(&mut con)
+        .await
+        .unwrap();
+
+    let _: () = redis::cmd("PFMERGE")
+        .arg("site")
+        .arg("page:home")
+        .arg("page:about")
+        .query_async(&mut con)
+        .await
+        .unwrap();
+
+    let count: i64 = redis::cmd("PFCOUNT")
+        .arg("site")
+        .query_async(&mut con)
+        .await
+        .unwrap();
+    assert_eq!(count, 4);
+
+    let value: Vec<u8> = redis::cmd("GET")
+        .arg("site")
+        .query_async(&mut con)
+        .await
+        .unwrap();
+    assert_eq!(&value[..4], b"HYLL");
+
+    let _: () = redis::cmd("SET")
+        .arg("plain")
+        .arg("x")
+        .query_async(&mut con)
+        .await
+        .unwrap();
+    let err = redis::cmd("PFCOUNT")
+        .arg("plain")
+        .query_async::<i64>(&mut con)
+        .await
+        .unwrap_err();
+    assert_eq!(err.code(), Some("WRONGTYPE"));
+
+    // HLLs from Redis can hold any byte, so SET has to carry them intact
+    let registers: Vec<u8> = (0..16384).map(|i| [13, 0, 1, 2, 10, 0, 3][i % 7]).collect();
+    for hll in [sparse_with_line_breaks(), dense(&registers)] {
+        assert!(hll.iter().any(|&b| b == b'\r' || b == b'\n'));
+        let db = Db::new();
+        db.set(&b("hll"), Bytes::from(hll.clone()), None);
+        let expected = db.pfcount(&[b("hll")]).unwrap() as i64;
+
+        let _: () = redis::cmd("SET")
+            .arg("from-redis")
+            .arg(&hll)
+            .query_async(&mut con)
+            .await
+            .unwrap();
+        let count: i64 = redis::cmd("PFCOUNT")
+            .arg("from-redis")
+            .query_async(&mut con)
+            .await
+            .unwrap();
+        assert_eq!(count, expected);
+    }
+    let count: i64 = redis::cmd("PFCOUNT")
+        .arg("from-redis")
+        .query_async(&mut con)
+        .await
+        .unwrap();
+    assert!(count > 16384, "{}", count);
+}
@@ -0,0 +1,402 @@
+use bytes::Bytes;
+use padis::db::{GeoFrom, GeoQuery, GeoShape, GeoSort, GeoUnit, ZAddFlags};
+use padis::{Command, Db, Frame, run_server};
+use tokio::net::TcpListener;
+
+fn b(s: &str) -> Bytes {
+    Bytes::copy_from_slice(s.as_bytes())
+}
+
+// The example data from Redis' geo documentation
+fn sicily() -> Db {
+    let db = Db::new();
+    db.geoadd(
+        &b("Sicily"),
+        ZAddFlags::default(),
+        vec![
+            (13.361389, 38.115556, b("Palermo")),
+            (15.087269, 37.502669, b("Catania")),
+            (12.758489, 38.788135, b("edge1")),
+            (17.241510, 38.788135, b("edge2")),
+        ],
+    )
+    .unwrap();
+    db
+}
+
+fn query(from: GeoFrom, shape: GeoShape, unit: GeoUnit) -> GeoQuery {
+    GeoQuery {
+        from,
+        shape,
+        unit,
+        sort: Some(GeoSort::Asc),
+        count: None,
+        any: false,
+    }
+}
+
+fn members(matches: &[padis::db::GeoMatch]) -> Vec<Bytes> {
+    matches.iter().map(|m| m.member.clone()).collect()
+}
+
+// Helper to build a command frame
+fn cmd_frame(args: &[&str]) -> Frame {
+    Frame::Array(
+        args.iter()
+            .map(|s| Frame::BulkString(Bytes::copy_from_slice(s.as_bytes())))
+            .collect(),
+    )
+}
+
+// === Db ===
+
+#[test]
+fn scores_are_52_bit_geohashes() {
+    let db = sicily();
+    assert_eq!(
+        db.zscore(&b("Sicily"), &b("Palermo")).unwrap(),
+        Some(3479099956230698.0)
+    );
+    assert_eq!(
+        db.zscore(&b("Sicily"), &b("Catania")).unwrap(),
+        Some(3479447370796909.0)
+    );
+}
+
+#[test]
+fn distances_match_redis() {
+    let db = sicily();
+    let dist = |unit| {
+        db.geodist(&b("Sicily"), &b("Palermo"), &b("Catania"), unit)
+            .unwrap()
+            .map(|d| format!("{:.4}", d))
+    };
+    assert_eq!(dist(GeoUnit::Meters).as_deref(), Some("166274.1516"));
+    assert_eq!(dist(GeoUnit::Kilometers).as_deref(), Some("166.2742"));
+    assert_eq!(dist(GeoUnit::Miles).as_deref(), Some("103.3182"));
+
+    let missing = db
+        .geodist(&b("Sicily"), &b("Palermo"), &b("Rome"), GeoUnit::Meters)
+        .unwrap();
+    assert_eq!(missing, None);
+}
+
+#[test]
+fn positions_and_hashes() {
+    let db = sicily();
+    let pos = db.geopos(&b("Sicily"), &[b("Palermo"), b("Rome")]).unwrap();
+    let (lon, lat) = pos[0].unwrap();
+    assert_eq!(format!("{:.17}", lon), "13.36138933897018433");
+    assert_eq!(format!("{:.17}", lat), "38.11555639549629859");
+    assert_eq!(pos[1], None);
+
+    let hashes = db
+        .geohash(&b("Sicily"), &[b("Palermo"), b("Catania")])
+        .unwrap();
+    assert_eq!(
+        hashes,
+        vec![
+            Some("sqc8b49rny0".to_string()),
+            Some("sqdtr74hyu0".to_string())
+        ]
+    );
+}
+
+#[test]
+fn search_by_radius() {
+    let db = sicily();
+    let q = query(
+        GeoFrom::LonLat(15.0, 37.0),
+        GeoShape::Radius(200.0),
+        GeoUnit::Kilometers,
+    );
+    let matches = db.geosearch(&b("Sicily"), &q).unwrap();
+    assert_eq!(members(&matches), vec![b("Catania"), b("Palermo")]);
+    assert_eq!(format!("{:.4}", matches[0].dist), "56.4413");
+    assert_eq!(format!("{:.4}", matches[1].dist), "190.4424");
+    assert_eq!(matches[1].hash, 3479099956230698);
+}
+
+#[test]
+fn search_by_box() {
+    let db = sicily();
+    let q = query(
+        GeoFrom::LonLat(15.0, 37.0),
+        GeoShape::Box {
+            width: 400.0,
+            height: 400.0,
+        },
+        GeoUnit::Kilometers,
+    );
+    let matches = db.geosearch(&b("Sicily"), &q).unwrap();
+    assert_eq!(
+        members(&matches),
+        vec![b("Catania"), b("Palermo"), b("edge2"), b("edge1")]
+    );
+    assert_eq!(format!("{:.4}", matches[2].dist), "279.7403");
+    assert_eq!(format!("{:.4}", matches[3].dist), "279.7405");
+}
+
+#[test]
+fn search_from_member_with_count() {
+    let db = sicily();
+    let mut q = query(
+        GeoFrom::Member(b("Palermo")),
+        GeoShape::Radius(500.0),
+        GeoUnit::Kilometers,
+    );
+    q.sort = None;
+    q.count = Some(2);
+    // COUNT without ANY returns the nearest
+    let matches = db.geosearch(&b("Sicily"), &q).unwrap();
+    assert_eq!(members(&matches), vec![b("Palermo"), b("edge1")]);
+
+    q.sort = Some(GeoSort::Desc);
+    let matches = db.geosearch(&b("Sicily"), &q).unwrap();
+    assert_eq!(members(&matches), vec![b("edge2"), b("Catania")]);
+
+    q.any = true;
+    assert_eq!(db.geosearch(&b("Sicily"), &q).unwrap().len(), 2);
+
+    q.from = GeoFrom::Member(b("Rome"));
+    assert!(db.geosearch(&b("Sicily"), &q).is_err());
+    assert!(db.geosearch(&b("nowhere"), &q).unwrap().is_empty());
+}
+
+#[test]
+fn search_store() {
+    let db = sicily();
+    let q = query(
+        GeoFrom::LonLat(15.0, 37.0),
+        GeoShape::Radius(200.0),
+        GeoUnit::Kilometers,
+    );
+    assert_eq!(
+        db.geosearchstore(&b("near"), &b("Sicily"), &q, false)
+            .unwrap(),
+        2
+    );
+    assert_eq!(
+        db.zscore(&b("near"), &b("Palermo")).unwrap(),
+        Some(3479099956230698.0)
+    );
+
+    db.geosearchstore(&b("dists"), &b("Sicily"), &q, true)
+        .unwrap();
+    let dist = db.zscore(&b("dists"), &b("Catania")).unwrap().unwrap();
+    assert!((dist - 56.4413).abs() < 0.001);
+}
+
+#[test]
+fn search_finds_points_across_cells() {
+    // Points all around a center near a cell edge should still be found
+    let db = Db::new();
+    let mut points = Vec::new();
+    for i in 0..360 {
+        let angle = (i as f64).to_radians();
+        let name = format!("p{}", i);
+        points.push((
+            0.0001 + 0.05 * angle.cos(),
+            0.0001 + 0.05 * angle.sin(),
+            b(&name),
+        ));
+    }
+    db.geoadd(&b("ring"), ZAddFlags::default(), points).unwrap();
+
+    let q = query(
+        GeoFrom::LonLat(0.0001, 0.0001),
+        GeoShape::Radius(10.0),
+        GeoUnit::Kilometers,
+    );
+    assert_eq!(db.geosearch(&b("ring"), &q).unwrap().len(), 360);
+
+    let q = query(
+        GeoFrom::LonLat(0.0001, 0.0001),
+        GeoShape::Radius(1.0),
+        GeoUnit::Kilometers,
+    );
+    assert!(db.geosearch(&b("ring"), &q).unwrap().is_empty());
+}
+
+#[test]
+fn rejects_out_of_range_coordinates() {
+    let db = Db::new();
+    let err = db
+        .geoadd(
+            &b("k"),
+            ZAddFlags::default(),
+            vec![(1.0, 100.0, b("north pole"))],
+        )
+        .unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "invalid longitude,latitude pair 1.000000,100.000000"
+    );
+    assert!(db.keys().is_empty());
+}
+
+// === Parsing ===
+
+#[test]
+fn parse_geosearch() {
+    let frame = cmd_frame(&[
+        "GEOSEARCH",
+        "k",
+        "FROMLONLAT",
+        "15",
+        "37",
+        "BYBOX",
+        "400",
+        "400",
+        "km",
+        "DESC",
+        "COUNT",
+        "3",
+        "ANY",
+        "WITHCOORD",
+        "WITHDIST",
+    ]);
+    let cmd = Command::from_frame(frame).unwrap();
+    assert!(matches!(
+        cmd,
+        Command::GeoSearch {
+            query: GeoQuery {
+                shape: GeoShape::Box { .. },
+                unit: GeoUnit::Kilometers,
+                sort: Some(GeoSort::Desc),
+                count: Some(3),
+                any: true,
+                ..
+            },
+            with_coord: true,
+            with_dist: true,
+            with_hash: false,
+            ..
+        }
+    ));
+}
+
+#[test]
+fn parse_rejects_bad_geo() {
+    let bad = [
+        vec!["GEOADD", "k", "1", "2"],
+        vec!["GEOADD", "k", "NX", "XX", "1", "2", "m"],
+        vec!["GEODIST", "k", "a", "b", "yards"],
+        vec!["GEOSEARCH", "k", "BYRADIUS", "1", "m"],
+        vec![
+            "GEOSEARCH",
+            "k",
+            "FROMMEMBER",
+            "a",
+            "FROMLONLAT",
+            "1",
+            "2",
+            "BYRADIUS",
+            "1",
+            "m",
+        ],
+        vec!["GEOSEARCH", "k", "FROMMEMBER", "a", "BYRADIUS", "-1", "m"],
+        vec![
+            "GEOSEARCH",
+            "k",
+            "FROMMEMBER",
+            "a",
+            "BYRADIUS",
+            "1",
+            "m",
+            "COUNT",
+            "0",
+        ],
+        vec![
+            "GEOSEARCHSTORE",
+            "d",
+            "k",
+            "FROMMEMBER",
+            "a",
+            "BYRADIUS",
+            "1",
+            "m",
+            "WITHDIST",
+        ],
+        vec![
+            "GEOSEARCH",
+            "k",
+            "FROMMEMBER",
+            "a",
+            "BYRADIUS",
+            "1",
+            "m",
+            "STOREDIST",
+        ],
+    ];
+    for args in bad {
+        assert!(Command::from_frame(cmd_frame(&args)).is_err(), "{:?}", args);
+    }
+}
+
+// === Integration ===
+
+#[tokio::test]
+async fn geo_over_tcp() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    tokio::spawn(async move { run_server(listener, Db::new()).await });
+
+    let client = redis::Client::open(format!("redis://127.0.0.1:{}", port)).unwrap();
+    let mut con = client.get_multiplexed_async_connection().await.unwrap();
+
+    let added: i64 = redis::cmd("GEOADD")
+        .arg("Sicily")
+        .arg(&["13.361389", "38.115556", "Palermo"])
+        .arg(&["15.087269", "37.502669", "Catania"])
+        .query_async(&mut con)
+        .await
+        .unwrap();
+    assert_eq!(added, 2);
+
+    let dist: String = redis::cmd("GEODIST")
+        .arg("Sicily")
+        .arg("Palermo")
+        .arg("Catania")
+        .arg("km")
+        .query_async(&mut con)
+        .await
+        .unwrap();
+    assert_eq!(dist, "166.2742");
+
+    let pos: Vec<Option<(String, String)>> = redis::cmd("GEOPOS")
+        .arg("Sicily")
+        .arg("Palermo")
+        .arg("Nowhere")
+        .query_async(&mut con)
+        .await
+        .unwrap();
+    assert_eq!(
+        pos,
+        vec![
+            Some(("13.36138933897018433".into(), "38.11555639549629859".into())),
+            None
+        ]
+    );
+
+    let found: Vec<(String, String, i64, (String, String))> = redis::cmd("GEOSEARCH")
+        .arg("Sicily")
+        .arg("FROMLONLAT")
+        .arg(15)
+        .arg(37)
+        .arg("BYRADIUS")
+        .arg(200)
+        .arg("km")
+        .arg("ASC")
+        .arg("WITHCOORD")
+        .arg("WITHDIST")
+        .arg("WITHHASH")
+        .query_async(&mut con)
+        .await
+        .unwrap();
+    assert_eq!(found[0].0, "Catania");
+    assert_eq!(found[0].1, "56.4413");
+    assert_eq!(found[0].2, 3479447370796909);
+    assert_eq!(found[0].3.0, "15.08726745843887329");
+    assert_eq!(found[1].0, "Palermo");
+}
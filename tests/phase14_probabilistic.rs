@@ -0,0 +1,429 @@
+use bytes::Bytes;
+use padis::db::{BloomInfoField, CuckooOptions};
+use padis::{Command, Db, Frame, run_server};
+use tokio::net::TcpListener;
+
+fn b(s: &str) -> Bytes {
+    Bytes::copy_from_slice(s.as_bytes())
+}
+
+fn items(prefix: &str, n: usize) -> Vec<Bytes> {
+    (0..n).map(|i| b(&format!("{}{}", prefix, i))).collect()
+}
+
+// Helper to build a command frame
+fn cmd_frame(args: &[&str]) -> Frame {
+    Frame::Array(
+        args.iter()
+            .map(|s| Frame::BulkString(Bytes::copy_from_slice(s.as_bytes())))
+            .collect(),
+    )
+}
+
+// === Db ===
+
+#[test]
+fn bloom_has_no_false_negatives() {
+    let db = Db::new();
+    db.bf_reserve(&b("bf"), 0.01, 1000, Some(2)).unwrap();
+    let added = db.bf_add(&b("bf"), &items("url", 1000)).unwrap();
+    assert!(added.iter().all(|a| a.is_ok()));
+
+    let found = db.bf_exists(&b("bf"), &items("url", 1000)).unwrap();
+    assert!(found.iter().all(|&f| f));
+
+    // Items never added are only occasionally reported, at about the error rate
+    let false_positives = db
+        .bf_exists(&b("bf"), &items("other", 10_000))
+        .unwrap()
+        .into_iter()
+        .filter(|&f| f)
+        .count();
+    assert!(false_positives < 200, "{}", false_positives);
+}
+
+#[test]
+fn bloom_add_reports_new_items() {
+    let db = Db::new();
+    let added = db.bf_add(&b("bf"), &[b("a"), b("b"), b("a")]).unwrap();
+    assert_eq!(
+        added.into_iter().map(Result::unwrap).collect::<Vec<_>>(),
+        vec![true, true, false]
+    );
+    assert_eq!(
+        db.bf_exists(&b("bf"), &[b("a"), b("c")]).unwrap(),
+        vec![true, false]
+    );
+    assert_eq!(db.bf_exists(&b("missing"), &[b("a")]).unwrap(), vec![false]);
+
+    // Created with the defaults
+    let info = db.bf_info(&b("bf")).unwrap();
+    assert_eq!(info.capacity, 100);
+    assert_eq!(info.filters, 1);
+    assert_eq!(info.items, 2);
+    assert_eq!(info.expansion, Some(2));
+}
+
+#[test]
+fn bloom_scales_with_sub_filters() {
+    let db = Db::new();
+    db.bf_reserve(&b("bf"), 0.01, 100, Some(2)).unwrap();
+    db.bf_add(&b("bf"), &items("x", 350)).unwrap();
+
+    let info = db.bf_info(&b("bf")).unwrap();
+    assert_eq!(info.filters, 3);
+    assert_eq!(info.capacity, 100 + 200 + 400);
+    assert!(info.items <= 350 && info.items > 340, "{}", info.items);
+    let found = db.bf_exists(&b("bf"), &items("x", 350)).unwrap();
+    assert!(found.iter().all(|&f| f));
+}
+
+#[test]
+fn non_scaling_bloom_fills_up() {
+    let db = Db::new();
+    db.bf_reserve(&b("bf"), 0.001, 10, None).unwrap();
+    let added = db.bf_add(&b("bf"), &items("x", 20)).unwrap();
+    assert!(added[..10].iter().all(|a| matches!(a, Ok(true))));
+    assert!(added[10..].iter().any(|a| a.is_err()));
+
+    let info = db.bf_info(&b("bf")).unwrap();
+    assert_eq!(info.filters, 1);
+    assert_eq!(info.items, 10);
+    assert_eq!(info.expansion, None);
+}
+
+#[test]
+fn bloom_errors() {
+    let db = Db::new();
+    db.bf_reserve(&b("bf"), 0.01, 100, Some(2)).unwrap();
+    assert_eq!(
+        db.bf_reserve(&b("bf"), 0.01, 100, Some(2))
+            .unwrap_err()
+            .to_string(),
+        "item exists"
+    );
+    assert_eq!(
+        db.bf_info(&b("missing")).unwrap_err().to_string(),
+        "not found"
+    );
+
+    db.set(&b("plain"), b("v"), None);
+    assert!(db.bf_add(&b("plain"), &[b("a")]).is_err());
+    assert!(db.bf_exists(&b("plain"), &[b("a")]).is_err());
+    assert!(db.bf_reserve(&b("plain"), 0.01, 100, None).is_err());
+    assert!(db.cf_add(&b("bf"), &b("a"), false).is_err());
+}
+
+#[test]
+fn oversized_filters_are_refused() {
+    let db = Db::new();
+    let too_big = "could not create filter, it would exceed the maximum size";
+    for capacity in [1 << 30, u64::MAX] {
+        assert_eq!(
+            db.bf_reserve(&b("bf"), 1e-9, capacity, Some(2))
+                .unwrap_err()
+                .to_string(),
+            too_big
+        );
+        let options = CuckooOptions {
+            capacity: capacity << 1,
+            ..Default::default()
+        };
+        assert_eq!(
+            db.cf_reserve(&b("cf"), options).unwrap_err().to_string(),
+            too_big
+        );
+    }
+    assert!(db.keys().is_empty());
+
+    // A sub-filter too big to add is an error for that item, not for the filter
+    db.bf_reserve(&b("bf"), 0.01, 1, Some(u64::MAX)).unwrap();
+    let added = db.bf_add(&b("bf"), &[b("a"), b("b")]).unwrap();
+    assert!(matches!(added[0], Ok(true)));
+    assert_eq!(added[1].as_ref().unwrap_err().to_string(), too_big);
+    assert_eq!(db.bf_info(&b("bf")).unwrap().filters, 1);
+}
+
+#[test]
+fn cuckoo_counts_and_deletes() {
+    let db = Db::new();
+    assert!(db.cf_add(&b("cf"), &b("a"), false).unwrap());
+    assert!(db.cf_add(&b("cf"), &b("a"), false).unwrap());
+    assert!(!db.cf_add(&b("cf"), &b("a"), true).unwrap());
+    assert!(db.cf_add(&b("cf"), &b("b"), true).unwrap());
+    assert_eq!(db.cf_count(&b("cf"), &b("a")).unwrap(), 2);
+
+    assert!(db.cf_del(&b("cf"), &b("a")).unwrap());
+    assert_eq!(db.cf_count(&b("cf"), &b("a")).unwrap(), 1);
+    assert!(db.cf_del(&b("cf"), &b("a")).unwrap());
+    assert!(!db.cf_del(&b("cf"), &b("a")).unwrap());
+    assert_eq!(
+        db.cf_exists(&b("cf"), &[b("a"), b("b")]).unwrap(),
+        vec![false, true]
+    );
+
+    // Emptying the filter keeps the key
+    assert!(db.cf_del(&b("cf"), &b("b")).unwrap());
+    assert_eq!(db.cf_exists(&b("cf"), &[b("b")]).unwrap(), vec![false]);
+    assert!(db.cf_reserve(&b("cf"), CuckooOptions::default()).is_err());
+
+    assert_eq!(db.cf_count(&b("missing"), &b("a")).unwrap(), 0);
+    assert_eq!(
+        db.cf_del(&b("missing"), &b("a")).unwrap_err().to_string(),
+        "not found"
+    );
+}
+
+#[test]
+fn cuckoo_expands_when_full() {
+    let db = Db::new();
+    let options = CuckooOptions {
+        capacity: 8,
+        bucket_size: 2,
+        max_iterations: 20,
+        expansion: 2,
+    };
+    db.cf_reserve(&b("cf"), options).unwrap();
+    for item in items("x", 200) {
+        db.cf_add(&b("cf"), &item, false).unwrap();
+    }
+    let found = db.cf_exists(&b("cf"), &items("x", 200)).unwrap();
+    assert!(found.iter().all(|&f| f));
+}
+
+#[test]
+fn full_cuckoo_keeps_its_items() {
+    let db = Db::new();
+    let options = CuckooOptions {
+        capacity: 8,
+        bucket_size: 2,
+        max_iterations: 20,
+        expansion: 0,
+    };
+    db.cf_reserve(&b("cf"), options).unwrap();
+
+    let mut added = Vec::new();
+    for item in items("x", 50) {
+        match db.cf_add(&b("cf"), &item, false) {
+            Ok(_) => added.push(item),
+            Err(e) => assert_eq!(e.to_string(), "Filter is full"),
+        }
+    }
+    assert!(added.len() >= 4 && added.len() <= 8, "{}", added.len());
+    // Kicks that didn't find room were undone
+    let found = db.cf_exists(&b("cf"), &added).unwrap();
+    assert!(found.iter().all(|&f| f));
+}
+
+// === Parsing ===
+
+#[test]
+fn parse_bloom_commands() {
+    assert!(matches!(
+        Command::from_frame(cmd_frame(&["BF.RESERVE", "k", "0.001", "500"])).unwrap(),
+        Command::BfReserve {
+            capacity: 500,
+            expansion: Some(2),
+            ..
+        }
+    ));
+    assert!(matches!(
+        Command::from_frame(cmd_frame(&[
+            "bf.reserve",
+            "k",
+            "0.1",
+            "10",
+            "EXPANSION",
+            "4"
+        ]))
+        .unwrap(),
+        Command::BfReserve {
+            expansion: Some(4),
+            ..
+        }
+    ));
+    assert!(matches!(
+        Command::from_frame(cmd_frame(&["BF.RESERVE", "k", "0.1", "10", "NONSCALING"])).unwrap(),
+        Command::BfReserve {
+            expansion: None,
+            ..
+        }
+    ));
+    assert!(matches!(
+        Command::from_frame(cmd_frame(&["BF.INFO", "k", "items"])).unwrap(),
+        Command::BfInfo {
+            field: Some(BloomInfoField::Items),
+            ..
+        }
+    ));
+
+    for bad in [
+        &["BF.RESERVE", "k", "1", "10"][..],
+        &["BF.RESERVE", "k", "0", "10"],
+        &["BF.RESERVE", "k", "0.1", "0"],
+        &["BF.RESERVE", "k", "0.1", "10", "EXPANSION", "0"],
+        &["BF.RESERVE", "k", "0.1", "1000000000000000"],
+        &["BF.RESERVE", "k", "0.1", "10", "EXPANSION", "40000"],
+        &[
+            "BF.RESERVE",
+            "k",
+            "0.1",
+            "10",
+            "EXPANSION",
+            "2",
+            "NONSCALING",
+        ],
+        &["BF.INFO", "k", "BOGUS"],
+        &["BF.ADD", "k"],
+        &["BF.ADD", "k", "a", "b"],
+        &["BF.MADD", "k"],
+        &["BF.MEXISTS", "k"],
+    ] {
+        assert!(Command::from_frame(cmd_frame(bad)).is_err(), "{:?}", bad);
+    }
+}
+
+#[test]
+fn parse_cuckoo_commands() {
+    assert!(matches!(
+        Command::from_frame(cmd_frame(&[
+            "CF.RESERVE",
+            "k",
+            "1000",
+            "BUCKETSIZE",
+            "4",
+            "MAXITERATIONS",
+            "50",
+            "EXPANSION",
+            "0"
+        ]))
+        .unwrap(),
+        Command::CfReserve {
+            options: CuckooOptions {
+                capacity: 1000,
+                bucket_size: 4,
+                max_iterations: 50,
+                expansion: 0
+            },
+            ..
+        }
+    ));
+    assert!(matches!(
+        Command::from_frame(cmd_frame(&["CF.ADDNX", "k", "a"])).unwrap(),
+        Command::CfAdd { nx: true, .. }
+    ));
+
+    for bad in [
+        &["CF.RESERVE", "k", "0"][..],
+        &["CF.RESERVE", "k", "10", "BUCKETSIZE", "0"],
+        &["CF.RESERVE", "k", "10", "BUCKETSIZE", "256"],
+        &["CF.RESERVE", "k", "10", "EXPANSION", "40000"],
+        &["CF.RESERVE", "k", "1000000000000000"],
+        &["CF.DEL", "k"],
+        &["CF.COUNT", "k", "a", "b"],
+        &["CF.MEXISTS", "k"],
+    ] {
+        assert!(Command::from_frame(cmd_frame(bad)).is_err(), "{:?}", bad);
+    }
+}
+
+// === Integration ===
+
+#[tokio::test]
+async fn filters_over_tcp() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    tokio::spawn(async move { run_server(listener, Db::new()).await });
+
+    let client = redis::Client::open(format!("redis://127.0.0.1:{}", port)).unwrap();
+    let mut con = client.get_multiplexed_async_connection().await.unwrap();
+
+    let _: () = redis::cmd("BF.RESERVE")
+        .arg("seen")
+        .arg(0.001)
+        .arg(1000)
+        .query_async(&mut con)
+        .await
+        .unwrap();
+    let added: Vec<i64> = redis::cmd("BF.MADD")
+        .arg("seen")
+        .arg(&["https://a", "https://b", "https://a"])
+        .query_async(&mut con)
+        .await
+        .unwrap();
+    assert_eq!(added, vec![1, 1, 0]);
+    let exists: i64 = redis::cmd("BF.EXISTS")
+        .arg("seen")
+        .arg("https://b")
+        .query_async(&mut con)
+        .await
+        .unwrap();
+    assert_eq!(exists, 1);
+
+    let info: Vec<redis::Value> = redis::cmd("BF.INFO")
+        .arg("seen")
+        .query_async(&mut con)
+        .await
+        .unwrap();
+    assert_eq!(info.len(), 10);
+    let items: Vec<i64> = redis::cmd("BF.INFO")
+        .arg("seen")
+        .arg("ITEMS")
+        .query_async(&mut con)
+        .await
+        .unwrap();
+    assert_eq!(items, vec![2]);
+
+    let err = redis::cmd("BF.RESERVE")
+        .arg("seen")
+        .arg(0.01)
+        .arg(10)
+        .query_async::<()>(&mut con)
+        .await
+        .unwrap_err();
+    assert!(err.to_string().contains("exists"), "{}", err);
+
+    let _: i64 = redis::cmd("CF.ADD")
+        .arg("sessions")
+        .arg("s1")
+        .query_async(&mut con)
+        .await
+        .unwrap();
+    let deleted: i64 = redis::cmd("CF.DEL")
+        .arg("sessions")
+        .arg("s1")
+        .query_async(&mut con)
+        .await
+        .unwrap();
+    assert_eq!(deleted, 1);
+    let count: i64 = redis::cmd("CF.COUNT")
+        .arg("sessions")
+        .arg("s1")
+        .query_async(&mut con)
+        .await
+        .unwrap();
+    assert_eq!(count, 0);
+
+    // Sizes too big to allocate are refused and the connection carries on
+    let err = redis::cmd("BF.RESERVE")
+        .arg("huge")
+        .arg(0.01)
+        .arg(1_000_000_000_000_000u64)
+        .query_async::<()>(&mut con)
+        .await
+        .unwrap_err();
+    assert!(err.to_string().contains("capacity"), "{}", err);
+    let err = redis::cmd("CF.RESERVE")
+        .arg("huge")
+        .arg(1_000_000_000_000_000u64)
+        .query_async::<()>(&mut con)
+        .await
+        .unwrap_err();
+    assert!(err.to_string().contains("capacity"), "{}", err);
+    let huge: Option<String> = redis::cmd("GET")
+        .arg("huge")
+        .query_async(&mut con)
+        .await
+        .unwrap();
+    assert!(huge.is_none());
+}
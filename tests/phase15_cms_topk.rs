@@ -0,0 +1,352 @@
+use bytes::Bytes;
+use padis::db::TopKOptions;
+use padis::{Command, Db, Frame, run_server};
+use tokio::net::TcpListener;
+
+fn b(s: &str) -> Bytes {
+    Bytes::copy_from_slice(s.as_bytes())
+}
+
+// Helper to build a command frame
+fn cmd_frame(args: &[&str]) -> Frame {
+    Frame::Array(
+        args.iter()
+            .map(|s| Frame::BulkString(Bytes::copy_from_slice(s.as_bytes())))
+            .collect(),
+    )
+}
+
+// === Db ===
+
+#[test]
+fn cms_counts_never_underestimate() {
+    let db = Db::new();
+    db.cms_init(&b("cms"), 2000, 5).unwrap();
+
+    let mut items = Vec::new();
+    for i in 0..500u64 {
+        items.push((b(&format!("key{}", i)), i % 7 + 1));
+    }
+    db.cms_incrby(&b("cms"), &items).unwrap();
+
+    let keys: Vec<Bytes> = items.iter().map(|(item, _)| item.clone()).collect();
+    let counts = db.cms_query(&b("cms"), &keys).unwrap();
+    let mut exact = 0;
+    for ((_, expected), count) in items.iter().zip(&counts) {
+        assert!(count >= expected);
+        exact += (count == expected) as usize;
+    }
+    assert!(exact > 450, "{}", exact);
+
+    let info = db.cms_info(&b("cms")).unwrap();
+    assert_eq!((info.width, info.depth), (2000, 5));
+    assert_eq!(info.count, items.iter().map(|(_, n)| n).sum::<u64>());
+}
+
+#[test]
+fn cms_incrby_returns_new_counts() {
+    let db = Db::new();
+    db.cms_init(&b("cms"), 100, 4).unwrap();
+    assert_eq!(
+        db.cms_incrby(&b("cms"), &[(b("a"), 5), (b("b"), 2), (b("a"), 1)])
+            .unwrap(),
+        vec![5, 2, 6]
+    );
+    assert_eq!(
+        db.cms_query(&b("cms"), &[b("a"), b("missing")]).unwrap(),
+        vec![6, 0]
+    );
+}
+
+#[test]
+fn cms_merge_weights_sources() {
+    let db = Db::new();
+    for key in ["a", "b", "dest"] {
+        db.cms_init(&b(key), 100, 4).unwrap();
+    }
+    db.cms_incrby(&b("a"), &[(b("x"), 3)]).unwrap();
+    db.cms_incrby(&b("b"), &[(b("x"), 1), (b("y"), 4)]).unwrap();
+    db.cms_incrby(&b("dest"), &[(b("z"), 9)]).unwrap();
+
+    db.cms_merge(&b("dest"), &[(b("a"), 1), (b("b"), 2)])
+        .unwrap();
+    assert_eq!(
+        db.cms_query(&b("dest"), &[b("x"), b("y"), b("z")]).unwrap(),
+        vec![5, 8, 0]
+    );
+    assert_eq!(db.cms_info(&b("dest")).unwrap().count, 3 + 2 * 5);
+
+    db.cms_init(&b("small"), 10, 4).unwrap();
+    assert_eq!(
+        db.cms_merge(&b("dest"), &[(b("small"), 1)])
+            .unwrap_err()
+            .to_string(),
+        "CMS: width/depth is not equal"
+    );
+    assert!(db.cms_merge(&b("nope"), &[(b("a"), 1)]).is_err());
+    assert!(db.cms_merge(&b("dest"), &[(b("nope"), 1)]).is_err());
+}
+
+#[test]
+fn cms_errors() {
+    let db = Db::new();
+    assert_eq!(
+        db.cms_query(&b("missing"), &[b("a")])
+            .unwrap_err()
+            .to_string(),
+        "CMS: key does not exist"
+    );
+    db.cms_init(&b("cms"), 10, 2).unwrap();
+    assert!(db.cms_init(&b("cms"), 10, 2).is_err());
+    db.set(&b("plain"), b("v"), None);
+    assert!(db.cms_incrby(&b("plain"), &[(b("a"), 1)]).is_err());
+
+    // Sizes that overflow or are too big to allocate
+    for (width, depth) in [(1 << 32, (1 << 32) + 1), (1 << 20, 1 << 20)] {
+        assert_eq!(
+            db.cms_init(&b("huge"), width, depth)
+                .unwrap_err()
+                .to_string(),
+            "CMS: width * depth is too large"
+        );
+    }
+    assert!(db.cms_query(&b("huge"), &[b("a")]).is_err());
+}
+
+#[test]
+fn topk_finds_heavy_hitters() {
+    let db = Db::new();
+    db.topk_reserve(&b("top"), TopKOptions::new(3)).unwrap();
+
+    // Three heavy keys among a long tail of light ones
+    let mut items = Vec::new();
+    for round in 0..50 {
+        for heavy in ["alpha", "beta", "gamma"] {
+            items.push((b(heavy), 1));
+        }
+        items.push((b(&format!("light{}", round)), 1));
+    }
+    db.topk_incrby(&b("top"), &items).unwrap();
+
+    let mut top: Vec<Bytes> = db
+        .topk_list(&b("top"))
+        .unwrap()
+        .into_iter()
+        .map(|(item, _)| item)
+        .collect();
+    top.sort();
+    assert_eq!(top, vec![b("alpha"), b("beta"), b("gamma")]);
+    assert_eq!(
+        db.topk_query(&b("top"), &[b("alpha"), b("light1")])
+            .unwrap(),
+        vec![true, false]
+    );
+}
+
+#[test]
+fn topk_reports_expelled_items() {
+    let db = Db::new();
+    let options = TopKOptions {
+        k: 1,
+        width: 50,
+        depth: 4,
+        decay: 0.9,
+    };
+    db.topk_reserve(&b("top"), options).unwrap();
+
+    assert_eq!(
+        db.topk_incrby(&b("top"), &[(b("a"), 2)]).unwrap(),
+        vec![None]
+    );
+    // Not heavy enough to take a's place
+    assert_eq!(
+        db.topk_incrby(&b("top"), &[(b("b"), 1)]).unwrap(),
+        vec![None]
+    );
+    assert_eq!(
+        db.topk_incrby(&b("top"), &[(b("b"), 5)]).unwrap(),
+        vec![Some(b("a"))]
+    );
+    assert_eq!(db.topk_list(&b("top")).unwrap(), vec![(b("b"), 6)]);
+    assert_eq!(db.topk_info(&b("top")).unwrap(), options);
+}
+
+#[test]
+fn topk_errors() {
+    let db = Db::new();
+    assert_eq!(
+        db.topk_list(&b("missing")).unwrap_err().to_string(),
+        "TopK: key does not exist"
+    );
+    db.topk_reserve(&b("top"), TopKOptions::new(2)).unwrap();
+    assert!(db.topk_reserve(&b("top"), TopKOptions::new(2)).is_err());
+    assert!(db.cms_query(&b("top"), &[b("a")]).is_err());
+
+    for options in [
+        TopKOptions::new(1 << 40),
+        TopKOptions {
+            width: usize::MAX,
+            depth: 2,
+            ..TopKOptions::new(2)
+        },
+    ] {
+        assert_eq!(
+            db.topk_reserve(&b("huge"), options)
+                .unwrap_err()
+                .to_string(),
+            "TopK: sketch is too large"
+        );
+    }
+}
+
+// === Parsing ===
+
+#[test]
+fn parse_cms_commands() {
+    assert!(matches!(
+        Command::from_frame(cmd_frame(&["CMS.INITBYPROB", "k", "0.001", "0.01"])).unwrap(),
+        Command::CmsInit {
+            width: 2000,
+            depth: 7,
+            ..
+        }
+    ));
+    assert!(matches!(
+        Command::from_frame(cmd_frame(&[
+            "CMS.MERGE", "d", "2", "a", "b", "WEIGHTS", "3", "-1"
+        ]))
+        .unwrap(),
+        Command::CmsMerge { sources, .. } if sources == vec![(b("a"), 3), (b("b"), -1)]
+    ));
+
+    for bad in [
+        &["CMS.INITBYDIM", "k", "0", "5"][..],
+        &["CMS.INITBYPROB", "k", "1", "0.1"],
+        &["CMS.INITBYDIM", "k", "4294967296", "4294967297"],
+        &["CMS.INITBYDIM", "k", "1000000", "1000"],
+        &["CMS.INITBYPROB", "k", "1e-300", "0.1"],
+        &["CMS.INCRBY", "k", "a"],
+        &["CMS.INCRBY", "k", "a", "-1"],
+        &["CMS.MERGE", "d", "0"],
+        &["CMS.MERGE", "d", "3", "a", "b"],
+        &["CMS.MERGE", "d", "2", "a", "b", "WEIGHTS", "1"],
+        &["CMS.QUERY", "k"],
+    ] {
+        assert!(Command::from_frame(cmd_frame(bad)).is_err(), "{:?}", bad);
+    }
+}
+
+#[test]
+fn parse_topk_commands() {
+    assert!(matches!(
+        Command::from_frame(cmd_frame(&["TOPK.RESERVE", "k", "10"])).unwrap(),
+        Command::TopKReserve { options, .. } if options == TopKOptions::new(10)
+    ));
+    assert!(matches!(
+        Command::from_frame(cmd_frame(&["TOPK.ADD", "k", "a", "b"])).unwrap(),
+        Command::TopKIncrBy { items, .. } if items == vec![(b("a"), 1), (b("b"), 1)]
+    ));
+    assert!(matches!(
+        Command::from_frame(cmd_frame(&["TOPK.LIST", "k", "withcount"])).unwrap(),
+        Command::TopKList {
+            with_count: true,
+            ..
+        }
+    ));
+
+    for bad in [
+        &["TOPK.RESERVE", "k", "0"][..],
+        &["TOPK.RESERVE", "k", "10", "8"],
+        &["TOPK.RESERVE", "k", "10", "8", "7", "1.5"],
+        &["TOPK.RESERVE", "k", "1000000000000000"],
+        &["TOPK.RESERVE", "k", "10", "4294967296", "4294967297", "0.9"],
+        &["TOPK.INCRBY", "k", "a", "0"],
+        &["TOPK.INCRBY", "k", "a", "100001"],
+        &["TOPK.ADD", "k"],
+    ] {
+        assert!(Command::from_frame(cmd_frame(bad)).is_err(), "{:?}", bad);
+    }
+}
+
+// === Integration ===
+
+#[tokio::test]
+async fn sketches_over_tcp() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    tokio::spawn(async move { run_server(listener, Db::new()).await });
+
+    let client = redis::Client::open(format!("redis://127.0.0.1:{}", port)).unwrap();
+    let mut con = client.get_multiplexed_async_connection().await.unwrap();
+
+    let _: () = redis::cmd("CMS.INITBYDIM")
+        .arg("calls")
+        .arg(1000)
+        .arg(5)
+        .query_async(&mut con)
+        .await
+        .unwrap();
+    let counts: Vec<i64> = redis::cmd("CMS.INCRBY")
+        .arg("calls")
+        .arg(&["key-a", "3", "key-b", "1"])
+        .query_async(&mut con)
+        .await
+        .unwrap();
+    assert_eq!(counts, vec![3, 1]);
+    let info: Vec<redis::Value> = redis::cmd("CMS.INFO")
+        .arg("calls")
+        .query_async(&mut con)
+        .await
+        .unwrap();
+    assert_eq!(info.len(), 6);
+
+    let _: () = redis::cmd("TOPK.RESERVE")
+        .arg("hitters")
+        .arg(2)
+        .query_async(&mut con)
+        .await
+        .unwrap();
+    let expelled: Vec<Option<String>> = redis::cmd("TOPK.ADD")
+        .arg("hitters")
+        .arg(&["key-a", "key-a", "key-b"])
+        .query_async(&mut con)
+        .await
+        .unwrap();
+    assert_eq!(expelled, vec![None, None, None]);
+    let list: Vec<redis::Value> = redis::cmd("TOPK.LIST")
+        .arg("hitters")
+        .arg("WITHCOUNT")
+        .query_async(&mut con)
+        .await
+        .unwrap();
+    assert_eq!(list.len(), 4);
+    let top: Vec<String> = redis::cmd("TOPK.LIST")
+        .arg("hitters")
+        .query_async(&mut con)
+        .await
+        .unwrap();
+    assert_eq!(top, vec!["key-a", "key-b"]);
+
+    // Sizes too big to allocate are refused and the connection carries on
+    let err = redis::cmd("CMS.INITBYDIM")
+        .arg("huge")
+        .arg(4294967296u64)
+        .arg(4294967297u64)
+        .query_async::<()>(&mut con)
+        .await
+        .unwrap_err();
+    assert!(err.to_string().contains("too large"), "{}", err);
+    let err = redis::cmd("TOPK.RESERVE")
+        .arg("huge")
+        .arg(1_000_000_000_000_000u64)
+        .query_async::<()>(&mut con)
+        .await
+        .unwrap_err();
+    assert!(err.to_string().contains("invalid k"), "{}", err);
+    let top: Vec<String> = redis::cmd("TOPK.LIST")
+        .arg("hitters")
+        .query_async(&mut con)
+        .await
+        .unwrap();
+    assert_eq!(top, vec!["key-a", "key-b"]);
+}
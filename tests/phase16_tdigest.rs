@@ -0,0 +1,270 @@
+use bytes::Bytes;
+use padis::{Command, Db, Frame, run_server};
+use tokio::net::TcpListener;
+
+fn b(s: &str) -> Bytes {
+    Bytes::copy_from_slice(s.as_bytes())
+}
+
+// Helper to build a command frame
+fn cmd_frame(args: &[&str]) -> Frame {
+    Frame::Array(
+        args.iter()
+            .map(|s| Frame::BulkString(Bytes::copy_from_slice(s.as_bytes())))
+            .collect(),
+    )
+}
+
+fn latencies(db: &Db, key: &str, range: std::ops::RangeInclusive<u32>) {
+    let values: Vec<f64> = range.map(f64::from).collect();
+    db.tdigest_add(&b(key), &values).unwrap();
+}
+
+fn assert_near(actual: f64, expected: f64, tolerance: f64) {
+    assert!(
+        (actual - expected).abs() <= tolerance,
+        "{} is not within {} of {}",
+        actual,
+        tolerance,
+        expected
+    );
+}
+
+// === Db ===
+
+#[test]
+fn estimates_quantiles() {
+    let db = Db::new();
+    db.tdigest_create(&b("td"), 100).unwrap();
+    latencies(&db, "td", 1..=10_000);
+
+    let q = db
+        .tdigest_quantile(&b("td"), &[0.0, 0.5, 0.99, 0.999, 1.0])
+        .unwrap();
+    assert_eq!(q[0], 1.0);
+    assert_near(q[1], 5000.0, 50.0);
+    assert_near(q[2], 9900.0, 10.0);
+    assert_near(q[3], 9990.0, 3.0);
+    assert_eq!(q[4], 10_000.0);
+
+    let cdf = db.tdigest_cdf(&b("td"), &[0.0, 2500.0, 20_000.0]).unwrap();
+    assert_eq!(cdf[0], 0.0);
+    assert_near(cdf[1], 0.25, 0.01);
+    assert_eq!(cdf[2], 1.0);
+
+    assert_eq!(db.tdigest_min_max(&b("td")).unwrap(), (1.0, 10_000.0));
+}
+
+#[test]
+fn small_digests_are_exact() {
+    let db = Db::new();
+    db.tdigest_create(&b("td"), 100).unwrap();
+    db.tdigest_add(&b("td"), &[1.0, 2.0, 3.0, 4.0, 5.0])
+        .unwrap();
+
+    assert_eq!(db.tdigest_quantile(&b("td"), &[0.5]).unwrap(), vec![3.0]);
+    assert_eq!(db.tdigest_cdf(&b("td"), &[3.0]).unwrap(), vec![0.5]);
+    assert_eq!(
+        db.tdigest_rank(&b("td"), &[0.0, 1.0, 3.0, 10.0], false)
+            .unwrap(),
+        vec![-1, 1, 3, 5]
+    );
+    assert_eq!(
+        db.tdigest_rank(&b("td"), &[0.0, 5.0, 10.0], true).unwrap(),
+        vec![5, 1, -1]
+    );
+    assert_eq!(db.tdigest_trimmed_mean(&b("td"), 0.2, 0.8).unwrap(), 3.0);
+}
+
+#[test]
+fn empty_digests_answer_nan() {
+    let db = Db::new();
+    db.tdigest_create(&b("td"), 100).unwrap();
+    assert!(db.tdigest_quantile(&b("td"), &[0.5]).unwrap()[0].is_nan());
+    assert!(db.tdigest_cdf(&b("td"), &[1.0]).unwrap()[0].is_nan());
+    assert!(db.tdigest_min_max(&b("td")).unwrap().0.is_nan());
+    assert!(
+        db.tdigest_trimmed_mean(&b("td"), 0.1, 0.9)
+            .unwrap()
+            .is_nan()
+    );
+    assert_eq!(db.tdigest_rank(&b("td"), &[1.0], false).unwrap(), vec![-2]);
+}
+
+#[test]
+fn trimmed_mean_drops_outliers() {
+    let db = Db::new();
+    db.tdigest_create(&b("td"), 100).unwrap();
+    latencies(&db, "td", 1..=1000);
+    db.tdigest_add(&b("td"), &[1e9; 10]).unwrap();
+
+    let mean = db.tdigest_trimmed_mean(&b("td"), 0.0, 0.95).unwrap();
+    assert!(mean < 1000.0, "{}", mean);
+}
+
+#[test]
+fn compresses_into_few_centroids() {
+    let db = Db::new();
+    db.tdigest_create(&b("td"), 50).unwrap();
+    latencies(&db, "td", 1..=100_000);
+
+    let info = db.tdigest_info(&b("td")).unwrap();
+    assert_eq!(info.compression, 50);
+    assert_eq!(info.capacity, 310);
+    assert_eq!(info.observations, 100_000.0);
+    assert!(info.total_compressions > 0);
+    assert!(info.merged_nodes < 310, "{}", info.merged_nodes);
+}
+
+#[test]
+fn merge_keeps_destination_unless_overriding() {
+    let db = Db::new();
+    db.tdigest_create(&b("a"), 100).unwrap();
+    db.tdigest_create(&b("b"), 200).unwrap();
+    latencies(&db, "a", 1..=500);
+    latencies(&db, "b", 501..=1000);
+
+    db.tdigest_merge(&b("all"), &[b("a"), b("b")], None, false)
+        .unwrap();
+    let info = db.tdigest_info(&b("all")).unwrap();
+    assert_eq!(info.compression, 200);
+    assert_eq!(info.observations, 1000.0);
+    assert_near(
+        db.tdigest_quantile(&b("all"), &[0.5]).unwrap()[0],
+        500.0,
+        5.0,
+    );
+
+    db.tdigest_merge(&b("all"), &[b("a")], Some(50), false)
+        .unwrap();
+    let info = db.tdigest_info(&b("all")).unwrap();
+    assert_eq!((info.compression, info.observations), (50, 1500.0));
+
+    db.tdigest_merge(&b("all"), &[b("a")], None, true).unwrap();
+    assert_eq!(db.tdigest_info(&b("all")).unwrap().observations, 500.0);
+}
+
+#[test]
+fn tdigest_errors() {
+    let db = Db::new();
+    assert_eq!(
+        db.tdigest_add(&b("missing"), &[1.0])
+            .unwrap_err()
+            .to_string(),
+        "T-Digest: key does not exist"
+    );
+    db.tdigest_create(&b("td"), 100).unwrap();
+    assert!(db.tdigest_create(&b("td"), 100).is_err());
+    assert!(
+        db.tdigest_merge(&b("out"), &[b("td"), b("missing")], None, false)
+            .is_err()
+    );
+    db.set(&b("plain"), b("v"), None);
+    assert!(db.tdigest_quantile(&b("plain"), &[0.5]).is_err());
+}
+
+// === Parsing ===
+
+#[test]
+fn parse_tdigest_commands() {
+    assert!(matches!(
+        Command::from_frame(cmd_frame(&["TDIGEST.CREATE", "k"])).unwrap(),
+        Command::TDigestCreate {
+            compression: 100,
+            ..
+        }
+    ));
+    assert!(matches!(
+        Command::from_frame(cmd_frame(&[
+            "TDIGEST.MERGE", "d", "2", "a", "b", "COMPRESSION", "20", "OVERRIDE"
+        ]))
+        .unwrap(),
+        Command::TDigestMerge { sources, compression: Some(20), override_dest: true, .. }
+            if sources.len() == 2
+    ));
+    assert!(matches!(
+        Command::from_frame(cmd_frame(&["TDIGEST.REVRANK", "k", "1", "2"])).unwrap(),
+        Command::TDigestRank { rev: true, values, .. } if values == vec![1.0, 2.0]
+    ));
+    assert!(matches!(
+        Command::from_frame(cmd_frame(&["TDIGEST.MAX", "k"])).unwrap(),
+        Command::TDigestMinMax { max: true, .. }
+    ));
+
+    for bad in [
+        &["TDIGEST.CREATE", "k", "COMPRESSION", "0"][..],
+        &["TDIGEST.ADD", "k"],
+        &["TDIGEST.ADD", "k", "1", "nan"],
+        &["TDIGEST.QUANTILE", "k", "1.5"],
+        &["TDIGEST.TRIMMED_MEAN", "k", "0.5", "0.1"],
+        &["TDIGEST.TRIMMED_MEAN", "k", "-0.1", "0.5"],
+        &["TDIGEST.MERGE", "d", "0"],
+        &["TDIGEST.MERGE", "d", "3", "a", "b"],
+        &["TDIGEST.MIN", "k", "extra"],
+    ] {
+        assert!(Command::from_frame(cmd_frame(bad)).is_err(), "{:?}", bad);
+    }
+}
+
+// === Integration ===
+
+#[tokio::test]
+async fn tdigest_over_tcp() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    tokio::spawn(async move { run_server(listener, Db::new()).await });
+
+    let client = redis::Client::open(format!("redis://127.0.0.1:{}", port)).unwrap();
+    let mut con = client.get_multiplexed_async_connection().await.unwrap();
+
+    let _: () = redis::cmd("TDIGEST.CREATE")
+        .arg("latency")
+        .arg("COMPRESSION")
+        .arg(100)
+        .query_async(&mut con)
+        .await
+        .unwrap();
+
+    let empty: Vec<String> = redis::cmd("TDIGEST.QUANTILE")
+        .arg("latency")
+        .arg(0.5)
+        .query_async(&mut con)
+        .await
+        .unwrap();
+    assert_eq!(empty, vec!["nan"]);
+
+    let _: () = redis::cmd("TDIGEST.ADD")
+        .arg("latency")
+        .arg(&[10, 20, 30, 40, 50])
+        .query_async(&mut con)
+        .await
+        .unwrap();
+    let quantiles: Vec<f64> = redis::cmd("TDIGEST.QUANTILE")
+        .arg("latency")
+        .arg(&[0.0, 0.5, 1.0])
+        .query_async(&mut con)
+        .await
+        .unwrap();
+    assert_eq!(quantiles, vec![10.0, 30.0, 50.0]);
+
+    let max: f64 = redis::cmd("TDIGEST.MAX")
+        .arg("latency")
+        .query_async(&mut con)
+        .await
+        .unwrap();
+    assert_eq!(max, 50.0);
+    let ranks: Vec<i64> = redis::cmd("TDIGEST.RANK")
+        .arg("latency")
+        .arg(&[5, 30, 60])
+        .query_async(&mut con)
+        .await
+        .unwrap();
+    assert_eq!(ranks, vec![-1, 3, 5]);
+
+    let info: Vec<redis::Value> = redis::cmd("TDIGEST.INFO")
+        .arg("latency")
+        .query_async(&mut con)
+        .await
+        .unwrap();
+    assert_eq!(info.len(), 18);
+}
@@ -0,0 +1,513 @@
+use bytes::Bytes;
+use padis::db::{JsonCondition, JsonFormat, JsonPath};
+use padis::{Command, Db, Frame, run_server};
+use serde_json::{Value, json};
+use tokio::net::TcpListener;
+
+fn b(s: &str) -> Bytes {
+    Bytes::copy_from_slice(s.as_bytes())
+}
+
+fn p(path: &str) -> JsonPath {
+    JsonPath::parse(path).unwrap()
+}
+
+// Helper to build a command frame
+fn cmd_frame(args: &[&str]) -> Frame {
+    Frame::Array(
+        args.iter()
+            .map(|s| Frame::BulkString(Bytes::copy_from_slice(s.as_bytes())))
+            .collect(),
+    )
+}
+
+fn store() -> Db {
+    let db = Db::new();
+    let doc = json!({
+        "name": "padis",
+        "version": 1,
+        "tags": ["fast", "small"],
+        "limits": {"rps": 100, "burst": 1.5},
+        "services": [
+            {"name": "api", "port": 8080, "public": true},
+            {"name": "worker", "port": 9000},
+            {"name": "admin", "port": 8081, "public": false}
+        ]
+    });
+    db.json_set(&b("cfg"), &p("$"), doc, None).unwrap();
+    db
+}
+
+fn get(db: &Db, path: &str) -> Value {
+    db.json_get(&b("cfg"), &[p(path)]).unwrap().unwrap()
+}
+
+// === Db ===
+
+#[test]
+fn legacy_and_jsonpath_get() {
+    let db = store();
+    assert_eq!(get(&db, ".name"), json!("padis"));
+    assert_eq!(get(&db, "limits.rps"), json!(100));
+    assert_eq!(get(&db, "$.name"), json!(["padis"]));
+    assert_eq!(get(&db, "$.services[*].port"), json!([8080, 9000, 8081]));
+    assert_eq!(get(&db, "$.services[-1].name"), json!(["admin"]));
+    assert_eq!(get(&db, "$.services[0:2].name"), json!(["api", "worker"]));
+    assert_eq!(get(&db, "$['tags'][0,1]"), json!(["fast", "small"]));
+    assert_eq!(get(&db, "$..port"), json!([8080, 9000, 8081]));
+    assert_eq!(get(&db, "$.missing"), json!([]));
+    assert_eq!(
+        db.json_get(&b("cfg"), &[p(".missing")])
+            .unwrap_err()
+            .to_string(),
+        "Path '.missing' does not exist"
+    );
+
+    // Several paths reply with an object keyed by path
+    assert_eq!(
+        db.json_get(&b("cfg"), &[p(".name"), p(".version")])
+            .unwrap()
+            .unwrap(),
+        json!({".name": "padis", ".version": 1})
+    );
+    assert_eq!(db.json_get(&b("missing"), &[]).unwrap(), None);
+}
+
+#[test]
+fn filters_select_matching_elements() {
+    let db = store();
+    assert_eq!(
+        get(&db, "$.services[?(@.port > 8080)].name"),
+        json!(["worker", "admin"])
+    );
+    assert_eq!(
+        get(&db, "$.services[?(@.public == true)].name"),
+        json!(["api"])
+    );
+    assert_eq!(
+        get(&db, "$.services[?(@.public)].name"),
+        json!(["api", "admin"])
+    );
+    assert_eq!(
+        get(
+            &db,
+            "$.services[?(@.name == 'worker' || @.port < 8081)].port"
+        ),
+        json!([8080, 9000])
+    );
+    assert_eq!(
+        get(
+            &db,
+            "$.services[?(@.port >= 8080 && @.public == false)].name"
+        ),
+        json!(["admin"])
+    );
+}
+
+#[test]
+fn set_patches_in_place() {
+    let db = store();
+    assert!(
+        db.json_set(&b("cfg"), &p("$.limits.rps"), json!(250), None)
+            .unwrap()
+    );
+    assert_eq!(get(&db, ".limits"), json!({"rps": 250, "burst": 1.5}));
+
+    // Every match is updated
+    assert!(
+        db.json_set(&b("cfg"), &p("$.services[*].port"), json!(1), None)
+            .unwrap()
+    );
+    assert_eq!(get(&db, "$..port"), json!([1, 1, 1]));
+
+    // A missing last key is created on its parent object
+    assert!(
+        db.json_set(&b("cfg"), &p(".limits.max"), json!(7), None)
+            .unwrap()
+    );
+    assert_eq!(get(&db, ".limits.max"), json!(7));
+    // But missing parents aren't
+    assert!(!db.json_set(&b("cfg"), &p("$.a.b"), json!(1), None).unwrap());
+
+    assert!(
+        !db.json_set(&b("cfg"), &p(".name"), json!("x"), Some(JsonCondition::Nx))
+            .unwrap()
+    );
+    assert!(
+        !db.json_set(&b("cfg"), &p(".nope"), json!("x"), Some(JsonCondition::Xx))
+            .unwrap()
+    );
+    assert!(
+        db.json_set(&b("cfg"), &p(".name"), json!("x"), Some(JsonCondition::Xx))
+            .unwrap()
+    );
+    assert_eq!(get(&db, ".name"), json!("x"));
+}
+
+#[test]
+fn new_keys_start_at_the_root() {
+    let db = Db::new();
+    assert_eq!(
+        db.json_set(&b("doc"), &p("$.a"), json!(1), None)
+            .unwrap_err()
+            .to_string(),
+        "new objects must be created at the root"
+    );
+    assert!(
+        !db.json_set(&b("doc"), &p("."), json!(1), Some(JsonCondition::Xx))
+            .unwrap()
+    );
+    assert!(
+        db.json_set(&b("doc"), &p("."), json!({"a": 1}), None)
+            .unwrap()
+    );
+
+    db.set(&b("plain"), b("v"), None);
+    assert!(db.json_set(&b("plain"), &p("$"), json!(1), None).is_err());
+    assert!(db.json_get(&b("plain"), &[]).is_err());
+}
+
+#[test]
+fn del_removes_matches() {
+    let db = store();
+    assert_eq!(
+        db.json_del(&b("cfg"), &p("$.services[*].public")).unwrap(),
+        2
+    );
+    assert_eq!(db.json_del(&b("cfg"), &p("$.tags[0,1]")).unwrap(), 2);
+    assert_eq!(get(&db, ".tags"), json!([]));
+    assert_eq!(db.json_del(&b("cfg"), &p(".nope")).unwrap(), 0);
+
+    assert_eq!(db.json_del(&b("cfg"), &JsonPath::root()).unwrap(), 1);
+    assert_eq!(db.json_get(&b("cfg"), &[]).unwrap(), None);
+    assert_eq!(db.json_del(&b("cfg"), &JsonPath::root()).unwrap(), 0);
+}
+
+#[test]
+fn numincrby_keeps_integers() {
+    let db = store();
+    let by = |n: Value| n.as_number().unwrap().clone();
+    assert_eq!(
+        db.json_numincrby(&b("cfg"), &p(".version"), &by(json!(2)))
+            .unwrap(),
+        vec![Some(3.into())]
+    );
+    assert_eq!(
+        db.json_numincrby(&b("cfg"), &p("$.limits.*"), &by(json!(0.5)))
+            .unwrap(),
+        vec![
+            serde_json::Number::from_f64(100.5),
+            serde_json::Number::from_f64(2.0)
+        ]
+    );
+    assert_eq!(
+        db.json_numincrby(&b("cfg"), &p("$.services[0].*"), &by(json!(1)))
+            .unwrap(),
+        vec![None, Some(8081.into()), None]
+    );
+    assert_eq!(
+        db.json_numincrby(&b("cfg"), &p(".name"), &by(json!(1)))
+            .unwrap_err()
+            .to_string(),
+        "WRONGTYPE wrong type of path value - expected number but found string"
+    );
+    assert_eq!(
+        db.json_numincrby(&b("nope"), &p(".a"), &by(json!(1)))
+            .unwrap_err()
+            .to_string(),
+        "could not perform this operation on a key that doesn't exist"
+    );
+}
+
+#[test]
+fn string_and_array_edits() {
+    let db = store();
+    assert_eq!(
+        db.json_strappend(&b("cfg"), &p(".name"), "-db").unwrap(),
+        vec![Some(8)]
+    );
+    assert_eq!(get(&db, ".name"), json!("padis-db"));
+
+    assert_eq!(
+        db.json_arrappend(&b("cfg"), &p("$.tags"), &[json!("new"), json!(1)])
+            .unwrap(),
+        vec![Some(4)]
+    );
+    assert_eq!(
+        db.json_arrinsert(&b("cfg"), &p(".tags"), 0, &[json!("first")])
+            .unwrap(),
+        vec![Some(5)]
+    );
+    assert_eq!(
+        db.json_arrinsert(&b("cfg"), &p(".tags"), -1, &[json!("penultimate")])
+            .unwrap(),
+        vec![Some(6)]
+    );
+    assert_eq!(
+        get(&db, ".tags"),
+        json!(["first", "fast", "small", "new", "penultimate", 1])
+    );
+    assert!(
+        db.json_arrinsert(&b("cfg"), &p(".tags"), 7, &[json!(0)])
+            .is_err()
+    );
+
+    assert_eq!(
+        db.json_arrpop(&b("cfg"), &p(".tags"), -1).unwrap(),
+        vec![Some(json!(1))]
+    );
+    assert_eq!(
+        db.json_arrpop(&b("cfg"), &p(".tags"), 100).unwrap(),
+        vec![Some(json!("penultimate"))]
+    );
+    assert_eq!(
+        db.json_arrpop(&b("cfg"), &p(".tags"), 0).unwrap(),
+        vec![Some(json!("first"))]
+    );
+
+    assert_eq!(
+        db.json_arrtrim(&b("cfg"), &p(".tags"), 1, -1).unwrap(),
+        vec![Some(2)]
+    );
+    assert_eq!(get(&db, ".tags"), json!(["small", "new"]));
+    assert_eq!(
+        db.json_arrtrim(&b("cfg"), &p(".tags"), 5, 10).unwrap(),
+        vec![Some(0)]
+    );
+    assert_eq!(
+        db.json_arrpop(&b("cfg"), &p("$.tags"), -1).unwrap(),
+        vec![None]
+    );
+}
+
+#[test]
+fn type_objkeys_and_mget() {
+    let db = store();
+    assert_eq!(
+        db.json_type(&b("cfg"), &p("$.*")).unwrap().unwrap(),
+        vec!["string", "integer", "array", "object", "array"]
+    );
+    assert_eq!(
+        db.json_type(&b("cfg"), &p(".limits.burst"))
+            .unwrap()
+            .unwrap(),
+        vec!["number"]
+    );
+    assert_eq!(db.json_type(&b("nope"), &JsonPath::root()).unwrap(), None);
+
+    assert_eq!(
+        db.json_objkeys(&b("cfg"), &p("$.services[0,1]"))
+            .unwrap()
+            .unwrap(),
+        vec![
+            Some(vec!["name".into(), "port".into(), "public".into()]),
+            Some(vec!["name".into(), "port".into()])
+        ]
+    );
+    assert!(db.json_objkeys(&b("cfg"), &p(".tags")).is_err());
+
+    db.json_set(&b("other"), &p("$"), json!({"name": "other"}), None)
+        .unwrap();
+    assert_eq!(
+        db.json_mget(&[b("cfg"), b("other"), b("nope")], &p("$.name")),
+        vec![Some(json!(["padis"])), Some(json!(["other"])), None]
+    );
+    assert_eq!(
+        db.json_mget(&[b("cfg"), b("other")], &p(".version")),
+        vec![Some(json!(1)), None]
+    );
+}
+
+#[test]
+fn formats_with_indentation() {
+    let format = JsonFormat {
+        indent: "  ".into(),
+        newline: "\n".into(),
+        space: " ".into(),
+    };
+    assert_eq!(
+        format.format(&json!({"a": [1, {}], "b": []})),
+        "{\n  \"a\": [\n    1,\n    {}\n  ],\n  \"b\": []\n}"
+    );
+    assert_eq!(
+        JsonFormat::default().format(&json!({"a": [1, 2]})),
+        "{\"a\":[1,2]}"
+    );
+}
+
+// === Parsing ===
+
+#[test]
+fn parse_json_paths() {
+    for ok in [
+        "$",
+        ".",
+        "a",
+        ".a.b",
+        "a[0]",
+        "$..x",
+        "$.a[*]",
+        "$['a b']",
+        "$[1:3]",
+        "$[::2]",
+        "$[-1]",
+        "$.a[?(@.b == 'c')]",
+        "$[?(@.x<1||@.y)]",
+    ] {
+        assert!(JsonPath::parse(ok).is_some(), "{}", ok);
+    }
+    for bad in ["$.", "$[", "$['a]", "$[?(@.a ==)]", "$a", ".a.", "$[?(1)]"] {
+        assert!(JsonPath::parse(bad).is_none(), "{}", bad);
+    }
+    assert!(JsonPath::parse(".a").unwrap().is_legacy());
+    assert!(!JsonPath::parse("$.a").unwrap().is_legacy());
+}
+
+#[test]
+fn parse_json_commands() {
+    assert!(matches!(
+        Command::from_frame(cmd_frame(&["JSON.SET", "k", "$", "{\"a\":1}", "NX"])).unwrap(),
+        Command::JsonSet { condition: Some(JsonCondition::Nx), value, .. } if value == json!({"a": 1})
+    ));
+    assert!(matches!(
+        Command::from_frame(cmd_frame(&[
+            "JSON.GET", "k", "INDENT", "\t", "$.a", "NEWLINE", "\n", ".b"
+        ]))
+        .unwrap(),
+        Command::JsonGet { paths, format, .. } if paths.len() == 2 && format.indent == "\t"
+    ));
+    assert!(matches!(
+        Command::from_frame(cmd_frame(&["JSON.STRAPPEND", "k", "\"x\""])).unwrap(),
+        Command::JsonStrAppend { path, value, .. } if path.is_legacy() && value == "x"
+    ));
+    assert!(matches!(
+        Command::from_frame(cmd_frame(&["JSON.ARRPOP", "k"])).unwrap(),
+        Command::JsonArrPop { index: -1, .. }
+    ));
+    assert!(matches!(
+        Command::from_frame(cmd_frame(&["JSON.MGET", "a", "b", "$.x"])).unwrap(),
+        Command::JsonMGet { keys, .. } if keys.len() == 2
+    ));
+
+    for bad in [
+        &["JSON.SET", "k", "$", "{bad"][..],
+        &["JSON.SET", "k", "$[", "1"],
+        &["JSON.NUMINCRBY", "k", "$", "\"a\""],
+        &["JSON.STRAPPEND", "k", "$", "x"],
+        &["JSON.ARRAPPEND", "k", "$"],
+        &["JSON.ARRINSERT", "k", "$", "x", "1"],
+        &["JSON.ARRTRIM", "k", "$", "0"],
+        &["JSON.MGET", "k"],
+    ] {
+        assert!(Command::from_frame(cmd_frame(bad)).is_err(), "{:?}", bad);
+    }
+}
+
+// === Integration ===
+
+#[tokio::test]
+async fn json_over_tcp() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    tokio::spawn(async move { run_server(listener, Db::new()).await });
+
+    let client = redis::Client::open(format!("redis://127.0.0.1:{}", port)).unwrap();
+    let mut con = client.get_multiplexed_async_connection().await.unwrap();
+
+    let _: () = redis::cmd("JSON.SET")
+        .arg("svc")
+        .arg("$")
+        .arg(r#"{"timeout":30,"hosts":["a"]}"#)
+        .query_async(&mut con)
+        .await
+        .unwrap();
+    let unset: Option<String> = redis::cmd("JSON.SET")
+        .arg("svc")
+        .arg("$.timeout")
+        .arg("10")
+        .arg("NX")
+        .query_async(&mut con)
+        .await
+        .unwrap();
+    assert_eq!(unset, None);
+
+    let incremented: String = redis::cmd("JSON.NUMINCRBY")
+        .arg("svc")
+        .arg("$.timeout")
+        .arg(5)
+        .query_async(&mut con)
+        .await
+        .unwrap();
+    assert_eq!(incremented, "[35]");
+    let legacy: String = redis::cmd("JSON.NUMINCRBY")
+        .arg("svc")
+        .arg(".timeout")
+        .arg(1)
+        .query_async(&mut con)
+        .await
+        .unwrap();
+    assert_eq!(legacy, "36");
+
+    let len: i64 = redis::cmd("JSON.ARRAPPEND")
+        .arg("svc")
+        .arg(".hosts")
+        .arg("\"b\"")
+        .query_async(&mut con)
+        .await
+        .unwrap();
+    assert_eq!(len, 2);
+    let lens: Vec<Option<i64>> = redis::cmd("JSON.ARRAPPEND")
+        .arg("svc")
+        .arg("$.*")
+        .arg("\"c\"")
+        .query_async(&mut con)
+        .await
+        .unwrap();
+    assert_eq!(lens, vec![None, Some(3)]);
+
+    let doc: String = redis::cmd("JSON.GET")
+        .arg("svc")
+        .query_async(&mut con)
+        .await
+        .unwrap();
+    assert_eq!(doc, r#"{"timeout":36,"hosts":["a","b","c"]}"#);
+
+    let kind: String = redis::cmd("JSON.TYPE")
+        .arg("svc")
+        .arg(".hosts")
+        .query_async(&mut con)
+        .await
+        .unwrap();
+    assert_eq!(kind, "array");
+    let keys: Vec<String> = redis::cmd("JSON.OBJKEYS")
+        .arg("svc")
+        .query_async(&mut con)
+        .await
+        .unwrap();
+    assert_eq!(keys, vec!["timeout", "hosts"]);
+
+    let popped: String = redis::cmd("JSON.ARRPOP")
+        .arg("svc")
+        .arg(".hosts")
+        .query_async(&mut con)
+        .await
+        .unwrap();
+    assert_eq!(popped, "\"c\"");
+
+    let err = redis::cmd("JSON.SET")
+        .arg("fresh")
+        .arg("$.a")
+        .arg("1")
+        .query_async::<()>(&mut con)
+        .await
+        .unwrap_err();
+    assert!(err.to_string().contains("root"), "{}", err);
+
+    let deleted: i64 = redis::cmd("JSON.DEL")
+        .arg("svc")
+        .arg("$.hosts")
+        .query_async(&mut con)
+        .await
+        .unwrap();
+    assert_eq!(deleted, 1);
+}
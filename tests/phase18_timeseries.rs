@@ -0,0 +1,698 @@
+use bytes::Bytes;
+use padis::db::{Aggregation, Aggregator, DuplicatePolicy, LabelFilter, TsOptions, TsRangeQuery};
+use padis::{Command, Db, Frame, run_server};
+use tokio::net::TcpListener;
+
+fn b(s: &str) -> Bytes {
+    Bytes::copy_from_slice(s.as_bytes())
+}
+
+// Helper to build a command frame
+fn cmd_frame(args: &[&str]) -> Frame {
+    Frame::Array(
+        args.iter()
+            .map(|s| Frame::BulkString(Bytes::copy_from_slice(s.as_bytes())))
+            .collect(),
+    )
+}
+
+fn all() -> TsRangeQuery {
+    TsRangeQuery {
+        from: 0,
+        to: u64::MAX,
+        rev: false,
+        filter_by_value: None,
+        count: None,
+        aggregation: None,
+    }
+}
+
+fn aggregated(aggregator: Aggregator, bucket: u64) -> TsRangeQuery {
+    TsRangeQuery {
+        aggregation: Some(Aggregation { aggregator, bucket }),
+        ..all()
+    }
+}
+
+fn add(db: &Db, key: &str, samples: &[(u64, f64)]) {
+    for &(ts, value) in samples {
+        db.ts_add(&b(key), Some(ts), value, &TsOptions::default(), None)
+            .unwrap();
+    }
+}
+
+fn labelled(db: &Db, key: &str, labels: &[(&str, &str)]) {
+    let options = TsOptions {
+        labels: labels.iter().map(|(l, v)| (b(l), b(v))).collect(),
+        ..TsOptions::default()
+    };
+    db.ts_create(&b(key), options).unwrap();
+}
+
+fn filter(label: &str, values: &[&str], equal: bool) -> LabelFilter {
+    LabelFilter {
+        label: b(label),
+        values: values.iter().map(|v| b(v)).collect(),
+        equal,
+    }
+}
+
+// === Db ===
+
+#[test]
+fn add_creates_series_and_ranges_in_order() {
+    let db = Db::new();
+    add(&db, "cpu", &[(30, 3.0), (10, 1.0), (20, 2.0)]);
+
+    assert_eq!(
+        db.ts_range(&b("cpu"), &all()).unwrap(),
+        vec![(10, 1.0), (20, 2.0), (30, 3.0)]
+    );
+    assert_eq!(db.ts_get(&b("cpu")).unwrap(), Some((30, 3.0)));
+
+    let query = TsRangeQuery {
+        from: 15,
+        to: 30,
+        rev: true,
+        count: Some(1),
+        ..all()
+    };
+    assert_eq!(db.ts_range(&b("cpu"), &query).unwrap(), vec![(30, 3.0)]);
+    let query = TsRangeQuery {
+        filter_by_value: Some((1.5, 2.5)),
+        ..all()
+    };
+    assert_eq!(db.ts_range(&b("cpu"), &query).unwrap(), vec![(20, 2.0)]);
+    let query = TsRangeQuery {
+        from: 40,
+        to: 5,
+        ..all()
+    };
+    assert_eq!(db.ts_range(&b("cpu"), &query).unwrap(), vec![]);
+
+    // A sample at the current time when none is given
+    let now = db
+        .ts_add(&b("cpu"), None, 4.0, &TsOptions::default(), None)
+        .unwrap();
+    assert!(now > 1_600_000_000_000);
+}
+
+#[test]
+fn create_rejects_existing_keys() {
+    let db = Db::new();
+    db.ts_create(&b("cpu"), TsOptions::default()).unwrap();
+    assert_eq!(
+        db.ts_create(&b("cpu"), TsOptions::default())
+            .unwrap_err()
+            .to_string(),
+        "TSDB: key already exists"
+    );
+    assert_eq!(db.ts_get(&b("cpu")).unwrap(), None);
+    assert_eq!(
+        db.ts_range(&b("missing"), &all()).unwrap_err().to_string(),
+        "TSDB: the key does not exist"
+    );
+
+    db.set(&b("plain"), b("v"), None);
+    assert!(
+        db.ts_add(&b("plain"), Some(1), 1.0, &TsOptions::default(), None)
+            .is_err()
+    );
+}
+
+#[test]
+fn retention_drops_old_samples() {
+    let db = Db::new();
+    let options = TsOptions {
+        retention: 100,
+        ..TsOptions::default()
+    };
+    db.ts_create(&b("cpu"), options).unwrap();
+    add(&db, "cpu", &[(0, 1.0), (50, 2.0), (120, 3.0)]);
+    assert_eq!(
+        db.ts_range(&b("cpu"), &all()).unwrap(),
+        vec![(50, 2.0), (120, 3.0)]
+    );
+
+    assert_eq!(
+        db.ts_add(&b("cpu"), Some(10), 1.0, &TsOptions::default(), None)
+            .unwrap_err()
+            .to_string(),
+        "TSDB: Timestamp is older than retention"
+    );
+    // Still inside the window
+    add(&db, "cpu", &[(30, 0.5)]);
+    assert_eq!(db.ts_info(&b("cpu")).unwrap().total_samples, 3);
+}
+
+#[test]
+fn duplicate_policies() {
+    let db = Db::new();
+    add(&db, "cpu", &[(10, 5.0)]);
+    assert_eq!(
+        db.ts_add(&b("cpu"), Some(10), 1.0, &TsOptions::default(), None)
+            .unwrap_err()
+            .to_string(),
+        "TSDB: Error at upsert, update is not supported when DUPLICATE_POLICY is set to BLOCK mode"
+    );
+
+    let cases = [
+        (DuplicatePolicy::First, 5.0),
+        (DuplicatePolicy::Last, 2.0),
+        (DuplicatePolicy::Min, 2.0),
+        (DuplicatePolicy::Max, 5.0),
+        (DuplicatePolicy::Sum, 7.0),
+    ];
+    for (policy, expected) in cases {
+        let db = Db::new();
+        add(&db, "cpu", &[(10, 5.0)]);
+        db.ts_add(
+            &b("cpu"),
+            Some(10),
+            2.0,
+            &TsOptions::default(),
+            Some(policy),
+        )
+        .unwrap();
+        assert_eq!(
+            db.ts_get(&b("cpu")).unwrap(),
+            Some((10, expected)),
+            "{:?}",
+            policy
+        );
+    }
+
+    // The series' own policy applies when TS.ADD doesn't override it
+    let options = TsOptions {
+        duplicate_policy: DuplicatePolicy::Sum,
+        ..TsOptions::default()
+    };
+    db.ts_create(&b("sum"), options).unwrap();
+    add(&db, "sum", &[(1, 1.0), (1, 1.0), (1, 1.0)]);
+    assert_eq!(db.ts_get(&b("sum")).unwrap(), Some((1, 3.0)));
+}
+
+#[test]
+fn aggregates_into_buckets() {
+    let db = Db::new();
+    add(
+        &db,
+        "cpu",
+        &[
+            (0, 1.0),
+            (5, 5.0),
+            (9, 3.0),
+            (10, 10.0),
+            (25, 2.0),
+            (29, 4.0),
+        ],
+    );
+
+    let range = |aggregator| db.ts_range(&b("cpu"), &aggregated(aggregator, 10)).unwrap();
+    assert_eq!(
+        range(Aggregator::Avg),
+        vec![(0, 3.0), (10, 10.0), (20, 3.0)]
+    );
+    assert_eq!(
+        range(Aggregator::Sum),
+        vec![(0, 9.0), (10, 10.0), (20, 6.0)]
+    );
+    assert_eq!(
+        range(Aggregator::Min),
+        vec![(0, 1.0), (10, 10.0), (20, 2.0)]
+    );
+    assert_eq!(
+        range(Aggregator::Max),
+        vec![(0, 5.0), (10, 10.0), (20, 4.0)]
+    );
+    assert_eq!(
+        range(Aggregator::Count),
+        vec![(0, 3.0), (10, 1.0), (20, 2.0)]
+    );
+    assert_eq!(
+        range(Aggregator::First),
+        vec![(0, 1.0), (10, 10.0), (20, 2.0)]
+    );
+    assert_eq!(
+        range(Aggregator::Last),
+        vec![(0, 3.0), (10, 10.0), (20, 4.0)]
+    );
+    assert_eq!(
+        range(Aggregator::Range),
+        vec![(0, 4.0), (10, 0.0), (20, 2.0)]
+    );
+
+    let query = TsRangeQuery {
+        rev: true,
+        count: Some(2),
+        ..aggregated(Aggregator::Sum, 10)
+    };
+    assert_eq!(
+        db.ts_range(&b("cpu"), &query).unwrap(),
+        vec![(20, 6.0), (10, 10.0)]
+    );
+}
+
+#[test]
+fn mrange_filters_by_labels() {
+    let db = Db::new();
+    labelled(&db, "cpu:a", &[("host", "a"), ("metric", "cpu")]);
+    labelled(
+        &db,
+        "cpu:b",
+        &[("host", "b"), ("metric", "cpu"), ("dc", "eu")],
+    );
+    labelled(&db, "mem:a", &[("host", "a"), ("metric", "mem")]);
+    add(&db, "cpu:a", &[(1, 10.0), (2, 20.0)]);
+    add(&db, "cpu:b", &[(1, 30.0)]);
+    db.set(&b("plain"), b("v"), None);
+
+    let keys = |filters: &[LabelFilter]| -> Vec<Bytes> {
+        db.ts_mrange(filters, &all())
+            .into_iter()
+            .map(|m| m.key)
+            .collect()
+    };
+    assert_eq!(
+        keys(&[filter("metric", &["cpu"], true)]),
+        vec![b("cpu:a"), b("cpu:b")]
+    );
+    assert_eq!(
+        keys(&[
+            filter("host", &["a"], true),
+            filter("metric", &["cpu"], false)
+        ]),
+        vec![b("mem:a")]
+    );
+    assert_eq!(
+        keys(&[
+            filter("metric", &["cpu", "mem"], true),
+            filter("dc", &[""], true)
+        ]),
+        vec![b("cpu:a"), b("mem:a")]
+    );
+    assert_eq!(
+        keys(&[filter("metric", &["cpu"], true), filter("dc", &[""], false)]),
+        vec![b("cpu:b")]
+    );
+
+    let matches = db.ts_mrange(
+        &[filter("host", &["a"], true)],
+        &aggregated(Aggregator::Sum, 10),
+    );
+    assert_eq!(matches[0].samples, vec![(0, 30.0)]);
+    assert_eq!(matches[0].labels[1], (b("metric"), b("cpu")));
+    assert_eq!(matches[1].samples, vec![]);
+}
+
+#[test]
+fn rules_compact_closed_buckets() {
+    let db = Db::new();
+    db.ts_create(&b("raw"), TsOptions::default()).unwrap();
+    db.ts_create(&b("avg"), TsOptions::default()).unwrap();
+    let avg = Aggregation {
+        aggregator: Aggregator::Avg,
+        bucket: 10,
+    };
+    db.ts_create_rule(&b("raw"), &b("avg"), avg, 0).unwrap();
+
+    add(&db, "raw", &[(1, 1.0), (5, 3.0), (9, 5.0)]);
+    // The first bucket is still open
+    assert_eq!(db.ts_range(&b("avg"), &all()).unwrap(), vec![]);
+
+    add(&db, "raw", &[(12, 10.0), (35, 1.0)]);
+    assert_eq!(
+        db.ts_range(&b("avg"), &all()).unwrap(),
+        vec![(0, 3.0), (10, 10.0)]
+    );
+
+    // A late sample rewrites its bucket
+    add(&db, "raw", &[(18, 20.0)]);
+    assert_eq!(
+        db.ts_range(&b("avg"), &all()).unwrap(),
+        vec![(0, 3.0), (10, 15.0)]
+    );
+
+    let info = db.ts_info(&b("raw")).unwrap();
+    assert_eq!(info.rules, vec![(b("avg"), avg, 0)]);
+    assert_eq!(db.ts_info(&b("avg")).unwrap().source_key, Some(b("raw")));
+
+    db.ts_delete_rule(&b("raw"), &b("avg")).unwrap();
+    assert_eq!(db.ts_info(&b("raw")).unwrap().rules, vec![]);
+    assert_eq!(db.ts_info(&b("avg")).unwrap().source_key, None);
+    assert_eq!(
+        db.ts_delete_rule(&b("raw"), &b("avg"))
+            .unwrap_err()
+            .to_string(),
+        "TSDB: compaction rule does not exist"
+    );
+}
+
+#[test]
+fn rules_use_their_alignment() {
+    let db = Db::new();
+    db.ts_create(&b("raw"), TsOptions::default()).unwrap();
+    db.ts_create(&b("max"), TsOptions::default()).unwrap();
+    let max = Aggregation {
+        aggregator: Aggregator::Max,
+        bucket: 10,
+    };
+    db.ts_create_rule(&b("raw"), &b("max"), max, 5).unwrap();
+    add(&db, "raw", &[(5, 1.0), (14, 2.0), (15, 9.0), (30, 0.0)]);
+    assert_eq!(
+        db.ts_range(&b("max"), &all()).unwrap(),
+        vec![(5, 2.0), (15, 9.0)]
+    );
+}
+
+#[test]
+fn rules_do_not_chain() {
+    let db = Db::new();
+    for key in ["a", "b", "c"] {
+        db.ts_create(&b(key), TsOptions::default()).unwrap();
+    }
+    let sum = Aggregation {
+        aggregator: Aggregator::Sum,
+        bucket: 10,
+    };
+    let err = |source: &str, destination: &str| {
+        db.ts_create_rule(&b(source), &b(destination), sum, 0)
+            .unwrap_err()
+            .to_string()
+    };
+    assert_eq!(
+        err("a", "a"),
+        "TSDB: the source key and destination key should be different"
+    );
+    assert_eq!(err("a", "missing"), "TSDB: the key does not exist");
+
+    db.ts_create_rule(&b("a"), &b("b"), sum, 0).unwrap();
+    assert_eq!(
+        err("c", "b"),
+        "TSDB: the destination key already has a src rule"
+    );
+    assert_eq!(
+        err("c", "a"),
+        "TSDB: the destination key already has a dst rule"
+    );
+    assert_eq!(
+        err("b", "c"),
+        "TSDB: the source key already has a source rule"
+    );
+
+    // One source can feed several destinations
+    db.ts_create_rule(&b("a"), &b("c"), sum, 0).unwrap();
+    add(&db, "a", &[(1, 1.0), (2, 2.0), (10, 0.0)]);
+    assert_eq!(db.ts_range(&b("b"), &all()).unwrap(), vec![(0, 3.0)]);
+    assert_eq!(db.ts_range(&b("c"), &all()).unwrap(), vec![(0, 3.0)]);
+}
+
+// === Parsing ===
+
+#[test]
+fn parse_ts_commands() {
+    let Command::TsAdd {
+        timestamp,
+        value,
+        options,
+        on_duplicate,
+        ..
+    } = Command::from_frame(cmd_frame(&[
+        "TS.ADD",
+        "k",
+        "*",
+        "1.5",
+        "RETENTION",
+        "60000",
+        "ON_DUPLICATE",
+        "sum",
+        "LABELS",
+        "host",
+        "a",
+    ]))
+    .unwrap()
+    else {
+        panic!("expected TS.ADD");
+    };
+    assert_eq!(timestamp, None);
+    assert_eq!(value, 1.5);
+    assert_eq!(options.retention, 60000);
+    assert_eq!(options.labels, vec![(b("host"), b("a"))]);
+    assert_eq!(on_duplicate, Some(DuplicatePolicy::Sum));
+
+    let Command::TsRange { query, .. } = Command::from_frame(cmd_frame(&[
+        "TS.REVRANGE",
+        "k",
+        "-",
+        "+",
+        "COUNT",
+        "5",
+        "AGGREGATION",
+        "avg",
+        "60000",
+    ]))
+    .unwrap() else {
+        panic!("expected TS.REVRANGE");
+    };
+    assert_eq!(
+        query,
+        TsRangeQuery {
+            rev: true,
+            count: Some(5),
+            ..aggregated(Aggregator::Avg, 60000)
+        }
+    );
+
+    let Command::TsMRange {
+        filters,
+        with_labels,
+        ..
+    } = Command::from_frame(cmd_frame(&[
+        "TS.MRANGE",
+        "0",
+        "100",
+        "WITHLABELS",
+        "FILTER",
+        "metric=cpu",
+        "host!=(a, b)",
+        "dc=",
+    ]))
+    .unwrap()
+    else {
+        panic!("expected TS.MRANGE");
+    };
+    assert!(with_labels);
+    assert_eq!(
+        filters,
+        vec![
+            filter("metric", &["cpu"], true),
+            filter("host", &["a", "b"], false),
+            filter("dc", &[""], true),
+        ]
+    );
+
+    assert!(matches!(
+        Command::from_frame(cmd_frame(&[
+            "TS.CREATERULE",
+            "a",
+            "b",
+            "AGGREGATION",
+            "max",
+            "10",
+            "5"
+        ]))
+        .unwrap(),
+        Command::TsCreateRule {
+            align: 5,
+            aggregation: Aggregation {
+                aggregator: Aggregator::Max,
+                bucket: 10
+            },
+            ..
+        }
+    ));
+}
+
+#[test]
+fn parse_ts_errors() {
+    let error = |args: &[&str]| match Command::from_frame(cmd_frame(args)) {
+        Err(e) => e.to_string(),
+        Ok(_) => panic!("{:?} parsed", args),
+    };
+    assert_eq!(error(&["TS.ADD", "k", "x", "1"]), "TSDB: invalid timestamp");
+    assert_eq!(
+        error(&["TS.ADD", "k", "-1", "1"]),
+        "TSDB: invalid timestamp"
+    );
+    assert_eq!(
+        error(&["TS.ADD", "k", "9223372036854775808", "1"]),
+        "TSDB: invalid timestamp"
+    );
+    assert_eq!(
+        error(&["TS.ADD", "k", "18446744073709551615", "1"]),
+        "TSDB: invalid timestamp"
+    );
+    assert_eq!(
+        error(&["TS.RANGE", "k", "0", "18446744073709551615"]),
+        "TSDB: invalid timestamp"
+    );
+    assert_eq!(error(&["TS.ADD", "k", "1", "x"]), "TSDB: invalid value");
+    assert_eq!(
+        error(&["TS.CREATE", "k", "DUPLICATE_POLICY", "newest"]),
+        "TSDB: Unknown DUPLICATE_POLICY"
+    );
+    assert_eq!(
+        error(&["TS.CREATE", "k", "LABELS", "host"]),
+        "TSDB: Couldn't parse LABELS"
+    );
+    assert_eq!(
+        error(&["TS.CREATE", "k", "ON_DUPLICATE", "sum"]),
+        "Syntax error"
+    );
+    assert_eq!(
+        error(&["TS.RANGE", "k", "-", "+", "AGGREGATION", "median", "10"]),
+        "TSDB: Unknown aggregation type"
+    );
+    assert_eq!(
+        error(&["TS.RANGE", "k", "-", "+", "AGGREGATION", "avg", "0"]),
+        "TSDB: bucketDuration must be greater than zero"
+    );
+    assert_eq!(
+        error(&["TS.RANGE", "k", "-", "+", "FILTER", "a=b"]),
+        "Syntax error"
+    );
+    assert_eq!(
+        error(&["TS.MRANGE", "-", "+"]),
+        "TSDB: missing FILTER argument"
+    );
+    assert_eq!(
+        error(&["TS.MRANGE", "-", "+", "FILTER", "a!=b", "c="]),
+        "TSDB: please provide at least one matcher"
+    );
+    assert_eq!(
+        error(&["TS.MRANGE", "-", "+", "FILTER", "nolabel"]),
+        "TSDB: failed parsing labels"
+    );
+}
+
+// === Integration ===
+
+#[tokio::test]
+async fn timeseries_over_tcp() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    tokio::spawn(async move { run_server(listener, Db::new()).await });
+
+    let client = redis::Client::open(format!("redis://127.0.0.1:{}", port)).unwrap();
+    let mut con = client.get_multiplexed_async_connection().await.unwrap();
+
+    for key in ["cpu:1", "cpu:1:avg"] {
+        let _: () = redis::cmd("TS.CREATE")
+            .arg(key)
+            .arg("RETENTION")
+            .arg(0)
+            .arg("LABELS")
+            .arg("host")
+            .arg("1")
+            .query_async(&mut con)
+            .await
+            .unwrap();
+    }
+    let _: () = redis::cmd("TS.CREATERULE")
+        .arg("cpu:1")
+        .arg("cpu:1:avg")
+        .arg("AGGREGATION")
+        .arg("avg")
+        .arg(1000)
+        .query_async(&mut con)
+        .await
+        .unwrap();
+
+    for (ts, value) in [(1000, 1.0), (1500, 2.0), (2000, 3.5)] {
+        let added: i64 = redis::cmd("TS.ADD")
+            .arg("cpu:1")
+            .arg(ts)
+            .arg(value)
+            .query_async(&mut con)
+            .await
+            .unwrap();
+        assert_eq!(added, ts);
+    }
+
+    let range: Vec<(i64, String)> = redis::cmd("TS.RANGE")
+        .arg("cpu:1")
+        .arg("-")
+        .arg("+")
+        .query_async(&mut con)
+        .await
+        .unwrap();
+    assert_eq!(
+        range,
+        vec![(1000, "1".into()), (1500, "2".into()), (2000, "3.5".into())]
+    );
+
+    let compacted: Vec<(i64, String)> = redis::cmd("TS.RANGE")
+        .arg("cpu:1:avg")
+        .arg("-")
+        .arg("+")
+        .query_async(&mut con)
+        .await
+        .unwrap();
+    assert_eq!(compacted, vec![(1000, "1.5".into())]);
+
+    let latest: (i64, String) = redis::cmd("TS.GET")
+        .arg("cpu:1")
+        .query_async(&mut con)
+        .await
+        .unwrap();
+    assert_eq!(latest, (2000, "3.5".into()));
+
+    type Series = (String, Vec<(String, String)>, Vec<(i64, String)>);
+    let series: Vec<Series> = redis::cmd("TS.MREVRANGE")
+        .arg("-")
+        .arg("+")
+        .arg("WITHLABELS")
+        .arg("AGGREGATION")
+        .arg("max")
+        .arg(1000)
+        .arg("FILTER")
+        .arg("host=1")
+        .query_async(&mut con)
+        .await
+        .unwrap();
+    assert_eq!(
+        series,
+        vec![
+            (
+                "cpu:1".into(),
+                vec![("host".into(), "1".into())],
+                vec![(2000, "3.5".into()), (1000, "2".into())]
+            ),
+            (
+                "cpu:1:avg".into(),
+                vec![("host".into(), "1".into())],
+                vec![(1000, "1.5".into())]
+            ),
+        ]
+    );
+
+    let err = redis::cmd("TS.ADD")
+        .arg("cpu:1")
+        .arg(2000)
+        .arg(1)
+        .query_async::<i64>(&mut con)
+        .await
+        .unwrap_err();
+    assert!(err.to_string().contains("BLOCK"), "{}", err);
+
+    let info: redis::Value = redis::cmd("TS.INFO")
+        .arg("cpu:1")
+        .query_async(&mut con)
+        .await
+        .unwrap();
+    let redis::Value::Array(fields) = info else {
+        panic!("expected an array");
+    };
+    assert_eq!(fields[1], redis::Value::Int(3));
+    assert_eq!(fields[13], redis::Value::Nil);
+}
@@ -0,0 +1,617 @@
+use bytes::Bytes;
+use padis::db::{Quantization, VAddOptions, VFilter, VSimQuery, VSimTarget};
+use padis::{Command, Db, Frame, run_server};
+use serde_json::json;
+use tokio::net::TcpListener;
+
+fn b(s: &str) -> Bytes {
+    Bytes::copy_from_slice(s.as_bytes())
+}
+
+// Helper to build a command frame
+fn cmd_frame(args: &[&str]) -> Frame {
+    Frame::Array(
+        args.iter()
+            .map(|s| Frame::BulkString(Bytes::copy_from_slice(s.as_bytes())))
+            .collect(),
+    )
+}
+
+fn noquant() -> VAddOptions {
+    VAddOptions {
+        quantization: Quantization::NoQuant,
+        ..VAddOptions::default()
+    }
+}
+
+fn query(target: VSimTarget, count: usize) -> VSimQuery {
+    VSimQuery {
+        target,
+        count,
+        ef: None,
+        filter: None,
+        filter_ef: None,
+        truth: false,
+    }
+}
+
+fn names(db: &Db, key: &str, query: &VSimQuery) -> Vec<Bytes> {
+    db.vsim(&b(key), query)
+        .unwrap()
+        .into_iter()
+        .map(|m| m.element)
+        .collect()
+}
+
+// Deterministic pseudo random vectors, so recall doesn't depend on the run
+fn random_vectors(count: usize, dim: usize, seed: u64) -> Vec<Vec<f32>> {
+    let mut state = seed;
+    let mut next = move || {
+        state = state
+            .wrapping_mul(6364136223846793005)
+            .wrapping_add(1442695040888963407);
+        ((state >> 33) as f32 / (1u64 << 31) as f32) * 2.0 - 1.0
+    };
+    (0..count)
+        .map(|_| (0..dim).map(|_| next()).collect())
+        .collect()
+}
+
+fn assert_near(actual: f32, expected: f32, tolerance: f32) {
+    assert!(
+        (actual - expected).abs() <= tolerance,
+        "{} is not within {} of {}",
+        actual,
+        tolerance,
+        expected
+    );
+}
+
+// === Db ===
+
+#[test]
+fn vadd_stores_vectors() {
+    let db = Db::new();
+    assert!(db.vadd(&b("v"), &[3.0, 4.0], &b("a"), noquant()).unwrap());
+    assert!(db.vadd(&b("v"), &[1.0, 0.0], &b("b"), noquant()).unwrap());
+    assert_eq!(db.vcard(&b("v")).unwrap(), 2);
+    assert_eq!(db.vdim(&b("v")).unwrap(), 2);
+    assert_eq!(db.vemb(&b("v"), &b("a")).unwrap(), Some(vec![3.0, 4.0]));
+    assert_eq!(db.vemb(&b("v"), &b("missing")).unwrap(), None);
+    assert_eq!(db.vemb(&b("missing"), &b("a")).unwrap(), None);
+
+    // Adding again replaces the vector
+    assert!(!db.vadd(&b("v"), &[0.0, 2.0], &b("a"), noquant()).unwrap());
+    assert_eq!(db.vemb(&b("v"), &b("a")).unwrap(), Some(vec![0.0, 2.0]));
+    assert_eq!(db.vcard(&b("v")).unwrap(), 2);
+
+    assert_eq!(
+        db.vadd(&b("v"), &[1.0, 2.0, 3.0], &b("c"), noquant())
+            .unwrap_err()
+            .to_string(),
+        "Vector dimension mismatch - got 3 but set has 2"
+    );
+    assert_eq!(
+        db.vadd(&b("v"), &[1.0, 2.0], &b("c"), VAddOptions::default())
+            .unwrap_err()
+            .to_string(),
+        "asked quantization mismatch with existing vector set"
+    );
+    assert_eq!(db.vcard(&b("missing")).unwrap(), 0);
+    assert_eq!(
+        db.vdim(&b("missing")).unwrap_err().to_string(),
+        "key does not exist"
+    );
+
+    db.set(&b("plain"), b("v"), None);
+    assert!(db.vadd(&b("plain"), &[1.0], &b("a"), noquant()).is_err());
+}
+
+#[test]
+fn quantized_embeddings_are_approximate() {
+    let db = Db::new();
+    db.vadd(&b("q8"), &[0.5, -1.0, 2.0], &b("a"), VAddOptions::default())
+        .unwrap();
+    let emb = db.vemb(&b("q8"), &b("a")).unwrap().unwrap();
+    for (actual, expected) in emb.into_iter().zip([0.5, -1.0, 2.0]) {
+        assert_near(actual, expected, 0.02);
+    }
+
+    let bin = VAddOptions {
+        quantization: Quantization::Bin,
+        ..VAddOptions::default()
+    };
+    db.vadd(&b("bin"), &[3.0, -4.0, 0.0, 0.0], &b("a"), bin.clone())
+        .unwrap();
+    db.vadd(&b("bin"), &[1.0, -9.0, -1.0, -2.0], &b("b"), bin)
+        .unwrap();
+    // Only the signs are kept
+    assert_eq!(
+        db.vemb(&b("bin"), &b("a")).unwrap(),
+        Some(vec![2.5, -2.5, -2.5, -2.5])
+    );
+    let matches = db
+        .vsim(&b("bin"), &query(VSimTarget::Element(b("a")), 2))
+        .unwrap();
+    assert_eq!(matches[0].score, 1.0);
+    assert_eq!(matches[1].score, 1.0);
+}
+
+#[test]
+fn vsim_ranks_by_cosine_similarity() {
+    let db = Db::new();
+    let points = [
+        ("east", [1.0, 0.0]),
+        ("northeast", [1.0, 1.0]),
+        ("north", [0.0, 5.0]),
+        ("west", [-2.0, 0.0]),
+    ];
+    for (name, vector) in points {
+        db.vadd(&b("dirs"), &vector, &b(name), noquant()).unwrap();
+    }
+
+    let matches = db
+        .vsim(&b("dirs"), &query(VSimTarget::Vector(vec![10.0, 1.0]), 10))
+        .unwrap();
+    let order: Vec<Bytes> = matches.iter().map(|m| m.element.clone()).collect();
+    assert_eq!(
+        order,
+        vec![b("east"), b("northeast"), b("north"), b("west")]
+    );
+    assert!(matches.windows(2).all(|w| w[0].score >= w[1].score));
+
+    // An element finds itself first, with a perfect score
+    let matches = db
+        .vsim(&b("dirs"), &query(VSimTarget::Element(b("north")), 2))
+        .unwrap();
+    assert_eq!(matches[0].element, b("north"));
+    assert!((matches[0].score - 1.0).abs() < 1e-6);
+    assert_eq!(matches[1].element, b("northeast"));
+    assert!((matches[1].score - (1.0 + 0.5f64.sqrt()) / 2.0).abs() < 1e-6);
+
+    let opposite = db
+        .vsim(&b("dirs"), &query(VSimTarget::Element(b("west")), 4))
+        .unwrap();
+    assert_eq!(opposite[3].element, b("east"));
+    assert!(opposite[3].score.abs() < 1e-6);
+
+    assert_eq!(
+        db.vsim(&b("dirs"), &query(VSimTarget::Element(b("south")), 1))
+            .unwrap_err()
+            .to_string(),
+        "element not found in set"
+    );
+    assert_eq!(
+        db.vsim(&b("dirs"), &query(VSimTarget::Vector(vec![1.0]), 1))
+            .unwrap_err()
+            .to_string(),
+        "Vector dimension mismatch - got 1 but set has 2"
+    );
+    assert_eq!(
+        names(&db, "missing", &query(VSimTarget::Vector(vec![1.0]), 1)),
+        Vec::<Bytes>::new()
+    );
+}
+
+#[test]
+fn graph_search_matches_a_full_scan() {
+    let db = Db::new();
+    let vectors = random_vectors(600, 16, 7);
+    for (i, vector) in vectors.iter().enumerate() {
+        db.vadd(&b("emb"), vector, &b(&i.to_string()), noquant())
+            .unwrap();
+    }
+
+    let recall = |db: &Db| {
+        let mut hits = 0;
+        for target in random_vectors(20, 16, 99) {
+            let approx = query(VSimTarget::Vector(target), 10);
+            let exact = VSimQuery {
+                truth: true,
+                ..approx.clone()
+            };
+            let expected = names(db, "emb", &exact);
+            hits += names(db, "emb", &approx)
+                .iter()
+                .filter(|n| expected.contains(n))
+                .count();
+        }
+        hits as f64 / 200.0
+    };
+    assert!(recall(&db) >= 0.95, "recall {}", recall(&db));
+
+    // Removing most elements leaves a graph that still finds the rest
+    for i in (0..600).filter(|i| i % 3 != 0) {
+        assert!(db.vrem(&b("emb"), &b(&i.to_string())).unwrap());
+    }
+    assert_eq!(db.vcard(&b("emb")).unwrap(), 200);
+    assert!(recall(&db) >= 0.95, "recall after removal {}", recall(&db));
+    let remaining = names(
+        &db,
+        "emb",
+        &query(VSimTarget::Vector(vectors[1].clone()), 200),
+    );
+    assert_eq!(remaining.len(), 200);
+    assert!(
+        remaining
+            .iter()
+            .all(|n| std::str::from_utf8(n).unwrap().parse::<usize>().unwrap() % 3 == 0)
+    );
+}
+
+#[test]
+fn vrem_deletes_the_last_element_with_the_key() {
+    let db = Db::new();
+    db.vadd(&b("v"), &[1.0, 0.0], &b("a"), VAddOptions::default())
+        .unwrap();
+    db.vadd(&b("v"), &[0.0, 1.0], &b("b"), VAddOptions::default())
+        .unwrap();
+    assert!(db.vrem(&b("v"), &b("a")).unwrap());
+    assert!(!db.vrem(&b("v"), &b("a")).unwrap());
+    assert_eq!(
+        names(&db, "v", &query(VSimTarget::Vector(vec![1.0, 0.0]), 5)),
+        vec![b("b")]
+    );
+    assert!(db.vrem(&b("v"), &b("b")).unwrap());
+    assert!(db.vdim(&b("v")).is_err());
+    assert!(!db.vrem(&b("v"), &b("b")).unwrap());
+}
+
+#[test]
+fn attributes_and_filters() {
+    let db = Db::new();
+    let movies = [
+        (
+            "alien",
+            [1.0, 0.1],
+            Some(json!({"year": 1979, "genre": "scifi", "rating": 8.5})),
+        ),
+        (
+            "arrival",
+            [1.0, 0.2],
+            Some(json!({"year": 2016, "genre": "scifi", "rating": 7.9})),
+        ),
+        (
+            "heat",
+            [1.0, 0.3],
+            Some(json!({"year": 1995, "genre": "crime", "tags": ["heist"]})),
+        ),
+        ("untagged", [1.0, 0.0], None),
+    ];
+    for (name, vector, attributes) in movies {
+        let options = VAddOptions {
+            attributes,
+            ..VAddOptions::default()
+        };
+        db.vadd(&b("movies"), &vector, &b(name), options).unwrap();
+    }
+
+    let filtered = |expression: &str| {
+        let query = VSimQuery {
+            filter: Some(VFilter::parse(expression).unwrap()),
+            ..query(VSimTarget::Vector(vec![1.0, 0.0]), 10)
+        };
+        names(&db, "movies", &query)
+    };
+    assert_eq!(
+        filtered(".genre == \"scifi\""),
+        vec![b("alien"), b("arrival")]
+    );
+    assert_eq!(
+        filtered(".year > 1990 and .genre != 'scifi'"),
+        vec![b("heat")]
+    );
+    assert_eq!(
+        filtered(".year < 1980 || .rating >= 7.9 && .year > 2000"),
+        vec![b("alien"), b("arrival")]
+    );
+    assert_eq!(filtered("\"heist\" in .tags"), vec![b("heat")]);
+    assert_eq!(
+        filtered(".genre in [\"crime\", \"drama\"]"),
+        vec![b("heat")]
+    );
+    assert_eq!(filtered("'sci' in .genre"), vec![b("alien"), b("arrival")]);
+    assert_eq!(
+        filtered("(.year - 1900) * 2 ** 2 % 100 == 16"),
+        vec![b("alien")]
+    );
+    assert_eq!(filtered("not (.year > 1980)"), vec![b("alien")]);
+    assert!(filtered("!.tags").is_empty());
+    assert!(filtered(".missing == 1").is_empty());
+
+    assert_eq!(
+        db.vgetattr(&b("movies"), &b("heat")).unwrap(),
+        Some(json!({"year": 1995, "genre": "crime", "tags": ["heist"]}))
+    );
+    assert!(
+        db.vsetattr(
+            &b("movies"),
+            &b("untagged"),
+            Some(json!({"genre": "scifi"}))
+        )
+        .unwrap()
+    );
+    assert_eq!(
+        filtered(".genre == \"scifi\""),
+        vec![b("untagged"), b("alien"), b("arrival")]
+    );
+    assert!(db.vsetattr(&b("movies"), &b("alien"), None).unwrap());
+    assert_eq!(db.vgetattr(&b("movies"), &b("alien")).unwrap(), None);
+    assert!(!db.vsetattr(&b("movies"), &b("nope"), None).unwrap());
+
+    // Replacing a vector keeps the attributes unless new ones are given
+    db.vadd(
+        &b("movies"),
+        &[0.0, 1.0],
+        &b("heat"),
+        VAddOptions::default(),
+    )
+    .unwrap();
+    assert!(db.vgetattr(&b("movies"), &b("heat")).unwrap().is_some());
+
+    let matches = db
+        .vsim(&b("movies"), &query(VSimTarget::Element(b("arrival")), 1))
+        .unwrap();
+    assert_eq!(matches[0].attributes.as_ref().unwrap()["year"], 2016);
+}
+
+#[test]
+fn filter_expressions_parse() {
+    for ok in [
+        ".a",
+        ".a == 1",
+        "-.a < -1.5e3",
+        ".a in [1, 'two', \"three\"]",
+        "(.a > 1 and .b) or not .c",
+        ".s == 'it\\'s'",
+        "true && !false",
+    ] {
+        assert!(VFilter::parse(ok).is_some(), "{}", ok);
+    }
+    for bad in [
+        "",
+        ".a ==",
+        "(.a",
+        ".a == 'open",
+        "[1, 2",
+        "a == 1",
+        ".a 1",
+        "==",
+    ] {
+        assert!(VFilter::parse(bad).is_none(), "{}", bad);
+    }
+}
+
+// === Parsing ===
+
+#[test]
+fn parse_vector_commands() {
+    let Command::VAdd {
+        values, options, ..
+    } = Command::from_frame(cmd_frame(&[
+        "VADD",
+        "k",
+        "VALUES",
+        "3",
+        "1",
+        "2.5",
+        "-3",
+        "e",
+        "BIN",
+        "EF",
+        "50",
+        "M",
+        "8",
+        "SETATTR",
+        "{\"a\":1}",
+        "CAS",
+    ]))
+    .unwrap()
+    else {
+        panic!("expected VADD");
+    };
+    assert_eq!(values, vec![1.0, 2.5, -3.0]);
+    assert_eq!(
+        options,
+        VAddOptions {
+            quantization: Quantization::Bin,
+            ef: Some(50),
+            m: Some(8),
+            attributes: Some(json!({"a": 1})),
+        }
+    );
+
+    let blob: Vec<u8> = [1.0f32, -2.0]
+        .iter()
+        .flat_map(|v| v.to_le_bytes())
+        .collect();
+    let frame = Frame::Array(vec![
+        Frame::BulkString(b("VSIM")),
+        Frame::BulkString(b("k")),
+        Frame::BulkString(b("FP32")),
+        Frame::BulkString(Bytes::from(blob)),
+        Frame::BulkString(b("COUNT")),
+        Frame::BulkString(b("3")),
+        Frame::BulkString(b("WITHSCORES")),
+        Frame::BulkString(b("FILTER")),
+        Frame::BulkString(b(".x > 1")),
+    ]);
+    let Command::VSim {
+        query, with_scores, ..
+    } = Command::from_frame(frame).unwrap()
+    else {
+        panic!("expected VSIM");
+    };
+    assert!(with_scores);
+    assert_eq!(query.target, VSimTarget::Vector(vec![1.0, -2.0]));
+    assert_eq!(query.count, 3);
+    assert!(query.filter.is_some());
+
+    assert!(matches!(
+        Command::from_frame(cmd_frame(&["VSETATTR", "k", "e", ""])).unwrap(),
+        Command::VSetAttr {
+            attributes: None,
+            ..
+        }
+    ));
+
+    for bad in [
+        &["VADD", "k", "VALUES", "2", "1", "e"][..],
+        &["VADD", "k", "VALUES", "0", "e"],
+        &["VADD", "k", "VALUES", "1", "x", "e"],
+        &["VADD", "k", "FP32", "abc", "e"],
+        &["VADD", "k", "1", "2", "e"],
+        &["VADD", "k", "REDUCE", "2", "VALUES", "1", "1", "e"],
+        &["VADD", "k", "VALUES", "1", "1", "e", "SETATTR", "{bad"],
+        &["VADD", "k", "VALUES", "1", "1", "e", "M", "0"],
+        &["VSIM", "k", "ELE", "e", "COUNT", "0"],
+        &["VSIM", "k", "ELE", "e", "FILTER", ".a =="],
+        &["VSIM", "k", "ELE", "e", "BOGUS"],
+        &["VSETATTR", "k", "e", "not json"],
+    ] {
+        assert!(Command::from_frame(cmd_frame(bad)).is_err(), "{:?}", bad);
+    }
+}
+
+// === Integration ===
+
+#[tokio::test]
+async fn vector_sets_over_tcp() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    tokio::spawn(async move { run_server(listener, Db::new()).await });
+
+    let client = redis::Client::open(format!("redis://127.0.0.1:{}", port)).unwrap();
+    let mut con = client.get_multiplexed_async_connection().await.unwrap();
+
+    for (name, x, y, year) in [
+        ("a", 1.0, 0.0, 2001),
+        ("b", 0.0, 1.0, 1999),
+        ("c", 1.0, 1.0, 2020),
+    ] {
+        let added: i64 = redis::cmd("VADD")
+            .arg("points")
+            .arg("VALUES")
+            .arg(2)
+            .arg(x)
+            .arg(y)
+            .arg(name)
+            .arg("NOQUANT")
+            .arg("SETATTR")
+            .arg(format!("{{\"year\":{}}}", year))
+            .query_async(&mut con)
+            .await
+            .unwrap();
+        assert_eq!(added, 1);
+    }
+    let blob: Vec<u8> = [0.0f32, -1.0]
+        .iter()
+        .flat_map(|v| v.to_le_bytes())
+        .collect();
+    let added: i64 = redis::cmd("VADD")
+        .arg("points")
+        .arg("FP32")
+        .arg(blob)
+        .arg("d")
+        .arg("NOQUANT")
+        .query_async(&mut con)
+        .await
+        .unwrap();
+    assert_eq!(added, 1);
+
+    let card: i64 = redis::cmd("VCARD")
+        .arg("points")
+        .query_async(&mut con)
+        .await
+        .unwrap();
+    assert_eq!(card, 4);
+    let dim: i64 = redis::cmd("VDIM")
+        .arg("points")
+        .query_async(&mut con)
+        .await
+        .unwrap();
+    assert_eq!(dim, 2);
+
+    let similar: Vec<String> = redis::cmd("VSIM")
+        .arg("points")
+        .arg("ELE")
+        .arg("a")
+        .arg("WITHSCORES")
+        .arg("COUNT")
+        .arg(2)
+        .query_async(&mut con)
+        .await
+        .unwrap();
+    assert_eq!(similar[0], "a");
+    assert_eq!(similar[1], "1");
+    assert_eq!(similar[2], "c");
+    assert!(similar[3].starts_with("0.85355"), "{}", similar[3]);
+
+    let filtered: Vec<String> = redis::cmd("VSIM")
+        .arg("points")
+        .arg("VALUES")
+        .arg(2)
+        .arg(1)
+        .arg(0)
+        .arg("FILTER")
+        .arg(".year >= 2000")
+        .arg("WITHATTRIBS")
+        .query_async(&mut con)
+        .await
+        .unwrap();
+    assert_eq!(
+        filtered,
+        vec!["a", "{\"year\":2001}", "c", "{\"year\":2020}"]
+    );
+
+    let emb: Vec<f64> = redis::cmd("VEMB")
+        .arg("points")
+        .arg("d")
+        .query_async(&mut con)
+        .await
+        .unwrap();
+    assert_eq!(emb, vec![0.0, -1.0]);
+
+    let set: i64 = redis::cmd("VSETATTR")
+        .arg("points")
+        .arg("d")
+        .arg("{\"year\":1950}")
+        .query_async(&mut con)
+        .await
+        .unwrap();
+    assert_eq!(set, 1);
+    let attrs: Option<String> = redis::cmd("VGETATTR")
+        .arg("points")
+        .arg("d")
+        .query_async(&mut con)
+        .await
+        .unwrap();
+    assert_eq!(attrs.as_deref(), Some("{\"year\":1950}"));
+
+    let removed: i64 = redis::cmd("VREM")
+        .arg("points")
+        .arg("d")
+        .query_async(&mut con)
+        .await
+        .unwrap();
+    assert_eq!(removed, 1);
+
+    let err = redis::cmd("VADD")
+        .arg("points")
+        .arg("VALUES")
+        .arg(1)
+        .arg(1)
+        .arg("e")
+        .arg("NOQUANT")
+        .query_async::<i64>(&mut con)
+        .await
+        .unwrap_err();
+    assert!(err.to_string().contains("dimension mismatch"), "{}", err);
+
+    let err = redis::cmd("VDIM")
+        .arg("nope")
+        .query_async::<i64>(&mut con)
+        .await
+        .unwrap_err();
+    assert!(err.to_string().contains("does not exist"), "{}", err);
+}
@@ -44,6 +44,13 @@ fn parse_empty_bulk_string() {
     assert_eq!(frame, Frame::BulkString(Bytes::from("")));
 }
 
+#[test]
+fn parse_bulk_string_with_line_breaks() {
+    let mut cursor = Cursor::new(&b"$6\r\na\r\nb\nc\r\n"[..]);
+    let frame = Frame::parse(&mut cursor).unwrap();
+    assert_eq!(frame, Frame::BulkString(Bytes::from("a\r\nb\nc")));
+}
+
 #[test]
 fn parse_null() {
     let mut cursor = Cursor::new(&b"$-1\r\n"[..]);
@@ -0,0 +1,689 @@
+use bytes::Bytes;
+use padis::db::{FieldSpec, FieldType, FtQuery, FtSearchOptions, IndexSpec};
+use padis::{Command, Db, Frame, run_server};
+use tokio::net::TcpListener;
+
+fn b(s: &str) -> Bytes {
+    Bytes::copy_from_slice(s.as_bytes())
+}
+
+// Helper to build a command frame
+fn cmd_frame(args: &[&str]) -> Frame {
+    Frame::Array(
+        args.iter()
+            .map(|s| Frame::BulkString(Bytes::copy_from_slice(s.as_bytes())))
+            .collect(),
+    )
+}
+
+fn hset(db: &Db, key: &str, pairs: &[(&str, &str)]) {
+    db.hset(&b(key), pairs.iter().map(|(f, v)| (b(f), b(v))).collect())
+        .unwrap();
+}
+
+fn field(name: &str, kind: FieldType) -> FieldSpec {
+    FieldSpec {
+        name: b(name),
+        alias: None,
+        kind,
+        sortable: false,
+    }
+}
+
+fn tag() -> FieldType {
+    FieldType::Tag {
+        separator: ',',
+        case_sensitive: false,
+    }
+}
+
+fn text() -> FieldType {
+    FieldType::Text { weight: 1.0 }
+}
+
+// A product catalogue under `product:`, indexed by `idx`
+fn catalogue() -> Db {
+    let db = Db::new();
+    hset(
+        &db,
+        "product:1",
+        &[
+            ("name", "Red running shoes"),
+            ("tags", "Sport,Shoes"),
+            ("price", "80"),
+        ],
+    );
+    hset(
+        &db,
+        "product:2",
+        &[
+            ("name", "Blue rain jacket"),
+            ("tags", "outdoor"),
+            ("price", "120.5"),
+        ],
+    );
+    hset(
+        &db,
+        "product:3",
+        &[
+            ("name", "Trail running jacket"),
+            ("tags", "sport, outdoor"),
+            ("price", "150"),
+        ],
+    );
+    hset(&db, "other:1", &[("name", "running club"), ("price", "5")]);
+    db.ft_create(
+        &b("idx"),
+        IndexSpec {
+            prefixes: vec![b("product:")],
+            schema: vec![
+                field("name", text()),
+                field("tags", tag()),
+                field("price", FieldType::Numeric),
+            ],
+        },
+    )
+    .unwrap();
+    db
+}
+
+fn search(db: &Db, query: &str) -> Vec<Bytes> {
+    search_with(db, query, &FtSearchOptions::default())
+}
+
+fn search_with(db: &Db, query: &str, options: &FtSearchOptions) -> Vec<Bytes> {
+    let results = db
+        .ft_search(&b("idx"), &FtQuery::parse(query).unwrap(), options)
+        .unwrap();
+    results.docs.into_iter().map(|doc| doc.key).collect()
+}
+
+fn sorted(mut keys: Vec<Bytes>) -> Vec<Bytes> {
+    keys.sort();
+    keys
+}
+
+// === Db ===
+
+#[test]
+fn hash_commands() {
+    let db = Db::new();
+    assert_eq!(
+        db.hset(&b("h"), vec![(b("a"), b("1")), (b("b"), b("2"))])
+            .unwrap(),
+        2
+    );
+    assert_eq!(
+        db.hset(&b("h"), vec![(b("a"), b("3")), (b("c"), b("4"))])
+            .unwrap(),
+        1
+    );
+    assert_eq!(db.hget(&b("h"), &b("a")).unwrap(), Some(b("3")));
+    assert_eq!(db.hget(&b("h"), &b("z")).unwrap(), None);
+    assert_eq!(db.hlen(&b("h")).unwrap(), 3);
+    let mut all = db.hgetall(&b("h")).unwrap();
+    all.sort();
+    assert_eq!(
+        all,
+        vec![(b("a"), b("3")), (b("b"), b("2")), (b("c"), b("4"))]
+    );
+
+    assert_eq!(db.hdel(&b("h"), &[b("a"), b("z")]).unwrap(), 1);
+    assert_eq!(db.hdel(&b("h"), &[b("b"), b("c")]).unwrap(), 2);
+    assert_eq!(db.hlen(&b("h")).unwrap(), 0);
+    assert!(!db.del(&b("h")));
+
+    db.set(&b("s"), b("v"), None);
+    assert!(db.hset(&b("s"), vec![(b("a"), b("1"))]).is_err());
+    assert!(db.hget(&b("s"), &b("a")).is_err());
+}
+
+#[test]
+fn tag_numeric_and_text_queries() {
+    let db = catalogue();
+    assert_eq!(
+        sorted(search(&db, "*")),
+        vec![b("product:1"), b("product:2"), b("product:3")]
+    );
+
+    // Tags are case insensitive and trimmed
+    assert_eq!(
+        sorted(search(&db, "@tags:{SPORT}")),
+        vec![b("product:1"), b("product:3")]
+    );
+    assert_eq!(
+        sorted(search(&db, "@tags:{shoes | outdoor}")),
+        vec![b("product:1"), b("product:2"), b("product:3")]
+    );
+    assert_eq!(
+        search(&db, "@tags:{out*} @tags:{sport}"),
+        vec![b("product:3")]
+    );
+
+    assert_eq!(
+        sorted(search(&db, "@price:[100 150]")),
+        vec![b("product:2"), b("product:3")]
+    );
+    assert_eq!(search(&db, "@price:[100 (150]"), vec![b("product:2")]);
+    assert_eq!(search(&db, "@price:[(120.5 +inf]"), vec![b("product:3")]);
+    assert_eq!(search(&db, "@price:[-inf 80]"), vec![b("product:1")]);
+
+    // Words match any TEXT field, ignoring case
+    assert_eq!(
+        sorted(search(&db, "Running")),
+        vec![b("product:1"), b("product:3")]
+    );
+    assert_eq!(search(&db, "running jacket"), vec![b("product:3")]);
+    assert_eq!(
+        sorted(search(&db, "@name:jack*")),
+        vec![b("product:2"), b("product:3")]
+    );
+    assert_eq!(
+        sorted(search(&db, "@name:(shoes | rain)")),
+        vec![b("product:1"), b("product:2")]
+    );
+    assert_eq!(search(&db, "jacket -@tags:{sport}"), vec![b("product:2")]);
+    assert_eq!(sorted(search(&db, "-running")), vec![b("product:2")]);
+    assert_eq!(
+        sorted(search(&db, "shoes | @price:[140 200]")),
+        vec![b("product:1"), b("product:3")]
+    );
+    assert!(search(&db, "club").is_empty());
+}
+
+#[test]
+fn index_follows_writes() {
+    let db = catalogue();
+    hset(
+        &db,
+        "product:4",
+        &[("name", "Green shoes"), ("price", "60")],
+    );
+    assert_eq!(
+        sorted(search(&db, "shoes")),
+        vec![b("product:1"), b("product:4")]
+    );
+
+    // Changing a value moves the document between terms
+    hset(
+        &db,
+        "product:1",
+        &[("name", "Red sandals"), ("price", "30")],
+    );
+    assert_eq!(search(&db, "shoes"), vec![b("product:4")]);
+    assert_eq!(search(&db, "sandals"), vec![b("product:1")]);
+    assert_eq!(
+        sorted(search(&db, "@price:[0 70]")),
+        vec![b("product:1"), b("product:4")]
+    );
+
+    db.hdel(&b("product:4"), &[b("price")]).unwrap();
+    assert_eq!(search(&db, "@price:[0 70]"), vec![b("product:1")]);
+    assert_eq!(search(&db, "green"), vec![b("product:4")]);
+
+    db.del(&b("product:4"));
+    assert!(search(&db, "green").is_empty());
+
+    // Overwriting a hash with another type drops it from the index
+    db.set(&b("product:2"), b("gone"), None);
+    assert!(search(&db, "rain").is_empty());
+    db.sadd(&b("set"), vec![b("x")]).unwrap();
+    db.set_op_store(padis::db::SetOp::Union, &b("product:3"), &[b("set")])
+        .unwrap();
+    assert_eq!(search(&db, "*"), vec![b("product:1")]);
+    assert_eq!(db.ft_info(&b("idx")).unwrap().num_docs, 1);
+}
+
+#[test]
+fn sorting_paging_and_content() {
+    let db = catalogue();
+    hset(&db, "product:4", &[("name", "Unpriced socks")]);
+    let by_price = |descending| FtSearchOptions {
+        sort_by: Some((b("price"), descending)),
+        ..FtSearchOptions::default()
+    };
+    assert_eq!(
+        search_with(&db, "*", &by_price(false)),
+        vec![
+            b("product:1"),
+            b("product:2"),
+            b("product:3"),
+            b("product:4")
+        ]
+    );
+    assert_eq!(
+        search_with(&db, "*", &by_price(true)),
+        vec![
+            b("product:3"),
+            b("product:2"),
+            b("product:1"),
+            b("product:4")
+        ]
+    );
+    let by_name = FtSearchOptions {
+        sort_by: Some((b("name"), false)),
+        ..FtSearchOptions::default()
+    };
+    assert_eq!(
+        search_with(&db, "*", &by_name),
+        vec![
+            b("product:2"),
+            b("product:1"),
+            b("product:3"),
+            b("product:4")
+        ]
+    );
+
+    let page = FtSearchOptions {
+        offset: 1,
+        limit: 2,
+        ..by_price(false)
+    };
+    let results = db
+        .ft_search(&b("idx"), &FtQuery::parse("*").unwrap(), &page)
+        .unwrap();
+    assert_eq!(results.total, 4);
+    assert_eq!(
+        results
+            .docs
+            .iter()
+            .map(|d| d.key.clone())
+            .collect::<Vec<_>>(),
+        vec![b("product:2"), b("product:3")]
+    );
+
+    let mut fields = results.docs[0].fields.clone();
+    fields.sort();
+    assert_eq!(
+        fields,
+        vec![
+            (b("name"), b("Blue rain jacket")),
+            (b("price"), b("120.5")),
+            (b("tags"), b("outdoor")),
+        ]
+    );
+    let returning = FtSearchOptions {
+        return_fields: Some(vec![b("price"), b("missing")]),
+        ..page.clone()
+    };
+    let results = db
+        .ft_search(&b("idx"), &FtQuery::parse("*").unwrap(), &returning)
+        .unwrap();
+    assert_eq!(results.docs[0].fields, vec![(b("price"), b("120.5"))]);
+    let bare = FtSearchOptions {
+        no_content: true,
+        ..page
+    };
+    let results = db
+        .ft_search(&b("idx"), &FtQuery::parse("*").unwrap(), &bare)
+        .unwrap();
+    assert!(results.docs[0].fields.is_empty());
+}
+
+#[test]
+fn text_matches_rank_by_relevance() {
+    let db = Db::new();
+    db.ft_create(
+        &b("docs"),
+        IndexSpec {
+            prefixes: vec![b("")],
+            schema: vec![
+                FieldSpec {
+                    name: b("title"),
+                    alias: Some(b("t")),
+                    kind: FieldType::Text { weight: 5.0 },
+                    sortable: false,
+                },
+                field("body", text()),
+            ],
+        },
+    )
+    .unwrap();
+    hset(
+        &db,
+        "a",
+        &[("title", "other"), ("body", "redis redis redis")],
+    );
+    hset(&db, "b", &[("title", "about redis"), ("body", "nothing")]);
+    hset(&db, "c", &[("title", "else"), ("body", "a redis mention")]);
+    hset(&db, "d", &[("title", "else"), ("body", "unrelated")]);
+
+    let results = db
+        .ft_search(
+            &b("docs"),
+            &FtQuery::parse("redis").unwrap(),
+            &FtSearchOptions::default(),
+        )
+        .unwrap();
+    let keys: Vec<Bytes> = results.docs.iter().map(|d| d.key.clone()).collect();
+    assert_eq!(keys, vec![b("b"), b("a"), b("c")]);
+    assert!(results.docs[0].score > results.docs[1].score);
+
+    // Fields are queried by their alias
+    let results = db
+        .ft_search(
+            &b("docs"),
+            &FtQuery::parse("@t:redis").unwrap(),
+            &FtSearchOptions::default(),
+        )
+        .unwrap();
+    assert_eq!(results.total, 1);
+    assert_eq!(
+        db.ft_search(
+            &b("docs"),
+            &FtQuery::parse("@title:redis").unwrap(),
+            &FtSearchOptions::default(),
+        )
+        .unwrap_err()
+        .to_string(),
+        "Unknown field `title`"
+    );
+}
+
+#[test]
+fn info_errors_and_dropping() {
+    let db = catalogue();
+    hset(
+        &db,
+        "product:bad",
+        &[("name", "Broken"), ("price", "cheap")],
+    );
+    let info = db.ft_info(&b("idx")).unwrap();
+    assert_eq!(info.num_docs, 3);
+    assert_eq!(info.failures, 1);
+    assert_eq!(info.max_doc_id, 3);
+    // red running shoes blue rain jacket trail
+    assert_eq!(info.num_terms, 7);
+    assert_eq!(info.spec.prefixes, vec![b("product:")]);
+
+    let spec = info.spec.clone();
+    assert_eq!(
+        db.ft_create(&b("idx"), spec).unwrap_err().to_string(),
+        "Index already exists"
+    );
+    let unknown = |query: &str| {
+        db.ft_search(
+            &b("idx"),
+            &FtQuery::parse(query).unwrap(),
+            &FtSearchOptions::default(),
+        )
+        .unwrap_err()
+        .to_string()
+    };
+    assert_eq!(unknown("@nope:{x}"), "Unknown field `nope`");
+    assert_eq!(unknown("@price:{x}"), "Field `price` is not a TAG field");
+    assert_eq!(
+        unknown("@tags:[1 2]"),
+        "Field `tags` is not a NUMERIC field"
+    );
+    assert_eq!(unknown("@price:word"), "Field `price` is not a TEXT field");
+    assert_eq!(
+        db.ft_info(&b("missing")).unwrap_err().to_string(),
+        "Unknown index name"
+    );
+
+    db.ft_dropindex(&b("idx"), false).unwrap();
+    assert!(db.ft_info(&b("idx")).is_err());
+    assert_eq!(db.hlen(&b("product:1")).unwrap(), 3);
+
+    let db = catalogue();
+    db.ft_dropindex(&b("idx"), true).unwrap();
+    assert_eq!(db.hlen(&b("product:1")).unwrap(), 0);
+    assert_eq!(db.hlen(&b("other:1")).unwrap(), 2);
+    assert!(db.ft_dropindex(&b("idx"), true).is_err());
+}
+
+#[test]
+fn queries_parse() {
+    for ok in [
+        "*",
+        "hello",
+        "hello world",
+        "hel*",
+        "@f:{a | b\\ c | d*}",
+        "@n:[1 2]",
+        "@n:[(1, +inf]",
+        "-@f:{a} (x | y) @t:(a b)",
+        "a | b | c",
+    ] {
+        assert!(FtQuery::parse(ok).is_some(), "{}", ok);
+    }
+    for bad in [
+        "", "(a", "@f:{}", "@f:{a", "@n:[1]", "@n:[x 2]", "@f", "a |", "* a", ")",
+    ] {
+        assert!(FtQuery::parse(bad).is_none(), "{}", bad);
+    }
+}
+
+// === Parsing ===
+
+#[test]
+fn parse_search_commands() {
+    let Command::FtCreate { name, spec } = Command::from_frame(cmd_frame(&[
+        "FT.CREATE",
+        "idx",
+        "ON",
+        "HASH",
+        "PREFIX",
+        "2",
+        "a:",
+        "b:",
+        "SCHEMA",
+        "title",
+        "AS",
+        "t",
+        "TEXT",
+        "WEIGHT",
+        "2",
+        "SORTABLE",
+        "tags",
+        "TAG",
+        "SEPARATOR",
+        ";",
+        "CASESENSITIVE",
+        "n",
+        "NUMERIC",
+    ]))
+    .unwrap() else {
+        panic!("expected FT.CREATE");
+    };
+    assert_eq!(name, b("idx"));
+    assert_eq!(
+        spec,
+        IndexSpec {
+            prefixes: vec![b("a:"), b("b:")],
+            schema: vec![
+                FieldSpec {
+                    name: b("title"),
+                    alias: Some(b("t")),
+                    kind: FieldType::Text { weight: 2.0 },
+                    sortable: true,
+                },
+                FieldSpec {
+                    name: b("tags"),
+                    alias: None,
+                    kind: FieldType::Tag {
+                        separator: ';',
+                        case_sensitive: true,
+                    },
+                    sortable: false,
+                },
+                field("n", FieldType::Numeric),
+            ],
+        }
+    );
+
+    let Command::FtSearch { options, .. } = Command::from_frame(cmd_frame(&[
+        "FT.SEARCH",
+        "idx",
+        "@n:[1 2]",
+        "NOCONTENT",
+        "SORTBY",
+        "n",
+        "DESC",
+        "LIMIT",
+        "5",
+        "20",
+        "DIALECT",
+        "2",
+    ]))
+    .unwrap() else {
+        panic!("expected FT.SEARCH");
+    };
+    assert_eq!(
+        options,
+        FtSearchOptions {
+            no_content: true,
+            sort_by: Some((b("n"), true)),
+            offset: 5,
+            limit: 20,
+            ..FtSearchOptions::default()
+        }
+    );
+
+    for bad in [
+        &["FT.CREATE", "idx", "ON", "JSON", "SCHEMA", "a", "TEXT"][..],
+        &["FT.CREATE", "idx", "SCHEMA"],
+        &["FT.CREATE", "idx", "SCHEMA", "a", "VECTOR"],
+        &["FT.CREATE", "idx", "SCHEMA", "a", "TEXT", "a", "TAG"],
+        &["FT.CREATE", "idx", "SCHEMA", "a", "TAG", "SEPARATOR", "ab"],
+        &["FT.CREATE", "idx", "PREFIX", "3", "a", "SCHEMA"],
+        &["FT.SEARCH", "idx", "(unclosed"],
+        &["FT.SEARCH", "idx", "*", "LIMIT", "0"],
+        &["FT.SEARCH", "idx", "*", "BOGUS"],
+        &["FT.DROPINDEX", "idx", "XX"],
+        &["HSET", "h", "f"],
+        &["HSET", "h"],
+    ] {
+        assert!(Command::from_frame(cmd_frame(bad)).is_err(), "{:?}", bad);
+    }
+}
+
+// === Integration ===
+
+#[tokio::test]
+async fn search_over_tcp() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    tokio::spawn(async move { run_server(listener, Db::new()).await });
+
+    let client = redis::Client::open(format!("redis://127.0.0.1:{}", port)).unwrap();
+    let mut con = client.get_multiplexed_async_connection().await.unwrap();
+
+    let created: String = redis::cmd("FT.CREATE")
+        .arg(&["users", "PREFIX", "1", "user:", "SCHEMA"])
+        .arg(&["name", "TEXT", "city", "TAG", "age", "NUMERIC", "SORTABLE"])
+        .query_async(&mut con)
+        .await
+        .unwrap();
+    assert_eq!(created, "OK");
+
+    for (key, name, city, age) in [
+        ("user:1", "Ada Lovelace", "London", "36"),
+        ("user:2", "Alan Turing", "London", "41"),
+        ("user:3", "Grace Hopper", "New York", "85"),
+    ] {
+        let added: i64 = redis::cmd("HSET")
+            .arg(key)
+            .arg(&["name", name, "city", city, "age", age])
+            .query_async(&mut con)
+            .await
+            .unwrap();
+        assert_eq!(added, 3);
+    }
+
+    let found: Vec<redis::Value> = redis::cmd("FT.SEARCH")
+        .arg(&["users", "@city:{london}", "SORTBY", "age", "DESC"])
+        .arg(&["RETURN", "1", "name"])
+        .query_async(&mut con)
+        .await
+        .unwrap();
+    assert_eq!(found.len(), 5);
+    assert_eq!(found[0], redis::Value::Int(2));
+    assert_eq!(
+        redis::from_redis_value::<String>(found[1].clone()).unwrap(),
+        "user:2"
+    );
+    assert_eq!(
+        redis::from_redis_value::<Vec<String>>(found[2].clone()).unwrap(),
+        vec!["name", "Alan Turing"]
+    );
+    assert_eq!(
+        redis::from_redis_value::<String>(found[3].clone()).unwrap(),
+        "user:1"
+    );
+
+    let keys: (i64, Vec<String>) = redis::cmd("FT.SEARCH")
+        .arg(&["users", "@city:{new\\ york} | ada", "NOCONTENT"])
+        .query_async(&mut con)
+        .await
+        .map(|v: Vec<redis::Value>| {
+            (
+                redis::from_redis_value(v[0].clone()).unwrap(),
+                v[1..]
+                    .iter()
+                    .map(|k| redis::from_redis_value(k.clone()).unwrap())
+                    .collect(),
+            )
+        })
+        .unwrap();
+    assert_eq!(keys.0, 2);
+    assert_eq!(
+        {
+            let mut k = keys.1;
+            k.sort();
+            k
+        },
+        vec!["user:1", "user:3"]
+    );
+
+    let all: Vec<String> = redis::cmd("HGETALL")
+        .arg("user:3")
+        .query_async(&mut con)
+        .await
+        .unwrap();
+    assert_eq!(all.len(), 6);
+    let age: String = redis::cmd("HGET")
+        .arg(&["user:3", "age"])
+        .query_async(&mut con)
+        .await
+        .unwrap();
+    assert_eq!(age, "85");
+
+    let info: Vec<redis::Value> = redis::cmd("FT.INFO")
+        .arg("users")
+        .query_async(&mut con)
+        .await
+        .unwrap();
+    assert_eq!(
+        redis::from_redis_value::<String>(info[8].clone()).unwrap(),
+        "num_docs"
+    );
+    assert_eq!(info[9], redis::Value::Int(3));
+
+    let err = redis::cmd("FT.SEARCH")
+        .arg(&["users", "@city:("])
+        .query_async::<redis::Value>(&mut con)
+        .await
+        .unwrap_err();
+    assert!(err.to_string().contains("error in query"), "{}", err);
+
+    let dropped: String = redis::cmd("FT.DROPINDEX")
+        .arg(&["users", "DD"])
+        .query_async(&mut con)
+        .await
+        .unwrap();
+    assert_eq!(dropped, "OK");
+    let len: i64 = redis::cmd("HLEN")
+        .arg("user:1")
+        .query_async(&mut con)
+        .await
+        .unwrap();
+    assert_eq!(len, 0);
+    let err = redis::cmd("FT.INFO")
+        .arg("users")
+        .query_async::<redis::Value>(&mut con)
+        .await
+        .unwrap_err();
+    assert!(err.to_string().contains("index name"), "{}", err);
+}
@@ -0,0 +1,220 @@
+use bytes::Bytes;
+use padis::db::{Message, SUBSCRIBER_QUEUE};
+use padis::{Command, Connection, Db, Frame, run_server};
+use std::time::Duration;
+use tokio::net::{TcpListener, TcpStream};
+
+fn b(s: &str) -> Bytes {
+    Bytes::copy_from_slice(s.as_bytes())
+}
+
+// Helper to build a command frame
+fn cmd_frame(args: &[&str]) -> Frame {
+    Frame::Array(
+        args.iter()
+            .map(|s| Frame::BulkString(Bytes::copy_from_slice(s.as_bytes())))
+            .collect(),
+    )
+}
+
+fn message(channel: &str, payload: &str) -> Message {
+    Message {
+        pattern: None,
+        channel: b(channel),
+        payload: b(payload),
+        sharded: false,
+    }
+}
+
+// === Db ===
+
+#[test]
+fn subscribe_counts_distinct_channels() {
+    let db = Db::new();
+    let mut sub = db.subscriber();
+    assert_eq!(
+        sub.subscribe(&[b("a"), b("b"), b("a")]),
+        vec![(b("a"), 1), (b("b"), 2), (b("a"), 2)]
+    );
+    assert_eq!(sub.count(), 2);
+    assert_eq!(
+        sub.unsubscribe(&[b("a"), b("c")]),
+        vec![(Some(b("a")), 1), (Some(b("c")), 1)]
+    );
+    assert_eq!(sub.count(), 1);
+}
+
+#[test]
+fn unsubscribe_without_channels_leaves_all() {
+    let db = Db::new();
+    let mut sub = db.subscriber();
+    sub.subscribe(&[b("b"), b("a")]);
+    assert_eq!(
+        sub.unsubscribe(&[]),
+        vec![(Some(b("a")), 1), (Some(b("b")), 0)]
+    );
+    // Nothing left to leave
+    assert_eq!(sub.unsubscribe(&[]), vec![(None, 0)]);
+    assert_eq!(db.publish(&b("a"), &b("x")), 0);
+}
+
+#[tokio::test]
+async fn publish_reaches_every_subscriber() {
+    let db = Db::new();
+    let mut one = db.subscriber();
+    let mut two = db.subscriber();
+    one.subscribe(&[b("news")]);
+    two.subscribe(&[b("news"), b("sport")]);
+
+    assert_eq!(db.publish(&b("news"), &b("hello")), 2);
+    assert_eq!(db.publish(&b("sport"), &b("goal")), 1);
+    assert_eq!(db.publish(&b("weather"), &b("rain")), 0);
+
+    assert_eq!(one.recv().await, Some(message("news", "hello")));
+    assert_eq!(two.recv().await, Some(message("news", "hello")));
+    assert_eq!(two.recv().await, Some(message("sport", "goal")));
+
+    // Dropping a subscriber unsubscribes it
+    drop(two);
+    assert_eq!(db.publish(&b("news"), &b("again")), 1);
+}
+
+#[tokio::test]
+async fn lagging_subscriber_is_dropped() {
+    let db = Db::new();
+    let mut slow = db.subscriber();
+    slow.subscribe(&[b("ch")]);
+
+    for i in 0..SUBSCRIBER_QUEUE {
+        assert_eq!(db.publish(&b("ch"), &b(&i.to_string())), 1);
+    }
+    // The queue is full, so the publisher drops the subscriber instead of waiting
+    assert_eq!(db.publish(&b("ch"), &b("overflow")), 0);
+    assert_eq!(slow.count(), 0);
+    assert_eq!(db.publish(&b("ch"), &b("later")), 0);
+
+    // What was queued is still delivered, then the queue ends
+    for i in 0..SUBSCRIBER_QUEUE {
+        assert_eq!(slow.recv().await, Some(message("ch", &i.to_string())));
+    }
+    assert_eq!(slow.recv().await, None);
+}
+
+// === Parsing ===
+
+#[test]
+fn parse_pubsub_commands() {
+    let Ok(Command::Subscribe { channels }) =
+        Command::from_frame(cmd_frame(&["SUBSCRIBE", "a", "b"]))
+    else {
+        panic!("expected SUBSCRIBE");
+    };
+    assert_eq!(channels, vec![b("a"), b("b")]);
+    assert!(Command::from_frame(cmd_frame(&["SUBSCRIBE"])).is_err());
+
+    let Ok(Command::Unsubscribe { channels }) = Command::from_frame(cmd_frame(&["UNSUBSCRIBE"]))
+    else {
+        panic!("expected UNSUBSCRIBE");
+    };
+    assert!(channels.is_empty());
+
+    let Ok(Command::Publish { channel, message }) =
+        Command::from_frame(cmd_frame(&["PUBLISH", "ch", "hi"]))
+    else {
+        panic!("expected PUBLISH");
+    };
+    assert_eq!((channel, message), (b("ch"), b("hi")));
+    assert!(Command::from_frame(cmd_frame(&["PUBLISH", "ch"])).is_err());
+    assert!(Command::from_frame(cmd_frame(&["PUBLISH", "ch", "a", "b"])).is_err());
+
+    assert!(matches!(
+        Command::from_frame(cmd_frame(&["QUIT"])),
+        Ok(Command::Quit)
+    ));
+}
+
+// === Integration ===
+
+fn array(items: &[&str]) -> Frame {
+    Frame::Array(items.iter().map(|s| Frame::BulkString(b(s))).collect())
+}
+
+fn reply(kind: &str, channel: &str, count: i64) -> Frame {
+    Frame::Array(vec![
+        Frame::BulkString(b(kind)),
+        Frame::BulkString(b(channel)),
+        Frame::Integer(count),
+    ])
+}
+
+async fn connect(port: u16) -> Connection<TcpStream> {
+    Connection::new(TcpStream::connect(("127.0.0.1", port)).await.unwrap())
+}
+
+async fn roundtrip(conn: &mut Connection<TcpStream>, args: &[&str]) -> Frame {
+    conn.write_frame(&cmd_frame(args)).await.unwrap();
+    conn.read_frame().await.unwrap().unwrap()
+}
+
+#[tokio::test]
+async fn pubsub_over_tcp() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    tokio::spawn(async move { run_server(listener, Db::new()).await });
+
+    let mut sub = connect(port).await;
+    let mut publisher = connect(port).await;
+
+    sub.write_frame(&cmd_frame(&["SUBSCRIBE", "news", "sport"]))
+        .await
+        .unwrap();
+    assert_eq!(
+        sub.read_frame().await.unwrap(),
+        Some(reply("subscribe", "news", 1))
+    );
+    assert_eq!(
+        sub.read_frame().await.unwrap(),
+        Some(reply("subscribe", "sport", 2))
+    );
+
+    assert_eq!(
+        roundtrip(&mut publisher, &["PUBLISH", "news", "hello"]).await,
+        Frame::Integer(1)
+    );
+    assert_eq!(
+        sub.read_frame().await.unwrap(),
+        Some(array(&["message", "news", "hello"]))
+    );
+
+    // Only pub/sub commands are allowed while subscribed
+    let Frame::SimpleError(err) = roundtrip(&mut sub, &["SET", "k", "v"]).await else {
+        panic!("expected an error");
+    };
+    assert!(err.starts_with("Can't execute 'set'"), "{}", err);
+    assert_eq!(roundtrip(&mut sub, &["PING"]).await, array(&["pong", ""]));
+
+    assert_eq!(
+        roundtrip(&mut sub, &["UNSUBSCRIBE", "news"]).await,
+        reply("unsubscribe", "news", 1)
+    );
+    assert_eq!(
+        roundtrip(&mut publisher, &["PUBLISH", "news", "ignored"]).await,
+        Frame::Integer(0)
+    );
+    assert_eq!(
+        roundtrip(&mut sub, &["UNSUBSCRIBE"]).await,
+        reply("unsubscribe", "sport", 0)
+    );
+
+    // Back to a normal connection
+    assert_eq!(
+        roundtrip(&mut sub, &["SET", "k", "v"]).await,
+        Frame::SimpleString("OK".into())
+    );
+    assert_eq!(
+        roundtrip(&mut sub, &["QUIT"]).await,
+        Frame::SimpleString("OK".into())
+    );
+    let closed = tokio::time::timeout(Duration::from_secs(1), sub.read_frame()).await;
+    assert!(matches!(closed, Ok(Ok(None))));
+}
@@ -0,0 +1,222 @@
+use bytes::Bytes;
+use padis::db::Message;
+use padis::{Command, Connection, Db, Frame, run_server};
+use tokio::net::{TcpListener, TcpStream};
+
+fn b(s: &str) -> Bytes {
+    Bytes::copy_from_slice(s.as_bytes())
+}
+
+// Helper to build a command frame
+fn cmd_frame(args: &[&str]) -> Frame {
+    Frame::Array(
+        args.iter()
+            .map(|s| Frame::BulkString(Bytes::copy_from_slice(s.as_bytes())))
+            .collect(),
+    )
+}
+
+fn pmessage(pattern: &str, channel: &str, payload: &str) -> Message {
+    Message {
+        pattern: Some(b(pattern)),
+        channel: b(channel),
+        payload: b(payload),
+        sharded: false,
+    }
+}
+
+// Whether a subscription to `pattern` receives a message on `channel`
+fn glob_matches(pattern: &str, channel: &str) -> bool {
+    let db = Db::new();
+    let mut sub = db.subscriber();
+    sub.psubscribe(&[b(pattern)]);
+    db.publish(&b(channel), &b("x")) == 1
+}
+
+// === Db ===
+
+#[test]
+fn glob_semantics() {
+    let cases = [
+        ("orders.*", "orders.eu", true),
+        ("orders.*", "orders.", true),
+        ("orders.*", "orders", false),
+        ("*", "", true),
+        ("h?llo", "hello", true),
+        ("h?llo", "hllo", false),
+        ("h[ae]llo", "hallo", true),
+        ("h[ae]llo", "hillo", false),
+        ("h[^e]llo", "hallo", true),
+        ("h[^e]llo", "hello", false),
+        ("h[a-c]llo", "hbllo", true),
+        ("h[c-a]llo", "hbllo", true),
+        ("h[a-c]llo", "hdllo", false),
+        ("a\\*b", "a*b", true),
+        ("a\\*b", "axb", false),
+        ("[\\]]", "]", true),
+        ("*a*b*c", "xxaxxbxxbxc", true),
+        ("*a*b*c", "xxaxxbxxbx", false),
+        ("a**b", "ab", true),
+        ("a[bc", "ab", true),
+        ("a[]", "a]", false),
+        ("news", "news", true),
+        ("news", "newsletter", false),
+    ];
+    for (pattern, channel, expected) in cases {
+        assert_eq!(
+            glob_matches(pattern, channel),
+            expected,
+            "{} against {}",
+            pattern,
+            channel
+        );
+    }
+}
+
+#[tokio::test]
+async fn pattern_and_channel_subscriptions_both_deliver() {
+    let db = Db::new();
+    let mut sub = db.subscriber();
+    assert_eq!(sub.subscribe(&[b("orders.eu")]), vec![(b("orders.eu"), 1)]);
+    assert_eq!(
+        sub.psubscribe(&[b("orders.*"), b("*.eu"), b("orders.*")]),
+        vec![(b("orders.*"), 2), (b("*.eu"), 3), (b("orders.*"), 3)]
+    );
+
+    // One delivery per matching subscription
+    assert_eq!(db.publish(&b("orders.eu"), &b("o1")), 3);
+    assert_eq!(db.publish(&b("orders.us"), &b("o2")), 1);
+    assert_eq!(db.publish(&b("users.eu"), &b("u1")), 1);
+    assert_eq!(db.publish(&b("users.us"), &b("u2")), 0);
+
+    let mut received = Vec::new();
+    for _ in 0..5 {
+        received.push(sub.recv().await.unwrap());
+    }
+    assert_eq!(received[0].pattern, None);
+    let mut patterned: Vec<_> = received[..3]
+        .iter()
+        .filter_map(|m| m.pattern.clone())
+        .collect();
+    patterned.sort();
+    assert_eq!(patterned, vec![b("*.eu"), b("orders.*")]);
+    assert_eq!(received[3], pmessage("orders.*", "orders.us", "o2"));
+    assert_eq!(received[4], pmessage("*.eu", "users.eu", "u1"));
+}
+
+#[test]
+fn punsubscribe_leaves_patterns_only() {
+    let db = Db::new();
+    let mut sub = db.subscriber();
+    sub.subscribe(&[b("news")]);
+    sub.psubscribe(&[b("b*"), b("a*")]);
+
+    assert_eq!(
+        sub.punsubscribe(&[]),
+        vec![(Some(b("a*")), 2), (Some(b("b*")), 1)]
+    );
+    assert_eq!(sub.punsubscribe(&[]), vec![(None, 1)]);
+    assert_eq!(db.publish(&b("abc"), &b("x")), 0);
+    assert_eq!(db.publish(&b("news"), &b("x")), 1);
+}
+
+#[tokio::test]
+async fn thousands_of_patterns() {
+    let db = Db::new();
+    let mut subs: Vec<_> = (0..2000)
+        .map(|i| {
+            let mut sub = db.subscriber();
+            sub.psubscribe(&[b(&format!("tenant{}.*", i))]);
+            sub
+        })
+        .collect();
+    let mut catch_all = db.subscriber();
+    catch_all.psubscribe(&[b("*.audit")]);
+
+    for i in 0..2000 {
+        assert_eq!(db.publish(&b(&format!("tenant{}.orders", i)), &b("o")), 1);
+    }
+    assert_eq!(db.publish(&b("tenant7.audit"), &b("a")), 2);
+
+    assert_eq!(
+        subs[42].recv().await,
+        Some(pmessage("tenant42.*", "tenant42.orders", "o"))
+    );
+    assert_eq!(
+        catch_all.recv().await,
+        Some(pmessage("*.audit", "tenant7.audit", "a"))
+    );
+
+    drop(subs.remove(7));
+    assert_eq!(db.publish(&b("tenant7.audit"), &b("a")), 1);
+}
+
+// === Parsing ===
+
+#[test]
+fn parse_pattern_commands() {
+    let Ok(Command::PSubscribe { patterns }) =
+        Command::from_frame(cmd_frame(&["PSUBSCRIBE", "a*", "b?"]))
+    else {
+        panic!("expected PSUBSCRIBE");
+    };
+    assert_eq!(patterns, vec![b("a*"), b("b?")]);
+    assert!(Command::from_frame(cmd_frame(&["PSUBSCRIBE"])).is_err());
+
+    let Ok(Command::PUnsubscribe { patterns }) = Command::from_frame(cmd_frame(&["PUNSUBSCRIBE"]))
+    else {
+        panic!("expected PUNSUBSCRIBE");
+    };
+    assert!(patterns.is_empty());
+}
+
+// === Integration ===
+
+async fn roundtrip(conn: &mut Connection<TcpStream>, args: &[&str]) -> Frame {
+    conn.write_frame(&cmd_frame(args)).await.unwrap();
+    conn.read_frame().await.unwrap().unwrap()
+}
+
+fn array(items: &[&str]) -> Frame {
+    Frame::Array(items.iter().map(|s| Frame::BulkString(b(s))).collect())
+}
+
+fn reply(kind: &str, name: &str, count: i64) -> Frame {
+    Frame::Array(vec![
+        Frame::BulkString(b(kind)),
+        Frame::BulkString(b(name)),
+        Frame::Integer(count),
+    ])
+}
+
+#[tokio::test]
+async fn pattern_subscriptions_over_tcp() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    tokio::spawn(async move { run_server(listener, Db::new()).await });
+
+    let mut sub = Connection::new(TcpStream::connect(("127.0.0.1", port)).await.unwrap());
+    let mut publisher = Connection::new(TcpStream::connect(("127.0.0.1", port)).await.unwrap());
+
+    assert_eq!(
+        roundtrip(&mut sub, &["PSUBSCRIBE", "orders.*"]).await,
+        reply("psubscribe", "orders.*", 1)
+    );
+    assert_eq!(
+        roundtrip(&mut publisher, &["PUBLISH", "orders.eu", "o1"]).await,
+        Frame::Integer(1)
+    );
+    assert_eq!(
+        sub.read_frame().await.unwrap(),
+        Some(array(&["pmessage", "orders.*", "orders.eu", "o1"]))
+    );
+
+    assert_eq!(
+        roundtrip(&mut sub, &["PUNSUBSCRIBE"]).await,
+        reply("punsubscribe", "orders.*", 0)
+    );
+    assert_eq!(
+        roundtrip(&mut sub, &["PING"]).await,
+        Frame::SimpleString("PONG".into())
+    );
+}
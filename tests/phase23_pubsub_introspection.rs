@@ -0,0 +1,224 @@
+use bytes::Bytes;
+use padis::db::{Message, key_slot};
+use padis::{Command, Connection, Db, Frame, run_server};
+use tokio::net::{TcpListener, TcpStream};
+
+fn b(s: &str) -> Bytes {
+    Bytes::copy_from_slice(s.as_bytes())
+}
+
+// Helper to build a command frame
+fn cmd_frame(args: &[&str]) -> Frame {
+    Frame::Array(
+        args.iter()
+            .map(|s| Frame::BulkString(Bytes::copy_from_slice(s.as_bytes())))
+            .collect(),
+    )
+}
+
+// === Db ===
+
+#[test]
+fn key_slots_match_redis_cluster() {
+    assert_eq!(key_slot(b"123456789"), 12739);
+    assert_eq!(key_slot(b"foo"), 12182);
+    assert_eq!(key_slot(b""), 0);
+    // Only a non-empty hash tag is hashed
+    assert_eq!(key_slot(b"{user1000}.following"), key_slot(b"user1000"));
+    assert_ne!(key_slot(b"foo{}{bar}"), key_slot(b"bar"));
+    assert_eq!(key_slot(b"foo{{bar}}zap"), key_slot(b"{bar"));
+}
+
+#[test]
+fn channels_and_numsub() {
+    let db = Db::new();
+    let mut one = db.subscriber();
+    let mut two = db.subscriber();
+    one.subscribe(&[b("news.tech"), b("sport")]);
+    two.subscribe(&[b("news.tech")]);
+    two.psubscribe(&[b("news.*"), b("weather.*")]);
+    one.psubscribe(&[b("news.*")]);
+
+    assert_eq!(db.pubsub_channels(None), vec![b("news.tech"), b("sport")]);
+    assert_eq!(db.pubsub_channels(Some(b"news.*")), vec![b("news.tech")]);
+    assert_eq!(
+        db.pubsub_numsub(&[b("news.tech"), b("sport"), b("none")]),
+        vec![(b("news.tech"), 2), (b("sport"), 1), (b("none"), 0)]
+    );
+    // Distinct patterns, however many clients share them
+    assert_eq!(db.pubsub_numpat(), 2);
+
+    drop(two);
+    assert_eq!(db.pubsub_numpat(), 1);
+    assert_eq!(
+        db.pubsub_numsub(&[b("news.tech")]),
+        vec![(b("news.tech"), 1)]
+    );
+    one.unsubscribe(&[]);
+    assert!(db.pubsub_channels(None).is_empty());
+}
+
+#[tokio::test]
+async fn shard_channels_are_separate_from_global_ones() {
+    let db = Db::new();
+    let mut sub = db.subscriber();
+    assert_eq!(
+        sub.ssubscribe(&[b("{orders}.eu"), b("{orders}.us")]),
+        vec![(b("{orders}.eu"), 1), (b("{orders}.us"), 2)]
+    );
+    // Global subscriptions are counted on their own
+    assert_eq!(
+        sub.subscribe(&[b("{orders}.eu")]),
+        vec![(b("{orders}.eu"), 1)]
+    );
+    assert_eq!(sub.count(), 3);
+
+    assert_eq!(db.spublish(&b("{orders}.eu"), &b("o1")), 1);
+    assert_eq!(db.spublish(&b("{orders}.asia"), &b("o2")), 0);
+    assert_eq!(
+        sub.recv().await,
+        Some(Message {
+            pattern: None,
+            channel: b("{orders}.eu"),
+            payload: b("o1"),
+            sharded: true,
+        })
+    );
+
+    assert_eq!(
+        db.pubsub_shard_channels(None),
+        vec![b("{orders}.eu"), b("{orders}.us")]
+    );
+    assert_eq!(
+        db.pubsub_shard_channels(Some(b"*.us")),
+        vec![b("{orders}.us")]
+    );
+    assert_eq!(
+        db.pubsub_shard_numsub(&[b("{orders}.eu"), b("sport")]),
+        vec![(b("{orders}.eu"), 1), (b("sport"), 0)]
+    );
+    assert_eq!(db.pubsub_channels(None), vec![b("{orders}.eu")]);
+
+    assert_eq!(
+        sub.sunsubscribe(&[]),
+        vec![(Some(b("{orders}.eu")), 1), (Some(b("{orders}.us")), 0)]
+    );
+    assert_eq!(sub.count(), 1);
+    assert!(db.pubsub_shard_channels(None).is_empty());
+}
+
+// === Parsing ===
+
+#[test]
+fn parse_pubsub_subcommands() {
+    let Ok(Command::PubSubChannels { pattern }) =
+        Command::from_frame(cmd_frame(&["PUBSUB", "channels", "a*"]))
+    else {
+        panic!("expected PUBSUB CHANNELS");
+    };
+    assert_eq!(pattern, Some(b("a*")));
+    assert!(Command::from_frame(cmd_frame(&["PUBSUB", "CHANNELS", "a", "b"])).is_err());
+
+    let Ok(Command::PubSubNumSub { channels }) =
+        Command::from_frame(cmd_frame(&["PUBSUB", "NUMSUB"]))
+    else {
+        panic!("expected PUBSUB NUMSUB");
+    };
+    assert!(channels.is_empty());
+    assert!(matches!(
+        Command::from_frame(cmd_frame(&["PUBSUB", "NUMPAT"])),
+        Ok(Command::PubSubNumPat)
+    ));
+    assert!(Command::from_frame(cmd_frame(&["PUBSUB", "NUMPAT", "x"])).is_err());
+    assert!(Command::from_frame(cmd_frame(&["PUBSUB", "NOPE"])).is_err());
+    assert!(Command::from_frame(cmd_frame(&["PUBSUB"])).is_err());
+}
+
+#[test]
+fn parse_shard_commands() {
+    assert!(matches!(
+        Command::from_frame(cmd_frame(&["SSUBSCRIBE", "{a}.1", "{a}.2"])),
+        Ok(Command::SSubscribe { .. })
+    ));
+    let Err(err) = Command::from_frame(cmd_frame(&["SSUBSCRIBE", "foo", "bar"])) else {
+        panic!("expected CROSSSLOT");
+    };
+    assert!(err.to_string().starts_with("CROSSSLOT"));
+    assert!(Command::from_frame(cmd_frame(&["SUNSUBSCRIBE", "foo", "bar"])).is_err());
+    assert!(matches!(
+        Command::from_frame(cmd_frame(&["SUNSUBSCRIBE"])),
+        Ok(Command::SUnsubscribe { .. })
+    ));
+    assert!(matches!(
+        Command::from_frame(cmd_frame(&["SPUBLISH", "ch", "m"])),
+        Ok(Command::SPublish { .. })
+    ));
+}
+
+// === Integration ===
+
+async fn roundtrip(conn: &mut Connection<TcpStream>, args: &[&str]) -> Frame {
+    conn.write_frame(&cmd_frame(args)).await.unwrap();
+    conn.read_frame().await.unwrap().unwrap()
+}
+
+fn array(items: &[&str]) -> Frame {
+    Frame::Array(items.iter().map(|s| Frame::BulkString(b(s))).collect())
+}
+
+#[tokio::test]
+async fn introspection_and_shards_over_tcp() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    tokio::spawn(async move { run_server(listener, Db::new()).await });
+
+    let mut sub = Connection::new(TcpStream::connect(("127.0.0.1", port)).await.unwrap());
+    let mut ops = Connection::new(TcpStream::connect(("127.0.0.1", port)).await.unwrap());
+
+    assert_eq!(
+        roundtrip(&mut sub, &["SSUBSCRIBE", "orders"]).await,
+        Frame::Array(vec![
+            Frame::BulkString(b("ssubscribe")),
+            Frame::BulkString(b("orders")),
+            Frame::Integer(1),
+        ])
+    );
+    roundtrip(&mut sub, &["SUBSCRIBE", "news"]).await;
+
+    assert_eq!(
+        roundtrip(&mut ops, &["PUBSUB", "SHARDCHANNELS"]).await,
+        array(&["orders"])
+    );
+    assert_eq!(
+        roundtrip(&mut ops, &["PUBSUB", "NUMSUB", "news", "none"]).await,
+        Frame::Array(vec![
+            Frame::BulkString(b("news")),
+            Frame::Integer(1),
+            Frame::BulkString(b("none")),
+            Frame::Integer(0),
+        ])
+    );
+    assert_eq!(
+        roundtrip(&mut ops, &["PUBSUB", "NUMPAT"]).await,
+        Frame::Integer(0)
+    );
+
+    // PUBLISH doesn't reach shard subscribers, SPUBLISH does
+    assert_eq!(
+        roundtrip(&mut ops, &["PUBLISH", "orders", "lost"]).await,
+        Frame::Integer(0)
+    );
+    assert_eq!(
+        roundtrip(&mut ops, &["SPUBLISH", "orders", "o1"]).await,
+        Frame::Integer(1)
+    );
+    assert_eq!(
+        sub.read_frame().await.unwrap(),
+        Some(array(&["smessage", "orders", "o1"]))
+    );
+
+    let Frame::SimpleError(err) = roundtrip(&mut sub, &["SSUBSCRIBE", "foo", "bar"]).await else {
+        panic!("expected an error");
+    };
+    assert!(err.starts_with("CROSSSLOT"), "{}", err);
+}
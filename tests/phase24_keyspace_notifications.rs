@@ -0,0 +1,314 @@
+use bytes::Bytes;
+use padis::db::{NotifyEvents, Subscriber};
+use padis::{Command, Connection, Db, Frame, run_server};
+use std::time::Duration;
+use tokio::net::{TcpListener, TcpStream};
+
+fn b(s: &str) -> Bytes {
+    Bytes::copy_from_slice(s.as_bytes())
+}
+
+// Helper to build a command frame
+fn cmd_frame(args: &[&str]) -> Frame {
+    Frame::Array(
+        args.iter()
+            .map(|s| Frame::BulkString(Bytes::copy_from_slice(s.as_bytes())))
+            .collect(),
+    )
+}
+
+// A subscriber to every key event, with the given classes enabled
+fn keyevents(db: &Db, flags: &str) -> Subscriber {
+    db.config_set(&[(b("notify-keyspace-events"), b(flags))])
+        .unwrap();
+    let mut sub = db.subscriber();
+    sub.psubscribe(&[b("__keyevent@0__:*")]);
+    sub
+}
+
+// (event, key) pairs received so far, up to a marker published here
+async fn drain(db: &Db, sub: &mut Subscriber) -> Vec<(String, String)> {
+    db.publish(&b("__keyevent@0__:marker"), &b(""));
+    let mut events = Vec::new();
+    loop {
+        let message = sub.recv().await.unwrap();
+        let event = String::from_utf8_lossy(&message.channel["__keyevent@0__:".len()..]);
+        if event == "marker" {
+            return events;
+        }
+        events.push((
+            event.into_owned(),
+            String::from_utf8_lossy(&message.payload).into_owned(),
+        ));
+    }
+}
+
+fn pairs(expected: &[(&str, &str)]) -> Vec<(String, String)> {
+    expected
+        .iter()
+        .map(|(event, key)| (event.to_string(), key.to_string()))
+        .collect()
+}
+
+// === Db ===
+
+#[test]
+fn notify_flags_round_trip() {
+    let cases = [
+        ("", ""),
+        ("KEA", "AKE"),
+        ("Kg$", "g$K"),
+        ("Exe", "xeE"),
+        ("AKEmn", "AmnKE"),
+        ("AKEe", "AKE"),
+        ("g$lshzxetK", "AK"),
+    ];
+    for (flags, shown) in cases {
+        let events = NotifyEvents::parse(flags.as_bytes()).unwrap();
+        assert_eq!(events.to_string(), shown, "{}", flags);
+    }
+    assert_eq!(NotifyEvents::parse(b"Kq"), None);
+    assert_eq!(NotifyEvents::parse(b"d"), None);
+}
+
+#[tokio::test]
+async fn string_and_generic_events() {
+    let db = Db::new();
+    let mut sub = keyevents(&db, "E$g");
+
+    db.set(&b("a"), b("1"), None);
+    db.set(&b("b"), b("2"), Some(Duration::from_secs(60)));
+    assert!(db.del(&b("a")));
+    assert!(!db.del(&b("a")));
+    assert_eq!(
+        drain(&db, &mut sub).await,
+        pairs(&[("set", "a"), ("set", "b"), ("expire", "b"), ("del", "a")])
+    );
+}
+
+#[tokio::test]
+async fn classes_filter_events() {
+    let db = Db::new();
+    let mut sub = keyevents(&db, "Es");
+
+    db.set(&b("str"), b("1"), None);
+    db.sadd(&b("tags"), vec![b("x"), b("y")]).unwrap();
+    // Nothing was added, so nothing fires
+    db.sadd(&b("tags"), vec![b("x")]).unwrap();
+    db.srem(&b("tags"), &[b("x"), b("y")]).unwrap();
+    assert_eq!(
+        drain(&db, &mut sub).await,
+        pairs(&[("sadd", "tags"), ("srem", "tags")])
+    );
+
+    // The emptied set's deletion is generic
+    db.config_set(&[(b("notify-keyspace-events"), b("Esg"))])
+        .unwrap();
+    db.sadd(&b("tags"), vec![b("x")]).unwrap();
+    db.srem(&b("tags"), &[b("x")]).unwrap();
+    assert_eq!(
+        drain(&db, &mut sub).await,
+        pairs(&[("sadd", "tags"), ("srem", "tags"), ("del", "tags")])
+    );
+}
+
+#[tokio::test]
+async fn expired_new_and_keymiss() {
+    let db = Db::new();
+    let mut sub = keyevents(&db, "Exnm");
+
+    db.set(&b("short"), b("1"), Some(Duration::from_millis(10)));
+    tokio::time::sleep(Duration::from_millis(30)).await;
+    // Reading the expired key finds it missing as well
+    assert_eq!(db.get(&b("short")), None);
+    db.sadd(&b("tags"), vec![b("x")]).unwrap();
+    assert_eq!(
+        drain(&db, &mut sub).await,
+        pairs(&[
+            ("new", "short"),
+            ("expired", "short"),
+            ("keymiss", "short"),
+            ("new", "tags"),
+        ])
+    );
+
+    // Writes to a missing key aren't misses
+    db.srem(&b("nothing"), &[b("x")]).unwrap();
+    db.set(&b("gone"), b("1"), Some(Duration::from_millis(10)));
+    tokio::time::sleep(Duration::from_millis(30)).await;
+    assert!(!db.keys().contains(&b("gone")));
+    assert_eq!(
+        drain(&db, &mut sub).await,
+        pairs(&[("new", "gone"), ("expired", "gone")])
+    );
+}
+
+#[tokio::test]
+async fn flush_publishes_nothing() {
+    let db = Db::new();
+    let mut sub = keyevents(&db, "KEA");
+    db.set(&b("a"), b("1"), None);
+    db.flush();
+    db.set(&b("b"), b("2"), None);
+    // Like in Redis, only the writes around the flush show up
+    assert_eq!(
+        drain(&db, &mut sub).await,
+        pairs(&[("set", "a"), ("set", "b")])
+    );
+}
+
+#[tokio::test]
+async fn keyspace_channel_carries_the_event() {
+    let db = Db::new();
+    db.config_set(&[(b("notify-keyspace-events"), b("K$"))])
+        .unwrap();
+    let mut sub = db.subscriber();
+    sub.subscribe(&[b("__keyspace@0__:greeting")]);
+
+    db.set(&b("greeting"), b("hi"), None);
+    let message = sub.recv().await.unwrap();
+    assert_eq!(message.channel, b("__keyspace@0__:greeting"));
+    assert_eq!(message.payload, b("set"));
+}
+
+#[tokio::test]
+async fn off_by_default() {
+    let db = Db::new();
+    assert_eq!(
+        db.config_get(&[b("notify-keyspace-events")]),
+        vec![(b("notify-keyspace-events"), b(""))]
+    );
+    let mut sub = db.subscriber();
+    sub.psubscribe(&[b("__keyevent@0__:*")]);
+    db.set(&b("a"), b("1"), None);
+    assert!(drain(&db, &mut sub).await.is_empty());
+
+    // Classes without a channel publish nothing either
+    db.config_set(&[(b("notify-keyspace-events"), b("A"))])
+        .unwrap();
+    db.set(&b("a"), b("1"), None);
+    assert!(drain(&db, &mut sub).await.is_empty());
+}
+
+#[test]
+fn config_get_and_set() {
+    let db = Db::new();
+    db.config_set(&[(b("NOTIFY-KEYSPACE-EVENTS"), b("gK$"))])
+        .unwrap();
+    assert_eq!(
+        db.config_get(&[b("notify-*"), b("*keyspace*")]),
+        vec![(b("notify-keyspace-events"), b("g$K"))]
+    );
+    assert!(db.config_get(&[b("maxmemory")]).is_empty());
+
+    let err = db
+        .config_set(&[(b("notify-keyspace-events"), b("E")), (b("nope"), b("1"))])
+        .unwrap_err();
+    assert!(err.to_string().contains("'nope'"), "{}", err);
+    let err = db
+        .config_set(&[(b("notify-keyspace-events"), b("Kq"))])
+        .unwrap_err();
+    assert!(err.to_string().starts_with("CONFIG SET failed"), "{}", err);
+    // Neither failure changed anything
+    assert_eq!(
+        db.config_get(&[b("notify-keyspace-events")]),
+        vec![(b("notify-keyspace-events"), b("g$K"))]
+    );
+
+    // Eviction events never fire, but the flag round-trips like in Redis
+    db.config_set(&[(b("notify-keyspace-events"), b("KEe"))])
+        .unwrap();
+    assert_eq!(
+        db.config_get(&[b("notify-keyspace-events")]),
+        vec![(b("notify-keyspace-events"), b("eKE"))]
+    );
+}
+
+// === Parsing ===
+
+#[test]
+fn parse_config() {
+    let Ok(Command::ConfigGet { patterns }) =
+        Command::from_frame(cmd_frame(&["CONFIG", "get", "a*", "b"]))
+    else {
+        panic!("expected CONFIG GET");
+    };
+    assert_eq!(patterns, vec![b("a*"), b("b")]);
+    assert!(Command::from_frame(cmd_frame(&["CONFIG", "GET"])).is_err());
+
+    let Ok(Command::ConfigSet { pairs }) = Command::from_frame(cmd_frame(&[
+        "CONFIG",
+        "SET",
+        "notify-keyspace-events",
+        "KEA",
+    ])) else {
+        panic!("expected CONFIG SET");
+    };
+    assert_eq!(pairs, vec![(b("notify-keyspace-events"), b("KEA"))]);
+    assert!(Command::from_frame(cmd_frame(&["CONFIG", "SET", "a"])).is_err());
+    assert!(Command::from_frame(cmd_frame(&["CONFIG", "SET", "a", "1", "b"])).is_err());
+    assert!(Command::from_frame(cmd_frame(&["CONFIG", "REWRITE"])).is_err());
+    assert!(Command::from_frame(cmd_frame(&["CONFIG"])).is_err());
+}
+
+// === Integration ===
+
+async fn roundtrip(conn: &mut Connection<TcpStream>, args: &[&str]) -> Frame {
+    conn.write_frame(&cmd_frame(args)).await.unwrap();
+    conn.read_frame().await.unwrap().unwrap()
+}
+
+fn array(items: &[&str]) -> Frame {
+    Frame::Array(items.iter().map(|s| Frame::BulkString(b(s))).collect())
+}
+
+#[tokio::test]
+async fn notifications_over_tcp() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    tokio::spawn(async move { run_server(listener, Db::new()).await });
+
+    let mut sub = Connection::new(TcpStream::connect(("127.0.0.1", port)).await.unwrap());
+    let mut ops = Connection::new(TcpStream::connect(("127.0.0.1", port)).await.unwrap());
+
+    assert_eq!(
+        roundtrip(
+            &mut ops,
+            &["CONFIG", "SET", "notify-keyspace-events", "KEA"]
+        )
+        .await,
+        Frame::SimpleString("OK".into())
+    );
+    assert_eq!(
+        roundtrip(&mut ops, &["CONFIG", "GET", "notify-keyspace-events"]).await,
+        array(&["notify-keyspace-events", "AKE"])
+    );
+    roundtrip(&mut sub, &["PSUBSCRIBE", "__key*__:*"]).await;
+
+    roundtrip(&mut ops, &["SADD", "tags", "x"]).await;
+    assert_eq!(
+        sub.read_frame().await.unwrap(),
+        Some(array(&[
+            "pmessage",
+            "__key*__:*",
+            "__keyspace@0__:tags",
+            "sadd"
+        ]))
+    );
+    assert_eq!(
+        sub.read_frame().await.unwrap(),
+        Some(array(&[
+            "pmessage",
+            "__key*__:*",
+            "__keyevent@0__:sadd",
+            "tags"
+        ]))
+    );
+
+    let Frame::SimpleError(err) =
+        roundtrip(&mut ops, &["CONFIG", "SET", "notify-keyspace-events", "Z"]).await
+    else {
+        panic!("expected an error");
+    };
+    assert!(err.starts_with("CONFIG SET failed"), "{}", err);
+}
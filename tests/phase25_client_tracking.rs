@@ -0,0 +1,438 @@
+use bytes::Bytes;
+use padis::db::{Subscriber, TrackingOptions};
+use padis::frame::ParseError;
+use padis::{Command, Connection, Db, Frame, run_server};
+use std::io::Cursor;
+use std::time::Duration;
+use tokio::net::{TcpListener, TcpStream};
+
+fn b(s: &str) -> Bytes {
+    Bytes::copy_from_slice(s.as_bytes())
+}
+
+// Helper to build a command frame
+fn cmd_frame(args: &[&str]) -> Frame {
+    Frame::Array(
+        args.iter()
+            .map(|s| Frame::BulkString(Bytes::copy_from_slice(s.as_bytes())))
+            .collect(),
+    )
+}
+
+// A connected client with tracking switched on, and a handle making commands as it
+fn tracking_client(db: &Db, options: TrackingOptions) -> (Subscriber, Db) {
+    let sub = db.subscriber();
+    db.client_tracking(sub.id(), true, options).unwrap();
+    let client = db.for_client(sub.id());
+    (sub, client)
+}
+
+async fn invalidated(sub: &mut Subscriber) -> Vec<Bytes> {
+    sub.recv_invalidation().await.unwrap()
+}
+
+// Nothing arrives for a short while
+async fn quiet(sub: &mut Subscriber) {
+    let next = tokio::time::timeout(Duration::from_millis(20), sub.recv_invalidation()).await;
+    assert!(next.is_err(), "unexpected invalidation {:?}", next);
+}
+
+// === Db ===
+
+#[tokio::test]
+async fn reads_are_invalidated_once() {
+    let db = Db::new();
+    let (mut sub, client) = tracking_client(&db, TrackingOptions::default());
+
+    db.set(&b("a"), b("1"), None);
+    db.set(&b("b"), b("1"), None);
+    // Only what the client read is tracked, including keys it found missing
+    assert_eq!(client.get(&b("a")), Some(b("1")));
+    assert_eq!(client.get(&b("missing")), None);
+    db.set(&b("b"), b("2"), None);
+    quiet(&mut sub).await;
+
+    db.set(&b("a"), b("2"), None);
+    assert_eq!(invalidated(&mut sub).await, vec![b("a")]);
+    db.sadd(&b("missing"), vec![b("x")]).unwrap();
+    assert_eq!(invalidated(&mut sub).await, vec![b("missing")]);
+
+    // Until it is read again
+    db.set(&b("a"), b("3"), None);
+    quiet(&mut sub).await;
+    client.get(&b("a"));
+    assert!(db.del(&b("a")));
+    assert_eq!(invalidated(&mut sub).await, vec![b("a")]);
+}
+
+#[tokio::test]
+async fn own_writes_and_noloop() {
+    let db = Db::new();
+    let (mut sub, client) = tracking_client(&db, TrackingOptions::default());
+    client.get(&b("k"));
+    client.set(&b("k"), b("1"), None);
+    assert_eq!(invalidated(&mut sub).await, vec![b("k")]);
+
+    db.client_tracking(
+        sub.id(),
+        true,
+        TrackingOptions {
+            noloop: true,
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    client.get(&b("k"));
+    client.set(&b("k"), b("2"), None);
+    quiet(&mut sub).await;
+    // The write still stopped the key being tracked, as in Redis
+    db.set(&b("k"), b("3"), None);
+    quiet(&mut sub).await;
+    client.get(&b("k"));
+    db.set(&b("k"), b("4"), None);
+    assert_eq!(invalidated(&mut sub).await, vec![b("k")]);
+}
+
+#[tokio::test]
+async fn bcast_prefixes() {
+    let db = Db::new();
+    let (mut sub, client) = tracking_client(
+        &db,
+        TrackingOptions {
+            bcast: true,
+            prefixes: vec![b("user:"), b("session:")],
+            ..Default::default()
+        },
+    );
+
+    // Nothing has to be read first
+    db.set(&b("user:1"), b("ann"), None);
+    assert_eq!(invalidated(&mut sub).await, vec![b("user:1")]);
+    db.hset(&b("session:9"), vec![(b("ttl"), b("60"))]).unwrap();
+    assert_eq!(invalidated(&mut sub).await, vec![b("session:9")]);
+    client.get(&b("order:1"));
+    db.set(&b("order:1"), b("x"), None);
+    quiet(&mut sub).await;
+
+    // Without prefixes every key is broadcast
+    let (mut all, _) = tracking_client(
+        &db,
+        TrackingOptions {
+            bcast: true,
+            ..Default::default()
+        },
+    );
+    db.set(&b("order:1"), b("y"), None);
+    assert_eq!(invalidated(&mut all).await, vec![b("order:1")]);
+}
+
+#[tokio::test]
+async fn optin_and_optout() {
+    let db = Db::new();
+    let (mut sub, mut client) = tracking_client(
+        &db,
+        TrackingOptions {
+            optin: true,
+            ..Default::default()
+        },
+    );
+    client.get(&b("a"));
+    client.client_caching(true).unwrap();
+    client.get(&b("b"));
+    client.reset_caching();
+    client.get(&b("c"));
+    for key in ["a", "b", "c"] {
+        db.set(&b(key), b("1"), None);
+    }
+    assert_eq!(invalidated(&mut sub).await, vec![b("b")]);
+    quiet(&mut sub).await;
+    assert!(client.client_caching(false).is_err());
+
+    let (mut sub, mut client) = tracking_client(
+        &db,
+        TrackingOptions {
+            optout: true,
+            ..Default::default()
+        },
+    );
+    client.get(&b("a"));
+    client.client_caching(false).unwrap();
+    client.get(&b("b"));
+    db.set(&b("a"), b("2"), None);
+    db.set(&b("b"), b("2"), None);
+    assert_eq!(invalidated(&mut sub).await, vec![b("a")]);
+    quiet(&mut sub).await;
+}
+
+#[tokio::test]
+async fn redirect_to_another_client() {
+    let db = Db::new();
+    let mut cache = db.subscriber();
+    let (mut sub, client) = tracking_client(
+        &db,
+        TrackingOptions {
+            redirect: Some(cache.id()),
+            ..Default::default()
+        },
+    );
+    assert_eq!(db.client_getredir(sub.id()), cache.id() as i64);
+
+    client.get(&b("k"));
+    db.set(&b("k"), b("1"), None);
+    assert_eq!(invalidated(&mut cache).await, vec![b("k")]);
+    quiet(&mut sub).await;
+
+    let err = db
+        .client_tracking(
+            sub.id(),
+            true,
+            TrackingOptions {
+                redirect: Some(9999),
+                ..Default::default()
+            },
+        )
+        .unwrap_err();
+    assert!(err.to_string().contains("does not exist"), "{}", err);
+}
+
+#[test]
+fn tracking_state_and_errors() {
+    let db = Db::new();
+    let sub = db.subscriber();
+    let id = sub.id();
+    assert_eq!(db.client_getredir(id), -1);
+    let mut plain = db.for_client(id);
+    assert!(plain.client_caching(true).is_err());
+
+    let bcast = |prefixes: &[&str]| TrackingOptions {
+        bcast: true,
+        prefixes: prefixes.iter().map(|p| b(p)).collect(),
+        ..Default::default()
+    };
+    db.client_tracking(id, true, bcast(&["user:"])).unwrap();
+    assert_eq!(db.client_getredir(id), 0);
+    // Prefixes accumulate, but can't overlap
+    db.client_tracking(id, true, bcast(&["user:", "order:"]))
+        .unwrap();
+    let err = db
+        .client_tracking(id, true, bcast(&["user:admin"]))
+        .unwrap_err();
+    assert!(
+        err.to_string().starts_with("Prefix 'user:admin' overlaps"),
+        "{}",
+        err
+    );
+    assert!(db.client_tracking(id, true, bcast(&["a", "ab"])).is_err());
+    // The mode can only change by turning tracking off first
+    assert!(
+        db.client_tracking(id, true, TrackingOptions::default())
+            .is_err()
+    );
+    db.client_tracking(id, false, TrackingOptions::default())
+        .unwrap();
+    db.client_tracking(id, true, TrackingOptions::default())
+        .unwrap();
+
+    drop(sub);
+    assert_eq!(db.client_getredir(id), -1);
+}
+
+// === Parsing ===
+
+#[test]
+fn parse_client_tracking() {
+    let Ok(Command::ClientTracking { on, options }) = Command::from_frame(cmd_frame(&[
+        "CLIENT", "tracking", "on", "bcast", "prefix", "a:", "PREFIX", "b:", "noloop", "REDIRECT",
+        "7",
+    ])) else {
+        panic!("expected CLIENT TRACKING");
+    };
+    assert!(on);
+    assert_eq!(
+        options,
+        TrackingOptions {
+            redirect: Some(7),
+            bcast: true,
+            prefixes: vec![b("a:"), b("b:")],
+            noloop: true,
+            ..Default::default()
+        }
+    );
+
+    let errors = [
+        vec!["CLIENT", "TRACKING"],
+        vec!["CLIENT", "TRACKING", "maybe"],
+        vec!["CLIENT", "TRACKING", "ON", "PREFIX", "a"],
+        vec!["CLIENT", "TRACKING", "ON", "OPTIN", "OPTOUT"],
+        vec!["CLIENT", "TRACKING", "ON", "BCAST", "OPTIN"],
+        vec!["CLIENT", "TRACKING", "ON", "REDIRECT", "x"],
+        vec!["CLIENT", "TRACKING", "ON", "SOMETIMES"],
+        vec!["CLIENT", "CACHING", "maybe"],
+        vec!["CLIENT", "ID", "x"],
+        vec!["CLIENT"],
+    ];
+    for args in errors {
+        assert!(Command::from_frame(cmd_frame(&args)).is_err(), "{:?}", args);
+    }
+    assert!(matches!(
+        Command::from_frame(cmd_frame(&["CLIENT", "CACHING", "no"])),
+        Ok(Command::ClientCaching { yes: false })
+    ));
+    assert!(matches!(
+        Command::from_frame(cmd_frame(&["client", "id"])),
+        Ok(Command::ClientId)
+    ));
+}
+
+#[test]
+fn parse_hello() {
+    assert!(matches!(
+        Command::from_frame(cmd_frame(&["HELLO"])),
+        Ok(Command::Hello { protover: None })
+    ));
+    assert!(matches!(
+        Command::from_frame(cmd_frame(&["HELLO", "3"])),
+        Ok(Command::Hello { protover: Some(3) })
+    ));
+    let Err(err) = Command::from_frame(cmd_frame(&["HELLO", "4"])) else {
+        panic!("expected NOPROTO");
+    };
+    assert!(err.to_string().starts_with("NOPROTO"));
+}
+
+#[test]
+fn push_and_map_frames_round_trip() {
+    let frames = [
+        Frame::Push(vec![
+            Frame::BulkString(b("invalidate")),
+            Frame::Array(vec![Frame::BulkString(b("k"))]),
+        ]),
+        Frame::Map(vec![
+            (Frame::BulkString(b("proto")), Frame::Integer(3)),
+            (Frame::BulkString(b("modules")), Frame::Array(Vec::new())),
+        ]),
+    ];
+    for frame in frames {
+        let bytes = frame.to_bytes();
+        assert_eq!(Frame::parse(&mut Cursor::new(&bytes[..])).unwrap(), frame);
+        let partial = &bytes[..bytes.len() - 1];
+        assert!(matches!(
+            Frame::parse(&mut Cursor::new(partial)),
+            Err(ParseError::Incomplete)
+        ));
+    }
+    assert_eq!(
+        &Frame::Push(vec![Frame::Integer(1)]).to_bytes()[..],
+        b">1\r\n:1\r\n"
+    );
+}
+
+// === Integration ===
+
+async fn roundtrip(conn: &mut Connection<TcpStream>, args: &[&str]) -> Frame {
+    conn.write_frame(&cmd_frame(args)).await.unwrap();
+    conn.read_frame().await.unwrap().unwrap()
+}
+
+fn ok() -> Frame {
+    Frame::SimpleString("OK".into())
+}
+
+async fn start() -> u16 {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    tokio::spawn(async move { run_server(listener, Db::new()).await });
+    port
+}
+
+async fn connect(port: u16) -> Connection<TcpStream> {
+    Connection::new(TcpStream::connect(("127.0.0.1", port)).await.unwrap())
+}
+
+#[tokio::test]
+async fn resp3_pushes_invalidations() {
+    let port = start().await;
+    let mut cache = connect(port).await;
+    let mut ops = connect(port).await;
+
+    let Frame::Map(hello) = roundtrip(&mut cache, &["HELLO", "3"]).await else {
+        panic!("expected a map");
+    };
+    assert!(hello.contains(&(Frame::BulkString(b("proto")), Frame::Integer(3))));
+    assert_eq!(
+        roundtrip(&mut cache, &["CLIENT", "TRACKING", "ON"]).await,
+        ok()
+    );
+    roundtrip(&mut ops, &["SET", "k", "1"]).await;
+    assert_eq!(
+        roundtrip(&mut cache, &["GET", "k"]).await,
+        Frame::BulkString(b("1"))
+    );
+
+    roundtrip(&mut ops, &["SET", "k", "2"]).await;
+    assert_eq!(
+        cache.read_frame().await.unwrap(),
+        Some(Frame::Push(vec![
+            Frame::BulkString(b("invalidate")),
+            Frame::Array(vec![Frame::BulkString(b("k"))]),
+        ]))
+    );
+
+    // Subscribed RESP3 connections can still run commands, and messages are pushed
+    roundtrip(&mut cache, &["SUBSCRIBE", "news"]).await;
+    assert_eq!(
+        roundtrip(&mut cache, &["GET", "k"]).await,
+        Frame::BulkString(b("2"))
+    );
+    roundtrip(&mut ops, &["PUBLISH", "news", "hi"]).await;
+    assert_eq!(
+        cache.read_frame().await.unwrap(),
+        Some(Frame::Push(vec![
+            Frame::BulkString(b("message")),
+            Frame::BulkString(b("news")),
+            Frame::BulkString(b("hi")),
+        ]))
+    );
+}
+
+#[tokio::test]
+async fn resp2_redirects_to_the_invalidation_channel() {
+    let port = start().await;
+    let mut listener = connect(port).await;
+    let mut cache = connect(port).await;
+    let mut ops = connect(port).await;
+
+    let Frame::Integer(id) = roundtrip(&mut listener, &["CLIENT", "ID"]).await else {
+        panic!("expected an ID");
+    };
+    roundtrip(&mut listener, &["SUBSCRIBE", "__redis__:invalidate"]).await;
+    let id = id.to_string();
+    assert_eq!(
+        roundtrip(&mut cache, &["CLIENT", "TRACKING", "ON", "REDIRECT", &id]).await,
+        ok()
+    );
+    assert_eq!(
+        roundtrip(&mut cache, &["CLIENT", "GETREDIR"]).await,
+        Frame::Integer(id.parse().unwrap())
+    );
+    roundtrip(&mut cache, &["GET", "k"]).await;
+
+    roundtrip(&mut ops, &["SET", "k", "1"]).await;
+    assert_eq!(
+        listener.read_frame().await.unwrap(),
+        Some(Frame::Array(vec![
+            Frame::BulkString(b("message")),
+            Frame::BulkString(b("__redis__:invalidate")),
+            Frame::Array(vec![Frame::BulkString(b("k"))]),
+        ]))
+    );
+
+    let Frame::SimpleError(err) = roundtrip(&mut cache, &["CLIENT", "CACHING", "YES"]).await else {
+        panic!("expected an error");
+    };
+    assert!(
+        err.starts_with("CLIENT CACHING can be called only"),
+        "{}",
+        err
+    );
+}
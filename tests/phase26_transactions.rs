@@ -0,0 +1,223 @@
+use bytes::Bytes;
+use padis::{Command, Connection, Db, Frame, run_server};
+use std::time::Duration;
+use tokio::net::{TcpListener, TcpStream};
+
+fn b(s: &str) -> Bytes {
+    Bytes::copy_from_slice(s.as_bytes())
+}
+
+// Helper to build a command frame
+fn cmd_frame(args: &[&str]) -> Frame {
+    Frame::Array(
+        args.iter()
+            .map(|s| Frame::BulkString(Bytes::copy_from_slice(s.as_bytes())))
+            .collect(),
+    )
+}
+
+// === Db ===
+
+#[tokio::test]
+async fn exclusive_gate_keeps_other_commands_out() {
+    let db = Db::new();
+    let gate = db.enter_exclusive().await.unwrap();
+    db.set(&b("balance"), b("10"), None);
+
+    let other = db.clone();
+    let writer = tokio::spawn(async move {
+        let _gate = other.enter().await.unwrap();
+        other.set(&b("balance"), b("0"), None);
+    });
+    // The other client's write waits for the transaction, however long it takes
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    assert!(!writer.is_finished());
+    assert_eq!(db.get(&b("balance")), Some(b("10")));
+
+    drop(gate);
+    writer.await.unwrap();
+    assert_eq!(db.get(&b("balance")), Some(b("0")));
+}
+
+#[tokio::test]
+async fn shared_gates_run_together() {
+    let db = Db::new();
+    let _first = db.enter().await.unwrap();
+    let second = tokio::time::timeout(Duration::from_millis(50), db.enter()).await;
+    assert!(second.is_ok());
+
+    // An exclusive one waits for both
+    let exclusive = tokio::time::timeout(Duration::from_millis(50), db.enter_exclusive()).await;
+    assert!(exclusive.is_err());
+}
+
+// === Parsing ===
+
+#[test]
+fn parse_transaction_commands() {
+    assert!(matches!(
+        Command::from_frame(cmd_frame(&["multi"])),
+        Ok(Command::Multi)
+    ));
+    assert!(matches!(
+        Command::from_frame(cmd_frame(&["EXEC"])),
+        Ok(Command::Exec)
+    ));
+    assert!(matches!(
+        Command::from_frame(cmd_frame(&["Discard"])),
+        Ok(Command::Discard)
+    ));
+    assert!(Command::from_frame(cmd_frame(&["MULTI", "x"])).is_err());
+    assert!(Command::from_frame(cmd_frame(&["EXEC", "x"])).is_err());
+    assert!(Command::from_frame(cmd_frame(&["DISCARD", "x"])).is_err());
+}
+
+// === Integration ===
+
+async fn roundtrip(conn: &mut Connection<TcpStream>, args: &[&str]) -> Frame {
+    conn.write_frame(&cmd_frame(args)).await.unwrap();
+    conn.read_frame().await.unwrap().unwrap()
+}
+
+fn ok() -> Frame {
+    Frame::SimpleString("OK".into())
+}
+
+fn queued() -> Frame {
+    Frame::SimpleString("QUEUED".into())
+}
+
+async fn start() -> u16 {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    tokio::spawn(async move { run_server(listener, Db::new()).await });
+    port
+}
+
+async fn connect(port: u16) -> Connection<TcpStream> {
+    Connection::new(TcpStream::connect(("127.0.0.1", port)).await.unwrap())
+}
+
+fn error(frame: Frame) -> String {
+    match frame {
+        Frame::SimpleError(err) => err,
+        _ => panic!("expected an error"),
+    }
+}
+
+#[tokio::test]
+async fn exec_runs_the_queued_commands() {
+    let port = start().await;
+    let mut client = connect(port).await;
+    let mut other = connect(port).await;
+
+    assert_eq!(roundtrip(&mut client, &["MULTI"]).await, ok());
+    assert_eq!(roundtrip(&mut client, &["SET", "a", "1"]).await, queued());
+    assert_eq!(roundtrip(&mut client, &["SADD", "a", "x"]).await, queued());
+    assert_eq!(roundtrip(&mut client, &["GET", "a"]).await, queued());
+    // Nothing has run yet
+    assert_eq!(roundtrip(&mut other, &["GET", "a"]).await, Frame::Null);
+
+    let Frame::Array(replies) = roundtrip(&mut client, &["EXEC"]).await else {
+        panic!("expected an array");
+    };
+    assert_eq!(replies.len(), 3);
+    assert_eq!(replies[0], ok());
+    // A command failing as it runs doesn't stop the rest
+    assert!(error(replies[1].clone()).starts_with("WRONGTYPE"));
+    assert_eq!(replies[2], Frame::BulkString(b("1")));
+    assert_eq!(
+        roundtrip(&mut other, &["GET", "a"]).await,
+        Frame::BulkString(b("1"))
+    );
+}
+
+#[tokio::test]
+async fn parse_errors_abort_exec() {
+    let port = start().await;
+    let mut client = connect(port).await;
+
+    roundtrip(&mut client, &["MULTI"]).await;
+    assert_eq!(roundtrip(&mut client, &["SET", "a", "1"]).await, queued());
+    error(roundtrip(&mut client, &["SET", "a"]).await);
+    error(roundtrip(&mut client, &["NOSUCHCOMMAND"]).await);
+    let err = error(roundtrip(&mut client, &["EXEC"]).await);
+    assert!(err.starts_with("EXECABORT"), "{}", err);
+    assert_eq!(roundtrip(&mut client, &["GET", "a"]).await, Frame::Null);
+
+    // Connection state commands can't be queued either
+    roundtrip(&mut client, &["MULTI"]).await;
+    error(roundtrip(&mut client, &["SUBSCRIBE", "news"]).await);
+    let err = error(roundtrip(&mut client, &["EXEC"]).await);
+    assert!(err.starts_with("EXECABORT"), "{}", err);
+}
+
+#[tokio::test]
+async fn discard_and_misplaced_commands() {
+    let port = start().await;
+    let mut client = connect(port).await;
+
+    assert_eq!(
+        error(roundtrip(&mut client, &["EXEC"]).await),
+        "EXEC without MULTI"
+    );
+    assert_eq!(
+        error(roundtrip(&mut client, &["DISCARD"]).await),
+        "DISCARD without MULTI"
+    );
+
+    roundtrip(&mut client, &["MULTI"]).await;
+    roundtrip(&mut client, &["SET", "a", "1"]).await;
+    assert_eq!(roundtrip(&mut client, &["DISCARD"]).await, ok());
+    assert_eq!(roundtrip(&mut client, &["GET", "a"]).await, Frame::Null);
+
+    // A nested MULTI is refused without aborting the transaction
+    roundtrip(&mut client, &["MULTI"]).await;
+    assert_eq!(
+        error(roundtrip(&mut client, &["MULTI"]).await),
+        "MULTI calls can not be nested"
+    );
+    roundtrip(&mut client, &["SET", "a", "2"]).await;
+    assert_eq!(
+        roundtrip(&mut client, &["EXEC"]).await,
+        Frame::Array(vec![ok()])
+    );
+    assert_eq!(
+        roundtrip(&mut client, &["EXEC"]).await,
+        Frame::SimpleError("EXEC without MULTI".into())
+    );
+}
+
+#[tokio::test]
+async fn commands_outside_the_keyspace_wait_for_a_transaction() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let db = Db::new();
+    tokio::spawn(run_server(listener, db.clone()));
+    let mut subscriber = connect(port).await;
+    let mut client = connect(port).await;
+    roundtrip(&mut subscriber, &["SUBSCRIBE", "news"]).await;
+
+    // Hold the gate the way EXEC does, and publish from another client meanwhile
+    let gate = db.enter_exclusive().await.unwrap();
+    client
+        .write_frame(&cmd_frame(&["PUBLISH", "news", "late"]))
+        .await
+        .unwrap();
+    let early = tokio::time::timeout(Duration::from_millis(100), client.read_frame()).await;
+    assert!(early.is_err(), "PUBLISH ran while the gate was held");
+
+    drop(gate);
+    assert_eq!(
+        client.read_frame().await.unwrap().unwrap(),
+        Frame::Integer(1)
+    );
+    assert_eq!(
+        subscriber.read_frame().await.unwrap().unwrap(),
+        Frame::Array(vec![
+            Frame::BulkString(b("message")),
+            Frame::BulkString(b("news")),
+            Frame::BulkString(b("late")),
+        ])
+    );
+}
@@ -0,0 +1,236 @@
+use bytes::Bytes;
+use padis::{Command, Connection, Db, Frame, run_server};
+use std::io::Cursor;
+use std::time::Duration;
+use tokio::net::{TcpListener, TcpStream};
+
+fn b(s: &str) -> Bytes {
+    Bytes::copy_from_slice(s.as_bytes())
+}
+
+// Helper to build a command frame
+fn cmd_frame(args: &[&str]) -> Frame {
+    Frame::Array(
+        args.iter()
+            .map(|s| Frame::BulkString(Bytes::copy_from_slice(s.as_bytes())))
+            .collect(),
+    )
+}
+
+// === Db ===
+
+#[test]
+fn writes_break_watches() {
+    let db = Db::new();
+    db.set(&b("balance"), b("10"), None);
+    db.watch(1, &[b("balance"), b("other")]);
+    db.watch(2, &[b("other")]);
+    assert!(!db.watched_changed(1));
+
+    // Reads don't count
+    db.get(&b("balance"));
+    assert!(!db.watched_changed(1));
+
+    db.set(&b("balance"), b("0"), None);
+    assert!(db.watched_changed(1));
+    assert!(!db.watched_changed(2));
+
+    // Creating a watched key counts as well
+    db.sadd(&b("other"), vec![b("x")]).unwrap();
+    assert!(db.watched_changed(2));
+
+    db.unwatch(1);
+    assert!(!db.watched_changed(1));
+    db.watch(1, &[b("balance")]);
+    assert!(!db.watched_changed(1));
+}
+
+#[test]
+fn expiry_breaks_watches() {
+    let db = Db::new();
+    db.set(&b("session"), b("1"), Some(Duration::from_millis(20)));
+    db.watch(1, &[b("session")]);
+    assert!(!db.watched_changed(1));
+    // Nothing has removed the key yet, but it's gone
+    std::thread::sleep(Duration::from_millis(40));
+    assert!(db.watched_changed(1));
+
+    // A key already expired when watched stays missing, which isn't a change
+    db.set(&b("stale"), b("1"), Some(Duration::from_millis(10)));
+    std::thread::sleep(Duration::from_millis(30));
+    db.watch(2, &[b("stale")]);
+    assert_eq!(db.get(&b("stale")), None);
+    assert!(!db.watched_changed(2));
+}
+
+#[test]
+fn flush_breaks_watches() {
+    let db = Db::new();
+    db.set(&b("a"), b("1"), None);
+    db.set(&b("b"), b("1"), None);
+    db.watch(1, &[b("a")]);
+    db.watch(2, &[b("missing")]);
+
+    db.flush();
+    assert!(db.keys().is_empty());
+    assert!(db.watched_changed(1));
+    assert!(!db.watched_changed(2));
+}
+
+#[test]
+fn disconnecting_releases_watches() {
+    let db = Db::new();
+    let sub = db.subscriber();
+    let id = sub.id();
+    db.watch(id, &[b("a")]);
+    db.set(&b("a"), b("1"), None);
+    assert!(db.watched_changed(id));
+    drop(sub);
+    assert!(!db.watched_changed(id));
+}
+
+// === Parsing ===
+
+#[test]
+fn parse_watch_commands() {
+    let Ok(Command::Watch { keys }) = Command::from_frame(cmd_frame(&["WATCH", "a", "b"])) else {
+        panic!("expected WATCH");
+    };
+    assert_eq!(keys, vec![b("a"), b("b")]);
+    assert!(Command::from_frame(cmd_frame(&["WATCH"])).is_err());
+
+    assert!(matches!(
+        Command::from_frame(cmd_frame(&["unwatch"])),
+        Ok(Command::Unwatch)
+    ));
+    assert!(Command::from_frame(cmd_frame(&["UNWATCH", "a"])).is_err());
+
+    for args in [
+        &["FLUSHDB"][..],
+        &["FLUSHALL"],
+        &["flushdb", "async"],
+        &["FLUSHALL", "SYNC"],
+    ] {
+        assert!(matches!(
+            Command::from_frame(cmd_frame(args)),
+            Ok(Command::Flush)
+        ));
+    }
+    assert!(Command::from_frame(cmd_frame(&["FLUSHDB", "LATER"])).is_err());
+}
+
+#[test]
+fn null_array_round_trips() {
+    assert_eq!(&Frame::NullArray.to_bytes()[..], b"*-1\r\n");
+    let bytes = Frame::NullArray.to_bytes();
+    let mut cursor = Cursor::new(&bytes[..]);
+    assert_eq!(Frame::parse(&mut cursor).unwrap(), Frame::NullArray);
+}
+
+// === Integration ===
+
+async fn roundtrip(conn: &mut Connection<TcpStream>, args: &[&str]) -> Frame {
+    conn.write_frame(&cmd_frame(args)).await.unwrap();
+    conn.read_frame().await.unwrap().unwrap()
+}
+
+fn ok() -> Frame {
+    Frame::SimpleString("OK".into())
+}
+
+async fn start() -> u16 {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    tokio::spawn(async move { run_server(listener, Db::new()).await });
+    port
+}
+
+async fn connect(port: u16) -> Connection<TcpStream> {
+    Connection::new(TcpStream::connect(("127.0.0.1", port)).await.unwrap())
+}
+
+// Reads the balance and writes it back doubled, unless it changed in the meantime
+async fn double_balance(
+    client: &mut Connection<TcpStream>,
+    other: Option<&mut Connection<TcpStream>>,
+) -> Frame {
+    assert_eq!(roundtrip(client, &["WATCH", "balance"]).await, ok());
+    let Frame::BulkString(balance) = roundtrip(client, &["GET", "balance"]).await else {
+        panic!("expected a balance");
+    };
+    let doubled = std::str::from_utf8(&balance)
+        .unwrap()
+        .parse::<i64>()
+        .unwrap()
+        * 2;
+    if let Some(other) = other {
+        roundtrip(other, &["SET", "balance", "100"]).await;
+    }
+    roundtrip(client, &["MULTI"]).await;
+    roundtrip(client, &["SET", "balance", &doubled.to_string()]).await;
+    roundtrip(client, &["EXEC"]).await
+}
+
+#[tokio::test]
+async fn check_and_set() {
+    let port = start().await;
+    let mut client = connect(port).await;
+    let mut other = connect(port).await;
+    roundtrip(&mut client, &["SET", "balance", "10"]).await;
+
+    assert_eq!(
+        double_balance(&mut client, Some(&mut other)).await,
+        Frame::NullArray
+    );
+    assert_eq!(
+        roundtrip(&mut client, &["GET", "balance"]).await,
+        Frame::BulkString(b("100"))
+    );
+    // EXEC released the watch, so retrying works
+    assert_eq!(
+        double_balance(&mut client, None).await,
+        Frame::Array(vec![ok()])
+    );
+    assert_eq!(
+        roundtrip(&mut client, &["GET", "balance"]).await,
+        Frame::BulkString(b("200"))
+    );
+}
+
+#[tokio::test]
+async fn unwatch_discard_and_flush() {
+    let port = start().await;
+    let mut client = connect(port).await;
+    let mut other = connect(port).await;
+
+    // UNWATCH forgets the change
+    roundtrip(&mut client, &["WATCH", "a"]).await;
+    roundtrip(&mut other, &["SET", "a", "1"]).await;
+    assert_eq!(roundtrip(&mut client, &["UNWATCH"]).await, ok());
+    roundtrip(&mut client, &["MULTI"]).await;
+    assert_eq!(
+        roundtrip(&mut client, &["EXEC"]).await,
+        Frame::Array(vec![])
+    );
+
+    // So does DISCARD
+    roundtrip(&mut client, &["WATCH", "a"]).await;
+    roundtrip(&mut other, &["SET", "a", "2"]).await;
+    roundtrip(&mut client, &["MULTI"]).await;
+    assert_eq!(
+        roundtrip(&mut client, &["WATCH", "b"]).await,
+        Frame::SimpleError("WATCH inside MULTI is not allowed".into())
+    );
+    assert_eq!(roundtrip(&mut client, &["DISCARD"]).await, ok());
+    roundtrip(&mut client, &["MULTI"]).await;
+    assert_eq!(
+        roundtrip(&mut client, &["EXEC"]).await,
+        Frame::Array(vec![])
+    );
+
+    roundtrip(&mut client, &["WATCH", "a"]).await;
+    assert_eq!(roundtrip(&mut other, &["FLUSHALL"]).await, ok());
+    assert_eq!(roundtrip(&mut other, &["GET", "a"]).await, Frame::Null);
+    roundtrip(&mut client, &["MULTI"]).await;
+    assert_eq!(roundtrip(&mut client, &["EXEC"]).await, Frame::NullArray);
+}
@@ -0,0 +1,566 @@
+use bytes::Bytes;
+use padis::db::sha1_hex;
+use padis::{Command, Connection, Db, Frame, run_server, script};
+use std::thread;
+use std::time::Duration;
+use tokio::net::{TcpListener, TcpStream};
+
+fn b(s: &str) -> Bytes {
+    Bytes::copy_from_slice(s.as_bytes())
+}
+
+// Helper to build a command frame
+fn cmd_frame(args: &[&str]) -> Frame {
+    Frame::Array(
+        args.iter()
+            .map(|s| Frame::BulkString(Bytes::copy_from_slice(s.as_bytes())))
+            .collect(),
+    )
+}
+
+fn eval(db: &Db, body: &str, keys: &[&str], args: &[&str]) -> Frame {
+    script::eval(
+        db,
+        b(body),
+        keys.iter().map(|k| b(k)).collect(),
+        args.iter().map(|a| b(a)).collect(),
+    )
+}
+
+fn bulk(s: &str) -> Frame {
+    Frame::BulkString(b(s))
+}
+
+fn error(frame: Frame) -> String {
+    match frame {
+        Frame::SimpleError(err) => err,
+        _ => panic!("expected an error"),
+    }
+}
+
+// === Db ===
+
+#[test]
+fn lua_values_become_replies() {
+    let db = Db::new();
+    let cases = [
+        ("return 42", Frame::Integer(42)),
+        ("return 3.99", Frame::Integer(3)),
+        ("return -2.5", Frame::Integer(-2)),
+        ("return 'hi'", bulk("hi")),
+        ("return true", Frame::Integer(1)),
+        ("return false", Frame::Null),
+        ("return nil", Frame::Null),
+        ("return", Frame::Null),
+        (
+            "return {1, 'two', {3}}",
+            Frame::Array(vec![
+                Frame::Integer(1),
+                bulk("two"),
+                Frame::Array(vec![Frame::Integer(3)]),
+            ]),
+        ),
+        // Arrays stop at the first nil
+        ("return {1, nil, 3}", Frame::Array(vec![Frame::Integer(1)])),
+        ("return {ok = 'FINE'}", Frame::SimpleString("FINE".into())),
+        (
+            "return {err = 'BAD thing'}",
+            Frame::SimpleError("BAD thing".into()),
+        ),
+        (
+            "return redis.status_reply('DONE')",
+            Frame::SimpleString("DONE".into()),
+        ),
+        (
+            "return redis.error_reply('NOPE no')",
+            Frame::SimpleError("NOPE no".into()),
+        ),
+    ];
+    for (body, expected) in cases {
+        assert_eq!(eval(&db, body, &[], &[]), expected, "{}", body);
+    }
+}
+
+#[test]
+fn replies_become_lua_values() {
+    let db = Db::new();
+    db.sadd(&b("tags"), vec![b("x")]).unwrap();
+    let cases = [
+        ("return redis.call('SET', 'k', 'v').ok", bulk("OK")),
+        ("return type(redis.call('GET', 'k'))", bulk("string")),
+        (
+            "return redis.call('GET', 'missing') == false",
+            Frame::Integer(1),
+        ),
+        ("return type(redis.call('SCARD', 'tags'))", bulk("number")),
+        ("return redis.call('SMEMBERS', 'tags')[1]", bulk("x")),
+        (
+            "return redis.pcall('SADD', 'k', 'x').err ~= nil",
+            Frame::Integer(1),
+        ),
+        // Numbers are passed on as strings
+        (
+            "redis.call('SET', 'n', 12) return redis.call('GET', 'n')",
+            bulk("12"),
+        ),
+    ];
+    for (body, expected) in cases {
+        assert_eq!(eval(&db, body, &[], &[]), expected, "{}", body);
+    }
+}
+
+#[test]
+fn keys_and_argv() {
+    let db = Db::new();
+    let body = "redis.call('SET', KEYS[1], ARGV[1]) return {KEYS[2], ARGV[2], #KEYS, #ARGV}";
+    assert_eq!(
+        eval(&db, body, &["a", "b"], &["1", "2", "3"]),
+        Frame::Array(vec![
+            bulk("b"),
+            bulk("2"),
+            Frame::Integer(2),
+            Frame::Integer(3)
+        ])
+    );
+    assert_eq!(db.get(&b("a")), Some(b("1")));
+}
+
+#[test]
+fn call_errors_end_the_script() {
+    let db = Db::new();
+    db.sadd(&b("tags"), vec![b("x")]).unwrap();
+
+    // redis.call raises the command's error, stopping the script there
+    let err = error(eval(
+        &db,
+        "redis.call('GET', 'tags') redis.call('SET', 'after', '1')",
+        &[],
+        &[],
+    ));
+    assert!(err.starts_with("WRONGTYPE"), "{}", err);
+    assert_eq!(db.get(&b("after")), None);
+
+    // redis.pcall hands it back instead
+    assert_eq!(
+        eval(
+            &db,
+            "local r = redis.pcall('GET', 'tags') return r.err:sub(1, 9)",
+            &[],
+            &[],
+        ),
+        bulk("WRONGTYPE")
+    );
+
+    assert_eq!(
+        error(eval(&db, "return redis.call('NOSUCH')", &[], &[])),
+        "Unknown Redis command called from script"
+    );
+    for denied in [
+        "'MULTI'",
+        "'EXEC'",
+        "'WATCH', 'x'",
+        "'SUBSCRIBE', 'x'",
+        "'EVAL', 'return 1', '0'",
+        "'SCRIPT', 'FLUSH'",
+    ] {
+        assert_eq!(
+            error(eval(
+                &db,
+                &format!("return redis.call({})", denied),
+                &[],
+                &[],
+            )),
+            "This Redis command is not allowed from script",
+            "{}",
+            denied
+        );
+    }
+    assert!(error(eval(&db, "return redis.call('SET', {})", &[], &[])).contains("arguments"));
+    assert!(error(eval(&db, "return redis.call()", &[], &[])).contains("at least one"));
+
+    let err = error(eval(&db, "return +", &[], &[]));
+    assert!(err.starts_with("Error compiling script"), "{}", err);
+    let err = error(eval(&db, "error('boom')", &[], &[]));
+    assert!(err.contains("user_script:1: boom"), "{}", err);
+    // The filesystem is out of reach
+    assert!(error(eval(&db, "return dofile('/etc/passwd')", &[], &[])).contains("nil"));
+}
+
+#[test]
+fn script_cache() {
+    let db = Db::new();
+    let body = "return ARGV[1]";
+    let sha = sha1_hex(body.as_bytes());
+    assert_eq!(sha, "098e0f0d1448c0a81dafe820f66d460eb09263da");
+    assert_eq!(db.script_exists(&[b(&sha)]), vec![false]);
+    assert_eq!(
+        error(script::evalsha(&db, sha.as_bytes(), vec![], vec![]))
+            .split(' ')
+            .next(),
+        Some("NOSCRIPT")
+    );
+
+    // EVAL caches what it runs
+    eval(&db, body, &[], &["x"]);
+    assert_eq!(db.script_exists(&[b(&sha), b("ffff")]), vec![true, false]);
+    assert_eq!(
+        script::evalsha(&db, sha.to_uppercase().as_bytes(), vec![], vec![b("y")]),
+        bulk("y")
+    );
+
+    db.script_flush();
+    assert_eq!(db.script_exists(&[b(&sha)]), vec![false]);
+    assert_eq!(db.script_load(b(body)), sha);
+    assert_eq!(db.script_exists(&[b(&sha)]), vec![true]);
+}
+
+#[test]
+fn cjson_library() {
+    let db = Db::new();
+    let cases = [
+        ("return cjson.encode({1, 2, 'x'})", bulk("[1,2,\"x\"]")),
+        (
+            "return cjson.encode({a = {true, false}})",
+            bulk("{\"a\":[true,false]}"),
+        ),
+        ("return cjson.encode({})", bulk("{}")),
+        ("return cjson.encode(cjson.null)", bulk("null")),
+        ("return cjson.encode(1.5)", bulk("1.5")),
+        (
+            "local v = cjson.decode('{\"a\": [1, 2.5, \"b\"]}') return {v.a[1], v.a[3], tostring(v.a[2])}",
+            Frame::Array(vec![Frame::Integer(1), bulk("b"), bulk("2.5")]),
+        ),
+        (
+            "return cjson.decode('null') == cjson.null",
+            Frame::Integer(1),
+        ),
+        ("return cjson.decode('[1, null, 3]')[3]", Frame::Integer(3)),
+    ];
+    for (body, expected) in cases {
+        assert_eq!(eval(&db, body, &[], &[]), expected, "{}", body);
+    }
+    assert!(error(eval(&db, "return cjson.decode('{')", &[], &[])).contains("EOF"));
+    assert!(error(eval(&db, "return cjson.encode(print)", &[], &[])).contains("Cannot serialise"));
+}
+
+#[test]
+fn struct_library() {
+    let db = Db::new();
+    let cases = [
+        (
+            "return struct.pack('>I2', 258)",
+            Frame::BulkString(Bytes::from_static(b"\x01\x02")),
+        ),
+        (
+            "return struct.pack('<i', -2)",
+            Frame::BulkString(Bytes::from_static(b"\xfe\xff\xff\xff")),
+        ),
+        (
+            "return struct.pack('bxs', 1, 'ab')",
+            Frame::BulkString(Bytes::from_static(b"\x01\x00ab\x00")),
+        ),
+        (
+            "return struct.pack('!4 b i', 1, 2)",
+            Frame::BulkString(Bytes::from_static(b"\x01\x00\x00\x00\x02\x00\x00\x00")),
+        ),
+        ("return struct.size('>hid')", Frame::Integer(14)),
+        ("return struct.size('!8 b d')", Frame::Integer(16)),
+        (
+            "return {struct.unpack('>hH', struct.pack('>hH', -3, 65535))}",
+            Frame::Array(vec![
+                Frame::Integer(-3),
+                Frame::Integer(65535),
+                Frame::Integer(5),
+            ]),
+        ),
+        (
+            "return {struct.unpack('Bc0s', struct.pack('Bc0s', 3, 'abc', 'de'))}",
+            Frame::Array(vec![bulk("abc"), bulk("de"), Frame::Integer(8)]),
+        ),
+        (
+            "return struct.unpack('d', struct.pack('d', 2.5)) * 2",
+            Frame::Integer(5),
+        ),
+        // Unpacking can start part way through
+        ("return struct.unpack('b', 'xyz', 3)", Frame::Integer(122)),
+    ];
+    for (body, expected) in cases {
+        assert_eq!(eval(&db, body, &[], &[]), expected, "{}", body);
+    }
+    assert!(error(eval(&db, "return struct.size('s')", &[], &[])).contains("undefined sizes"));
+    assert!(error(eval(&db, "return struct.unpack('i', 'ab')", &[], &[])).contains("too short"));
+    assert!(error(eval(&db, "return struct.pack('q', 1)", &[], &[])).contains("invalid format"));
+}
+
+#[test]
+fn script_kill() {
+    let db = Db::new();
+    assert_eq!(
+        db.script_kill().unwrap_err().to_string().split(' ').next(),
+        Some("NOTBUSY")
+    );
+    db.config_set(&[(b("busy-reply-threshold"), b("0"))])
+        .unwrap();
+    assert!(!db.script_busy());
+
+    let runner = db.clone();
+    let looping = thread::spawn(move || eval(&runner, "while true do end", &[], &[]));
+    while !db.script_busy() {
+        thread::sleep(Duration::from_millis(1));
+    }
+    db.script_kill().unwrap();
+    let err = error(looping.join().unwrap());
+    assert!(err.starts_with("Script killed by user"), "{}", err);
+    assert!(!db.script_busy());
+
+    // Once a script has written, it has to be left to finish
+    let runner = db.clone();
+    let writing = thread::spawn(move || {
+        eval(
+            &runner,
+            "redis.call('SET', 'k', 'v') local i = 0 while i < 3000000 do i = i + 1 end return i",
+            &[],
+            &[],
+        )
+    });
+    while !db.script_busy() {
+        thread::sleep(Duration::from_millis(1));
+    }
+    assert!(
+        db.script_kill()
+            .unwrap_err()
+            .to_string()
+            .starts_with("UNKILLABLE")
+    );
+    assert_eq!(writing.join().unwrap(), Frame::Integer(3000000));
+}
+
+// === Parsing ===
+
+#[test]
+fn parse_scripting_commands() {
+    let Ok(Command::Eval { script, keys, args }) =
+        Command::from_frame(cmd_frame(&["EVAL", "return 1", "2", "a", "b", "c"]))
+    else {
+        panic!("expected EVAL");
+    };
+    assert_eq!(script, b("return 1"));
+    assert_eq!(keys, vec![b("a"), b("b")]);
+    assert_eq!(args, vec![b("c")]);
+
+    let Ok(Command::EvalSha { sha, keys, args }) =
+        Command::from_frame(cmd_frame(&["evalsha", "abc", "0"]))
+    else {
+        panic!("expected EVALSHA");
+    };
+    assert_eq!(sha, b("abc"));
+    assert!(keys.is_empty() && args.is_empty());
+
+    let err = Command::from_frame(cmd_frame(&["EVAL", "return 1", "2", "a"]))
+        .err()
+        .unwrap();
+    assert_eq!(
+        err.to_string(),
+        "Number of keys can't be greater than number of args"
+    );
+    let err = Command::from_frame(cmd_frame(&["EVAL", "return 1", "-1"]))
+        .err()
+        .unwrap();
+    assert_eq!(err.to_string(), "Number of keys can't be negative");
+    assert!(Command::from_frame(cmd_frame(&["EVAL", "return 1"])).is_err());
+
+    assert!(matches!(
+        Command::from_frame(cmd_frame(&["SCRIPT", "load", "return 1"])),
+        Ok(Command::ScriptLoad { .. })
+    ));
+    let Ok(Command::ScriptExists { shas }) =
+        Command::from_frame(cmd_frame(&["SCRIPT", "EXISTS", "a", "b"]))
+    else {
+        panic!("expected SCRIPT EXISTS");
+    };
+    assert_eq!(shas, vec![b("a"), b("b")]);
+    assert!(matches!(
+        Command::from_frame(cmd_frame(&["SCRIPT", "FLUSH", "ASYNC"])),
+        Ok(Command::ScriptFlush)
+    ));
+    assert!(matches!(
+        Command::from_frame(cmd_frame(&["SCRIPT", "KILL"])),
+        Ok(Command::ScriptKill)
+    ));
+    assert!(Command::from_frame(cmd_frame(&["SCRIPT", "EXISTS"])).is_err());
+    assert!(Command::from_frame(cmd_frame(&["SCRIPT", "KILL", "now"])).is_err());
+    assert!(Command::from_frame(cmd_frame(&["SCRIPT", "DEBUG", "YES"])).is_err());
+}
+
+// === Integration ===
+
+async fn roundtrip(conn: &mut Connection<TcpStream>, args: &[&str]) -> Frame {
+    conn.write_frame(&cmd_frame(args)).await.unwrap();
+    conn.read_frame().await.unwrap().unwrap()
+}
+
+async fn start() -> u16 {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    tokio::spawn(async move { run_server(listener, Db::new()).await });
+    port
+}
+
+async fn connect(port: u16) -> Connection<TcpStream> {
+    Connection::new(TcpStream::connect(("127.0.0.1", port)).await.unwrap())
+}
+
+#[tokio::test]
+async fn scripts_over_tcp() {
+    let port = start().await;
+    let mut client = connect(port).await;
+
+    let body = "return redis.call('INCRBYFLOAT', KEYS[1])";
+    let err = error(roundtrip(&mut client, &["EVAL", body, "1", "k"]).await);
+    assert_eq!(err, "Unknown Redis command called from script");
+
+    let body = "redis.call('SADD', KEYS[1], unpack(ARGV)) return redis.call('SCARD', KEYS[1])";
+    assert_eq!(
+        roundtrip(&mut client, &["EVAL", body, "1", "tags", "a", "b"]).await,
+        Frame::Integer(2)
+    );
+    let Frame::BulkString(sha) = roundtrip(&mut client, &["SCRIPT", "LOAD", body]).await else {
+        panic!("expected a SHA1");
+    };
+    let sha = String::from_utf8(sha.to_vec()).unwrap();
+    assert_eq!(
+        roundtrip(&mut client, &["EVALSHA", &sha, "1", "tags", "c"]).await,
+        Frame::Integer(3)
+    );
+    assert_eq!(
+        roundtrip(&mut client, &["SCRIPT", "EXISTS", &sha, "nope"]).await,
+        Frame::Array(vec![Frame::Integer(1), Frame::Integer(0)])
+    );
+
+    // Scripts can be queued like any other command
+    roundtrip(&mut client, &["MULTI"]).await;
+    roundtrip(&mut client, &["EVALSHA", &sha, "1", "tags", "d"]).await;
+    assert_eq!(
+        roundtrip(&mut client, &["EXEC"]).await,
+        Frame::Array(vec![Frame::Integer(4)])
+    );
+    assert_eq!(
+        roundtrip(&mut client, &["SCRIPT", "FLUSH"]).await,
+        Frame::SimpleString("OK".into())
+    );
+    let err = error(roundtrip(&mut client, &["EVALSHA", &sha, "1", "tags"]).await);
+    assert!(err.starts_with("NOSCRIPT"), "{}", err);
+}
+
+#[tokio::test]
+async fn multi_line_scripts_over_tcp() {
+    let port = start().await;
+    let mut client = connect(port).await;
+
+    assert_eq!(
+        roundtrip(&mut client, &["EVAL", "local x = 1\nreturn x", "0"]).await,
+        Frame::Integer(1)
+    );
+    let body = "-- counts its arguments\r\nlocal n = 0\r\nfor _, v in ipairs(ARGV) do\n  n = n + 1\nend\nreturn n";
+    assert_eq!(
+        roundtrip(&mut client, &["EVAL", body, "0", "a", "b", "c"]).await,
+        Frame::Integer(3)
+    );
+    let Frame::BulkString(sha) = roundtrip(&mut client, &["SCRIPT", "LOAD", body]).await else {
+        panic!("expected a SHA1");
+    };
+    let sha = String::from_utf8(sha.to_vec()).unwrap();
+    assert_eq!(
+        roundtrip(&mut client, &["EVALSHA", &sha, "0", "a"]).await,
+        Frame::Integer(1)
+    );
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn busy_scripts_can_be_killed() {
+    let port = start().await;
+    let mut runner = connect(port).await;
+    let mut other = connect(port).await;
+
+    roundtrip(&mut other, &["CONFIG", "SET", "busy-reply-threshold", "0"]).await;
+    runner
+        .write_frame(&cmd_frame(&["EVAL", "while true do end", "0"]))
+        .await
+        .unwrap();
+    // Wait for the script to take over
+    let err = loop {
+        match roundtrip(&mut other, &["PING"]).await {
+            Frame::SimpleError(err) => break err,
+            _ => tokio::time::sleep(Duration::from_millis(5)).await,
+        }
+    };
+    assert!(err.starts_with("BUSY"), "{}", err);
+
+    assert_eq!(
+        roundtrip(&mut other, &["SCRIPT", "KILL"]).await,
+        Frame::SimpleString("OK".into())
+    );
+    let err = error(runner.read_frame().await.unwrap().unwrap());
+    assert!(err.starts_with("Script killed by user"), "{}", err);
+    assert_eq!(
+        roundtrip(&mut other, &["PING"]).await,
+        Frame::SimpleString("PONG".into())
+    );
+}
+
+// Sends a command without waiting for its reply
+async fn send(conn: &mut Connection<TcpStream>, args: &[&str]) {
+    conn.write_frame(&cmd_frame(args)).await.unwrap();
+}
+
+async fn reply(conn: &mut Connection<TcpStream>) -> Frame {
+    tokio::time::timeout(Duration::from_secs(5), conn.read_frame())
+        .await
+        .expect("no reply within 5s")
+        .unwrap()
+        .unwrap()
+}
+
+// Clients that sent a command before the script became busy wait without tying up a worker
+// thread, so SCRIPT KILL still gets through with only two of them
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn clients_waiting_on_a_script_are_told_it_is_busy() {
+    let port = start().await;
+    let mut runner = connect(port).await;
+    let mut waiters = [connect(port).await, connect(port).await];
+    let mut killer = connect(port).await;
+    roundtrip(
+        &mut killer,
+        &["CONFIG", "SET", "busy-reply-threshold", "500"],
+    )
+    .await;
+
+    for in_exec in [false, true] {
+        if in_exec {
+            roundtrip(&mut runner, &["MULTI"]).await;
+            roundtrip(&mut runner, &["EVAL", "while true do end", "0"]).await;
+            send(&mut runner, &["EXEC"]).await;
+        } else {
+            send(&mut runner, &["EVAL", "while true do end", "0"]).await;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        for waiter in &mut waiters {
+            send(waiter, &["GET", "foo"]).await;
+        }
+
+        for waiter in &mut waiters {
+            let err = error(reply(waiter).await);
+            assert!(err.starts_with("BUSY"), "{}", err);
+        }
+        send(&mut killer, &["SCRIPT", "KILL"]).await;
+        assert_eq!(reply(&mut killer).await, Frame::SimpleString("OK".into()));
+        let err = match reply(&mut runner).await {
+            Frame::Array(mut replies) if in_exec => error(replies.remove(0)),
+            frame => error(frame),
+        };
+        assert!(err.starts_with("Script killed by user"), "{}", err);
+        for waiter in &mut waiters {
+            assert_eq!(roundtrip(waiter, &["GET", "foo"]).await, Frame::Null);
+        }
+    }
+}
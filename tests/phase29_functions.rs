@@ -0,0 +1,616 @@
+use bytes::Bytes;
+use padis::db::RestorePolicy;
+use padis::{Command, Connection, Db, Frame, run_server, script};
+use tokio::net::{TcpListener, TcpStream};
+
+fn b(s: &str) -> Bytes {
+    Bytes::copy_from_slice(s.as_bytes())
+}
+
+// Helper to build a command frame
+fn cmd_frame(args: &[&str]) -> Frame {
+    Frame::Array(
+        args.iter()
+            .map(|s| Frame::BulkString(Bytes::copy_from_slice(s.as_bytes())))
+            .collect(),
+    )
+}
+
+fn load(db: &Db, code: &str) -> Frame {
+    script::function_load(db, b(code), false)
+}
+
+fn fcall(db: &Db, name: &str, keys: &[&str], args: &[&str]) -> Frame {
+    script::fcall(
+        db,
+        name.as_bytes(),
+        keys.iter().map(|k| b(k)).collect(),
+        args.iter().map(|a| b(a)).collect(),
+        false,
+    )
+}
+
+fn fcall_ro(db: &Db, name: &str, keys: &[&str]) -> Frame {
+    script::fcall(
+        db,
+        name.as_bytes(),
+        keys.iter().map(|k| b(k)).collect(),
+        Vec::new(),
+        true,
+    )
+}
+
+fn bulk(s: &str) -> Frame {
+    Frame::BulkString(b(s))
+}
+
+fn ok() -> Frame {
+    Frame::SimpleString("OK".into())
+}
+
+fn error(frame: Frame) -> String {
+    match frame {
+        Frame::SimpleError(err) => err,
+        _ => panic!("expected an error"),
+    }
+}
+
+const COUNTER: &str = "#!lua name=counter
+redis.register_function('incr_by', function(keys, args)
+    local n = tonumber(redis.call('GET', keys[1]) or 0) + args[1]
+    redis.call('SET', keys[1], n)
+    return n
+end)
+redis.register_function{
+    function_name = 'peek',
+    callback = function(keys) return redis.call('GET', keys[1]) end,
+    flags = {'no-writes'},
+    description = 'reads the counter',
+}";
+
+// === Db ===
+
+#[test]
+fn load_and_call_functions() {
+    let db = Db::new();
+    assert_eq!(load(&db, COUNTER), bulk("counter"));
+    assert_eq!(fcall(&db, "incr_by", &["n"], &["5"]), Frame::Integer(5));
+    assert_eq!(fcall(&db, "incr_by", &["n"], &["2"]), Frame::Integer(7));
+    assert_eq!(fcall(&db, "peek", &["n"], &[]), bulk("7"));
+    assert_eq!(error(fcall(&db, "nope", &[], &[])), "Function not found");
+
+    // Functions get keys and arguments as parameters rather than KEYS and ARGV
+    load(
+        &db,
+        "#!LUA name=globals\nredis.register_function('g', function() return {type(KEYS), type(ARGV)} end)",
+    );
+    assert_eq!(
+        fcall(&db, "g", &["k"], &["a"]),
+        Frame::Array(vec![bulk("nil"), bulk("nil")])
+    );
+
+    let libraries = db.function_list(None);
+    assert_eq!(libraries.len(), 2);
+    let counter = &libraries[0];
+    assert_eq!(counter.name, "counter");
+    assert_eq!(counter.code, b(COUNTER));
+    let names: Vec<_> = counter.functions.keys().cloned().collect();
+    assert_eq!(names, ["incr_by", "peek"]);
+    let peek = &counter.functions["peek"];
+    assert_eq!(peek.description, Some(b("reads the counter")));
+    assert_eq!(peek.flags, ["no-writes"]);
+    assert!(peek.no_writes());
+    assert!(!counter.functions["incr_by"].no_writes());
+    assert_eq!(db.function_list(Some(b"glob*")).len(), 1);
+    assert!(db.function_list(Some(b"x*")).is_empty());
+}
+
+#[test]
+fn bad_libraries_are_not_loaded() {
+    let db = Db::new();
+    let register = "\nredis.register_function('f', function() return 1 end)";
+    let cases = [
+        ("return 1", "Missing library metadata".to_string()),
+        (
+            &format!("#!python name=p{}", register),
+            "Engine 'python' not found".to_string(),
+        ),
+        (
+            &format!("#!lua{}", register),
+            "Library name was not given".to_string(),
+        ),
+        (
+            &format!("#!lua name=l version=2{}", register),
+            "Invalid metadata value given: version=2".to_string(),
+        ),
+        (
+            &format!("#!lua name=my-lib{}", register),
+            "Library names can only contain letters, numbers, or underscores(_) and must be at least one character long".to_string(),
+        ),
+        (
+            "#!lua name=empty\nlocal x = 1",
+            "No functions registered".to_string(),
+        ),
+        (
+            "#!lua name=l\nredis.register_function('f', 1)",
+            "Error registering functions: callback argument given to redis.register_function must be a function".to_string(),
+        ),
+        (
+            "#!lua name=l\nredis.register_function('bad name', function() end)",
+            "Error registering functions: Function names can only contain letters, numbers, or underscores(_) and must be at least one character long".to_string(),
+        ),
+        (
+            "#!lua name=l\nredis.register_function{function_name='f', callback=function() end, flags={'fast'}}",
+            "Error registering functions: unknown flag given".to_string(),
+        ),
+        (
+            "#!lua name=l\nredis.register_function{function_name='f', callback=function() end, speed=1}",
+            "Error registering functions: unknown argument given to redis.register_function".to_string(),
+        ),
+        (
+            "#!lua name=l\nredis.register_function('f', function() end)\nredis.register_function('f', function() end)",
+            "Error registering functions: Function already exists in the library".to_string(),
+        ),
+        (
+            "#!lua name=l\nredis.call('SET', 'k', 'v')",
+            "Error registering functions: ".to_string(),
+        ),
+    ];
+    for (code, expected) in cases {
+        let err = error(load(&db, code));
+        assert!(err.starts_with(&expected), "{}: {}", code, err);
+    }
+    let err = error(load(
+        &db,
+        "#!lua name=l\nredis.register_function('f', function() end",
+    ));
+    assert!(err.starts_with("Error compiling function: "), "{}", err);
+    let err = error(load(&db, "#!lua name=l\nwhile true do end"));
+    assert_eq!(err, "Error registering functions: FUNCTION LOAD timeout");
+    assert!(db.function_list(None).is_empty());
+}
+
+#[test]
+fn libraries_and_their_functions_are_unique() {
+    let db = Db::new();
+    load(&db, COUNTER);
+    assert_eq!(
+        error(load(&db, COUNTER)),
+        "Library 'counter' already exists"
+    );
+    assert_eq!(
+        error(load(
+            &db,
+            "#!lua name=other\nredis.register_function('peek', function() return 0 end)"
+        )),
+        "Function peek already exists"
+    );
+
+    // REPLACE swaps the whole library, dropping functions it no longer registers
+    let code = "#!lua name=counter\nredis.register_function('peek', function() return 'new' end)";
+    assert_eq!(script::function_load(&db, b(code), true), bulk("counter"));
+    assert_eq!(fcall(&db, "peek", &["n"], &[]), bulk("new"));
+    assert_eq!(
+        error(fcall(&db, "incr_by", &["n"], &["1"])),
+        "Function not found"
+    );
+
+    db.function_delete(b"counter").unwrap();
+    assert_eq!(
+        db.function_delete(b"counter").unwrap_err().to_string(),
+        "Library not found"
+    );
+    assert_eq!(error(fcall(&db, "peek", &[], &[])), "Function not found");
+
+    load(&db, COUNTER);
+    db.function_flush();
+    assert!(db.function_list(None).is_empty());
+}
+
+#[test]
+fn read_only_functions() {
+    let db = Db::new();
+    load(&db, COUNTER);
+    load(
+        &db,
+        "#!lua name=sneaky\nredis.register_function{function_name='set', callback=function(keys) return redis.pcall('SET', keys[1], 'v') end, flags={'no-writes'}}",
+    );
+
+    fcall(&db, "incr_by", &["n"], &["3"]);
+    assert_eq!(fcall_ro(&db, "peek", &["n"]), bulk("3"));
+    assert_eq!(
+        error(fcall_ro(&db, "incr_by", &["n"])),
+        "Can not execute a script with write flag using *_ro command."
+    );
+    // no-writes is enforced whichever command calls the function
+    for frame in [fcall(&db, "set", &["k"], &[]), fcall_ro(&db, "set", &["k"])] {
+        assert_eq!(
+            error(frame),
+            "Write commands are not allowed from read-only scripts."
+        );
+    }
+    assert_eq!(fcall(&db, "peek", &["k"], &[]), Frame::Null);
+}
+
+#[test]
+fn dump_and_restore() {
+    let db = Db::new();
+    load(&db, COUNTER);
+    load(
+        &db,
+        "#!lua name=echo\nredis.register_function('echo', function(_, args) return args[1] end)",
+    );
+    let payload = db.function_dump();
+
+    let other = Db::new();
+    assert_eq!(
+        script::function_restore(&other, &payload, RestorePolicy::Append),
+        ok()
+    );
+    let names: Vec<_> = other
+        .function_list(None)
+        .into_iter()
+        .map(|library| library.name)
+        .collect();
+    assert_eq!(names, ["counter", "echo"]);
+    assert_eq!(fcall(&other, "echo", &[], &["hi"]), bulk("hi"));
+
+    // APPEND fails as a whole when a library is already there
+    other.function_delete(b"echo").unwrap();
+    assert_eq!(
+        error(script::function_restore(
+            &other,
+            &payload,
+            RestorePolicy::Append
+        )),
+        "Library 'counter' already exists"
+    );
+    assert_eq!(other.function_list(None).len(), 1);
+    assert_eq!(
+        script::function_restore(&other, &payload, RestorePolicy::Replace),
+        ok()
+    );
+    assert_eq!(other.function_list(None).len(), 2);
+
+    // FLUSH drops libraries the payload doesn't have
+    load(
+        &other,
+        "#!lua name=extra\nredis.register_function('extra', function() return 1 end)",
+    );
+    assert_eq!(
+        script::function_restore(&other, &payload, RestorePolicy::Flush),
+        ok()
+    );
+    assert_eq!(other.function_list(None).len(), 2);
+    assert_eq!(
+        error(fcall(&other, "extra", &[], &[])),
+        "Function not found"
+    );
+
+    let mut corrupt = payload.to_vec();
+    corrupt[3] ^= 1;
+    for bad in [&corrupt[..], &payload[..5]] {
+        assert_eq!(
+            error(script::function_restore(&other, bad, RestorePolicy::Flush)),
+            "payload version or checksum are wrong"
+        );
+    }
+    assert_eq!(other.function_list(None).len(), 2);
+
+    // An empty registry dumps to just the version and checksum
+    assert_eq!(Db::new().function_dump().len(), 10);
+}
+
+// CRC-64/Jones as payloads are checked with
+fn crc64(data: &[u8]) -> u64 {
+    let poly = 0xad93d23594c935a9u64.reverse_bits();
+    let mut crc = 0u64;
+    for &byte in data {
+        crc ^= byte as u64;
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ poly
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    crc
+}
+
+// A payload with one library of LZF compressed code, claiming it decompresses to `len` bytes
+fn compressed_payload(compressed: &[u8], len: u64) -> Vec<u8> {
+    let mut payload = vec![245, 0xc3, 0x80];
+    payload.extend_from_slice(&(compressed.len() as u32).to_be_bytes());
+    payload.push(0x81);
+    payload.extend_from_slice(&len.to_be_bytes());
+    payload.extend_from_slice(compressed);
+    payload.extend_from_slice(&11u16.to_le_bytes());
+    let crc = crc64(&payload);
+    payload.extend_from_slice(&crc.to_le_bytes());
+    payload
+}
+
+// LZF literal runs of up to 32 bytes each
+fn literals(out: &mut Vec<u8>, bytes: &[u8]) {
+    for chunk in bytes.chunks(32) {
+        out.push(chunk.len() as u8 - 1);
+        out.extend_from_slice(chunk);
+    }
+}
+
+#[test]
+fn restore_compressed_code() {
+    let (head, tail) = (
+        "#!lua name=aaa\nredis.register_function('aaa', function() return 'a",
+        "' end)",
+    );
+    let code = format!("{}{}{}", head, "a".repeat(100), tail);
+    // The 100 more "a"s are a back reference one byte back
+    let mut compressed = vec![];
+    literals(&mut compressed, head.as_bytes());
+    compressed.extend_from_slice(&[7 << 5, 100 - 2 - 7, 0]);
+    literals(&mut compressed, tail.as_bytes());
+
+    let db = Db::new();
+    let payload = compressed_payload(&compressed, code.len() as u64);
+    assert_eq!(
+        script::function_restore(&db, &payload, RestorePolicy::Append),
+        ok()
+    );
+    assert_eq!(fcall(&db, "aaa", &[], &[]), bulk(&"a".repeat(101)));
+
+    // Lengths the input doesn't expand to are refused, before anything is allocated for them
+    for len in [1 << 42, code.len() as u64 + 1, code.len() as u64 - 1] {
+        let payload = compressed_payload(&compressed, len);
+        assert_eq!(
+            error(script::function_restore(
+                &Db::new(),
+                &payload,
+                RestorePolicy::Append
+            )),
+            "payload version or checksum are wrong"
+        );
+    }
+}
+
+#[test]
+fn libraries_survive_restarts() {
+    let path = std::env::temp_dir().join(format!("padis-functions-{}.dump", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+
+    let db = Db::new();
+    script::function_persist(&db, &path).unwrap();
+    load(&db, COUNTER);
+    load(
+        &db,
+        "#!lua name=echo\nredis.register_function('echo', function(_, args) return args[1] end)",
+    );
+    db.function_delete(b"counter").unwrap();
+
+    // A new server picks up where the last one left off
+    let restarted = Db::new();
+    script::function_persist(&restarted, &path).unwrap();
+    let names: Vec<_> = restarted
+        .function_list(None)
+        .into_iter()
+        .map(|library| library.name)
+        .collect();
+    assert_eq!(names, ["echo"]);
+    assert_eq!(fcall(&restarted, "echo", &[], &["hi"]), bulk("hi"));
+
+    restarted.function_flush();
+    let flushed = Db::new();
+    script::function_persist(&flushed, &path).unwrap();
+    assert!(flushed.function_list(None).is_empty());
+
+    std::fs::write(&path, b"not a payload").unwrap();
+    assert!(script::function_persist(&Db::new(), &path).is_err());
+    std::fs::remove_file(&path).unwrap();
+}
+
+// === Parsing ===
+
+#[test]
+fn parse_function_commands() {
+    let Ok(Command::FCall {
+        function,
+        keys,
+        args,
+        read_only,
+    }) = Command::from_frame(cmd_frame(&["FCALL_RO", "f", "1", "k", "a"]))
+    else {
+        panic!("expected FCALL_RO");
+    };
+    assert_eq!(function, b("f"));
+    assert_eq!(keys, [b("k")]);
+    assert_eq!(args, [b("a")]);
+    assert!(read_only);
+    assert!(matches!(
+        Command::from_frame(cmd_frame(&["fcall", "f", "0"])),
+        Ok(Command::FCall {
+            read_only: false,
+            ..
+        })
+    ));
+
+    let Ok(Command::FunctionLoad { code, replace }) =
+        Command::from_frame(cmd_frame(&["FUNCTION", "load", "replace", "#!lua name=x"]))
+    else {
+        panic!("expected FUNCTION LOAD");
+    };
+    assert_eq!(code, b("#!lua name=x"));
+    assert!(replace);
+
+    let Ok(Command::FunctionList { pattern, with_code }) = Command::from_frame(cmd_frame(&[
+        "FUNCTION",
+        "LIST",
+        "WITHCODE",
+        "LIBRARYNAME",
+        "my*",
+    ])) else {
+        panic!("expected FUNCTION LIST");
+    };
+    assert_eq!(pattern, Some(b("my*")));
+    assert!(with_code);
+
+    for (policy, expected) in [
+        (None, RestorePolicy::Append),
+        (Some("APPEND"), RestorePolicy::Append),
+        (Some("replace"), RestorePolicy::Replace),
+        (Some("FLUSH"), RestorePolicy::Flush),
+    ] {
+        let mut args = vec!["FUNCTION", "RESTORE", "payload"];
+        args.extend(policy);
+        let Ok(Command::FunctionRestore { payload, policy }) =
+            Command::from_frame(cmd_frame(&args))
+        else {
+            panic!("expected FUNCTION RESTORE");
+        };
+        assert_eq!(payload, b("payload"));
+        assert_eq!(policy, expected);
+    }
+
+    for args in [
+        &["FUNCTION", "DELETE", "lib"][..],
+        &["FUNCTION", "DUMP"],
+        &["FUNCTION", "FLUSH", "ASYNC"],
+        &["FUNCTION", "KILL"],
+    ] {
+        assert!(Command::from_frame(cmd_frame(args)).is_ok(), "{:?}", args);
+    }
+    for args in [
+        &["FUNCTION", "STATS", "now"][..],
+        &["FUNCTION", "LIST", "NAME"],
+        &["FUNCTION", "DUMP", "extra"],
+        &["FUNCTION", "RESTORE", "payload", "MERGE"],
+        &["FCALL", "f", "2", "k"],
+    ] {
+        assert!(Command::from_frame(cmd_frame(args)).is_err(), "{:?}", args);
+    }
+
+    // Scripts can't manage or call functions themselves
+    let Ok(cmd) = Command::from_frame(cmd_frame(&["FCALL", "f", "0"])) else {
+        panic!("expected FCALL");
+    };
+    assert!(cmd.is_denied_in_script());
+    let Ok(cmd) = Command::from_frame(cmd_frame(&["FUNCTION", "KILL"])) else {
+        panic!("expected FUNCTION KILL");
+    };
+    assert!(cmd.allowed_when_busy());
+}
+
+// === Integration ===
+
+async fn roundtrip(conn: &mut Connection<TcpStream>, args: &[&str]) -> Frame {
+    conn.write_frame(&cmd_frame(args)).await.unwrap();
+    conn.read_frame().await.unwrap().unwrap()
+}
+
+async fn start() -> u16 {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    tokio::spawn(async move { run_server(listener, Db::new()).await });
+    port
+}
+
+async fn connect(port: u16) -> Connection<TcpStream> {
+    Connection::new(TcpStream::connect(("127.0.0.1", port)).await.unwrap())
+}
+
+#[tokio::test]
+async fn functions_over_tcp() {
+    let port = start().await;
+    let mut client = connect(port).await;
+
+    assert_eq!(
+        roundtrip(&mut client, &["FUNCTION", "LOAD", COUNTER]).await,
+        bulk("counter")
+    );
+    assert_eq!(
+        roundtrip(&mut client, &["FCALL", "incr_by", "1", "n", "4"]).await,
+        Frame::Integer(4)
+    );
+    assert_eq!(
+        roundtrip(&mut client, &["FCALL_RO", "peek", "1", "n"]).await,
+        bulk("4")
+    );
+
+    // Functions can be queued like any other command
+    roundtrip(&mut client, &["MULTI"]).await;
+    roundtrip(&mut client, &["FCALL", "incr_by", "1", "n", "1"]).await;
+    roundtrip(&mut client, &["FCALL_RO", "peek", "1", "n"]).await;
+    assert_eq!(
+        roundtrip(&mut client, &["EXEC"]).await,
+        Frame::Array(vec![Frame::Integer(5), bulk("5")])
+    );
+
+    let listed = roundtrip(
+        &mut client,
+        &["FUNCTION", "LIST", "LIBRARYNAME", "count*", "WITHCODE"],
+    )
+    .await;
+    let function = |name: &str, description: Frame, flags: &[&str]| {
+        Frame::Array(vec![
+            bulk("name"),
+            bulk(name),
+            bulk("description"),
+            description,
+            bulk("flags"),
+            Frame::Array(flags.iter().map(|flag| bulk(flag)).collect()),
+        ])
+    };
+    assert_eq!(
+        listed,
+        Frame::Array(vec![Frame::Array(vec![
+            bulk("library_name"),
+            bulk("counter"),
+            bulk("engine"),
+            bulk("LUA"),
+            bulk("functions"),
+            Frame::Array(vec![
+                function("incr_by", Frame::Null, &[]),
+                function("peek", bulk("reads the counter"), &["no-writes"]),
+            ]),
+            bulk("library_code"),
+            bulk(COUNTER),
+        ])])
+    );
+
+    // Ship the library to another server
+    let Frame::BulkString(payload) = roundtrip(&mut client, &["FUNCTION", "DUMP"]).await else {
+        panic!("expected a payload");
+    };
+    let mut other = connect(start().await).await;
+    other
+        .write_frame(&Frame::Array(vec![
+            bulk("FUNCTION"),
+            bulk("RESTORE"),
+            Frame::BulkString(payload),
+        ]))
+        .await
+        .unwrap();
+    assert_eq!(other.read_frame().await.unwrap().unwrap(), ok());
+    assert_eq!(
+        roundtrip(&mut other, &["FCALL", "incr_by", "1", "n", "9"]).await,
+        Frame::Integer(9)
+    );
+
+    assert_eq!(
+        roundtrip(&mut client, &["FUNCTION", "DELETE", "counter"]).await,
+        ok()
+    );
+    assert_eq!(
+        error(roundtrip(&mut client, &["FUNCTION", "DELETE", "counter"]).await),
+        "Library not found"
+    );
+    assert_eq!(
+        error(roundtrip(&mut client, &["FUNCTION", "KILL"]).await),
+        "NOTBUSY No scripts in execution right now."
+    );
+    assert_eq!(roundtrip(&mut other, &["FUNCTION", "FLUSH"]).await, ok());
+    assert_eq!(
+        roundtrip(&mut other, &["FUNCTION", "LIST"]).await,
+        Frame::Array(vec![])
+    );
+}
@@ -0,0 +1,528 @@
+use bytes::Bytes;
+use padis::{Command, Connection, Db, Frame, run_server, script, wasm};
+use tokio::net::{TcpListener, TcpStream};
+
+fn b(s: &str) -> Bytes {
+    Bytes::copy_from_slice(s.as_bytes())
+}
+
+// Helper to build a command frame
+fn cmd_frame(args: &[&str]) -> Frame {
+    Frame::Array(
+        args.iter()
+            .map(|s| Frame::BulkString(Bytes::copy_from_slice(s.as_bytes())))
+            .collect(),
+    )
+}
+
+fn bulk(s: &str) -> Frame {
+    Frame::BulkString(b(s))
+}
+
+fn ok() -> Frame {
+    Frame::SimpleString("OK".into())
+}
+
+fn error(frame: Frame) -> String {
+    match frame {
+        Frame::SimpleError(err) => err,
+        _ => panic!("expected an error"),
+    }
+}
+
+const READONLY: i32 = 1;
+
+// Every command the test module can register, as (name, handler, flags)
+const COMMANDS: &[(&str, &str, i32)] = &[
+    ("WASM.ECHO", "echo", READONLY),
+    ("wasm.call", "call", 0),
+    ("WASM.SET", "set", 0),
+    ("WASM.GET", "get", READONLY),
+    ("WASM.RSET", "set", READONLY),
+    ("WASM.RCALL", "call", READONLY),
+    ("WASM.SPIN", "spin", READONLY),
+    ("WASM.CHATTY", "chatty", READONLY),
+    ("WASM.GROW", "grow", READONLY),
+];
+
+// Where the module keeps the key and replies its handlers use
+const KEY: (usize, &str) = (256, "greeting");
+const VALUE: (usize, &str) = (288, "+hi\r\n");
+const OK: (usize, &str) = (320, "+OK\r\n");
+const NULL: (usize, &str) = (352, "$-1\r\n");
+const GREW: (usize, &str) = (384, "+grew\r\n");
+const NO_MEMORY: (usize, &str) = (416, "-ERR no memory\r\n");
+
+// A pointer and length as handlers return them
+fn packed((offset, text): (usize, &str)) -> i64 {
+    ((offset as i64) << 32) | text.len() as i64
+}
+
+fn data((offset, text): (usize, &str)) -> String {
+    format!(
+        "(data (i32.const {}) \"{}\")",
+        offset,
+        text.replace('\r', "\\0d").replace('\n', "\\0a")
+    )
+}
+
+// A module whose padis_init registers `commands` and then runs `init`, with `pages` of memory
+fn module(commands: &[(&str, &str, i32)], init: &str, pages: u32) -> Vec<u8> {
+    let mut statics: Vec<String> = [KEY, VALUE, OK, NULL, GREW, NO_MEMORY]
+        .into_iter()
+        .map(data)
+        .collect();
+    let mut registrations = String::new();
+    for (i, (name, handler, flags)) in commands.iter().enumerate() {
+        let at = 1024 + i * 64;
+        statics.push(data((at, name)));
+        statics.push(data((at + 32, handler)));
+        registrations += &format!(
+            "(call $register (i32.const {}) (i32.const {}) (i32.const {}) (i32.const {}) (i32.const {}))\n",
+            at,
+            name.len(),
+            at + 32,
+            handler.len(),
+            flags
+        );
+    }
+    let wat = format!(
+        r#"(module
+  (import "padis" "register_command" (func $register (param i32 i32 i32 i32 i32)))
+  (import "padis" "get" (func $get (param i32 i32) (result i64)))
+  (import "padis" "set" (func $set (param i32 i32 i32 i32)))
+  (import "padis" "call" (func $call (param i32 i32) (result i64)))
+  (import "padis" "log" (func $log (param i32 i32)))
+  (memory (export "memory") {pages})
+  (global $heap (mut i32) (i32.const 4096))
+  {statics}
+  ;; A bump allocator, growing memory as it needs to
+  (func (export "padis_alloc") (param $len i32) (result i32)
+    (local $ptr i32)
+    (local.set $ptr (global.get $heap))
+    (global.set $heap (i32.add (global.get $heap) (local.get $len)))
+    (block $done
+      (loop $grow
+        (br_if $done (i32.le_u (global.get $heap) (i32.mul (memory.size) (i32.const 65536))))
+        (if (i32.eq (memory.grow (i32.const 1)) (i32.const -1)) (then unreachable))
+        (br $grow)))
+    (local.get $ptr))
+  (func (export "padis_init")
+    {registrations}
+    {init})
+  ;; Replies with its arguments
+  (func (export "echo") (param $ptr i32) (param $len i32) (result i64)
+    (i64.or
+      (i64.shl (i64.extend_i32_u (local.get $ptr)) (i64.const 32))
+      (i64.extend_i32_u (local.get $len))))
+  ;; Runs its arguments as a command
+  (func (export "call") (param $ptr i32) (param $len i32) (result i64)
+    (call $call (local.get $ptr) (local.get $len)))
+  (func (export "set") (param i32 i32) (result i64)
+    (call $set (i32.const {key}) (i32.const {key_len}) (i32.const {value}) (i32.const {value_len}))
+    (call $log (i32.const {key}) (i32.const {key_len}))
+    (i64.const {ok}))
+  ;; The value is itself a RESP reply
+  (func (export "get") (param i32 i32) (result i64)
+    (local $value i64)
+    (local.set $value (call $get (i32.const {key}) (i32.const {key_len})))
+    (if (result i64) (i64.lt_s (local.get $value) (i64.const 0))
+      (then (i64.const {null}))
+      (else (local.get $value))))
+  (func (export "spin") (param i32 i32) (result i64)
+    (loop $forever (br $forever))
+    (unreachable))
+  (func (export "chatty") (param i32 i32) (result i64)
+    (loop $forever
+      (drop (call $get (i32.const {key}) (i32.const {key_len})))
+      (br $forever))
+    (unreachable))
+  (func (export "grow") (param i32 i32) (result i64)
+    (if (result i64) (i32.eq (memory.grow (i32.const 100)) (i32.const -1))
+      (then (i64.const {no_memory}))
+      (else (i64.const {grew}))))
+  (func (export "wrong") (param i32) (result i32) (local.get 0)))"#,
+        statics = statics.join("\n  "),
+        key = KEY.0,
+        key_len = KEY.1.len(),
+        value = VALUE.0,
+        value_len = VALUE.1.len(),
+        ok = packed(OK),
+        null = packed(NULL),
+        grew = packed(GREW),
+        no_memory = packed(NO_MEMORY),
+    );
+    wat::parse_str(wat).unwrap()
+}
+
+fn load(db: &Db) {
+    wasm::load(db, "test", "test.wasm", &module(COMMANDS, "", 1)).unwrap();
+}
+
+fn run(db: &Db, args: &[&str]) -> Frame {
+    let Ok(cmd) = Command::from_frame_in(cmd_frame(args), db) else {
+        panic!("expected a module command");
+    };
+    let Command::ModuleCall { name, args, .. } = cmd else {
+        panic!("expected a module command");
+    };
+    wasm::call(db, &name, args)
+}
+
+// === Db ===
+
+#[test]
+fn module_commands() {
+    let db = Db::new();
+    load(&db);
+
+    assert_eq!(
+        run(&db, &["wasm.echo", "a", "b c"]),
+        Frame::Array(vec![bulk("a"), bulk("b c")])
+    );
+    assert_eq!(run(&db, &["WASM.ECHO"]), Frame::Array(vec![]));
+    assert_eq!(run(&db, &["WASM.GET"]), Frame::Null);
+    assert_eq!(run(&db, &["WASM.SET"]), ok());
+    assert_eq!(db.get(&b("greeting")), Some(b("+hi\r\n")));
+    assert_eq!(run(&db, &["WASM.GET"]), Frame::SimpleString("hi".into()));
+
+    // Commands run through `call` get their replies, errors included
+    assert_eq!(run(&db, &["WASM.CALL", "SET", "k", "v"]), ok());
+    assert_eq!(db.get(&b("k")), Some(b("v")));
+    assert_eq!(run(&db, &["WASM.CALL", "GET", "k"]), bulk("v"));
+    assert_eq!(
+        error(run(&db, &["WASM.CALL", "SADD", "k", "m"])),
+        "WRONGTYPE Operation against a key holding the wrong kind of value"
+    );
+    assert_eq!(
+        error(run(&db, &["WASM.CALL", "MULTI"])),
+        "This Redis command is not allowed from module commands"
+    );
+    assert_eq!(error(run(&db, &["WASM.CALL", "NOPE"])), "Unknown Command");
+    db.config_set(&[(b("wasm-fuel-limit"), b("0"))]).unwrap();
+    assert_eq!(
+        error(run(&db, &["WASM.ECHO"])),
+        "module exceeded wasm-fuel-limit"
+    );
+
+    let modules = db.module_list();
+    assert_eq!(modules.len(), 1);
+    assert_eq!(modules[0].name, "test");
+    assert_eq!(modules[0].path, "test.wasm");
+    let names: Vec<_> = modules[0].commands.iter().map(|c| c.name.clone()).collect();
+    assert_eq!(names[..2], [b("WASM.ECHO"), b("WASM.CALL")]);
+}
+
+#[test]
+fn read_only_commands() {
+    let db = Db::new();
+    load(&db);
+    assert_eq!(
+        error(run(&db, &["WASM.RSET"])),
+        "Write commands are not allowed from read-only module commands."
+    );
+    assert_eq!(
+        error(run(&db, &["WASM.RCALL", "SET", "k", "v"])),
+        "Write commands are not allowed from read-only module commands."
+    );
+    assert_eq!(run(&db, &["WASM.RCALL", "GET", "k"]), Frame::Null);
+    assert_eq!(db.get(&b("greeting")), None);
+
+    // Only commands registered without READONLY count as writes
+    let Ok(echo) = Command::from_frame_in(cmd_frame(&["WASM.ECHO"]), &db) else {
+        panic!("expected WASM.ECHO");
+    };
+    assert!(!echo.is_write());
+    let Ok(set) = Command::from_frame_in(cmd_frame(&["WASM.SET"]), &db) else {
+        panic!("expected WASM.SET");
+    };
+    assert!(set.is_write());
+}
+
+#[test]
+fn limits() {
+    let db = Db::new();
+    load(&db);
+    assert_eq!(
+        error(run(&db, &["WASM.SPIN"])),
+        "module exceeded wasm-fuel-limit"
+    );
+    db.config_set(&[(b("wasm-time-limit"), b("0"))]).unwrap();
+    assert_eq!(
+        error(run(&db, &["WASM.CHATTY"])),
+        "module exceeded wasm-time-limit"
+    );
+
+    assert_eq!(run(&db, &["WASM.GROW"]), Frame::SimpleString("grew".into()));
+    db.config_set(&[(b("wasm-memory-limit"), b("1048576"))])
+        .unwrap();
+    assert_eq!(error(run(&db, &["WASM.GROW"])), "ERR no memory");
+
+    assert_eq!(
+        db.config_get(&[b("wasm-*")]),
+        vec![
+            (b("wasm-fuel-limit"), b("100000000")),
+            (b("wasm-time-limit"), b("0")),
+            (b("wasm-memory-limit"), b("1048576")),
+        ]
+    );
+    assert!(db.config_set(&[(b("wasm-fuel-limit"), b("lots"))]).is_err());
+
+    // The time limit can't stop a command that only computes, so fuel is capped instead
+    db.config_set(&[(b("wasm-fuel-limit"), b("1000000000"))])
+        .unwrap();
+    let err = db
+        .config_set(&[(b("wasm-fuel-limit"), b("1000000001"))])
+        .unwrap_err();
+    assert!(err.to_string().contains("'wasm-fuel-limit'"), "{}", err);
+    assert_eq!(
+        db.config_get(&[b("wasm-fuel-limit")]),
+        vec![(b("wasm-fuel-limit"), b("1000000000"))]
+    );
+}
+
+#[test]
+fn bad_modules_are_not_loaded() {
+    let db = Db::new();
+    let cases: Vec<(Vec<u8>, &str)> = vec![
+        (b"not wasm".to_vec(), "Error loading the extension: "),
+        (
+            wat::parse_str("(module (memory (export \"memory\") 1))").unwrap(),
+            "Error loading the extension: ",
+        ),
+        (
+            module(&[], "", 1),
+            "Error loading the extension: the module registered no commands",
+        ),
+        (
+            module(&[("GET", "get", 0)], "", 1),
+            "Error loading the extension: Command 'GET' is already registered",
+        ),
+        (
+            module(&[("A", "echo", 0), ("a", "echo", 0)], "", 1),
+            "Error loading the extension: Command 'A' is already registered",
+        ),
+        (
+            module(&[("A", "missing", 0)], "", 1),
+            "Error loading the extension: handler 'missing' must be exported as (i32, i32) -> i64",
+        ),
+        (
+            module(&[("A", "wrong", 0)], "", 1),
+            "Error loading the extension: handler 'wrong' must be exported as (i32, i32) -> i64",
+        ),
+        (
+            module(
+                COMMANDS,
+                "(drop (call $get (i32.const 0) (i32.const 1)))",
+                1,
+            ),
+            "Error loading the extension: the keyspace can't be used from padis_init",
+        ),
+        (
+            module(COMMANDS, "(loop $forever (br $forever))", 1),
+            "Error loading the extension: module exceeded wasm-fuel-limit",
+        ),
+        (module(COMMANDS, "", 2048), "Error loading the extension: "),
+    ];
+    for (wasm, expected) in cases {
+        let err = wasm::load(&db, "bad", "bad.wasm", &wasm).unwrap_err();
+        assert!(err.starts_with(expected), "{}", err);
+    }
+    assert!(db.module_list().is_empty());
+
+    load(&db);
+    assert_eq!(
+        wasm::load(
+            &db,
+            "test",
+            "again.wasm",
+            &module(&[("OTHER", "echo", 0)], "", 1)
+        )
+        .unwrap_err(),
+        "Module 'test' is already loaded"
+    );
+    assert_eq!(
+        wasm::load(
+            &db,
+            "other",
+            "other.wasm",
+            &module(&[("wasm.echo", "echo", 0)], "", 1)
+        )
+        .unwrap_err(),
+        "Command 'WASM.ECHO' is already registered"
+    );
+}
+
+#[test]
+fn unloading_removes_commands() {
+    let db = Db::new();
+    load(&db);
+    db.module_unload(b"test").unwrap();
+    assert!(matches!(
+        Command::from_frame_in(cmd_frame(&["WASM.ECHO"]), &db),
+        Err(padis::cmd::CommandError::Unknown(_))
+    ));
+    assert_eq!(
+        db.module_unload(b"test").unwrap_err().to_string(),
+        "Error unloading module: no such module with that name"
+    );
+    // The module can be loaded again under the same name
+    load(&db);
+}
+
+// === Parsing ===
+
+#[test]
+fn parse_module_commands() {
+    let Ok(Command::ModuleLoad { path }) =
+        Command::from_frame(cmd_frame(&["module", "load", "/tmp/m.wasm"]))
+    else {
+        panic!("expected MODULE LOAD");
+    };
+    assert_eq!(path, b("/tmp/m.wasm"));
+    let Ok(Command::ModuleUnload { name }) =
+        Command::from_frame(cmd_frame(&["MODULE", "UNLOAD", "m"]))
+    else {
+        panic!("expected MODULE UNLOAD");
+    };
+    assert_eq!(name, b("m"));
+    assert!(matches!(
+        Command::from_frame(cmd_frame(&["MODULE", "LIST"])),
+        Ok(Command::ModuleList)
+    ));
+    for args in [
+        &["MODULE", "LOAD"][..],
+        &["MODULE", "LOAD", "/tmp/m.wasm", "arg"],
+        &["MODULE", "LIST", "extra"],
+        &["MODULE", "LOADEX", "/tmp/m.wasm"],
+    ] {
+        assert!(Command::from_frame(cmd_frame(args)).is_err(), "{:?}", args);
+    }
+
+    // Only a parse that knows the keyspace sees modules' commands
+    let db = Db::new();
+    load(&db);
+    assert!(matches!(
+        Command::from_frame(cmd_frame(&["WASM.ECHO"])),
+        Err(padis::cmd::CommandError::Unknown(_))
+    ));
+    let Ok(Command::ModuleCall {
+        name,
+        args,
+        read_only,
+    }) = Command::from_frame_in(cmd_frame(&["wasm.Echo", "x"]), &db)
+    else {
+        panic!("expected a module command");
+    };
+    assert_eq!(name, b("WASM.ECHO"));
+    assert_eq!(args, [b("x")]);
+    assert!(read_only);
+}
+
+// === Integration ===
+
+async fn roundtrip(conn: &mut Connection<TcpStream>, args: &[&str]) -> Frame {
+    conn.write_frame(&cmd_frame(args)).await.unwrap();
+    conn.read_frame().await.unwrap().unwrap()
+}
+
+async fn start() -> u16 {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    tokio::spawn(async move { run_server(listener, Db::new()).await });
+    port
+}
+
+async fn connect(port: u16) -> Connection<TcpStream> {
+    Connection::new(TcpStream::connect(("127.0.0.1", port)).await.unwrap())
+}
+
+#[tokio::test]
+async fn modules_over_tcp() {
+    let dir = std::env::temp_dir().join(format!("padis-wasm-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("greeter.wasm");
+    std::fs::write(&path, module(COMMANDS, "", 1)).unwrap();
+    let path = path.to_str().unwrap();
+
+    let port = start().await;
+    let mut client = connect(port).await;
+    assert_eq!(
+        error(roundtrip(&mut client, &["WASM.ECHO", "a"]).await),
+        "Unknown Command"
+    );
+    let err = error(roundtrip(&mut client, &["MODULE", "LOAD", "/nonexistent.wasm"]).await);
+    assert!(err.starts_with("Error loading the extension: "), "{}", err);
+    assert_eq!(
+        roundtrip(&mut client, &["MODULE", "LOAD", path]).await,
+        ok()
+    );
+    assert_eq!(
+        roundtrip(&mut client, &["WASM.ECHO", "a"]).await,
+        Frame::Array(vec![bulk("a")])
+    );
+    assert_eq!(
+        roundtrip(&mut client, &["WASM.CALL", "SET", "k", "v"]).await,
+        ok()
+    );
+    assert_eq!(roundtrip(&mut client, &["GET", "k"]).await, bulk("v"));
+
+    // Module commands queue in transactions and can be called from scripts
+    roundtrip(&mut client, &["MULTI"]).await;
+    roundtrip(&mut client, &["WASM.SET"]).await;
+    roundtrip(&mut client, &["WASM.GET"]).await;
+    assert_eq!(
+        roundtrip(&mut client, &["EXEC"]).await,
+        Frame::Array(vec![ok(), Frame::SimpleString("hi".into())])
+    );
+    assert_eq!(
+        roundtrip(
+            &mut client,
+            &[
+                "EVAL",
+                "return redis.call('WASM.CALL', 'GET', KEYS[1])",
+                "1",
+                "k"
+            ]
+        )
+        .await,
+        bulk("v")
+    );
+
+    let listed = roundtrip(&mut client, &["MODULE", "LIST"]).await;
+    let Frame::Array(modules) = listed else {
+        panic!("expected modules");
+    };
+    let Frame::Array(fields) = &modules[0] else {
+        panic!("expected a module");
+    };
+    assert_eq!(
+        fields[..4],
+        [bulk("name"), bulk("greeter"), bulk("path"), bulk(path)]
+    );
+
+    assert_eq!(
+        roundtrip(&mut client, &["MODULE", "UNLOAD", "greeter"]).await,
+        ok()
+    );
+    assert_eq!(
+        error(roundtrip(&mut client, &["WASM.ECHO", "a"]).await),
+        "Unknown Command"
+    );
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn scripts_reach_module_commands() {
+    let db = Db::new();
+    load(&db);
+    assert_eq!(
+        script::eval(
+            &db,
+            b("return redis.call('wasm.echo', 'x')"),
+            vec![],
+            vec![]
+        ),
+        Frame::Array(vec![bulk("x")])
+    );
+}
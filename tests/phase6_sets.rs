@@ -0,0 +1,291 @@
+use bytes::Bytes;
+use padis::db::SetOp;
+use padis::{Command, Db, Frame, run_server};
+use std::collections::HashSet;
+use std::time::Duration;
+use tokio::net::TcpListener;
+
+fn b(s: &str) -> Bytes {
+    Bytes::copy_from_slice(s.as_bytes())
+}
+
+fn members(items: &[&str]) -> Vec<Bytes> {
+    items.iter().map(|s| b(s)).collect()
+}
+
+fn sorted(mut items: Vec<Bytes>) -> Vec<Bytes> {
+    items.sort();
+    items
+}
+
+// Helper to build a command frame
+fn cmd_frame(args: &[&str]) -> Frame {
+    Frame::Array(
+        args.iter()
+            .map(|s| Frame::BulkString(Bytes::copy_from_slice(s.as_bytes())))
+            .collect(),
+    )
+}
+
+// === Db ===
+
+#[test]
+fn sadd_counts_only_new_members() {
+    let db = Db::new();
+    assert_eq!(db.sadd(&b("s"), members(&["a", "b"])).unwrap(), 2);
+    assert_eq!(db.sadd(&b("s"), members(&["b", "c", "c"])).unwrap(), 1);
+    assert_eq!(db.scard(&b("s")).unwrap(), 3);
+}
+
+#[test]
+fn srem_deletes_empty_set() {
+    let db = Db::new();
+    db.sadd(&b("s"), members(&["a", "b"])).unwrap();
+    assert_eq!(db.srem(&b("s"), &members(&["a", "b", "x"])).unwrap(), 2);
+    assert!(db.keys().is_empty());
+}
+
+#[test]
+fn membership_checks() {
+    let db = Db::new();
+    db.sadd(&b("s"), members(&["a"])).unwrap();
+    assert!(db.sismember(&b("s"), &b("a")).unwrap());
+    assert!(!db.sismember(&b("missing"), &b("a")).unwrap());
+    assert_eq!(
+        db.smismember(&b("s"), &members(&["a", "b"])).unwrap(),
+        vec![true, false]
+    );
+}
+
+#[test]
+fn set_commands_on_string_are_wrong_type() {
+    let db = Db::new();
+    db.set(&b("str"), b("value"), None);
+    assert!(db.sadd(&b("str"), members(&["a"])).is_err());
+    assert!(db.smembers(&b("str")).is_err());
+    assert!(db.set_op(SetOp::Union, &[b("str")]).is_err());
+}
+
+#[test]
+fn get_on_set_is_wrong_type() {
+    let db = Db::new();
+    db.sadd(&b("s"), members(&["a"])).unwrap();
+    assert!(db.get_string(&b("s")).is_err());
+    assert!(db.get(&b("s")).is_none());
+}
+
+#[test]
+fn spop_removes_members() {
+    let db = Db::new();
+    db.sadd(&b("s"), members(&["a", "b", "c"])).unwrap();
+    let popped = db.spop(&b("s"), 2).unwrap();
+    assert_eq!(popped.len(), 2);
+    for member in &popped {
+        assert!(!db.sismember(&b("s"), member).unwrap());
+    }
+    assert_eq!(db.spop(&b("s"), 5).unwrap().len(), 1);
+    assert!(db.keys().is_empty());
+}
+
+#[test]
+fn srandmember_positive_and_negative_counts() {
+    let db = Db::new();
+    db.sadd(&b("s"), members(&["a", "b", "c"])).unwrap();
+
+    let distinct = db.srandmember(&b("s"), 10).unwrap();
+    assert_eq!(distinct.len(), 3);
+    assert_eq!(distinct.iter().collect::<HashSet<_>>().len(), 3);
+
+    let repeated = db.srandmember(&b("s"), -10).unwrap();
+    assert_eq!(repeated.len(), 10);
+    assert_eq!(db.scard(&b("s")).unwrap(), 3);
+}
+
+#[test]
+fn huge_counts_stop_at_the_set() {
+    let db = Db::new();
+    db.sadd(&b("s"), members(&["a", "b", "c"])).unwrap();
+    assert_eq!(
+        db.srandmember(&b("s"), 1_000_000_000_000_000)
+            .unwrap()
+            .len(),
+        3
+    );
+    assert_eq!(db.spop(&b("s"), 1_000_000_000_000_000).unwrap().len(), 3);
+    assert!(db.keys().is_empty());
+}
+
+#[test]
+fn set_algebra() {
+    let db = Db::new();
+    db.sadd(&b("a"), members(&["1", "2", "3"])).unwrap();
+    db.sadd(&b("b"), members(&["2", "3", "4"])).unwrap();
+
+    let keys = [b("a"), b("b")];
+    assert_eq!(
+        sorted(db.set_op(SetOp::Inter, &keys).unwrap()),
+        members(&["2", "3"])
+    );
+    assert_eq!(
+        sorted(db.set_op(SetOp::Union, &keys).unwrap()),
+        members(&["1", "2", "3", "4"])
+    );
+    assert_eq!(db.set_op(SetOp::Diff, &keys).unwrap(), members(&["1"]));
+    assert!(
+        db.set_op(SetOp::Inter, &[b("a"), b("missing")])
+            .unwrap()
+            .is_empty()
+    );
+}
+
+#[test]
+fn store_replaces_destination() {
+    let db = Db::new();
+    db.sadd(&b("a"), members(&["1", "2"])).unwrap();
+    db.sadd(&b("b"), members(&["2"])).unwrap();
+    db.set(&b("dest"), b("string"), Some(Duration::from_millis(50)));
+
+    let n = db
+        .set_op_store(SetOp::Inter, &b("dest"), &[b("a"), b("b")])
+        .unwrap();
+    assert_eq!(n, 1);
+    assert_eq!(db.smembers(&b("dest")).unwrap(), members(&["2"]));
+
+    // An empty result deletes the destination
+    let n = db
+        .set_op_store(SetOp::Diff, &b("dest"), &[b("b"), b("a")])
+        .unwrap();
+    assert_eq!(n, 0);
+    assert!(!db.keys().contains(&b("dest")));
+}
+
+#[test]
+fn sintercard_honours_limit() {
+    let db = Db::new();
+    db.sadd(&b("a"), members(&["1", "2", "3", "4"])).unwrap();
+    db.sadd(&b("b"), members(&["1", "2", "3"])).unwrap();
+    assert_eq!(db.sintercard(&[b("a"), b("b")], 0).unwrap(), 3);
+    assert_eq!(db.sintercard(&[b("a"), b("b")], 2).unwrap(), 2);
+}
+
+#[test]
+fn smove_between_sets() {
+    let db = Db::new();
+    db.sadd(&b("src"), members(&["x"])).unwrap();
+    assert!(db.smove(&b("src"), &b("dst"), &b("x")).unwrap());
+    assert!(!db.smove(&b("src"), &b("dst"), &b("x")).unwrap());
+    assert!(db.sismember(&b("dst"), &b("x")).unwrap());
+    assert!(!db.keys().contains(&b("src")));
+}
+
+// === Parsing ===
+
+#[test]
+fn parse_sadd_requires_member() {
+    assert!(Command::from_frame(cmd_frame(&["SADD", "s"])).is_err());
+    let cmd = Command::from_frame(cmd_frame(&["sadd", "s", "a", "b"])).unwrap();
+    assert!(matches!(cmd, Command::SAdd { members, .. } if members.len() == 2));
+}
+
+#[test]
+fn parse_sintercard_with_limit() {
+    let cmd = Command::from_frame(cmd_frame(&["SINTERCARD", "2", "a", "b", "LIMIT", "5"])).unwrap();
+    assert!(matches!(cmd, Command::SInterCard { keys, limit: 5 } if keys.len() == 2));
+
+    assert!(Command::from_frame(cmd_frame(&["SINTERCARD", "3", "a", "b"])).is_err());
+}
+
+#[test]
+fn parse_store_commands() {
+    let cmd = Command::from_frame(cmd_frame(&["SUNIONSTORE", "dest", "a", "b"])).unwrap();
+    assert!(matches!(
+        cmd,
+        Command::SetOpStore { op: SetOp::Union, destination, .. } if destination == "dest"
+    ));
+}
+
+#[test]
+fn parse_srandmember_bounds_repeats() {
+    let cmd = Command::from_frame(cmd_frame(&["SRANDMEMBER", "s", "-16777216"])).unwrap();
+    assert!(matches!(
+        cmd,
+        Command::SRandMember {
+            count: Some(-16777216),
+            ..
+        }
+    ));
+
+    let Err(err) = Command::from_frame(cmd_frame(&["SRANDMEMBER", "s", "-1000000000000000"]))
+    else {
+        panic!("expected an error");
+    };
+    assert_eq!(err.to_string(), "value is out of range");
+}
+
+// === Integration ===
+
+#[tokio::test]
+async fn set_commands_over_tcp() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    tokio::spawn(async move { run_server(listener, Db::new()).await });
+
+    let client = redis::Client::open(format!("redis://127.0.0.1:{}", port)).unwrap();
+    let mut con = client.get_multiplexed_async_connection().await.unwrap();
+
+    let added: i64 = redis::cmd("SADD")
+        .arg("tags")
+        .arg("rust")
+        .arg("redis")
+        .query_async(&mut con)
+        .await
+        .unwrap();
+    assert_eq!(added, 2);
+
+    let mut tags: Vec<String> = redis::cmd("SMEMBERS")
+        .arg("tags")
+        .query_async(&mut con)
+        .await
+        .unwrap();
+    tags.sort();
+    assert_eq!(tags, vec!["redis", "rust"]);
+
+    let popped: Option<String> = redis::cmd("SPOP")
+        .arg("missing")
+        .query_async(&mut con)
+        .await
+        .unwrap();
+    assert!(popped.is_none());
+
+    // Huge counts reply with what there is rather than allocating for the count
+    let sampled: Vec<String> = redis::cmd("SRANDMEMBER")
+        .arg("tags")
+        .arg("1000000000000000")
+        .query_async(&mut con)
+        .await
+        .unwrap();
+    assert_eq!(sampled.len(), 2);
+    let err = redis::cmd("SRANDMEMBER")
+        .arg("tags")
+        .arg("-1000000000000000")
+        .query_async::<Vec<String>>(&mut con)
+        .await
+        .unwrap_err();
+    assert!(err.to_string().contains("out of range"), "{}", err);
+    let popped: Vec<String> = redis::cmd("SPOP")
+        .arg("tags")
+        .arg("1000000000000000")
+        .query_async(&mut con)
+        .await
+        .unwrap();
+    assert_eq!(popped.len(), 2);
+    let _: i64 = redis::cmd("SADD")
+        .arg("tags")
+        .arg("rust")
+        .query_async(&mut con)
+        .await
+        .unwrap();
+
+    let result: Result<String, _> = redis::cmd("GET").arg("tags").query_async(&mut con).await;
+    assert!(result.is_err());
+}
@@ -0,0 +1,427 @@
+use bytes::Bytes;
+use padis::db::{LexBound, RangeBy, ScoreBound, ZAddComparison, ZAddCondition, ZAddFlags, ZRange};
+use padis::{Command, Db, Frame, run_server};
+use tokio::net::TcpListener;
+
+fn b(s: &str) -> Bytes {
+    Bytes::copy_from_slice(s.as_bytes())
+}
+
+fn pairs(items: &[(f64, &str)]) -> Vec<(f64, Bytes)> {
+    items.iter().map(|(s, m)| (*s, b(m))).collect()
+}
+
+fn members(items: Vec<(Bytes, f64)>) -> Vec<Bytes> {
+    items.into_iter().map(|(m, _)| m).collect()
+}
+
+fn by_rank(start: i64, stop: i64, rev: bool) -> ZRange {
+    ZRange {
+        by: RangeBy::Rank { start, stop },
+        rev,
+        limit: None,
+    }
+}
+
+// Helper to build a command frame
+fn cmd_frame(args: &[&str]) -> Frame {
+    Frame::Array(
+        args.iter()
+            .map(|s| Frame::BulkString(Bytes::copy_from_slice(s.as_bytes())))
+            .collect(),
+    )
+}
+
+fn leaderboard() -> Db {
+    let db = Db::new();
+    db.zadd(
+        &b("lb"),
+        ZAddFlags::default(),
+        pairs(&[
+            (10.0, "alice"),
+            (20.0, "bob"),
+            (20.0, "carol"),
+            (30.0, "dave"),
+        ]),
+    )
+    .unwrap();
+    db
+}
+
+// === Db ===
+
+#[test]
+fn zadd_and_zscore() {
+    let db = leaderboard();
+    assert_eq!(db.zcard(&b("lb")).unwrap(), 4);
+    assert_eq!(db.zscore(&b("lb"), &b("bob")).unwrap(), Some(20.0));
+    assert_eq!(db.zscore(&b("lb"), &b("zed")).unwrap(), None);
+}
+
+#[test]
+fn zadd_flags() {
+    let db = leaderboard();
+    let nx = ZAddFlags {
+        condition: Some(ZAddCondition::Nx),
+        ..Default::default()
+    };
+    assert_eq!(
+        db.zadd(&b("lb"), nx, pairs(&[(1.0, "alice"), (5.0, "eve")]))
+            .unwrap(),
+        1
+    );
+    assert_eq!(db.zscore(&b("lb"), &b("alice")).unwrap(), Some(10.0));
+
+    let xx_ch = ZAddFlags {
+        condition: Some(ZAddCondition::Xx),
+        ch: true,
+        ..Default::default()
+    };
+    assert_eq!(
+        db.zadd(&b("lb"), xx_ch, pairs(&[(11.0, "alice"), (1.0, "zed")]))
+            .unwrap(),
+        1
+    );
+    assert_eq!(db.zscore(&b("lb"), &b("zed")).unwrap(), None);
+
+    let gt = ZAddFlags {
+        comparison: Some(ZAddComparison::Gt),
+        ch: true,
+        ..Default::default()
+    };
+    assert_eq!(
+        db.zadd(&b("lb"), gt, pairs(&[(1.0, "alice"), (99.0, "bob")]))
+            .unwrap(),
+        1
+    );
+    assert_eq!(db.zscore(&b("lb"), &b("alice")).unwrap(), Some(11.0));
+    assert_eq!(db.zscore(&b("lb"), &b("bob")).unwrap(), Some(99.0));
+}
+
+#[test]
+fn zadd_xx_does_not_create_key() {
+    let db = Db::new();
+    let xx = ZAddFlags {
+        condition: Some(ZAddCondition::Xx),
+        ..Default::default()
+    };
+    db.zadd(&b("z"), xx, pairs(&[(1.0, "a")])).unwrap();
+    assert!(db.keys().is_empty());
+}
+
+#[test]
+fn zincrby_and_nan() {
+    let db = leaderboard();
+    let score = db
+        .zincrby(&b("lb"), ZAddFlags::default(), 5.0, &b("alice"))
+        .unwrap();
+    assert_eq!(score, Some(15.0));
+
+    db.zincrby(&b("lb"), ZAddFlags::default(), f64::INFINITY, &b("alice"))
+        .unwrap();
+    assert!(
+        db.zincrby(
+            &b("lb"),
+            ZAddFlags::default(),
+            f64::NEG_INFINITY,
+            &b("alice")
+        )
+        .is_err()
+    );
+}
+
+#[test]
+fn ranks_break_ties_by_member() {
+    let db = leaderboard();
+    assert_eq!(
+        db.zrank(&b("lb"), &b("alice"), false).unwrap(),
+        Some((0, 10.0))
+    );
+    assert_eq!(
+        db.zrank(&b("lb"), &b("carol"), false).unwrap(),
+        Some((2, 20.0))
+    );
+    assert_eq!(
+        db.zrank(&b("lb"), &b("carol"), true).unwrap(),
+        Some((1, 20.0))
+    );
+    assert_eq!(db.zrank(&b("lb"), &b("zed"), false).unwrap(), None);
+}
+
+#[test]
+fn range_by_rank() {
+    let db = leaderboard();
+    assert_eq!(
+        members(db.zrange(&b("lb"), &by_rank(0, -1, false)).unwrap()),
+        vec![b("alice"), b("bob"), b("carol"), b("dave")]
+    );
+    assert_eq!(
+        members(db.zrange(&b("lb"), &by_rank(0, 1, true)).unwrap()),
+        vec![b("dave"), b("carol")]
+    );
+    assert!(
+        db.zrange(&b("lb"), &by_rank(5, 10, false))
+            .unwrap()
+            .is_empty()
+    );
+}
+
+#[test]
+fn range_by_score_with_exclusive_bounds_and_limit() {
+    let db = leaderboard();
+    let range = ZRange {
+        by: RangeBy::Score {
+            min: ScoreBound::Exclusive(10.0),
+            max: ScoreBound::Inclusive(f64::INFINITY),
+        },
+        rev: false,
+        limit: Some((1, 2)),
+    };
+    assert_eq!(
+        members(db.zrange(&b("lb"), &range).unwrap()),
+        vec![b("carol"), b("dave")]
+    );
+
+    let rev = ZRange {
+        by: RangeBy::Score {
+            min: ScoreBound::Inclusive(10.0),
+            max: ScoreBound::Exclusive(30.0),
+        },
+        rev: true,
+        limit: None,
+    };
+    assert_eq!(
+        members(db.zrange(&b("lb"), &rev).unwrap()),
+        vec![b("carol"), b("bob"), b("alice")]
+    );
+}
+
+#[test]
+fn range_and_count_by_lex() {
+    let db = Db::new();
+    db.zadd(
+        &b("z"),
+        ZAddFlags::default(),
+        pairs(&[(0.0, "a"), (0.0, "b"), (0.0, "c"), (0.0, "d")]),
+    )
+    .unwrap();
+
+    let by = RangeBy::Lex {
+        min: LexBound::Exclusive(b("a")),
+        max: LexBound::Inclusive(b("c")),
+    };
+    let range = ZRange {
+        by: by.clone(),
+        rev: false,
+        limit: None,
+    };
+    assert_eq!(
+        members(db.zrange(&b("z"), &range).unwrap()),
+        vec![b("b"), b("c")]
+    );
+    assert_eq!(db.zcount(&b("z"), &by).unwrap(), 2);
+
+    let all = RangeBy::Lex {
+        min: LexBound::Min,
+        max: LexBound::Max,
+    };
+    assert_eq!(db.zcount(&b("z"), &all).unwrap(), 4);
+}
+
+#[test]
+fn zcount_by_score() {
+    let db = leaderboard();
+    let by = RangeBy::Score {
+        min: ScoreBound::Inclusive(20.0),
+        max: ScoreBound::Inclusive(30.0),
+    };
+    assert_eq!(db.zcount(&b("lb"), &by).unwrap(), 3);
+
+    let empty = RangeBy::Score {
+        min: ScoreBound::Exclusive(20.0),
+        max: ScoreBound::Exclusive(30.0),
+    };
+    assert_eq!(db.zcount(&b("lb"), &empty).unwrap(), 0);
+}
+
+#[test]
+fn zpop_both_ends() {
+    let db = leaderboard();
+    assert_eq!(
+        db.zpop(&b("lb"), 1, false).unwrap(),
+        vec![(b("alice"), 10.0)]
+    );
+    assert_eq!(db.zpop(&b("lb"), 1, true).unwrap(), vec![(b("dave"), 30.0)]);
+    assert_eq!(db.zpop(&b("lb"), 10, false).unwrap().len(), 2);
+    assert!(db.keys().is_empty());
+}
+
+#[test]
+fn zrandmember_counts() {
+    let db = leaderboard();
+    let distinct = db.zrandmember(&b("lb"), 1_000_000_000_000_000).unwrap();
+    assert_eq!(distinct.len(), 4);
+    assert_eq!(db.zrandmember(&b("lb"), -10).unwrap().len(), 10);
+    assert_eq!(db.zrandmember(&b("lb"), 0).unwrap(), vec![]);
+}
+
+#[test]
+fn zmscore_and_zrem() {
+    let db = leaderboard();
+    assert_eq!(
+        db.zmscore(&b("lb"), &[b("alice"), b("zed")]).unwrap(),
+        vec![Some(10.0), None]
+    );
+    assert_eq!(db.zrem(&b("lb"), &[b("alice"), b("zed")]).unwrap(), 1);
+    assert_eq!(
+        db.zrank(&b("lb"), &b("bob"), false).unwrap(),
+        Some((0, 20.0))
+    );
+}
+
+#[test]
+fn ranks_stay_consistent_under_churn() {
+    let db = Db::new();
+    let mut expected: Vec<(i64, String)> = Vec::new();
+
+    for i in 0..500i64 {
+        let member = format!("m{}", (i * 7919) % 300);
+        let score = (i * 31) % 97;
+        expected.retain(|(_, m)| *m != member);
+        expected.push((score, member.clone()));
+        db.zadd(
+            &b("z"),
+            ZAddFlags::default(),
+            vec![(score as f64, b(&member))],
+        )
+        .unwrap();
+
+        if i % 5 == 0 {
+            let victim = format!("m{}", (i * 13) % 300);
+            expected.retain(|(_, m)| *m != victim);
+            db.zrem(&b("z"), &[b(&victim)]).unwrap();
+        }
+    }
+    expected.sort();
+
+    let all = db.zrange(&b("z"), &by_rank(0, -1, false)).unwrap();
+    let got: Vec<(i64, String)> = all
+        .iter()
+        .map(|(m, s)| (*s as i64, String::from_utf8(m.to_vec()).unwrap()))
+        .collect();
+    assert_eq!(got, expected);
+
+    for (rank, (_, member)) in expected.iter().enumerate() {
+        let (found, _) = db.zrank(&b("z"), &b(member), false).unwrap().unwrap();
+        assert_eq!(found, rank);
+    }
+}
+
+// === Parsing ===
+
+#[test]
+fn parse_zadd_rejects_incompatible_flags() {
+    assert!(Command::from_frame(cmd_frame(&["ZADD", "z", "NX", "XX", "1", "a"])).is_err());
+    assert!(Command::from_frame(cmd_frame(&["ZADD", "z", "GT", "NX", "1", "a"])).is_err());
+    assert!(Command::from_frame(cmd_frame(&["ZADD", "z", "INCR", "1", "a", "2", "b"])).is_err());
+    assert!(Command::from_frame(cmd_frame(&["ZADD", "z", "nan", "a"])).is_err());
+}
+
+#[test]
+fn parse_zrange_rev_byscore_swaps_bounds() {
+    let frame = cmd_frame(&[
+        "ZRANGE", "z", "(5", "-inf", "BYSCORE", "REV", "LIMIT", "0", "2",
+    ]);
+    let cmd = Command::from_frame(frame).unwrap();
+    let expected = ZRange {
+        by: RangeBy::Score {
+            min: ScoreBound::Inclusive(f64::NEG_INFINITY),
+            max: ScoreBound::Exclusive(5.0),
+        },
+        rev: true,
+        limit: Some((0, 2)),
+    };
+    assert!(matches!(cmd, Command::ZRange { range, .. } if range == expected));
+}
+
+#[test]
+fn parse_zrange_invalid_combinations() {
+    assert!(Command::from_frame(cmd_frame(&["ZRANGE", "z", "0", "1", "LIMIT", "0", "1"])).is_err());
+    assert!(
+        Command::from_frame(cmd_frame(&["ZRANGE", "z", "-", "+", "BYLEX", "WITHSCORES"])).is_err()
+    );
+    assert!(Command::from_frame(cmd_frame(&["ZRANGEBYLEX", "z", "a", "+"])).is_err());
+    assert!(Command::from_frame(cmd_frame(&["ZCOUNT", "z", "x", "1"])).is_err());
+}
+
+#[test]
+fn parse_zrandmember_bounds_repeats() {
+    let Err(err) = Command::from_frame(cmd_frame(&["ZRANDMEMBER", "z", "-1000000000000000"]))
+    else {
+        panic!("expected an error");
+    };
+    assert_eq!(err.to_string(), "value is out of range");
+    assert!(Command::from_frame(cmd_frame(&["ZRANDMEMBER", "z", "-3", "WITHSCORES"])).is_ok());
+}
+
+// === Integration ===
+
+#[tokio::test]
+async fn sorted_set_commands_over_tcp() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    tokio::spawn(async move { run_server(listener, Db::new()).await });
+
+    let client = redis::Client::open(format!("redis://127.0.0.1:{}", port)).unwrap();
+    let mut con = client.get_multiplexed_async_connection().await.unwrap();
+
+    let added: i64 = redis::cmd("ZADD")
+        .arg("lb")
+        .arg(1.5)
+        .arg("a")
+        .arg(2)
+        .arg("b")
+        .query_async(&mut con)
+        .await
+        .unwrap();
+    assert_eq!(added, 2);
+
+    let range: Vec<(String, f64)> = redis::cmd("ZRANGE")
+        .arg("lb")
+        .arg("+inf")
+        .arg("-inf")
+        .arg("BYSCORE")
+        .arg("REV")
+        .arg("WITHSCORES")
+        .query_async(&mut con)
+        .await
+        .unwrap();
+    assert_eq!(range, vec![("b".to_string(), 2.0), ("a".to_string(), 1.5)]);
+
+    let rank: (i64, String) = redis::cmd("ZRANK")
+        .arg("lb")
+        .arg("b")
+        .arg("WITHSCORE")
+        .query_async(&mut con)
+        .await
+        .unwrap();
+    assert_eq!(rank, (1, "2".to_string()));
+
+    let score: String = redis::cmd("ZINCRBY")
+        .arg("lb")
+        .arg("0.25")
+        .arg("a")
+        .query_async(&mut con)
+        .await
+        .unwrap();
+    assert_eq!(score, "1.75");
+
+    // Huge counts reply with what there is rather than allocating for the count
+    let sampled: Vec<String> = redis::cmd("ZRANDMEMBER")
+        .arg("lb")
+        .arg("1000000000000000")
+        .query_async(&mut con)
+        .await
+        .unwrap();
+    assert_eq!(sampled.len(), 2);
+}
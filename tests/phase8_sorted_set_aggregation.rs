@@ -0,0 +1,259 @@
+use bytes::Bytes;
+use padis::db::{Aggregate, RangeBy, ScoreBound, SetOp, ZAddFlags, ZRange};
+use padis::{Command, Db, Frame, run_server};
+use tokio::net::TcpListener;
+
+fn b(s: &str) -> Bytes {
+    Bytes::copy_from_slice(s.as_bytes())
+}
+
+fn zadd(db: &Db, key: &str, items: &[(f64, &str)]) {
+    let pairs = items.iter().map(|(s, m)| (*s, b(m))).collect();
+    db.zadd(&b(key), ZAddFlags::default(), pairs).unwrap();
+}
+
+fn scored(items: &[(&str, f64)]) -> Vec<(Bytes, f64)> {
+    items.iter().map(|(m, s)| (b(m), *s)).collect()
+}
+
+// Helper to build a command frame
+fn cmd_frame(args: &[&str]) -> Frame {
+    Frame::Array(
+        args.iter()
+            .map(|s| Frame::BulkString(Bytes::copy_from_slice(s.as_bytes())))
+            .collect(),
+    )
+}
+
+fn weekly() -> Db {
+    let db = Db::new();
+    zadd(&db, "week1", &[(10.0, "alice"), (5.0, "bob")]);
+    zadd(&db, "week2", &[(3.0, "bob"), (7.0, "carol")]);
+    db
+}
+
+// === Db ===
+
+#[test]
+fn union_sums_scores() {
+    let db = weekly();
+    let result = db
+        .zset_op(
+            SetOp::Union,
+            &[b("week1"), b("week2")],
+            None,
+            Aggregate::Sum,
+        )
+        .unwrap();
+    assert_eq!(
+        result,
+        scored(&[("carol", 7.0), ("bob", 8.0), ("alice", 10.0)])
+    );
+}
+
+#[test]
+fn union_with_weights_and_max() {
+    let db = weekly();
+    let result = db
+        .zset_op(
+            SetOp::Union,
+            &[b("week1"), b("week2")],
+            Some(&[1.0, 2.0]),
+            Aggregate::Max,
+        )
+        .unwrap();
+    assert_eq!(
+        result,
+        scored(&[("bob", 6.0), ("alice", 10.0), ("carol", 14.0)])
+    );
+}
+
+#[test]
+fn inter_with_min() {
+    let db = weekly();
+    let result = db
+        .zset_op(
+            SetOp::Inter,
+            &[b("week1"), b("week2")],
+            None,
+            Aggregate::Min,
+        )
+        .unwrap();
+    assert_eq!(result, scored(&[("bob", 3.0)]));
+
+    let missing = db
+        .zset_op(SetOp::Inter, &[b("week1"), b("nope")], None, Aggregate::Sum)
+        .unwrap();
+    assert!(missing.is_empty());
+}
+
+#[test]
+fn diff_keeps_first_scores() {
+    let db = weekly();
+    let result = db
+        .zset_op(SetOp::Diff, &[b("week1"), b("week2")], None, Aggregate::Sum)
+        .unwrap();
+    assert_eq!(result, scored(&[("alice", 10.0)]));
+}
+
+#[test]
+fn plain_sets_score_one() {
+    let db = weekly();
+    db.sadd(&b("vip"), vec![b("alice"), b("dave")]).unwrap();
+    let result = db
+        .zset_op(SetOp::Union, &[b("week1"), b("vip")], None, Aggregate::Sum)
+        .unwrap();
+    assert_eq!(
+        result,
+        scored(&[("dave", 1.0), ("bob", 5.0), ("alice", 11.0)])
+    );
+}
+
+#[test]
+fn infinities_follow_redis_rules() {
+    let db = Db::new();
+    zadd(&db, "a", &[(f64::INFINITY, "x")]);
+    zadd(&db, "b", &[(f64::NEG_INFINITY, "x")]);
+
+    // inf + -inf would be NaN, Redis stores 0
+    let sum = db
+        .zset_op(SetOp::Union, &[b("a"), b("b")], None, Aggregate::Sum)
+        .unwrap();
+    assert_eq!(sum, scored(&[("x", 0.0)]));
+
+    // A zero weight times inf would be NaN too
+    let weighted = db
+        .zset_op(SetOp::Union, &[b("a")], Some(&[0.0]), Aggregate::Sum)
+        .unwrap();
+    assert_eq!(weighted, scored(&[("x", 0.0)]));
+}
+
+#[test]
+fn store_replaces_destination() {
+    let db = weekly();
+    db.set(&b("dest"), b("old"), None);
+    let n = db
+        .zset_op_store(
+            SetOp::Union,
+            &b("dest"),
+            &[b("week1"), b("week2")],
+            None,
+            Aggregate::Sum,
+        )
+        .unwrap();
+    assert_eq!(n, 3);
+    assert_eq!(db.zscore(&b("dest"), &b("bob")).unwrap(), Some(8.0));
+
+    let n = db
+        .zset_op_store(
+            SetOp::Inter,
+            &b("dest"),
+            &[b("week1"), b("nope")],
+            None,
+            Aggregate::Sum,
+        )
+        .unwrap();
+    assert_eq!(n, 0);
+    assert!(!db.keys().contains(&b("dest")));
+}
+
+#[test]
+fn zrangestore_uses_range_engine() {
+    let db = weekly();
+    let range = ZRange {
+        by: RangeBy::Score {
+            min: ScoreBound::Exclusive(5.0),
+            max: ScoreBound::Inclusive(f64::INFINITY),
+        },
+        rev: false,
+        limit: None,
+    };
+    assert_eq!(db.zrangestore(&b("top"), &b("week1"), &range).unwrap(), 1);
+    assert_eq!(db.zscore(&b("top"), &b("alice")).unwrap(), Some(10.0));
+}
+
+// === Parsing ===
+
+#[test]
+fn parse_weights_and_aggregate() {
+    let frame = cmd_frame(&[
+        "ZUNIONSTORE",
+        "out",
+        "2",
+        "a",
+        "b",
+        "WEIGHTS",
+        "2",
+        "3",
+        "AGGREGATE",
+        "MIN",
+    ]);
+    let cmd = Command::from_frame(frame).unwrap();
+    assert!(matches!(
+        cmd,
+        Command::ZSetOpStore { op: SetOp::Union, weights: Some(w), aggregate: Aggregate::Min, .. }
+            if w == vec![2.0, 3.0]
+    ));
+}
+
+#[test]
+fn parse_rejects_bad_options() {
+    assert!(Command::from_frame(cmd_frame(&["ZUNION", "0", "a"])).is_err());
+    assert!(Command::from_frame(cmd_frame(&["ZUNION", "2", "a", "b", "WEIGHTS", "1"])).is_err());
+    assert!(Command::from_frame(cmd_frame(&["ZDIFF", "2", "a", "b", "AGGREGATE", "SUM"])).is_err());
+    assert!(Command::from_frame(cmd_frame(&["ZINTERSTORE", "d", "1", "a", "WITHSCORES"])).is_err());
+    assert!(
+        Command::from_frame(cmd_frame(&[
+            "ZRANGESTORE",
+            "d",
+            "s",
+            "0",
+            "1",
+            "WITHSCORES"
+        ]))
+        .is_err()
+    );
+}
+
+// === Integration ===
+
+#[tokio::test]
+async fn aggregation_over_tcp() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    tokio::spawn(async move { run_server(listener, weekly()).await });
+
+    let client = redis::Client::open(format!("redis://127.0.0.1:{}", port)).unwrap();
+    let mut con = client.get_multiplexed_async_connection().await.unwrap();
+
+    let stored: i64 = redis::cmd("ZUNIONSTORE")
+        .arg("total")
+        .arg(2)
+        .arg("week1")
+        .arg("week2")
+        .query_async(&mut con)
+        .await
+        .unwrap();
+    assert_eq!(stored, 3);
+
+    let top: Vec<(String, f64)> = redis::cmd("ZINTER")
+        .arg(2)
+        .arg("week1")
+        .arg("total")
+        .arg("WITHSCORES")
+        .query_async(&mut con)
+        .await
+        .unwrap();
+    assert_eq!(top, vec![("bob".into(), 13.0), ("alice".into(), 20.0)]);
+
+    let copied: i64 = redis::cmd("ZRANGESTORE")
+        .arg("best")
+        .arg("total")
+        .arg(0)
+        .arg(0)
+        .arg("REV")
+        .query_async(&mut con)
+        .await
+        .unwrap();
+    assert_eq!(copied, 1);
+}
@@ -0,0 +1,219 @@
+use bytes::Bytes;
+use padis::db::ZAddFlags;
+use padis::{Command, Connection, Db, Frame, run_server};
+use std::time::Duration;
+use tokio::net::{TcpListener, TcpStream};
+
+fn b(s: &str) -> Bytes {
+    Bytes::copy_from_slice(s.as_bytes())
+}
+
+fn zadd(db: &Db, key: &str, items: &[(f64, &str)]) {
+    let pairs = items.iter().map(|(s, m)| (*s, b(m))).collect();
+    db.zadd(&b(key), ZAddFlags::default(), pairs).unwrap();
+}
+
+// Helper to build a command frame
+fn cmd_frame(args: &[&str]) -> Frame {
+    Frame::Array(
+        args.iter()
+            .map(|s| Frame::BulkString(Bytes::copy_from_slice(s.as_bytes())))
+            .collect(),
+    )
+}
+
+// === Db ===
+
+#[tokio::test]
+async fn pops_immediately_when_data_exists() {
+    let db = Db::new();
+    zadd(&db, "q", &[(1.0, "a"), (2.0, "b")]);
+    let popped = db.bzmpop(&[b("q")], true, 1, None).await.unwrap();
+    assert_eq!(popped, Some((b("q"), vec![(b("b"), 2.0)])));
+}
+
+#[tokio::test]
+async fn times_out_with_nothing() {
+    let db = Db::new();
+    let popped = db
+        .bzmpop(&[b("q")], false, 1, Some(Duration::from_millis(20)))
+        .await
+        .unwrap();
+    assert!(popped.is_none());
+}
+
+#[tokio::test]
+async fn wakes_on_write() {
+    let db = Db::new();
+    let waiter = {
+        let db = db.clone();
+        tokio::spawn(async move { db.bzmpop(&[b("q")], false, 1, None).await })
+    };
+    tokio::time::sleep(Duration::from_millis(20)).await;
+
+    zadd(&db, "q", &[(5.0, "job")]);
+    let popped = waiter.await.unwrap().unwrap();
+    assert_eq!(popped, Some((b("q"), vec![(b("job"), 5.0)])));
+    assert!(db.keys().is_empty());
+}
+
+#[tokio::test]
+async fn serves_waiters_in_order() {
+    let db = Db::new();
+    let mut waiters = Vec::new();
+    for _ in 0..3 {
+        let db2 = db.clone();
+        waiters.push(tokio::spawn(async move {
+            db2.bzmpop(&[b("q")], false, 1, None).await
+        }));
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+
+    for (i, member) in ["first", "second", "third"].iter().enumerate() {
+        zadd(&db, "q", &[(i as f64, member)]);
+    }
+
+    let mut got = Vec::new();
+    for waiter in waiters {
+        let (_, items) = waiter.await.unwrap().unwrap().unwrap();
+        got.push(items[0].0.clone());
+    }
+    assert_eq!(got, vec![b("first"), b("second"), b("third")]);
+}
+
+#[tokio::test]
+async fn multi_key_wait_served_by_first_ready_key() {
+    let db = Db::new();
+    let waiter = {
+        let db = db.clone();
+        tokio::spawn(async move { db.bzmpop(&[b("a"), b("b")], false, 10, None).await })
+    };
+    tokio::time::sleep(Duration::from_millis(20)).await;
+
+    zadd(&db, "b", &[(1.0, "x"), (2.0, "y")]);
+    let (key, items) = waiter.await.unwrap().unwrap().unwrap();
+    assert_eq!(key, b("b"));
+    assert_eq!(items.len(), 2);
+
+    // Writing to the other key afterwards leaves its data alone
+    zadd(&db, "a", &[(1.0, "z")]);
+    assert_eq!(db.zcard(&b("a")).unwrap(), 1);
+}
+
+#[tokio::test]
+async fn timed_out_waiter_does_not_take_elements() {
+    let db = Db::new();
+    db.bzmpop(&[b("q")], false, 1, Some(Duration::from_millis(10)))
+        .await
+        .unwrap();
+    zadd(&db, "q", &[(1.0, "a")]);
+    assert_eq!(db.zcard(&b("q")).unwrap(), 1);
+}
+
+#[tokio::test]
+async fn wrong_type_fails_immediately() {
+    let db = Db::new();
+    db.set(&b("s"), b("v"), None);
+    assert!(db.bzmpop(&[b("s")], false, 1, None).await.is_err());
+}
+
+// === Parsing ===
+
+#[test]
+fn parse_blocking_commands() {
+    let cmd = Command::from_frame(cmd_frame(&["BZPOPMIN", "a", "b", "0"])).unwrap();
+    assert!(
+        matches!(cmd, Command::BZPop { ref keys, max: false, timeout: None } if keys.len() == 2)
+    );
+    assert!(cmd.is_blocking());
+
+    let cmd =
+        Command::from_frame(cmd_frame(&["BZMPOP", "1.5", "1", "a", "MAX", "COUNT", "3"])).unwrap();
+    assert!(matches!(
+        cmd,
+        Command::BZMPop { max: true, count: 3, timeout: Some(t), .. } if t == Duration::from_millis(1500)
+    ));
+
+    let cmd = Command::from_frame(cmd_frame(&["ZMPOP", "2", "a", "b", "MIN"])).unwrap();
+    assert!(!cmd.is_blocking());
+
+    assert!(Command::from_frame(cmd_frame(&["BZPOPMAX", "a", "-1"])).is_err());
+    assert!(Command::from_frame(cmd_frame(&["BZPOPMAX", "a"])).is_err());
+    let Err(err) = Command::from_frame(cmd_frame(&["BZPOPMIN", "a", "1e20"])) else {
+        panic!("expected an error");
+    };
+    assert_eq!(err.to_string(), "timeout is out of range");
+    assert!(Command::from_frame(cmd_frame(&["ZMPOP", "1", "a", "SIDEWAYS"])).is_err());
+    assert!(Command::from_frame(cmd_frame(&["ZMPOP", "1", "a", "MIN", "COUNT", "0"])).is_err());
+}
+
+// === Integration ===
+
+#[tokio::test]
+async fn bzpopmin_over_tcp() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    tokio::spawn(async move { run_server(listener, Db::new()).await });
+
+    let client = redis::Client::open(format!("redis://127.0.0.1:{}", port)).unwrap();
+    let mut consumer = client.get_multiplexed_async_connection().await.unwrap();
+    let mut producer = client.get_multiplexed_async_connection().await.unwrap();
+
+    let waiting = tokio::spawn(async move {
+        let popped: Option<(String, String, f64)> = redis::cmd("BZPOPMIN")
+            .arg("jobs")
+            .arg(0)
+            .query_async(&mut consumer)
+            .await
+            .unwrap();
+        popped
+    });
+    tokio::time::sleep(Duration::from_millis(50)).await;
+
+    let _: i64 = redis::cmd("ZADD")
+        .arg("jobs")
+        .arg(1)
+        .arg("build")
+        .query_async(&mut producer)
+        .await
+        .unwrap();
+
+    assert_eq!(
+        waiting.await.unwrap(),
+        Some(("jobs".to_string(), "build".to_string(), 1.0))
+    );
+
+    let timed_out: Option<(String, String, f64)> = redis::cmd("BZPOPMAX")
+        .arg("jobs")
+        .arg("0.05")
+        .query_async(&mut producer)
+        .await
+        .unwrap();
+    assert!(timed_out.is_none());
+}
+
+#[tokio::test]
+async fn timeouts_reply_with_null_array() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    tokio::spawn(async move { run_server(listener, Db::new()).await });
+    let mut conn = Connection::new(TcpStream::connect(("127.0.0.1", port)).await.unwrap());
+
+    for args in [
+        &["BZPOPMIN", "jobs", "0.01"][..],
+        &["BZMPOP", "0.01", "1", "jobs", "MIN"],
+        &["ZMPOP", "1", "jobs", "MAX"],
+    ] {
+        conn.write_frame(&cmd_frame(args)).await.unwrap();
+        assert_eq!(conn.read_frame().await.unwrap(), Some(Frame::NullArray));
+    }
+
+    // A timeout too big for a Duration is an error, not a dropped connection
+    conn.write_frame(&cmd_frame(&["BZPOPMIN", "jobs", "1e20"]))
+        .await
+        .unwrap();
+    assert_eq!(
+        conn.read_frame().await.unwrap(),
+        Some(Frame::SimpleError("timeout is out of range".into()))
+    );
+}